@@ -0,0 +1,408 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Works out how many arguments fit into a command line, and groups them
+//! into batches accordingly.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::env;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::mem;
+
+/// The size limit to use if the OS won't tell us what its own is.
+const FALLBACK_ARG_MAX: usize = 128 * 1024;
+
+/// Like GNU xargs, we don't use more than this by default, even if the OS
+/// would let us.
+const DEFAULT_SIZE_LIMIT: usize = 128 * 1024;
+
+/// POSIX recommends leaving this much room to spare so that the command can
+/// safely modify its environment.
+const HEADROOM: usize = 2048;
+
+/// The smallest limit on the size of arguments and environment together that
+/// POSIX allows a system to have.
+pub const POSIX_ARG_MAX: usize = 4096;
+
+/// The space taken up by the pointer to each argument and environment
+/// variable, which counts towards the OS's limit too.
+const POINTER_SIZE: usize = mem::size_of::<*const u8>();
+
+/// The space an argument takes up in a command line (including its
+/// terminating NUL, but not the pointer to it).
+pub fn arg_size(arg: &OsStr) -> usize {
+    arg.len() + 1
+}
+
+/// The space that arguments of the given total size take up as far as the
+/// OS is concerned, once the pointers to them (and the null pointer after
+/// them) are included.
+fn os_size(size: usize, args: usize) -> usize {
+    size + (args + 1) * POINTER_SIZE
+}
+
+#[cfg(unix)]
+fn os_arg_max() -> Option<usize> {
+    let arg_max = unsafe { ::libc::sysconf(::libc::_SC_ARG_MAX) };
+    if arg_max > 0 {
+        Some(arg_max as usize)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn os_arg_max() -> Option<usize> {
+    None
+}
+
+/// The space the given environment variables take up, as GNU xargs counts
+/// it: each one is stored as "key=value\0".
+fn environment_size<I: IntoIterator<Item = (OsString, OsString)>>(vars: I) -> usize {
+    vars.into_iter().map(|(key, value)| key.len() + value.len() + 2).sum()
+}
+
+/// How long (in bytes) command lines can be, along with the numbers that
+/// went into working that out (which xargs --show-limits prints).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SizeLimits {
+    /// The space the current environment takes up.
+    pub environment: usize,
+    /// What the OS allows for arguments, less the environment and the
+    /// headroom that POSIX recommends.
+    pub posix_upper_limit: usize,
+    /// The most space the arguments can take up: as with GNU xargs, the
+    /// upper limit less the environment again, which leaves room for the
+    /// pointers to the environment variables, and for the command to add
+    /// to them.
+    pub max_usable: usize,
+    /// The limit to use unless asked for another (e.g. by xargs -s).
+    pub default: usize,
+}
+
+impl SizeLimits {
+    fn new(arg_max: usize, environment: usize) -> SizeLimits {
+        let posix_upper_limit = arg_max.saturating_sub(environment).saturating_sub(HEADROOM);
+        let max_usable = posix_upper_limit.saturating_sub(environment);
+        SizeLimits {
+            environment: environment,
+            posix_upper_limit: posix_upper_limit,
+            max_usable: max_usable,
+            default: cmp::min(max_usable, DEFAULT_SIZE_LIMIT),
+        }
+    }
+}
+
+/// Works out how long a command line can be, based on what the OS allows for
+/// arguments and environment together, minus what the environment is already
+/// using. Anything that builds command lines out of lists of items should use
+/// this.
+pub fn size_limits() -> SizeLimits {
+    SizeLimits::new(os_arg_max().unwrap_or(FALLBACK_ARG_MAX),
+                    environment_size(env::vars_os()))
+}
+
+/// The limits on how many items go into each batch.
+#[derive(Clone, Copy, Debug)]
+pub struct BatchLimits {
+    /// The longest a command line can be, counting the terminating NUL of
+    /// each argument (as xargs -s does).
+    pub size: usize,
+    /// The most space the arguments can take up as far as the OS is
+    /// concerned, which also counts the pointer to each of them.
+    pub os_size: usize,
+    /// The most items in a batch (for -n).
+    pub max_args: Option<usize>,
+    /// The most input lines whose items can go into a batch (for -L).
+    pub max_lines: Option<usize>,
+    /// Whether it's an error for the size limit to stop a batch from
+    /// reaching max_args or max_lines (for -x).
+    pub exit_if_size_exceeded: bool,
+}
+
+impl BatchLimits {
+    /// Limits batches by size alone: size as it's usually counted, and
+    /// os_size as the OS counts it.
+    pub fn new(size: usize, os_size: usize) -> BatchLimits {
+        BatchLimits {
+            size: size,
+            os_size: os_size,
+            max_args: None,
+            max_lines: None,
+            exit_if_size_exceeded: false,
+        }
+    }
+}
+
+/// Collects items into batches, each of which gets appended to a command for
+/// a single invocation. A batch is full when adding another item would make
+/// the command line too long, or when it has max_args items (for -n), or
+/// when it has the items from max_lines input lines (for -L).
+pub struct CommandBatcher {
+    limits: BatchLimits,
+    /// The size of the command itself, and the number of arguments in it.
+    command_size: usize,
+    command_args: usize,
+    items: Vec<OsString>,
+    size: usize,
+    lines: usize,
+    full_batches: VecDeque<Vec<OsString>>,
+}
+
+impl CommandBatcher {
+    /// Creates a batcher for the given command (the executable followed by
+    /// any initial arguments). Fails if the command alone is too long.
+    pub fn new(command: &[OsString], limits: BatchLimits) -> Result<CommandBatcher, Box<Error>> {
+        let batcher = CommandBatcher {
+            limits: limits,
+            command_size: command.iter().map(|arg| arg_size(arg)).sum(),
+            command_args: command.len(),
+            items: vec![],
+            size: 0,
+            lines: 0,
+            full_batches: VecDeque::new(),
+        };
+        if !batcher.fits(0, 0) {
+            return Err(From::from("argument list too long"));
+        }
+        Ok(batcher)
+    }
+
+    /// Whether the command would still fit within the limits with the given
+    /// number of items of the given total size appended to it.
+    fn fits(&self, items_size: usize, items: usize) -> bool {
+        let size = self.command_size + items_size;
+        size <= self.limits.size &&
+        os_size(size, self.command_args + items) <= self.limits.os_size
+    }
+
+    /// Adds an item. ends_line says whether it was the last item on its
+    /// input line. Fails if the item wouldn't fit in a batch on its own.
+    pub fn add(&mut self, item: OsString, ends_line: bool) -> Result<(), Box<Error>> {
+        let item_size = arg_size(&item);
+        if !self.fits(item_size, 1) {
+            return Err(From::from("argument line too long"));
+        }
+        if !self.fits(self.size + item_size, self.items.len() + 1) {
+            let limited_by_count = self.limits.max_args.is_some() ||
+                                   self.limits.max_lines.is_some();
+            if self.limits.exit_if_size_exceeded && limited_by_count {
+                return Err(From::from("argument list too long"));
+            }
+            self.finish_batch();
+        }
+        self.size += item_size;
+        self.items.push(item);
+        if ends_line {
+            self.lines += 1;
+        }
+        if Some(self.items.len()) == self.limits.max_args ||
+           Some(self.lines) == self.limits.max_lines {
+            self.finish_batch();
+        }
+        Ok(())
+    }
+
+    fn finish_batch(&mut self) {
+        if let Some(batch) = self.take() {
+            self.full_batches.push_back(batch);
+        }
+    }
+
+    /// Returns the next batch that's full, if there is one.
+    pub fn next_batch(&mut self) -> Option<Vec<OsString>> {
+        self.full_batches.pop_front()
+    }
+
+    /// Returns whatever items haven't been handed back yet, if there are any.
+    pub fn take(&mut self) -> Option<Vec<OsString>> {
+        if let Some(batch) = self.next_batch() {
+            return Some(batch);
+        }
+        if self.items.is_empty() {
+            return None;
+        }
+        self.size = 0;
+        self.lines = 0;
+        Some(mem::replace(&mut self.items, vec![]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use super::*;
+
+    fn os_strings(strs: &[&str]) -> Vec<OsString> {
+        strs.iter().map(OsString::from).collect()
+    }
+
+    fn limits(size: usize, max_args: Option<usize>, max_lines: Option<usize>) -> BatchLimits {
+        BatchLimits {
+            max_args: max_args,
+            max_lines: max_lines,
+            ..BatchLimits::new(size, usize::max_value())
+        }
+    }
+
+    #[test]
+    fn os_size_counts_pointers() {
+        assert_eq!(arg_size(&OsString::from("abc")), 4);
+        assert_eq!(os_size(0, 0), POINTER_SIZE);
+        // "echo\0" and "abc\0", a pointer to each, and a null pointer
+        assert_eq!(os_size(9, 2), 9 + 3 * POINTER_SIZE);
+    }
+
+    #[test]
+    fn environment_size_counts_strings() {
+        assert_eq!(environment_size(vec![]), 0);
+        let vars = vec![(OsString::from("A"), OsString::from("bc")),
+                        (OsString::from("DEF"), OsString::from(""))];
+        // "A=bc\0" and "DEF=\0"
+        assert_eq!(environment_size(vars), 10);
+    }
+
+    #[test]
+    fn size_limits_leave_room_for_the_environment() {
+        let limits = SizeLimits::new(2 * 1024 * 1024, 3000);
+        assert_eq!(limits,
+                   SizeLimits {
+                       environment: 3000,
+                       posix_upper_limit: 2 * 1024 * 1024 - 3000 - 2048,
+                       max_usable: 2 * 1024 * 1024 - 3000 - 2048 - 3000,
+                       default: 128 * 1024,
+                   });
+
+        // the default is never more than the OS allows
+        let limits = SizeLimits::new(64 * 1024, 1000);
+        assert_eq!(limits.default, 64 * 1024 - 1000 - 2048 - 1000);
+        assert_eq!(limits.default, limits.max_usable);
+
+        // ...even if the environment's already too big
+        let limits = SizeLimits::new(POSIX_ARG_MAX, 10000);
+        assert_eq!((limits.max_usable, limits.default), (0, 0));
+    }
+
+    #[test]
+    fn size_limits_are_sensible() {
+        let limits = size_limits();
+        assert!(limits.default > 0);
+        assert!(limits.default <= DEFAULT_SIZE_LIMIT);
+        assert!(limits.default <= limits.max_usable);
+        assert_eq!(limits.max_usable + limits.environment, limits.posix_upper_limit);
+    }
+
+    #[test]
+    fn batches_fill_up_to_the_limit() {
+        // "echo\0" is 5 bytes, leaving room for 3 "ab\0"s
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo"]), limits(14, None, None))
+            .unwrap();
+        for _ in 0..3 {
+            batcher.add(OsString::from("ab"), false).unwrap();
+            assert_eq!(batcher.next_batch(), None);
+        }
+        batcher.add(OsString::from("cd"), false).unwrap();
+        assert_eq!(batcher.next_batch(), Some(os_strings(&["ab", "ab", "ab"])));
+        batcher.add(OsString::from("e"), false).unwrap();
+        assert_eq!(batcher.next_batch(), None);
+        assert_eq!(batcher.take(), Some(os_strings(&["cd", "e"])));
+        assert_eq!(batcher.take(), None);
+    }
+
+    #[test]
+    fn batches_fill_up_to_the_os_limit() {
+        // as the OS sees it, "echo\0" takes up 5 bytes plus 2 pointers, and
+        // each "ab\0" 3 bytes plus a pointer, leaving room for 2 of them
+        let os_limit = 5 + 2 * POINTER_SIZE + 2 * (3 + POINTER_SIZE);
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo"]),
+                                              BatchLimits::new(100, os_limit))
+            .unwrap();
+        for _ in 0..3 {
+            batcher.add(OsString::from("ab"), false).unwrap();
+        }
+        assert_eq!(batcher.next_batch(), Some(os_strings(&["ab", "ab"])));
+        assert_eq!(batcher.take(), Some(os_strings(&["ab"])));
+
+        let os_limit = 5 + 2 * POINTER_SIZE - 1;
+        assert!(CommandBatcher::new(&os_strings(&["echo"]), BatchLimits::new(100, os_limit))
+            .is_err());
+    }
+
+    #[test]
+    fn oversized_items_and_commands() {
+        // "echo\0x\0" is 7 bytes, leaving room for 4
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo", "x"]),
+                                              limits(11, None, None))
+            .unwrap();
+        assert!(batcher.add(OsString::from("abc"), false).is_ok());
+        let e = batcher.add(OsString::from("abcd"), false).unwrap_err();
+        assert_eq!(e.to_string(), "argument line too long");
+
+        let e = CommandBatcher::new(&os_strings(&["echo", "abcde"]), limits(10, None, None))
+            .err()
+            .unwrap();
+        assert_eq!(e.to_string(), "argument list too long");
+    }
+
+    fn batches(batcher: &mut CommandBatcher, items: &[(&str, bool)]) -> Vec<Vec<OsString>> {
+        let mut batches = vec![];
+        for &(item, ends_line) in items {
+            batcher.add(OsString::from(item), ends_line).unwrap();
+            while let Some(batch) = batcher.next_batch() {
+                batches.push(batch);
+            }
+        }
+        batches.extend(batcher.take());
+        batches
+    }
+
+    #[test]
+    fn max_args() {
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo"]), limits(100, Some(2), None))
+            .unwrap();
+        let items = [("a", true), ("b", true), ("c", false), ("d", false), ("e", false)];
+        assert_eq!(batches(&mut batcher, &items),
+                   vec![os_strings(&["a", "b"]), os_strings(&["c", "d"]), os_strings(&["e"])]);
+
+        // whichever limit is hit first wins
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo"]), limits(11, Some(2), None))
+            .unwrap();
+        let items = [("a", false), ("bc", false), ("d", false)];
+        assert_eq!(batches(&mut batcher, &items),
+                   vec![os_strings(&["a", "bc"]), os_strings(&["d"])]);
+        let items = [("abc", false), ("de", false)];
+        assert_eq!(batches(&mut batcher, &items),
+                   vec![os_strings(&["abc"]), os_strings(&["de"])]);
+    }
+
+    #[test]
+    fn max_lines() {
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo"]), limits(100, None, Some(2)))
+            .unwrap();
+        let items = [("a", false), ("b", true), ("c", true), ("d", true), ("e", false)];
+        assert_eq!(batches(&mut batcher, &items),
+                   vec![os_strings(&["a", "b", "c"]), os_strings(&["d", "e"])]);
+    }
+
+    #[test]
+    fn exit_if_size_exceeded() {
+        let mut limits = limits(11, Some(2), None);
+        limits.exit_if_size_exceeded = true;
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo"]), limits).unwrap();
+        batcher.add(OsString::from("abc"), false).unwrap();
+        let e = batcher.add(OsString::from("de"), false).unwrap_err();
+        assert_eq!(e.to_string(), "argument list too long");
+
+        // without -n or -L, the size limit is the only limit
+        limits.max_args = None;
+        let mut batcher = CommandBatcher::new(&os_strings(&["echo"]), limits).unwrap();
+        batcher.add(OsString::from("abc"), false).unwrap();
+        batcher.add(OsString::from("de"), false).unwrap();
+        assert_eq!(batcher.next_batch(), Some(os_strings(&["abc"])));
+    }
+}
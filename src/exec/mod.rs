@@ -0,0 +1,76 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! The plumbing for running other commands that find's -exec family and
+//! xargs have in common: fitting arguments into command lines, starting
+//! commands, and telling how they exited.
+
+pub mod batch;
+pub mod outcome;
+#[cfg(windows)]
+pub mod windows;
+
+use std::error::Error;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+pub use self::batch::{BatchLimits, CommandBatcher};
+pub use self::outcome::ChildOutcome;
+
+/// Creates a command that runs the executable with the given arguments, in
+/// the given directory (or in our own working directory if there isn't
+/// one). On Windows, the executable is resolved and the arguments quoted
+/// the way cmd would (see `windows::new_command`).
+pub fn new_command<E, I, S>(executable: E, args: I, dir: Option<&Path>) -> Command
+    where E: AsRef<OsStr>,
+          I: IntoIterator<Item = S>,
+          S: AsRef<OsStr>
+{
+    let mut command = platform_command(executable.as_ref(), args);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    command
+}
+
+#[cfg(windows)]
+fn platform_command<I, S>(executable: &OsStr, args: I) -> Command
+    where I: IntoIterator<Item = S>,
+          S: AsRef<OsStr>
+{
+    windows::new_command(executable, args)
+}
+
+#[cfg(not(windows))]
+fn platform_command<I, S>(executable: &OsStr, args: I) -> Command
+    where I: IntoIterator<Item = S>,
+          S: AsRef<OsStr>
+{
+    let mut command = Command::new(executable);
+    command.args(args);
+    command
+}
+
+/// Fails if the executable is a batch file, which mustn't be given file
+/// names that anyone could have chosen (see `windows::check_not_batch_file`).
+/// There's no such problem anywhere but Windows.
+#[cfg(windows)]
+pub fn check_not_batch_file(executable: &str) -> Result<(), Box<Error>> {
+    windows::check_not_batch_file(executable)
+}
+
+#[cfg(not(windows))]
+pub fn check_not_batch_file(_: &str) -> Result<(), Box<Error>> {
+    Ok(())
+}
+
+/// Runs the command and waits for it to finish. Anything buffered for the
+/// same output should be flushed first, so that it appears before anything
+/// the command writes.
+pub fn run(command: &mut Command) -> ChildOutcome {
+    ChildOutcome::new(command.status())
+}
@@ -0,0 +1,89 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+use std::io;
+use std::process::ExitStatus;
+
+#[cfg(unix)]
+fn terminating_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn terminating_signal(_: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// How running a command turned out.
+#[derive(Debug)]
+pub enum ChildOutcome {
+    /// The command exited with the given status.
+    Exited(i32),
+    /// The command was killed by the given signal.
+    Killed(i32),
+    /// The command couldn't be run, or couldn't be waited for.
+    NotRun(io::Error),
+}
+
+impl ChildOutcome {
+    /// Classifies the result of running (or waiting for) a command.
+    pub fn new(result: io::Result<ExitStatus>) -> ChildOutcome {
+        let status = match result {
+            Ok(status) => status,
+            Err(e) => return ChildOutcome::NotRun(e),
+        };
+        match (status.code(), terminating_signal(&status)) {
+            (Some(code), _) => ChildOutcome::Exited(code),
+            (None, Some(signal)) => ChildOutcome::Killed(signal),
+            // this can't happen, but if it did, it wouldn't be a success
+            (None, None) => ChildOutcome::Exited(1),
+        }
+    }
+
+    /// Whether the command ran and exited successfully.
+    pub fn success(&self) -> bool {
+        match *self {
+            ChildOutcome::Exited(0) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn classifies_exit_statuses() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let outcome = ChildOutcome::new(Ok(ExitStatus::from_raw(0)));
+        assert!(outcome.success());
+        match ChildOutcome::new(Ok(ExitStatus::from_raw(255 << 8))) {
+            ChildOutcome::Exited(255) => {}
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        let outcome = ChildOutcome::new(Ok(ExitStatus::from_raw(9)));
+        assert!(!outcome.success());
+        match outcome {
+            ChildOutcome::Killed(9) => {}
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn classifies_spawn_failures() {
+        let outcome = ChildOutcome::new(Err(io::Error::new(io::ErrorKind::NotFound, "not found")));
+        assert!(!outcome.success());
+        match outcome {
+            ChildOutcome::NotRun(ref e) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Windows has no argv: a new process gets a single command line, which it
+//! splits into arguments itself (usually following the C runtime's rules).
+//! Batch files are worse, as they're run by cmd.exe, which has rules of its
+//! own that no amount of quoting can fully tame. This module works out what
+//! to run and how to quote its arguments.
+
+use std::env;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The extensions to try if PATHEXT isn't set (the same as cmd's default).
+const DEFAULT_PATHEXT: &'static str = ".COM;.EXE;.BAT;.CMD";
+
+const TAB: u16 = b'\t' as u16;
+const NEWLINE: u16 = b'\n' as u16;
+const VERTICAL_TAB: u16 = 0x0b;
+const SPACE: u16 = b' ' as u16;
+const QUOTE: u16 = b'"' as u16;
+const BACKSLASH: u16 = b'\\' as u16;
+
+/// Creates a command that runs the program with the given arguments. The
+/// program is resolved as cmd would (see `resolve_program`), and arguments
+/// are quoted so that programs that split their command lines the way the C
+/// runtime does get them back unchanged. Batch files are left to std, which
+/// quotes for cmd (and refuses arguments it can't quote safely).
+pub fn new_command<I, S>(program: &OsStr, args: I) -> Command
+    where I: IntoIterator<Item = S>,
+          S: AsRef<OsStr>
+{
+    let program = resolve_program(program).unwrap_or_else(|| PathBuf::from(program));
+    let mut command = Command::new(&program);
+    if is_batch_file(&program) {
+        command.args(args);
+    } else {
+        for arg in args {
+            command.raw_arg(quote_arg(arg.as_ref()));
+        }
+    }
+    command
+}
+
+/// Finds the file that cmd would run for a program name without an
+/// extension, by trying each extension in PATHEXT. A bare name is looked for
+/// along PATH, but unlike cmd, not in the current directory first: -execdir
+/// runs commands in the directories being searched, and they shouldn't get
+/// to choose what runs. Returns None if the name already has an extension,
+/// or if nothing was found: either way, it's best used as it is.
+pub fn resolve_program(program: &OsStr) -> Option<PathBuf> {
+    resolve_program_in(Path::new(program),
+                       env::var_os("PATH").as_ref().map(|path| path.as_os_str()),
+                       env::var_os("PATHEXT").as_ref().map(|pathext| pathext.as_os_str()))
+}
+
+fn resolve_program_in(program: &Path,
+                      search_path: Option<&OsStr>,
+                      pathext: Option<&OsStr>)
+                      -> Option<PathBuf> {
+    if program.extension().is_some() || program.as_os_str().is_empty() {
+        return None;
+    }
+    let pathext = pathext.unwrap_or_else(|| OsStr::new(DEFAULT_PATHEXT)).to_string_lossy();
+    let has_dir = program.parent().map_or(false, |parent| parent != Path::new(""));
+    let dirs: Vec<PathBuf> = if has_dir {
+        vec![PathBuf::new()]
+    } else {
+        search_path.map_or(vec![], |path| env::split_paths(path).collect())
+    };
+    for dir in dirs {
+        for extension in pathext.split(';').filter(|extension| !extension.is_empty()) {
+            let mut candidate = dir.join(program).into_os_string();
+            candidate.push(extension);
+            let candidate = PathBuf::from(candidate);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+/// Whether the program is a batch file, which cmd.exe has to run.
+pub fn is_batch_file(program: &Path) -> bool {
+    match program.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => {
+            extension.eq_ignore_ascii_case("bat") || extension.eq_ignore_ascii_case("cmd")
+        }
+        None => false,
+    }
+}
+
+/// Fails if the executable is (or resolves to) a batch file. cmd.exe's
+/// parsing of its command line can't be relied on, so file names that
+/// anyone could have chosen mustn't be passed to one (see CVE-2024-24576)
+/// unless the user has said that's all right.
+pub fn check_not_batch_file(executable: &str) -> Result<(), Box<Error>> {
+    let program = resolve_program(OsStr::new(executable))
+        .unwrap_or_else(|| PathBuf::from(executable));
+    if is_batch_file(&program) {
+        return Err(From::from(format!("refusing to pass file names to the batch file ‘{}’, as \
+                                       cmd.exe may not pass them on safely (use \
+                                       --allow-batch-exec to run it anyway)",
+                                      executable)));
+    }
+    Ok(())
+}
+
+/// Quotes an argument for a program that splits its command line the way the
+/// C runtime does. Backslashes are only special before a quote, where they're
+/// doubled (including before the closing quote), and quotes are escaped with
+/// a backslash. Arguments that don't need quoting are left alone.
+pub fn quote_arg(arg: &OsStr) -> OsString {
+    let arg: Vec<u16> = arg.encode_wide().collect();
+    let needs_quotes = arg.is_empty() ||
+                       arg.iter().any(|c| [SPACE, TAB, NEWLINE, VERTICAL_TAB, QUOTE].contains(c));
+    if !needs_quotes {
+        return OsString::from_wide(&arg);
+    }
+
+    let mut quoted = vec![QUOTE];
+    let mut backslashes = 0;
+    for &c in &arg {
+        if c == BACKSLASH {
+            backslashes += 1;
+        } else {
+            if c == QUOTE {
+                quoted.extend(::std::iter::repeat(BACKSLASH).take(backslashes + 1));
+            }
+            backslashes = 0;
+        }
+        quoted.push(c);
+    }
+    quoted.extend(::std::iter::repeat(BACKSLASH).take(backslashes));
+    quoted.push(QUOTE);
+    OsString::from_wide(&quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::fs::{self, File};
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn quoted(arg: &str) -> String {
+        quote_arg(OsStr::new(arg)).into_string().unwrap()
+    }
+
+    #[test]
+    fn quote_arg_follows_c_runtime_rules() {
+        assert_eq!(quoted("plain"), "plain");
+        assert_eq!(quoted(r"C:\dir\file"), r"C:\dir\file");
+        assert_eq!(quoted(""), r#""""#);
+        assert_eq!(quoted("a b"), r#""a b""#);
+        assert_eq!(quoted(r#"a"b"#), r#""a\"b""#);
+        // backslashes are only doubled before a quote...
+        assert_eq!(quoted(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quoted(r"a\b c"), r#""a\b c""#);
+        // ...including the closing one
+        assert_eq!(quoted(r"dir with spaces\"), r#""dir with spaces\\""#);
+        assert_eq!(quoted(r"trailing\"), r"trailing\");
+    }
+
+    #[test]
+    fn resolve_program_uses_pathext() {
+        let temp_dir = TempDir::new("resolve_program_uses_pathext").unwrap();
+        let bin = temp_dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        File::create(bin.join("tool.cmd")).unwrap();
+        File::create(bin.join("other.exe")).unwrap();
+        let pathext = Some(OsStr::new(".EXE;.CMD"));
+
+        assert_eq!(resolve_program_in(Path::new("tool"), Some(bin.as_os_str()), pathext),
+                   Some(bin.join("tool.CMD")));
+        assert_eq!(resolve_program_in(&bin.join("other"), None, pathext),
+                   Some(bin.join("other.EXE")));
+        // only names without extensions are resolved
+        assert_eq!(resolve_program_in(Path::new("tool.cmd"), Some(bin.as_os_str()), pathext),
+                   None);
+        assert_eq!(resolve_program_in(Path::new("missing"), Some(bin.as_os_str()), pathext),
+                   None);
+        // and bare names aren't looked for in the current directory
+        assert_eq!(resolve_program_in(Path::new("tool"), None, pathext), None);
+    }
+
+    #[test]
+    fn batch_files() {
+        assert!(is_batch_file(Path::new("script.bat")));
+        assert!(is_batch_file(Path::new(r"C:\dir\SCRIPT.CMD")));
+        assert!(!is_batch_file(Path::new("program.exe")));
+        assert!(!is_batch_file(Path::new("program")));
+        assert!(check_not_batch_file("script.bat").is_err());
+        assert!(check_not_batch_file("program.exe").is_ok());
+    }
+}
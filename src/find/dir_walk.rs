@@ -0,0 +1,892 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Reading the directories below a start point, for find's walk. Each one is
+//! opened relative to the directory it's in (with openat, on unix) rather
+//! than by its path, which only gets longer the deeper the walk goes: so, as
+//! with GNU find, the walk can go further down than a path can be long
+//! (PATH_MAX), and entries' metadata can be looked up there too (see
+//! `Entry::dir`). And so as not to run out of file descriptors, only the
+//! last few directories down are kept open: a directory that's been closed
+//! is opened again through the ".." of the one below it, when the walk
+//! comes back up to it.
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::vec;
+
+use find::compare_file_names;
+use find::metadata::{self, FileKind, FileMetadata};
+
+/// How many of the directories a walk is in are kept open at once, not
+/// counting any that were gone into through a symbolic link (as following
+/// ".." back from one of those leads somewhere else).
+const MAX_OPEN_DIRS: usize = 8;
+
+/// A directory that's open for reading, whose entries can be looked up (and
+/// opened in turn) by name.
+#[cfg(unix)]
+pub struct Dir {
+    stream: *mut ::libc::DIR,
+}
+
+/// A directory that's open for reading. Without openat and friends, it's
+/// just a path (so nothing can be any deeper than a path can be long).
+#[cfg(not(unix))]
+pub struct Dir {
+    path: PathBuf,
+}
+
+/// What identifies a directory, to tell whether following a link would lead
+/// back to one that the walk is already in.
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq)]
+struct DirId(u64, u64);
+
+#[cfg(not(unix))]
+#[derive(Clone, Debug, PartialEq)]
+struct DirId(PathBuf);
+
+#[cfg(unix)]
+impl Dir {
+    /// Opens the directory at the given path (or, if it's a symbolic link,
+    /// the one it points to).
+    pub fn open(path: &Path) -> io::Result<Dir> {
+        Dir::open_at(::libc::AT_FDCWD, path.as_os_str(), true)
+    }
+
+    /// Opens the directory with the given name in this one. If follow_links
+    /// is false, it can't be a symbolic link.
+    pub fn open_child(&self, name: &OsStr, follow_links: bool) -> io::Result<Dir> {
+        Dir::open_at(self.fd(), name, follow_links)
+    }
+
+    fn open_at(dir_fd: ::libc::c_int, name: &OsStr, follow_links: bool) -> io::Result<Dir> {
+        let name = c_string(name)?;
+        let mut flags = ::libc::O_RDONLY | ::libc::O_DIRECTORY | ::libc::O_CLOEXEC;
+        if !follow_links {
+            flags |= ::libc::O_NOFOLLOW;
+        }
+        let fd = unsafe { ::libc::openat(dir_fd, name.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = unsafe { ::libc::fdopendir(fd) };
+        if stream.is_null() {
+            let error = io::Error::last_os_error();
+            unsafe { ::libc::close(fd) };
+            return Err(error);
+        }
+        Ok(Dir { stream: stream })
+    }
+
+    fn fd(&self) -> ::libc::c_int {
+        unsafe { ::libc::dirfd(self.stream) }
+    }
+
+    /// Looks up the metadata of the entry with the given name (or, if
+    /// follow_links is true and it's a symbolic link, of what it points to).
+    pub fn stat(&self, name: &OsStr, follow_links: bool) -> io::Result<FileMetadata> {
+        let name = c_string(name)?;
+        let flags = if follow_links { 0 } else { ::libc::AT_SYMLINK_NOFOLLOW };
+        let mut stat: ::libc::stat = unsafe { ::std::mem::zeroed() };
+        if unsafe { ::libc::fstatat(self.fd(), name.as_ptr(), &mut stat, flags) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(FileMetadata::from_stat(&stat))
+    }
+
+    /// Reads where the symbolic link with the given name points.
+    pub fn read_link(&self, name: &OsStr) -> io::Result<PathBuf> {
+        use std::os::unix::ffi::OsStringExt;
+
+        let name = c_string(name)?;
+        let mut buffer: Vec<u8> = Vec::with_capacity(256);
+        loop {
+            let len = unsafe {
+                ::libc::readlinkat(self.fd(),
+                                   name.as_ptr(),
+                                   buffer.as_mut_ptr() as *mut ::libc::c_char,
+                                   buffer.capacity())
+            };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // (if it fills the buffer, it may have been cut short)
+            if (len as usize) < buffer.capacity() {
+                unsafe { buffer.set_len(len as usize) };
+                return Ok(PathBuf::from(OsString::from_vec(buffer)));
+            }
+            let capacity = buffer.capacity();
+            buffer.reserve(capacity * 2);
+        }
+    }
+
+    fn id(&self) -> io::Result<DirId> {
+        let mut stat: ::libc::stat = unsafe { ::std::mem::zeroed() };
+        if unsafe { ::libc::fstat(self.fd(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(DirId(stat.st_dev as u64, stat.st_ino as u64))
+    }
+
+    /// Calls the function with the name of each of the directory's entries
+    /// (apart from "." and ".."), along with its type, if the directory says
+    /// what it is.
+    fn read_entries<F>(&self, mut f: F) -> io::Result<()>
+        where F: FnMut(&OsStr, Option<FileKind>)
+    {
+        use std::ffi::CStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        loop {
+            // readdir only says it's failed (rather than got to the end) by
+            // setting errno
+            clear_errno();
+            let entry = unsafe { ::libc::readdir(self.stream) };
+            if entry.is_null() {
+                let error = io::Error::last_os_error();
+                return match error.raw_os_error() {
+                    Some(0) => Ok(()),
+                    _ => Err(error),
+                };
+            }
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) }.to_bytes();
+            if name != b"." && name != b".." {
+                f(OsStr::from_bytes(name), entry_kind(unsafe { (*entry).d_type }));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Dir {
+    fn drop(&mut self) {
+        unsafe { ::libc::closedir(self.stream) };
+    }
+}
+
+#[cfg(not(unix))]
+impl Dir {
+    pub fn open(path: &Path) -> io::Result<Dir> {
+        Ok(Dir { path: path.to_path_buf() })
+    }
+
+    pub fn open_child(&self, name: &OsStr, _: bool) -> io::Result<Dir> {
+        Dir::open(&self.path.join(name))
+    }
+
+    pub fn stat(&self, name: &OsStr, follow_links: bool) -> io::Result<FileMetadata> {
+        metadata::read_metadata(&self.path.join(name), follow_links)
+    }
+
+    pub fn read_link(&self, name: &OsStr) -> io::Result<PathBuf> {
+        fs::read_link(self.path.join(name))
+    }
+
+    fn id(&self) -> io::Result<DirId> {
+        Ok(DirId(fs::canonicalize(&self.path)?))
+    }
+
+    fn read_entries<F>(&self, mut f: F) -> io::Result<()>
+        where F: FnMut(&OsStr, Option<FileKind>)
+    {
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let kind = entry.file_type().ok().map(|file_type| FileKind::from_file_type(&file_type));
+            f(&entry.file_name(), kind);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn c_string(name: &OsStr) -> io::Result<::std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(::std::ffi::CString::new(name.as_bytes())?)
+}
+
+#[cfg(unix)]
+fn clear_errno() {
+    extern "C" {
+        #[cfg_attr(any(target_os = "linux", target_os = "emscripten"),
+                   link_name = "__errno_location")]
+        #[cfg_attr(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"),
+                   link_name = "__errno")]
+        #[cfg_attr(any(target_os = "macos", target_os = "ios", target_os = "freebsd"),
+                   link_name = "__error")]
+        fn errno_location() -> *mut ::libc::c_int;
+    }
+    unsafe { *errno_location() = 0 };
+}
+
+/// The sort of file a directory entry's type (d_type) says it is. None for
+/// DT_UNKNOWN, which some file systems give for everything.
+#[cfg(unix)]
+fn entry_kind(d_type: u8) -> Option<FileKind> {
+    match d_type {
+        ::libc::DT_REG => Some(FileKind::File),
+        ::libc::DT_DIR => Some(FileKind::Dir),
+        ::libc::DT_LNK => Some(FileKind::Symlink),
+        ::libc::DT_FIFO => Some(FileKind::Fifo),
+        ::libc::DT_SOCK => Some(FileKind::Socket),
+        ::libc::DT_BLK => Some(FileKind::BlockDevice),
+        ::libc::DT_CHR => Some(FileKind::CharDevice),
+        _ => None,
+    }
+}
+
+/// What identifies the directory at the given path, which has the given
+/// metadata.
+#[cfg(unix)]
+fn dir_id(_: &Path, metadata: &FileMetadata) -> DirId {
+    DirId(metadata.dev, metadata.ino)
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _: &FileMetadata) -> DirId {
+    DirId(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// The last component of an entry's path: its name in the directory it's
+/// in (or, for a start point, the whole path).
+fn file_name(path: &Path) -> &OsStr {
+    path.file_name().unwrap_or_else(|| path.as_os_str())
+}
+
+/// Looks up the metadata of an entry (or, if follow_links is true and it's
+/// a symbolic link, of what it points to): by name, in the directory it's
+/// in, if that's given, and otherwise by its path.
+pub fn stat_in(dir: Option<&Dir>, path: &Path, follow_links: bool) -> io::Result<FileMetadata> {
+    match dir {
+        Some(dir) => dir.stat(file_name(path), follow_links),
+        None => metadata::read_metadata(path, follow_links),
+    }
+}
+
+/// Reads where an entry that's a symbolic link points, in the same way.
+pub fn read_link_in(dir: Option<&Dir>, path: &Path) -> io::Result<PathBuf> {
+    match dir {
+        Some(dir) => dir.read_link(file_name(path)),
+        None => fs::read_link(path),
+    }
+}
+
+/// Something a walk has come to.
+pub struct Entry {
+    /// The start point, with the names of the directories the entry's in
+    /// and its own name joined on.
+    pub path: PathBuf,
+    /// The directory the entry's in, which the walk has open (for anything
+    /// but a start point).
+    pub dir: Option<Rc<Dir>>,
+    /// What sort of file the entry is. For a symbolic link that the walk has
+    /// followed, that's what it points to.
+    pub file_type: FileKind,
+    /// Whether the entry's path is a symbolic link (even if it was followed).
+    pub path_is_symlink: bool,
+    /// How far below the start point the entry is.
+    pub depth: usize,
+}
+
+/// Something a walk couldn't read: the entry (or the directory whose
+/// contents) it was trying to, how deep that is, and what went wrong.
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub error: io::Error,
+}
+
+/// One of the directories a walk is in.
+struct Frame {
+    path: PathBuf,
+    /// None if it's been closed (see `MAX_OPEN_DIRS`), until the walk comes
+    /// back to it.
+    dir: Option<Rc<Dir>>,
+    id: DirId,
+    /// What's in it that the walk hasn't come to yet.
+    children: vec::IntoIter<Child>,
+    depth: usize,
+    /// Whether the walk came into it through a symbolic link.
+    through_link: bool,
+    /// With contents_first, the directory's own entry, which comes after
+    /// everything in it (when it's given the directory it's in, as that may
+    /// have been closed and opened again in the meantime).
+    entry: Option<Entry>,
+}
+
+/// An entry in a directory that's been read: its path, and its type if the
+/// directory said.
+struct Child {
+    path: PathBuf,
+    kind: Option<FileKind>,
+}
+
+/// A directory that's going to be read.
+struct Pending {
+    path: PathBuf,
+    /// The directory it's in (None for a start point).
+    parent: Option<Rc<Dir>>,
+    depth: usize,
+    through_link: bool,
+    /// The names of what's in it, if they're known already (see
+    /// `DirWalk::set_contents`).
+    contents: Option<Vec<OsString>>,
+}
+
+/// Walks everything below a start point (and the start point itself),
+/// directories first (or with `contents_first`, last), giving an `Entry` for
+/// each of them, or a `WalkError` for anything that couldn't be read. Paths
+/// are made by joining each entry's name onto its directory's path as it
+/// stands, so a start point of "dir/" gives "dir/sub", and "dir//" gives
+/// "dir//sub".
+pub struct DirWalk {
+    /// The start point, until the walk gets to it.
+    start: Option<PathBuf>,
+    follow_links: bool,
+    follow_start: bool,
+    contents_first: bool,
+    min_depth: usize,
+    max_depth: usize,
+    sort: bool,
+    frames: Vec<Frame>,
+    /// The last directory that the walk gave an entry for, which will be
+    /// read next, unless it's skipped.
+    pending: Option<Pending>,
+    /// An entry to give after an error, which took its place.
+    queued: Option<Entry>,
+}
+
+impl DirWalk {
+    pub fn new<P: Into<PathBuf>>(start: P) -> DirWalk {
+        DirWalk {
+            start: Some(start.into()),
+            follow_links: false,
+            follow_start: false,
+            contents_first: false,
+            min_depth: 0,
+            max_depth: usize::max_value(),
+            sort: false,
+            frames: vec![],
+            pending: None,
+            queued: None,
+        }
+    }
+
+    /// Sets whether symbolic links are followed (with -L): if so, they're
+    /// walked as what they point to, unless they don't point to anything,
+    /// and ones that lead back to a directory the walk is in are errors.
+    pub fn follow_links(mut self, follow_links: bool) -> DirWalk {
+        self.follow_links = follow_links;
+        self
+    }
+
+    /// Sets whether the start point is followed if it's a symbolic link,
+    /// even if nothing else is (with -H).
+    pub fn follow_start(mut self, follow_start: bool) -> DirWalk {
+        self.follow_start = follow_start;
+        self
+    }
+
+    /// Sets whether a directory's entry comes after what's in it (with
+    /// -depth), rather than before.
+    pub fn contents_first(mut self, contents_first: bool) -> DirWalk {
+        self.contents_first = contents_first;
+        self
+    }
+
+    /// Sets how deep entries have to be to be given (though anything
+    /// shallower is still walked).
+    pub fn min_depth(mut self, min_depth: usize) -> DirWalk {
+        self.min_depth = min_depth;
+        self
+    }
+
+    /// Sets how deep the walk goes.
+    pub fn max_depth(mut self, max_depth: usize) -> DirWalk {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets whether each directory's entries come in the order of their
+    /// names (see `compare_file_names`), rather than the directory's own.
+    pub fn sort(mut self, sort: bool) -> DirWalk {
+        self.sort = sort;
+        self
+    }
+
+    /// Skips what's in the directory that the walk last gave an entry for.
+    /// That makes no difference to anything else: not to an entry that
+    /// isn't a directory, nor (with contents_first) to one that's already
+    /// been read.
+    pub fn skip_current_dir(&mut self) {
+        self.pending = None;
+    }
+
+    /// Has the directory that the walk last gave an entry for walked as
+    /// though the given names were what's in it, without reading it (for
+    /// updatedb --incremental, which knows what's in a directory that hasn't
+    /// changed). Like `skip_current_dir`, it makes no difference to anything
+    /// but a directory that's yet to be read.
+    pub fn set_contents(&mut self, names: Vec<OsString>) {
+        if let Some(ref mut pending) = self.pending {
+            pending.contents = Some(names);
+        }
+    }
+
+    /// Comes to the start point.
+    fn start(&mut self, path: PathBuf) -> Option<Result<Entry, WalkError>> {
+        let failed = |path, error| {
+            Some(Err(WalkError {
+                path: path,
+                depth: 0,
+                error: error,
+            }))
+        };
+        let mut file_type = match metadata::read_metadata(&path, false) {
+            Ok(metadata) => metadata.kind,
+            Err(error) => return failed(path, error),
+        };
+        let path_is_symlink = file_type == FileKind::Symlink;
+        if path_is_symlink && (self.follow_links || self.follow_start) {
+            match metadata::read_metadata(&path, true) {
+                Ok(metadata) => file_type = metadata.kind,
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return failed(path, error),
+            }
+        }
+        self.found(Entry {
+            path: path,
+            dir: None,
+            file_type: file_type,
+            path_is_symlink: path_is_symlink,
+            depth: 0,
+        })
+    }
+
+    /// Comes to an entry in the directory the walk is in.
+    fn visit(&mut self, child: Child) -> Option<Result<Entry, WalkError>> {
+        let (dir, depth) = match self.frames.last() {
+            Some(frame) => (frame.dir.clone(), frame.depth + 1),
+            None => return None,
+        };
+        let failed = |path, error| {
+            Some(Err(WalkError {
+                path: path,
+                depth: depth,
+                error: error,
+            }))
+        };
+        let mut file_type = match child.kind {
+            Some(kind) => kind,
+            None => {
+                match stat_in(dir.as_ref().map(|dir| &**dir), &child.path, false) {
+                    Ok(metadata) => metadata.kind,
+                    Err(error) => return failed(child.path, error),
+                }
+            }
+        };
+        let path_is_symlink = file_type == FileKind::Symlink;
+        if path_is_symlink && self.follow_links {
+            match stat_in(dir.as_ref().map(|dir| &**dir), &child.path, true) {
+                Ok(metadata) => {
+                    file_type = metadata.kind;
+                    if file_type == FileKind::Dir && depth < self.max_depth {
+                        if let Some(error) = self.check_for_loop(&child.path, &metadata) {
+                            return failed(child.path, error);
+                        }
+                    }
+                }
+                // a link to nothing is just a link
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return failed(child.path, error),
+            }
+        }
+        self.found(Entry {
+            path: child.path,
+            dir: dir,
+            file_type: file_type,
+            path_is_symlink: path_is_symlink,
+            depth: depth,
+        })
+    }
+
+    /// An error if the directory that a link points to (which has the given
+    /// metadata) is one that the walk is already in.
+    fn check_for_loop(&self, path: &Path, metadata: &FileMetadata) -> Option<io::Error> {
+        let id = dir_id(path, metadata);
+        self.frames.iter().find(|frame| frame.id == id).map(|ancestor| {
+            io::Error::new(io::ErrorKind::Other,
+                           format!("File system loop found: {} points to an ancestor {}",
+                                   path.display(),
+                                   ancestor.path.display()))
+        })
+    }
+
+    /// Gives the entry (unless it's not deep enough), and arranges for what's
+    /// in it to come next, if it's a directory that the walk goes into (or,
+    /// with contents_first, reads it now, leaving the entry until the end).
+    fn found(&mut self, entry: Entry) -> Option<Result<Entry, WalkError>> {
+        if entry.file_type == FileKind::Dir && entry.depth < self.max_depth {
+            let pending = Pending {
+                path: entry.path.clone(),
+                parent: entry.dir.clone(),
+                depth: entry.depth,
+                through_link: entry.path_is_symlink,
+                contents: None,
+            };
+            if self.contents_first {
+                // (holding on to the directory it's in would keep that open
+                // until the end: it's the one the walk comes back to)
+                let entry = Entry { dir: None, ..entry };
+                return self.push(pending, Some(entry)).err().map(Err);
+            }
+            self.pending = Some(pending);
+        }
+        if entry.depth < self.min_depth {
+            return None;
+        }
+        Some(Ok(entry))
+    }
+
+    /// Reads a directory, which the walk is then in. If it can't be read,
+    /// its entry (if it's being held back until after what's in it) comes
+    /// after the error instead.
+    fn push(&mut self, pending: Pending, entry: Option<Entry>) -> Result<(), WalkError> {
+        let (dir, id, children) = match self.read(&pending) {
+            Ok(contents) => contents,
+            Err(error) => {
+                self.queued = entry.filter(|entry| entry.depth >= self.min_depth);
+                return Err(WalkError {
+                    path: pending.path,
+                    depth: pending.depth,
+                    error: error,
+                });
+            }
+        };
+        self.frames.push(Frame {
+            path: pending.path,
+            dir: Some(Rc::new(dir)),
+            id: id,
+            children: children.into_iter(),
+            depth: pending.depth,
+            through_link: pending.through_link,
+            entry: entry,
+        });
+        // the directory that's now one too many down is closed, unless
+        // there'd be no way back to it
+        let frames = self.frames.len();
+        if frames > MAX_OPEN_DIRS && !self.frames[frames - MAX_OPEN_DIRS].through_link {
+            self.frames[frames - MAX_OPEN_DIRS - 1].dir = None;
+        }
+        Ok(())
+    }
+
+    fn read(&self, pending: &Pending) -> io::Result<(Dir, DirId, Vec<Child>)> {
+        let dir = match pending.parent {
+            Some(ref parent) => parent.open_child(file_name(&pending.path), pending.through_link)?,
+            None => Dir::open(&pending.path)?,
+        };
+        let id = dir.id()?;
+        let mut children = vec![];
+        {
+            let mut add = |name: &OsStr, kind| {
+                // (with room for a separator, so it's only allocated once)
+                let mut path =
+                    PathBuf::with_capacity(pending.path.as_os_str().len() + name.len() + 1);
+                path.push(&pending.path);
+                path.push(name);
+                children.push(Child {
+                    path: path,
+                    kind: kind,
+                });
+            };
+            match pending.contents {
+                Some(ref names) => {
+                    for name in names {
+                        add(name, None);
+                    }
+                }
+                None => dir.read_entries(add)?,
+            }
+        }
+        if self.sort {
+            children.sort_by(|a, b| compare_file_names(file_name(&a.path), file_name(&b.path)));
+        }
+        Ok((dir, id, children))
+    }
+
+    /// Leaves the directory the walk is in, once it's come to everything in
+    /// it, for the one it's in, opening that again if it's been closed. If
+    /// that fails, there's no going back to the rest of what's in it.
+    fn pop(&mut self) -> Option<Result<Entry, WalkError>> {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return None,
+        };
+        let mut entry = frame.entry;
+        let mut error = None;
+        if let Some(parent) = self.frames.last_mut() {
+            if parent.dir.is_none() {
+                match reopen(frame.dir.as_ref().map(|dir| &**dir), frame.through_link, parent) {
+                    Ok(dir) => parent.dir = Some(Rc::new(dir)),
+                    Err(e) => {
+                        parent.children = vec![].into_iter();
+                        error = Some(WalkError {
+                            path: parent.path.clone(),
+                            depth: parent.depth,
+                            error: e,
+                        });
+                    }
+                }
+            }
+            if let Some(ref mut entry) = entry {
+                entry.dir = parent.dir.clone();
+            }
+        }
+        let entry = entry.filter(|entry| entry.depth >= self.min_depth);
+        match error {
+            Some(error) => {
+                self.queued = entry;
+                Some(Err(error))
+            }
+            None => entry.map(Ok),
+        }
+    }
+}
+
+/// Opens a directory that was closed along the way down (see
+/// `MAX_OPEN_DIRS`) again, through the ".." of the one the walk was in below
+/// it (or failing that, by its path), making sure that it's the same
+/// directory as before.
+fn reopen(child: Option<&Dir>, through_link: bool, frame: &Frame) -> io::Result<Dir> {
+    let dir = match child {
+        Some(child) if !through_link => child.open_child(OsStr::new(".."), false)?,
+        _ => Dir::open(&frame.path)?,
+    };
+    if dir.id()? != frame.id {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  "the directory was moved while it was being searched"));
+    }
+    Ok(dir)
+}
+
+impl Iterator for DirWalk {
+    type Item = Result<Entry, WalkError>;
+
+    fn next(&mut self) -> Option<Result<Entry, WalkError>> {
+        if let Some(start) = self.start.take() {
+            if let Some(result) = self.start(start) {
+                return Some(result);
+            }
+        }
+        loop {
+            if let Some(entry) = self.queued.take() {
+                return Some(Ok(entry));
+            }
+            if let Some(pending) = self.pending.take() {
+                if let Err(error) = self.push(pending, None) {
+                    return Some(Err(error));
+                }
+            }
+            let child = match self.frames.last_mut() {
+                Some(frame) => frame.children.next(),
+                None => return None,
+            };
+            let result = match child {
+                Some(child) => self.visit(child),
+                None => self.pop(),
+            };
+            if result.is_some() {
+                return result;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempdir::TempDir;
+
+    use find::tests::fix_up_slashes;
+    use super::*;
+
+    /// The paths of what the walk gives (or "error: " and the path of what
+    /// it couldn't read).
+    fn paths(walk: DirWalk) -> Vec<String> {
+        walk.map(|result| match result {
+                Ok(entry) => entry.path.to_string_lossy().into_owned(),
+                Err(error) => format!("error: {}", error.path.to_string_lossy()),
+            })
+            .collect()
+    }
+
+    fn expected(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| fix_up_slashes(path)).collect()
+    }
+
+    #[test]
+    fn walk_orders_and_depths() {
+        let walk = DirWalk::new("test_data/depth").sort(true);
+        assert_eq!(paths(walk),
+                   expected(&["test_data/depth",
+                              "test_data/depth/1",
+                              "test_data/depth/1/2",
+                              "test_data/depth/1/2/3",
+                              "test_data/depth/1/2/3/f3",
+                              "test_data/depth/1/2/f2",
+                              "test_data/depth/1/f1",
+                              "test_data/depth/f0"]));
+
+        let walk = DirWalk::new("test_data/depth").sort(true).contents_first(true);
+        assert_eq!(paths(walk),
+                   expected(&["test_data/depth/1/2/3/f3",
+                              "test_data/depth/1/2/3",
+                              "test_data/depth/1/2/f2",
+                              "test_data/depth/1/2",
+                              "test_data/depth/1/f1",
+                              "test_data/depth/1",
+                              "test_data/depth/f0",
+                              "test_data/depth"]));
+
+        let walk = DirWalk::new("test_data/depth/").sort(true).min_depth(2).max_depth(2);
+        assert_eq!(paths(walk),
+                   expected(&["test_data/depth/1/2", "test_data/depth/1/f1"]));
+    }
+
+    #[test]
+    fn skip_current_dir() {
+        let mut walk = DirWalk::new("test_data/depth").sort(true);
+        let mut seen = vec![];
+        while let Some(entry) = walk.next() {
+            let entry = entry.unwrap();
+            if entry.path.ends_with("1") {
+                walk.skip_current_dir();
+            }
+            seen.push(entry.path.to_string_lossy().into_owned());
+        }
+        assert_eq!(seen,
+                   expected(&["test_data/depth", "test_data/depth/1", "test_data/depth/f0"]));
+    }
+
+    #[test]
+    fn set_contents() {
+        // what's given is walked (in order, and with anything that's gone
+        // as an error) instead of what's really there
+        let mut walk = DirWalk::new("test_data/depth").sort(true);
+        let mut seen = vec![];
+        while let Some(result) = walk.next() {
+            match result {
+                Ok(entry) => {
+                    if entry.depth == 0 {
+                        walk.set_contents(vec![OsString::from("f0"), OsString::from("gone")]);
+                    }
+                    seen.push(entry.path.to_string_lossy().into_owned());
+                }
+                Err(error) => seen.push(format!("error: {}", error.path.to_string_lossy())),
+            }
+        }
+        let mut want = expected(&["test_data/depth", "test_data/depth/f0"]);
+        want.push(format!("error: {}", fix_up_slashes("test_data/depth/gone")));
+        assert_eq!(seen, want);
+    }
+
+    #[test]
+    fn entries_know_their_directories() {
+        for result in DirWalk::new("test_data/simple") {
+            let entry = result.unwrap();
+            assert_eq!(entry.dir.is_some(), entry.depth > 0);
+            let metadata = stat_in(entry.dir.as_ref().map(|dir| &**dir), &entry.path, false)
+                .unwrap();
+            // (which doesn't say when the file was created, as std does)
+            let expected = metadata::read_metadata(&entry.path, false).unwrap();
+            assert_eq!(FileMetadata { created: None, ..metadata.clone() },
+                       FileMetadata { created: None, ..expected },
+                       "{:?}",
+                       entry.path);
+            assert_eq!(metadata.kind, entry.file_type);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(paths(DirWalk::new("test_data/no_such_file")),
+                   expected(&["error: test_data/no_such_file"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new("dir_walk_links").unwrap();
+        let root = temp_dir.path().to_string_lossy().into_owned();
+        fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        symlink("..", temp_dir.path().join("dir/loop")).unwrap();
+        symlink("nowhere", temp_dir.path().join("dir/broken")).unwrap();
+        symlink("dir", temp_dir.path().join("link")).unwrap();
+        let walk = |walk: DirWalk| {
+            let paths = paths(walk.sort(true));
+            paths.iter().map(|path| path.replacen(&root, "", 1)).collect::<Vec<_>>()
+        };
+
+        // links are just links, unless they're followed
+        assert_eq!(walk(DirWalk::new(&root)),
+                   vec!["", "/dir", "/dir/broken", "/dir/loop", "/link"]);
+        assert_eq!(walk(DirWalk::new(format!("{}/link", root))), vec!["/link"]);
+        assert_eq!(walk(DirWalk::new(format!("{}/link", root)).follow_start(true)),
+                   vec!["/link", "/link/broken", "/link/loop"]);
+        // and when they are, going round a loop is an error, but a link to
+        // nothing is just a link
+        assert_eq!(walk(DirWalk::new(&root).follow_links(true)),
+                   vec!["",
+                        "/dir",
+                        "/dir/broken",
+                        "error: /dir/loop",
+                        "/link",
+                        "/link/broken",
+                        "error: /link/loop"]);
+        let entry = DirWalk::new(format!("{}/link", root)).follow_start(true).next().unwrap();
+        let entry = entry.unwrap();
+        assert_eq!((entry.file_type, entry.path_is_symlink), (FileKind::Dir, true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn deep_trees() {
+        // deeper than the number of directories kept open, so that the walk
+        // has to go back up into ones it's closed
+        let temp_dir = TempDir::new("dir_walk_deep_trees").unwrap();
+        let mut dir = temp_dir.path().to_path_buf();
+        for _ in 0..MAX_OPEN_DIRS * 2 {
+            dir.push("d");
+            fs::create_dir(&dir).unwrap();
+            fs::File::create(dir.with_file_name("f")).unwrap();
+        }
+        for &contents_first in &[false, true] {
+            let walk = DirWalk::new(temp_dir.path()).contents_first(contents_first);
+            let mut files = 0;
+            for result in walk {
+                let entry = result.unwrap();
+                if entry.depth > 0 {
+                    assert!(entry.dir.is_some());
+                    let metadata = stat_in(entry.dir.as_ref().map(|dir| &**dir), &entry.path, false)
+                        .unwrap();
+                    assert_eq!(metadata.kind, entry.file_type);
+                }
+                if entry.file_type == FileKind::File {
+                    files += 1;
+                }
+            }
+            assert_eq!(files, MAX_OPEN_DIRS * 2);
+        }
+    }
+}
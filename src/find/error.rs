@@ -0,0 +1,95 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Most errors are just strings wrapped up in a `Box<Error>`, but a few are
+//! common enough mistakes that they deserve a more helpful message - and a
+//! type of their own, so that callers (and tests) can tell them apart.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum FindError {
+    /// A non-option argument turned up in the middle of the expression (e.g.
+    /// "src" in "find -name foo src"). previous_predicate is the predicate
+    /// before it, if there was one.
+    PathsMustPrecedeExpression {
+        path: String,
+        previous_predicate: Option<String>,
+    },
+    /// A config was built (see `ConfigBuilder`) with a minimum depth greater
+    /// than its maximum, so nothing could ever be found.
+    MinDepthAboveMaxDepth { min_depth: usize, max_depth: usize },
+}
+
+/// Returns whether the shell would have treated the argument as a pattern.
+fn looks_like_pattern(arg: &str) -> bool {
+    arg.contains(|c| c == '*' || c == '?' || c == '[')
+}
+
+impl fmt::Display for FindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FindError::PathsMustPrecedeExpression { ref path, ref previous_predicate } => {
+                write!(f, "paths must precede expression: ‘{}’", path)?;
+                if let Some(ref predicate) = *previous_predicate {
+                    if looks_like_pattern(path) {
+                        write!(f, "\npossible unquoted pattern after predicate ‘{}’?", predicate)?;
+                    }
+                }
+                write!(f,
+                       "\nPut any paths before the expression, and quote patterns (e.g. -name \
+                        '*.c') to stop the shell from expanding them.")
+            }
+            FindError::MinDepthAboveMaxDepth { min_depth, max_depth } => {
+                write!(f,
+                       "the minimum depth ({}) is greater than the maximum depth ({})",
+                       min_depth,
+                       max_depth)
+            }
+        }
+    }
+}
+
+impl Error for FindError {
+    fn description(&self) -> &str {
+        match *self {
+            FindError::PathsMustPrecedeExpression { .. } => "paths must precede expression",
+            FindError::MinDepthAboveMaxDepth { .. } => "minimum depth above maximum depth",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_must_precede_expression_messages() {
+        let e = FindError::PathsMustPrecedeExpression {
+            path: "src".to_string(),
+            previous_predicate: Some("-name".to_string()),
+        };
+        let message = e.to_string();
+        assert!(message.starts_with("paths must precede expression: ‘src’\n"));
+        assert!(!message.contains("possible unquoted pattern"));
+        assert!(message.contains("quote patterns"));
+
+        let e = FindError::PathsMustPrecedeExpression {
+            path: "*.c".to_string(),
+            previous_predicate: Some("-name".to_string()),
+        };
+        assert!(e.to_string().starts_with("paths must precede expression: ‘*.c’\npossible \
+                                            unquoted pattern after predicate ‘-name’?\n"));
+    }
+
+    #[test]
+    fn min_depth_above_max_depth_message() {
+        let e = FindError::MinDepthAboveMaxDepth { min_depth: 3, max_depth: 2 };
+        assert_eq!(e.to_string(),
+                   "the minimum depth (3) is greater than the maximum depth (2)");
+    }
+}
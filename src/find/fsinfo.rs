@@ -0,0 +1,371 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Information about the filesystems that files are on, which updatedb uses
+//! to leave some types of filesystem out, find's -fstype tests, and -printf's
+//! %F prints.
+
+use std::cell::RefCell;
+#[cfg(unix)]
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+
+/// Undoes the escaping of spaces and the like (as "\040") in the mount
+/// table.
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut result = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            str::from_utf8(&bytes[i + 1..i + 4])
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                i += 4;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// A mounted filesystem.
+#[derive(Clone, Debug, PartialEq)]
+struct Mount {
+    /// Where it's mounted.
+    dir: PathBuf,
+    fs_type: Rc<str>,
+    /// The device its files are on, if that's known.
+    dev: Option<u64>,
+}
+
+/// Turns a device's major and minor numbers into a device number, as Linux
+/// encodes them (the same as `FileMetadata::dev`).
+fn linux_makedev(major: u64, minor: u64) -> u64 {
+    ((major & 0xfffff000) << 32) | ((major & 0xfff) << 8) | ((minor & 0xffffff00) << 12) |
+    (minor & 0xff)
+}
+
+/// Parses a mount table in the format of /proc/mounts (or /etc/mtab), which
+/// doesn't give devices.
+fn parse_mounts(table: &str) -> Vec<Mount> {
+    table.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            match (fields.next(), fields.next()) {
+                (Some(dir), Some(fs_type)) => {
+                    Some(Mount {
+                        dir: PathBuf::from(unescape_mount_field(dir)),
+                        fs_type: Rc::from(fs_type),
+                        dev: None,
+                    })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Parses a mount table in the format of /proc/self/mountinfo, which gives
+/// each mount's device as "major:minor" in its third field, and its type
+/// after a "-" that ends a list of optional fields.
+fn parse_mountinfo(table: &str) -> Vec<Mount> {
+    table.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let separator = fields.iter().skip(6).position(|field| *field == "-")? + 6;
+            let mut numbers = fields[2].splitn(2, ':').map(|number| number.parse::<u64>());
+            let dev = match (numbers.next(), numbers.next()) {
+                (Some(Ok(major)), Some(Ok(minor))) => linux_makedev(major, minor),
+                _ => return None,
+            };
+            Some(Mount {
+                dir: PathBuf::from(unescape_mount_field(fields[4])),
+                fs_type: Rc::from(*fields.get(separator + 1)?),
+                dev: Some(dev),
+            })
+        })
+        .collect()
+}
+
+/// Fills in the devices of mounts that don't say, by looking at their mount
+/// points (which aren't on the filesystem mounted there if they're hidden
+/// by a later mount, but then the later one comes first when looking a
+/// device up).
+#[cfg(unix)]
+fn stat_mount_points(mounts: &mut Vec<Mount>) {
+    use std::os::unix::fs::MetadataExt;
+
+    for mount in mounts.iter_mut().filter(|mount| mount.dev.is_none()) {
+        mount.dev = fs::metadata(&mount.dir).ok().map(|metadata| metadata.dev());
+    }
+}
+
+/// Reads the first of the given files that can be read.
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "freebsd"))))]
+fn read_first(paths: &[&str]) -> Option<String> {
+    paths.iter().filter_map(|path| fs::read_to_string(path).ok()).next()
+}
+
+/// Reads the system's mount table. Linux's mountinfo gives every mount's
+/// device, so nothing has to be looked at to find them.
+#[cfg(target_os = "linux")]
+fn read_system_mounts() -> Vec<Mount> {
+    if let Some(table) = read_first(&["/proc/self/mountinfo"]) {
+        return parse_mountinfo(&table);
+    }
+    let mut mounts = parse_mounts(&read_first(&["/proc/self/mounts", "/etc/mtab"])
+        .unwrap_or_default());
+    stat_mount_points(&mut mounts);
+    mounts
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+fn read_system_mounts() -> Vec<Mount> {
+    use std::ffi::CStr;
+    use std::ptr;
+    use std::slice;
+
+    let mut buffer: *mut ::libc::statfs = ptr::null_mut();
+    // the buffer belongs to the C library, which reuses it next time
+    let count = unsafe { ::libc::getmntinfo(&mut buffer, ::libc::MNT_NOWAIT) };
+    if count <= 0 || buffer.is_null() {
+        return vec![];
+    }
+    let mut mounts: Vec<Mount> = unsafe { slice::from_raw_parts(buffer, count as usize) }
+        .iter()
+        .map(|info| {
+            let dir = unsafe { CStr::from_ptr(info.f_mntonname.as_ptr()) };
+            let fs_type = unsafe { CStr::from_ptr(info.f_fstypename.as_ptr()) };
+            Mount {
+                dir: PathBuf::from(dir.to_string_lossy().into_owned()),
+                fs_type: Rc::from(fs_type.to_string_lossy().as_ref()),
+                dev: None,
+            }
+        })
+        .collect();
+    stat_mount_points(&mut mounts);
+    mounts
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))))]
+fn read_system_mounts() -> Vec<Mount> {
+    let mut mounts = parse_mounts(&read_first(&["/etc/mtab"]).unwrap_or_default());
+    stat_mount_points(&mut mounts);
+    mounts
+}
+
+/// There's no mount table to read here, so no file is on any particular
+/// type of filesystem.
+#[cfg(not(unix))]
+fn read_system_mounts() -> Vec<Mount> {
+    vec![]
+}
+
+/// The mounted filesystems, so that we can tell what type of filesystem a
+/// file is on (for -fstype and -printf's %F, and updatedb's --prunefs). The
+/// system's table is read the first time it's needed, as most runs of find
+/// don't need it, and then kept until it's refreshed.
+pub struct MountTable {
+    read: Box<Fn() -> Vec<Mount>>,
+    /// Mount points and the types of filesystem mounted there, in the order
+    /// they were mounted. None until the table's been read.
+    mounts: RefCell<Option<Vec<Mount>>>,
+}
+
+impl MountTable {
+    /// The system's mount table.
+    pub fn new() -> MountTable {
+        MountTable {
+            read: Box::new(read_system_mounts),
+            mounts: RefCell::new(None),
+        }
+    }
+
+    fn with_mounts(mounts: Vec<Mount>) -> MountTable {
+        MountTable {
+            read: Box::new(move || mounts.clone()),
+            mounts: RefCell::new(None),
+        }
+    }
+
+    /// A table in the format of /proc/mounts (or /etc/mtab), which doesn't
+    /// give devices, so no file is found by device. Mainly useful for tests.
+    pub fn parse(table: &str) -> MountTable {
+        MountTable::with_mounts(parse_mounts(table))
+    }
+
+    /// A table in the format of Linux's /proc/self/mountinfo. Mainly useful
+    /// for tests.
+    pub fn parse_mountinfo(table: &str) -> MountTable {
+        MountTable::with_mounts(parse_mountinfo(table))
+    }
+
+    /// Makes the table be read again the next time it's needed, for when
+    /// filesystems may have been mounted or unmounted since.
+    pub fn refresh(&self) {
+        *self.mounts.borrow_mut() = None;
+    }
+
+    /// Returns the type of filesystem of the mount that `better` prefers to
+    /// the best one before it (if any). Later mounts hide earlier ones, so
+    /// it's the last one it prefers to all those before.
+    fn find<F>(&self, better: F) -> Option<Rc<str>>
+        where F: Fn(&Mount, Option<&Mount>) -> bool
+    {
+        let mut mounts = self.mounts.borrow_mut();
+        if mounts.is_none() {
+            *mounts = Some((self.read)());
+        }
+        let mut best: Option<&Mount> = None;
+        for mount in mounts.as_ref().unwrap() {
+            if better(mount, best) {
+                best = Some(mount);
+            }
+        }
+        best.map(|mount| mount.fs_type.clone())
+    }
+
+    /// Returns the type of filesystem that the given (absolute, canonical)
+    /// path is on: that of the most recent mount on the closest mount point
+    /// above it.
+    pub fn fs_type(&self, path: &Path) -> Option<Rc<str>> {
+        self.find(|mount, best| {
+            path.starts_with(&mount.dir) &&
+            best.map_or(true, |best| {
+                mount.dir.components().count() >= best.dir.components().count()
+            })
+        })
+    }
+
+    /// Returns the type of filesystem on the given device (as in
+    /// `FileMetadata::dev`), if it's mounted. Bind mounts of the same device
+    /// have the same type, so it doesn't matter which is found.
+    pub fn fs_type_for_dev(&self, dev: u64) -> Option<Rc<str>> {
+        self.find(|mount, _| mount.dev == Some(dev))
+    }
+}
+
+impl Default for MountTable {
+    fn default() -> MountTable {
+        MountTable::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::*;
+
+    /// Part of a real /proc/self/mountinfo, with a bind mount of /home/user
+    /// (the same device as /home, but only what's under /user in it), a
+    /// mount point with a space, and mounts with and without optional fields.
+    const MOUNTINFO: &'static str = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw,errors=remount-ro
+23 22 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw
+40 22 259:3 / /home rw,relatime shared:29 - xfs /dev/nvme0n1p3 rw,attr2,inode64
+41 22 259:3 /user /srv/user\\040data rw,relatime shared:29 - xfs /dev/nvme0n1p3 rw
+42 22 0:45 / /tmp rw,nosuid,nodev - tmpfs tmpfs rw,size=8G
+43 23 0:46 / /proc rw,relatime - tmpfs none rw
+44 22 0:47 / /mnt/remote rw,relatime master:3 shared:40 - fuse.sshfs host: rw,user_id=0
+bad line
+45 22 x:y / /bad rw - ext4 /dev/bad rw
+";
+
+    fn fs_type(table: &MountTable, path: &str) -> Option<String> {
+        table.fs_type(Path::new(path)).map(|fs_type| fs_type.to_string())
+    }
+
+    fn fs_type_for_dev(table: &MountTable, major: u64, minor: u64) -> Option<String> {
+        table.fs_type_for_dev(linux_makedev(major, minor)).map(|fs_type| fs_type.to_string())
+    }
+
+    #[test]
+    fn mount_table_fs_type() {
+        let table = MountTable::parse("/dev/sda1 / ext4 rw 0 0\n\
+                                       proc /proc proc rw 0 0\n\
+                                       server:/ /mnt/with\\040space nfs rw 0 0\n\
+                                       tmpfs /proc tmpfs rw 0 0\n\
+                                       bad line\n");
+        assert_eq!(fs_type(&table, "/"), Some("ext4".to_string()));
+        assert_eq!(fs_type(&table, "/home/user"), Some("ext4".to_string()));
+        // the later mount hides the earlier one
+        assert_eq!(fs_type(&table, "/proc/1"), Some("tmpfs".to_string()));
+        assert_eq!(fs_type(&table, "/procfoo"), Some("ext4".to_string()));
+        assert_eq!(fs_type(&table, "/mnt/with space/x"), Some("nfs".to_string()));
+        assert_eq!(fs_type(&MountTable::parse(""), "/"), None);
+        // this format doesn't say which device is which
+        assert_eq!(fs_type_for_dev(&table, 8, 1), None);
+    }
+
+    #[test]
+    fn mountinfo_fs_type() {
+        let table = MountTable::parse_mountinfo(MOUNTINFO);
+        assert_eq!(fs_type(&table, "/srv/user data/file"), Some("xfs".to_string()));
+        assert_eq!(fs_type(&table, "/proc/1"), Some("tmpfs".to_string()));
+        assert_eq!(fs_type(&table, "/mnt/remote"), Some("fuse.sshfs".to_string()));
+        // lines that don't make sense are left out
+        assert_eq!(fs_type(&table, "/bad"), Some("ext4".to_string()));
+
+        assert_eq!(fs_type_for_dev(&table, 259, 2), Some("ext4".to_string()));
+        // both mounts of the device say the same
+        assert_eq!(fs_type_for_dev(&table, 259, 3), Some("xfs".to_string()));
+        assert_eq!(fs_type_for_dev(&table, 0, 45), Some("tmpfs".to_string()));
+        assert_eq!(fs_type_for_dev(&table, 0, 47), Some("fuse.sshfs".to_string()));
+        assert_eq!(fs_type_for_dev(&table, 8, 1), None);
+    }
+
+    #[test]
+    fn device_numbers() {
+        assert_eq!(linux_makedev(8, 1), 0x801);
+        assert_eq!(linux_makedev(259, 3), 0x10303);
+        assert_eq!(linux_makedev(0x1234, 0x56789), 0x1000_5672_3489);
+    }
+
+    #[test]
+    fn refresh_reads_again() {
+        use std::cell::Cell;
+
+        let reads = Rc::new(Cell::new(0));
+        let counter = reads.clone();
+        let table = MountTable {
+            read: Box::new(move || {
+                counter.set(counter.get() + 1);
+                parse_mountinfo(MOUNTINFO)
+            }),
+            mounts: RefCell::new(None),
+        };
+        assert_eq!(reads.get(), 0);
+        assert_eq!(fs_type_for_dev(&table, 259, 2), Some("ext4".to_string()));
+        assert_eq!(fs_type(&table, "/tmp"), Some("tmpfs".to_string()));
+        assert_eq!(reads.get(), 1);
+        table.refresh();
+        assert_eq!(reads.get(), 1);
+        assert_eq!(fs_type(&table, "/tmp"), Some("tmpfs".to_string()));
+        assert_eq!(reads.get(), 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn system_mount_table() {
+        use std::os::unix::fs::MetadataExt;
+
+        // whatever's mounted where the tests are, it's in the table
+        let dev = fs::metadata("test_data").unwrap().dev();
+        assert!(MountTable::new().fs_type_for_dev(dev).is_some());
+    }
+}
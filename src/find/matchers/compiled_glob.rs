@@ -0,0 +1,290 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Shell wildcard patterns, as used by -name and -path. Most of the entries
+//! find comes across don't match, and the patterns people use (e.g. "*.rs"
+//! or "build/*") usually have a literal prefix or suffix that rules them out
+//! much more cheaply than going through the whole pattern does.
+
+use glob::{MatchOptions, Pattern, PatternError};
+use std::fmt;
+use std::path;
+
+#[cfg(test)]
+use std::cell::Cell;
+
+#[cfg(test)]
+thread_local! {
+    /// How many times `CompiledGlob::matches` decided the answer without the
+    /// glob crate's help, so that tests can tell whether the fast path kicked
+    /// in.
+    static FAST_PATH_DECISIONS: Cell<usize> = Cell::new(0);
+}
+
+/// A `glob::Pattern`, along with the literal text that any string it
+/// matches has to start and end with. Matching gives exactly the same
+/// results as `Pattern::matches` would (or, for a caseless glob, as
+/// `Pattern::matches_with` would ignoring case).
+#[derive(Clone)]
+pub struct CompiledGlob {
+    pattern: Pattern,
+    prefix: String,
+    suffix: String,
+    /// Whether the pattern has no wildcards at all, in which case `prefix`
+    /// is the whole of it.
+    literal: bool,
+    /// False for a caseless glob, whose pattern is in lower case.
+    case_sensitive: bool,
+}
+
+/// Whether the character can be part of a literal prefix or suffix. As well
+/// as wildcards, this rules out brackets (rather than working out which of
+/// them close a class), and, on Windows, path separators (which the glob
+/// crate treats as equal to each other).
+fn is_plain(c: char) -> bool {
+    !(c == '*' || c == '?' || c == '[' || c == ']' || (cfg!(windows) && path::is_separator(c)))
+}
+
+impl CompiledGlob {
+    pub fn new(pattern_string: &str) -> Result<CompiledGlob, PatternError> {
+        let pattern = Pattern::new(pattern_string)?;
+        // "**" swallows the separator after it, so what follows isn't quite
+        // what the pattern's text suggests: such patterns don't get the fast
+        // path.
+        if pattern_string.contains("**") {
+            return Ok(CompiledGlob {
+                pattern: pattern,
+                prefix: String::new(),
+                suffix: String::new(),
+                literal: false,
+                case_sensitive: true,
+            });
+        }
+        let prefix_end = pattern_string.find(|c| !is_plain(c)).unwrap_or(pattern_string.len());
+        let suffix_start = pattern_string.rfind(|c| !is_plain(c))
+            .map_or(pattern_string.len(), |i| i + 1);
+        Ok(CompiledGlob {
+            pattern: pattern,
+            prefix: pattern_string[..prefix_end].to_string(),
+            suffix: pattern_string[suffix_start.max(prefix_end)..].to_string(),
+            literal: prefix_end == pattern_string.len(),
+            case_sensitive: true,
+        })
+    }
+
+    /// Compiles a pattern that ignores case (for -iname and -ipath). The
+    /// glob crate only ignores the case of ASCII characters, so the pattern
+    /// is put into lower case, as are any strings with other characters in
+    /// them before they're matched.
+    pub fn new_caseless(pattern_string: &str) -> Result<CompiledGlob, PatternError> {
+        let mut glob = CompiledGlob::new(&pattern_string.to_lowercase())?;
+        glob.case_sensitive = false;
+        Ok(glob)
+    }
+
+    /// The literal text that anything the pattern matches starts with
+    /// (which, if the pattern is literal, is all it matches).
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The literal text that anything the pattern matches ends with.
+    pub fn suffix(&self) -> &str {
+        &self.suffix
+    }
+
+    /// Whether the pattern has no wildcards, and so only matches its prefix.
+    pub fn is_literal(&self) -> bool {
+        self.literal
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        if !self.case_sensitive && !s.is_ascii() {
+            return self.matches_folded(&s.to_lowercase());
+        }
+        self.matches_folded(s)
+    }
+
+    /// Matches a string in which, for a caseless glob, anything that isn't
+    /// ASCII has been put in lower case.
+    fn matches_folded(&self, s: &str) -> bool {
+        // The prefix and suffix are separate parts of the pattern (unless
+        // it's literal, when there's no suffix), each matching exactly the
+        // characters they consist of (give or take case).
+        // (The comparisons are of bytes, as the prefix and suffix needn't
+        // end on a character boundary in the string.)
+        let bytes = s.as_bytes();
+        if bytes.len() < self.prefix.len() + self.suffix.len() ||
+           !self.text_equal(&bytes[..self.prefix.len()], self.prefix.as_bytes()) ||
+           !self.text_equal(&bytes[bytes.len() - self.suffix.len()..], self.suffix.as_bytes()) {
+            record_fast_path_decision();
+            return false;
+        }
+        if self.literal {
+            record_fast_path_decision();
+            return s.len() == self.prefix.len();
+        }
+        let options = MatchOptions { case_sensitive: self.case_sensitive, ..MatchOptions::new() };
+        self.pattern.matches_with(s, &options)
+    }
+
+    fn text_equal(&self, a: &[u8], b: &[u8]) -> bool {
+        if self.case_sensitive {
+            a == b
+        } else {
+            a.eq_ignore_ascii_case(b)
+        }
+    }
+}
+
+#[cfg(test)]
+fn record_fast_path_decision() {
+    FAST_PATH_DECISIONS.with(|decisions| decisions.set(decisions.get() + 1));
+}
+
+#[cfg(not(test))]
+fn record_fast_path_decision() {}
+
+impl fmt::Display for CompiledGlob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.pattern.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glob::Pattern;
+    use super::*;
+
+    fn fast_path_decisions() -> usize {
+        FAST_PATH_DECISIONS.with(|decisions| decisions.get())
+    }
+
+    #[test]
+    fn prefix_and_suffix() {
+        let glob = |pattern| {
+            let glob = CompiledGlob::new(pattern).unwrap();
+            (glob.prefix, glob.suffix, glob.literal)
+        };
+        let owned = |prefix: &str, suffix: &str, literal| {
+            (prefix.to_string(), suffix.to_string(), literal)
+        };
+        assert_eq!(glob("*.rs"), owned("", ".rs", false));
+        assert_eq!(glob("build/*"), owned("build/", "", false));
+        assert_eq!(glob("a*b?c"), owned("a", "c", false));
+        assert_eq!(glob("[ab]cd"), owned("", "cd", false));
+        assert_eq!(glob("ab[]]"), owned("ab", "", false));
+        assert_eq!(glob("Makefile"), owned("Makefile", "", true));
+        assert_eq!(glob(""), owned("", "", true));
+        assert_eq!(glob("src/**/*.rs"), owned("", "", false));
+    }
+
+    /// The fast path is only worth having if it's actually taken: these
+    /// names are all decided by the prefix or suffix alone, and only the
+    /// last needs the full pattern.
+    #[test]
+    fn fast_path_kicks_in() {
+        let glob = CompiledGlob::new("*.rs").unwrap();
+        let before = fast_path_decisions();
+        assert!(!glob.matches("main.c"));
+        assert!(!glob.matches("rs"));
+        assert_eq!(fast_path_decisions(), before + 2);
+        assert!(glob.matches("main.rs"));
+        assert_eq!(fast_path_decisions(), before + 2);
+
+        let glob = CompiledGlob::new("Makefile").unwrap();
+        let before = fast_path_decisions();
+        assert!(glob.matches("Makefile"));
+        assert!(!glob.matches("Makefile.am"));
+        assert_eq!(fast_path_decisions(), before + 2);
+    }
+
+    /// Compares the results of every combination of a corpus of patterns
+    /// and strings with those of the glob crate, which `CompiledGlob` has to
+    /// agree with exactly.
+    #[test]
+    fn agrees_with_glob() {
+        let pieces = ["", "a", "b", ".", "/", "ab", "ba", "a.b", "*", "?", "[ab]", "[!a]", "[]]",
+                      "[a-c]", "**", "]"];
+        let mut patterns = vec![];
+        for first in &pieces {
+            for second in &pieces {
+                for third in &pieces {
+                    patterns.push(format!("{}{}{}", first, second, third));
+                }
+            }
+        }
+        let letters = ["", "a", "b", "c", ".", "/", "]", "[", "*", "?", "ab", "a.b"];
+        let mut strings = vec![];
+        for first in &letters {
+            for second in &letters {
+                for third in &letters {
+                    strings.push(format!("{}{}{}", first, second, third));
+                }
+            }
+        }
+
+        let mut compared = 0;
+        for pattern_string in &patterns {
+            let pattern = match Pattern::new(pattern_string) {
+                Ok(pattern) => pattern,
+                Err(_) => {
+                    assert!(CompiledGlob::new(pattern_string).is_err());
+                    continue;
+                }
+            };
+            let glob = CompiledGlob::new(pattern_string).unwrap();
+            for s in &strings {
+                assert_eq!(glob.matches(s),
+                           pattern.matches(s),
+                           "pattern {:?}, string {:?}",
+                           pattern_string,
+                           s);
+                compared += 1;
+            }
+        }
+        assert!(compared > 1000000, "only {} comparisons", compared);
+    }
+
+    #[test]
+    fn caseless() {
+        let glob = CompiledGlob::new_caseless("Make*").unwrap();
+        for s in &["Makefile", "MAKEFILE", "makefile.am"] {
+            assert!(glob.matches(s), "{}", s);
+        }
+        assert!(!glob.matches("GNUmakefile"));
+        assert_eq!(glob.to_string(), "make*");
+
+        // the glob crate only ignores the case of ASCII characters, but
+        // everything else is put in lower case before it gets that far
+        let glob = CompiledGlob::new_caseless("ÄB?").unwrap();
+        assert!(glob.matches("äbc"));
+        assert!(glob.matches("ÄBc"));
+        assert!(!glob.matches("abc"));
+
+        // the prefix can end in the middle of a character of the string
+        assert!(!CompiledGlob::new_caseless("a*").unwrap().matches("é"));
+        assert!(!CompiledGlob::new("a*").unwrap().matches("é"));
+
+        // otherwise, it's the same as putting both in lower case
+        let pieces = ["", "a", "B", "Ab", "*", "?", "[a-c]", "[A-C]", "[!a]", "[bB]"];
+        let strings = ["", "a", "A", "b", "B", "ab", "AB", "aB", "c", "C", "d", "abc", "ABC"];
+        for first in &pieces {
+            for second in &pieces {
+                let pattern_string = format!("{}{}", first, second);
+                let glob = CompiledGlob::new_caseless(&pattern_string).unwrap();
+                let pattern = Pattern::new(&pattern_string.to_lowercase()).unwrap();
+                for s in &strings {
+                    assert_eq!(glob.matches(s),
+                               pattern.matches(&s.to_lowercase()),
+                               "pattern {:?}, string {:?}",
+                               pattern_string,
+                               s);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! SELinux security contexts, for -context and -printf's %Z. Reading them
+//! needs the "selinux" feature (and Linux). Without it, both are still
+//! recognised, but rejected with an error saying why, so that scripts using
+//! them get a sensible message rather than "unknown predicate".
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::compiled_glob::CompiledGlob;
+
+/// Whether security contexts can be read in this build.
+pub const SUPPORTED: bool = cfg!(all(feature = "selinux", target_os = "linux"));
+
+/// The error for -context and %Z when security contexts can't be read.
+pub fn unsupported() -> Box<Error> {
+    From::from("find was compiled without SELinux support")
+}
+
+/// Fails with `unsupported()` unless security contexts can be read.
+pub fn check_supported() -> Result<(), Box<Error>> {
+    if SUPPORTED { Ok(()) } else { Err(unsupported()) }
+}
+
+/// Reads the security context of the file at the given path (or, if
+/// follow_links is true and it's a symbolic link, of its target).
+#[cfg(all(feature = "selinux", target_os = "linux"))]
+fn read_context(path: &::std::path::Path, follow_links: bool) -> io::Result<Vec<u8>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = b"security.selinux\0".as_ptr() as *const ::libc::c_char;
+    let mut context = vec![0u8; 256];
+    loop {
+        let buffer = context.as_mut_ptr() as *mut ::libc::c_void;
+        let len = unsafe {
+            if follow_links {
+                ::libc::getxattr(path.as_ptr(), name, buffer, context.len())
+            } else {
+                ::libc::lgetxattr(path.as_ptr(), name, buffer, context.len())
+            }
+        };
+        if len >= 0 {
+            context.truncate(len as usize);
+            // the kernel includes the terminating NUL
+            if context.last() == Some(&0) {
+                context.pop();
+            }
+            return Ok(context);
+        }
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(::libc::ERANGE) {
+            return Err(e);
+        }
+        let len = context.len() * 2;
+        context.resize(len, 0);
+    }
+}
+
+#[cfg(not(all(feature = "selinux", target_os = "linux")))]
+fn read_context(_: &::std::path::Path, _: bool) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Other, unsupported().to_string()))
+}
+
+/// Returns the entry's security context. As with metadata, when links are
+/// followed for the entry, that's the context of what a symbolic link points
+/// to, unless that doesn't exist.
+pub fn security_context(file_info: &WalkEntry, matcher_io: &MatcherIO) -> io::Result<Vec<u8>> {
+    if matcher_io.follows_symlinks(file_info) {
+        match read_context(file_info.path(), true) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            result => return result,
+        }
+    }
+    read_context(file_info.path(), false)
+}
+
+/// This matcher compares a file's security context against a shell wildcard
+/// pattern, as -context does.
+pub struct ContextMatcher {
+    pattern: CompiledGlob,
+}
+
+impl ContextMatcher {
+    pub fn new(pattern_string: &str) -> Result<ContextMatcher, Box<Error>> {
+        check_supported()?;
+        Ok(ContextMatcher { pattern: CompiledGlob::new(pattern_string)? })
+    }
+
+    pub fn new_box(pattern_string: &str) -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(ContextMatcher::new(pattern_string)?))
+    }
+}
+
+impl Matcher for ContextMatcher {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match security_context(file_info, matcher_io) {
+            Ok(context) => self.pattern.matches(&String::from_utf8_lossy(&context)),
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                false
+            }
+        }
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "context({})", self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use find::matchers::printf::Printf;
+    use super::*;
+
+    #[test]
+    fn unsupported_error() {
+        assert_eq!(unsupported().to_string(), "find was compiled without SELinux support");
+        // -context and %Z are there either way, but only work with support
+        let printf = Printf::new("%Z\n").map(|printf| Box::new(printf) as Box<Matcher>);
+        for result in &[ContextMatcher::new_box("*"), printf] {
+            match *result {
+                Ok(_) => assert!(SUPPORTED),
+                Err(ref e) => {
+                    assert!(!SUPPORTED);
+                    assert_eq!(e.to_string(), unsupported().to_string());
+                }
+            }
+        }
+    }
+
+    #[cfg(all(feature = "selinux", target_os = "linux"))]
+    #[test]
+    fn context_matcher() {
+        use find::matchers::tests::get_dir_entry_for;
+        use find::tests::FakeDependencies;
+
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let deps = FakeDependencies::new();
+        // only where files have contexts (i.e. SELinux is enabled)
+        let context = match security_context(&abbbc, &deps.new_matcher_io()) {
+            Ok(context) => String::from_utf8(context).unwrap(),
+            Err(_) => return,
+        };
+        let matches = |pattern: &str| {
+            ContextMatcher::new(pattern).unwrap().matches(&abbbc, &mut deps.new_matcher_io())
+        };
+        assert!(matches(&context));
+        assert!(matches("*:*"));
+        assert!(!matches("*no such type*"));
+    }
+}
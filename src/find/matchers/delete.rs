@@ -8,13 +8,14 @@
  */
 
 use std::env;
-use std::io::{self, stderr, Write};
-use std::fs::{self, FileType};
+use std::fmt;
+use std::io;
+use std::fs;
 use std::path::{Path, PathBuf};
 
-use walkdir::DirEntry;
 
-use find::matchers::{Matcher, MatcherIO};
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::metadata::FileKind;
 
 pub struct DeleteMatcher {
     current_dir: PathBuf
@@ -31,7 +32,7 @@ impl DeleteMatcher {
         Ok(Box::new(DeleteMatcher::new()?))
     }
 
-    fn delete(&self, file_path: &Path, file_type: FileType) -> io::Result<()> {
+    fn delete(&self, file_path: &Path, file_type: FileKind) -> io::Result<()> {
         if file_type.is_dir() {
             fs::remove_dir(file_path)
         } else {
@@ -41,7 +42,7 @@ impl DeleteMatcher {
 }
 
 impl Matcher for DeleteMatcher {
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
         let path = file_info.path();
         if path == self.current_dir {
             return false;
@@ -49,11 +50,8 @@ impl Matcher for DeleteMatcher {
 
         match self.delete(path, file_info.file_type()) {
             Ok(_) => true,
-            Err(f) => {
-                writeln!(&mut stderr(),
-                         "Failed to delete {}: {}",
-                         file_info.path().to_string_lossy(),
-                         f).unwrap();
+            Err(e) => {
+                report_io_error(path, "cannot delete", &e, matcher_io);
                 false
             }
         }
@@ -62,6 +60,10 @@ impl Matcher for DeleteMatcher {
     fn has_side_effects(&self) -> bool {
         true
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "delete")
+    }
 }
 
 #[cfg(test)]
@@ -4,14 +4,15 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
+use std::cell::RefCell;
 use std::error::Error;
-use std::ffi::OsString;
-use std::io::{Write, stderr};
-use std::path::Path;
-use std::process::Command;
-use walkdir::DirEntry;
+use std::fmt;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 
-use find::matchers::{Matcher, MatcherIO};
+use exec::{self, BatchLimits, ChildOutcome, CommandBatcher};
+use find::matchers::{report_io_error, write_io_error, Matcher, MatcherIO, WalkEntry};
 
 enum Arg {
     Filename,
@@ -22,6 +23,7 @@ pub struct SingleExecMatcher {
     executable: String,
     args: Vec<Arg>,
     exec_in_parent_dir: bool,
+    ask_first: bool,
 }
 
 impl SingleExecMatcher {
@@ -29,6 +31,24 @@ impl SingleExecMatcher {
                args: &[&str],
                exec_in_parent_dir: bool)
                -> Result<SingleExecMatcher, Box<Error>> {
+        SingleExecMatcher::new_impl(executable, args, exec_in_parent_dir, false)
+    }
+
+    /// Creates a matcher that asks the user whether to run the command each
+    /// time (i.e. for -ok and -okdir). If the answer's no, the file doesn't
+    /// match.
+    pub fn new_with_confirmation(executable: &str,
+                                 args: &[&str],
+                                 exec_in_parent_dir: bool)
+                                 -> Result<SingleExecMatcher, Box<Error>> {
+        SingleExecMatcher::new_impl(executable, args, exec_in_parent_dir, true)
+    }
+
+    fn new_impl(executable: &str,
+                args: &[&str],
+                exec_in_parent_dir: bool,
+                ask_first: bool)
+                -> Result<SingleExecMatcher, Box<Error>> {
 
         let transformed_args = args.iter()
             .map(|&a| match a {
@@ -41,6 +61,7 @@ impl SingleExecMatcher {
             executable: executable.to_string(),
             args: transformed_args,
             exec_in_parent_dir: exec_in_parent_dir,
+            ask_first: ask_first,
         })
     }
 
@@ -50,49 +71,312 @@ impl SingleExecMatcher {
                    -> Result<Box<Matcher>, Box<Error>> {
         Ok(Box::new(SingleExecMatcher::new(executable, args, exec_in_parent_dir)?))
     }
+
+    pub fn new_box_with_confirmation(executable: &str,
+                                     args: &[&str],
+                                     exec_in_parent_dir: bool)
+                                     -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(SingleExecMatcher::new_with_confirmation(executable,
+                                                             args,
+                                                             exec_in_parent_dir)?))
+    }
+}
+
+/// For -execdir and friends: the directory a path is in, and its name
+/// there. The name is always a single component: the last one of the path
+/// as given (so "dir/.." is ".." in "dir"), or "." for a path that's only a
+/// root (which is its own directory).
+fn split_for_command(path: &Path) -> (PathBuf, OsString) {
+    let mut components = path.components();
+    let name = match components.next_back() {
+        Some(Component::RootDir) |
+        Some(Component::Prefix(_)) => return (path.to_path_buf(), OsString::from(".")),
+        Some(component) => component.as_os_str().to_os_string(),
+        None => OsString::from("."),
+    };
+    let dir = components.as_path();
+    if dir.as_os_str().is_empty() {
+        (PathBuf::from("."), name)
+    } else {
+        (dir.to_path_buf(), name)
+    }
+}
+
+/// The path to pass to the command for the given entry: the path itself, or
+/// for -execdir and friends, its name prefixed with "./". Even a name like
+/// "-rf" (or "." or "..") is then never mistaken for an option, and the
+/// command only ever gets a path relative to the directory it's run in.
+fn path_for_command(file_info: &WalkEntry, exec_in_parent_dir: bool) -> PathBuf {
+    if exec_in_parent_dir {
+        Path::new(".").join(split_for_command(file_info.path()).1)
+    } else {
+        file_info.path().to_path_buf()
+    }
+}
+
+/// The directory to run the command in for the given entry: the one it's in
+/// for -execdir and friends (see `command_dir`), and otherwise our own
+/// working directory.
+fn dir_for_command(file_info: &WalkEntry,
+                   exec_in_parent_dir: bool)
+                   -> io::Result<Option<PathBuf>> {
+    if exec_in_parent_dir {
+        command_dir(&split_for_command(file_info.path()).0).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// The directory is resolved to its canonical path before the command's
+/// started in it, so that the command starts where the walk found the file
+/// even if a directory on the way is replaced by a symbolic link in the
+/// meantime (except while it's being resolved).
+#[cfg(unix)]
+fn command_dir(dir: &Path) -> io::Result<PathBuf> {
+    ::std::fs::canonicalize(dir)
+}
+
+/// Windows' canonical paths are verbatim ones (starting "\\?\"), which not
+/// every program can be started in, so the directory's used as it is.
+#[cfg(not(unix))]
+fn command_dir(dir: &Path) -> io::Result<PathBuf> {
+    Ok(dir.to_path_buf())
+}
+
+/// Runs the command, after flushing our own output so that it appears
+/// before anything the command writes. As with GNU find, a command that
+/// can't be run (or is killed) is reported, and doesn't succeed.
+fn run_command<I, S>(executable: &str,
+                     args: I,
+                     dir: Option<&Path>,
+                     matcher_io: &mut MatcherIO)
+                     -> bool
+    where I: IntoIterator<Item = S>,
+          S: AsRef<OsStr>
+{
+    let mut command = exec::new_command(executable, args, dir);
+    matcher_io.flush_output();
+    matcher_io.record_command_run();
+    match exec::run(&mut command) {
+        ChildOutcome::NotRun(e) => {
+            write_io_error(Path::new(executable), "", &e, matcher_io);
+            false
+        }
+        ChildOutcome::Killed(signal) => {
+            writeln!(matcher_io.deps.get_error_output().borrow_mut(),
+                     "find: ‘{}’ terminated by signal {}",
+                     executable,
+                     signal)
+                .unwrap();
+            false
+        }
+        outcome => outcome.success(),
+    }
+}
+
+/// Asks the user whether to run the given command, returning whether they
+/// said yes. Only answers beginning with 'y' or 'Y' count as a yes: anything
+/// else (including no answer at all) is a no.
+fn confirm(executable: &str, args: &[&OsStr], matcher_io: &mut MatcherIO) -> bool {
+    // make sure anything we've already printed appears before the question
+    matcher_io.flush_output();
+    let mut prompt = format!("< {}", executable);
+    for arg in args {
+        prompt.push(' ');
+        prompt.push_str(&arg.to_string_lossy());
+    }
+    write!(matcher_io.deps.get_error_output().borrow_mut(), "{} > ? ", prompt).unwrap();
+
+    let mut response = String::new();
+    match matcher_io.deps.get_input().borrow_mut().read_line(&mut response) {
+        Ok(_) => response.starts_with('y') || response.starts_with('Y'),
+        Err(_) => false,
+    }
 }
 
 impl Matcher for SingleExecMatcher {
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        let mut command = Command::new(&self.executable);
-        let path_to_file = if self.exec_in_parent_dir {
-            if let Some(f) = file_info.path().file_name() {
-                Path::new(".").join(f)
-            } else {
-                Path::new(".").join(file_info.path())
-            }
-        } else {
-            file_info.path().to_path_buf()
-        };
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let path_to_file = path_for_command(file_info, self.exec_in_parent_dir);
 
-        for arg in &self.args {
-            command.arg(match arg {
+        let args: Vec<&OsStr> = self.args
+            .iter()
+            .map(|arg| match arg {
                 &Arg::LiteralArg(ref a) => a.as_os_str(),
                 &Arg::Filename => path_to_file.as_os_str(),
-            });
-        }
-        if self.exec_in_parent_dir {
-
-            if file_info.path() == Path::new(".") {
-                command.current_dir(file_info.path());
-            } else if let Some(parent) = file_info.path().parent() {
-                command.current_dir(parent);
-            }
+            })
+            .collect();
+        if self.ask_first && !confirm(&self.executable, &args, matcher_io) {
+            return false;
         }
-        match command.status() {
-            Ok(status) => return status.success(),
+        let dir = match dir_for_command(file_info, self.exec_in_parent_dir) {
+            Ok(dir) => dir,
             Err(e) => {
-                writeln!(&mut stderr(), "Failed to run {}: {}", self.executable, e).unwrap();
+                report_io_error(file_info.path(), "", &e, matcher_io);
                 return false;
             }
-        }
+        };
+        // A command that can't be run (or is killed) is reported each time,
+        // and doesn't match, but find carries on. Only the former affects
+        // find's exit code, as a command failing is the usual way of saying
+        // that a file doesn't match.
+        run_command(&self.executable, &args, dir.as_ref().map(|dir| dir.as_path()), matcher_io)
     }
 
     fn has_side_effects(&self) -> bool {
         return true;
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match (self.ask_first, self.exec_in_parent_dir) {
+            (false, false) => "exec",
+            (false, true) => "execdir",
+            (true, false) => "ok",
+            (true, true) => "okdir",
+        };
+        write!(f, "{}({}", name, self.executable)?;
+        for arg in &self.args {
+            match *arg {
+                Arg::Filename => write!(f, " {{}}")?,
+                Arg::LiteralArg(ref arg) => write!(f, " {}", arg.to_string_lossy())?,
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+/// Runs the command on as many files at a time as fit on its command line,
+/// as -exec ... {} + does (with the files' paths appended to the command),
+/// or for -execdir ... {} +, as many files from the same directory at a
+/// time. It always matches: if any of the commands fails, find's exit code
+/// says so instead.
+///
+/// Each file is added to a batch when it's matched, so anything printed for
+/// it by an earlier action (e.g. "-print -exec ... {} +") has been printed
+/// by the time its batch is run, and our output is flushed before each
+/// batch, so it all appears before anything the command writes. The last
+/// batch is run by `finished`, which happens even if find stops early (e.g.
+/// with -quit), and before the exit code is worked out.
+pub struct MultiExecMatcher {
+    executable: String,
+    /// The arguments that come before the files' paths.
+    args: Vec<OsString>,
+    exec_in_parent_dir: bool,
+    batcher: RefCell<CommandBatcher>,
+    /// The directory the files in the current batch are in (for -execdir).
+    batch_dir: RefCell<Option<PathBuf>>,
+}
+
+impl MultiExecMatcher {
+    /// Creates a matcher for the given executable, with the given arguments
+    /// coming before the files' paths (i.e. without the final "{}").
+    pub fn new(executable: &str,
+               args: &[&str],
+               exec_in_parent_dir: bool)
+               -> Result<MultiExecMatcher, Box<Error>> {
+        let limits = exec::batch::size_limits();
+        MultiExecMatcher::new_with_limits(executable,
+                                          args,
+                                          exec_in_parent_dir,
+                                          BatchLimits::new(limits.default, limits.max_usable))
+    }
+
+    /// The same as `new`, but with the given limits on the size of batches.
+    pub fn new_with_limits(executable: &str,
+                           args: &[&str],
+                           exec_in_parent_dir: bool,
+                           limits: BatchLimits)
+                           -> Result<MultiExecMatcher, Box<Error>> {
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        let mut command = vec![OsString::from(executable)];
+        command.extend(args.iter().cloned());
+        Ok(MultiExecMatcher {
+            executable: executable.to_string(),
+            args: args,
+            exec_in_parent_dir: exec_in_parent_dir,
+            batcher: RefCell::new(CommandBatcher::new(&command, limits)?),
+            batch_dir: RefCell::new(None),
+        })
+    }
+
+    pub fn new_box(executable: &str,
+                   args: &[&str],
+                   exec_in_parent_dir: bool)
+                   -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(MultiExecMatcher::new(executable, args, exec_in_parent_dir)?))
+    }
+
+    /// Runs the command on one batch of files.
+    fn run_batch(&self, batch: Vec<OsString>, matcher_io: &mut MatcherIO) {
+        let batch_dir = self.batch_dir.borrow().clone();
+        if !run_command(&self.executable,
+                        self.args.iter().chain(&batch),
+                        batch_dir.as_ref().map(|dir| dir.as_path()),
+                        matcher_io) {
+            matcher_io.set_exit_code(1);
+        }
+    }
+
+    /// Runs the command on the files that have been added so far.
+    fn run_remaining_batches(&self, matcher_io: &mut MatcherIO) {
+        while let Some(batch) = self.batcher.borrow_mut().take() {
+            self.run_batch(batch, matcher_io);
+        }
+    }
 }
 
+impl Matcher for MultiExecMatcher {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        if self.exec_in_parent_dir {
+            // each batch only has files from one directory in it
+            let dir = match dir_for_command(file_info, true) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    report_io_error(file_info.path(), "", &e, matcher_io);
+                    return true;
+                }
+            };
+            if *self.batch_dir.borrow() != dir {
+                self.run_remaining_batches(matcher_io);
+                *self.batch_dir.borrow_mut() = dir;
+            }
+        }
+        let path_to_file = path_for_command(file_info, self.exec_in_parent_dir);
+        let added = self.batcher.borrow_mut().add(path_to_file.into_os_string(), false);
+        if let Err(e) = added {
+            writeln!(matcher_io.deps.get_error_output().borrow_mut(),
+                     "find: ‘{}’: {}",
+                     file_info.path().to_string_lossy(),
+                     e)
+                .unwrap();
+            matcher_io.set_exit_code(1);
+        }
+        while let Some(batch) = self.batcher.borrow_mut().next_batch() {
+            self.run_batch(batch, matcher_io);
+        }
+        true
+    }
+
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+
+    fn finished(&self, matcher_io: &mut MatcherIO) {
+        self.run_remaining_batches(matcher_io);
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.exec_in_parent_dir {
+            "execdir"
+        } else {
+            "exec"
+        };
+        write!(f, "{}({}", name, self.executable)?;
+        for arg in &self.args {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        write!(f, " {{}} +)")
+    }
+}
 
 #[cfg(test)]
 /// No tests here, because we need to call out to an external executable. See
@@ -0,0 +1,185 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Constant folding: simplifying an expression using what's known about
+//! -true and -false before anything's evaluated. Expressions written by
+//! other programs can have thousands of clauses that can't make any
+//! difference (e.g. "-false -a ..."), and there's no point evaluating them
+//! for every file.
+//!
+//! Nothing that does something (see `lint::does_something`) is ever
+//! removed, or moved relative to anything else, so the only difference it
+//! makes is to which tests are evaluated. Tests that are dropped can't then
+//! complain about a file (e.g. that it's vanished), but GNU find's
+//! optimiser takes the same liberty.
+
+use find::matchers::{rebuild_matcher, Matcher, MatcherKind};
+use find::matchers::lint::does_something;
+use find::matchers::logical_matchers::{FalseMatcher, TrueMatcher};
+
+fn constant_matcher(value: bool) -> Box<Matcher> {
+    if value {
+        TrueMatcher::new_box()
+    } else {
+        FalseMatcher::new_box()
+    }
+}
+
+/// Makes a logical matcher of the given kind from what's left of its
+/// children: with just one, it's that one, and with none, a constant.
+fn rebuild(kind: MatcherKind, mut children: Vec<Box<Matcher>>, if_empty: bool) -> Box<Matcher> {
+    match children.len() {
+        0 => constant_matcher(if_empty),
+        1 => children.pop().unwrap(),
+        _ => rebuild_matcher(kind, children).unwrap(),
+    }
+}
+
+/// Folds the (already folded) children of -a or -o, where `identity` is the
+/// value that lets evaluation go on to the next child (true for -a, false
+/// for -o). A constant child with that value makes no difference, and one
+/// without it means nothing after it is ever evaluated: then, if nothing
+/// before it does anything either, the whole thing is that constant.
+fn fold_short_circuit(kind: MatcherKind,
+                      children: Vec<Box<Matcher>>,
+                      identity: bool)
+                      -> Box<Matcher> {
+    let mut kept = vec![];
+    for child in children {
+        match child.constant() {
+            Some(value) if value == identity => {}
+            Some(_) => {
+                kept.push(child);
+                break;
+            }
+            None => kept.push(child),
+        }
+    }
+    if let Some(value) = kept.last().and_then(|last| last.constant()) {
+        if kept.iter().all(|child| !does_something(child.as_ref())) {
+            return constant_matcher(value);
+        }
+    }
+    rebuild(kind, kept, identity)
+}
+
+/// Returns an equivalent of the matcher with everything that can be worked
+/// out in advance worked out, and anything that can't make a difference
+/// removed.
+pub fn fold_constants(mut matcher: Box<Matcher>) -> Box<Matcher> {
+    let kind = matcher.kind();
+    if kind == MatcherKind::Leaf {
+        return matcher;
+    }
+    let children: Vec<_> = matcher.take_children().into_iter().map(fold_constants).collect();
+    match kind {
+        MatcherKind::And => fold_short_circuit(kind, children, true),
+        MatcherKind::Or => fold_short_circuit(kind, children, false),
+        MatcherKind::List => {
+            // only the last child's value counts, so the others are only
+            // there for what they do
+            let last = children.len().saturating_sub(1);
+            let kept = children.into_iter()
+                .enumerate()
+                .filter(|&(i, ref child)| i == last || does_something(child.as_ref()))
+                .map(|(_, child)| child)
+                .collect();
+            rebuild(kind, kept, true)
+        }
+        MatcherKind::Not | MatcherKind::Leaf => {
+            match children.first().and_then(|child| child.constant()) {
+                Some(value) => constant_matcher(!value),
+                None => rebuild_matcher(kind, children).unwrap(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::rc::Rc;
+    use walkdir::WalkDir;
+
+    use find::Config;
+    use find::matchers::{build_matcher_tree, MatcherRegistry, WalkEntry};
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    fn build(args: &[&str]) -> Box<Matcher> {
+        let registry = MatcherRegistry::default();
+        build_matcher_tree(args, &mut Config::default(), &registry, 0, false).unwrap().1
+    }
+
+    fn folded(args: &[&str]) -> String {
+        fold_constants(build(args)).to_string()
+    }
+
+    #[test]
+    fn folds_constants() {
+        let cases = [(&["-false", "-name", "a"][..], "false"),
+                     (&["-name", "a", "-false", "-name", "b"], "false"),
+                     (&["-true", "-name", "a", "-true"], "name(a)"),
+                     (&["-name", "a", "-o", "-true", "-o", "-name", "b"], "true"),
+                     (&["-false", "-o", "-name", "a", "-o", "-false"], "name(a)"),
+                     (&["!", "-false"], "true"),
+                     (&["!", "(", "-name", "a", "-o", "-true", ")"], "false"),
+                     (&["-name", "a", ",", "-type", "f", ",", "-false"], "false"),
+                     (&["-true", "-a", "-true"], "true"),
+                     (&["-false", "-o", "-false"], "false"),
+                     // what does something stays, in order, along with
+                     // anything before it
+                     (&["-print", "-false"], "AND( print, false )"),
+                     (&["-false", "-print"], "false"),
+                     (&["-name", "a", "-print", "-false", "-print"],
+                      "AND( name(a), print, false )"),
+                     (&["-prune", "-o", "-true"], "OR( prune, true )"),
+                     (&["-name", "a", ",", "-print", ",", "-name", "b"],
+                      "LIST( print, name(b) )"),
+                     (&["-true", "-o", "-print"], "true"),
+                     (&["!", "-print"], "NOT( print )"),
+                     (&["-name", "a", "-type", "f"], "AND( name(a), type(f) )")];
+        for &(args, expected) in &cases {
+            assert_eq!(folded(args), expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn folding_preserves_behaviour() {
+        let corpus: &[&[&str]] = &[&["-name", "a*", "-false", "-o", "-type", "d"],
+                                   &["-true", "-name", "*c", "-o", "-false"],
+                                   &["!", "(", "-false", "-o", "-name", "sub*", ")"],
+                                   &["-type", "f", ",", "-true", ",", "-name", "*b*"],
+                                   &["(", "-false", "-o", "-true", ")", "-type", "f"],
+                                   &["-not", "-true", "-o", "!", "-not", "-name", "f*"],
+                                   &["-name", "1", "-o", "(", "-true", ",", "-false", ")"],
+                                   &["-print", "-false", "-o", "-type", "d", "-print"],
+                                   &["-false", "-print", "-o", "-name", "*3", "-print"],
+                                   &["-type", "d", "-prune", "-o", "-true", "-print"]];
+        let starting_point: Rc<Path> = Rc::from(Path::new("test_data"));
+        for args in corpus {
+            let original = build(args);
+            let folded = fold_constants(build(args));
+            let original_deps = FakeDependencies::new();
+            let folded_deps = FakeDependencies::new();
+            for dir_entry in WalkDir::new("test_data") {
+                let entry = WalkEntry::new(dir_entry.unwrap(), starting_point.clone());
+                let mut original_io = original_deps.new_matcher_io();
+                let mut folded_io = folded_deps.new_matcher_io();
+                assert_eq!(folded.matches(&entry, &mut folded_io),
+                           original.matches(&entry, &mut original_io),
+                           "{:?} {}",
+                           args,
+                           entry.path().display());
+                assert_eq!(folded_io.walk_control(), original_io.walk_control(), "{:?}", args);
+            }
+            assert_eq!(folded_deps.get_output_as_string(),
+                       original_deps.get_output_as_string(),
+                       "{:?}",
+                       args);
+        }
+    }
+}
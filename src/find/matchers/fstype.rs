@@ -0,0 +1,92 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+use std::fmt;
+
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
+
+/// This matcher checks the type of filesystem a file is on (e.g. "ext4" or
+/// "nfs"), as -fstype does. The type comes from the mount table, by the
+/// file's device, so a file on a device that isn't in it is on "unknown"
+/// (as -printf's %F says too).
+#[derive(Clone)]
+pub struct FsTypeMatcher {
+    fs_type: String,
+}
+
+impl FsTypeMatcher {
+    pub fn new(fs_type: &str) -> FsTypeMatcher {
+        FsTypeMatcher { fs_type: fs_type.to_string() }
+    }
+
+    pub fn new_box(fs_type: &str) -> Box<Matcher> {
+        Box::new(FsTypeMatcher::new(fs_type))
+    }
+}
+
+impl Matcher for FsTypeMatcher {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match matcher_io.metadata(file_info) {
+            Ok(metadata) => *matcher_io.fs_type(metadata.dev) == *self.fs_type,
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                false
+            }
+        }
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fstype({})", self.fs_type)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl ParallelMatcher for FsTypeMatcher {}
+
+#[cfg(test)]
+mod tests {
+    use find::fsinfo::MountTable;
+    use find::matchers::Matcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::metadata::FileMetadata;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    #[test]
+    fn fstype_matcher() {
+        let mut deps = FakeDependencies::new();
+        deps.set_mount_table(MountTable::parse_mountinfo("1 0 0:45 / / rw - tmpfs tmpfs rw\n\
+                                                          2 1 8:1 / /a rw - ext4 /dev/sda1 \
+                                                          rw\n"));
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let subdir = get_dir_entry_for("test_data/simple", "subdir");
+        deps.set_metadata(abbbc.path(), FileMetadata { dev: 0x801, ..FileMetadata::default() });
+        deps.set_metadata(subdir.path(), FileMetadata { dev: 99, ..FileMetadata::default() });
+        let matches = |fs_type: &str, entry: &WalkEntry| {
+            FsTypeMatcher::new(fs_type).matches(entry, &mut deps.new_matcher_io())
+        };
+        assert!(matches("ext4", &abbbc));
+        assert!(!matches("tmpfs", &abbbc));
+        assert!(!matches("ext", &abbbc));
+        // a device that isn't mounted anywhere (as far as the table says)
+        assert!(matches("unknown", &subdir));
+        assert!(!matches("tmpfs", &subdir));
+    }
+
+    #[test]
+    fn fstype_matcher_errors() {
+        let mut deps = FakeDependencies::new();
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        deps.set_metadata_error(abbbc.path(), 2);
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(!FsTypeMatcher::new("unknown").matches(&abbbc, &mut matcher_io));
+        assert_eq!(matcher_io.exit_code(), 1);
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Expressions written by programs (e.g. ones that turn .gitignore files
+//! into find arguments) can have thousands of -name and -path tests joined
+//! by -o, which would otherwise be tried one after another for every entry.
+//! An OR made up of nothing but such tests is collapsed into a
+//! `GlobSetMatcher` instead, which indexes the patterns so that only a few
+//! of them have to be tried for any one entry.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use find::matchers::{Matcher, MatcherIO, WalkEntry};
+use find::matchers::compiled_glob::CompiledGlob;
+use find::matchers::parallel::ParallelMatcher;
+
+/// How many alternatives an OR needs before it's collapsed. Smaller ones are
+/// cheap enough to try in turn.
+pub const MIN_ALTERNATIVES: usize = 16;
+
+/// What a glob test compares its pattern with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GlobSubject {
+    /// The entry's name (-name).
+    Name,
+    /// The entry's name, in lower case (-iname, whose pattern is in lower
+    /// case too).
+    CaselessName,
+    /// The entry's path (-path).
+    Path,
+    /// The entry's path, in lower case (-ipath).
+    CaselessPath,
+}
+
+/// Patterns that are all compared with the same string, indexed by the
+/// literal text they start or end with.
+#[derive(Clone, Default)]
+struct GlobSet {
+    /// Patterns without wildcards, which only match their own text.
+    literals: HashSet<String>,
+    /// Patterns ending in literal text, by the last character of it.
+    by_last_char: HashMap<char, Vec<CompiledGlob>>,
+    /// Patterns starting (but not ending) with literal text, by the first
+    /// character of it.
+    by_first_char: HashMap<char, Vec<CompiledGlob>>,
+    /// Everything else (e.g. "*"), which has to be tried every time.
+    others: Vec<CompiledGlob>,
+}
+
+impl GlobSet {
+    fn insert(&mut self, glob: &CompiledGlob) {
+        if glob.is_literal() {
+            self.literals.insert(glob.prefix().to_string());
+        } else if let Some(c) = glob.suffix().chars().last() {
+            self.by_last_char.entry(c).or_insert_with(Vec::new).push(glob.clone());
+        } else if let Some(c) = glob.prefix().chars().next() {
+            self.by_first_char.entry(c).or_insert_with(Vec::new).push(glob.clone());
+        } else {
+            self.others.push(glob.clone());
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.literals.is_empty() && self.by_last_char.is_empty() &&
+        self.by_first_char.is_empty() && self.others.is_empty()
+    }
+
+    /// Whether any of the patterns match, adding the number of them that had
+    /// to be tried to `probes`.
+    fn matches(&self, s: &str, probes: &mut usize) -> bool {
+        if self.literals.contains(s) {
+            return true;
+        }
+        let empty = vec![];
+        let by_last_char = s.chars().last().and_then(|c| self.by_last_char.get(&c));
+        let by_first_char = s.chars().next().and_then(|c| self.by_first_char.get(&c));
+        by_last_char.unwrap_or(&empty)
+            .iter()
+            .chain(by_first_char.unwrap_or(&empty))
+            .chain(&self.others)
+            .any(|glob| {
+                *probes += 1;
+                glob.matches(s)
+            })
+    }
+}
+
+/// Whether the string matches any of the case-sensitive patterns, or (in
+/// lower case) any of the caseless ones.
+fn matches_either_case(s: &str, globs: &GlobSet, caseless_globs: &GlobSet, probes: &mut usize)
+                       -> bool {
+    globs.matches(s, probes) ||
+    (!caseless_globs.is_empty() && caseless_globs.matches(&s.to_lowercase(), probes))
+}
+
+/// The patterns of an OR's tests, by what they're compared with.
+#[derive(Clone, Default)]
+struct Globs {
+    names: GlobSet,
+    caseless_names: GlobSet,
+    paths: GlobSet,
+    caseless_paths: GlobSet,
+}
+
+impl Globs {
+    fn matches(&self, file_info: &WalkEntry, probes: &mut usize) -> bool {
+        matches_either_case(&file_info.name().to_string_lossy(),
+                            &self.names,
+                            &self.caseless_names,
+                            probes) ||
+        matches_either_case(&file_info.path().to_string_lossy(),
+                            &self.paths,
+                            &self.caseless_paths,
+                            probes)
+    }
+}
+
+/// An OR of -name, -iname, -path and -ipath tests, which matches exactly
+/// what the OR would have.
+pub struct GlobSetMatcher {
+    /// The tests the OR was made of, for describing it.
+    alternatives: Vec<Box<Matcher>>,
+    globs: Globs,
+    /// How many patterns have been tried so far, for seeing how well the
+    /// indexing works.
+    probes: Cell<usize>,
+}
+
+impl GlobSetMatcher {
+    /// Whether an OR of the given alternatives can (and should) be collapsed.
+    pub fn can_collapse(alternatives: &[Box<Matcher>]) -> bool {
+        alternatives.len() >= MIN_ALTERNATIVES &&
+        alternatives.iter().all(|alternative| alternative.glob().is_some())
+    }
+
+    /// Collapses the alternatives, which must all be glob tests.
+    pub fn new(alternatives: Vec<Box<Matcher>>) -> GlobSetMatcher {
+        let mut globs = Globs::default();
+        for alternative in &alternatives {
+            let (subject, glob) = alternative.glob().expect("only glob tests can be collapsed");
+            match subject {
+                GlobSubject::Name => globs.names.insert(glob),
+                GlobSubject::CaselessName => globs.caseless_names.insert(glob),
+                GlobSubject::Path => globs.paths.insert(glob),
+                GlobSubject::CaselessPath => globs.caseless_paths.insert(glob),
+            }
+        }
+        GlobSetMatcher {
+            alternatives: alternatives,
+            globs: globs,
+            probes: Cell::new(0),
+        }
+    }
+
+    pub fn new_box(alternatives: Vec<Box<Matcher>>) -> Box<Matcher> {
+        Box::new(GlobSetMatcher::new(alternatives))
+    }
+
+    #[cfg(test)]
+    pub fn probes(&self) -> usize {
+        self.probes.get()
+    }
+}
+
+impl Matcher for GlobSetMatcher {
+    fn matches(&self, file_info: &WalkEntry, _: &mut MatcherIO) -> bool {
+        let mut probes = 0;
+        let matched = self.globs.matches(file_info, &mut probes);
+        self.probes.set(self.probes.get() + probes);
+        matched
+    }
+
+    // it's still the OR it was made from, as far as anyone looking at the
+    // expression is concerned
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OR( ")?;
+        for (i, alternative) in self.alternatives.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            alternative.describe(f)?;
+        }
+        write!(f, " )")
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(ParallelGlobSetMatcher {
+            globs: self.globs.clone(),
+            description: (self as &Matcher).to_string(),
+        }))
+    }
+}
+
+/// The copy of a `GlobSetMatcher` for other threads to evaluate (see
+/// `Matcher::parallel`), which has the same patterns and description, but
+/// doesn't count probes.
+struct ParallelGlobSetMatcher {
+    globs: Globs,
+    description: String,
+}
+
+impl Matcher for ParallelGlobSetMatcher {
+    fn matches(&self, file_info: &WalkEntry, _: &mut MatcherIO) -> bool {
+        self.globs.matches(file_info, &mut 0)
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl ParallelMatcher for ParallelGlobSetMatcher {}
+
+#[cfg(test)]
+mod tests {
+    use find::matchers::logical_matchers::TrueMatcher;
+    use find::matchers::name::{CaselessPathMatcher, NameMatcher, PathMatcher};
+    use find::matchers::tests::get_dir_entry_for;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    /// 1,000 alternatives, of all four kinds, none of which match anything
+    /// in test_data/simple.
+    fn alternatives() -> Vec<Box<Matcher>> {
+        let mut alternatives = vec![];
+        for i in 0..250 {
+            alternatives.push(NameMatcher::new_box(&format!("file{}.o", i), false).unwrap());
+            alternatives.push(NameMatcher::new_box(&format!("*.EXT{}", i), true).unwrap());
+            alternatives.push(PathMatcher::new_box(&format!("build{}/*", i)).unwrap());
+            alternatives.push(CaselessPathMatcher::new_box(&format!("*/TARGET{}", i)).unwrap());
+        }
+        alternatives
+    }
+
+    #[test]
+    fn only_ors_of_enough_glob_tests_are_collapsed() {
+        assert!(GlobSetMatcher::can_collapse(&alternatives()));
+        let mut few = alternatives();
+        few.truncate(MIN_ALTERNATIVES - 1);
+        assert!(!GlobSetMatcher::can_collapse(&few));
+        let mut mixed = alternatives();
+        mixed.push(TrueMatcher::new_box());
+        assert!(!GlobSetMatcher::can_collapse(&mixed));
+    }
+
+    #[test]
+    fn matches_what_the_or_would() {
+        let deps = FakeDependencies::new();
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let upper = get_dir_entry_for("test_data/simple/subdir", "ABBBC");
+        let subdir = get_dir_entry_for("test_data/simple", "subdir");
+
+        let matcher = GlobSetMatcher::new(alternatives());
+        for entry in &[&abbbc, &upper, &subdir] {
+            assert!(!matcher.matches(entry, &mut deps.new_matcher_io()));
+        }
+
+        let extra: Vec<Box<Matcher>> =
+            vec![NameMatcher::new_box("abbbc", false).unwrap(),
+                 NameMatcher::new_box("AB*C", true).unwrap(),
+                 PathMatcher::new_box("test_data/simple/sub*").unwrap(),
+                 CaselessPathMatcher::new_box("*/SUBDIR/abbbc").unwrap()];
+        for (i, extra) in extra.into_iter().enumerate() {
+            let mut alternatives = alternatives();
+            alternatives.insert(500, extra);
+            let expected = [[true, false, false], [true, true, false], [false, true, true],
+                            [false, true, false]][i];
+            let matcher = GlobSetMatcher::new(alternatives);
+            // as does the copy for other threads
+            let copy = matcher.parallel().unwrap();
+            assert_eq!(copy.to_string(), (&matcher as &Matcher).to_string());
+            for (entry, &expected) in [&abbbc, &upper, &subdir].iter().zip(&expected) {
+                let or = matcher.alternatives
+                    .iter()
+                    .any(|alternative| alternative.matches(entry, &mut deps.new_matcher_io()));
+                assert_eq!(or, expected, "{} {}", i, entry.path().display());
+                assert_eq!(matcher.matches(entry, &mut deps.new_matcher_io()),
+                           expected,
+                           "{} {}",
+                           i,
+                           entry.path().display());
+                assert_eq!(copy.matches(entry, &mut deps.new_matcher_io()), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn only_tries_a_few_patterns() {
+        let deps = FakeDependencies::new();
+        let matcher = GlobSetMatcher::new(alternatives());
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+        // only patterns ending in "c", or starting with "a" (for the name)
+        // or "t" (for the path), could have matched, and there aren't any
+        assert_eq!(matcher.probes(), 0);
+
+        let mut alternatives = alternatives();
+        alternatives.push(PathMatcher::new_box("test_data/*/abbbc").unwrap());
+        let matcher = GlobSetMatcher::new(alternatives);
+        assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+        assert_eq!(matcher.probes(), 1);
+    }
+
+    #[test]
+    fn describes_itself_as_the_or() {
+        let matcher = GlobSetMatcher::new(alternatives());
+        let description = (&matcher as &Matcher).to_string();
+        assert!(description.starts_with("OR( name(file0.o), iname(*.ext0), path(build0/*), \
+                                         ipath(*/target0), name(file1.o), "),
+                "{}",
+                description);
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! -printj: a line of JSON for each file, for programs that would otherwise
+//! have to pick apart what -printf writes (and get it wrong for names with
+//! newlines in them).
+
+use std::fmt;
+
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::output_file::Destination;
+use find::matchers::printf::{epoch_seconds, type_letter};
+use find::quoting::{base64, json_quote, path_bytes};
+
+/// This matcher writes a JSON object describing the file (on a line of its
+/// own) to stdout. Its fields are:
+///
+/// * "path": the path, as -print would write it. JSON strings are Unicode,
+///   so if the path isn't valid UTF-8, the bytes that aren't become U+FFFD,
+///   and there's a "path_bytes" field too, with the exact bytes in base64.
+/// * "file_type": the letter -type uses for the file's type.
+/// * "size": the size in bytes.
+/// * "mtime": when the file was last modified, in seconds since the epoch
+///   (null if the platform doesn't say).
+/// * "depth": how far below its start point the file is.
+/// * "mode", "uid" and "gid": the permission bits (as a number) and owners,
+///   on Unix only.
+pub struct JsonPrinter {
+    destination: Destination,
+}
+
+impl JsonPrinter {
+    pub fn new() -> JsonPrinter {
+        JsonPrinter { destination: Destination::Output }
+    }
+
+    pub fn new_box() -> Box<Matcher> {
+        Box::new(JsonPrinter::new())
+    }
+}
+
+/// Adds a field (after the first) to an object that's being written.
+fn push_field(json: &mut Vec<u8>, name: &str, value: &[u8]) {
+    json.push(b',');
+    json.extend(json_quote(name.as_bytes()));
+    json.push(b':');
+    json.extend_from_slice(value);
+}
+
+impl Matcher for JsonPrinter {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let metadata = match matcher_io.metadata(file_info) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                return true;
+            }
+        };
+
+        let path = path_bytes(file_info.path());
+        let mut json = b"{\"path\":".to_vec();
+        json.extend(json_quote(&path));
+        if String::from_utf8(path.clone()).is_err() {
+            push_field(&mut json, "path_bytes", &json_quote(base64(&path).as_bytes()));
+        }
+        let file_type = type_letter(file_info.file_type()).to_string();
+        push_field(&mut json, "file_type", &json_quote(file_type.as_bytes()));
+        push_field(&mut json, "size", metadata.len.to_string().as_bytes());
+        let mtime = metadata.modified
+            .map_or_else(|| "null".to_string(), |time| epoch_seconds(time).0.to_string());
+        push_field(&mut json, "mtime", mtime.as_bytes());
+        push_field(&mut json, "depth", file_info.depth().to_string().as_bytes());
+        if cfg!(unix) {
+            push_field(&mut json, "mode", (metadata.mode & 0o7777).to_string().as_bytes());
+            push_field(&mut json, "uid", metadata.uid.to_string().as_bytes());
+            push_field(&mut json, "gid", metadata.gid.to_string().as_bytes());
+        }
+        json.extend_from_slice(b"}\n");
+
+        self.destination.write(matcher_io, false, |out| out.write_all(&json));
+        true
+    }
+
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+
+    fn only_writes_output(&self) -> bool {
+        self.destination.is_output()
+    }
+
+    fn finished(&self, matcher_io: &mut MatcherIO) {
+        self.destination.finished(matcher_io);
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "printj")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use find::matchers::Matcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::metadata::FileMetadata;
+    use find::tests::{fix_up_slashes, FakeDependencies};
+    use super::*;
+
+    #[test]
+    fn prints_json() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(abbbc.path(),
+                          FileMetadata {
+                              len: 1234,
+                              mode: 0o100644,
+                              uid: 1000,
+                              gid: 100,
+                              modified: Some(UNIX_EPOCH + Duration::from_millis(1_500_000_000_999)),
+                              ..FileMetadata::default()
+                          });
+        assert!(JsonPrinter::new().matches(&abbbc, &mut deps.new_matcher_io()));
+        let unix_fields = if cfg!(unix) { ",\"mode\":420,\"uid\":1000,\"gid\":100" } else { "" };
+        assert_eq!(deps.get_output_as_string(),
+                   format!("{{\"path\":{},\"file_type\":\"f\",\"size\":1234,\
+                            \"mtime\":1500000000,\"depth\":1{}}}\n",
+                           String::from_utf8(json_quote(fix_up_slashes("test_data/simple/abbbc")
+                                   .as_bytes()))
+                               .unwrap(),
+                           unix_fields));
+    }
+
+    #[test]
+    fn prints_json_without_mtime() {
+        let subdir = get_dir_entry_for("test_data/simple", "subdir");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(subdir.path(), FileMetadata::default());
+        assert!(JsonPrinter::new().matches(&subdir, &mut deps.new_matcher_io()));
+        let output = deps.get_output_as_string();
+        assert!(output.contains(",\"file_type\":\"d\",\"size\":0,\"mtime\":null,\"depth\":1"),
+                "{}",
+                output);
+    }
+
+    #[test]
+    fn metadata_errors() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata_error(abbbc.path(), 2);
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(JsonPrinter::new().matches(&abbbc, &mut matcher_io));
+        assert_eq!(matcher_io.exit_code(), 1);
+        assert_eq!(deps.get_output_as_string(), "");
+    }
+}
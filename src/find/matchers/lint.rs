@@ -0,0 +1,110 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Hints about expressions that are valid but probably don't do what was
+//! meant, which are given when -warn is asked for explicitly (GNU find gives
+//! none of them, so they're off by default).
+//!
+//! The one there is at the moment is for the precedence trap in
+//! "-name a -o -name b -exec c ;": -a binds more tightly than -o, so the
+//! -exec only applies to files named b, and files named a match without
+//! anything happening to them.
+
+use find::matchers::{Matcher, MatcherKind};
+
+/// Whether evaluating the matcher does anything other than produce a result:
+/// it has side effects, or changes how the walk goes on (e.g. -prune).
+pub fn does_something(matcher: &Matcher) -> bool {
+    matcher.has_side_effects() || matcher.affects_walk() ||
+    matcher.children().iter().any(|child| does_something(child.as_ref()))
+}
+
+/// Whether the matcher looks like tests followed by actions, e.g.
+/// "-name b -exec c ;".
+fn is_tests_then_actions(matcher: &Matcher) -> bool {
+    matcher.kind() == MatcherKind::And &&
+    matcher.children().first().map_or(false, |first| !does_something(first.as_ref())) &&
+    matcher.has_side_effects()
+}
+
+/// Adds hints about the matcher (and everything below it) to `hints`.
+/// `value_used` says whether anything depends on whether it matches (which
+/// for the whole expression, nothing does).
+pub fn check_expression(matcher: &(Matcher + 'static), value_used: bool, hints: &mut Vec<String>) {
+    let children = matcher.children();
+    if matcher.kind() == MatcherKind::Or && !value_used &&
+       children.iter().any(|child| !does_something(child.as_ref())) &&
+       children.iter().any(|child| is_tests_then_actions(child.as_ref())) {
+        hints.push(format!("the actions in {} only apply to some of its alternatives, so the \
+                            others do nothing when they match. To apply them to all of the \
+                            alternatives, put those in parentheses, e.g. ‘( -name a -o -name b \
+                            ) -exec ...’.",
+                           matcher));
+    }
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let child_value_used = match matcher.kind() {
+            // only the last item in a list decides whether the list matches
+            MatcherKind::List => last && value_used,
+            // and the last operand of -a or -o only matters if the result
+            // does, whereas the others decide whether the rest are evaluated
+            MatcherKind::And | MatcherKind::Or => !last || value_used,
+            MatcherKind::Not | MatcherKind::Leaf => value_used,
+        };
+        check_expression(child.as_ref(), child_value_used, hints);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use find::Config;
+    use find::matchers::{build_matcher_tree, MatcherRegistry};
+    use super::*;
+
+    fn hints(args: &[&str]) -> Vec<String> {
+        let registry = MatcherRegistry::default();
+        let (_, matcher) = build_matcher_tree(args, &mut Config::default(), &registry, 0, false)
+            .unwrap();
+        let mut hints = vec![];
+        check_expression(matcher.as_ref(), false, &mut hints);
+        hints
+    }
+
+    #[test]
+    fn precedence_trap() {
+        let found = hints(&["-name", "a", "-o", "-name", "b", "-exec", "rm", "{}", ";"]);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with("the actions in OR( name(a), AND( name(b), exec(rm {}) ) \
+                                      ) only apply to some of its alternatives"),
+                "{}",
+                found[0]);
+
+        // it's the same inside a list, as long as the list doesn't need the
+        // OR's value
+        assert_eq!(hints(&["-name", "a", "-o", "-name", "b", "-print", ",", "-print"]).len(),
+                   1);
+    }
+
+    #[test]
+    fn no_hints() {
+        for args in &[// parenthesised, as it should be
+                      &["(", "-name", "a", "-o", "-name", "b", ")", "-exec", "rm", "{}", ";"]
+                           [..],
+                      // the usual way of pruning
+                      &["-path", "./x", "-prune", "-o", "-name", "*.c", "-print"],
+                      &["-name", "x", "-prune", "-o", "-print"],
+                      // printing everything but some files
+                      &["-name", "*.o", "-o", "-print"],
+                      // no actions at all (so everything that matches is printed)
+                      &["-name", "a", "-o", "-name", "b"],
+                      // actions on both sides
+                      &["-name", "a", "-print", "-o", "-name", "b", "-print0"],
+                      // the OR's value decides whether to print
+                      &["(", "-name", "a", "-o", "-name", "b", "-print", ")", "-print0"]] {
+            assert_eq!(hints(args), Vec::<String>::new(), "{:?}", args);
+        }
+    }
+}
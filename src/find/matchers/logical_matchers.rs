@@ -10,11 +10,31 @@
 //! when parsing command-line options (e.g. "-foo -o -bar -baz" is equivalent
 //! to "-foo -o ( -bar -baz )", not "( -foo -o -bar ) -baz").
 use std::error::Error;
+use std::fmt;
 use std::iter::Iterator;
+use std::mem;
 use std::path::PathBuf;
-use walkdir::DirEntry;
-
-use find::matchers::{Matcher, MatcherIO};
+use std::slice;
+
+use find::matchers::{Matcher, MatcherIO, MatcherKind, Prefilter, WalkEntry};
+use find::matchers::glob_set::GlobSetMatcher;
+use find::matchers::parallel::ParallelMatcher;
+
+/// Describes a logical matcher as its name followed by its sub-matchers'
+/// descriptions, e.g. "AND( name(*.c), print )".
+fn describe_submatchers(f: &mut fmt::Formatter,
+                        name: &str,
+                        submatchers: &[Box<Matcher>])
+                        -> fmt::Result {
+    write!(f, "{}( ", name)?;
+    for (i, submatcher) in submatchers.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        submatcher.describe(f)?;
+    }
+    write!(f, " )")
+}
 
 /// This matcher contains a collection of other matchers. A file only matches
 /// if it matches ALL the contained sub-matchers. For sub-matchers that have
@@ -33,10 +53,16 @@ impl AndMatcher {
 
 impl Matcher for AndMatcher {
     /// Returns true if all sub-matchers return true. Short-circuiting does take
-    /// place. If the nth sub-matcher returns false, then we immediately return
-    /// and don't make any further calls.
-    fn matches(&self, dir_entry: &DirEntry, matcher_io: &mut MatcherIO) -> bool {
-        self.submatchers.iter().all(|x| x.matches(dir_entry, matcher_io))
+    /// place. If the nth sub-matcher returns false (or asks find to quit), then
+    /// we immediately return and don't make any further calls.
+    fn matches(&self, dir_entry: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        self.submatchers
+            .iter()
+            .all(|x| !matcher_io.should_quit() && x.matches(dir_entry, matcher_io))
+    }
+
+    fn prefilter(&self) -> Option<Prefilter> {
+        Prefilter::all(self.submatchers.iter().map(|x| x.prefilter()))
     }
 
     fn has_side_effects(&self) -> bool {
@@ -49,11 +75,27 @@ impl Matcher for AndMatcher {
         }
     }
 
-    fn finished(&self) {
+    fn finished(&self, matcher_io: &mut MatcherIO) {
         for m in &self.submatchers {
-            m.finished();
+            m.finished(matcher_io);
         }
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        describe_submatchers(f, "AND", &self.submatchers)
+    }
+
+    fn kind(&self) -> MatcherKind {
+        MatcherKind::And
+    }
+
+    fn children(&self) -> &[Box<Matcher>] {
+        &self.submatchers
+    }
+
+    fn take_children(&mut self) -> Vec<Box<Matcher>> {
+        mem::replace(&mut self.submatchers, vec![])
+    }
 }
 
 pub struct AndMatcherBuilder {
@@ -102,9 +144,12 @@ impl OrMatcher {
 impl Matcher for OrMatcher {
     /// Returns true if any sub-matcher returns true. Short-circuiting does take
     /// place. If the nth sub-matcher returns true, then we immediately return
-    /// and don't make any further calls.
-    fn matches(&self, dir_entry: &DirEntry, matcher_io: &mut MatcherIO) -> bool {
-        self.submatchers.iter().any(|x| x.matches(dir_entry, matcher_io))
+    /// and don't make any further calls. The same goes for a sub-matcher asking
+    /// find to quit, although in that case we return false.
+    fn matches(&self, dir_entry: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        self.submatchers
+            .iter()
+            .any(|x| !matcher_io.should_quit() && x.matches(dir_entry, matcher_io))
     }
 
     fn has_side_effects(&self) -> bool {
@@ -117,11 +162,27 @@ impl Matcher for OrMatcher {
         }
     }
 
-    fn finished(&self) {
+    fn finished(&self, matcher_io: &mut MatcherIO) {
         for m in &self.submatchers {
-            m.finished();
+            m.finished(matcher_io);
         }
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        describe_submatchers(f, "OR", &self.submatchers)
+    }
+
+    fn kind(&self) -> MatcherKind {
+        MatcherKind::Or
+    }
+
+    fn children(&self) -> &[Box<Matcher>] {
+        &self.submatchers
+    }
+
+    fn take_children(&mut self) -> Vec<Box<Matcher>> {
+        mem::replace(&mut self.submatchers, vec![])
+    }
 }
 
 pub struct OrMatcherBuilder {
@@ -161,6 +222,11 @@ impl OrMatcherBuilder {
         for x in self.submatchers {
             submatchers.push(x.build());
         }
+        // lots of -name and -path tests (and nothing else) are better off
+        // indexed than tried one by one
+        if GlobSetMatcher::can_collapse(&submatchers) {
+            return GlobSetMatcher::new_box(submatchers);
+        }
         Box::new(OrMatcher::new(submatchers))
     }
 }
@@ -183,11 +249,15 @@ impl ListMatcher {
 
 
 impl Matcher for ListMatcher {
-    /// Calls matches on all submatcher objects, with no short-circuiting.
-    /// Returns the result of the call to the final submatcher
-    fn matches(&self, dir_entry: &DirEntry, matcher_io: &mut MatcherIO) -> bool {
+    /// Calls matches on all submatcher objects, with no short-circuiting
+    /// (unless one of them asks find to quit). Returns the result of the call
+    /// to the final submatcher
+    fn matches(&self, dir_entry: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
         let mut rc = false;
         for matcher in &self.submatchers {
+            if matcher_io.should_quit() {
+                break;
+            }
             rc = matcher.matches(dir_entry, matcher_io);
         }
         rc
@@ -203,11 +273,27 @@ impl Matcher for ListMatcher {
         }
     }
 
-    fn finished(&self) {
+    fn finished(&self, matcher_io: &mut MatcherIO) {
         for m in &self.submatchers {
-            m.finished();
+            m.finished(matcher_io);
         }
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        describe_submatchers(f, "LIST", &self.submatchers)
+    }
+
+    fn kind(&self) -> MatcherKind {
+        MatcherKind::List
+    }
+
+    fn children(&self) -> &[Box<Matcher>] {
+        &self.submatchers
+    }
+
+    fn take_children(&mut self) -> Vec<Box<Matcher>> {
+        mem::replace(&mut self.submatchers, vec![])
+    }
 }
 
 pub struct ListMatcherBuilder {
@@ -273,7 +359,27 @@ impl ListMatcherBuilder {
 }
 
 
+/// Makes a logical matcher of the given kind from its children (e.g. ones
+/// taken from another matcher with `Matcher::take_children`, then
+/// reordered). Returns None for leaves, which have no children to be made
+/// from, and for NOT with anything other than exactly one child.
+pub fn rebuild_matcher(kind: MatcherKind,
+                       mut children: Vec<Box<Matcher>>)
+                       -> Option<Box<Matcher>> {
+    match kind {
+        MatcherKind::And => Some(Box::new(AndMatcher::new(children))),
+        MatcherKind::Or => Some(Box::new(OrMatcher::new(children))),
+        MatcherKind::List => Some(Box::new(ListMatcher::new(children))),
+        MatcherKind::Not if children.len() == 1 => {
+            Some(NotMatcher::new_box(children.pop().unwrap()))
+        }
+        MatcherKind::Not | MatcherKind::Leaf => None,
+    }
+}
+
+
 /// A simple matcher that always matches.
+#[derive(Clone)]
 pub struct TrueMatcher;
 
 impl TrueMatcher {
@@ -283,20 +389,49 @@ impl TrueMatcher {
 }
 
 impl Matcher for TrueMatcher {
-    fn matches(&self, _dir_entry: &DirEntry, _: &mut MatcherIO) -> bool {
+    fn matches(&self, _dir_entry: &WalkEntry, _: &mut MatcherIO) -> bool {
         true
     }
+
+    fn constant(&self) -> Option<bool> {
+        Some(true)
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "true")
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
+impl ParallelMatcher for TrueMatcher {}
+
 /// A simple matcher that never matches.
+#[derive(Clone)]
 pub struct FalseMatcher;
 
 impl Matcher for FalseMatcher {
-    fn matches(&self, _dir_entry: &DirEntry, _: &mut MatcherIO) -> bool {
+    fn matches(&self, _dir_entry: &WalkEntry, _: &mut MatcherIO) -> bool {
         false
     }
+
+    fn constant(&self) -> Option<bool> {
+        Some(false)
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "false")
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
+impl ParallelMatcher for FalseMatcher {}
+
 impl FalseMatcher {
     pub fn new_box() -> Box<Matcher> {
         Box::new(FalseMatcher {})
@@ -320,7 +455,7 @@ impl NotMatcher {
 }
 
 impl Matcher for NotMatcher {
-    fn matches(&self, dir_entry: &DirEntry, matcher_io: &mut MatcherIO) -> bool {
+    fn matches(&self, dir_entry: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
         !self.submatcher.matches(dir_entry, matcher_io)
     }
 
@@ -332,15 +467,35 @@ impl Matcher for NotMatcher {
         self.submatcher.finished_dir(dir);
     }
 
-    fn finished(&self) {
-        self.submatcher.finished();
+    fn finished(&self, matcher_io: &mut MatcherIO) {
+        self.submatcher.finished(matcher_io);
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NOT( ")?;
+        self.submatcher.describe(f)?;
+        write!(f, " )")
+    }
+
+    fn kind(&self) -> MatcherKind {
+        MatcherKind::Not
+    }
+
+    fn children(&self) -> &[Box<Matcher>] {
+        slice::from_ref(&self.submatcher)
+    }
+
+    fn take_children(&mut self) -> Vec<Box<Matcher>> {
+        vec![mem::replace(&mut self.submatcher, TrueMatcher::new_box())]
     }
 }
 
 #[cfg(test)]
 
 mod tests {
-    use walkdir::DirEntry;
+    use std::cell::Cell;
+    use std::rc::Rc;
+        use find::matchers::quit::QuitMatcher;
     use find::matchers::tests::get_dir_entry_for;
     use super::*;
     use find::matchers::{Matcher, MatcherIO};
@@ -350,13 +505,17 @@ mod tests {
     pub struct HasSideEffects {}
 
     impl Matcher for HasSideEffects {
-        fn matches(&self, _: &DirEntry, _: &mut MatcherIO) -> bool {
+        fn matches(&self, _: &WalkEntry, _: &mut MatcherIO) -> bool {
             false
         }
 
         fn has_side_effects(&self) -> bool {
             true
         }
+
+        fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "has_side_effects")
+        }
     }
 
     impl HasSideEffects {
@@ -365,6 +524,54 @@ mod tests {
         }
     }
 
+    /// Matcher impl that counts how many times it's been evaluated and told
+    /// that find has finished.
+    pub struct Probe {
+        matches: Rc<Cell<usize>>,
+        finished: Rc<Cell<usize>>,
+    }
+
+    impl Matcher for Probe {
+        fn matches(&self, _: &WalkEntry, _: &mut MatcherIO) -> bool {
+            self.matches.set(self.matches.get() + 1);
+            true
+        }
+
+        fn finished(&self, _: &mut MatcherIO) {
+            self.finished.set(self.finished.get() + 1);
+        }
+
+        fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "probe")
+        }
+    }
+
+    impl Probe {
+        pub fn new_box(matches: &Rc<Cell<usize>>, finished: &Rc<Cell<usize>>) -> Box<Matcher> {
+            Box::new(Probe {
+                matches: matches.clone(),
+                finished: finished.clone(),
+            })
+        }
+    }
+
+    /// Matcher impl that fails when find has finished.
+    pub struct FailsWhenFinished {}
+
+    impl Matcher for FailsWhenFinished {
+        fn matches(&self, _: &WalkEntry, _: &mut MatcherIO) -> bool {
+            true
+        }
+
+        fn finished(&self, matcher_io: &mut MatcherIO) {
+            matcher_io.set_exit_code(1);
+        }
+
+        fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "fails_when_finished")
+        }
+    }
+
 
 
     #[test]
@@ -515,4 +722,59 @@ mod tests {
         assert!(!hasnt_fx.has_side_effects());
     }
 
+    #[test]
+    fn finished_is_forwarded_exactly_once() {
+        let unused = Rc::new(Cell::new(0));
+        let counts: Vec<Rc<Cell<usize>>> = (0..4).map(|_| Rc::new(Cell::new(0))).collect();
+
+        // ( p0 ! p1 -o p2 ) , p3
+        let and_matcher = AndMatcher::new(vec![Probe::new_box(&unused, &counts[0]),
+                                               NotMatcher::new_box(Probe::new_box(&unused,
+                                                                                  &counts[1]))]);
+        let or_matcher = OrMatcher::new(vec![Box::new(and_matcher),
+                                             Probe::new_box(&unused, &counts[2])]);
+        let list_matcher = ListMatcher::new(vec![Box::new(or_matcher),
+                                                 Probe::new_box(&unused, &counts[3])]);
+
+        let deps = FakeDependencies::new();
+        list_matcher.finished(&mut deps.new_matcher_io());
+        for count in &counts {
+            assert_eq!(count.get(), 1);
+        }
+    }
+
+    #[test]
+    fn finished_can_set_exit_code() {
+        let matcher = AndMatcher::new(vec![TrueMatcher::new_box(),
+                                           Box::new(FailsWhenFinished {})]);
+        let deps = FakeDependencies::new();
+        let mut matcher_io = deps.new_matcher_io();
+        assert_eq!(matcher_io.exit_code(), 0);
+        matcher.finished(&mut matcher_io);
+        assert_eq!(matcher_io.exit_code(), 1);
+    }
+
+    #[test]
+    fn quit_stops_evaluation() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let unused = Rc::new(Cell::new(0));
+        let deps = FakeDependencies::new();
+
+        let matches = Rc::new(Cell::new(0));
+        let and_matcher = AndMatcher::new(vec![QuitMatcher::new_box(),
+                                               Probe::new_box(&matches, &unused)]);
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(!and_matcher.matches(&abbbc, &mut matcher_io));
+        assert!(matcher_io.should_quit());
+        assert_eq!(matches.get(), 0);
+
+        let matches = Rc::new(Cell::new(0));
+        let list_matcher = ListMatcher::new(vec![Probe::new_box(&matches, &unused),
+                                                 QuitMatcher::new_box(),
+                                                 Probe::new_box(&matches, &unused)]);
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(list_matcher.matches(&abbbc, &mut matcher_io));
+        assert!(matcher_io.should_quit());
+        assert_eq!(matches.get(), 1);
+    }
 }
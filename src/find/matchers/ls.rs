@@ -0,0 +1,458 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! -ls, which describes each file the way "ls -dils" would, and the pieces of
+//! that description that -printf shares (so that the two can't disagree).
+
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::output_file::{Destination, OutputFile};
+use find::metadata::{FileKind, FileMetadata};
+use find::quoting::{c_escape, path_bytes};
+use find::time_zone::{TimeZone, MONTHS};
+
+/// Times further from now than this (about six months) are shown with a
+/// year rather than a time of day, as ls does.
+const RECENT_SECONDS: u64 = 15_778_476;
+
+/// The character ls uses for a type of file.
+pub fn type_char(file_type: FileKind) -> char {
+    match file_type {
+        FileKind::File => '-',
+        FileKind::Dir => 'd',
+        FileKind::Symlink => 'l',
+        FileKind::Fifo => 'p',
+        FileKind::Socket => 's',
+        FileKind::BlockDevice => 'b',
+        FileKind::CharDevice => 'c',
+        FileKind::Other => '?',
+    }
+}
+
+/// Renders a mode the way ls does (e.g. "drwxr-xr-x"): the type of file,
+/// then read, write and execute permission for the owner, the group and
+/// everyone else. The set-user-id, set-group-id and sticky bits take the
+/// place of the corresponding execute bits, in lower case if the execute
+/// bit is also set and upper case if it isn't.
+pub fn format_mode(mode: u32, file_type: FileKind) -> String {
+    let mut result = String::with_capacity(10);
+    result.push(type_char(file_type));
+    // each class's bits, and the special bit that shares its execute slot
+    let classes = [(mode >> 6, mode & 0o4000 != 0, 's'),
+                   (mode >> 3, mode & 0o2000 != 0, 's'),
+                   (mode, mode & 0o1000 != 0, 't')];
+    for &(bits, special, special_char) in &classes {
+        result.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        result.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        result.push(match (special, bits & 0o1 != 0) {
+            (true, true) => special_char,
+            (true, false) => special_char.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    result
+}
+
+pub fn seconds_since_epoch(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Renders a time the way ls does: "Oct 16 12:34" if it's within about six
+/// months of now, and "Oct 16  2016" if it isn't. The month's always in
+/// English (see `find::time_zone`).
+fn format_time(time: SystemTime, now: SystemTime, zone: &TimeZone) -> String {
+    let recent = match now.duration_since(time) {
+        Ok(age) => age.as_secs() <= RECENT_SECONDS,
+        // a little way into the future is still recent
+        Err(e) => e.duration().as_secs() <= 60 * 60,
+    };
+    let time = zone.broken_down(seconds_since_epoch(time));
+    let month = &MONTHS[time.month][..3];
+    if recent {
+        format!("{} {:>2} {:02}:{:02}", month, time.day, time.hour, time.minute)
+    } else {
+        format!("{} {:>2} {:>5}", month, time.day, time.year)
+    }
+}
+
+/// The major and minor numbers of the device that a block or character
+/// device file stands for, which -ls shows in place of its size. None for
+/// any other type of file (going by the type bits of the mode).
+#[cfg(unix)]
+fn device_numbers(metadata: &FileMetadata) -> Option<(u64, u64)> {
+    const S_IFMT: u32 = 0o170000;
+    const S_IFCHR: u32 = 0o020000;
+    const S_IFBLK: u32 = 0o060000;
+    match metadata.mode & S_IFMT {
+        S_IFCHR | S_IFBLK => {
+            let rdev = metadata.rdev as ::libc::dev_t;
+            Some((::libc::major(rdev) as u64, ::libc::minor(rdev) as u64))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn device_numbers(_: &FileMetadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// A path as -ls writes it, with anything unusual in it escaped. On
+/// Windows, the backslashes that separate its components are left alone.
+fn escape_path(path: &Path) -> Vec<u8> {
+    let bytes = path_bytes(path);
+    if cfg!(windows) {
+        let components: Vec<Vec<u8>> = bytes.split(|&byte| byte == b'\\').map(c_escape).collect();
+        components.join(&b'\\')
+    } else {
+        c_escape(&bytes)
+    }
+}
+
+/// The -ls action: writes the file's inode number, disk usage in blocks (see
+/// `MatcherIO::ls_block_size`), mode, number of links, owner, group, size
+/// (or major and minor device numbers, for a device), modification time and
+/// path (plus where it points, if it's a symbolic link), to stdout or (for
+/// -fls) a file. Always matches.
+pub struct Ls {
+    destination: Destination,
+}
+
+impl Ls {
+    pub fn new() -> Ls {
+        Ls { destination: Destination::Output }
+    }
+
+    pub fn new_box() -> Box<Matcher> {
+        Box::new(Ls::new())
+    }
+
+    pub fn new_box_for_file(file: Rc<OutputFile>) -> Box<Matcher> {
+        Box::new(Ls { destination: Destination::File(file) })
+    }
+}
+
+impl Matcher for Ls {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let metadata = match matcher_io.metadata(file_info) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                return true;
+            }
+        };
+
+        let size = match device_numbers(&metadata) {
+            Some((major, minor)) => format!("{:>3}, {:>3}", major, minor),
+            None => metadata.len.to_string(),
+        };
+        let mut output = format!("{:>9} {:>6} {} {:>3} {:<8} {:<8} {:>8} {} ",
+                                 metadata.ino,
+                                 metadata.blocks_of(matcher_io.ls_block_size()),
+                                 format_mode(metadata.mode, file_info.file_type()),
+                                 metadata.nlink,
+                                 matcher_io.user_group_cache().user_display_name(metadata.uid),
+                                 matcher_io.user_group_cache().group_display_name(metadata.gid),
+                                 size,
+                                 metadata.modified
+                                     .map_or_else(|| "?".to_string(),
+                                                  |time| {
+                                                      format_time(time,
+                                                                  matcher_io.now(),
+                                                                  matcher_io.time_zone())
+                                                  }))
+            .into_bytes();
+        output.extend(escape_path(file_info.path()));
+        if file_info.file_type().is_symlink() {
+            // if where it points can't be read, it's left out (and said why)
+            match file_info.read_link() {
+                Ok(target) => {
+                    output.extend_from_slice(b" -> ");
+                    output.extend(escape_path(&target));
+                }
+                Err(e) => report_io_error(file_info.path(), "", &e, matcher_io),
+            }
+        }
+        output.push(b'\n');
+
+        self.destination.write(matcher_io, false, |out| out.write_all(&output));
+        true
+    }
+
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+
+    fn only_writes_output(&self) -> bool {
+        self.destination.is_output()
+    }
+
+    fn finished(&self, matcher_io: &mut MatcherIO) {
+        self.destination.finished(matcher_io);
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.destination {
+            Destination::Output => write!(f, "ls"),
+            Destination::File(ref file) => write!(f, "fls({})", file.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use std::fs;
+    use std::time::Duration;
+
+    use find::matchers::Matcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::tests::{fix_up_slashes, FakeDependencies};
+    use find::users::UserGroupCache;
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn format_mode_table() {
+        use std::os::unix::fs::FileTypeExt;
+        use std::path::PathBuf;
+        use tempdir::TempDir;
+
+        use fixtures::{make_fifo, make_file, make_socket, make_symlink};
+
+        // real files of each type (and those that can't be made here are left
+        // out), to check that their types are told apart
+        let temp_dir = TempDir::new("format_mode_table").unwrap();
+        let dir = temp_dir.path();
+        let file_type = |path: &::std::path::Path| {
+            FileKind::from_file_type(&fs::symlink_metadata(path).unwrap().file_type())
+        };
+        let specials: Vec<(char, Option<PathBuf>)> = vec![('l', make_symlink(dir, "link", "file")),
+                                                          ('p', make_fifo(dir, "fifo")),
+                                                          ('s', make_socket(dir, "socket"))];
+        let mut types = vec![('-', file_type(&make_file(dir, "file", b""))),
+                             ('d', file_type(dir)),
+                             ('c', file_type("/dev/null".as_ref()))];
+        for (kind, path) in specials {
+            if let Some(path) = path {
+                types.push((kind, file_type(&path)));
+            }
+        }
+        // there mightn't be any block devices (e.g. in a container)
+        if let Some(entry) = fs::read_dir("/dev")
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_type().unwrap().is_block_device()) {
+            types.push(('b', FileKind::from_file_type(&entry.file_type().unwrap())));
+        }
+
+        let table = [(0o644, '-', "-rw-r--r--"),
+                     (0o755, 'd', "drwxr-xr-x"),
+                     (0o777, 'l', "lrwxrwxrwx"),
+                     (0o600, 'p', "prw-------"),
+                     (0o755, 's', "srwxr-xr-x"),
+                     (0o666, 'c', "crw-rw-rw-"),
+                     (0o660, 'b', "brw-rw----"),
+                     (0o000, '-', "----------"),
+                     (0o421, '-', "-r---w---x"),
+                     // the special bits, with and without the execute bits
+                     (0o4755, '-', "-rwsr-xr-x"),
+                     (0o4644, '-', "-rwSr--r--"),
+                     (0o2755, '-', "-rwxr-sr-x"),
+                     (0o2644, '-', "-rw-r-Sr--"),
+                     (0o1777, 'd', "drwxrwxrwt"),
+                     (0o1776, 'd', "drwxrwxrwT"),
+                     (0o7777, '-', "-rwsrwsrwt"),
+                     (0o7000, '-', "---S--S--T"),
+                     // the type bits of the mode are ignored in favour of the type
+                     (0o100644, 'd', "drw-r--r--")];
+        for &(mode, kind, expected) in &table {
+            if let Some(&(_, file_type)) = types.iter().find(|&&(c, _)| c == kind) {
+                assert_eq!(format_mode(mode, file_type), expected, "mode {:o}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn format_mode_of_walk_entries() {
+        let dir = get_dir_entry_for("test_data", "simple");
+        assert_eq!(format_mode(0o755, dir.file_type()), "drwxr-xr-x");
+        let file = get_dir_entry_for("test_data/simple", "abbbc");
+        assert_eq!(format_mode(0o4644, file.file_type()), "-rwSr--r--");
+    }
+
+    #[test]
+    fn format_time_recent_and_old() {
+        let now = SystemTime::now();
+        let recent = Regex::new(r"^[A-Z][a-z]{2} [ 123]\d \d\d:\d\d$").unwrap();
+        let old = Regex::new(r"^[A-Z][a-z]{2} [ 123]\d  \d{4}$").unwrap();
+
+        let hour = Duration::from_secs(60 * 60);
+        let local = TimeZone::Local;
+        assert!(recent.is_match(&format_time(now, now, &local)));
+        assert!(recent.is_match(&format_time(now - hour * 24 * 30, now, &local)));
+        assert!(recent.is_match(&format_time(now + hour / 2, now, &local)));
+        assert!(old.is_match(&format_time(now - hour * 24 * 365, now, &local)));
+        assert!(old.is_match(&format_time(now + hour * 24, now, &local)));
+        assert_eq!(format_time(UNIX_EPOCH + hour * 24 * 200, now, &local)[..3], *"Jul");
+    }
+
+    #[test]
+    fn ls_line() {
+        let mut deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        deps.set_time(UNIX_EPOCH + Duration::from_secs(1_000_000_000));
+        deps.set_user_group_cache(UserGroupCache::with_lookups(Box::new(|_| {
+                                                                   Some("root".to_string())
+                                                               }),
+                                                               Box::new(|_| None)));
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              len: 1234,
+                              mode: 0o100644,
+                              gid: 100,
+                              ino: 42,
+                              nlink: 2,
+                              blocks: 8,
+                              // long enough ago to be shown with a year
+                              modified: Some(UNIX_EPOCH + Duration::from_secs(86400 * 200 + 43200)),
+                              ..FileMetadata::default()
+                          });
+        assert!(Ls::new().matches(&file_info, &mut deps.new_matcher_io()));
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("       42      4 -rw-r--r--   2 root     100          1234 \
+                                   Jul 20  1970 test_data/simple/abbbc\n"));
+    }
+
+    #[test]
+    fn ls_time_zone() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let time = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let time_column = |tz: &str| -> String {
+            let mut deps = FakeDependencies::new();
+            deps.set_time(time);
+            deps.set_env_var("TZ", tz);
+            deps.set_metadata(file_info.path(),
+                              FileMetadata { modified: Some(time), ..FileMetadata::default() });
+            assert!(Ls::new().matches(&file_info, &mut deps.new_matcher_io()));
+            let output = deps.get_output_as_string();
+            output.split_whitespace().skip(7).take(3).collect::<Vec<_>>().join(" ")
+        };
+        // the month's in English whatever the locale
+        assert_eq!(time_column("UTC0"), "Sep 9 01:46");
+        assert_eq!(time_column("PST8"), "Sep 8 17:46");
+    }
+
+    #[test]
+    fn ls_block_size() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let blocks_column = |deps: &FakeDependencies| -> String {
+            assert!(Ls::new().matches(&file_info, &mut deps.new_matcher_io()));
+            deps.get_output_as_string().split_whitespace().nth(1).unwrap().to_string()
+        };
+        let mut deps = FakeDependencies::new();
+        // 8K allocated
+        let metadata = FileMetadata {
+            blocks: 16,
+            ..FileMetadata::default()
+        };
+        deps.set_metadata(file_info.path(), metadata.clone());
+        assert_eq!(blocks_column(&deps), "8");
+
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(), metadata);
+        deps.set_env_var("POSIXLY_CORRECT", "");
+        assert_eq!(blocks_column(&deps), "16");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ls_escapes_names() {
+        use std::fs::File;
+        use std::os::unix::fs::symlink;
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("ls_escapes_names").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        File::create(temp_dir.path().join("new\nline")).unwrap();
+        symlink("back\\slash", temp_dir.path().join("sp ace")).unwrap();
+
+        for &(name, expected) in &[("new\nline", "/new\\nline\n"),
+                                   ("sp ace", "/sp\\ ace -> back\\\\slash\n")] {
+            let deps = FakeDependencies::new();
+            assert!(Ls::new().matches(&get_dir_entry_for(&dir, name), &mut deps.new_matcher_io()));
+            let output = deps.get_output_as_string();
+            assert!(output.ends_with(&format!("{}{}", dir, expected)), "{}", output);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ls_device_numbers() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let size_column = |mode: u32, rdev: u64| -> String {
+            let mut deps = FakeDependencies::new();
+            deps.set_metadata(file_info.path(),
+                              FileMetadata {
+                                  len: 1234,
+                                  mode: mode,
+                                  rdev: rdev,
+                                  ..FileMetadata::default()
+                              });
+            assert!(Ls::new().matches(&file_info, &mut deps.new_matcher_io()));
+            // the size column's eight characters wide (at least), and with
+            // no modification time it's right before a "?"
+            let output = deps.get_output_as_string();
+            let end = output.find(" ? ").unwrap();
+            output[end - 9..end].to_string()
+        };
+        let null = ::libc::makedev(1, 3) as u64;
+        assert_eq!(size_column(0o020666, null), "   1,   3");
+        assert_eq!(size_column(0o060660, ::libc::makedev(259, 1024) as u64), "259, 1024");
+        // anything else shows its size, whatever its rdev
+        assert_eq!(size_column(0o100644, null), "     1234");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ls_unreadable_link() {
+        use std::os::unix::fs::symlink;
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("ls_unreadable_link").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        symlink("target", temp_dir.path().join("link")).unwrap();
+        let file_info = get_dir_entry_for(&dir, "link");
+        // gone by the time it's listed, but with its metadata already read
+        fs::remove_file(file_info.path()).unwrap();
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(), FileMetadata::default());
+
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(Ls::new().matches(&file_info, &mut matcher_io));
+        assert!(deps.get_output_as_string().ends_with(&format!("{}/link\n", dir)));
+        assert_eq!(deps.get_error_output_as_string(),
+                   format!("find: ‘{}/link’: No such file or directory\n", dir));
+        assert_eq!(matcher_io.exit_code(), 1);
+    }
+
+    #[test]
+    fn ls_metadata_error() {
+        let mut deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        deps.set_metadata_error(file_info.path(), 2);
+        assert!(Ls::new().matches(&file_info, &mut deps.new_matcher_io()));
+        assert_eq!(deps.get_output_as_string(), "");
+    }
+}
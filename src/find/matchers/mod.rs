@@ -4,31 +4,109 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
+mod compiled_glob;
+mod context;
 mod delete;
 pub mod exec;
+mod fold;
+mod fstype;
+mod glob_set;
+mod json;
+mod lint;
 mod logical_matchers;
+mod ls;
 mod name;
+mod output_file;
+pub mod parallel;
 mod perm;
+mod prefilter;
 mod printer;
+mod printf;
 mod prune;
+mod quit;
+mod regex_matcher;
+mod registry;
+mod samefile;
 mod size;
+mod stats;
 mod time;
 mod type_matcher;
+mod user;
+mod walk_entry;
 
 use regex::Regex;
+use std::cell::{Cell, RefCell};
 use std::error::Error;
-use std::path::PathBuf;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::SystemTime;
-use walkdir::DirEntry;
 
 use find::{Config, Dependencies};
+use find::error::FindError;
+use find::matchers::compiled_glob::CompiledGlob;
+use find::matchers::glob_set::GlobSubject;
+use find::matchers::parallel::ParallelMatcher;
+use find::metadata::{self, FileMetadata};
+use find::time_zone::TimeZone;
+use find::users::UserGroupCache;
+
+pub use self::logical_matchers::rebuild_matcher;
+pub use self::output_file::OutputFiles;
+pub use self::prefilter::Prefilter;
+pub use self::printer::ColorMode;
+pub use self::printf::FormatCache;
+pub use self::regex_matcher::RegexType;
+pub use self::registry::{ArgIter, MatcherFactory, MatcherRegistry, Standard};
+pub use self::walk_entry::WalkEntry;
+
+
+
+/// What the matchers have asked the walker to do, which it looks at after
+/// evaluating the expression for each entry.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WalkControl {
+    /// Whether to skip what's below the current entry (-prune). This only
+    /// applies to the entry it was set for: it's cleared before the next
+    /// one, and makes no difference for anything but a directory that's
+    /// about to be read.
+    pub skip_descendants: bool,
+    /// Whether to stop walking altogether (-quit), which lasts for the rest
+    /// of the run.
+    pub quit: bool,
+}
 
-
+impl WalkControl {
+    /// Clears whatever only applied to the previous entry.
+    pub fn start_entry(&mut self) {
+        self.skip_descendants = false;
+    }
+}
 
 /// Struct holding references to outputs and any inputs that can't be derived
-/// from the file/directory info.
+/// from the file/directory info. A single instance lives for the whole of a
+/// find run, so it's also where matchers record state that outlives a single
+/// entry (e.g. a request to quit, or a failure that should affect the exit
+/// code).
 pub struct MatcherIO<'a> {
-    should_skip_dir: bool,
+    walk_control: WalkControl,
+    exit_code: i32,
+    follow_links: bool,
+    follow_start_points: bool,
+    now: SystemTime,
+    age_reference_time: SystemTime,
+    ignore_readdir_race: bool,
+    ls_block_size: u64,
+    time_zone: TimeZone,
+    highlight_matches: bool,
+    highlighted: Option<Range<usize>>,
+    /// The current entry's metadata, once it's been looked up, so that
+    /// however many matchers want it, it's only looked up once.
+    cached_metadata: RefCell<Option<(PathBuf, FileMetadata)>>,
+    stat_calls: Cell<usize>,
+    commands_run: usize,
     deps: &'a Dependencies<'a>,
 }
 
@@ -36,30 +114,311 @@ impl<'a> MatcherIO<'a> {
     pub fn new(deps: &'a Dependencies<'a>) -> MatcherIO<'a> {
         MatcherIO {
             deps: deps,
-            should_skip_dir: false,
+            walk_control: WalkControl::default(),
+            exit_code: 0,
+            follow_links: false,
+            follow_start_points: false,
+            now: deps.now(),
+            age_reference_time: deps.now(),
+            ignore_readdir_race: false,
+            ls_block_size: if deps.env_var("POSIXLY_CORRECT").is_some() { 512 } else { 1024 },
+            time_zone: TimeZone::from_tz(deps.env_var("TZ").as_ref().map(|tz| tz.as_os_str())),
+            highlight_matches: false,
+            highlighted: None,
+            cached_metadata: RefCell::new(None),
+            stat_calls: Cell::new(0),
+            commands_run: 0,
         }
     }
 
+    /// The time zone that -ls, -printf and -daystart work in: the one TZ
+    /// names (see `find::time_zone`), unless --utc was given.
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.time_zone
+    }
+
+    /// Makes times be shown in UTC, and -daystart mean midnight UTC (with
+    /// --utc). This should come before `set_day_start`.
+    pub fn set_utc(&mut self) {
+        self.time_zone = TimeZone::utc();
+    }
+
+    /// The size of the blocks -ls gives a file's disk usage in: as with GNU
+    /// find, 1K, or 512 bytes (as POSIX says) if POSIXLY_CORRECT is set.
+    pub fn ls_block_size(&self) -> u64 {
+        self.ls_block_size
+    }
+
     pub fn mark_current_dir_to_be_skipped(&mut self) {
-        self.should_skip_dir = true;
+        self.walk_control.skip_descendants = true;
     }
 
     pub fn should_skip_current_dir(&self) -> bool {
-        self.should_skip_dir
+        self.walk_control.skip_descendants
     }
 
+    /// Asks find to stop walking (and evaluating the current expression) as
+    /// soon as possible.
+    pub fn quit(&mut self) {
+        self.walk_control.quit = true;
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.walk_control.quit
+    }
+
+    /// What the matchers have asked the walker to do so far.
+    pub fn walk_control(&self) -> WalkControl {
+        self.walk_control
+    }
+
+    /// Gets ready for the expression to be evaluated for another entry, so
+    /// that nothing asked for the previous one (other than quitting) applies
+    /// to it.
+    pub fn start_entry(&mut self) {
+        self.walk_control.start_entry();
+        self.highlighted = None;
+        self.forget_metadata();
+    }
+
+    /// Makes the next request for the current entry's metadata look it up
+    /// again, when find itself has changed the file (e.g. by flushing what
+    /// -fprint has written to it).
+    pub fn forget_metadata(&self) {
+        *self.cached_metadata.borrow_mut() = None;
+    }
+
+    /// How many times metadata has been looked up (i.e. how many stat or
+    /// lstat calls there have been), for "-D stat".
+    pub fn stat_calls(&self) -> usize {
+        self.stat_calls.get()
+    }
+
+    /// Records that a command has been run (by -exec or friends).
+    pub fn record_command_run(&mut self) {
+        self.commands_run += 1;
+    }
+
+    /// How many commands have been run, for "-D stat".
+    pub fn commands_run(&self) -> usize {
+        self.commands_run
+    }
+
+    /// Looks up metadata from the dependencies: everything that does goes
+    /// through here (or `stat_entry`), so that it's counted.
+    fn stat(&self, path: &Path, follow_links: bool) -> io::Result<FileMetadata> {
+        self.stat_calls.set(self.stat_calls.get() + 1);
+        self.deps.metadata(path, follow_links)
+    }
+
+    fn stat_entry(&self, file_info: &WalkEntry, follow_links: bool) -> io::Result<FileMetadata> {
+        self.stat_calls.set(self.stat_calls.get() + 1);
+        self.deps.entry_metadata(file_info, follow_links)
+    }
+
+    /// Sets whether -print colours what it prints (with --color), and so
+    /// whether -name and -iname should say what they matched.
+    pub fn set_highlight_matches(&mut self, highlight_matches: bool) {
+        self.highlight_matches = highlight_matches;
+    }
+
+    pub fn highlights_matches(&self) -> bool {
+        self.highlight_matches
+    }
+
+    /// Records which bytes of the current entry's path a test matched, for
+    /// -print to highlight.
+    pub fn highlight(&mut self, range: Range<usize>) {
+        self.highlighted = Some(range);
+    }
+
+    /// The bytes of the current entry's path to highlight, if any.
+    pub fn highlighted(&self) -> Option<Range<usize>> {
+        self.highlighted.clone()
+    }
+
+    /// Records that something went wrong, so that find exits with a non-zero
+    /// status even though it carried on.
+    pub fn set_exit_code(&mut self, code: i32) {
+        self.exit_code = code;
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    /// The time find started, as far as anything that compares times with
+    /// "now" is concerned. It's read from the dependencies once, when the
+    /// `MatcherIO` is created, and stays the same for the whole run, so
+    /// matchers should always get it from here at match time (rather than
+    /// from the clock, or when they're constructed).
     pub fn now(&self) -> SystemTime {
-        self.deps.now()
+        self.now
+    }
+
+    /// Sets whether file ages are measured from the start of tomorrow (with
+    /// -daystart), so that e.g. "-mtime 0" means "modified today".
+    pub fn set_day_start(&mut self, day_start: bool) {
+        self.age_reference_time = if day_start {
+            time::start_of_next_day(self.now, &self.time_zone)
+        } else {
+            self.now
+        };
+    }
+
+    /// The time that -atime and friends measure files' ages from.
+    pub fn age_reference_time(&self) -> SystemTime {
+        self.age_reference_time
+    }
+
+    /// Sets whether files that have disappeared since their directory was
+    /// read go unreported (with -ignore_readdir_race).
+    pub fn set_ignore_readdir_race(&mut self, ignore_readdir_race: bool) {
+        self.ignore_readdir_race = ignore_readdir_race;
+    }
+
+    /// Flushes anything that's been written to the output, so that it
+    /// appears before whatever a command that's about to be run writes to
+    /// the same place. Errors are left for the final flush to report.
+    pub fn flush_output(&self) {
+        let _ = self.deps.get_output().borrow_mut().flush();
+    }
+
+    /// Sets whether metadata is looked up for what symbolic links point to
+    /// (with -L), rather than for the links themselves.
+    pub fn set_follow_links(&mut self, follow_links: bool) {
+        self.follow_links = follow_links;
+    }
+
+    /// Sets whether start points that are symbolic links stand for what they
+    /// point to, even if nothing below them does (as with -H).
+    pub fn set_follow_start_points(&mut self, follow_start_points: bool) {
+        self.follow_start_points = follow_start_points;
+    }
+
+    /// Whether the given entry, if it's a symbolic link, stands for what it
+    /// points to: whether its metadata (and so on) should be that of the
+    /// link's target. The walk has already decided the same for the entry's
+    /// type (see `WalkEntry::file_type`), so matchers that look any further
+    /// should ask here rather than working it out for themselves. With -L, that's every
+    /// entry; with -H, only start points; and otherwise none.
+    pub fn follows_symlinks(&self, file_info: &WalkEntry) -> bool {
+        self.follow_links || (self.follow_start_points && file_info.depth() == 0)
+    }
+
+    /// Returns the metadata of the given entry. When links are followed for
+    /// it, that's the metadata of what it points to if it's a symbolic link,
+    /// unless that doesn't exist (in which case, as with GNU find, it's the
+    /// link's own). It's only looked up once for each entry (see
+    /// `start_entry`).
+    pub fn metadata(&self, file_info: &WalkEntry) -> io::Result<FileMetadata> {
+        if let Some((ref path, ref metadata)) = *self.cached_metadata.borrow() {
+            if path == file_info.path() {
+                return Ok(metadata.clone());
+            }
+        }
+        let metadata = self.uncached_metadata(file_info)?;
+        *self.cached_metadata.borrow_mut() = Some((file_info.path().to_path_buf(),
+                                                   metadata.clone()));
+        Ok(metadata)
+    }
+
+    fn uncached_metadata(&self, file_info: &WalkEntry) -> io::Result<FileMetadata> {
+        if self.follows_symlinks(file_info) {
+            match self.stat_entry(file_info, true) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                result => return result,
+            }
+        }
+        self.stat_entry(file_info, false)
+    }
+
+    /// Returns the metadata of a directory that's being searched (of what
+    /// it points to, if it's a symbolic link that find has gone into).
+    pub fn directory_metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        self.stat(path, true)
+    }
+
+    /// Returns the cache used to translate uids and gids to names.
+    pub fn user_group_cache(&self) -> &UserGroupCache {
+        self.deps.user_group_cache()
+    }
+
+    /// Returns the type of filesystem on the given device, or "unknown" if
+    /// it isn't in the mount table, as with GNU find.
+    pub fn fs_type(&self, dev: u64) -> Rc<str> {
+        self.deps.mount_table().fs_type_for_dev(dev).unwrap_or_else(|| Rc::from("unknown"))
     }
 }
 
+/// Returns the message that describes the error, without the " (os error N)"
+/// that std adds to OS errors (which GNU find doesn't have).
+pub fn error_message(err: &io::Error) -> String {
+    let message = err.to_string();
+    if let Some(code) = err.raw_os_error() {
+        let suffix = format!(" (os error {})", code);
+        if message.ends_with(&suffix) {
+            return message[..message.len() - suffix.len()].to_string();
+        }
+    }
+    message
+}
+
+/// Reads the metadata of a file named in the expression to compare entries
+/// with (e.g. -newer's), as GNU find does: with -L, a symbolic link is
+/// followed (so a dangling one is an error), and otherwise it's the link
+/// itself that entries are compared with.
+pub fn reference_metadata(path: &str, follow_links: bool) -> Result<FileMetadata, Box<Error>> {
+    metadata::read_metadata(Path::new(path), follow_links)
+        .map_err(|e| From::from(format!("‘{}’: {}", path, error_message(&e))))
+}
+
+/// Reports a failure to do something with a file (e.g. get its metadata) in
+/// the same words as GNU find, so that scripts that look for them keep
+/// working: "find: ‘path’: Permission denied", or with context before the
+/// path (e.g. "find: cannot delete ‘path’: ...") if it's not just the file
+/// that failed. Also makes sure that find's exit code shows the failure.
+///
+/// With -ignore_readdir_race, a file that no longer exists isn't reported.
+pub fn report_io_error(path: &Path, context: &str, err: &io::Error, matcher_io: &mut MatcherIO) {
+    if matcher_io.ignore_readdir_race && err.kind() == io::ErrorKind::NotFound {
+        return;
+    }
+    write_io_error(path, context, err, matcher_io);
+}
+
+/// The same as `report_io_error`, but even with -ignore_readdir_race (e.g.
+/// for start points, which the user said would be there, or commands that
+/// couldn't be run).
+pub fn write_io_error(path: &Path, context: &str, err: &io::Error, matcher_io: &mut MatcherIO) {
+    let context = if context.is_empty() {
+        String::new()
+    } else {
+        format!("{} ", context)
+    };
+    writeln!(matcher_io.deps.get_error_output().borrow_mut(),
+             "find: {}‘{}’: {}",
+             context,
+             path.to_string_lossy(),
+             error_message(err))
+        .unwrap();
+    matcher_io.set_exit_code(1);
+}
+
 /// A basic interface that can be used to determine whether a directory entry
 /// is what's being searched for. To a first order approximation, find consists
 /// of building a chain of Matcher objets, and then walking a directory tree,
 /// passing each entry to the chain of Matchers.
+///
+/// The expression is evaluated on the walk's thread, for one entry after
+/// another, so matchers aren't `Send` or `Sync`. They're free to keep state
+/// in `Cell`s and `RefCell`s, and to share files through `Rc`s. -exec's
+/// pending batches, -fprint's files and the counts for "-D rates" all do
+/// that, and all rely on that ordering. Tests that don't can be copied for
+/// other threads to evaluate (see `parallel`).
 pub trait Matcher {
     /// Returns whether the given file matches the object's predicate.
-    fn matches(&self, file_info: &DirEntry, matcher_io: &mut MatcherIO) -> bool;
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool;
 
     /// Returns whether the matcher has any side-effects (e.g. executing a
     /// command, deleting a file). Iff no such matcher exists in the chain, then
@@ -71,15 +430,115 @@ pub trait Matcher {
         return false;
     }
 
+    /// Returns tests of an entry's name and type that every entry the matcher
+    /// matches passes, if there are any such tests and the matcher wouldn't
+    /// do anything for an entry that fails them (so find can skip evaluating
+    /// it). Only the matchers for -name, -iname and -type have them, along
+    /// with -a (for as many of its leading sub-matchers as have them).
+    fn prefilter(&self) -> Option<Prefilter> {
+        None
+    }
+
     /// Notification that find has finished processing a given directory.
     fn finished_dir(&self, _finished_directory: &PathBuf) {}
 
     /// Notification that find has finished processing all directories -
     /// allowing for any cleanup that isn't suitable for destructors (e.g.
-    /// blocking calls, I/O etc.)
-    fn finished(&self) {}
+    /// blocking calls, I/O etc.) Called exactly once per run, even if find
+    /// stopped early. Any failures should be reported via
+    /// `matcher_io.set_exit_code`.
+    fn finished(&self, _matcher_io: &mut MatcherIO) {}
+
+    /// Writes a description of the matcher (for "-D tree", or for anyone who
+    /// wants to see what an expression was built into): the predicate's name
+    /// and what its arguments were parsed into, e.g. "name(*.c)", or for the
+    /// logical matchers, their sub-matchers' descriptions in turn, e.g.
+    /// "AND( name(*.c), OR( type(f), type(l) ), print )".
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// What sort of node the matcher is in the expression tree, so that
+    /// passes over the tree can tell the logical matchers apart without
+    /// knowing their types. Everything else is a leaf.
+    fn kind(&self) -> MatcherKind {
+        MatcherKind::Leaf
+    }
+
+    /// The matchers a logical matcher combines, in the order they're
+    /// evaluated (leaves have none).
+    fn children(&self) -> &[Box<Matcher>] {
+        &[]
+    }
+
+    /// Takes the matchers a logical matcher combines, leaving it with none
+    /// (or, for NOT, a placeholder): it's meant to be dropped afterwards,
+    /// with `rebuild_matcher` making a new one from the (possibly reordered
+    /// or rewritten) children.
+    fn take_children(&mut self) -> Vec<Box<Matcher>> {
+        vec![]
+    }
+
+    /// For -name, -iname, -path and -ipath, what the pattern is compared
+    /// with, and the pattern, so that an OR of lots of them can be collapsed
+    /// into a `GlobSetMatcher`. None for everything else.
+    fn glob(&self) -> Option<(GlobSubject, &CompiledGlob)> {
+        None
+    }
+
+    /// What the matcher always returns, if it only ever returns one thing
+    /// and does nothing else (i.e. for -true and -false), so that the
+    /// expression can be simplified around it (see `fold::fold_constants`).
+    fn constant(&self) -> Option<bool> {
+        None
+    }
+
+    /// Returns whether the matcher changes how the walk goes on (e.g. -prune
+    /// skipping a directory), which isn't a side effect as far as the
+    /// default -print is concerned, but is still a reason to evaluate it.
+    /// Only used for hints about the expression (see -warn).
+    fn affects_walk(&self) -> bool {
+        false
+    }
+
+    /// A copy of the matcher that other threads can evaluate (with
+    /// --threads), if it's a test that gives the same answer whichever thread
+    /// evaluates it, and in whatever order it's given entries. None for
+    /// everything else (including the logical matchers, which
+    /// `parallel::ParallelPlan` copies itself).
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        None
+    }
+
+    /// Whether the matcher's an action whose only side effect is writing to
+    /// find's own output (e.g. -print, but not -fprint), which can be left
+    /// until other threads have evaluated the tests before it (see
+    /// `parallel::ParallelPlan`).
+    fn only_writes_output(&self) -> bool {
+        false
+    }
 }
 
+/// The sorts of node in a matcher tree. See `Matcher::kind`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MatcherKind {
+    /// A predicate or action, e.g. -name or -print.
+    Leaf,
+    /// -a (or two matchers side by side).
+    And,
+    /// -o.
+    Or,
+    /// ",".
+    List,
+    /// "!" or -not.
+    Not,
+}
+
+impl<'a> fmt::Display for Matcher + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.describe(f)
+    }
+}
+
+#[derive(Clone, Copy)]
 pub enum ComparableValue {
     MoreThan(u64),
     EqualTo(u64),
@@ -105,20 +564,77 @@ impl ComparableValue {
     }
 }
 
+/// Writes the value the way it would be given on the command line (e.g. "+3").
+impl fmt::Display for ComparableValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ComparableValue::MoreThan(limit) => write!(f, "+{}", limit),
+            ComparableValue::EqualTo(limit) => write!(f, "{}", limit),
+            ComparableValue::LessThan(limit) => write!(f, "-{}", limit),
+        }
+    }
+}
+
 
 /// Builds a single `AndMatcher` containing the Matcher objects corresponding
 /// to the passed in predicate arguments.
 pub fn build_top_level_matcher(args: &[&str],
                                config: &mut Config)
                                -> Result<Box<Matcher>, Box<Error>> {
-    let (_, top_level_matcher) = (build_matcher_tree(args, config, 0, false))?;
+    build_top_level_matcher_with_registry(args, config, &MatcherRegistry::default())
+}
 
-    // if the matcher doesn't have any side-effects, then we default to printing
-    if !top_level_matcher.has_side_effects() {
+/// The same as `build_top_level_matcher`, but predicates are looked up in the
+/// given registry (which will usually be `MatcherRegistry::with_builtins()`
+/// plus some extras).
+pub fn build_top_level_matcher_with_registry(args: &[&str],
+                                             config: &mut Config,
+                                             registry: &MatcherRegistry)
+                                             -> Result<Box<Matcher>, Box<Error>> {
+    let (_, top_level_matcher) = (build_matcher_tree(args, config, registry, 0, false))?;
+    if config.hints_enabled {
+        // nothing depends on whether the whole expression matches, unless
+        // that's what --has-match is asking
+        lint::check_expression(top_level_matcher.as_ref(), config.has_match, &mut config.warnings);
+    }
+
+    let top_level_matcher = if config.has_match {
+        // with --has-match, all that matters is whether anything matches, so
+        // we stop at the first thing that does (and don't print it)
         let mut new_and_matcher = logical_matchers::AndMatcherBuilder::new();
         new_and_matcher.new_and_condition(top_level_matcher);
-        new_and_matcher.new_and_condition(printer::Printer::new_box());
-        return Ok(new_and_matcher.build());
+        new_and_matcher.new_and_condition(quit::QuitMatcher::new_box());
+        new_and_matcher.build()
+    } else if !top_level_matcher.has_side_effects() {
+        // if the matcher doesn't have any side-effects, then we default to
+        // printing
+        let mut new_and_matcher = logical_matchers::AndMatcherBuilder::new();
+        new_and_matcher.new_and_condition(top_level_matcher);
+        new_and_matcher
+            .new_and_condition(printer::Printer::new_box(printer::PrintDelimiter::Newline));
+        new_and_matcher.build()
+    } else {
+        top_level_matcher
+    };
+    // Folding comes last: an action that's never reached (as in "-false
+    // -print") still means there's no default -print.
+    let top_level_matcher = fold::fold_constants(top_level_matcher);
+    if config.threads > 1 {
+        // (the counts for -D rates are kept by matchers that describe
+        // themselves as what they count)
+        let reason = if config.debug_rates {
+            Some("with -D rates".to_string())
+        } else {
+            parallel::ParallelPlan::new(top_level_matcher.as_ref())
+                .err()
+                .map(|matcher| {
+                    format!("as ‘{}’ can't be evaluated on more than one thread", matcher)
+                })
+        };
+        if let Some(reason) = reason {
+            config.warnings.push(format!("--threads={} is ignored {}", config.threads, reason));
+            config.threads = 1;
+        }
     }
     Ok(top_level_matcher)
 }
@@ -180,12 +696,28 @@ fn convert_arg_to_comparable_value_and_suffix(option_name: &str,
 }
 
 
+/// Global options (-maxdepth, -xdev etc.) apply to the whole expression
+/// wherever they are, so, like GNU find, we warn about one that comes after
+/// a test or action, which it might look like it doesn't affect.
+fn check_global_option_position(option: &str, config: &mut Config) {
+    if let Some(ref predicate) = config.first_predicate {
+        config.warnings.push(format!("you have specified the global option {0} after the \
+                                      argument {1}, but global options are not positional, \
+                                      i.e., {0} affects tests specified before it as well as \
+                                      those specified after it.  Please specify global \
+                                      options before other arguments.",
+                                     option,
+                                     predicate));
+    }
+}
+
 /// The main "translate command-line args into a matcher" function. Will call
 /// itself recursively if it encounters an opening bracket. A successful return
 /// consits of a tuple containing the new index into the args array to use (if
 /// called recursively) and the resulting matcher.
 fn build_matcher_tree(args: &[&str],
                       config: &mut Config,
+                      registry: &MatcherRegistry,
                       arg_index: usize,
                       expecting_bracket: bool)
                       -> Result<(usize, Box<Matcher>), Box<Error>> {
@@ -197,101 +729,12 @@ fn build_matcher_tree(args: &[&str],
     // multiple-character flags don't start with a double dash
     let mut i = arg_index;
     let mut invert_next_matcher = false;
+    let mut previous_predicate = None;
     while i < args.len() {
+        if config.posix_mode {
+            registry.check_posix(args[i])?;
+        }
         let possible_submatcher = match args[i] {
-            "-print" => Some(printer::Printer::new_box()),
-            "-true" => Some(logical_matchers::TrueMatcher::new_box()),
-            "-false" => Some(logical_matchers::FalseMatcher::new_box()),
-            "-name" => {
-                if i >= args.len() - 1 {
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                i += 1;
-                Some(name::NameMatcher::new_box(args[i].as_ref())?)
-            }
-            "-iname" => {
-                if i >= args.len() - 1 {
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                i += 1;
-                Some(name::CaselessNameMatcher::new_box(args[i])?)
-            }
-            "-type" => {
-                if i >= args.len() - 1 {
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                i += 1;
-                Some(type_matcher::TypeMatcher::new_box(args[i])?)
-            }
-            "-delete" => {
-                // -delete implicitly requires -depth
-                config.depth_first = true;
-                Some(delete::DeleteMatcher::new_box()?)
-            }
-            "-newer" => {
-                if i >= args.len() - 1 {
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                i += 1;
-                Some(time::NewerMatcher::new_box(args[i])?)
-            }
-            "-mtime" | "-atime" | "-ctime" => {
-                if i >= args.len() - 1 {
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                let file_time_type = match args[i] {
-                    "-atime" => time::FileTimeType::Accessed,
-                    "-ctime" => time::FileTimeType::Created,
-                    "-mtime" => time::FileTimeType::Modified,
-                    // This shouldn't be possible. We've already checked the value
-                    // is one of those three values.
-                    _ => unreachable!("Encountered unexpected value {}", args[i]),
-                };
-                let days = convert_arg_to_comparable_value(args[i], args[i + 1])?;
-                i += 1;
-                Some(time::FileTimeMatcher::new_box(file_time_type, days))
-            }
-            "-size" => {
-                if i >= args.len() - 1 {
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                let (size, unit) = convert_arg_to_comparable_value_and_suffix(args[i],
-                                                                              args[i + 1])?;
-                i += 1;
-                Some(size::SizeMatcher::new_box(size, &unit)?)
-            }
-            "-exec" | "-execdir" => {
-                let mut arg_index = i + 1;
-                while arg_index < args.len() && args[arg_index] != ";" {
-                    if args[arg_index] == "+" {
-                        // MultiExecMatcher isn't written yet
-                        return Err(From::from(format!("{} [args...] + isn't supported yet. \
-                                                       Only {} [args...] ;",
-                                                      args[i],
-                                                      args[i])));
-                    }
-                    arg_index += 1;
-                }
-                if arg_index < i + 2 || arg_index == args.len() {
-                    // at the minimum we need the executable and the ';'
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                let expression = args[i];
-                let executable = args[i + 1];
-                let exec_args = &args[i + 2..arg_index];
-                i = arg_index;
-                Some(exec::SingleExecMatcher::new_box(executable,
-                                                      exec_args,
-                                                      expression == "-execdir")?)
-            }
-            "-perm" => {
-                if i >= args.len() - 1 {
-                    return Err(From::from(format!("missing argument to {}", args[i])));
-                }
-                i += 1;
-                Some(perm::PermMatcher::new_box(args[i])?)
-            }
-            "-prune" => Some(prune::PruneMatcher::new_box()),
             "-not" | "!" => {
                 if !are_more_expressions(args, i) {
                     return Err(From::from(format!("expected an expression after {}", args[i])));
@@ -321,7 +764,8 @@ fn build_matcher_tree(args: &[&str],
                 None
             }
             "(" => {
-                let (new_arg_index, sub_matcher) = build_matcher_tree(args, config, i + 1, true)?;
+                let (new_arg_index, sub_matcher) =
+                    build_matcher_tree(args, config, registry, i + 1, true)?;
                 i = new_arg_index;
                 Some(sub_matcher)
             }
@@ -332,19 +776,20 @@ fn build_matcher_tree(args: &[&str],
                 return Ok((i, top_level_matcher.build()));
             }
             "-d" | "-depth" => {
-                // TODO add warning if it appears after actual testing criterion
+                check_global_option_position(args[i], config);
                 config.depth_first = true;
                 None
             }
             "-sorted" => {
-                // TODO add warning if it appears after actual testing criterion
-                config.sorted_output = true;
+                check_global_option_position(args[i], config);
+                config.sort_entries = true;
                 None
             }
             "-maxdepth" => {
                 if i >= args.len() - 1 {
                     return Err(From::from(format!("missing argument to {}", args[i])));
                 }
+                check_global_option_position(args[i], config);
                 config.max_depth = convert_arg_to_number(args[i], args[i + 1])?;
                 i += 1;
                 None
@@ -353,16 +798,87 @@ fn build_matcher_tree(args: &[&str],
                 if i >= args.len() - 1 {
                     return Err(From::from(format!("missing argument to {}", args[i])));
                 }
+                check_global_option_position(args[i], config);
                 config.min_depth = convert_arg_to_number(args[i], args[i + 1])?;
                 i += 1;
                 None
             }
+            "-daystart" => {
+                check_global_option_position(args[i], config);
+                config.day_start = true;
+                None
+            }
+            "-follow" => {
+                check_global_option_position(args[i], config);
+                config.follow_links = true;
+                None
+            }
+            "-xdev" | "-mount" => {
+                check_global_option_position(args[i], config);
+                config.same_file_system = true;
+                None
+            }
+            "-noleaf" => {
+                // Only there for GNU find's benefit: we never assume that a
+                // directory's link count says how many subdirectories it has.
+                check_global_option_position(args[i], config);
+                None
+            }
+            "-ignore_readdir_race" | "-noignore_readdir_race" => {
+                check_global_option_position(args[i], config);
+                config.ignore_readdir_race = args[i] == "-ignore_readdir_race";
+                None
+            }
+            "-warn" | "-nowarn" => {
+                config.warnings_enabled = args[i] == "-warn";
+                config.hints_enabled = args[i] == "-warn";
+                None
+            }
+            "-regextype" => {
+                if i >= args.len() - 1 {
+                    return Err(From::from(format!("missing argument to {}", args[i])));
+                }
+                let regex_type = RegexType::from_name(args[i + 1])?;
+                if config.bsd_extended_regex && regex_type != RegexType::Extended {
+                    return Err(From::from(format!("-E conflicts with -regextype {}",
+                                                  args[i + 1])));
+                }
+                config.regex_type = regex_type;
+                i += 1;
+                None
+            }
             "-help" | "--help" => {
                 config.help_requested = true;
                 None
             }
 
-            _ => return Err(From::from(format!("Unrecognized flag: '{}'", args[i]))),
+            predicate => {
+                match registry.get(predicate) {
+                    Some(factory) => {
+                        let mut arg_iter = ArgIter::new(args, i);
+                        let mut matcher = factory(&mut arg_iter, config)?;
+                        if config.debug_rates {
+                            let label = args[i..arg_iter.index() + 1].join(" ");
+                            matcher = stats::StatsWrapper::new_box(&label, matcher);
+                        }
+                        i = arg_iter.index();
+                        previous_predicate = Some(predicate);
+                        if config.first_predicate.is_none() {
+                            config.first_predicate = Some(predicate.to_string());
+                        }
+                        Some(matcher)
+                    }
+                    None if !predicate.starts_with('-') => {
+                        // Probably a path (or a shell-expanded pattern)
+                        // that's been put after the expression.
+                        return Err(Box::new(FindError::PathsMustPrecedeExpression {
+                            path: predicate.to_string(),
+                            previous_predicate: previous_predicate.map(|p: &str| p.to_string()),
+                        }));
+                    }
+                    None => return Err(From::from(format!("Unrecognized flag: '{}'", predicate))),
+                }
+            }
         };
         if let Some(submatcher) = possible_submatcher {
             if invert_next_matcher {
@@ -383,7 +899,12 @@ fn build_matcher_tree(args: &[&str],
 
 #[cfg(test)]
 mod tests {
-    use walkdir::{DirEntry, WalkDir};
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::path::Path;
+    use std::rc::Rc;
+    use tempdir::TempDir;
+    use walkdir::WalkDir;
     use find::Config;
     use find::tests::fix_up_slashes;
     use find::tests::FakeDependencies;
@@ -391,19 +912,114 @@ mod tests {
 
 
 
-    /// Helper function for tests to get a DirEntry object. directory should
-    /// probably be a string starting with "test_data/" (cargo's tests run with
-    /// a working directory set to the root findutils folder).
-    pub fn get_dir_entry_for(directory: &str, filename: &str) -> DirEntry {
-        for wrapped_dir_entry in WalkDir::new(fix_up_slashes(directory)) {
+    /// Helper function for tests to get a WalkEntry object, as find would
+    /// make it when walking directory. directory should probably be a string
+    /// starting with "test_data/" (cargo's tests run with a working directory
+    /// set to the root findutils folder).
+    pub fn get_dir_entry_for(directory: &str, filename: &str) -> WalkEntry {
+        let directory = fix_up_slashes(directory);
+        let starting_point: Rc<Path> = Rc::from(Path::new(&directory));
+        for wrapped_dir_entry in WalkDir::new(&directory) {
             let dir_entry = wrapped_dir_entry.unwrap();
             if dir_entry.file_name().to_string_lossy() == filename {
-                return dir_entry;
+                return WalkEntry::new(dir_entry, starting_point);
             }
         }
         panic!("Couldn't find {} in {}", directory, filename);
     }
 
+    #[test]
+    fn matcher_io_uses_dependencies_user_group_cache() {
+        let mut deps = FakeDependencies::new();
+        deps.set_user_group_cache(UserGroupCache::with_lookups(Box::new(|_| None),
+                                                               Box::new(|_| {
+                                                                   Some("staff".to_string())
+                                                               })));
+        let matcher_io = deps.new_matcher_io();
+        assert_eq!(matcher_io.user_group_cache().name_for_uid(0), None);
+        assert_eq!(matcher_io.user_group_cache().group_display_name(20).as_ref(),
+                   "staff");
+    }
+
+    #[test]
+    fn walk_control_only_quitting_outlasts_the_entry() {
+        let deps = FakeDependencies::new();
+        let mut matcher_io = deps.new_matcher_io();
+        matcher_io.mark_current_dir_to_be_skipped();
+        assert_eq!(matcher_io.walk_control(),
+                   WalkControl {
+                       skip_descendants: true,
+                       quit: false,
+                   });
+        matcher_io.quit();
+        matcher_io.start_entry();
+        assert_eq!(matcher_io.walk_control(),
+                   WalkControl {
+                       skip_descendants: false,
+                       quit: true,
+                   });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks_per_entry() {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new("follows_symlinks_per_entry").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        fs::File::create(temp_dir.path().join("file")).unwrap();
+        symlink("file", temp_dir.path().join("link")).unwrap();
+        // the same link as a start point, and below one
+        let entries = [get_dir_entry_for(&format!("{}/link", dir), "link"),
+                       get_dir_entry_for(&dir, "link")];
+        assert_eq!((entries[0].depth(), entries[1].depth()), (0, 1));
+
+        let deps = FakeDependencies::new();
+        for &follow_links in &[false, true] {
+            let mut matcher_io = deps.new_matcher_io();
+            matcher_io.set_follow_links(follow_links);
+            for entry in &entries {
+                assert_eq!(matcher_io.follows_symlinks(entry), follow_links);
+                // and the metadata is what the decision says it should be
+                let mode = matcher_io.metadata(entry).unwrap().mode;
+                assert_eq!(mode & 0o170000 == 0o120000, !follow_links);
+            }
+        }
+
+        // with -H, the start point is followed, but what's below it isn't
+        let mut matcher_io = deps.new_matcher_io();
+        matcher_io.set_follow_start_points(true);
+        for (entry, &followed) in entries.iter().zip(&[true, false]) {
+            matcher_io.start_entry();
+            assert_eq!(matcher_io.follows_symlinks(entry), followed);
+            let mode = matcher_io.metadata(entry).unwrap().mode;
+            assert_eq!(mode & 0o170000 == 0o120000, !followed);
+        }
+    }
+
+    #[test]
+    fn report_io_error_format() {
+        let deps = FakeDependencies::new();
+        let mut matcher_io = deps.new_matcher_io();
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "No such file or directory");
+        report_io_error(Path::new("a/b"), "", &not_found, &mut matcher_io);
+        let other = io::Error::new(io::ErrorKind::Other, "something went wrong");
+        report_io_error(Path::new("c"), "cannot delete", &other, &mut matcher_io);
+        assert_eq!(deps.get_error_output_as_string(),
+                   "find: ‘a/b’: No such file or directory\n\
+                    find: cannot delete ‘c’: something went wrong\n");
+        assert_eq!(matcher_io.exit_code(), 1);
+    }
+
+    #[test]
+    fn error_message_drops_os_error_code() {
+        let err = io::Error::from_raw_os_error(2);
+        assert!(err.to_string().ends_with(" (os error 2)"));
+        assert!(!error_message(&err).contains("os error"));
+        assert!(!error_message(&err).is_empty());
+    }
+
     #[test]
     fn build_top_level_matcher_name() {
         let abbbc_lower = get_dir_entry_for("./test_data/simple", "abbbc");
@@ -450,6 +1066,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn build_top_level_matcher_prefilter() {
+        let abbbc = get_dir_entry_for("./test_data/simple", "abbbc");
+        let subdir = get_dir_entry_for("./test_data/simple", "subdir");
+        let prefilter = |args: &[&str]| {
+            build_top_level_matcher(args, &mut Config::default()).unwrap().prefilter()
+        };
+
+        let name = prefilter(&["-name", "a*"]).unwrap();
+        assert!(name.passes(&abbbc));
+        assert!(!name.passes(&subdir));
+        // the -print that's added isn't part of it
+        assert!(!name.is_complete());
+
+        let type_and_name = prefilter(&["-type", "d", "-iname", "SUB*", "-print"]).unwrap();
+        assert!(!type_and_name.passes(&abbbc));
+        assert!(type_and_name.passes(&subdir));
+
+        let bracketed = prefilter(&["(", "-type", "f", "-name", "a*", ")", "-size", "0"]).unwrap();
+        assert!(bracketed.passes(&abbbc));
+        assert!(!bracketed.passes(&subdir));
+
+        // there can't be anything that might have an effect, or an
+        // alternative, before the tests
+        for args in &[&["-print", "-name", "a*"][..],
+                      &["-prune", "-name", "a*"],
+                      &["-size", "0", "-name", "a*"],
+                      &["-name", "a*", "-o", "-type", "d"],
+                      &["-not", "-name", "a*"],
+                      &["-name", "a*", "-print", ",", "-type", "d"]] {
+            assert!(prefilter(args).is_none(), "{:?}", args);
+        }
+        // but a test whose value is thrown away is folded away
+        assert!(prefilter(&["-name", "a*", ",", "-type", "d"]).is_some());
+    }
+
     #[test]
     fn build_top_level_matcher_not_needs_expression() {
         for arg in &["-not", "!"] {
@@ -463,6 +1115,192 @@ mod tests {
         }
     }
 
+    /// An example of a predicate defined outside the parser: "-ext foo"
+    /// matches files whose extension is foo.
+    struct ExtensionMatcher {
+        extension: String,
+    }
+
+    impl Matcher for ExtensionMatcher {
+        fn matches(&self, file_info: &WalkEntry, _: &mut MatcherIO) -> bool {
+            match file_info.path().extension() {
+                Some(extension) => extension.to_string_lossy() == self.extension,
+                None => false,
+            }
+        }
+
+        fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "ext({})", self.extension)
+        }
+    }
+
+    fn build_extension_matcher(args: &mut ArgIter,
+                               _: &mut Config)
+                               -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(ExtensionMatcher { extension: args.next_required()?.to_string() }))
+    }
+
+    /// The description of the matcher built from the arguments (without the
+    /// -print that find adds to expressions that don't do anything).
+    fn describe(args: &[&str]) -> String {
+        let registry = MatcherRegistry::default();
+        let (_, matcher) = build_matcher_tree(args, &mut Config::default(), &registry, 0, false)
+            .unwrap();
+        matcher.to_string()
+    }
+
+    #[test]
+    fn describe_matcher_tree() {
+        assert_eq!(describe(&["-name", "*.c", "(", "-type", "f", "-o", "-type", "l", ")",
+                              "-print"]),
+                   "AND( name(*.c), OR( type(f), type(l) ), print )");
+        assert_eq!(describe(&["!", "-name", "*.o", "-size", "+10k", ",", "-not", "(", "-mtime",
+                              "-3", "-o", "-false", ")", "-print0"]),
+                   "LIST( AND( NOT( name(*.o) ), size(+10k) ), \
+                    AND( NOT( OR( mtime(-3), false ) ), print0 ) )");
+    }
+
+    #[test]
+    fn describe_leaves() {
+        // leaves show what their arguments were parsed into
+        let cases = [(&["-iname", "ABC*"][..], "iname(abc*)"),
+                     (&["-size", "2"], "size(2b)"),
+                     (&["-size", "-3M"], "size(-3M)"),
+                     (&["-atime", "+1"], "atime(+1)"),
+                     (&["-ctime", "1"], "ctime(1)"),
+                     (&["-user", "0"], "user(0)"),
+                     (&["-group", "0"], "group(0)"),
+                     (&["-exec", "echo", "{}", "x", ";"], "exec(echo {} x)"),
+                     (&["-okdir", "rm", "{}", ";"], "okdir(rm {})"),
+                     (&["-printf", "%-10p %%\\t%#m\\c"], r"printf(%-10p %%\t%#m\c)"),
+                     (&["-prune", "-quit"], "AND( prune, quit )"),
+                     (&["-true", "-delete"], "AND( true, delete )"),
+                     (&["-ls"], "ls")];
+        for &(args, expected) in &cases {
+            assert_eq!(describe(args), expected, "{:?}", args);
+        }
+        #[cfg(unix)]
+        assert_eq!(describe(&["-perm", "/u+w,g+w"]), "perm(/0220)");
+    }
+
+    /// Counts the nodes of each kind in a matcher tree.
+    fn count_kinds(matcher: &Matcher, counts: &mut HashMap<MatcherKind, usize>) {
+        *counts.entry(matcher.kind()).or_insert(0) += 1;
+        for child in matcher.children() {
+            count_kinds(child.as_ref(), counts);
+        }
+    }
+
+    #[test]
+    fn matcher_tree_structure() {
+        let registry = MatcherRegistry::default();
+        let args = ["!", "-name", "*.o", "-size", "+10k", ",", "-not", "(", "-mtime", "-3", "-o",
+                    "-false", ")", "-print0"];
+        let (_, matcher) = build_matcher_tree(&args, &mut Config::default(), &registry, 0, false)
+            .unwrap();
+        let mut counts = HashMap::new();
+        count_kinds(matcher.as_ref(), &mut counts);
+        let expected: HashMap<_, _> = vec![(MatcherKind::Leaf, 5),
+                                           (MatcherKind::And, 2),
+                                           (MatcherKind::Or, 1),
+                                           (MatcherKind::List, 1),
+                                           (MatcherKind::Not, 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(counts, expected);
+        assert_eq!(matcher.kind(), MatcherKind::List);
+        assert_eq!(matcher.children()[1].children()[1].to_string(), "print0");
+    }
+
+    /// Takes apart a matcher tree and puts it back together, reversing the
+    /// children of any -a (which is safe as long as they have no side
+    /// effects).
+    fn rebuild(mut matcher: Box<Matcher>) -> Box<Matcher> {
+        let kind = matcher.kind();
+        if kind == MatcherKind::Leaf {
+            return matcher;
+        }
+        let mut children: Vec<_> = matcher.take_children().into_iter().map(rebuild).collect();
+        if kind == MatcherKind::And {
+            children.reverse();
+        }
+        rebuild_matcher(kind, children).unwrap()
+    }
+
+    #[test]
+    fn rebuild_matcher_tree() {
+        let registry = MatcherRegistry::default();
+        let args = ["-type", "f", "-name", "a*", "-o", "!", "(", "-name", "s*", "-o", "-type",
+                    "f", ")"];
+        let build = || {
+            build_matcher_tree(&args, &mut Config::default(), &registry, 0, false).unwrap().1
+        };
+        let rebuilt = rebuild(build());
+        assert_eq!(rebuilt.to_string(),
+                   "OR( AND( name(a*), type(f) ), NOT( OR( name(s*), type(f) ) ) )");
+
+        let original = build();
+        let deps = FakeDependencies::new();
+        let starting_point: Rc<Path> = Rc::from(Path::new("test_data"));
+        let mut entries = 0;
+        for dir_entry in WalkDir::new("test_data") {
+            let entry = WalkEntry::new(dir_entry.unwrap(), starting_point.clone());
+            assert_eq!(rebuilt.matches(&entry, &mut deps.new_matcher_io()),
+                       original.matches(&entry, &mut deps.new_matcher_io()),
+                       "{}",
+                       entry.path().display());
+            entries += 1;
+        }
+        assert!(entries > 10);
+
+        assert!(rebuild_matcher(MatcherKind::Leaf, vec![]).is_none());
+        assert!(rebuild_matcher(MatcherKind::Not, vec![]).is_none());
+    }
+
+    #[test]
+    fn build_top_level_matcher_custom_predicate() {
+        let mut registry = MatcherRegistry::with_builtins();
+        registry.register("-ext", build_extension_matcher);
+
+        let temp_dir = TempDir::new("custom_predicate").unwrap();
+        for name in &["a.rs", "b.txt", "c"] {
+            File::create(temp_dir.path().join(name)).unwrap();
+        }
+        let temp_dir_path = temp_dir.path().to_string_lossy();
+        let rs = get_dir_entry_for(&temp_dir_path, "a.rs");
+        let txt = get_dir_entry_for(&temp_dir_path, "b.txt");
+        let neither = get_dir_entry_for(&temp_dir_path, "c");
+
+        // the argument to -ext has to be consumed, or "rs" would be taken
+        // as a (bad) predicate.
+        let mut config = Config::default();
+        let matcher = build_top_level_matcher_with_registry(&["-ext", "rs", "-o", "-name", "c"],
+                                                            &mut config,
+                                                            &registry)
+            .unwrap();
+        let deps = FakeDependencies::new();
+        assert!(matcher.matches(&rs, &mut deps.new_matcher_io()));
+        assert!(!matcher.matches(&txt, &mut deps.new_matcher_io()));
+        assert!(matcher.matches(&neither, &mut deps.new_matcher_io()));
+        assert_eq!(deps.get_output_as_string(),
+                   format!("{}\n{}\n",
+                           rs.path().to_string_lossy(),
+                           neither.path().to_string_lossy()));
+
+        if let Err(e) = build_top_level_matcher_with_registry(&["-ext"], &mut config, &registry) {
+            assert_eq!(e.to_string(), "missing argument to -ext");
+        } else {
+            panic!("-ext without an argument should fail");
+        }
+
+        // and the predicate is unknown without the registry
+        if let Err(e) = build_top_level_matcher(&["-ext", "rs"], &mut config) {
+            assert_eq!(e.to_string(), "Unrecognized flag: '-ext'");
+        } else {
+            panic!("-ext shouldn't be recognised by default");
+        }
+    }
+
     #[test]
     fn build_top_level_matcher_missing_args() {
         for arg in &["-iname", "-name", "-type"] {
@@ -586,6 +1424,33 @@ mod tests {
                    fix_up_slashes("./test_data/simple/abbbc\n"));
     }
 
+    #[test]
+    fn build_top_level_matcher_folds_constants() {
+        let abbbc = get_dir_entry_for("./test_data/simple", "abbbc");
+        // folding never loses an action that would have been reached, or
+        // adds a -print that wouldn't have been
+        for &(args, expected, output) in &[(&["-false", "-print"][..], "false", false),
+                                           (&["-print", "-false"], "AND( print, false )", true),
+                                           (&["-false", "-print", "-o", "-name", "abbbc"],
+                                            "name(abbbc)",
+                                            false),
+                                           (&["-name", "x*", "-o", "-true"],
+                                            "print",
+                                            true)] {
+            let mut config = Config::default();
+            let deps = FakeDependencies::new();
+            let matcher = build_top_level_matcher(args, &mut config).unwrap();
+            assert_eq!(matcher.to_string(), expected, "{:?}", args);
+            matcher.matches(&abbbc, &mut deps.new_matcher_io());
+            let printed = if output {
+                fix_up_slashes("./test_data/simple/abbbc\n")
+            } else {
+                String::new()
+            };
+            assert_eq!(deps.get_output_as_string(), printed, "{:?}", args);
+        }
+    }
+
     #[test]
     fn build_top_level_matcher_list_works() {
         let abbbc = get_dir_entry_for("./test_data/simple", "abbbc");
@@ -602,6 +1467,56 @@ mod tests {
                    fix_up_slashes("./test_data/simple/abbbc\n./test_data/simple/abbbc\n"));
     }
 
+    #[test]
+    fn build_top_level_matcher_precedence() {
+        // ( ) binds tightest, then !, then -a (or nothing), then -o, then ,
+        // which binds loosest, and whose value is that of its last
+        // expression - all checked against GNU find
+        let cases = [("-true , ( -false -o -true ) -false", false),
+                     ("-true , ( -false , -true )", true),
+                     ("( -true , -false ) -o -true", true),
+                     ("( -false , -true ) -a -true", true),
+                     ("! ( -true , -false )", true),
+                     ("! -false , -false", false),
+                     ("-false -o -true , -false", false),
+                     ("-true , -false -o -true", true),
+                     ("-false , -true -a ! -false", true),
+                     ("( -false -o ( -true , -false ) ) -o -true", true),
+                     ("( ( -false , -true ) ) , ( -true , ( -false ) )", false),
+                     ("-true -false -o -true , -true -o -false", true),
+                     ("-false -o -true -false , ! -true -o -true", true),
+                     ("! ! -true , -false -o ! -true", false),
+                     ("( -true -o -false , -false ) -o -false", false),
+                     ("-true , -true , -false", false),
+                     ("( -false , -true , -true ) -false -o -true", true)];
+        let abbbc = get_dir_entry_for("./test_data/simple", "abbbc");
+        for &(expression, expected) in &cases {
+            let args: Vec<&str> = expression.split(' ').collect();
+            let matcher = build_top_level_matcher(&args, &mut Config::default()).unwrap();
+            let deps = FakeDependencies::new();
+            assert_eq!(matcher.matches(&abbbc, &mut deps.new_matcher_io()),
+                       expected,
+                       "{}",
+                       expression);
+        }
+
+        // and a , in parentheses only sequences what's in them: the
+        // expressions either side of the parentheses are evaluated (or not)
+        // as they would be for any other group
+        let cases = [("-printf a , ( -false -o -printf b ) -printf c", "abc"),
+                     ("-printf a , ( -false , -printf b ) -printf c -o -printf d", "abc"),
+                     ("( -printf a , -false ) -printf b -o -printf c , -printf d", "acd"),
+                     ("-false ( -printf a , -printf b ) -o -printf c", "c"),
+                     ("! ( -printf a , -false ) -printf b , -printf c", "abc")];
+        for &(expression, expected) in &cases {
+            let args: Vec<&str> = expression.split(' ').collect();
+            let matcher = build_top_level_matcher(&args, &mut Config::default()).unwrap();
+            let deps = FakeDependencies::new();
+            matcher.matches(&abbbc, &mut deps.new_matcher_io());
+            assert_eq!(deps.get_output_as_string(), expected, "{}", expression);
+        }
+    }
+
     #[test]
     fn build_top_level_matcher_list_without_expr1() {
         let mut config = Config::default();
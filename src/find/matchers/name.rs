@@ -4,61 +4,188 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
-use glob::Pattern;
 use glob::PatternError;
-use walkdir::DirEntry;
 
-use find::matchers::{Matcher, MatcherIO};
+use std::fmt;
+use std::ops::Range;
+use std::path;
 
-/// This matcher makes a case-sensitive comparison of the name against a
-/// shell wildcard pattern. See `glob::Pattern` for details on the exact
-/// syntax.
+use find::matchers::{Matcher, MatcherIO, Prefilter, WalkEntry};
+use find::matchers::compiled_glob::CompiledGlob;
+use find::matchers::glob_set::GlobSubject;
+use find::matchers::parallel::ParallelMatcher;
+use find::quoting::path_bytes;
+
+/// This matcher compares the name against a shell wildcard pattern (see
+/// `glob::Pattern` for details on the exact syntax), either as it is
+/// (-name) or ignoring case (-iname).
+#[derive(Clone)]
 pub struct NameMatcher {
-    pattern: Pattern,
+    pattern: CompiledGlob,
+    case_insensitive: bool,
 }
 
 impl NameMatcher {
-    pub fn new(pattern_string: &str) -> Result<NameMatcher, PatternError> {
-        let p = Pattern::new(pattern_string)?;
-        Ok(NameMatcher { pattern: p })
+    pub fn new(pattern_string: &str, case_insensitive: bool) -> Result<NameMatcher, PatternError> {
+        let pattern = if case_insensitive {
+            CompiledGlob::new_caseless(pattern_string)?
+        } else {
+            CompiledGlob::new(pattern_string)?
+        };
+        Ok(NameMatcher {
+            pattern: pattern,
+            case_insensitive: case_insensitive,
+        })
     }
 
-    pub fn new_box(pattern_string: &str) -> Result<Box<Matcher>, PatternError> {
-        Ok(Box::new(NameMatcher::new(pattern_string)?))
+    pub fn new_box(pattern_string: &str,
+                   case_insensitive: bool)
+                   -> Result<Box<Matcher>, PatternError> {
+        Ok(Box::new(NameMatcher::new(pattern_string, case_insensitive)?))
+    }
+
+    /// Matches like `matches`, but says what matched: the range of the bytes
+    /// of the entry's path that its name takes up (which, as a pattern has
+    /// to match the whole name, is all of it), or None if it doesn't match.
+    pub fn matched_range(&self, file_info: &WalkEntry) -> Option<Range<usize>> {
+        let name = file_info.name();
+        if !self.pattern.matches(name.to_string_lossy().as_ref()) {
+            return None;
+        }
+        let path = path_bytes(file_info.path());
+        let name_len = path_bytes(path::Path::new(name)).len();
+        // a start point's name doesn't include any trailing slashes (unless
+        // it's nothing but slashes)
+        let mut end = path.len();
+        while end > name_len && path::is_separator(path[end - 1] as char) {
+            end -= 1;
+        }
+        Some(end - name_len..end)
     }
 }
 
 impl Matcher for NameMatcher {
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        self.pattern.matches(file_info.file_name().to_string_lossy().as_ref())
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        if !matcher_io.highlights_matches() {
+            return self.pattern.matches(file_info.name().to_string_lossy().as_ref());
+        }
+        match self.matched_range(file_info) {
+            Some(range) => {
+                matcher_io.highlight(range);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn prefilter(&self) -> Option<Prefilter> {
+        let pattern = self.pattern.clone();
+        Some(Prefilter::new(move |file_info| {
+            pattern.matches(file_info.name().to_string_lossy().as_ref())
+        }))
+    }
+
+    fn glob(&self) -> Option<(GlobSubject, &CompiledGlob)> {
+        if self.case_insensitive {
+            Some((GlobSubject::CaselessName, &self.pattern))
+        } else {
+            Some((GlobSubject::Name, &self.pattern))
+        }
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.case_insensitive {
+            write!(f, "iname({})", self.pattern)
+        } else {
+            write!(f, "name({})", self.pattern)
+        }
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
     }
 }
 
-/// This matcher makes a case-insensitive comparison of the name against a
-/// shell wildcard pattern. See `glob::Pattern` for details on the exact
-/// syntax.
-pub struct CaselessNameMatcher {
-    pattern: Pattern,
+impl ParallelMatcher for NameMatcher {}
+
+
+/// This matcher makes a case-sensitive comparison of the whole path (as it
+/// would be printed) against a shell wildcard pattern, in which "*" and "?"
+/// match slashes too.
+#[derive(Clone)]
+pub struct PathMatcher {
+    pattern: CompiledGlob,
 }
 
-impl CaselessNameMatcher {
-    pub fn new(pattern_string: &str) -> Result<CaselessNameMatcher, PatternError> {
-        let p = Pattern::new(&pattern_string.to_lowercase())?;
-        Ok(CaselessNameMatcher { pattern: p })
+impl PathMatcher {
+    pub fn new(pattern_string: &str) -> Result<PathMatcher, PatternError> {
+        let p = CompiledGlob::new(pattern_string)?;
+        Ok(PathMatcher { pattern: p })
     }
 
     pub fn new_box(pattern_string: &str) -> Result<Box<Matcher>, PatternError> {
-        Ok(Box::new(CaselessNameMatcher::new(pattern_string)?))
+        Ok(Box::new(PathMatcher::new(pattern_string)?))
     }
 }
 
-impl super::Matcher for CaselessNameMatcher {
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        self.pattern
-            .matches(file_info.file_name().to_string_lossy().to_lowercase().as_ref())
+impl Matcher for PathMatcher {
+    fn matches(&self, file_info: &WalkEntry, _: &mut MatcherIO) -> bool {
+        self.pattern.matches(file_info.path().to_string_lossy().as_ref())
+    }
+
+    fn glob(&self) -> Option<(GlobSubject, &CompiledGlob)> {
+        Some((GlobSubject::Path, &self.pattern))
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "path({})", self.pattern)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl ParallelMatcher for PathMatcher {}
+
+/// This matcher makes a case-insensitive comparison of the whole path
+/// against a shell wildcard pattern, as `PathMatcher` does.
+#[derive(Clone)]
+pub struct CaselessPathMatcher {
+    pattern: CompiledGlob,
+}
+
+impl CaselessPathMatcher {
+    pub fn new(pattern_string: &str) -> Result<CaselessPathMatcher, PatternError> {
+        let p = CompiledGlob::new(&pattern_string.to_lowercase())?;
+        Ok(CaselessPathMatcher { pattern: p })
+    }
+
+    pub fn new_box(pattern_string: &str) -> Result<Box<Matcher>, PatternError> {
+        Ok(Box::new(CaselessPathMatcher::new(pattern_string)?))
+    }
+}
+
+impl Matcher for CaselessPathMatcher {
+    fn matches(&self, file_info: &WalkEntry, _: &mut MatcherIO) -> bool {
+        self.pattern.matches(file_info.path().to_string_lossy().to_lowercase().as_ref())
+    }
+
+    fn glob(&self) -> Option<(GlobSubject, &CompiledGlob)> {
+        Some((GlobSubject::CaselessPath, &self.pattern))
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ipath({})", self.pattern)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
     }
 }
 
+impl ParallelMatcher for CaselessPathMatcher {}
+
 
 #[cfg(test)]
 
@@ -72,7 +199,7 @@ mod tests {
     #[test]
     fn matching_with_wrong_case_returns_false() {
         let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
-        let matcher = NameMatcher::new(&"A*C".to_string()).unwrap();
+        let matcher = NameMatcher::new("A*C", false).unwrap();
         let deps = FakeDependencies::new();
         assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()));
     }
@@ -80,7 +207,7 @@ mod tests {
     #[test]
     fn matching_with_right_case_returns_true() {
         let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
-        let matcher = NameMatcher::new(&"abb?c".to_string()).unwrap();
+        let matcher = NameMatcher::new("abb?c", false).unwrap();
         let deps = FakeDependencies::new();
         assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
     }
@@ -88,21 +215,21 @@ mod tests {
     #[test]
     fn not_matching_returns_false() {
         let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
-        let matcher = NameMatcher::new(&"should't match".to_string()).unwrap();
+        let matcher = NameMatcher::new("should't match", false).unwrap();
         let deps = FakeDependencies::new();
         assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()));
     }
 
     #[test]
     fn cant_create_with_invalid_pattern() {
-        let result = NameMatcher::new(&"a**c".to_string());
+        let result = NameMatcher::new("a**c", false);
         assert!(result.is_err());
     }
 
     #[test]
     fn caseless_matching_with_wrong_case_returns_true() {
         let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
-        let matcher = CaselessNameMatcher::new(&"A*C".to_string()).unwrap();
+        let matcher = NameMatcher::new("A*C", true).unwrap();
         let deps = FakeDependencies::new();
         assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
     }
@@ -110,7 +237,7 @@ mod tests {
     #[test]
     fn caseless_matching_with_right_case_returns_true() {
         let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
-        let matcher = CaselessNameMatcher::new(&"abb?c".to_string()).unwrap();
+        let matcher = NameMatcher::new("abb?c", true).unwrap();
         let deps = FakeDependencies::new();
         assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
     }
@@ -118,14 +245,106 @@ mod tests {
     #[test]
     fn caseless_not_matching_returns_false() {
         let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
-        let matcher = CaselessNameMatcher::new(&"should't match".to_string()).unwrap();
+        let matcher = NameMatcher::new("should't match", true).unwrap();
         let deps = FakeDependencies::new();
         assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()));
     }
 
     #[test]
     fn caseless_cant_create_with_invalid_pattern() {
-        let result = CaselessNameMatcher::new(&"a**c".to_string());
+        let result = NameMatcher::new("a**c", true);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn matched_ranges() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let path_len = abbbc.path().to_string_lossy().len();
+        let matcher = NameMatcher::new("A*C", true).unwrap();
+        assert_eq!(matcher.matched_range(&abbbc), Some(path_len - 5..path_len));
+        assert_eq!(NameMatcher::new("A*C", false).unwrap().matched_range(&abbbc), None);
+
+        // a start point's trailing slash isn't part of its name
+        let simple = get_dir_entry_for("test_data/simple/", "simple");
+        let path_len = simple.path().to_string_lossy().len();
+        let matcher = NameMatcher::new("sim*", false).unwrap();
+        assert_eq!(matcher.matched_range(&simple), Some(path_len - 7..path_len - 1));
+    }
+
+    #[test]
+    fn highlights_matches() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let path_len = abbbc.path().to_string_lossy().len();
+        let deps = FakeDependencies::new();
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(NameMatcher::new("a*", false).unwrap().matches(&abbbc, &mut matcher_io));
+        // only asked for with --color
+        assert_eq!(matcher_io.highlighted(), None);
+
+        matcher_io.set_highlight_matches(true);
+        assert!(!NameMatcher::new("b*", false).unwrap().matches(&abbbc, &mut matcher_io));
+        assert_eq!(matcher_io.highlighted(), None);
+        assert!(NameMatcher::new("a*", false).unwrap().matches(&abbbc, &mut matcher_io));
+        assert_eq!(matcher_io.highlighted(), Some(path_len - 5..path_len));
+        matcher_io.start_entry();
+        assert_eq!(matcher_io.highlighted(), None);
+    }
+
+    /// Things that have to work the same way whether or not case is ignored.
+    #[test]
+    fn shared_edge_cases() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let deps = FakeDependencies::new();
+        for &case_insensitive in &[false, true] {
+            for pattern in &["abbbc", "*", "a*", "*c", "a?bbc", "a*b*c", "[a]bbbc", "[!x]bbbc",
+                             "[a-c]bb[!d-z]c", "abb*bc", "**"] {
+                let matcher = NameMatcher::new(pattern, case_insensitive).unwrap();
+                assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()),
+                        "{} {}",
+                        pattern,
+                        case_insensitive);
+            }
+            for pattern in &["", "abbb", "bbbc", "abbbc?", "[!a]bbbc", "*/abbbc", "[]]*"] {
+                let matcher = NameMatcher::new(pattern, case_insensitive).unwrap();
+                assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()),
+                        "{} {}",
+                        pattern,
+                        case_insensitive);
+            }
+            assert!(NameMatcher::new("[abc", case_insensitive).is_err());
+        }
+    }
+
+    #[test]
+    fn describe_name_matchers() {
+        let matcher: Box<Matcher> = NameMatcher::new_box("A*c", false).unwrap();
+        assert_eq!(matcher.to_string(), "name(A*c)");
+        let matcher: Box<Matcher> = NameMatcher::new_box("A*c", true).unwrap();
+        assert_eq!(matcher.to_string(), "iname(a*c)");
+    }
+
+    #[test]
+    fn path_matching() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let deps = FakeDependencies::new();
+        for pattern in &["test_data/simple/abbbc", "*/abbbc", "test_data*", "*simple/a*c"] {
+            let matcher = PathMatcher::new(pattern).unwrap();
+            assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()), "{}", pattern);
+        }
+        for pattern in &["abbbc", "*/ABBBC", "test_data/*/x*"] {
+            let matcher = PathMatcher::new(pattern).unwrap();
+            assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()), "{}", pattern);
+        }
+        assert!(PathMatcher::new("a**c").is_err());
+    }
+
+    #[test]
+    fn caseless_path_matching() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let deps = FakeDependencies::new();
+        let matcher = CaselessPathMatcher::new("TEST_DATA/*/ABB?C").unwrap();
+        assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+        let matcher = CaselessPathMatcher::new("*/SUBDIR/*").unwrap();
+        assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+    }
 }
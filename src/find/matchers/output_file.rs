@@ -0,0 +1,314 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! -fprint, -fprint0, -fprintf and -fls write to a file rather than to find's
+//! own output. Each file is created (or truncated, unless --append-output
+//! says to add to what's there) once the whole expression has been parsed
+//! (so that a mistake in it doesn't leave anything behind), and only once
+//! however many times it's named, so that predicates naming the same file
+//! take turns writing to it rather than overwriting each other.
+//!
+//! The files can be inside the tree being searched. As they exist before the
+//! walk starts, find comes across each of them exactly once, like any other
+//! file: writing to one makes it longer but doesn't change the directory
+//! listing being read. Before the expression is evaluated for one, whatever
+//! has been written to it so far is flushed (see `OutputFiles::get`), so
+//! that tests of its size or contents don't depend on how much of that was
+//! still buffered.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use find::matchers::{error_message, report_io_error, MatcherIO};
+use find::metadata::FileMetadata;
+
+/// A file opened for -fprint and friends.
+pub struct OutputFile {
+    /// The name the file was given on the command line.
+    name: String,
+    /// None until the file's been created.
+    writer: RefCell<Option<BufWriter<File>>>,
+    /// The file's device and inode numbers, which are how find recognises
+    /// it when it comes across it (None on platforms without them).
+    id: Cell<Option<(u64, u64)>>,
+}
+
+impl OutputFile {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        match *self.writer.borrow_mut() {
+            Some(ref mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Calls `write` with the file's writer.
+    fn write<F>(&self, flush: bool, write: F) -> io::Result<()>
+        where F: FnOnce(&mut Write) -> io::Result<()>
+    {
+        match *self.writer.borrow_mut() {
+            Some(ref mut writer) => {
+                write(writer)?;
+                if flush { writer.flush() } else { Ok(()) }
+            }
+            None => Err(io::Error::new(io::ErrorKind::Other, "file hasn't been created")),
+        }
+    }
+
+    /// Creates the file, or opens it to be added to if `append` is true. A
+    /// new file's permissions are left to the umask, as with the shell's
+    /// redirections.
+    fn create(&self, append: bool) -> Result<(), Box<Error>> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(&self.name)
+            .map_err(|e| format!("‘{}’: {}", self.name, error_message(&e)))?;
+        self.id.set(file.metadata().ok().and_then(|metadata| file_id(&metadata)));
+        *self.writer.borrow_mut() = Some(BufWriter::new(file));
+        Ok(())
+    }
+}
+
+/// Where a printing matcher's output goes.
+#[derive(Clone)]
+pub enum Destination {
+    /// find's own output (normally stdout).
+    Output,
+    /// A file named by -fprint etc.
+    File(Rc<OutputFile>),
+}
+
+impl Destination {
+    /// Whether it's find's own output.
+    pub fn is_output(&self) -> bool {
+        match *self {
+            Destination::Output => true,
+            Destination::File(_) => false,
+        }
+    }
+
+    /// Calls `write` with the destination's writer, flushing it afterwards
+    /// if asked to. If writing to find's output fails because whoever was
+    /// reading it has stopped (e.g. "find | head"), find is told to quit.
+    /// Any other failure to write to it (e.g. a full disk) is reported, and
+    /// makes find's exit code 1, and find quits then too, as there'd be no
+    /// point carrying on. Failures to write to a file are reported, and make
+    /// find's exit code 1, but it carries on.
+    pub fn write<F>(&self, matcher_io: &mut MatcherIO, flush: bool, write: F)
+        where F: FnOnce(&mut Write) -> io::Result<()>
+    {
+        match *self {
+            Destination::Output => {
+                let result = {
+                    let mut out = matcher_io.deps.get_output().borrow_mut();
+                    write(&mut *out).and_then(|_| if flush { out.flush() } else { Ok(()) })
+                };
+                match result {
+                    Ok(()) => {}
+                    Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => matcher_io.quit(),
+                    Err(e) => {
+                        let _ = writeln!(matcher_io.deps.get_error_output().borrow_mut(),
+                                         "find: write error: {}",
+                                         error_message(&e));
+                        matcher_io.deps.output_failed();
+                        matcher_io.set_exit_code(1);
+                        matcher_io.quit();
+                    }
+                }
+            }
+            Destination::File(ref file) => {
+                if let Err(e) = file.write(flush, write) {
+                    report_io_error(Path::new(&file.name), "", &e, matcher_io);
+                }
+            }
+        }
+    }
+
+    /// Flushes a file's output once find has finished, reporting any
+    /// failure (find's own output is flushed by whoever owns it).
+    pub fn finished(&self, matcher_io: &mut MatcherIO) {
+        if let Destination::File(ref file) = *self {
+            if let Err(e) = file.flush() {
+                report_io_error(Path::new(&file.name), "", &e, matcher_io);
+            }
+        }
+    }
+}
+
+/// The files that have been opened for -fprint and friends, by name.
+#[derive(Default)]
+pub struct OutputFiles {
+    files: HashMap<String, Rc<OutputFile>>,
+}
+
+impl OutputFiles {
+    pub fn new() -> OutputFiles {
+        OutputFiles { files: HashMap::new() }
+    }
+
+    /// Returns the file with the given name, which will be created by
+    /// `create_all` (the same file each time it's asked for).
+    pub fn open(&mut self, name: &str) -> Rc<OutputFile> {
+        self.files
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                Rc::new(OutputFile {
+                    name: name.to_string(),
+                    writer: RefCell::new(None),
+                    id: Cell::new(None),
+                })
+            })
+            .clone()
+    }
+
+    /// Creates (or truncates) all the files that have been opened, failing
+    /// if any of them can't be. With `append`, existing files are added to
+    /// instead, as with --append-output.
+    pub fn create_all(&self, append: bool) -> Result<(), Box<Error>> {
+        for file in self.files.values() {
+            file.create(append)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the output file with the given metadata, if it's one of them.
+    pub fn get(&self, metadata: &FileMetadata) -> Option<&Rc<OutputFile>> {
+        if metadata.dev == 0 && metadata.ino == 0 {
+            return None;
+        }
+        self.files.values().find(|file| file.id.get() == Some((metadata.dev, metadata.ino)))
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &::std::fs::Metadata) -> Option<(u64, u64)> {
+    let metadata = FileMetadata::from_metadata(metadata);
+    Some((metadata.dev, metadata.ino))
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &::std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempdir::TempDir;
+
+    use find::metadata;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    #[test]
+    fn opens_each_file_once() {
+        let temp_dir = TempDir::new("output_file").unwrap();
+        let path = temp_dir.path().join("out.txt");
+        fs::write(&path, "old contents").unwrap();
+        let name = path.to_string_lossy().to_string();
+
+        let mut output_files = OutputFiles::new();
+        assert!(output_files.is_empty());
+        let first = Destination::File(output_files.open(&name));
+        let second = Destination::File(output_files.open(&name));
+        assert!(!output_files.is_empty());
+        // nothing happens to the file until it's created
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old contents");
+        output_files.create_all(false).unwrap();
+
+        let deps = FakeDependencies::new();
+        let mut matcher_io = deps.new_matcher_io();
+        first.write(&mut matcher_io, false, |out| out.write_all(b"first\n"));
+        second.write(&mut matcher_io, false, |out| out.write_all(b"second\n"));
+        first.finished(&mut matcher_io);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        assert_eq!(matcher_io.exit_code(), 0);
+    }
+
+    #[test]
+    fn appends_to_existing_files() {
+        let temp_dir = TempDir::new("output_file").unwrap();
+        let path = temp_dir.path().join("out.txt");
+        let name = path.to_string_lossy().to_string();
+        let deps = FakeDependencies::new();
+        let run = |append: bool| {
+            let mut output_files = OutputFiles::new();
+            let file = Destination::File(output_files.open(&name));
+            output_files.create_all(append).unwrap();
+            let mut matcher_io = deps.new_matcher_io();
+            file.write(&mut matcher_io, false, |out| out.write_all(b"line\n"));
+            file.finished(&mut matcher_io);
+        };
+
+        // a new file's created either way
+        run(true);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line\n");
+        run(true);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line\nline\n");
+        run(false);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_follow_umask() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new("output_file").unwrap();
+        let path = temp_dir.path().join("out.txt");
+        let mut output_files = OutputFiles::new();
+        output_files.open(&path.to_string_lossy());
+        output_files.create_all(false).unwrap();
+        // the same as any other new file
+        let reference = temp_dir.path().join("reference.txt");
+        File::create(&reference).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), mode(&reference));
+    }
+
+    #[test]
+    fn recognises_output_files() {
+        let temp_dir = TempDir::new("output_file").unwrap();
+        let path = temp_dir.path().join("out.txt");
+        let other = temp_dir.path().join("other.txt");
+        File::create(&other).unwrap();
+
+        let mut output_files = OutputFiles::new();
+        let file = output_files.open(&path.to_string_lossy());
+        output_files.create_all(false).unwrap();
+        let metadata_of = |path: &Path| metadata::read_metadata(path, false).unwrap();
+        if cfg!(unix) {
+            assert!(Rc::ptr_eq(output_files.get(&metadata_of(&path)).unwrap(), &file));
+        }
+        assert!(output_files.get(&metadata_of(&other)).is_none());
+        assert!(output_files.get(&FileMetadata::default()).is_none());
+    }
+
+    #[test]
+    fn open_failure() {
+        let temp_dir = TempDir::new("output_file").unwrap();
+        let name = temp_dir.path().join("missing").join("out.txt").to_string_lossy().to_string();
+        let mut output_files = OutputFiles::new();
+        output_files.open(&name);
+        let e = output_files.create_all(false).err().unwrap();
+        assert!(e.to_string().starts_with(&format!("‘{}’: ", name)), "{}", e);
+    }
+}
@@ -0,0 +1,616 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Evaluating the expression on more than one thread (--threads). Matchers
+//! aren't `Send` or `Sync` (see `Matcher`), but the tests that only look at
+//! the entry they're given, like -name, -type and -size, can make copies of
+//! themselves that are (see `Matcher::parallel`). So when an expression is
+//! made of nothing but such tests, followed by an action that only writes
+//! to find's output (as the default -print does), the walk sends entries to
+//! worker threads in batches for them to evaluate the tests, and runs the
+//! action itself for the entries they match, in the order it came to them
+//! (see `ParallelTests`). The output is the same as with one thread, and
+//! stays on the walk's thread, so it needs no lock.
+//!
+//! Anything else keeps the whole expression on the walk's thread (see
+//! `ParallelPlan::new`): -exec and -ok run commands (and -ok asks about
+//! them) in the order the entries are found, -fprint and friends share
+//! their files with whatever else names them, -prune and -quit steer the
+//! walk as it goes, and "-D rates" keeps count as it goes. So does a walk
+//! whose dependencies the worker threads can't stand in for (see
+//! `WorkerDependencies`).
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+
+use find::Dependencies;
+use find::fsinfo::MountTable;
+use find::matchers::{Matcher, MatcherIO, MatcherKind, WalkEntry};
+use find::matchers::walk_entry::DetachedEntry;
+use find::metadata::{self, FileMetadata};
+use find::time_zone::TimeZone;
+use find::users::UserGroupCache;
+
+/// How many entries are sent to a worker thread at a time.
+const BATCH_SIZE: usize = 256;
+
+/// How many batches the walk can be waiting for the results of, for each
+/// worker thread, before it stops to run the action for the oldest.
+const BATCHES_PER_WORKER: usize = 2;
+
+/// A matcher that can be evaluated on any thread, for as many entries at a
+/// time as there are threads: a test that only looks at the entry it's
+/// given (and what `MatcherIO` can tell it about the entry, e.g. its
+/// metadata), and keeps nothing from one entry to the next. See
+/// `Matcher::parallel`.
+pub trait ParallelMatcher: Matcher + Send + Sync {}
+
+impl fmt::Display for ParallelMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.describe(f)
+    }
+}
+
+/// The copy of an AND, OR, NOT or LIST: the same logic, over the copies of
+/// its sub-matchers.
+struct ParallelNode {
+    kind: MatcherKind,
+    children: Vec<Box<ParallelMatcher>>,
+}
+
+impl Matcher for ParallelNode {
+    // (tests never ask find to quit, so unlike the originals, these needn't
+    // check)
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let mut children = self.children.iter();
+        match self.kind {
+            MatcherKind::And => children.all(|child| child.matches(file_info, matcher_io)),
+            MatcherKind::Or => children.any(|child| child.matches(file_info, matcher_io)),
+            MatcherKind::Not => !self.children[0].matches(file_info, matcher_io),
+            MatcherKind::List => {
+                children.fold(false, |_, child| child.matches(file_info, matcher_io))
+            }
+            MatcherKind::Leaf => unreachable!("leaves make copies of themselves"),
+        }
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.kind {
+            MatcherKind::And => "AND",
+            MatcherKind::Or => "OR",
+            MatcherKind::Not => "NOT",
+            MatcherKind::List => "LIST",
+            MatcherKind::Leaf => unreachable!("leaves make copies of themselves"),
+        };
+        write!(f, "{}( ", name)?;
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            child.describe(f)?;
+        }
+        write!(f, " )")
+    }
+
+    fn kind(&self) -> MatcherKind {
+        self.kind
+    }
+}
+
+impl ParallelMatcher for ParallelNode {}
+
+/// Copies an expression so that it can be evaluated on other threads, or
+/// returns the first matcher in it that can't be.
+fn parallel_copy(matcher: &Matcher) -> Result<Box<ParallelMatcher>, &Matcher> {
+    if matcher.kind() == MatcherKind::Leaf {
+        return matcher.parallel().ok_or(matcher);
+    }
+    let children = matcher.children()
+        .iter()
+        .map(|child| parallel_copy(child.as_ref()))
+        .collect::<Result<_, _>>()?;
+    Ok(Box::new(ParallelNode {
+        kind: matcher.kind(),
+        children: children,
+    }))
+}
+
+/// How a top-level matcher (see `build_top_level_matcher`) is evaluated on
+/// more than one thread: its tests by worker threads, and its action by the
+/// walk's own thread, for each entry the tests match.
+pub struct ParallelPlan<'m> {
+    pub tests: Arc<ParallelMatcher>,
+    pub action: &'m Matcher,
+}
+
+impl<'m> ParallelPlan<'m> {
+    /// Splits the matcher into tests and an action, if that's all it's made
+    /// of: an AND of tests, the last of which is an action that only writes
+    /// to find's output (or just such an action). Otherwise, returns what
+    /// keeps it on one thread (as -D tree would describe it).
+    pub fn new(matcher: &'m Matcher) -> Result<ParallelPlan<'m>, String> {
+        let (action, tests) = match (matcher.kind(), matcher.children().split_last()) {
+            (MatcherKind::And, Some((action, tests))) => (action.as_ref(), tests),
+            _ => (matcher, &[][..]),
+        };
+        if !action.only_writes_output() {
+            return Err(action.to_string());
+        }
+        let mut copies = vec![];
+        for test in tests {
+            copies.push(parallel_copy(test.as_ref()).map_err(|test| test.to_string())?);
+        }
+        Ok(ParallelPlan {
+            tests: Arc::new(ParallelNode {
+                kind: MatcherKind::And,
+                children: copies,
+            }),
+            action: action,
+        })
+    }
+}
+
+/// The settings in the walk's `MatcherIO` (see `Walker::run`), for the
+/// worker threads to evaluate the tests with.
+struct Settings {
+    follow_links: bool,
+    follow_start_points: bool,
+    now: SystemTime,
+    age_reference_time: SystemTime,
+    ignore_readdir_race: bool,
+    ls_block_size: u64,
+    time_zone: TimeZone,
+    highlight_matches: bool,
+}
+
+impl Settings {
+    fn of(matcher_io: &MatcherIO) -> Settings {
+        Settings {
+            follow_links: matcher_io.follow_links,
+            follow_start_points: matcher_io.follow_start_points,
+            now: matcher_io.now,
+            age_reference_time: matcher_io.age_reference_time,
+            ignore_readdir_race: matcher_io.ignore_readdir_race,
+            ls_block_size: matcher_io.ls_block_size,
+            time_zone: matcher_io.time_zone.clone(),
+            highlight_matches: matcher_io.highlight_matches,
+        }
+    }
+
+    fn apply(self, matcher_io: &mut MatcherIO) {
+        matcher_io.follow_links = self.follow_links;
+        matcher_io.follow_start_points = self.follow_start_points;
+        matcher_io.now = self.now;
+        matcher_io.age_reference_time = self.age_reference_time;
+        matcher_io.ignore_readdir_race = self.ignore_readdir_race;
+        matcher_io.ls_block_size = self.ls_block_size;
+        matcher_io.time_zone = self.time_zone;
+        matcher_io.highlight_matches = self.highlight_matches;
+    }
+}
+
+/// A worker thread's dependencies. Tests don't write to the output or read
+/// any input, and what they write to the error output (e.g. about files that
+/// have gone) is kept, for the walk's thread to write along with the results
+/// (see `BatchResult`). Metadata comes from the file system, and users,
+/// groups and mounts from the system's tables, so these only stand in for
+/// dependencies that do the same (see
+/// `Dependencies::supports_worker_threads`).
+struct WorkerDependencies {
+    output: RefCell<io::Sink>,
+    error_output: RefCell<Vec<u8>>,
+    input: RefCell<io::Empty>,
+    now: SystemTime,
+    user_group_cache: UserGroupCache,
+    mount_table: MountTable,
+}
+
+impl WorkerDependencies {
+    fn new(now: SystemTime) -> WorkerDependencies {
+        WorkerDependencies {
+            output: RefCell::new(io::sink()),
+            error_output: RefCell::new(vec![]),
+            input: RefCell::new(io::empty()),
+            now: now,
+            user_group_cache: UserGroupCache::new(),
+            mount_table: MountTable::new(),
+        }
+    }
+}
+
+impl<'a> Dependencies<'a> for WorkerDependencies {
+    fn get_output(&'a self) -> &'a RefCell<Write> {
+        &self.output
+    }
+
+    fn get_error_output(&'a self) -> &'a RefCell<Write> {
+        &self.error_output
+    }
+
+    fn get_input(&'a self) -> &'a RefCell<BufRead> {
+        &self.input
+    }
+
+    fn now(&'a self) -> SystemTime {
+        self.now
+    }
+
+    fn user_group_cache(&'a self) -> &'a UserGroupCache {
+        &self.user_group_cache
+    }
+
+    fn mount_table(&'a self) -> &'a MountTable {
+        &self.mount_table
+    }
+
+    fn metadata(&'a self, path: &Path, follow_links: bool) -> io::Result<FileMetadata> {
+        metadata::read_metadata(path, follow_links)
+    }
+
+    // (what the environment says is already in the settings)
+    fn env_var(&'a self, _name: &str) -> Option<OsString> {
+        None
+    }
+}
+
+/// Entries for a worker thread to evaluate the tests for, which are all
+/// under the same starting point.
+struct Batch {
+    id: usize,
+    starting_point: PathBuf,
+    entries: Vec<DetachedEntry>,
+}
+
+/// What a worker thread found for a batch.
+struct BatchResult {
+    id: usize,
+    /// For each entry, None if the tests didn't match it, or if they did,
+    /// what they highlighted of its path (see `MatcherIO::highlight`).
+    matches: Vec<Option<Option<Range<usize>>>>,
+    /// What the tests wrote to the error output.
+    errors: Vec<u8>,
+    exit_code: i32,
+    stat_calls: usize,
+}
+
+/// Makes the walk's thread panic too if a worker thread does, rather than
+/// leaving it waiting for results that won't come.
+struct PanicGuard(Sender<Option<BatchResult>>);
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let _ = self.0.send(None);
+        }
+    }
+}
+
+/// A worker thread: evaluates the tests for batches until the walk's done.
+fn work(tests: Arc<ParallelMatcher>,
+        settings: Settings,
+        batches: Arc<Mutex<Receiver<Batch>>>,
+        results: Sender<Option<BatchResult>>) {
+    let results = PanicGuard(results);
+    let deps = WorkerDependencies::new(settings.now);
+    let mut matcher_io = MatcherIO::new(&deps);
+    settings.apply(&mut matcher_io);
+    loop {
+        // (the lock's only held while waiting for the next batch)
+        let batch = batches.lock().unwrap().recv();
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(_) => return,
+        };
+        let starting_point: Rc<Path> = Rc::from(batch.starting_point.as_path());
+        let stat_calls = matcher_io.stat_calls();
+        let matches = batch.entries
+            .into_iter()
+            .map(|entry| {
+                let entry = entry.attach(starting_point.clone());
+                matcher_io.start_entry();
+                if tests.matches(&entry, &mut matcher_io) {
+                    Some(matcher_io.highlighted())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let result = BatchResult {
+            id: batch.id,
+            matches: matches,
+            errors: mem::replace(&mut *deps.error_output.borrow_mut(), vec![]),
+            exit_code: matcher_io.exit_code(),
+            stat_calls: matcher_io.stat_calls() - stat_calls,
+        };
+        matcher_io.set_exit_code(0);
+        if results.0.send(Some(result)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Worker threads evaluating the tests of a `ParallelPlan` for the entries
+/// the walk gives them, and the entries that are waiting for the results.
+pub struct ParallelTests {
+    /// None once the workers have been told to stop.
+    batches: Option<Sender<Batch>>,
+    results: Receiver<Option<BatchResult>>,
+    workers: Vec<JoinHandle<()>>,
+    /// The entries for the next batch, which hasn't been sent yet.
+    batch: Vec<WalkEntry>,
+    /// The entries of the batches that have been sent, oldest first, and the
+    /// id of the oldest.
+    sent: VecDeque<Vec<WalkEntry>>,
+    oldest_sent: usize,
+    /// Results that came back before those of a batch sent before them.
+    early_results: HashMap<usize, BatchResult>,
+}
+
+impl ParallelTests {
+    /// Starts the worker threads, which evaluate the tests with the same
+    /// settings (e.g. -L) as the given `MatcherIO`.
+    pub fn new(threads: usize, tests: Arc<ParallelMatcher>, matcher_io: &MatcherIO)
+               -> ParallelTests {
+        let (batch_sender, batch_receiver) = mpsc::channel();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let batch_receiver = Arc::new(Mutex::new(batch_receiver));
+        let workers = (0..threads)
+            .map(|_| {
+                let tests = tests.clone();
+                let settings = Settings::of(matcher_io);
+                let batches = batch_receiver.clone();
+                let results = result_sender.clone();
+                thread::spawn(move || work(tests, settings, batches, results))
+            })
+            .collect();
+        ParallelTests {
+            batches: Some(batch_sender),
+            results: result_receiver,
+            workers: workers,
+            batch: vec![],
+            sent: VecDeque::new(),
+            oldest_sent: 0,
+            early_results: HashMap::new(),
+        }
+    }
+
+    /// Adds an entry to those waiting for the tests to be evaluated.
+    pub fn push(&mut self, entry: WalkEntry) {
+        if self.batch.first().map_or(false, |first| {
+            first.starting_point() != entry.starting_point()
+        }) {
+            self.send_batch();
+        }
+        self.batch.push(entry);
+        if self.batch.len() >= BATCH_SIZE {
+            self.send_batch();
+        }
+    }
+
+    fn send_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let entries = mem::replace(&mut self.batch, vec![]);
+        let batch = Batch {
+            id: self.oldest_sent + self.sent.len(),
+            starting_point: entries[0].starting_point().to_path_buf(),
+            entries: entries.iter().map(WalkEntry::detach).collect(),
+        };
+        // (the workers only stop once this has been dropped)
+        self.batches.as_ref().unwrap().send(batch).unwrap();
+        self.sent.push_back(entries);
+    }
+
+    /// Waits for the results of the oldest batch that's been sent.
+    fn oldest_result(&mut self) -> BatchResult {
+        if let Some(result) = self.early_results.remove(&self.oldest_sent) {
+            return result;
+        }
+        loop {
+            match self.results.recv() {
+                Ok(Some(result)) => {
+                    if result.id == self.oldest_sent {
+                        return result;
+                    }
+                    self.early_results.insert(result.id, result);
+                }
+                _ => panic!("a thread evaluating the expression panicked"),
+            }
+        }
+    }
+
+    /// Runs the action for the entries that the tests matched, in the order
+    /// they were given, as their results come in: for all of them if `all`
+    /// is true, and otherwise, for as many as it takes for the walk not to
+    /// get too far ahead of the workers. Anything the workers wrote to the
+    /// error output is written first (batch by batch), and their exit codes
+    /// and metadata lookups count as the walk's own. Stops if the action
+    /// asks find to quit. Returns how many entries the whole expression
+    /// matched.
+    pub fn run_action(&mut self, action: &Matcher, all: bool, matcher_io: &mut MatcherIO)
+                      -> usize {
+        if all {
+            self.send_batch();
+        }
+        let mut matched = 0;
+        while !matcher_io.should_quit() &&
+              (all && !self.sent.is_empty() ||
+               self.sent.len() > BATCHES_PER_WORKER * self.workers.len()) {
+            let result = self.oldest_result();
+            let entries = self.sent.pop_front().unwrap();
+            self.oldest_sent += 1;
+            let _ = matcher_io.deps.get_error_output().borrow_mut().write_all(&result.errors);
+            if result.exit_code != 0 {
+                matcher_io.set_exit_code(result.exit_code);
+            }
+            matcher_io.stat_calls.set(matcher_io.stat_calls.get() + result.stat_calls);
+            for (entry, highlighted) in entries.iter().zip(result.matches) {
+                if let Some(highlighted) = highlighted {
+                    matcher_io.start_entry();
+                    if let Some(range) = highlighted {
+                        matcher_io.highlight(range);
+                    }
+                    if action.matches(entry, matcher_io) {
+                        matched += 1;
+                    }
+                    if matcher_io.should_quit() {
+                        break;
+                    }
+                }
+            }
+        }
+        matched
+    }
+}
+
+impl Drop for ParallelTests {
+    /// Tells the workers to stop once they've finished what they have, and
+    /// waits for them to.
+    fn drop(&mut self) {
+        self.batches = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use find::matchers::{Matcher, MatcherIO, WalkEntry};
+    use find::matchers::logical_matchers::{AndMatcher, FalseMatcher, ListMatcher, NotMatcher,
+                                           OrMatcher, TrueMatcher};
+    use find::matchers::name::NameMatcher;
+    use find::matchers::printer::{PrintDelimiter, Printer};
+    use find::matchers::prune::PruneMatcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::matchers::type_matcher::TypeMatcher;
+    use find::tests::{fix_up_slashes, FakeDependencies};
+    use super::*;
+
+    /// A test that can't be evaluated on other threads.
+    struct Unsafe;
+
+    impl Matcher for Unsafe {
+        fn matches(&self, _: &WalkEntry, _: &mut MatcherIO) -> bool {
+            true
+        }
+
+        fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "unsafe")
+        }
+    }
+
+    fn name(pattern: &str) -> Box<Matcher> {
+        NameMatcher::new_box(pattern, false).unwrap()
+    }
+
+    fn print() -> Box<Matcher> {
+        Printer::new_box(PrintDelimiter::Newline)
+    }
+
+    fn and(submatchers: Vec<Box<Matcher>>) -> Box<Matcher> {
+        Box::new(AndMatcher::new(submatchers))
+    }
+
+    fn or(submatchers: Vec<Box<Matcher>>) -> Box<Matcher> {
+        Box::new(OrMatcher::new(submatchers))
+    }
+
+    fn list(submatchers: Vec<Box<Matcher>>) -> Box<Matcher> {
+        Box::new(ListMatcher::new(submatchers))
+    }
+
+    #[test]
+    fn copies_behave_the_same() {
+        let expressions = vec![and(vec![name("a*"), TypeMatcher::new_box("f", false).unwrap()]),
+                               or(vec![name("x*"), name("*c")]),
+                               NotMatcher::new_box(name("abbbc")),
+                               list(vec![name("abbbc"), TrueMatcher::new_box()]),
+                               list(vec![TrueMatcher::new_box(), name("abbbc")]),
+                               and(vec![]),
+                               or(vec![FalseMatcher::new_box()])];
+        let deps = FakeDependencies::new();
+        for expression in &expressions {
+            let copy = parallel_copy(expression.as_ref()).ok().unwrap();
+            assert_eq!(copy.to_string(), expression.to_string());
+            for name in &["simple", "abbbc", "subdir", "ABBBC"] {
+                let entry = get_dir_entry_for("test_data/simple", name);
+                assert_eq!(copy.matches(&entry, &mut deps.new_matcher_io()),
+                           expression.matches(&entry, &mut deps.new_matcher_io()),
+                           "{} for {}",
+                           expression,
+                           name);
+            }
+        }
+    }
+
+    #[test]
+    fn tests_and_an_action_are_split() {
+        let matcher = and(vec![name("a*"), NotMatcher::new_box(name("*c")), print()]);
+        let plan = ParallelPlan::new(matcher.as_ref()).ok().unwrap();
+        assert_eq!(plan.tests.to_string(), "AND( name(a*), NOT( name(*c) ) )");
+        assert_eq!(plan.action.to_string(), "print");
+
+        // an action on its own has nothing to test first
+        let matcher = print();
+        let plan = ParallelPlan::new(matcher.as_ref()).ok().unwrap();
+        assert!(plan.tests.children().is_empty());
+        assert_eq!(plan.action.to_string(), "print");
+    }
+
+    #[test]
+    fn anything_else_stays_on_one_thread() {
+        let reason = |matcher: Box<Matcher>| ParallelPlan::new(matcher.as_ref()).err().unwrap();
+        // a test that can't be copied, however deep it is
+        assert_eq!(reason(and(vec![or(vec![name("a"), Box::new(Unsafe)]), print()])),
+                   "unsafe");
+        // steering the walk
+        assert_eq!(reason(and(vec![PruneMatcher::new_box(), print()])), "prune");
+        // no action at the end, or more than one
+        assert_eq!(reason(name("a")), "name(a)");
+        assert_eq!(reason(and(vec![print(), print()])), "print");
+    }
+
+    #[test]
+    fn actions_run_in_order() {
+        let matcher = and(vec![NotMatcher::new_box(name("subdir")), print()]);
+        let plan = ParallelPlan::new(matcher.as_ref()).ok().unwrap();
+        let deps = FakeDependencies::new();
+        let mut matcher_io = deps.new_matcher_io();
+        let mut parallel = ParallelTests::new(3, plan.tests.clone(), &matcher_io);
+        let mut matched = 0;
+        for _ in 0..1000 {
+            for name in &["simple", "abbbc", "subdir", "ABBBC"] {
+                parallel.push(get_dir_entry_for("test_data/simple", name));
+                matched += parallel.run_action(plan.action, false, &mut matcher_io);
+            }
+        }
+        matched += parallel.run_action(plan.action, true, &mut matcher_io);
+        assert_eq!(matched, 3000);
+        let expected = ["test_data/simple",
+                        "test_data/simple/abbbc",
+                        "test_data/simple/subdir/ABBBC"]
+            .iter()
+            .map(|path| format!("{}\n", fix_up_slashes(path)))
+            .collect::<String>();
+        assert_eq!(deps.get_output_as_string(), expected.repeat(1000));
+        assert_eq!(matcher_io.exit_code(), 0);
+    }
+}
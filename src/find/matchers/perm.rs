@@ -9,12 +9,12 @@
 //! even try.
 
 use std::error::Error;
-use std::io::{stderr, Write};
+use std::fmt;
 #[cfg(unix)]
 use std::str::FromStr;
-use walkdir::DirEntry;
 
-use find::matchers::{Matcher, MatcherIO};
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
 
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -80,17 +80,21 @@ mod parsing {
         comparison_type: ComparisonType,
         string_pattern: &'a str,
         category_bit_pattern: u32,
+        /// Whether an operator with no categories before it applies to all of
+        /// them, as POSIX says it does (so that "+w" is the same as "a+w").
+        posix: bool,
     }
 
 
     impl<'a> Parser<'a> {
-        fn new(string_pattern: &'a str) -> Parser<'a> {
+        fn new(string_pattern: &'a str, posix: bool) -> Parser<'a> {
             Parser {
                 state: ParserState::Beginning,
                 bit_pattern: 0,
                 comparison_type: ComparisonType::Exact,
                 string_pattern: string_pattern,
                 category_bit_pattern: 0,
+                posix: posix,
             }
         }
 
@@ -168,11 +172,17 @@ mod parsing {
                     };
                 }
                 '=' | '+' => {
-                    if let ParserState::GatheringCategories = self.state {
-                        self.state = ParserState::GatheringPermissions;
-                    } else {
-                        return self.error();
+                    match self.state {
+                        ParserState::GatheringCategories => {}
+                        ParserState::Beginning if self.posix => {}
+                        _ => {
+                            return self.error();
+                        }
+                    };
+                    if self.posix && self.category_bit_pattern == 0 {
+                        self.category_bit_pattern = 0o111;
                     }
+                    self.state = ParserState::GatheringPermissions;
                 }
                 'r' => {
                     if let ParserState::GatheringPermissions = self.state {
@@ -233,6 +243,16 @@ mod parsing {
     }
 
     pub fn parse(string_value: &str) -> Result<(u32, ComparisonType), Box<Error>> {
+        parse_with(string_value, false)
+    }
+
+    /// The same as `parse`, but a leading '+' is the start of a symbolic
+    /// mode (as POSIX says) rather than an error.
+    pub fn parse_posix(string_value: &str) -> Result<(u32, ComparisonType), Box<Error>> {
+        parse_with(string_value, true)
+    }
+
+    fn parse_with(string_value: &str, posix: bool) -> Result<(u32, ComparisonType), Box<Error>> {
         // safe to unwrap as the regex is a compile-time constant.
         let re = Regex::new("^([/-]?)([0-7]+)$").unwrap();
 
@@ -252,7 +272,7 @@ mod parsing {
 
         }
         // no: so we've got a /u=rw,g=r form instead (or an invalid string).
-        let mut p = Parser::new(string_value);
+        let mut p = Parser::new(string_value, posix);
         for c in string_value.chars() {
             p.handle_char(&c)?;
         }
@@ -261,12 +281,14 @@ mod parsing {
 }
 
 #[cfg(unix)]
+#[derive(Clone)]
 pub struct PermMatcher {
     pattern: u32,
     comparison_type: ComparisonType,
 }
 
 #[cfg(not(unix))]
+#[derive(Clone)]
 pub struct PermMatcher {}
 
 impl PermMatcher {
@@ -279,50 +301,85 @@ impl PermMatcher {
         })
     }
 
+    /// Creates a matcher for a mode as POSIX reads them, where "+mode" is a
+    /// symbolic mode with no categories (e.g. "+w" is the same as "a+w").
+    #[cfg(unix)]
+    pub fn new_posix(pattern: &str) -> Result<PermMatcher, Box<Error>> {
+        let (bit_pattern, comparison_type) = parsing::parse_posix(pattern)?;
+        Ok(PermMatcher {
+            pattern: bit_pattern,
+            comparison_type: comparison_type,
+        })
+    }
+
     #[cfg(not(unix))]
     pub fn new(_dummy_pattern: &str) -> Result<PermMatcher, Box<Error>> {
         Err(From::from("Permission matching is not available on this platform"))
     }
 
+    #[cfg(not(unix))]
+    pub fn new_posix(dummy_pattern: &str) -> Result<PermMatcher, Box<Error>> {
+        PermMatcher::new(dummy_pattern)
+    }
+
     pub fn new_box(pattern: &str) -> Result<Box<Matcher>, Box<Error>> {
         Ok(Box::new(PermMatcher::new(pattern)?))
     }
+
+    pub fn new_box_posix(pattern: &str) -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(PermMatcher::new_posix(pattern)?))
+    }
 }
 
 impl Matcher for PermMatcher {
     #[cfg(unix)]
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        use std::os::unix::fs::PermissionsExt;
-        match file_info.metadata() {
-            Ok(metadata) => {
-                self.comparison_type.mode_bits_match(self.pattern, metadata.permissions().mode())
-            }
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match matcher_io.metadata(file_info) {
+            Ok(metadata) => self.comparison_type.mode_bits_match(self.pattern, metadata.mode),
             Err(e) => {
-                writeln!(&mut stderr(),
-                         "Error getting permissions for {}: {}",
-                         file_info.path().to_string_lossy(),
-                         e)
-                    .unwrap();
+                report_io_error(file_info.path(), "", &e, matcher_io);
                 false
             }
         }
     }
 
     #[cfg(not(unix))]
-    fn matches(&self, _dummy_file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        writeln!(&mut stderr(),
+    fn matches(&self, _dummy_file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        writeln!(matcher_io.deps.get_error_output().borrow_mut(),
                  "Permission matching not available on this platform!")
             .unwrap();
         return false;
     }
+
+    #[cfg(unix)]
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = match self.comparison_type {
+            ComparisonType::Exact => "",
+            ComparisonType::AtLeast => "-",
+            ComparisonType::AnyOf => "/",
+        };
+        write!(f, "perm({}{:04o})", prefix, self.pattern)
+    }
+
+    #[cfg(not(unix))]
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "perm")
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
+impl ParallelMatcher for PermMatcher {}
+
 
 #[cfg(test)]
 #[cfg(unix)]
 mod tests {
     use find::matchers::Matcher;
     use find::matchers::tests::get_dir_entry_for;
+    use find::metadata::FileMetadata;
     use find::tests::FakeDependencies;
     use super::*;
     use super::parsing;
@@ -439,6 +496,26 @@ mod tests {
                 "overflowing octal value should fail");
     }
 
+    #[test]
+    fn parsing_posix() {
+        // a leading '+' is an operator that applies to all categories...
+        assert_eq!(parsing::parse_posix("+w").unwrap(),
+                   (0o222, ComparisonType::Exact));
+        assert_eq!(parsing::parse_posix("-+x").unwrap(),
+                   (0o111, ComparisonType::AtLeast));
+        assert_eq!(parsing::parse_posix("u=rw,+r").unwrap(),
+                   (0o644, ComparisonType::Exact));
+        assert!(parsing::parse("+w").is_err());
+        // ...not the start of an octal mode
+        assert!(parsing::parse_posix("+644").is_err());
+        assert!(parsing::parse_posix("+u=rwx,g=rx,o+r").is_err());
+        // and everything else is read as usual
+        assert_eq!(parsing::parse_posix("-u=rwx").unwrap(),
+                   (0o700, ComparisonType::AtLeast));
+        assert_eq!(parsing::parse_posix("644").unwrap(),
+                   (0o644, ComparisonType::Exact));
+    }
+
     #[test]
     fn comparison_type_matching() {
         let c = ComparisonType::Exact;
@@ -499,4 +576,30 @@ mod tests {
         assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()),
                 "user-executable pattern should not match file");
     }
+
+    #[test]
+    fn perm_matches_fake_mode() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              mode: 0o104751,
+                              ..FileMetadata::default()
+                          });
+
+        for pattern in &["4751", "u=rwxs,g=rx,o=x", "-u=s", "/o=w,o=x"] {
+            assert!(PermMatcher::new(pattern)
+                        .unwrap()
+                        .matches(&file_info, &mut deps.new_matcher_io()),
+                    "{} should match mode 4751",
+                    pattern);
+        }
+        for pattern in &["751", "-g=w", "/o=w"] {
+            assert!(!PermMatcher::new(pattern)
+                         .unwrap()
+                         .matches(&file_info, &mut deps.new_matcher_io()),
+                    "{} shouldn't match mode 4751",
+                    pattern);
+        }
+    }
 }
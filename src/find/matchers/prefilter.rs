@@ -0,0 +1,118 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Cheap tests that find's walker can apply to an entry before evaluating the
+//! whole expression for it. Many expressions start with "-name" or "-type"
+//! (e.g. "-type f -name '*.rs' -exec ..."), and most entries fail those, so
+//! there's no need to go through the rest of the matcher tree for them.
+
+use find::matchers::WalkEntry;
+
+/// A set of tests of an entry's name and type (which the walker already
+/// knows, so they don't need any system calls). An entry that fails any of
+/// them doesn't match, and evaluating the expression for it wouldn't do
+/// anything either, so it can be skipped. See `Matcher::prefilter`.
+pub struct Prefilter {
+    tests: Vec<Box<Fn(&WalkEntry) -> bool>>,
+    /// Whether the tests are all there is to the matcher: it matches exactly
+    /// the entries that pass them, and has no effects of its own.
+    complete: bool,
+}
+
+impl Prefilter {
+    /// Makes a prefilter that's all there is to a matcher.
+    pub fn new<F>(test: F) -> Prefilter
+        where F: Fn(&WalkEntry) -> bool + 'static
+    {
+        Prefilter {
+            tests: vec![Box::new(test)],
+            complete: true,
+        }
+    }
+
+    /// Combines the prefilters of a sequence of matchers that all have to
+    /// match (as with -a). Each matcher's prefilter can only be used if
+    /// those before it are complete: otherwise, an entry that failed it
+    /// could still have had some effect.
+    pub fn all<I>(prefilters: I) -> Option<Prefilter>
+        where I: IntoIterator<Item = Option<Prefilter>>
+    {
+        let mut combined = Prefilter {
+            tests: vec![],
+            complete: true,
+        };
+        for prefilter in prefilters {
+            match prefilter {
+                Some(prefilter) => {
+                    combined.tests.extend(prefilter.tests);
+                    if !prefilter.complete {
+                        combined.complete = false;
+                        break;
+                    }
+                }
+                None => {
+                    combined.complete = false;
+                    break;
+                }
+            }
+        }
+        if combined.tests.is_empty() {
+            None
+        } else {
+            Some(combined)
+        }
+    }
+
+    /// Whether the entry passes all the tests, and so has to go through the
+    /// expression properly.
+    pub fn passes(&self, dir_entry: &WalkEntry) -> bool {
+        self.tests.iter().all(|test| test(dir_entry))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use find::matchers::tests::get_dir_entry_for;
+    use super::*;
+
+    fn name_is(name: &'static str) -> Option<Prefilter> {
+        Some(Prefilter::new(move |dir_entry| dir_entry.file_name().to_string_lossy() == name))
+    }
+
+    #[test]
+    fn passes_all_tests() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let prefilter = Prefilter::all(vec![name_is("abbbc"),
+                                            Some(Prefilter::new(|dir_entry: &WalkEntry| {
+                                                dir_entry.file_type().is_file()
+                                            }))])
+            .unwrap();
+        assert!(prefilter.passes(&abbbc));
+        assert!(prefilter.is_complete());
+
+        let prefilter = Prefilter::all(vec![name_is("abbbc"), name_is("other")]).unwrap();
+        assert!(!prefilter.passes(&abbbc));
+    }
+
+    #[test]
+    fn all_stops_at_incomplete_prefilter() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        assert!(Prefilter::all(vec![None, name_is("other")]).is_none());
+
+        let prefilter = Prefilter::all(vec![name_is("abbbc"), None, name_is("other")]).unwrap();
+        assert!(prefilter.passes(&abbbc));
+        assert!(!prefilter.is_complete());
+
+        let incomplete = Prefilter::all(vec![name_is("abbbc"), None]);
+        let prefilter = Prefilter::all(vec![incomplete, name_is("other")]).unwrap();
+        assert!(prefilter.passes(&abbbc));
+        assert!(!prefilter.is_complete());
+    }
+}
@@ -4,35 +4,223 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
-use walkdir::DirEntry;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{self, Path};
+use std::rc::Rc;
 
-use find::matchers::{Matcher, MatcherIO};
+use find::matchers::{Matcher, MatcherIO, WalkEntry};
+use find::matchers::output_file::{Destination, OutputFile};
+use find::quoting::{path_bytes, tty_literal};
 
-/// This matcher just prints the name of the file to stdout.
-pub struct Printer;
+/// The byte written after each path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrintDelimiter {
+    /// -print: paths are separated by newlines.
+    Newline,
+    /// -print0: paths are separated by NUL bytes, which (unlike newlines)
+    /// can't appear in file names.
+    Null,
+}
+
+impl PrintDelimiter {
+    fn as_byte(&self) -> u8 {
+        match *self {
+            PrintDelimiter::Newline => b'\n',
+            PrintDelimiter::Null => b'\0',
+        }
+    }
+}
+
+/// When -print colours what it prints (see --color).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorMode {
+    /// Only when the output is a terminal (the default for --color).
+    Auto,
+    /// Whatever the output is.
+    Always,
+    /// Never (the default without --color).
+    Never,
+}
+
+impl ColorMode {
+    /// Looks up the mode with the given name (the argument to --color).
+    pub fn from_name(name: &str) -> Result<ColorMode, Box<Error>> {
+        match name {
+            "auto" | "tty" | "if-tty" => Ok(ColorMode::Auto),
+            "always" | "yes" | "force" => Ok(ColorMode::Always),
+            "never" | "no" | "none" => Ok(ColorMode::Never),
+            _ => {
+                Err(From::from(format!("invalid argument ‘{}’ for ‘--color’ (valid arguments \
+                                        are ‘auto’, ‘always’ and ‘never’)",
+                                       name)))
+            }
+        }
+    }
+
+    /// Whether to colour output that does or doesn't go to a terminal.
+    pub fn is_active(&self, output_is_terminal: bool) -> bool {
+        match *self {
+            ColorMode::Auto => output_is_terminal,
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+/// What the part of a name that -name or -iname matched is written in: bold
+/// red, as grep does.
+const HIGHLIGHT: &'static [u8] = b"\x1b[1;31m";
+/// What directory separators are written in: dim, so that the names stand
+/// out.
+const DIM: &'static [u8] = b"\x1b[2m";
+const RESET: &'static [u8] = b"\x1b[0m";
+
+fn is_separator(byte: u8) -> bool {
+    byte < 0x80 && path::is_separator(byte as char)
+}
+
+/// Writes a path in colour: the given range of it (if any) highlighted, and
+/// its separators dimmed. Each of the other parts goes through `quote`
+/// first (which, as no part ends in the middle of a character, can make
+/// them safe for a terminal).
+fn write_highlighted(out: &mut Write,
+                     path: &[u8],
+                     highlighted: Option<Range<usize>>,
+                     quote: &Fn(&[u8]) -> Vec<u8>)
+                     -> io::Result<()> {
+    let mut i = 0;
+    while i < path.len() {
+        if let Some(ref range) = highlighted {
+            if range.start == i && range.end > i {
+                out.write_all(HIGHLIGHT)?;
+                out.write_all(&quote(&path[range.clone()]))?;
+                out.write_all(RESET)?;
+                i = range.end;
+                continue;
+            }
+        }
+        let separators = is_separator(path[i]);
+        let end = (i..path.len())
+            .find(|&j| {
+                is_separator(path[j]) != separators ||
+                highlighted.as_ref().map_or(false, |range| range.start == j)
+            })
+            .unwrap_or(path.len());
+        if separators {
+            out.write_all(DIM)?;
+            out.write_all(&path[i..end])?;
+            out.write_all(RESET)?;
+        } else {
+            out.write_all(&quote(&path[i..end]))?;
+        }
+        i = end;
+    }
+    Ok(())
+}
+
+/// Writes the raw bytes of a path (no lossy UTF-8 conversion, no
+/// formatting machinery) to the given output.
+#[cfg(unix)]
+pub fn write_path(output: &mut Write, path: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    output.write_all(path.as_os_str().as_bytes())
+}
+
+/// Writes a path to the given output. Paths aren't just bytes on this
+/// platform, so there's no choice but to go through a (lossy) conversion.
+#[cfg(not(unix))]
+pub fn write_path(output: &mut Write, path: &Path) -> io::Result<()> {
+    output.write_all(path.to_string_lossy().as_bytes())
+}
+
+/// This matcher just prints the name of the file to stdout (or, for
+/// -fprint and -fprint0, to a file).
+pub struct Printer {
+    delimiter: PrintDelimiter,
+    destination: Destination,
+}
 
 impl Printer {
-    pub fn new() -> Printer {
-        Printer {}
+    pub fn new(delimiter: PrintDelimiter) -> Printer {
+        Printer {
+            delimiter: delimiter,
+            destination: Destination::Output,
+        }
     }
 
-    pub fn new_box() -> Box<Matcher> {
-        Box::new(Printer::new())
+    pub fn new_box(delimiter: PrintDelimiter) -> Box<Matcher> {
+        Box::new(Printer::new(delimiter))
+    }
+
+    pub fn new_box_for_file(delimiter: PrintDelimiter, file: Rc<OutputFile>) -> Box<Matcher> {
+        Box::new(Printer {
+            delimiter: delimiter,
+            destination: Destination::File(file),
+        })
     }
 }
 
 impl Matcher for Printer {
-    fn matches(&self, file_info: &DirEntry, matcher_io: &mut MatcherIO) -> bool {
-        writeln!(matcher_io.deps.get_output().borrow_mut(),
-                 "{}",
-                 file_info.path().to_string_lossy())
-            .unwrap();
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        // as with GNU find, a name can't mess up the terminal it's printed
+        // on (but -print0's output is meant for other programs)
+        let to_terminal = match self.destination {
+            Destination::Output => {
+                self.delimiter == PrintDelimiter::Newline && matcher_io.deps.output_is_terminal()
+            }
+            Destination::File(_) => false,
+        };
+        // and with --color, only what -print writes to the output is coloured
+        let color = match self.destination {
+            Destination::Output => {
+                self.delimiter == PrintDelimiter::Newline && matcher_io.highlights_matches()
+            }
+            Destination::File(_) => false,
+        };
+        let highlighted = matcher_io.highlighted();
+        self.destination.write(matcher_io, false, |out| {
+            if color {
+                let quote = |bytes: &[u8]| if to_terminal {
+                    tty_literal(bytes)
+                } else {
+                    bytes.to_vec()
+                };
+                write_highlighted(out, &path_bytes(file_info.path()), highlighted, &quote)?;
+            } else if to_terminal {
+                out.write_all(&tty_literal(&path_bytes(file_info.path())))?;
+            } else {
+                write_path(out, file_info.path())?;
+            }
+            out.write_all(&[self.delimiter.as_byte()])
+        });
         true
     }
 
     fn has_side_effects(&self) -> bool {
         true
     }
+
+    fn only_writes_output(&self) -> bool {
+        self.destination.is_output()
+    }
+
+    fn finished(&self, matcher_io: &mut MatcherIO) {
+        self.destination.finished(matcher_io);
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.delimiter {
+            PrintDelimiter::Newline => "print",
+            PrintDelimiter::Null => "print0",
+        };
+        match self.destination {
+            Destination::Output => write!(f, "{}", name),
+            Destination::File(ref file) => write!(f, "f{}({})", name, file.name()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -48,10 +236,109 @@ mod tests {
     fn prints() {
         let abbbc = get_dir_entry_for("./test_data/simple", "abbbc");
 
-        let matcher = Printer::new();
+        let matcher = Printer::new(PrintDelimiter::Newline);
         let deps = FakeDependencies::new();
         assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
         assert_eq!(fix_up_slashes("./test_data/simple/abbbc\n"),
                    deps.get_output_as_string());
     }
+
+    #[test]
+    fn prints_null_delimited() {
+        let abbbc = get_dir_entry_for("./test_data/simple", "abbbc");
+
+        let matcher = Printer::new(PrintDelimiter::Null);
+        let deps = FakeDependencies::new();
+        assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+        assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+        assert_eq!(fix_up_slashes("./test_data/simple/abbbc\0./test_data/simple/abbbc\0"),
+                   deps.get_output_as_string());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prints_safely_to_a_terminal() {
+        use std::fs::File;
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("prints_safely_to_a_terminal").unwrap();
+        File::create(temp_dir.path().join("new\nline")).unwrap();
+        let entry = get_dir_entry_for(&temp_dir.path().to_string_lossy(), "new\nline");
+        let prefix = temp_dir.path().to_string_lossy().to_string();
+
+        let mut deps = FakeDependencies::new();
+        for delimiter in &[PrintDelimiter::Newline, PrintDelimiter::Null] {
+            assert!(Printer::new(*delimiter).matches(&entry, &mut deps.new_matcher_io()));
+        }
+        assert_eq!(deps.get_output_as_string(),
+                   format!("{0}/new\nline\n{0}/new\nline\0", prefix));
+
+        deps = FakeDependencies::new();
+        deps.set_output_is_terminal();
+        for delimiter in &[PrintDelimiter::Newline, PrintDelimiter::Null] {
+            assert!(Printer::new(*delimiter).matches(&entry, &mut deps.new_matcher_io()));
+        }
+        assert_eq!(deps.get_output_as_string(),
+                   format!("{0}/new?line\n{0}/new\nline\0", prefix));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn prints_non_utf8_bytes_verbatim() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut output = Vec::new();
+        write_path(&mut output, Path::new(OsStr::from_bytes(b"a\xffb"))).unwrap();
+        assert_eq!(output, b"a\xffb");
+    }
+
+    #[test]
+    fn color_modes() {
+        assert_eq!(ColorMode::from_name("auto").unwrap(), ColorMode::Auto);
+        assert_eq!(ColorMode::from_name("always").unwrap(), ColorMode::Always);
+        assert_eq!(ColorMode::from_name("never").unwrap(), ColorMode::Never);
+        assert_eq!(ColorMode::from_name("sometimes").unwrap_err().to_string(),
+                   "invalid argument ‘sometimes’ for ‘--color’ (valid arguments are ‘auto’, \
+                    ‘always’ and ‘never’)");
+        assert!(ColorMode::Auto.is_active(true));
+        assert!(!ColorMode::Auto.is_active(false));
+        assert!(ColorMode::Always.is_active(false));
+        assert!(!ColorMode::Never.is_active(true));
+    }
+
+    #[test]
+    fn writes_highlighted() {
+        let write = |path: &[u8], highlighted| {
+            let mut output = vec![];
+            write_highlighted(&mut output, path, highlighted, &|bytes| tty_literal(bytes))
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(write(b"./dir//abbbc", Some(7..12)),
+                   ".\x1b[2m/\x1b[0mdir\x1b[2m//\x1b[0m\x1b[1;31mabbbc\x1b[0m");
+        assert_eq!(write(b"dir/new\nline", Some(4..12)),
+                   "dir\x1b[2m/\x1b[0m\x1b[1;31mnew?line\x1b[0m");
+        assert_eq!(write(b"dir/abbbc", None), "dir\x1b[2m/\x1b[0mabbbc");
+        assert_eq!(write(b"/", Some(0..1)), "\x1b[1;31m/\x1b[0m");
+    }
+
+    #[test]
+    fn prints_highlighted_to_a_terminal() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let path_len = abbbc.path().to_string_lossy().len();
+        let mut deps = FakeDependencies::new();
+        deps.set_output_is_terminal();
+        let mut matcher_io = deps.new_matcher_io();
+        matcher_io.set_highlight_matches(true);
+        matcher_io.highlight(path_len - 5..path_len);
+        for delimiter in &[PrintDelimiter::Newline, PrintDelimiter::Null] {
+            assert!(Printer::new(*delimiter).matches(&abbbc, &mut matcher_io));
+        }
+        let sep = format!("\x1b[2m{}\x1b[0m", fix_up_slashes("/"));
+        assert_eq!(deps.get_output_as_string(),
+                   format!("test_data{0}simple{0}\x1b[1;31mabbbc\x1b[0m\n{1}\0",
+                           sep,
+                           fix_up_slashes("test_data/simple/abbbc")));
+    }
 }
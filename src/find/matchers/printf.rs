@@ -0,0 +1,1141 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! -printf, which writes whatever it's asked to about each file, following a
+//! format with backslash escapes and %-directives (as in printf(3), but with
+//! find's own set of directives).
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::context;
+use find::matchers::ls::{format_mode, type_char};
+use find::matchers::output_file::Destination;
+use find::metadata::{FileKind, FileMetadata};
+use find::quoting::{path_bytes, shell_quote};
+use find::time_zone::{TimeZone, MONTHS, WEEKDAYS};
+
+/// The flags, width and precision of a directive (e.g. the "-10" in
+/// "%-10p").
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Padding {
+    /// The '-' flag: pad on the right rather than the left.
+    left_justify: bool,
+    /// The '0' flag: pad with zeroes rather than spaces. As with GNU find,
+    /// this is only for the directives that are numbers as far as printf(3)
+    /// is concerned (%d, %m and %S): it prints the others as strings.
+    zero_pad: bool,
+    /// The '#' flag: use an alternate form (e.g. a leading 0 for %m).
+    alternate: bool,
+    width: usize,
+    /// The most bytes of a string to write.
+    precision: Option<usize>,
+}
+
+/// Writes the padding as it would appear in a directive (e.g. "-10").
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.left_justify {
+            write!(f, "-")?;
+        }
+        if self.zero_pad {
+            write!(f, "0")?;
+        }
+        if self.alternate {
+            write!(f, "#")?;
+        }
+        if self.width > 0 {
+            write!(f, "{}", self.width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum FormatComponent {
+    Literal(Vec<u8>),
+    Directive(char, Padding),
+    /// One of a file's times, or part of it (e.g. %TY, its year of
+    /// modification).
+    TimeDirective(char, char, Padding),
+    /// \c: nothing more is written for the file.
+    Stop,
+}
+
+/// Writes the component as it could appear in a format: literal text is
+/// escaped where it has to be (or where it's unprintable).
+impl fmt::Display for FormatComponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatComponent::Literal(ref bytes) => {
+                for &byte in bytes {
+                    match byte {
+                        b'\\' => write!(f, "\\\\")?,
+                        b'%' => write!(f, "%%")?,
+                        b'\n' => write!(f, "\\n")?,
+                        b'\t' => write!(f, "\\t")?,
+                        b' '..=b'~' => write!(f, "{}", byte as char)?,
+                        _ => write!(f, "\\{:03o}", byte)?,
+                    }
+                }
+                Ok(())
+            }
+            FormatComponent::Directive(directive, ref padding) => {
+                write!(f, "%{}{}", padding, directive)
+            }
+            FormatComponent::TimeDirective(time, field, ref padding) => {
+                write!(f, "%{}{}{}", padding, time, field)
+            }
+            FormatComponent::Stop => write!(f, "\\c"),
+        }
+    }
+}
+
+/// The directives that are understood (anything else is written as it is).
+const DIRECTIVES: &'static str = "pqfhPHdsSmMDFbknlugUGyYZact";
+
+/// The directives that need the file's metadata.
+const METADATA_DIRECTIVES: &'static str = "sSmMDFbknugUGact";
+
+/// The directives that are followed by which part of a time to write: %A for
+/// the access time, %C for the status change time (as with -ctime), and %T
+/// for the modification time.
+const TIME_DIRECTIVES: &'static str = "ACT";
+
+/// The parts of a time that %A, %C and %T can write (as in strftime(3),
+/// except that seconds have a fractional part, and "@" gives the seconds
+/// since the epoch with one too, where "s" doesn't).
+const TIME_FIELDS: &'static str = "@aAbBdDeFhHIjklmMpsSTuwyYZ+";
+
+/// Reads the (octal) digits of an escape like "\101", returning the byte and
+/// how many digits there were. At most three digits are read.
+fn parse_octal_escape(digits: &[char]) -> (u8, usize) {
+    let mut value: u32 = 0;
+    let mut count = 0;
+    while count < 3 && count < digits.len() && digits[count] >= '0' && digits[count] <= '7' {
+        value = value * 8 + digits[count].to_digit(8).unwrap();
+        count += 1;
+    }
+    (value as u8, count)
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// Parses a format into its components, noting any directives that aren't
+/// understood (each only once) in `unknown_directives`.
+fn parse_components(format: &str,
+                    unknown_directives: &mut Vec<char>)
+                    -> Result<Vec<FormatComponent>, Box<Error>> {
+    let chars: Vec<char> = format.chars().collect();
+    let mut components = vec![];
+    let mut literal = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                match chars[i] {
+                    'a' => literal.push(b'\x07'),
+                    'b' => literal.push(b'\x08'),
+                    'f' => literal.push(b'\x0c'),
+                    'n' => literal.push(b'\n'),
+                    'r' => literal.push(b'\r'),
+                    't' => literal.push(b'\t'),
+                    'v' => literal.push(b'\x0b'),
+                    '\\' => literal.push(b'\\'),
+                    'c' => {
+                        components.push(FormatComponent::Literal(literal));
+                        literal = vec![];
+                        components.push(FormatComponent::Stop);
+                    }
+                    '0'..='7' => {
+                        let (byte, digits) = parse_octal_escape(&chars[i..]);
+                        literal.push(byte);
+                        i += digits - 1;
+                    }
+                    other => {
+                        literal.push(b'\\');
+                        push_char(&mut literal, other);
+                    }
+                }
+                i += 1;
+            }
+            '%' => {
+                let start = i;
+                i += 1;
+                let mut padding = Padding::default();
+                while i < chars.len() && "-+ #0".contains(chars[i]) {
+                    match chars[i] {
+                        '-' => padding.left_justify = true,
+                        '0' => padding.zero_pad = true,
+                        '#' => padding.alternate = true,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_digit(10) {
+                    padding.width = padding.width * 10 + chars[i].to_digit(10).unwrap() as usize;
+                    i += 1;
+                }
+                if i < chars.len() && chars[i] == '.' {
+                    let mut precision = 0;
+                    i += 1;
+                    while i < chars.len() && chars[i].is_digit(10) {
+                        precision = precision * 10 + chars[i].to_digit(10).unwrap() as usize;
+                        i += 1;
+                    }
+                    padding.precision = Some(precision);
+                }
+                match chars.get(i) {
+                    // GNU find rejects these too, rather than guess what was
+                    // meant
+                    None if i == start + 1 => {
+                        return Err(From::from("% at end of format string"));
+                    }
+                    None => {
+                        let directive: String = chars[start..].iter().collect();
+                        return Err(From::from(format!("incomplete format directive ‘{}’ at \
+                                                       end of format string",
+                                                      directive)));
+                    }
+                    Some(&'%') => literal.push(b'%'),
+                    Some(&reserved) if "{[(".contains(reserved) => {
+                        return Err(From::from(format!("the format directive ‘%{}’ is reserved \
+                                                       for future use",
+                                                      reserved)));
+                    }
+                    Some(&time) if TIME_DIRECTIVES.contains(time) => {
+                        match chars.get(i + 1) {
+                            None => {
+                                let directive: String = chars[start..].iter().collect();
+                                return Err(From::from(format!("incomplete format directive \
+                                                               ‘{}’ at end of format string",
+                                                              directive)));
+                            }
+                            Some(&field) if TIME_FIELDS.contains(field) => {
+                                components.push(FormatComponent::Literal(literal));
+                                literal = vec![];
+                                components.push(FormatComponent::TimeDirective(time,
+                                                                               field,
+                                                                               padding));
+                            }
+                            Some(_) => {
+                                for &c in &chars[start..i + 2] {
+                                    push_char(&mut literal, c);
+                                }
+                            }
+                        }
+                        i += 1;
+                    }
+                    Some(&directive) if DIRECTIVES.contains(directive) => {
+                        components.push(FormatComponent::Literal(literal));
+                        literal = vec![];
+                        components.push(FormatComponent::Directive(directive, padding));
+                    }
+                    // as with GNU find, anything else is written as it is
+                    Some(&directive) => {
+                        if !unknown_directives.contains(&directive) {
+                            unknown_directives.push(directive);
+                        }
+                        for &c in &chars[start..i + 1] {
+                            push_char(&mut literal, c);
+                        }
+                    }
+                }
+                i += 1;
+            }
+            other => {
+                push_char(&mut literal, other);
+                i += 1;
+            }
+        }
+    }
+    components.push(FormatComponent::Literal(literal));
+    components.retain(|component| *component != FormatComponent::Literal(vec![]));
+    Ok(components)
+}
+
+/// A parsed -printf format, which can be shared by any number of `Printf`s.
+pub struct Format {
+    components: Vec<FormatComponent>,
+    needs_metadata: bool,
+    /// The directives that aren't understood, so are written as they are.
+    unknown_directives: Vec<char>,
+}
+
+impl Format {
+    /// Parses the format, failing if it's malformed (e.g. it ends partway
+    /// through a directive) or asks for something this build of find can't
+    /// provide. Either way, that's found out before any files are searched.
+    pub fn new(format: &str) -> Result<Format, Box<Error>> {
+        let mut unknown_directives = vec![];
+        let components = parse_components(format, &mut unknown_directives)?;
+        let needs_metadata = components.iter().any(|component| match *component {
+            FormatComponent::Directive(directive, _) => METADATA_DIRECTIVES.contains(directive),
+            FormatComponent::TimeDirective(..) => true,
+            _ => false,
+        });
+        let needs_context = components.iter().any(|component| match *component {
+            FormatComponent::Directive('Z', _) => true,
+            _ => false,
+        });
+        if needs_context {
+            context::check_supported()?;
+        }
+        Ok(Format {
+            components: components,
+            needs_metadata: needs_metadata,
+            unknown_directives: unknown_directives,
+        })
+    }
+
+    /// The warnings to give about the format (once, however many files it's
+    /// used for): one for each directive that isn't understood, as with GNU
+    /// find.
+    pub fn warnings(&self) -> Vec<String> {
+        self.unknown_directives
+            .iter()
+            .map(|directive| format!("unrecognized format directive ‘%{}’", directive))
+            .collect()
+    }
+}
+
+/// The formats that have been parsed while building an expression, so that
+/// one that's used more than once (as generated commands often do) is only
+/// parsed once.
+#[derive(Default)]
+pub struct FormatCache {
+    formats: HashMap<String, Rc<Format>>,
+}
+
+impl FormatCache {
+    pub fn new() -> FormatCache {
+        FormatCache::default()
+    }
+
+    /// Returns the parsed format, parsing it if it hasn't been already.
+    pub fn get(&mut self, format: &str) -> Result<Rc<Format>, Box<Error>> {
+        if let Some(parsed) = self.formats.get(format) {
+            return Ok(parsed.clone());
+        }
+        let parsed = Rc::new(Format::new(format)?);
+        self.formats.insert(format.to_string(), parsed.clone());
+        Ok(parsed)
+    }
+}
+
+/// The file's time that %A, %C or %T (or %a, %c or %t) is about, if the
+/// platform records it.
+fn file_time(time: char, metadata: &FileMetadata) -> Option<SystemTime> {
+    match time {
+        'A' => metadata.accessed,
+        'C' => metadata.changed,
+        _ => metadata.modified,
+    }
+}
+
+/// Splits a time into whole seconds since the epoch (rounded down, so
+/// negative for times before it) and nanoseconds after that.
+pub fn epoch_seconds(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => (duration.as_secs() as i64, duration.subsec_nanos()),
+        Err(e) => {
+            let before = e.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanoseconds => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanoseconds),
+            }
+        }
+    }
+}
+
+/// Writes part of a time, as %T and friends do. Seconds have ten digits
+/// after the point, as with GNU find (the last is always 0). The "c" field
+/// (which is only used for %a, %c and %t) gives everything, in the form
+/// ctime(3) uses.
+fn format_time_field(field: char, time: SystemTime, zone: &TimeZone) -> String {
+    let (seconds, nanoseconds) = epoch_seconds(time);
+    let t = zone.broken_down(seconds);
+    let hour_of_12 = if t.hour % 12 == 0 { 12 } else { t.hour % 12 };
+    match field {
+        '@' => format!("{}.{:09}0", seconds, nanoseconds),
+        'a' => WEEKDAYS[t.weekday][..3].to_string(),
+        'A' => WEEKDAYS[t.weekday].to_string(),
+        'b' | 'h' => MONTHS[t.month][..3].to_string(),
+        'B' => MONTHS[t.month].to_string(),
+        'c' => {
+            format!("{} {} {:>2} {:02}:{:02}:{:02}.{:09}0 {}",
+                    &WEEKDAYS[t.weekday][..3],
+                    &MONTHS[t.month][..3],
+                    t.day,
+                    t.hour,
+                    t.minute,
+                    t.second,
+                    nanoseconds,
+                    t.year)
+        }
+        'd' => format!("{:02}", t.day),
+        'D' => format!("{:02}/{:02}/{:02}", t.month + 1, t.day, t.year.rem_euclid(100)),
+        'e' => format!("{:>2}", t.day),
+        'F' => format!("{}-{:02}-{:02}", t.year, t.month + 1, t.day),
+        'H' => format!("{:02}", t.hour),
+        'I' => format!("{:02}", hour_of_12),
+        'j' => format!("{:03}", t.year_day + 1),
+        'k' => format!("{:>2}", t.hour),
+        'l' => format!("{:>2}", hour_of_12),
+        'm' => format!("{:02}", t.month + 1),
+        'M' => format!("{:02}", t.minute),
+        'p' => if t.hour < 12 { "AM" } else { "PM" }.to_string(),
+        's' => seconds.to_string(),
+        'S' => format!("{:02}.{:09}0", t.second, nanoseconds),
+        'T' => format!("{:02}:{:02}:{:02}.{:09}0", t.hour, t.minute, t.second, nanoseconds),
+        '+' => {
+            format!("{}-{:02}-{:02}+{:02}:{:02}:{:02}.{:09}0",
+                    t.year,
+                    t.month + 1,
+                    t.day,
+                    t.hour,
+                    t.minute,
+                    t.second,
+                    nanoseconds)
+        }
+        'u' => (if t.weekday == 0 { 7 } else { t.weekday }).to_string(),
+        'w' => t.weekday.to_string(),
+        'y' => format!("{:02}", t.year.rem_euclid(100)),
+        'Y' => t.year.to_string(),
+        'Z' => t.zone_name,
+        _ => unreachable!("unknown time field {}", field),
+    }
+}
+
+/// The last name in the path, for %f, as GNU find has it: with one slash if
+/// there are any after it (so "a/w//" gives "w/"), and "/" for the root.
+fn base_name(path: &[u8]) -> &[u8] {
+    let end = match path.iter().rposition(|&byte| byte != b'/') {
+        Some(last) => last + 1,
+        // nothing but slashes
+        None => return &path[..path.len().min(1)],
+    };
+    let start = path[..end].iter().rposition(|&byte| byte == b'/').map_or(0, |slash| slash + 1);
+    &path[start..path.len().min(end + 1)]
+}
+
+/// The directories the path is in, for %h, as GNU find has them: what comes
+/// before the last slash, once any slashes at the end are gone, or "." if
+/// there's no slash. (GNU find leaves the slashes at the end of a name one
+/// character long at the start of the path, so "w/" is in "w".)
+fn dir_name(path: &[u8]) -> &[u8] {
+    let end = match path.iter().rposition(|&byte| byte != b'/') {
+        Some(last) if last > 0 => last + 1,
+        _ => path.len(),
+    };
+    match path[..end].iter().rposition(|&byte| byte == b'/') {
+        Some(slash) => &path[..slash],
+        None => b".",
+    }
+}
+
+/// Writes the value, padded (or truncated) as the directive asks.
+fn write_padded(output: &mut Vec<u8>, value: &[u8], is_number: bool, padding: &Padding) {
+    let value = match padding.precision {
+        Some(precision) if !is_number && precision < value.len() => &value[..precision],
+        _ => value,
+    };
+    let fill = padding.width.saturating_sub(value.len());
+    if padding.left_justify {
+        output.extend_from_slice(value);
+        output.extend(::std::iter::repeat(b' ').take(fill));
+    } else {
+        let pad = if padding.zero_pad && is_number { b'0' } else { b' ' };
+        output.extend(::std::iter::repeat(pad).take(fill));
+        output.extend_from_slice(value);
+    }
+}
+
+/// Formats a number the way C's "%g" does (which is what GNU find uses for
+/// %S), with the given number of significant digits: "1", "0.5", "1.5e+06".
+fn format_g(value: f64, significant_digits: usize) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return value.to_string();
+    }
+    let digits = significant_digits.max(1);
+    // rounding to that many digits first, as it can change the exponent
+    let scientific = format!("{:.*e}", digits - 1, value);
+    let exponent: i32 = scientific[scientific.find('e').unwrap() + 1..].parse().unwrap();
+    let strip_zeros = |s: &str| if s.contains('.') {
+        s.trim_right_matches('0').trim_right_matches('.').to_string()
+    } else {
+        s.to_string()
+    };
+    if exponent < -4 || exponent >= digits as i32 {
+        let mantissa = &scientific[..scientific.find('e').unwrap()];
+        format!("{}e{}{:02}",
+                strip_zeros(mantissa),
+                if exponent < 0 { '-' } else { '+' },
+                exponent.abs())
+    } else {
+        strip_zeros(&format!("{:.*}", (digits as i32 - 1 - exponent) as usize, value))
+    }
+}
+
+/// The letter -printf's %y uses for a type of file: the same as -type's.
+pub fn type_letter(file_type: FileKind) -> char {
+    match type_char(file_type) {
+        '-' => 'f',
+        '?' => 'U',
+        c => c,
+    }
+}
+
+/// The letter -printf's %Y uses for the entry's type: the same as %y,
+/// except that symbolic links are followed, with "L" for one that's part of
+/// a loop, "N" for one that points to something that doesn't exist, and "?"
+/// if there's some other problem finding out.
+fn followed_type_letter(file_info: &WalkEntry) -> char {
+    if !file_info.file_type().is_symlink() {
+        return type_letter(file_info.file_type());
+    }
+    match file_info.stat(true) {
+        Ok(metadata) => type_letter(metadata.kind),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => 'N',
+        Err(ref e) if is_loop_error(e) => 'L',
+        Err(_) => '?',
+    }
+}
+
+#[cfg(unix)]
+fn is_loop_error(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(::libc::ELOOP)
+}
+
+#[cfg(not(unix))]
+fn is_loop_error(_: &io::Error) -> bool {
+    false
+}
+
+/// The -printf (and -fprintf) action. Always matches.
+pub struct Printf {
+    format: Rc<Format>,
+    destination: Destination,
+}
+
+impl Printf {
+    /// Creates a Printf with a format of its own, writing to find's output.
+    #[cfg(test)]
+    pub fn new(format: &str) -> Result<Printf, Box<Error>> {
+        Ok(Printf::with_format(Rc::new(Format::new(format)?), Destination::Output))
+    }
+
+    /// Creates a Printf using a format that's already been parsed (see
+    /// `FormatCache`).
+    pub fn with_format(format: Rc<Format>, destination: Destination) -> Printf {
+        Printf {
+            format: format,
+            destination: destination,
+        }
+    }
+
+    /// Writes the directive's value for the entry.
+    fn write_directive(&self,
+                       output: &mut Vec<u8>,
+                       directive: char,
+                       padding: &Padding,
+                       file_info: &WalkEntry,
+                       metadata: &FileMetadata,
+                       matcher_io: &mut MatcherIO) {
+        let path = file_info.path();
+        let string_value = match directive {
+            'p' => path_bytes(path),
+            // not in GNU find: for writing shell scripts
+            'q' => shell_quote(&path_bytes(path)),
+            'f' => base_name(&path_bytes(path)).to_vec(),
+            'h' => dir_name(&path_bytes(path)).to_vec(),
+            // the walk builds paths by joining names onto the starting point
+            // it was given, so this can't fail, however the starting points
+            // overlap as strings (e.g. "a" and "ab")
+            'P' => path_bytes(path.strip_prefix(file_info.starting_point()).unwrap_or(path)),
+            'H' => path_bytes(file_info.starting_point()),
+            'F' => matcher_io.fs_type(metadata.dev).as_bytes().to_vec(),
+            'l' => file_info.read_link().map(|target| path_bytes(&target)).unwrap_or_default(),
+            'y' => type_letter(file_info.file_type()).to_string().into_bytes(),
+            'a' | 'c' | 't' => {
+                let time = directive.to_ascii_uppercase();
+                file_time(time, metadata).map_or_else(Vec::new, |file_time| {
+                    format_time_field('c', file_time, matcher_io.time_zone()).into_bytes()
+                })
+            }
+            'Y' => followed_type_letter(file_info).to_string().into_bytes(),
+            'Z' => {
+                match context::security_context(file_info, matcher_io) {
+                    Ok(context) => context,
+                    Err(e) => {
+                        report_io_error(path, "", &e, matcher_io);
+                        vec![]
+                    }
+                }
+            }
+            'd' | 'm' => {
+                let number = match directive {
+                    'd' => file_info.depth().to_string(),
+                    _ if padding.alternate => format!("0{:o}", metadata.mode & 0o7777),
+                    _ => format!("{:o}", metadata.mode & 0o7777),
+                };
+                write_padded(output, number.as_bytes(), true, padding);
+                return;
+            }
+            'M' => format_mode(metadata.mode, file_info.file_type()).into_bytes(),
+            // names if there are any, and ids if not (so never "unknown")
+            'u' => {
+                matcher_io.user_group_cache().user_display_name(metadata.uid).as_bytes().to_vec()
+            }
+            'g' => {
+                matcher_io.user_group_cache().group_display_name(metadata.gid).as_bytes().to_vec()
+            }
+            'S' => {
+                // Sparseness: how much space is allocated, relative to the
+                // size. As with GNU find, an empty file counts as 1.
+                let sparseness = if metadata.len == 0 {
+                    1.0
+                } else {
+                    metadata.blocks as f64 * 512.0 / metadata.len as f64
+                };
+                let value = format_g(sparseness, padding.precision.unwrap_or(6));
+                write_padded(output, value.as_bytes(), true, padding);
+                return;
+            }
+            // GNU find prints these as strings, so they're padded with
+            // spaces, and cut short by a precision, like any other string
+            _ => {
+                let number = match directive {
+                    's' => metadata.len,
+                    'D' => metadata.dev,
+                    // unlike -ls, these ignore POSIXLY_CORRECT, as with GNU find
+                    'b' => metadata.blocks_of(512),
+                    'k' => metadata.blocks_of(1024),
+                    'n' => metadata.nlink,
+                    'U' => metadata.uid as u64,
+                    'G' => metadata.gid as u64,
+                    _ => unreachable!("unknown directive {}", directive),
+                };
+                number.to_string().into_bytes()
+            }
+        };
+        write_padded(output, &string_value, false, padding);
+    }
+}
+
+impl Matcher for Printf {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let metadata = if self.format.needs_metadata {
+            match matcher_io.metadata(file_info) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    report_io_error(file_info.path(), "", &e, matcher_io);
+                    return true;
+                }
+            }
+        } else {
+            FileMetadata::default()
+        };
+
+        let mut output = vec![];
+        let mut stopped = false;
+        for component in &self.format.components {
+            match *component {
+                FormatComponent::Literal(ref bytes) => output.extend_from_slice(bytes),
+                FormatComponent::Directive(directive, ref padding) => {
+                    self.write_directive(&mut output,
+                                         directive,
+                                         padding,
+                                         file_info,
+                                         &metadata,
+                                         matcher_io)
+                }
+                FormatComponent::TimeDirective(time, field, ref padding) => {
+                    let value = file_time(time, &metadata).map_or_else(Vec::new, |file_time| {
+                        format_time_field(field, file_time, matcher_io.time_zone()).into_bytes()
+                    });
+                    write_padded(&mut output, &value, false, padding);
+                }
+                FormatComponent::Stop => {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+
+        self.destination.write(matcher_io, stopped, |out| out.write_all(&output));
+        true
+    }
+
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+
+    fn only_writes_output(&self) -> bool {
+        self.destination.is_output()
+    }
+
+    fn finished(&self, matcher_io: &mut MatcherIO) {
+        self.destination.finished(matcher_io);
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.destination {
+            Destination::Output => write!(f, "printf(")?,
+            Destination::File(ref file) => write!(f, "fprintf({}, ", file.name())?,
+        }
+        for component in &self.format.components {
+            write!(f, "{}", component)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    use find::matchers::{Matcher, WalkEntry};
+    use find::matchers::ls::Ls;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::fsinfo::MountTable;
+    use find::metadata::FileMetadata;
+    use find::tests::{fix_up_slashes, FakeDependencies};
+    use find::users::UserGroupCache;
+    use super::*;
+
+    fn printf(format: &str, file_info: &WalkEntry, deps: &FakeDependencies) -> String {
+        let matcher = Printf::new(format).unwrap();
+        assert!(matcher.matches(file_info, &mut deps.new_matcher_io()));
+        deps.get_output_as_string()
+    }
+
+    fn literal(s: &str) -> FormatComponent {
+        FormatComponent::Literal(s.as_bytes().to_vec())
+    }
+
+    fn parse_format(format: &str) -> Result<Vec<FormatComponent>, Box<Error>> {
+        parse_components(format, &mut vec![])
+    }
+
+    #[test]
+    fn parse_escapes() {
+        assert_eq!(parse_format(r"a\tb\\n\n\101\0x\q").unwrap(),
+                   vec![literal("a\tb\\n\nA\0x\\q")]);
+        assert_eq!(parse_format(r"before\cafter").unwrap(),
+                   vec![literal("before"), FormatComponent::Stop, literal("after")]);
+        assert_eq!(parse_format("trailing\\").unwrap(), vec![literal("trailing\\")]);
+    }
+
+    #[test]
+    fn parse_directives() {
+        assert_eq!(parse_format("%p\n").unwrap(),
+                   vec![FormatComponent::Directive('p', Padding::default()), literal("\n")]);
+        assert_eq!(parse_format("100%% %-10.3f").unwrap(),
+                   vec![literal("100% "),
+                        FormatComponent::Directive('f',
+                                                   Padding {
+                                                       left_justify: true,
+                                                       width: 10,
+                                                       precision: Some(3),
+                                                       ..Padding::default()
+                                                   })]);
+        // unknown directives are left as they are
+        assert_eq!(parse_format("%5z %%").unwrap(), vec![literal("%5z %")]);
+        // but one that's cut short by the end of the format is an error
+        assert_eq!(parse_format("%5z %").unwrap_err().to_string(),
+                   "% at end of format string");
+        assert_eq!(parse_format("%-5").unwrap_err().to_string(),
+                   "incomplete format directive ‘%-5’ at end of format string");
+        // and so are the ones GNU find keeps for later
+        for format in &["%{", "a %[x", "%-5(p"] {
+            let reserved = format.chars().find(|c| "{[(".contains(*c)).unwrap();
+            assert_eq!(parse_format(format).unwrap_err().to_string(),
+                       format!("the format directive ‘%{}’ is reserved for future use",
+                               reserved));
+        }
+    }
+
+    #[test]
+    fn unknown_directive_warnings() {
+        let format = Format::new("%z %5z %y %j%%z\\n").unwrap();
+        assert_eq!(format.warnings(),
+                   vec!["unrecognized format directive ‘%z’",
+                        "unrecognized format directive ‘%j’"]);
+        assert!(Format::new("%p %Tq %%\\n").unwrap().warnings().is_empty());
+    }
+
+    #[test]
+    fn format_cache() {
+        let mut cache = FormatCache::new();
+        let first = cache.get("%p\n").unwrap();
+        assert!(Rc::ptr_eq(&first, &cache.get("%p\n").unwrap()));
+        assert!(!Rc::ptr_eq(&first, &cache.get("%f\n").unwrap()));
+        // formats that don't parse aren't kept
+        assert!(cache.get("%").is_err());
+        assert!(cache.get("%").is_err());
+    }
+
+    #[test]
+    fn path_directives() {
+        let deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "ABBBC");
+        assert_eq!(printf("%p|%f|%h|%P|%H|%d\n", &file_info, &deps),
+                   fix_up_slashes("test_data/simple/subdir/ABBBC|ABBBC|test_data/simple/subdir|\
+                                   subdir/ABBBC|test_data/simple|2\n"));
+
+        let deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "simple");
+        assert_eq!(printf("[%P] %d", &file_info, &deps), "[] 0");
+    }
+
+    #[test]
+    fn base_and_dir_names() {
+        // what GNU find prints for "%f %h" with these paths
+        let names = [("w/", "w/", "w"),
+                     ("w//", "w/", "w/"),
+                     ("w//a", "a", "w/"),
+                     ("w/a/", "a/", "w"),
+                     ("ab/", "ab/", "."),
+                     ("ab/c//", "c/", "ab"),
+                     ("ab/./c", "c", "ab/."),
+                     (".", ".", "."),
+                     ("./", "./", "."),
+                     ("/", "/", ""),
+                     ("//", "/", "/"),
+                     ("/tmp", "tmp", ""),
+                     ("/tmp/", "tmp/", ""),
+                     ("///tmp", "tmp", "//"),
+                     ("//tmp//", "tmp/", "/")];
+        for &(path, base, dir) in names.iter() {
+            assert_eq!((base_name(path.as_bytes()), dir_name(path.as_bytes())),
+                       (base.as_bytes(), dir.as_bytes()),
+                       "{}",
+                       path);
+        }
+
+        // and for the entries under a start point with slashes at its end
+        let deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple//", "abbbc");
+        assert_eq!(printf("%p|%f|%h", &file_info, &deps),
+                   "test_data/simple//abbbc|abbbc|test_data/simple/");
+    }
+
+    #[test]
+    fn padding() {
+        let mut deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              len: 42,
+                              mode: 0o100644,
+                              ..FileMetadata::default()
+                          });
+        assert_eq!(printf("%-7f|%7f|%.3f|%05s|%-5s|%m|%#m\\c never", &file_info, &deps),
+                   "abbbc  |  abbbc|abb|   42|42   |644|0644");
+
+        // as with GNU find, only %d, %m and %S are numbers to be padded with
+        // zeroes, and the rest are strings to be cut short by a precision
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              len: 4096,
+                              blocks: 8,
+                              mode: 0o40755,
+                              nlink: 3,
+                              ..FileMetadata::default()
+                          });
+        assert_eq!(printf("%05d|%05m|%#06m|%05s|%05n|%05U|%.2s|%05S", &file_info, &deps),
+                   "00001|00755|000755| 4096|    3|    0|40|00001");
+    }
+
+    #[test]
+    fn metadata_directives() {
+        let mut deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              len: 5000,
+                              dev: 2049,
+                              nlink: 3,
+                              blocks: 9,
+                              ..FileMetadata::default()
+                          });
+        // %k rounds up
+        assert_eq!(printf("%s %D %n %b %k %S|%.2S|%8S", &file_info, &deps),
+                   "5000 2049 3 9 5 0.9216|0.92|  0.9216");
+
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(), FileMetadata::default());
+        assert_eq!(printf("%s %S", &file_info, &deps), "0 1");
+    }
+
+    #[test]
+    fn time_directives() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let printf_in = |tz: &str, format: &str| -> String {
+            let mut deps = FakeDependencies::new();
+            deps.set_env_var("TZ", tz);
+            let second = Duration::from_secs(1);
+            deps.set_metadata(file_info.path(),
+                              FileMetadata {
+                                  accessed: Some(UNIX_EPOCH + second * 1_000_000_000),
+                                  changed: Some(UNIX_EPOCH + second * 86400),
+                                  modified: Some(UNIX_EPOCH + second * 1_000_000_000 +
+                                                 Duration::from_millis(250)),
+                                  ..FileMetadata::default()
+                              });
+            printf(format, &file_info, &deps)
+        };
+        // all the same as GNU find
+        assert_eq!(printf_in("UTC0", "%t|%c|%TF %TT %TZ|%T@"),
+                   "Sun Sep  9 01:46:40.2500000000 2001|Fri Jan  2 00:00:00.0000000000 \
+                    1970|2001-09-09 01:46:40.2500000000 UTC|1000000000.2500000000");
+        assert_eq!(printf_in("PST8", "%t|%c|%TF %TT %TZ|%T@"),
+                   "Sat Sep  8 17:46:40.2500000000 2001|Thu Jan  1 16:00:00.0000000000 \
+                    1970|2001-09-08 17:46:40.2500000000 PST|1000000000.2500000000");
+        assert_eq!(printf_in("UTC0", "%Aa %AA %Ab %AB %Ah %Ad %Ae %AD %AH %AI %Aj %Ak %Al %Am \
+                                      %AM %Ap %AS %Au %Aw %Ay %AY %A+"),
+                   "Sun Sunday Sep September Sep 09  9 09/09/01 01 01 252  1  1 09 46 AM \
+                    40.0000000000 7 0 01 2001 2001-09-09+01:46:40.0000000000");
+        assert_eq!(printf_in("IST-5:30", "%AH:%AM %Ap %AI %Al %Aa %Aj %Au"),
+                   "07:16 AM 07  7 Sun 252 7");
+        assert_eq!(printf_in("PST8", "%AH:%AM %Ap %AI %Al %Aa %Aj %Au"),
+                   "17:46 PM 05  5 Sat 251 6");
+        assert_eq!(printf_in("PST8", "%Ts|%As|%Cs"), "1000000000|1000000000|86400");
+        // padding applies to the whole field, and unknown fields are written
+        // as they are
+        assert_eq!(printf_in("UTC0", "[%6TY|%-4Tm|%.3TS|%TQ]"), "[  2001|09  |40.|%TQ]");
+    }
+
+    #[test]
+    fn time_directives_need_a_field() {
+        assert_eq!(parse_format("%T").unwrap_err().to_string(),
+                   "incomplete format directive ‘%T’ at end of format string");
+        assert_eq!(parse_format("%-5A").unwrap_err().to_string(),
+                   "incomplete format directive ‘%-5A’ at end of format string");
+        assert_eq!(parse_format("%CY").unwrap(),
+                   vec![FormatComponent::TimeDirective('C', 'Y', Padding::default())]);
+    }
+
+    #[test]
+    fn g_format() {
+        assert_eq!(format_g(1.0, 6), "1");
+        assert_eq!(format_g(0.5, 6), "0.5");
+        assert_eq!(format_g(1.0 / 3.0, 6), "0.333333");
+        assert_eq!(format_g(2.0 / 3.0, 2), "0.67");
+        assert_eq!(format_g(8.0, 6), "8");
+        assert_eq!(format_g(123456.0, 6), "123456");
+        assert_eq!(format_g(1234567.0, 6), "1.23457e+06");
+        assert_eq!(format_g(999999.5, 6), "1e+06");
+        assert_eq!(format_g(0.0001, 6), "0.0001");
+        assert_eq!(format_g(0.00001234, 6), "1.234e-05");
+        assert_eq!(format_g(0.0, 6), "0");
+        assert_eq!(format_g(12.5, 0), "1e+01");
+    }
+
+    #[test]
+    fn symbolic_mode_directive() {
+        let mut deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              mode: 0o104754,
+                              ..FileMetadata::default()
+                          });
+        assert_eq!(printf("%M|%12M|%-12M|", &file_info, &deps),
+                   "-rwsr-xr--|  -rwsr-xr--|-rwsr-xr--  |");
+
+        // as does -ls
+        let mut ls_deps = FakeDependencies::new();
+        ls_deps.set_metadata(file_info.path(),
+                             FileMetadata {
+                                 mode: 0o104754,
+                                 ..FileMetadata::default()
+                             });
+        assert!(Ls::new().matches(&file_info, &mut ls_deps.new_matcher_io()));
+        assert!(ls_deps.get_output_as_string().contains(" -rwsr-xr-- "));
+    }
+
+    /// Fake dependencies where the only user is root and the only group is
+    /// users (100), and the file has the given owners.
+    fn deps_with_owners(file_info: &WalkEntry, uid: u32, gid: u32) -> FakeDependencies {
+        let mut deps = FakeDependencies::new();
+        let users = |uid| if uid == 0 { Some("root".to_string()) } else { None };
+        let groups = |gid| if gid == 100 { Some("users".to_string()) } else { None };
+        deps.set_user_group_cache(UserGroupCache::with_lookups(Box::new(users), Box::new(groups)));
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              uid: uid,
+                              gid: gid,
+                              ..FileMetadata::default()
+                          });
+        deps
+    }
+
+    #[test]
+    fn owner_directives() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let deps = deps_with_owners(&file_info, 0, 100);
+        assert_eq!(printf("%u %g %U %G|%-8u|%8g|", &file_info, &deps),
+                   "root users 0 100|root    |   users|");
+
+        // unknown ids are printed as numbers, padded just the same
+        let deps = deps_with_owners(&file_info, 1234, 5678);
+        assert_eq!(printf("%u %g %U %G|%-8u|%08U|", &file_info, &deps),
+                   "1234 5678 1234 5678|1234    |    1234|");
+    }
+
+    #[test]
+    fn metadata_errors() {
+        let mut deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        deps.set_metadata_error(file_info.path(), 13);
+        assert_eq!(printf("%p %s\n", &file_info, &deps), "");
+        assert!(deps.get_error_output_as_string().contains("abbbc"));
+
+        // directives that don't need metadata don't fail
+        let deps = FakeDependencies::new();
+        assert_eq!(printf("%f\n", &file_info, &deps), "abbbc\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn real_metadata_directives() {
+        use std::os::unix::fs::MetadataExt;
+
+        use fixtures::{make_file, make_hard_link, make_sparse_file, make_symlink};
+
+        let temp_dir = TempDir::new("printf_real_metadata").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let file = make_file(temp_dir.path(), "file", &[b'x'; 5000]);
+        make_hard_link(temp_dir.path(), "link", &file);
+        // a sparse-ish file: a megabyte, almost none of it written
+        make_sparse_file(temp_dir.path(), "sparse", 1024 * 1024);
+        make_symlink(temp_dir.path(), "symlink", "file").unwrap();
+
+        let deps = FakeDependencies::new();
+        let file_info = get_dir_entry_for(&dir, "file");
+        let output = printf("%s %n %D %b %k %l|", &file_info, &deps);
+        let fields: Vec<&str> = output.split(' ').collect();
+        let metadata = fs::metadata(&file).unwrap();
+        assert_eq!(&fields[..3],
+                   &["5000", "2", &metadata.dev().to_string()[..]]);
+        let blocks: u64 = fields[3].parse().unwrap();
+        assert_eq!(blocks, metadata.blocks());
+        assert_eq!(fields[4].parse::<u64>().unwrap(), (blocks + 1) / 2);
+        assert_eq!(fields[5], "|");
+
+        // a file that's all there has a sparseness of (at least) 1, with
+        // any slack in its last block; a file with holes has less, if the
+        // filesystem supports them (otherwise it's the same)
+        let sparseness: f64 = printf("%S", &file_info, &FakeDependencies::new()).parse().unwrap();
+        assert!(sparseness >= 1.0, "{}", sparseness);
+        let sparse_info = get_dir_entry_for(&dir, "sparse");
+        let output = printf("%b %S", &sparse_info, &FakeDependencies::new());
+        let fields: Vec<&str> = output.split(' ').collect();
+        let blocks: u64 = fields[0].parse().unwrap();
+        assert!(blocks * 512 <= 1024 * 1024);
+        let sparse_sparseness: f64 = fields[1].parse().unwrap();
+        assert!(sparse_sparseness <= sparseness, "{} {}", sparse_sparseness, sparseness);
+        if blocks * 512 < 1024 * 1024 {
+            assert!(sparse_sparseness < 1.0, "{}", sparse_sparseness);
+        }
+
+        // a directory's size is whatever the filesystem says it is
+        let dir_info = get_dir_entry_for(&temp_dir.path().parent().unwrap().to_string_lossy(),
+                                         &temp_dir.path().file_name().unwrap().to_string_lossy());
+        assert_eq!(printf("%s", &dir_info, &FakeDependencies::new()),
+                   fs::metadata(temp_dir.path()).unwrap().len().to_string());
+
+        let deps = FakeDependencies::new();
+        let symlink_info = get_dir_entry_for(&dir, "symlink");
+        assert_eq!(printf("%l", &symlink_info, &deps), "file");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quoted_path_directive() {
+        let temp_dir = TempDir::new("printf_quoted_path").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        File::create(temp_dir.path().join("it's here")).unwrap();
+
+        let file_info = get_dir_entry_for(&dir, "it's here");
+        assert_eq!(printf("rm -- %q\n", &file_info, &FakeDependencies::new()),
+                   format!("rm -- '{}/it'\\''s here'\n", dir));
+        // and names that are safe as they are are left alone
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        assert_eq!(printf("%q", &file_info, &FakeDependencies::new()),
+                   fix_up_slashes("test_data/simple/abbbc"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn type_directives() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new("printf_type").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        File::create(temp_dir.path().join("file")).unwrap();
+        fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        symlink("file", temp_dir.path().join("link")).unwrap();
+        symlink("dir", temp_dir.path().join("dir_link")).unwrap();
+        symlink("nowhere", temp_dir.path().join("dangling")).unwrap();
+        symlink("loop", temp_dir.path().join("loop")).unwrap();
+
+        for &(name, expected) in &[("file", "f f"),
+                                   ("dir", "d d"),
+                                   ("link", "l f"),
+                                   ("dir_link", "l d"),
+                                   ("dangling", "l N"),
+                                   ("loop", "l L")] {
+            let file_info = get_dir_entry_for(&dir, name);
+            assert_eq!(printf("%y %Y", &file_info, &FakeDependencies::new()),
+                       expected,
+                       "{}",
+                       name);
+        }
+        let file_info = get_dir_entry_for("/dev/null", "null");
+        assert_eq!(printf("%y%Y", &file_info, &FakeDependencies::new()), "cc");
+    }
+
+    #[test]
+    fn fs_type_directive() {
+        let mut deps = FakeDependencies::new();
+        deps.set_mount_table(MountTable::parse_mountinfo("1 0 0:45 / / rw - tmpfs tmpfs rw\n\
+                                                          2 1 8:1 / /a rw - ext4 /dev/sda1 rw\n"));
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let subdir = get_dir_entry_for("test_data/simple", "subdir");
+        deps.set_metadata(abbbc.path(), FileMetadata { dev: 45, ..FileMetadata::default() });
+        deps.set_metadata(subdir.path(), FileMetadata { dev: 99, ..FileMetadata::default() });
+        assert_eq!(printf("%F\n", &abbbc, &deps), "tmpfs\n");
+        // a device that isn't mounted anywhere (as far as the table says)
+        assert_eq!(printf("%F\n", &subdir, &deps), "tmpfs\nunknown\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn fs_type_directive_real_mounts() {
+        let deps = FakeDependencies::new();
+        let output = printf("%F", &get_dir_entry_for("test_data/simple", "abbbc"), &deps);
+        assert!(!output.is_empty());
+        assert_ne!(output, "unknown");
+    }
+}
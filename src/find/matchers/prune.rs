@@ -4,9 +4,10 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
-use walkdir::DirEntry;
 
-use find::matchers::{Matcher, MatcherIO};
+use std::fmt;
+
+use find::matchers::{Matcher, MatcherIO, WalkEntry};
 
 /// This matcher checks the type of the file.
 pub struct PruneMatcher;
@@ -22,10 +23,18 @@ impl PruneMatcher {
 }
 
 impl Matcher for PruneMatcher {
-    fn matches(&self, _: &DirEntry, matcher_io: &mut MatcherIO) -> bool {
+    fn matches(&self, _: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
         matcher_io.mark_current_dir_to_be_skipped();
         true
     }
+
+    fn affects_walk(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "prune")
+    }
 }
 #[cfg(test)]
 
@@ -0,0 +1,61 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+
+use std::fmt;
+
+use find::matchers::{Matcher, MatcherIO, WalkEntry};
+
+/// This matcher stops find in its tracks: no more entries are processed (and
+/// nothing more is evaluated for the current one).
+pub struct QuitMatcher;
+
+impl QuitMatcher {
+    pub fn new() -> QuitMatcher {
+        QuitMatcher {}
+    }
+
+    pub fn new_box() -> Box<Matcher> {
+        Box::new(QuitMatcher::new())
+    }
+}
+
+impl Matcher for QuitMatcher {
+    fn matches(&self, _: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        matcher_io.quit();
+        true
+    }
+
+    // Stopping is very much a side effect: "find -quit" shouldn't print the
+    // first entry.
+    fn has_side_effects(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "quit")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use find::matchers::Matcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    #[test]
+    fn quit_matcher() {
+        let dir = get_dir_entry_for("test_data", "simple");
+        let deps = FakeDependencies::new();
+
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(!matcher_io.should_quit());
+        let matcher = QuitMatcher::new();
+        assert!(matcher.matches(&dir, &mut matcher_io));
+        assert!(matcher_io.should_quit());
+    }
+}
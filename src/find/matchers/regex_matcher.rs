@@ -0,0 +1,339 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! -regex and -iregex, which match the whole path against a regular
+//! expression. Patterns are written in one of the POSIX-ish syntaxes that GNU
+//! find understands (see -regextype), and translated into the regex crate's.
+
+use regex::{self, Regex, RegexBuilder};
+use std::error::Error;
+use std::fmt;
+
+use find::matchers::{Matcher, MatcherIO, WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
+
+/// The syntaxes that -regextype can choose between, which differ in which
+/// characters are operators and which need a backslash to be one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RegexType {
+    /// GNU find's default (findutils-default or emacs): "\(", "\)" and "\|"
+    /// group and alternate, and "+" and "?" are operators.
+    Emacs,
+    /// Basic regular expressions (posix-basic, ed, sed and grep): "\(",
+    /// "\)", "\{", "\}", "\|", "\+" and "\?" are operators, and the same
+    /// characters without a backslash aren't.
+    Basic,
+    /// Extended regular expressions (posix-extended, egrep, awk etc.), where
+    /// "(", ")", "{", "}", "|", "+" and "?" are all operators.
+    Extended,
+}
+
+/// The names -regextype accepts (GNU find's), and what they mean.
+const REGEX_TYPES: &'static [(&'static str, RegexType)] =
+    &[("findutils-default", RegexType::Emacs),
+      ("ed", RegexType::Basic),
+      ("emacs", RegexType::Emacs),
+      ("gnu-awk", RegexType::Extended),
+      ("grep", RegexType::Basic),
+      ("posix-awk", RegexType::Extended),
+      ("awk", RegexType::Extended),
+      ("posix-basic", RegexType::Basic),
+      ("posix-egrep", RegexType::Extended),
+      ("egrep", RegexType::Extended),
+      ("posix-extended", RegexType::Extended),
+      ("posix-minimal-basic", RegexType::Basic),
+      ("sed", RegexType::Basic)];
+
+impl RegexType {
+    /// Looks up the syntax with the given name (the argument to -regextype).
+    pub fn from_name(name: &str) -> Result<RegexType, Box<Error>> {
+        match REGEX_TYPES.iter().find(|&&(type_name, _)| type_name == name) {
+            Some(&(_, regex_type)) => Ok(regex_type),
+            None => {
+                let valid_types: Vec<String> =
+                    REGEX_TYPES.iter().map(|&(type_name, _)| format!("‘{}’", type_name)).collect();
+                Err(From::from(format!("Unknown regular expression type ‘{}’; valid types \
+                                        are {}.",
+                                       name,
+                                       valid_types.join(", "))))
+            }
+        }
+    }
+}
+
+impl Default for RegexType {
+    fn default() -> RegexType {
+        RegexType::Emacs
+    }
+}
+
+/// Pushes a character that's meant literally.
+fn push_literal(translated: &mut String, c: char) {
+    translated.push_str(&regex::escape(&c.to_string()));
+}
+
+/// Translates a bracket expression (starting just after its "["), returning
+/// the index of the character after its "]". Backslashes aren't special in
+/// POSIX bracket expressions, but they (and some other characters) are in
+/// the regex crate's.
+fn translate_bracket(chars: &[char], start: usize, translated: &mut String) -> Option<usize> {
+    let mut i = start;
+    translated.push('[');
+    if chars.get(i) == Some(&'^') {
+        translated.push('^');
+        i += 1;
+    }
+    // a "]" straight away is part of the set
+    if chars.get(i) == Some(&']') {
+        translated.push_str("\\]");
+        i += 1;
+    }
+    while i < chars.len() {
+        match chars[i] {
+            ']' => {
+                translated.push(']');
+                return Some(i + 1);
+            }
+            '[' if chars.get(i + 1) == Some(&':') => {
+                // a character class like [:alpha:], which the regex crate
+                // understands as it is
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == ':' && chars[j + 1] == ']')?;
+                translated.extend(&chars[i..end + 2]);
+                i = end + 2;
+            }
+            '[' if chars.get(i + 1) == Some(&'=') || chars.get(i + 1) == Some(&'.') => {
+                // an equivalence class or collating symbol, which (without
+                // locales) is just the characters in it
+                let delimiter = chars[i + 1];
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == delimiter && chars[j + 1] == ']')?;
+                for &c in &chars[i + 2..end] {
+                    push_literal(translated, c);
+                }
+                i = end + 2;
+            }
+            c @ '[' | c @ '\\' | c @ '&' | c @ '~' => {
+                translated.push('\\');
+                translated.push(c);
+                i += 1;
+            }
+            c => {
+                translated.push(c);
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+/// Translates a pattern in the given syntax into the regex crate's, anchored
+/// at both ends (as -regex matches the whole path).
+pub fn translate(pattern: &str, regex_type: RegexType) -> Result<String, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut translated = String::from("\\A(?:");
+    // whether a "*" would have nothing to repeat, in which case it's literal
+    let mut at_start = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        let was_at_start = at_start;
+        at_start = false;
+        match c {
+            '\\' => {
+                let escaped = match chars.get(i) {
+                    Some(&escaped) => escaped,
+                    None => return Err("trailing backslash".to_string()),
+                };
+                i += 1;
+                match (escaped, regex_type) {
+                    ('(', RegexType::Emacs) |
+                    ('(', RegexType::Basic) |
+                    ('|', RegexType::Emacs) |
+                    ('|', RegexType::Basic) => {
+                        translated.push(escaped);
+                        at_start = true;
+                    }
+                    (')', RegexType::Emacs) |
+                    (')', RegexType::Basic) |
+                    ('{', RegexType::Basic) |
+                    ('}', RegexType::Basic) |
+                    ('+', RegexType::Basic) |
+                    ('?', RegexType::Basic) => translated.push(escaped),
+                    ('<', _) | ('>', _) => translated.push_str("\\b"),
+                    ('`', _) => translated.push_str("\\A"),
+                    ('\'', _) => translated.push_str("\\z"),
+                    ('w', _) | ('W', _) | ('s', _) | ('S', _) | ('b', _) | ('B', _) => {
+                        translated.push('\\');
+                        translated.push(escaped);
+                    }
+                    ('1'..='9', _) => {
+                        return Err("back-references aren't supported".to_string());
+                    }
+                    _ => push_literal(&mut translated, escaped),
+                }
+            }
+            '[' => {
+                i = match translate_bracket(&chars, i, &mut translated) {
+                    Some(end) => end,
+                    None => return Err("unmatched [".to_string()),
+                };
+            }
+            '(' | '|' if regex_type == RegexType::Extended => {
+                translated.push(c);
+                at_start = true;
+            }
+            ')' | '{' | '}' if regex_type == RegexType::Extended => translated.push(c),
+            '+' | '?' if regex_type != RegexType::Basic && !was_at_start => translated.push(c),
+            '*' if !was_at_start => translated.push(c),
+            '^' => {
+                translated.push(c);
+                at_start = true;
+            }
+            '.' | '$' => translated.push(c),
+            _ => push_literal(&mut translated, c),
+        }
+    }
+    translated.push_str(")\\z");
+    Ok(translated)
+}
+
+/// This matcher checks whether the whole path (as it would be printed)
+/// matches a regular expression.
+#[derive(Clone)]
+pub struct RegexMatcher {
+    regex: Regex,
+    pattern: String,
+    ignore_case: bool,
+}
+
+impl RegexMatcher {
+    pub fn new(pattern: &str,
+               regex_type: RegexType,
+               ignore_case: bool)
+               -> Result<RegexMatcher, Box<Error>> {
+        let invalid = |e: &fmt::Display| {
+            format!("invalid regular expression ‘{}’: {}", pattern, e)
+        };
+        let translated = translate(pattern, regex_type).map_err(|e| invalid(&e))?;
+        // as with GNU find, "." matches newlines too
+        let regex = RegexBuilder::new(&translated).case_insensitive(ignore_case)
+            .dot_matches_new_line(true)
+            .build()
+            .map_err(|e| invalid(&e))?;
+        Ok(RegexMatcher {
+            regex: regex,
+            pattern: pattern.to_string(),
+            ignore_case: ignore_case,
+        })
+    }
+
+    pub fn new_box(pattern: &str,
+                   regex_type: RegexType,
+                   ignore_case: bool)
+                   -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(RegexMatcher::new(pattern, regex_type, ignore_case)?))
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn matches(&self, file_info: &WalkEntry, _: &mut MatcherIO) -> bool {
+        self.regex.is_match(&file_info.path().to_string_lossy())
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.ignore_case { "iregex" } else { "regex" };
+        write!(f, "{}({})", name, self.pattern)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl ParallelMatcher for RegexMatcher {}
+
+#[cfg(test)]
+mod tests {
+    use find::matchers::Matcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    fn matches(pattern: &str, regex_type: RegexType, path: &str) -> bool {
+        let regex = Regex::new(&translate(pattern, regex_type).unwrap()).unwrap();
+        regex.is_match(path)
+    }
+
+    #[test]
+    fn regex_types() {
+        assert_eq!(RegexType::from_name("findutils-default").unwrap(), RegexType::Emacs);
+        assert_eq!(RegexType::from_name("sed").unwrap(), RegexType::Basic);
+        assert_eq!(RegexType::from_name("egrep").unwrap(), RegexType::Extended);
+        let e = RegexType::from_name("perl").unwrap_err();
+        assert!(e.to_string().starts_with("Unknown regular expression type ‘perl’; valid types \
+                                           are ‘findutils-default’, ‘ed’"),
+                "{}",
+                e);
+    }
+
+    #[test]
+    fn operators_depend_on_the_syntax() {
+        use self::RegexType::*;
+
+        // the whole path has to match
+        assert!(matches(".*/abc", Emacs, "./dir/abc"));
+        assert!(!matches("dir", Emacs, "./dir/abc"));
+
+        assert!(matches("a\\(b\\|c\\)+", Emacs, "abcb"));
+        assert!(matches("a(b|c)+", Emacs, "a(b|c)))"));
+        assert!(matches("a\\(b\\|c\\)\\+", Basic, "abcb"));
+        assert!(matches("a(b|c)+", Basic, "a(b|c)+"));
+        assert!(matches("ab\\{2\\}", Basic, "abb"));
+        assert!(matches("ab{2}", Basic, "ab{2}"));
+        assert!(matches("a(b|c)+", Extended, "abcb"));
+        assert!(matches("ab{2}", Extended, "abb"));
+        assert!(matches("a\\(b\\|c\\)\\+", Extended, "a(b|c)+"));
+
+        // "*" at the start has nothing to repeat
+        assert!(matches("*a*", Basic, "*aaa"));
+        assert!(matches("\\(*a\\)", Emacs, "*a"));
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        use self::RegexType::*;
+
+        assert!(matches("[]a]*", Emacs, "]a]"));
+        assert!(matches("[^]a]*", Emacs, "bcd"));
+        assert!(!matches("[^]a]*", Emacs, "bad"));
+        // backslashes aren't special in brackets
+        assert!(matches("[\\n]*", Extended, "\\n"));
+        assert!(matches("[[:digit:][:upper:]]*", Extended, "1A2B"));
+        assert!(matches("[[=a=][.-.]]*", Extended, "a-a"));
+        assert!(matches("[a&&b~]*", Extended, "&~ab"));
+        assert!(translate("[abc", Extended).is_err());
+        assert!(translate("abc\\", Extended).is_err());
+        assert!(translate("\\(a\\)\\1", Emacs).is_err());
+    }
+
+    #[test]
+    fn regex_matcher() {
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let deps = FakeDependencies::new();
+
+        let matcher = RegexMatcher::new(".*/ab+c", RegexType::Emacs, false).unwrap();
+        assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+        let matcher = RegexMatcher::new(".*/AB+C", RegexType::Emacs, false).unwrap();
+        assert!(!matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+        let matcher = RegexMatcher::new(".*/AB+C", RegexType::Emacs, true).unwrap();
+        assert!(matcher.matches(&abbbc, &mut deps.new_matcher_io()));
+
+        let e = RegexMatcher::new("a{", RegexType::Extended, false).err().unwrap();
+        assert!(e.to_string().starts_with("invalid regular expression ‘a{’: "), "{}", e);
+    }
+}
@@ -0,0 +1,486 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Predicates (e.g. -name, -exec) are looked up by name in a `MatcherRegistry`
+//! rather than being hard-coded into the parser, which means other crates can
+//! add their own without forking it. Operators, brackets and global options
+//! (-depth, -maxdepth etc.) change how the rest of the expression is parsed
+//! rather than just adding a matcher to it, so they're still handled by the
+//! parser itself.
+//!
+//! The registry also knows which predicates POSIX specifies, so that POSIX
+//! mode (see `Config::posix_mode`) can reject the rest.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::rc::Rc;
+
+use find::Config;
+use find::matchers::{Matcher, convert_arg_to_comparable_value,
+                     convert_arg_to_comparable_value_and_suffix};
+use find::matchers::{context, delete, exec, fstype, json, logical_matchers, ls, name, perm,
+                     printer, printf, prune, quit, regex_matcher, samefile, size, time,
+                     type_matcher, user};
+use find::matchers::output_file::Destination;
+
+/// Gives a predicate's factory access to the arguments that follow it. Any
+/// arguments the factory consumes are skipped over by the parser.
+pub struct ArgIter<'a> {
+    args: &'a [&'a str],
+    predicate_index: usize,
+    index: usize,
+}
+
+impl<'a> ArgIter<'a> {
+    /// Creates an iterator over the arguments following args[index], which
+    /// should be the predicate itself.
+    pub fn new(args: &'a [&'a str], index: usize) -> ArgIter<'a> {
+        ArgIter {
+            args: args,
+            predicate_index: index,
+            index: index,
+        }
+    }
+
+    /// The predicate whose arguments these are (e.g. "-name").
+    pub fn predicate(&self) -> &'a str {
+        self.args[self.predicate_index]
+    }
+
+    /// Returns the next argument, or a "missing argument" error if there
+    /// isn't one.
+    pub fn next_required(&mut self) -> Result<&'a str, Box<Error>> {
+        match self.next() {
+            Some(arg) => Ok(arg),
+            None => Err(From::from(format!("missing argument to {}", self.predicate()))),
+        }
+    }
+
+    /// Returns the next argument, or an error saying which of the
+    /// predicate's arguments (e.g. "file") is missing if there isn't one.
+    /// For predicates with more than one argument, where it's easy to lose
+    /// track.
+    pub fn next_required_named(&mut self, name: &str) -> Result<&'a str, Box<Error>> {
+        match self.next() {
+            Some(arg) => Ok(arg),
+            None => Err(From::from(format!("missing {} argument to {}", name, self.predicate()))),
+        }
+    }
+
+    /// The index (into the full argument list) of the last argument consumed,
+    /// or of the predicate if nothing's been consumed.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<'a> Iterator for ArgIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.index + 1 >= self.args.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.args[self.index])
+    }
+}
+
+/// Builds the matcher for a predicate, consuming any arguments it needs.
+pub type MatcherFactory = fn(&mut ArgIter, &mut Config) -> Result<Box<Matcher>, Box<Error>>;
+
+/// Where a predicate comes from, which decides whether it's available in
+/// POSIX mode.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Standard {
+    /// Specified by POSIX, so always available.
+    Posix,
+    /// An extension (from GNU find, or anywhere else), which POSIX mode
+    /// rejects.
+    Gnu,
+}
+
+/// The operators and global options that the parser handles itself, but
+/// which POSIX doesn't specify. Everything else it handles ("!", "-a", "-o",
+/// brackets and -depth) is in POSIX.
+const PARSER_EXTENSIONS: &'static [&'static str] = &["-not", "-or", ",", "-d", "-sorted",
+                                                     "-maxdepth", "-mindepth", "-help",
+                                                     "--help", "-daystart", "-follow",
+                                                     "-mount", "-noleaf",
+                                                     "-ignore_readdir_race",
+                                                     "-noignore_readdir_race", "-warn",
+                                                     "-nowarn", "-regextype"];
+
+struct Predicate {
+    factory: MatcherFactory,
+    standard: Standard,
+}
+
+/// A map from predicate names (including the leading '-') to the functions
+/// that build their matchers.
+pub struct MatcherRegistry {
+    predicates: HashMap<String, Predicate>,
+}
+
+impl MatcherRegistry {
+    /// Creates a registry with no predicates at all.
+    pub fn new() -> MatcherRegistry {
+        MatcherRegistry { predicates: HashMap::new() }
+    }
+
+    /// Creates a registry containing all the predicates find supports out of
+    /// the box.
+    pub fn with_builtins() -> MatcherRegistry {
+        let mut registry = MatcherRegistry::new();
+        registry.register_posix("-print", build_print);
+        registry.register("-print0", build_print);
+        registry.register("-printf", build_printf);
+        registry.register("-ls", build_ls);
+        registry.register("-printj", build_printj);
+        registry.register("-fprint", build_print);
+        registry.register("-fprint0", build_print);
+        registry.register("-fprintf", build_printf);
+        registry.register("-fls", build_ls);
+        registry.register("-true", build_true);
+        registry.register("-false", build_false);
+        registry.register_posix("-name", build_name);
+        registry.register("-iname", build_name);
+        registry.register_posix("-path", build_path);
+        registry.register("-ipath", build_path);
+        registry.register("-regex", build_regex);
+        registry.register("-iregex", build_regex);
+        registry.register_posix("-type", build_type);
+        registry.register("-delete", build_delete);
+        registry.register_posix("-newer", build_newer);
+        registry.register("-anewer", build_newer);
+        registry.register("-samefile", build_samefile);
+        registry.register("-fstype", build_fstype);
+        registry.register_posix("-mtime", build_file_time);
+        registry.register_posix("-atime", build_file_time);
+        registry.register_posix("-ctime", build_file_time);
+        registry.register_posix("-size", build_size);
+        registry.register_posix("-exec", build_exec);
+        registry.register("-execdir", build_exec);
+        registry.register_posix("-ok", build_exec);
+        registry.register("-okdir", build_exec);
+        registry.register_posix("-perm", build_perm);
+        registry.register_posix("-prune", build_prune);
+        registry.register("-quit", build_quit);
+        registry.register_posix("-user", build_user);
+        registry.register_posix("-group", build_group);
+        registry.register("-context", build_context);
+        registry
+    }
+
+    /// Adds a predicate that isn't in POSIX, replacing any existing
+    /// predicate of the same name.
+    pub fn register(&mut self, predicate: &str, factory: MatcherFactory) {
+        self.register_with_standard(predicate, factory, Standard::Gnu);
+    }
+
+    /// Adds a predicate that POSIX specifies, which is available in POSIX
+    /// mode too.
+    pub fn register_posix(&mut self, predicate: &str, factory: MatcherFactory) {
+        self.register_with_standard(predicate, factory, Standard::Posix);
+    }
+
+    fn register_with_standard(&mut self,
+                              predicate: &str,
+                              factory: MatcherFactory,
+                              standard: Standard) {
+        self.predicates.insert(predicate.to_string(),
+                               Predicate {
+                                   factory: factory,
+                                   standard: standard,
+                               });
+    }
+
+    /// Returns the factory for the given predicate, if there is one.
+    pub fn get(&self, predicate: &str) -> Option<MatcherFactory> {
+        self.predicates.get(predicate).map(|predicate| predicate.factory)
+    }
+
+    /// Returns where the given predicate, operator or global option comes
+    /// from, or None if it's none of those.
+    pub fn standard(&self, arg: &str) -> Option<Standard> {
+        if PARSER_EXTENSIONS.contains(&arg) {
+            return Some(Standard::Gnu);
+        }
+        self.predicates.get(arg).map(|predicate| predicate.standard)
+    }
+
+    /// Fails if the given argument is a predicate, operator or global option
+    /// that isn't available in POSIX mode.
+    pub fn check_posix(&self, arg: &str) -> Result<(), Box<Error>> {
+        if self.standard(arg) == Some(Standard::Gnu) {
+            return Err(From::from(format!("{} is not supported in POSIX mode", arg)));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MatcherRegistry {
+    fn default() -> MatcherRegistry {
+        MatcherRegistry::with_builtins()
+    }
+}
+
+fn build_print(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let delimiter = match args.predicate() {
+        "-print0" | "-fprint0" => printer::PrintDelimiter::Null,
+        _ => printer::PrintDelimiter::Newline,
+    };
+    if args.predicate().starts_with("-f") {
+        let file = config.output_files.open(args.next_required()?);
+        return Ok(printer::Printer::new_box_for_file(delimiter, file));
+    }
+    Ok(printer::Printer::new_box(delimiter))
+}
+
+fn build_printf(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    if args.predicate() == "-fprintf" {
+        let file_name = args.next_required_named("file")?;
+        let format = printf_format(args.next_required_named("format")?, config)?;
+        let file = config.output_files.open(file_name);
+        return Ok(Box::new(printf::Printf::with_format(format, Destination::File(file))));
+    }
+    let format = printf_format(args.next_required()?, config)?;
+    Ok(Box::new(printf::Printf::with_format(format, Destination::Output)))
+}
+
+/// Parses a -printf or -fprintf format (or finds it already parsed), adding
+/// any warnings about it that haven't already been given.
+fn printf_format(format: &str, config: &mut Config) -> Result<Rc<printf::Format>, Box<Error>> {
+    let format = config.printf_formats.get(format)?;
+    for warning in format.warnings() {
+        if !config.warnings.contains(&warning) {
+            config.warnings.push(warning);
+        }
+    }
+    Ok(format)
+}
+
+fn build_ls(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    if args.predicate() == "-fls" {
+        let file = config.output_files.open(args.next_required()?);
+        return Ok(ls::Ls::new_box_for_file(file));
+    }
+    Ok(ls::Ls::new_box())
+}
+
+fn build_printj(_: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    Ok(json::JsonPrinter::new_box())
+}
+
+fn build_true(_: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    Ok(logical_matchers::TrueMatcher::new_box())
+}
+
+fn build_false(_: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    Ok(logical_matchers::FalseMatcher::new_box())
+}
+
+fn build_name(args: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let pattern = args.next_required()?;
+    let case_insensitive = args.predicate() == "-iname";
+    Ok(name::NameMatcher::new_box(pattern, case_insensitive)?)
+}
+
+fn build_path(args: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let pattern = args.next_required()?;
+    if args.predicate() == "-ipath" {
+        Ok(name::CaselessPathMatcher::new_box(pattern)?)
+    } else {
+        Ok(name::PathMatcher::new_box(pattern)?)
+    }
+}
+
+fn build_regex(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let pattern = args.next_required()?;
+    let ignore_case = args.predicate() == "-iregex";
+    regex_matcher::RegexMatcher::new_box(pattern, config.regex_type, ignore_case)
+}
+
+fn build_type(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    type_matcher::TypeMatcher::new_box(args.next_required()?, config.stat_types)
+}
+
+fn build_fstype(args: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    Ok(fstype::FsTypeMatcher::new_box(args.next_required()?))
+}
+
+fn build_delete(_: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    // -delete implicitly requires -depth
+    config.depth_first = true;
+    Ok(delete::DeleteMatcher::new_box()?)
+}
+
+fn build_newer(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let file_time_type = match args.predicate() {
+        "-anewer" => time::FileTimeType::Accessed,
+        _ => time::FileTimeType::Modified,
+    };
+    // (a link named on the command line is followed with -H too)
+    time::NewerMatcher::new_box(file_time_type,
+                                args.next_required()?,
+                                config.follow_start_points)
+}
+
+fn build_samefile(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    samefile::SameFileMatcher::new_box(args.next_required()?, config.follow_start_points)
+}
+
+fn build_file_time(args: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let file_time_type = match args.predicate() {
+        "-atime" => time::FileTimeType::Accessed,
+        "-ctime" => time::FileTimeType::Changed,
+        _ => time::FileTimeType::Modified,
+    };
+    let days = convert_arg_to_comparable_value(args.predicate(), args.next_required()?)?;
+    Ok(time::FileTimeMatcher::new_box(file_time_type, days))
+}
+
+fn build_size(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let (size, unit) = convert_arg_to_comparable_value_and_suffix(args.predicate(),
+                                                                  args.next_required()?)?;
+    if config.posix_mode {
+        size::SizeMatcher::new_box_posix(size, &unit)
+    } else {
+        size::SizeMatcher::new_box(size, &unit)
+    }
+}
+
+fn build_exec(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    let expression = args.predicate();
+    let mut command = vec![];
+    loop {
+        match args.next() {
+            Some(";") => break,
+            // "+" only ends the command straight after "{}" (and -ok doesn't
+            // have the batched form at all): otherwise it's just an argument
+            Some("+") if command.len() > 1 && command.last() == Some(&"{}") &&
+                         !expression.starts_with("-ok") => {
+                command.pop();
+                if command[1..].contains(&"{}") {
+                    return Err(From::from(format!("Only one instance of {{}} is supported with \
+                                                   {} ... +",
+                                                  expression)));
+                }
+                if !config.allow_batch_exec {
+                    ::exec::check_not_batch_file(command[0])?;
+                }
+                return exec::MultiExecMatcher::new_box(command[0],
+                                                       &command[1..],
+                                                       expression.ends_with("dir"));
+            }
+            Some(arg) => command.push(arg),
+            None => return Err(From::from(format!("missing argument to {}", expression))),
+        }
+    }
+    if command.is_empty() {
+        // at the minimum we need the executable and the ';'
+        return Err(From::from(format!("missing argument to {}", expression)));
+    }
+    if command[1..].contains(&"{}") && !config.allow_batch_exec {
+        ::exec::check_not_batch_file(command[0])?;
+    }
+    let in_parent_dir = expression.ends_with("dir");
+    if expression.starts_with("-ok") {
+        exec::SingleExecMatcher::new_box_with_confirmation(command[0], &command[1..], in_parent_dir)
+    } else {
+        exec::SingleExecMatcher::new_box(command[0], &command[1..], in_parent_dir)
+    }
+}
+
+fn build_perm(args: &mut ArgIter, config: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    if config.posix_mode {
+        perm::PermMatcher::new_box_posix(args.next_required()?)
+    } else {
+        perm::PermMatcher::new_box(args.next_required()?)
+    }
+}
+
+fn build_prune(_: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    Ok(prune::PruneMatcher::new_box())
+}
+
+fn build_quit(_: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    Ok(quit::QuitMatcher::new_box())
+}
+
+fn build_user(args: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    user::UserMatcher::new_box(args.next_required()?)
+}
+
+fn build_group(args: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    user::GroupMatcher::new_box(args.next_required()?)
+}
+
+fn build_context(args: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    context::ContextMatcher::new_box(args.next_required()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arg_iter_consumes_arguments() {
+        let args = ["-true", "-name", "foo", "-print"];
+        let mut arg_iter = ArgIter::new(&args, 1);
+        assert_eq!(arg_iter.predicate(), "-name");
+        assert_eq!(arg_iter.index(), 1);
+        assert_eq!(arg_iter.next_required().unwrap(), "foo");
+        assert_eq!(arg_iter.index(), 2);
+        assert_eq!(arg_iter.next(), Some("-print"));
+        assert_eq!(arg_iter.next(), None);
+        assert_eq!(arg_iter.index(), 3);
+
+        let e = arg_iter.next_required().unwrap_err();
+        assert_eq!(e.to_string(), "missing argument to -name");
+    }
+
+    #[test]
+    fn registry_lookups() {
+        let mut registry = MatcherRegistry::new();
+        assert!(registry.get("-true").is_none());
+        registry.register("-true", build_true);
+        assert!(registry.get("-true").is_some());
+
+        let registry = MatcherRegistry::default();
+        for predicate in &["-print", "-name", "-exec", "-quit"] {
+            assert!(registry.get(predicate).is_some());
+        }
+        // operators and global options aren't predicates
+        for arg in &["-o", "(", "-maxdepth"] {
+            assert!(registry.get(arg).is_none());
+        }
+    }
+
+    #[test]
+    fn posix_classification() {
+        let mut registry = MatcherRegistry::default();
+        for arg in &["-name", "-size", "-exec", "-perm"] {
+            assert_eq!(registry.standard(arg), Some(Standard::Posix));
+            assert!(registry.check_posix(arg).is_ok());
+        }
+        for arg in &["-iname", "-print0", "-execdir", "-maxdepth", "-or"] {
+            assert_eq!(registry.standard(arg), Some(Standard::Gnu));
+        }
+        // things that aren't predicates at all are left for the parser
+        for arg in &["-o", "(", "-a", "-depth", "-nosuch", "foo"] {
+            assert!(registry.check_posix(arg).is_ok());
+        }
+        assert_eq!(registry.check_posix("-iname").unwrap_err().to_string(),
+                   "-iname is not supported in POSIX mode");
+
+        // predicates from elsewhere aren't in POSIX either
+        registry.register("-extra", build_true);
+        assert_eq!(registry.standard("-extra"), Some(Standard::Gnu));
+        registry.register_posix("-extra", build_true);
+        assert_eq!(registry.standard("-extra"), Some(Standard::Posix));
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+use std::error::Error;
+use std::fmt;
+
+use find::matchers::{reference_metadata, report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
+
+/// This matcher checks whether a file is the same file (i.e. has the same
+/// device and inode numbers) as the one the matcher is initialized with, as
+/// -samefile does. That finds hard links to it, and with -L, symbolic links
+/// to it too.
+#[derive(Clone)]
+pub struct SameFileMatcher {
+    path: String,
+    dev: u64,
+    ino: u64,
+}
+
+impl SameFileMatcher {
+    /// Reads the given file's device and inode numbers. If it's a symbolic
+    /// link, it's followed if follow_links is set (as with -L), and otherwise
+    /// it's the link itself that files are compared with.
+    pub fn new(path_to_file: &str, follow_links: bool) -> Result<SameFileMatcher, Box<Error>> {
+        let metadata = reference_metadata(path_to_file, follow_links)?;
+        if metadata.dev == 0 && metadata.ino == 0 {
+            return Err(From::from("-samefile isn't supported on this platform"));
+        }
+        Ok(SameFileMatcher {
+            path: path_to_file.to_string(),
+            dev: metadata.dev,
+            ino: metadata.ino,
+        })
+    }
+
+    pub fn new_box(path_to_file: &str, follow_links: bool) -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(SameFileMatcher::new(path_to_file, follow_links)?))
+    }
+}
+
+impl Matcher for SameFileMatcher {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match matcher_io.metadata(file_info) {
+            Ok(metadata) => metadata.dev == self.dev && metadata.ino == self.ino,
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                false
+            }
+        }
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "samefile({})", self.path)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl ParallelMatcher for SameFileMatcher {}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use tempdir::TempDir;
+
+    use find::matchers::Matcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn samefile_matcher() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new("samefile_matcher").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+        File::create(path("file")).unwrap();
+        File::create(path("other")).unwrap();
+        fs::hard_link(path("file"), path("hard")).unwrap();
+        symlink("file", path("link")).unwrap();
+        symlink("nowhere", path("dangling")).unwrap();
+
+        let deps = FakeDependencies::new();
+        let matching = |matcher: &SameFileMatcher, follow_links: bool| -> Vec<&str> {
+            let mut matching = vec![];
+            for name in &["file", "other", "hard", "link", "dangling"] {
+                let mut matcher_io = deps.new_matcher_io();
+                matcher_io.set_follow_links(follow_links);
+                if matcher.matches(&get_dir_entry_for(&dir, name), &mut matcher_io) {
+                    matching.push(*name);
+                }
+            }
+            matching
+        };
+
+        let matcher = SameFileMatcher::new(&path("file"), false).unwrap();
+        assert_eq!(matching(&matcher, false), ["file", "hard"]);
+        assert_eq!(matching(&matcher, true), ["file", "hard", "link"]);
+
+        // without -L, a symbolic link is only the same file as itself...
+        let matcher = SameFileMatcher::new(&path("link"), false).unwrap();
+        assert_eq!(matching(&matcher, false), ["link"]);
+        // ...and with it, it's the file it points to
+        let matcher = SameFileMatcher::new(&path("link"), true).unwrap();
+        assert_eq!(matching(&matcher, true), ["file", "hard", "link"]);
+
+        // which is fine for a dangling link without -L, but not with it
+        let matcher = SameFileMatcher::new(&path("dangling"), false).unwrap();
+        assert_eq!(matching(&matcher, false), ["dangling"]);
+        let e = SameFileMatcher::new(&path("dangling"), true).err().unwrap();
+        assert_eq!(e.to_string(),
+                   format!("‘{}’: No such file or directory", path("dangling")));
+    }
+}
@@ -5,11 +5,11 @@
 // https://opensource.org/licenses/MIT.
 
 use std::error::Error;
-use std::io::{stderr, Write};
+use std::fmt;
 use std::str::FromStr;
-use walkdir::DirEntry;
 
-use find::matchers::{ComparableValue, Matcher, MatcherIO};
+use find::matchers::{report_io_error, ComparableValue, Matcher, MatcherIO, WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
 
 #[derive(Clone, Copy, Debug)]
 enum Unit {
@@ -40,6 +40,20 @@ impl FromStr for Unit {
     }
 }
 
+impl Unit {
+    /// The suffix that stands for the unit in a -size argument.
+    fn suffix(self) -> &'static str {
+        match self {
+            Unit::Byte => "c",
+            Unit::TwoByteWord => "w",
+            Unit::Block => "b",
+            Unit::KibiByte => "k",
+            Unit::MebiByte => "M",
+            Unit::GibiByte => "G",
+        }
+    }
+}
+
 fn byte_size_to_unit_size(unit: Unit, byte_size: u64) -> u64 {
     // Short circuit (to avoid a overflow error when subtracting 1 later on)
     if byte_size == 0 {
@@ -64,6 +78,7 @@ fn byte_size_to_unit_size(unit: Unit, byte_size: u64) -> u64 {
 
 /// Matcher that checks whether a file's size if {less than | equal to | more than}
 /// N units in size.
+#[derive(Clone)]
 pub struct SizeMatcher {
     value_to_match: ComparableValue,
     unit: Unit,
@@ -79,36 +94,64 @@ impl SizeMatcher {
         })
     }
 
+    /// Creates a matcher that only accepts the units POSIX does: 512-byte
+    /// blocks by default, or bytes with a "c" suffix.
+    pub fn new_posix(value_to_match: ComparableValue,
+                     suffix_string: &str)
+                     -> Result<SizeMatcher, Box<Error>> {
+        match suffix_string {
+            "" | "c" => SizeMatcher::new(value_to_match, suffix_string),
+            _ => {
+                Err(From::from(format!("Invalid suffix {} for -size. Only allowed values in \
+                                        POSIX mode are <nothing> or c",
+                                       suffix_string)))
+            }
+        }
+    }
+
     pub fn new_box(value_to_match: ComparableValue,
                    suffix_string: &str)
                    -> Result<Box<Matcher>, Box<Error>> {
         Ok(Box::new(SizeMatcher::new(value_to_match, suffix_string)?))
     }
+
+    pub fn new_box_posix(value_to_match: ComparableValue,
+                         suffix_string: &str)
+                         -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(SizeMatcher::new_posix(value_to_match, suffix_string)?))
+    }
 }
 
 impl Matcher for SizeMatcher {
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        match file_info.metadata() {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match matcher_io.metadata(file_info) {
             Ok(metadata) => {
                 self.value_to_match
-                    .matches(byte_size_to_unit_size(self.unit, metadata.len()))
+                    .matches(byte_size_to_unit_size(self.unit, metadata.len))
             }
             Err(e) => {
-                writeln!(&mut stderr(),
-                         "Error getting file size for {}: {}",
-                         file_info.path().to_string_lossy(),
-                         e)
-                    .unwrap();
+                report_io_error(file_info.path(), "", &e, matcher_io);
                 false
             }
         }
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "size({}{})", self.value_to_match, self.unit.suffix())
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
+impl ParallelMatcher for SizeMatcher {}
+
 #[cfg(test)]
 mod tests {
     use find::matchers::{ComparableValue, Matcher};
     use find::matchers::tests::get_dir_entry_for;
+    use find::metadata::FileMetadata;
     use find::tests::FakeDependencies;
     use super::*;
     // need to explicitly use non-pub members
@@ -153,6 +196,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn size_matcher_posix_units() {
+        let file_info = get_dir_entry_for("./test_data/size", "512bytes");
+        let deps = FakeDependencies::new();
+
+        let matcher = SizeMatcher::new_posix(ComparableValue::EqualTo(1), "").unwrap();
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        let matcher = SizeMatcher::new_posix(ComparableValue::EqualTo(512), "c").unwrap();
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        for suffix in &["b", "w", "k", "M", "G"] {
+            let e = SizeMatcher::new_posix(ComparableValue::EqualTo(1), suffix).err().unwrap();
+            assert!(e.to_string().contains("POSIX mode"), "bad description: {}", e);
+        }
+    }
+
     #[test]
     fn size_matcher() {
         let file_info = get_dir_entry_for("./test_data/size", "512bytes");
@@ -166,4 +224,20 @@ mod tests {
         assert!(equal_to_1_blocks.matches(&file_info, &mut deps.new_matcher_io()),
                 "512-byte file should match size of 1 block");
     }
+
+    #[test]
+    fn size_matcher_fake_size() {
+        let file_info = get_dir_entry_for("./test_data/size", "512bytes");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              len: 3 * 1024 * 1024,
+                              ..FileMetadata::default()
+                          });
+
+        let matcher = SizeMatcher::new(ComparableValue::EqualTo(3), "M").unwrap();
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        let matcher = SizeMatcher::new(ComparableValue::LessThan(3072), "k").unwrap();
+        assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()));
+    }
 }
@@ -0,0 +1,128 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! How often each predicate was evaluated and how often it matched, for
+//! "-D rates". Rather than every matcher keeping its own counts, the builder
+//! wraps each predicate's matcher in a `StatsWrapper` when they're wanted.
+
+use std::cell::Cell;
+use std::fmt;
+use std::path::PathBuf;
+
+use find::matchers::{Matcher, MatcherIO, WalkEntry};
+
+/// Counts the evaluations and matches of the matcher it wraps, and reports
+/// them (to the error output, in expression order) when find has finished.
+pub struct StatsWrapper {
+    /// The predicate and its arguments, as they were given.
+    label: String,
+    matcher: Box<Matcher>,
+    evaluations: Cell<u64>,
+    matches: Cell<u64>,
+}
+
+impl StatsWrapper {
+    pub fn new(label: &str, matcher: Box<Matcher>) -> StatsWrapper {
+        StatsWrapper {
+            label: label.to_string(),
+            matcher: matcher,
+            evaluations: Cell::new(0),
+            matches: Cell::new(0),
+        }
+    }
+
+    pub fn new_box(label: &str, matcher: Box<Matcher>) -> Box<Matcher> {
+        Box::new(StatsWrapper::new(label, matcher))
+    }
+
+    /// The line reported for the wrapped matcher, e.g. "-name *.c: 10234
+    /// evaluations, 412 matches (4.0%)".
+    fn summary(&self) -> String {
+        let evaluations = self.evaluations.get();
+        let matches = self.matches.get();
+        let percentage = if evaluations == 0 {
+            0.0
+        } else {
+            matches as f64 * 100.0 / evaluations as f64
+        };
+        format!("{}: {} evaluations, {} matches ({:.1}%)",
+                self.label,
+                evaluations,
+                matches,
+                percentage)
+    }
+}
+
+impl Matcher for StatsWrapper {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let matched = self.matcher.matches(file_info, matcher_io);
+        self.evaluations.set(self.evaluations.get() + 1);
+        if matched {
+            self.matches.set(self.matches.get() + 1);
+        }
+        matched
+    }
+
+    fn has_side_effects(&self) -> bool {
+        self.matcher.has_side_effects()
+    }
+
+    fn affects_walk(&self) -> bool {
+        self.matcher.affects_walk()
+    }
+
+    // There's deliberately no prefilter: entries it skipped wouldn't be
+    // counted, and the rates are meant to be those of the whole expression.
+
+    fn finished_dir(&self, finished_directory: &PathBuf) {
+        self.matcher.finished_dir(finished_directory);
+    }
+
+    fn finished(&self, matcher_io: &mut MatcherIO) {
+        self.matcher.finished(matcher_io);
+        writeln!(matcher_io.deps.get_error_output().borrow_mut(), "{}", self.summary()).unwrap();
+    }
+
+    // the counts aren't part of the expression
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.matcher.describe(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use find::matchers::Matcher;
+    use find::matchers::name::NameMatcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    #[test]
+    fn counts_evaluations_and_matches() {
+        let deps = FakeDependencies::new();
+        let wrapper = StatsWrapper::new("-name a*", NameMatcher::new_box("a*", false).unwrap());
+        let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+        let subdir = get_dir_entry_for("test_data/simple", "subdir");
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(wrapper.matches(&abbbc, &mut matcher_io));
+        assert!(!wrapper.matches(&subdir, &mut matcher_io));
+        assert!(!wrapper.matches(&subdir, &mut matcher_io));
+        assert!(wrapper.prefilter().is_none());
+
+        wrapper.finished(&mut matcher_io);
+        assert_eq!(deps.get_error_output_as_string(),
+                   "-name a*: 3 evaluations, 1 matches (33.3%)\n");
+    }
+
+    #[test]
+    fn never_evaluated() {
+        let deps = FakeDependencies::new();
+        let wrapper = StatsWrapper::new("-name a*", NameMatcher::new_box("a*", false).unwrap());
+        wrapper.finished(&mut deps.new_matcher_io());
+        assert_eq!(deps.get_error_output_as_string(),
+                   "-name a*: 0 evaluations, 0 matches (0.0%)\n");
+    }
+}
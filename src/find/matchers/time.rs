@@ -4,36 +4,85 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
-use std;
 use std::error::Error;
-use std::fs::{self, Metadata};
-use std::io::{stderr, Write};
-use std::time::SystemTime;
-use walkdir::DirEntry;
+use std::fmt;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use find::matchers::{ComparableValue, Matcher, MatcherIO};
+use find::matchers::ls::seconds_since_epoch;
+use find::matchers::{reference_metadata, report_io_error, ComparableValue, Matcher, MatcherIO,
+                     WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
+use find::metadata::FileMetadata;
+use find::time_zone::TimeZone;
 
 const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
 
-/// This matcher checks whether a file is newer than the file the matcher is initialized with.
+/// Midnight (in the given time zone) at the end of the day the given time
+/// falls on, which is when -daystart measures ages from: a file modified at
+/// any time today is then 0 days old.
+pub fn start_of_next_day(time: SystemTime, zone: &TimeZone) -> SystemTime {
+    let seconds = seconds_since_epoch(time);
+    let time = zone.broken_down(seconds);
+    let seconds_into_day = time.hour as i64 * 3600 + time.minute as i64 * 60 +
+                           time.second as i64;
+    let start = seconds - seconds_into_day + SECONDS_PER_DAY;
+    if start >= 0 {
+        UNIX_EPOCH + Duration::from_secs(start as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs((-start) as u64)
+    }
+}
+
+/// How long before now the given time was, in whole seconds, rounded down.
+/// Times in the future (which are common enough, e.g. from clock skew between
+/// an NFS server and its clients) have negative ages: half a second from now
+/// is -1.
+pub fn age_in_seconds(now: SystemTime, time: SystemTime) -> i64 {
+    match now.duration_since(time) {
+        Ok(age) => age.as_secs() as i64,
+        Err(e) => {
+            let ahead = e.duration();
+            -(ahead.as_secs() as i64) - if ahead.subsec_nanos() > 0 { 1 } else { 0 }
+        }
+    }
+}
+
+/// This matcher checks whether one of a file's times (its modification time
+/// for -newer, or its access time for -anewer) is later than the
+/// modification time of the file the matcher is initialized with.
+#[derive(Clone)]
 pub struct NewerMatcher {
+    file_time_type: FileTimeType,
     given_modification_time: SystemTime,
 }
 
 impl NewerMatcher {
-    pub fn new(path_to_file: &str) -> Result<NewerMatcher, Box<Error>> {
-        let metadata = fs::metadata(path_to_file)?;
-        Ok(NewerMatcher { given_modification_time: metadata.modified()? })
+    /// Reads the given file's modification time. If it's a symbolic link,
+    /// it's followed if follow_links is set (as with -L), and otherwise it's
+    /// the link's own time that's used.
+    pub fn new(file_time_type: FileTimeType,
+               path_to_file: &str,
+               follow_links: bool)
+               -> Result<NewerMatcher, Box<Error>> {
+        let metadata = reference_metadata(path_to_file, follow_links)?;
+        Ok(NewerMatcher {
+            file_time_type: file_time_type,
+            given_modification_time: FileTimeType::Modified.get_file_time(&metadata)?,
+        })
     }
 
-    pub fn new_box(path_to_file: &str) -> Result<Box<Matcher>, Box<Error>> {
-        Ok(Box::new(NewerMatcher::new(path_to_file)?))
+    pub fn new_box(file_time_type: FileTimeType,
+                   path_to_file: &str,
+                   follow_links: bool)
+                   -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(NewerMatcher::new(file_time_type, path_to_file, follow_links)?))
     }
 
     /// Impementation of matches that returns a result, allowing use to use try!
     /// to deal with the errors.
-    fn matches_impl(&self, file_info: &DirEntry) -> Result<bool, Box<Error>> {
-        let this_time = file_info.metadata()?.modified()?;
+    fn matches_impl(&self, file_info: &WalkEntry, matcher_io: &MatcherIO) -> io::Result<bool> {
+        let this_time = self.file_time_type.get_file_time(&matcher_io.metadata(file_info)?)?;
         // duration_since returns an Ok duration if this_time <= given_modification_time
         // and returns an Err (with a duration) otherwise. So if this_time >
         // given_modification_time (in which case we want to return true) then
@@ -43,86 +92,109 @@ impl NewerMatcher {
 }
 
 impl Matcher for NewerMatcher {
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        match self.matches_impl(file_info) {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match self.matches_impl(file_info, matcher_io) {
             Err(e) => {
-                writeln!(&mut stderr(),
-                         "Error getting modification time for {}: {}",
-                         file_info.path().to_string_lossy(),
-                         e)
-                    .unwrap();
+                report_io_error(file_info.path(), "", &e, matcher_io);
                 false
             }
             Ok(t) => t,
         }
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.file_time_type {
+            FileTimeType::Accessed => "anewer",
+            FileTimeType::Changed => "cnewer",
+            FileTimeType::Modified => "newer",
+        };
+        match self.given_modification_time.duration_since(UNIX_EPOCH) {
+            Ok(time) => write!(f, "{}({}.{:09})", name, time.as_secs(), time.subsec_nanos()),
+            Err(_) => write!(f, "{}(before 1970)", name),
+        }
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
+impl ParallelMatcher for NewerMatcher {}
+
 #[derive(Clone, Copy, Debug)]
 pub enum FileTimeType {
     Accessed,
-    Created,
+    /// When the file's status last changed (see `FileMetadata::changed`).
+    Changed,
     Modified,
 }
 
 impl FileTimeType {
-    fn get_file_time(self, metadata: Metadata) -> std::io::Result<SystemTime> {
-        match self {
-            FileTimeType::Accessed => metadata.accessed(),
-            FileTimeType::Created => metadata.created(),
-            FileTimeType::Modified => metadata.modified(),
-        }
+    fn get_file_time(self, metadata: &FileMetadata) -> io::Result<SystemTime> {
+        let file_time = match self {
+            FileTimeType::Accessed => metadata.accessed,
+            FileTimeType::Changed => metadata.changed,
+            FileTimeType::Modified => metadata.modified,
+        };
+        file_time.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Other,
+                           format!("{:?} time is not available on this platform", self))
+        })
     }
 }
 
 /// This matcher checks whether a file's accessed|creation|modification time is
 /// {less than | exactly | more than} N days old.
+#[derive(Clone)]
 pub struct FileTimeMatcher {
     days: ComparableValue,
     file_time_type: FileTimeType,
 }
 
 impl Matcher for FileTimeMatcher {
-    fn matches(&self, file_info: &DirEntry, matcher_io: &mut MatcherIO) -> bool {
-        match self.matches_impl(file_info, matcher_io.now()) {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let now = matcher_io.age_reference_time();
+        match self.matches_impl(file_info, matcher_io, now) {
             Err(e) => {
-                writeln!(&mut stderr(),
-                         "Error getting {:?} time for {}: {}",
-                         self.file_time_type,
-                         file_info.path().to_string_lossy(),
-                         e)
-                    .unwrap();
+                report_io_error(file_info.path(), "", &e, matcher_io);
                 false
             }
             Ok(t) => t,
         }
     }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.file_time_type {
+            FileTimeType::Accessed => "atime",
+            FileTimeType::Changed => "ctime",
+            FileTimeType::Modified => "mtime",
+        };
+        write!(f, "{}({})", name, self.days)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
+impl ParallelMatcher for FileTimeMatcher {}
+
 
 
 impl FileTimeMatcher {
     /// Impementation of matches that returns a result, allowing use to use try!
     /// to deal with the errors.
-    fn matches_impl(&self, file_info: &DirEntry, now: SystemTime) -> Result<bool, Box<Error>> {
-        let this_time = self.file_time_type.get_file_time(file_info.metadata()?)?;
-        let mut is_negative = false;
-        // durations can't be negative. So duration_since returns a duration
-        // wrapped in an error if now < this_time.
-        let age = match now.duration_since(this_time) {
-            Ok(duration) => duration,
-            Err(e) => {
-                is_negative = true;
-                e.duration()
-            }
-        };
-        let age_in_seconds: i64 = age.as_secs() as i64 * if is_negative { -1 } else { 1 };
-        // rust division truncates towards zero (see
-        // https://github.com/rust-lang/rust/blob/master/src/libcore/ops.rs#L580 )
-        // so a simple age_in_seconds / SECONDS_PER_DAY gives the wrong answer
-        // for negative ages: a file whose age is 1 second in the future needs to
-        // count as -1 day old, not 0.
-        let age_in_days = age_in_seconds / SECONDS_PER_DAY + if is_negative { -1 } else { 0 };
+    fn matches_impl(&self,
+                    file_info: &WalkEntry,
+                    matcher_io: &MatcherIO,
+                    now: SystemTime)
+                    -> io::Result<bool> {
+        let this_time = self.file_time_type.get_file_time(&matcher_io.metadata(file_info)?)?;
+        // As with GNU find, a file from the future is less than any number
+        // of days old (even -0), but never exactly or more than some number:
+        // rounding down makes its age -1 days or less, never 0. (Rust's
+        // division truncates towards zero, hence div_euclid.)
+        let age_in_days = age_in_seconds(now, this_time).div_euclid(SECONDS_PER_DAY);
         Ok(self.days.imatches(age_in_days))
     }
 
@@ -145,10 +217,10 @@ mod tests {
     use std::thread;
     use std::time::{Duration, SystemTime};
     use tempdir::TempDir;
-    use walkdir::DirEntry;
-
+    
     use find::matchers::{ComparableValue, Matcher};
     use find::matchers::tests::get_dir_entry_for;
+    use find::metadata::FileMetadata;
     use find::tests::FakeDependencies;
     use super::*;
 
@@ -165,9 +237,15 @@ mod tests {
 
         let new_file = get_dir_entry_for(&temp_dir_path, &new_file_name);
 
-        let matcher_for_new =
-            NewerMatcher::new(&temp_dir.path().join(new_file_name).to_string_lossy()).unwrap();
-        let matcher_for_old = NewerMatcher::new(&old_file.path().to_string_lossy()).unwrap();
+        let matcher_for_new = NewerMatcher::new(FileTimeType::Modified,
+                                                &temp_dir.path()
+                                                    .join(new_file_name)
+                                                    .to_string_lossy(),
+                                                false)
+            .unwrap();
+        let matcher_for_old =
+            NewerMatcher::new(FileTimeType::Modified, &old_file.path().to_string_lossy(), false)
+                .unwrap();
         let deps = FakeDependencies::new();
 
         assert!(!matcher_for_new.matches(&old_file, &mut deps.new_matcher_io()),
@@ -178,12 +256,51 @@ mod tests {
                 "old_file shouldn't be newer than itself");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn newer_matcher_symlink_reference() {
+        use fixtures::{make_file_with_mtime, make_symlink};
+
+        let temp_dir = TempDir::new("newer_matcher_symlink_reference").unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let path = |name: &str| temp_dir.path().join(name);
+        let seconds = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        // the link (made now) is much newer than the file it points to, and
+        // the file being compared with both is in between
+        make_file_with_mtime(temp_dir.path(), "target", seconds(1000000000)).unwrap();
+        make_symlink(temp_dir.path(), "link", "target").unwrap();
+        make_symlink(temp_dir.path(), "dangling", "nowhere").unwrap();
+        make_file_with_mtime(temp_dir.path(), "file", seconds(1200000000)).unwrap();
+        let file = get_dir_entry_for(&dir, "file");
+        let link = path("link").to_string_lossy().to_string();
+        let dangling = path("dangling").to_string_lossy().to_string();
+        let deps = FakeDependencies::new();
+
+        // without -L, it's the link's own time
+        let matcher = NewerMatcher::new(FileTimeType::Modified, &link, false).unwrap();
+        assert!(!matcher.matches(&file, &mut deps.new_matcher_io()));
+        // with it, the target's
+        let matcher = NewerMatcher::new_box(FileTimeType::Modified, &link, true).unwrap();
+        assert_eq!(matcher.to_string(), "newer(1000000000.000000000)");
+        assert!(matcher.matches(&file, &mut deps.new_matcher_io()));
+        let matcher = NewerMatcher::new_box(FileTimeType::Accessed, &link, true).unwrap();
+        assert_eq!(matcher.to_string(), "anewer(1000000000.000000000)");
+        assert!(matcher.matches(&file, &mut deps.new_matcher_io()));
+
+        // a dangling link is fine without -L...
+        let matcher = NewerMatcher::new(FileTimeType::Modified, &dangling, false).unwrap();
+        assert!(!matcher.matches(&file, &mut deps.new_matcher_io()));
+        // ...but not with it
+        let e = NewerMatcher::new(FileTimeType::Modified, &dangling, true).err().unwrap();
+        assert_eq!(e.to_string(), format!("‘{}’: No such file or directory", dangling));
+    }
+
     #[test]
     fn file_time_matcher() {
         // this file should already exist
         let file = get_dir_entry_for("test_data", "simple");
 
-        let files_mtime = file.metadata().unwrap().modified().unwrap();
+        let files_mtime = file.metadata().unwrap().modified.unwrap();
 
         let exactly_one_day_matcher = FileTimeMatcher::new(FileTimeType::Modified,
                                                            ComparableValue::EqualTo(1));
@@ -195,7 +312,7 @@ mod tests {
                                                     ComparableValue::EqualTo(0));
 
         // set "now" to 2 days after the file was modified.
-        let mut deps = FakeDependencies::new();
+        let deps = FakeDependencies::new();
         deps.set_time(files_mtime + Duration::new(2 * super::SECONDS_PER_DAY as u64, 0));
         assert!(!exactly_one_day_matcher.matches(&file, &mut deps.new_matcher_io()),
                 "2 day old file shouldn't match exactly 1 day old");
@@ -244,9 +361,174 @@ mod tests {
     }
 
     #[test]
-    fn file_time_matcher_modified_created_accessed() {
+    fn file_time_matcher_fake_times() {
+        let file_info = get_dir_entry_for("test_data", "simple");
+        let now = SystemTime::now();
+        let day = Duration::from_secs(super::SECONDS_PER_DAY as u64);
+
+        let mut deps = FakeDependencies::new();
+        deps.set_time(now);
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              accessed: Some(now - day / 2),
+                              changed: None,
+                              modified: Some(now - day * 10),
+                              ..FileMetadata::default()
+                          });
+
+        let matcher = FileTimeMatcher::new(FileTimeType::Modified, ComparableValue::EqualTo(10));
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        let matcher = FileTimeMatcher::new(FileTimeType::Modified, ComparableValue::LessThan(10));
+        assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        let matcher = FileTimeMatcher::new(FileTimeType::Accessed, ComparableValue::EqualTo(0));
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        // times that aren't available never match
+        let matcher = FileTimeMatcher::new(FileTimeType::Changed, ComparableValue::MoreThan(0));
+        assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn file_time_matcher_uses_start_time() {
+        let file_info = get_dir_entry_for("test_data", "simple");
+        let now = SystemTime::now();
+        let day = Duration::from_secs(super::SECONDS_PER_DAY as u64);
+
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              modified: Some(now - day / 2),
+                              ..FileMetadata::default()
+                          });
+        deps.set_time(now);
+        let matcher = FileTimeMatcher::new(FileTimeType::Modified, ComparableValue::EqualTo(0));
+        let mut matcher_io = deps.new_matcher_io();
+        assert!(matcher.matches(&file_info, &mut matcher_io));
+        // the clock moving on (here, past the file's first birthday) makes no
+        // difference to a run that's already started
+        deps.set_time(now + day);
+        assert!(matcher.matches(&file_info, &mut matcher_io));
+        assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn file_time_matcher_day_start() {
+        let file_info = get_dir_entry_for("test_data", "simple");
+        let hour = Duration::from_secs(60 * 60);
+        // a summer's day, far from any daylight saving changes
+        let local = TimeZone::Local;
+        let midnight = start_of_next_day(UNIX_EPOCH + Duration::from_secs(1500000000), &local);
+        assert_eq!(start_of_next_day(midnight - Duration::from_secs(1), &local), midnight);
+        assert_eq!(start_of_next_day(midnight, &local), midnight + hour * 24);
+        // 1500000000 is 02:40 on the 14th of July 2017 in UTC, which is
+        // 18:40 on the 13th in PST8
+        let utc_midnight = UNIX_EPOCH + Duration::from_secs(1500000000 + 86400 - 9600);
+        let time = UNIX_EPOCH + Duration::from_secs(1500000000);
+        assert_eq!(start_of_next_day(time, &TimeZone::utc()), utc_midnight);
+        let pst = TimeZone::from_tz(Some(::std::ffi::OsStr::new("PST8")));
+        assert_eq!(start_of_next_day(time, &pst), utc_midnight - hour * 16);
+
+        // modified late yesterday evening, and it's now midday
+        let mut deps = FakeDependencies::new();
+        deps.set_time(midnight - hour * 12);
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              modified: Some(midnight - hour * 25),
+                              ..FileMetadata::default()
+                          });
+        let matcher = FileTimeMatcher::new(FileTimeType::Modified, ComparableValue::EqualTo(0));
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        let mut matcher_io = deps.new_matcher_io();
+        matcher_io.set_day_start(true);
+        assert!(!matcher.matches(&file_info, &mut matcher_io));
+        let matcher = FileTimeMatcher::new(FileTimeType::Modified, ComparableValue::EqualTo(1));
+        assert!(matcher.matches(&file_info, &mut matcher_io));
+    }
+
+    #[test]
+    fn ages() {
+        let now = SystemTime::now();
+        let second = Duration::from_secs(1);
+        let half_second = Duration::from_millis(500);
+        assert_eq!(age_in_seconds(now, now), 0);
+        assert_eq!(age_in_seconds(now, now - second), 1);
+        assert_eq!(age_in_seconds(now, now - half_second), 0);
+        assert_eq!(age_in_seconds(now, now + half_second), -1);
+        assert_eq!(age_in_seconds(now, now + second), -1);
+        assert_eq!(age_in_seconds(now, now + second + half_second), -2);
+    }
+
+    #[test]
+    fn file_time_matcher_future_times() {
+        let file_info = get_dir_entry_for("test_data", "simple");
+        let now = SystemTime::now();
+        let day = Duration::from_secs(super::SECONDS_PER_DAY as u64);
+        let mut deps = FakeDependencies::new();
+        deps.set_time(now);
+
+        // the same as GNU find: ahead of the clock by any amount, a file is
+        // less than any number of days old, but nothing else
+        for &ahead in &[Duration::from_millis(1), Duration::from_secs(30), day, day * 2] {
+            deps.set_metadata(file_info.path(),
+                              FileMetadata {
+                                  modified: Some(now + ahead),
+                                  ..FileMetadata::default()
+                              });
+            for (days, expected) in vec![(ComparableValue::EqualTo(0), false),
+                                         (ComparableValue::EqualTo(1), false),
+                                         (ComparableValue::LessThan(0), true),
+                                         (ComparableValue::LessThan(1), true),
+                                         (ComparableValue::LessThan(3), true),
+                                         (ComparableValue::MoreThan(0), false)] {
+                let matcher = FileTimeMatcher::new(FileTimeType::Modified, days);
+                assert_eq!(matcher.matches(&file_info, &mut deps.new_matcher_io()),
+                           expected,
+                           "{:?} ahead, {}",
+                           ahead,
+                           matcher.days);
+            }
+        }
+
+        // -newer just compares the times, so the clock doesn't come into it
+        let matcher =
+            NewerMatcher::new(FileTimeType::Modified, &file_info.path().to_string_lossy(), false)
+                .unwrap();
+        let modified = file_info.metadata().unwrap().modified.unwrap();
+        deps.set_time(modified - day);
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              modified: Some(modified + Duration::from_secs(1)),
+                              ..FileMetadata::default()
+                          });
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              modified: Some(modified),
+                              ..FileMetadata::default()
+                          });
+        assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn newer_matcher_fake_times() {
+        let file_info = get_dir_entry_for("test_data", "simple");
+        let matcher =
+            NewerMatcher::new(FileTimeType::Modified, &file_info.path().to_string_lossy(), false)
+                .unwrap();
+        let modified = file_info.metadata().unwrap().modified.unwrap();
+
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              modified: Some(modified + Duration::from_secs(1)),
+                              ..FileMetadata::default()
+                          });
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn file_time_matcher_modified_changed_accessed() {
 
-        let temp_dir = TempDir::new("file_time_matcher_modified_created_accessed").unwrap();
+        let temp_dir = TempDir::new("file_time_matcher_modified_changed_accessed").unwrap();
 
         // No easy way to independently set file times. So create it - setting creation time
         let foo_path = temp_dir.path().join("foo");
@@ -279,36 +561,36 @@ mod tests {
             let _ = f.read(&mut buffer);
         }
 
-        // OK our modification time and creation time should definitely be different
+        // OK our modification time and change time should definitely be different
         // and depending on our platform and file system, our accessed time migh be
         // different too.
 
         let file_info = get_dir_entry_for(&temp_dir.path().to_string_lossy(), "foo");
         let metadata = file_info.metadata().unwrap();
 
-        // metadata can return errors like StringError("creation time is not available on this platform currently")
-        // so skip tests that won't pass due to shortcomings in std:;fs.
-        if let Ok(accessed_time) = metadata.accessed() {
+        // some times aren't available on some platforms, so skip tests that
+        // won't pass due to shortcomings in std::fs.
+        if let Some(accessed_time) = metadata.accessed {
             test_matcher_for_file_time_type(&file_info, accessed_time, FileTimeType::Accessed);
         }
 
-        if let Ok(creation_time) = metadata.created() {
-            test_matcher_for_file_time_type(&file_info, creation_time, FileTimeType::Created);
+        if let Some(changed_time) = metadata.changed {
+            test_matcher_for_file_time_type(&file_info, changed_time, FileTimeType::Changed);
         }
 
-        if let Ok(modified_time) = metadata.modified() {
+        if let Some(modified_time) = metadata.modified {
             test_matcher_for_file_time_type(&file_info, modified_time, FileTimeType::Modified);
         }
     }
 
-    /// helper function for file_time_matcher_modified_created_accessed
-    fn test_matcher_for_file_time_type(file_info: &DirEntry,
+    /// helper function for file_time_matcher_modified_changed_accessed
+    fn test_matcher_for_file_time_type(file_info: &WalkEntry,
                                        file_time: SystemTime,
                                        file_time_type: FileTimeType) {
         {
             let matcher = FileTimeMatcher::new(file_time_type, ComparableValue::EqualTo(0));
 
-            let mut deps = FakeDependencies::new();
+            let deps = FakeDependencies::new();
             deps.set_time(file_time);
             assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()),
                     "{:?} time matcher should match",
@@ -5,45 +5,110 @@
 // https://opensource.org/licenses/MIT.
 
 use std::error::Error;
-use std::fs::FileType;
-use walkdir::DirEntry;
+use std::fmt;
 
-use find::matchers::{Matcher, MatcherIO};
+use find::matchers::{report_io_error, Matcher, MatcherIO, Prefilter, WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
+use find::metadata::FileKind;
 
-/// This matcher checks the type of the file.
+/// This matcher checks the type of the file. The type comes from the walk, so
+/// it's the type of a symbolic link itself, unless links are followed for
+/// the entry (see `MatcherIO::follows_symlinks`). On Windows,
+/// symbolic links to files and to directories, and junctions, are all
+/// symbolic links (not files or directories), as they're all reparse points
+/// that stand for some other path.
+///
+/// When the walk doesn't know an entry's type (see `WalkEntry::type_hint`),
+/// or with --stat-types (for file systems that say the wrong thing), the
+/// type comes from the file's metadata instead, which costs a system call.
+#[derive(Clone)]
 pub struct TypeMatcher {
-    file_type_fn: fn(&FileType) -> bool,
+    kind: FileKind,
+    type_char: char,
+    stat_types: bool,
 }
 
 impl TypeMatcher {
-    pub fn new(type_string: &str) -> Result<TypeMatcher, Box<Error>> {
-        let function = match type_string {
-            "f" => FileType::is_file,
-            "d" => FileType::is_dir,
-            "b" | "c" | "p" | "l" | "s" | "D" => {
+    pub fn new(type_string: &str, stat_types: bool) -> Result<TypeMatcher, Box<Error>> {
+        let kind = match type_string {
+            "f" => FileKind::File,
+            "d" => FileKind::Dir,
+            "l" => FileKind::Symlink,
+            "p" => FileKind::Fifo,
+            "s" => FileKind::Socket,
+            "b" => FileKind::BlockDevice,
+            "c" => FileKind::CharDevice,
+            "D" => {
                 return Err(From::from(format!("Type argument {} not supported yet", type_string)))
             }
             _ => return Err(From::from(format!("Unrecognised type argument {}", type_string))),
         };
-        Ok(TypeMatcher { file_type_fn: function })
+        Ok(TypeMatcher {
+            kind: kind,
+            // the match above only accepts single characters
+            type_char: type_string.chars().next().unwrap(),
+            stat_types: stat_types,
+        })
     }
 
-    pub fn new_box(type_string: &str) -> Result<Box<Matcher>, Box<Error>> {
-        Ok(Box::new(TypeMatcher::new(type_string)?))
+    pub fn new_box(type_string: &str, stat_types: bool) -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(TypeMatcher::new(type_string, stat_types)?))
     }
 }
 
 impl Matcher for TypeMatcher {
-    fn matches(&self, file_info: &DirEntry, _: &mut MatcherIO) -> bool {
-        (self.file_type_fn)(&file_info.file_type())
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        let type_hint = match file_info.type_hint() {
+            _ if self.stat_types => None,
+            // a link that stands for what it points to, though the walk
+            // didn't follow it (as with a start point with -H)
+            Some(FileKind::Symlink) if matcher_io.follows_symlinks(file_info) => None,
+            type_hint => type_hint,
+        };
+        if let Some(kind) = type_hint {
+            return kind == self.kind;
+        }
+        match matcher_io.metadata(file_info) {
+            Ok(metadata) => metadata.kind == self.kind,
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                false
+            }
+        }
+    }
+
+    fn prefilter(&self) -> Option<Prefilter> {
+        if self.stat_types {
+            return None;
+        }
+        // entries of unknown type have to be looked at properly, as do start
+        // points that are links (which may be followed)
+        let kind = self.kind;
+        Some(Prefilter::new(move |file_info| {
+            file_info.type_hint().map_or(true, |hint| {
+                hint == kind || (hint == FileKind::Symlink && file_info.depth() == 0)
+            })
+        }))
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "type({})", self.type_char)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
     }
 }
+
+impl ParallelMatcher for TypeMatcher {}
 #[cfg(test)]
 
 mod tests {
     use find::matchers::Matcher;
     use find::matchers::tests::get_dir_entry_for;
+    use find::metadata::FileMetadata;
     use find::tests::FakeDependencies;
+    use fixtures::{make_fifo, make_socket, make_symlink};
     use super::*;
 
     #[test]
@@ -52,7 +117,7 @@ mod tests {
         let dir = get_dir_entry_for("test_data", "simple");
         let deps = FakeDependencies::new();
 
-        let matcher = TypeMatcher::new(&"f".to_string()).unwrap();
+        let matcher = TypeMatcher::new("f", false).unwrap();
         assert!(!matcher.matches(&dir, &mut deps.new_matcher_io()));
         assert!(matcher.matches(&file, &mut deps.new_matcher_io()));
     }
@@ -63,15 +128,177 @@ mod tests {
         let dir = get_dir_entry_for("test_data", "simple");
         let deps = FakeDependencies::new();
 
-        let matcher = TypeMatcher::new(&"d".to_string()).unwrap();
+        let matcher = TypeMatcher::new("d", false).unwrap();
         assert!(matcher.matches(&dir, &mut deps.new_matcher_io()));
         assert!(!matcher.matches(&file, &mut deps.new_matcher_io()));
     }
 
+    #[test]
+    fn link_type_matcher() {
+        use std::fs;
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("link_type_matcher").unwrap();
+        fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        if make_symlink(temp_dir.path(), "link", "dir").is_none() {
+            return;
+        }
+        let temp_dir_path = temp_dir.path().to_string_lossy();
+        let dir = get_dir_entry_for(&temp_dir_path, "dir");
+        let link = get_dir_entry_for(&temp_dir_path, "link");
+        let deps = FakeDependencies::new();
+
+        let matcher = TypeMatcher::new("l", false).unwrap();
+        assert!(matcher.matches(&link, &mut deps.new_matcher_io()));
+        assert!(!matcher.matches(&dir, &mut deps.new_matcher_io()));
+        // a link to a directory isn't a directory
+        let matcher = TypeMatcher::new("d", false).unwrap();
+        assert!(!matcher.matches(&link, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn type_of_followed_start_points() {
+        use std::fs;
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("type_of_followed_start_points").unwrap();
+        fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        let link = match make_symlink(temp_dir.path(), "link", "dir") {
+            Some(link) => link,
+            None => return,
+        };
+        let link = link.to_string_lossy();
+        // the same link as a start point, and below one
+        let start_point = get_dir_entry_for(&link, "link");
+        let below = get_dir_entry_for(&temp_dir.path().to_string_lossy(), "link");
+        assert_eq!((start_point.depth(), below.depth()), (0, 1));
+        let deps = FakeDependencies::new();
+        let matches = |type_string: &str, entry: &WalkEntry, follow_start_points: bool| {
+            let matcher = TypeMatcher::new(type_string, false).unwrap();
+            let passes_prefilter =
+                matcher.prefilter().map_or(true, |prefilter| prefilter.passes(entry));
+            let mut matcher_io = deps.new_matcher_io();
+            matcher_io.set_follow_start_points(follow_start_points);
+            passes_prefilter && matcher.matches(entry, &mut matcher_io)
+        };
+
+        // with -H, a start point is what it points to
+        assert!(matches("d", &start_point, true));
+        assert!(!matches("l", &start_point, true));
+        // but a link below one is still a link
+        assert!(matches("l", &below, true));
+        assert!(!matches("d", &below, true));
+        // and without it, they're both links
+        assert!(matches("l", &start_point, false));
+        assert!(!matches("d", &start_point, false));
+    }
+
+    #[test]
+    fn special_type_matchers() {
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("special_type_matchers").unwrap();
+        let fifo = match make_fifo(temp_dir.path(), "fifo") {
+            Some(fifo) => fifo,
+            None => return,
+        };
+        let socket = match make_socket(temp_dir.path(), "socket") {
+            Some(socket) => socket,
+            None => return,
+        };
+        let entry = |path: &::std::path::Path| {
+            get_dir_entry_for(&temp_dir.path().to_string_lossy(),
+                              &path.file_name().unwrap().to_string_lossy())
+        };
+        let fifo = entry(&fifo);
+        let socket = entry(&socket);
+        let file = get_dir_entry_for("test_data/simple", "abbbc");
+        let deps = FakeDependencies::new();
+        let matches = |type_string: &str, entry: &WalkEntry| {
+            TypeMatcher::new(type_string, false).unwrap().matches(entry, &mut deps.new_matcher_io())
+        };
+
+        assert!(matches("p", &fifo));
+        assert!(!matches("p", &socket));
+        assert!(!matches("p", &file));
+        assert!(matches("s", &socket));
+        assert!(!matches("s", &fifo));
+        assert!(!matches("f", &fifo));
+        assert!(!matches("f", &socket));
+        assert!(!matches("b", &fifo));
+        assert!(!matches("c", &socket));
+
+        // when the walk doesn't say, they're what their metadata says
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(fifo.path(),
+                          FileMetadata { kind: FileKind::CharDevice, ..FileMetadata::default() });
+        let unknown = entry(fifo.path()).with_type_hint(None);
+        let matcher = TypeMatcher::new("c", false).unwrap();
+        assert!(matcher.matches(&unknown, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn type_hints() {
+        let deps = FakeDependencies::new();
+        let file = || get_dir_entry_for("test_data/simple", "abbbc");
+        let matches = |type_string: &str, stat_types: bool, entry: &WalkEntry| {
+            let matcher = TypeMatcher::new(type_string, stat_types).unwrap();
+            let passes_prefilter =
+                matcher.prefilter().map_or(true, |prefilter| prefilter.passes(entry));
+            let matches = matcher.matches(entry, &mut deps.new_matcher_io());
+            // the prefilter may let through what doesn't match, but not the
+            // other way round
+            assert!(passes_prefilter || !matches);
+            matches
+        };
+
+        // a known type is trusted, unless --stat-types says not to
+        let known = file().with_type_hint(Some(FileKind::File));
+        let unknown = file().with_type_hint(None);
+        let lying = file().with_type_hint(Some(FileKind::Dir));
+        assert!(matches("f", false, &known));
+        assert!(!matches("d", false, &known));
+        assert!(matches("d", false, &lying));
+        assert!(!matches("f", false, &lying));
+        for entry in &[&known, &unknown, &lying] {
+            assert!(matches("f", true, entry));
+            assert!(!matches("d", true, entry));
+        }
+        // and an unknown one is looked up
+        assert!(matches("f", false, &unknown));
+        assert!(!matches("d", false, &unknown));
+        assert!(TypeMatcher::new("f", false).unwrap().prefilter().unwrap().passes(&unknown));
+        assert!(TypeMatcher::new("f", true).unwrap().prefilter().is_none());
+    }
+
+    #[test]
+    fn type_lookup_uses_metadata() {
+        let mut deps = FakeDependencies::new();
+        let dir = get_dir_entry_for("test_data", "simple");
+        deps.set_metadata(dir.path(),
+                          FileMetadata { kind: FileKind::Symlink, ..FileMetadata::default() });
+        let matches = |type_string: &str, entry: &WalkEntry| {
+            TypeMatcher::new(type_string, true).unwrap().matches(entry, &mut deps.new_matcher_io())
+        };
+        assert!(matches("l", &dir));
+        assert!(!matches("d", &dir));
+
+        // if it can't be looked up, it doesn't match (and find says why)
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata_error(dir.path(), 13);
+        let mut matcher_io = deps.new_matcher_io();
+        let unknown = get_dir_entry_for("test_data", "simple").with_type_hint(None);
+        assert!(!TypeMatcher::new("d", false).unwrap().matches(&unknown, &mut matcher_io));
+        assert_eq!(matcher_io.exit_code(), 1);
+        assert!(deps.get_error_output_as_string().contains("simple"));
+    }
+
     #[test]
     fn cant_create_with_invalid_pattern() {
-        let result = TypeMatcher::new(&"xxx".to_string());
+        let result = TypeMatcher::new("xxx", false);
         assert!(result.is_err());
+        // doors are Solaris's, and we don't know about them
+        assert!(TypeMatcher::new("D", false).is_err());
     }
 
 }
@@ -0,0 +1,165 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+use std::error::Error;
+use std::fmt;
+
+use find::matchers::{report_io_error, Matcher, MatcherIO, WalkEntry};
+use find::matchers::parallel::ParallelMatcher;
+use find::users;
+
+/// Works out which id a -user or -group argument refers to. As with GNU find,
+/// names take priority, but anything that looks like a number is accepted
+/// even if there's no user (or group) with that id.
+fn parse_id(arg: &str, lookup: fn(&str) -> Option<u32>) -> Option<u32> {
+    lookup(arg).or_else(|| arg.parse::<u32>().ok())
+}
+
+/// This matcher checks whether a file is owned by a given user.
+#[derive(Clone)]
+pub struct UserMatcher {
+    uid: u32,
+}
+
+impl UserMatcher {
+    pub fn new(user: &str) -> Result<UserMatcher, Box<Error>> {
+        match parse_id(user, users::uid_for_name) {
+            Some(uid) => Ok(UserMatcher { uid: uid }),
+            None => Err(From::from(format!("'{}' is not the name of a known user", user))),
+        }
+    }
+
+    pub fn new_box(user: &str) -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(UserMatcher::new(user)?))
+    }
+}
+
+impl Matcher for UserMatcher {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match matcher_io.metadata(file_info) {
+            Ok(metadata) => metadata.uid == self.uid,
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                false
+            }
+        }
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "user({})", self.uid)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl ParallelMatcher for UserMatcher {}
+
+/// This matcher checks whether a file belongs to a given group.
+#[derive(Clone)]
+pub struct GroupMatcher {
+    gid: u32,
+}
+
+impl GroupMatcher {
+    pub fn new(group: &str) -> Result<GroupMatcher, Box<Error>> {
+        match parse_id(group, users::gid_for_name) {
+            Some(gid) => Ok(GroupMatcher { gid: gid }),
+            None => Err(From::from(format!("'{}' is not the name of an existing group", group))),
+        }
+    }
+
+    pub fn new_box(group: &str) -> Result<Box<Matcher>, Box<Error>> {
+        Ok(Box::new(GroupMatcher::new(group)?))
+    }
+}
+
+impl Matcher for GroupMatcher {
+    fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+        match matcher_io.metadata(file_info) {
+            Ok(metadata) => metadata.gid == self.gid,
+            Err(e) => {
+                report_io_error(file_info.path(), "", &e, matcher_io);
+                false
+            }
+        }
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "group({})", self.gid)
+    }
+
+    fn parallel(&self) -> Option<Box<ParallelMatcher>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl ParallelMatcher for GroupMatcher {}
+
+#[cfg(test)]
+mod tests {
+    use find::matchers::Matcher;
+    use find::matchers::tests::get_dir_entry_for;
+    use find::metadata::FileMetadata;
+    use find::tests::FakeDependencies;
+    use super::*;
+
+    #[test]
+    fn user_matcher() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              uid: 1234,
+                              gid: 5678,
+                              ..FileMetadata::default()
+                          });
+
+        let matcher = UserMatcher::new("1234").unwrap();
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        let matcher = UserMatcher::new("5678").unwrap();
+        assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn group_matcher() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(),
+                          FileMetadata {
+                              uid: 1234,
+                              gid: 5678,
+                              ..FileMetadata::default()
+                          });
+
+        let matcher = GroupMatcher::new("5678").unwrap();
+        assert!(matcher.matches(&file_info, &mut deps.new_matcher_io()));
+        let matcher = GroupMatcher::new("1234").unwrap();
+        assert!(!matcher.matches(&file_info, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn user_and_group_names() {
+        let file_info = get_dir_entry_for("test_data/simple", "abbbc");
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(file_info.path(), FileMetadata::default());
+
+        assert!(UserMatcher::new("root").unwrap().matches(&file_info, &mut deps.new_matcher_io()));
+    }
+
+    #[test]
+    fn unknown_names() {
+        if let Err(e) = UserMatcher::new("no-such-user-findutils") {
+            assert_eq!(e.to_string(),
+                       "'no-such-user-findutils' is not the name of a known user");
+        } else {
+            panic!("-user with an unknown user should fail");
+        }
+        assert!(GroupMatcher::new("no-such-group-findutils").is_err());
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::{self, Path, PathBuf};
+use std::rc::Rc;
+use walkdir::DirEntry;
+
+use find::dir_walk::{self, Dir};
+use find::metadata::{FileKind, FileMetadata};
+
+/// A file that find's walk has come to, as passed to matchers: its path,
+/// type etc., along with where it is relative to the starting point it was
+/// found under, and the directory it's in (which the walk has open, so that
+/// its metadata can be looked up however long its path is). Making one from
+/// the walk's entry doesn't allocate (the starting point is shared by all
+/// the entries under it), and matchers only ever borrow it, so find's own
+/// work per entry stays off the heap (see tests/find_alloc_tests.rs).
+pub struct WalkEntry {
+    path: PathBuf,
+    /// None for a starting point, or a copy of an entry made on another
+    /// thread (see `DetachedEntry`), which go by their paths instead.
+    dir: Option<Rc<Dir>>,
+    file_type: FileKind,
+    path_is_symlink: bool,
+    depth: usize,
+    starting_point: Rc<Path>,
+    type_hint: Option<FileKind>,
+}
+
+/// What there is to know about a `WalkEntry` apart from its starting point
+/// and directory, which (unlike the entry itself) can be sent to another
+/// thread, to make the same entry there (see `parallel`).
+pub struct DetachedEntry {
+    path: PathBuf,
+    file_type: FileKind,
+    path_is_symlink: bool,
+    depth: usize,
+    type_hint: Option<FileKind>,
+}
+
+impl DetachedEntry {
+    /// Makes the entry again, under the same starting point as before.
+    pub fn attach(self, starting_point: Rc<Path>) -> WalkEntry {
+        WalkEntry {
+            path: self.path,
+            dir: None,
+            file_type: self.file_type,
+            path_is_symlink: self.path_is_symlink,
+            depth: self.depth,
+            starting_point: starting_point,
+            type_hint: self.type_hint,
+        }
+    }
+}
+
+impl WalkEntry {
+    /// Wraps an entry from walking the given starting point (which should be
+    /// exactly what the walk was given, so that it's a prefix of the entry's
+    /// path).
+    pub fn from_dir_walk(entry: dir_walk::Entry, starting_point: Rc<Path>) -> WalkEntry {
+        WalkEntry {
+            path: entry.path,
+            dir: entry.dir,
+            file_type: entry.file_type,
+            path_is_symlink: entry.path_is_symlink,
+            depth: entry.depth,
+            starting_point: starting_point,
+            type_hint: Some(entry.file_type),
+        }
+    }
+
+    /// Wraps an entry from walkdir (as tests, and anything else that walks
+    /// directories its own way, can make).
+    pub fn new(entry: DirEntry, starting_point: Rc<Path>) -> WalkEntry {
+        let file_type = FileKind::from_file_type(&entry.file_type());
+        WalkEntry {
+            path: entry.path().to_path_buf(),
+            dir: None,
+            file_type: file_type,
+            path_is_symlink: entry.path_is_symlink(),
+            depth: entry.depth(),
+            starting_point: starting_point,
+            type_hint: Some(file_type),
+        }
+    }
+
+    /// The entry's path: its start point, with the names of the directories
+    /// it's in and its own name joined on.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The entry's file name (see `name` for what -name goes by).
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or_else(|| self.path.as_os_str())
+    }
+
+    /// The entry's type. With -L, that's the type of what a link points to,
+    /// unless it doesn't point to anything, and with -H, the same goes for
+    /// starting points.
+    pub fn file_type(&self) -> FileKind {
+        self.file_type
+    }
+
+    /// Whether the entry's path is a symbolic link (even if it was followed).
+    pub fn path_is_symlink(&self) -> bool {
+        self.path_is_symlink
+    }
+
+    /// The entry's metadata, straight from the file system (matchers should
+    /// use `MatcherIO::metadata`, which can be faked in tests). For a link
+    /// that the walk followed, that's the metadata of what it points to.
+    pub fn metadata(&self) -> io::Result<FileMetadata> {
+        self.stat(self.path_is_symlink && !self.file_type.is_symlink())
+    }
+
+    /// Looks up the entry's metadata (or, if follow_links is true and it's a
+    /// symbolic link, that of what it points to) from the file system: in
+    /// the directory the walk found it in, if it has that open, and
+    /// otherwise by its path.
+    pub fn stat(&self, follow_links: bool) -> io::Result<FileMetadata> {
+        dir_walk::stat_in(self.dir.as_ref().map(|dir| &**dir), &self.path, follow_links)
+    }
+
+    /// Reads where the entry points, if it's a symbolic link (in the same
+    /// way as `stat` looks up its metadata).
+    pub fn read_link(&self) -> io::Result<PathBuf> {
+        dir_walk::read_link_in(self.dir.as_ref().map(|dir| &**dir), &self.path)
+    }
+
+    /// Copies what there is to know about the entry, other than its starting
+    /// point, to make the same entry on another thread.
+    pub fn detach(&self) -> DetachedEntry {
+        DetachedEntry {
+            path: self.path.clone(),
+            file_type: self.file_type,
+            path_is_symlink: self.path_is_symlink,
+            depth: self.depth,
+            type_hint: self.type_hint,
+        }
+    }
+
+    /// Replaces what the walk says the entry's type is. None means that it
+    /// doesn't know (as when a directory entry's type is DT_UNKNOWN), and it
+    /// may also be wrong, as some file systems get it wrong.
+    pub fn with_type_hint(mut self, type_hint: Option<FileKind>) -> WalkEntry {
+        self.type_hint = type_hint;
+        self
+    }
+
+    /// What the walk says the entry's type is, if it knows, without asking
+    /// the file system. That's what -type goes by, unless it's None, or
+    /// --stat-types says not to trust it (see `TypeMatcher`). Entries from
+    /// the walk always know, as it looks up the type of a directory entry
+    /// that doesn't say what it is.
+    pub fn type_hint(&self) -> Option<FileKind> {
+        self.type_hint
+    }
+
+    /// How far below its starting point the entry is: 0 for the starting
+    /// point itself, 1 for what's in it, and so on.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The starting point that the entry was found under, as it was given on
+    /// the command line.
+    pub fn starting_point(&self) -> &Path {
+        &self.starting_point
+    }
+
+    /// The entry's name, as -name sees it. That's its file name, except for
+    /// starting points, where it's whatever comes after the last slash
+    /// (ignoring any at the end), as with GNU find: "." for "." and "./",
+    /// "dir" for "dir/", and "/" for "/" (where `Path::file_name` would give
+    /// nothing at all for "." and "/").
+    pub fn name(&self) -> &OsStr {
+        if self.depth > 0 {
+            return self.file_name();
+        }
+        let path = self.path();
+        // Paths that aren't valid unicode are left to `file_name`, which
+        // gets them right unless they end with a slash.
+        let path_str = match path.to_str() {
+            Some(path_str) => path_str,
+            None => return self.file_name(),
+        };
+        let trimmed = path_str.trim_right_matches(path::is_separator);
+        if trimmed.is_empty() {
+            // all slashes (or nothing at all)
+            return OsStr::new(&path_str[..path_str.len().min(1)]);
+        }
+        let start = trimmed.rfind(path::is_separator).map_or(0, |i| i + 1);
+        OsStr::new(&trimmed[start..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::rc::Rc;
+    use walkdir::WalkDir;
+
+    use find::matchers::tests::get_dir_entry_for;
+    use find::tests::fix_up_slashes;
+    use super::*;
+
+    #[test]
+    fn depth_and_starting_point() {
+        let start = get_dir_entry_for("test_data/simple", "simple");
+        assert_eq!(start.depth(), 0);
+        assert_eq!(start.starting_point(), Path::new("test_data/simple"));
+        assert_eq!(start.path(), start.starting_point());
+
+        let file = get_dir_entry_for("test_data/simple", "abbbc");
+        assert_eq!(file.depth(), 1);
+        assert_eq!(file.starting_point(), Path::new("test_data/simple"));
+
+        let nested = get_dir_entry_for("test_data/simple", "ABBBC");
+        assert_eq!(nested.depth(), 2);
+        assert_eq!(nested.path().strip_prefix(nested.starting_point()).unwrap(),
+                   Path::new("subdir/ABBBC"));
+    }
+
+    #[test]
+    fn names_of_starting_points() {
+        let name_of = |start: &str| {
+            let start = fix_up_slashes(start);
+            let entry = WalkDir::new(&start).max_depth(0).into_iter().next().unwrap().unwrap();
+            let entry = WalkEntry::new(entry, Rc::from(Path::new(&start)));
+            entry.name().to_string_lossy().to_string()
+        };
+        assert_eq!(name_of("."), ".");
+        assert_eq!(name_of("./"), ".");
+        assert_eq!(name_of("test_data/simple/"), "simple");
+        assert_eq!(name_of("test_data/simple//"), "simple");
+        assert_eq!(name_of("test_data/simple/."), ".");
+        assert_eq!(name_of("test_data/simple/subdir/.."), "..");
+        if cfg!(unix) {
+            assert_eq!(name_of("/"), "/");
+            assert_eq!(name_of("//"), "/");
+        }
+
+        let file = get_dir_entry_for("test_data/simple/", "abbbc");
+        assert_eq!(file.name(), file.file_name());
+    }
+
+    #[test]
+    fn depth_with_slashes_in_starting_point() {
+        // the depth doesn't depend on how many components the starting point
+        // has, or how they're separated
+        for start in &["test_data//simple/", "./test_data/simple"] {
+            let file = get_dir_entry_for(start, "abbbc");
+            assert_eq!(file.depth(), 1);
+            assert_eq!(file.starting_point(), Path::new(start));
+        }
+    }
+
+    #[test]
+    fn detached_entries_are_the_same() {
+        for name in &["simple", "abbbc", "ABBBC"] {
+            let entry = get_dir_entry_for("test_data/simple", name).with_type_hint(None);
+            let copy = entry.detach().attach(Rc::from(entry.starting_point()));
+            assert_eq!(copy.path(), entry.path());
+            assert_eq!(copy.name(), entry.name());
+            assert_eq!(copy.depth(), entry.depth());
+            assert_eq!(copy.starting_point(), entry.starting_point());
+            assert_eq!(copy.file_type(), entry.file_type());
+            assert_eq!(copy.path_is_symlink(), entry.path_is_symlink());
+            assert_eq!(copy.type_hint(), None);
+            assert_eq!(copy.metadata().unwrap(), entry.metadata().unwrap());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links() {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+        use tempdir::TempDir;
+
+        use find::dir_walk::DirWalk;
+
+        let temp_dir = TempDir::new("walk_entry_links").unwrap();
+        let start: Rc<Path> = Rc::from(temp_dir.path());
+        fs::File::create(temp_dir.path().join("file")).unwrap();
+        symlink("nowhere", temp_dir.path().join("broken")).unwrap();
+        symlink("file", temp_dir.path().join("link")).unwrap();
+        let entries: Vec<WalkEntry> = DirWalk::new(temp_dir.path())
+            .follow_links(true)
+            .sort(true)
+            .min_depth(1)
+            .map(|entry| WalkEntry::from_dir_walk(entry.unwrap(), start.clone()))
+            .collect();
+        let names: Vec<_> = entries.iter().map(|entry| entry.name()).collect();
+        assert_eq!(names, vec!["broken", "file", "link"]);
+
+        // a followed link is what it points to, apart from its path, and a
+        // link to nothing is just a link
+        let (broken, link) = (&entries[0], &entries[2]);
+        assert_eq!((link.file_type(), link.path_is_symlink()), (FileKind::File, true));
+        assert_eq!(link.metadata().unwrap().kind, FileKind::File);
+        assert_eq!(link.stat(false).unwrap().kind, FileKind::Symlink);
+        assert_eq!((broken.file_type(), broken.path_is_symlink()),
+                   (FileKind::Symlink, true));
+        assert_eq!(broken.type_hint(), Some(FileKind::Symlink));
+        assert_eq!(broken.metadata().unwrap().kind, FileKind::Symlink);
+        assert!(broken.stat(true).is_err());
+        assert_eq!(broken.read_link().unwrap(), Path::new("nowhere"));
+        assert_eq!(link.read_link().unwrap(), Path::new("file"));
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! `std::fs::Metadata` can only be obtained by asking the filesystem, which
+//! makes matchers that look at file attributes (times, permissions, owners)
+//! hard to test. So matchers get their metadata via the `Dependencies` trait,
+//! in the form of a plain struct that tests can fill in however they like.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The sorts of file that -type tells apart (so far).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+    /// Anything else (or, on platforms without them, any of the above that
+    /// isn't a file, directory or symbolic link).
+    Other,
+}
+
+impl FileKind {
+    pub fn from_file_type(file_type: &fs::FileType) -> FileKind {
+        if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            special_kind(file_type)
+        }
+    }
+
+    /// The sort of file that a mode (as in stat's st_mode) says it is.
+    #[cfg(unix)]
+    pub fn from_mode(mode: u32) -> FileKind {
+        let file_type = mode & ::libc::S_IFMT as u32;
+        let kinds = [(::libc::S_IFREG, FileKind::File),
+                     (::libc::S_IFDIR, FileKind::Dir),
+                     (::libc::S_IFLNK, FileKind::Symlink),
+                     (::libc::S_IFIFO, FileKind::Fifo),
+                     (::libc::S_IFSOCK, FileKind::Socket),
+                     (::libc::S_IFBLK, FileKind::BlockDevice),
+                     (::libc::S_IFCHR, FileKind::CharDevice)];
+        kinds.iter()
+            .find(|&&(bits, _)| bits as u32 == file_type)
+            .map_or(FileKind::Other, |&(_, kind)| kind)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        *self == FileKind::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        *self == FileKind::File
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        *self == FileKind::Symlink
+    }
+}
+
+#[cfg(unix)]
+fn special_kind(file_type: &fs::FileType) -> FileKind {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        FileKind::Fifo
+    } else if file_type.is_socket() {
+        FileKind::Socket
+    } else if file_type.is_block_device() {
+        FileKind::BlockDevice
+    } else if file_type.is_char_device() {
+        FileKind::CharDevice
+    } else {
+        FileKind::Other
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_: &fs::FileType) -> FileKind {
+    FileKind::Other
+}
+
+impl Default for FileKind {
+    fn default() -> FileKind {
+        FileKind::Other
+    }
+}
+
+/// The parts of a file's metadata that find's matchers care about.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileMetadata {
+    /// What sort of file it is.
+    pub kind: FileKind,
+    /// The size of the file, in bytes.
+    pub len: u64,
+    /// The file's mode (type and permission bits). Always 0 on platforms
+    /// without unix-style permissions.
+    pub mode: u32,
+    /// The owner's user id. Always 0 on platforms without uids.
+    pub uid: u32,
+    /// The owner's group id. Always 0 on platforms without gids.
+    pub gid: u32,
+    /// The id of the device the file is on. Always 0 on platforms that
+    /// don't have them.
+    pub dev: u64,
+    /// The file's inode number. Always 0 on platforms that don't have them.
+    pub ino: u64,
+    /// The number of hard links to the file. Always 0 on platforms that
+    /// don't count them.
+    pub nlink: u64,
+    /// The space allocated to the file, in 512-byte blocks (which may be less
+    /// than its size if it's sparse). Always 0 on platforms that don't say.
+    pub blocks: u64,
+    /// The device that a block or character device file stands for (an
+    /// encoding of its major and minor numbers). Always 0 on platforms that
+    /// don't have them.
+    pub rdev: u64,
+    /// The file's times. None if the platform (or filesystem) doesn't record
+    /// them.
+    pub accessed: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    /// When the file's status (its contents, or its metadata, e.g. its
+    /// permissions) last changed: the "c" of -ctime and %C. None on
+    /// platforms without it.
+    pub changed: Option<SystemTime>,
+}
+
+impl FileMetadata {
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &fs::Metadata) -> FileMetadata {
+        use std::os::unix::fs::MetadataExt;
+        FileMetadata {
+            kind: FileKind::from_file_type(&metadata.file_type()),
+            len: metadata.len(),
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            nlink: metadata.nlink(),
+            blocks: metadata.blocks(),
+            rdev: metadata.rdev(),
+            accessed: metadata.accessed().ok(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+            changed: changed_time(metadata),
+        }
+    }
+
+    /// The metadata from a stat call (e.g. one relative to a directory, as
+    /// find's walk makes: see `find::dir_walk`). It doesn't say when the
+    /// file was created.
+    #[cfg(unix)]
+    pub fn from_stat(stat: &::libc::stat) -> FileMetadata {
+        FileMetadata {
+            kind: FileKind::from_mode(stat.st_mode as u32),
+            len: stat.st_size as u64,
+            mode: stat.st_mode as u32,
+            uid: stat.st_uid as u32,
+            gid: stat.st_gid as u32,
+            dev: stat.st_dev as u64,
+            ino: stat.st_ino as u64,
+            nlink: stat.st_nlink as u64,
+            blocks: stat.st_blocks as u64,
+            rdev: stat.st_rdev as u64,
+            accessed: Some(system_time(stat.st_atime as i64, stat.st_atime_nsec as i64)),
+            created: None,
+            modified: Some(system_time(stat.st_mtime as i64, stat.st_mtime_nsec as i64)),
+            changed: Some(system_time(stat.st_ctime as i64, stat.st_ctime_nsec as i64)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(metadata: &fs::Metadata) -> FileMetadata {
+        FileMetadata {
+            kind: FileKind::from_file_type(&metadata.file_type()),
+            len: metadata.len(),
+            accessed: metadata.accessed().ok(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+            ..FileMetadata::default()
+        }
+    }
+
+    /// The space allocated to the file in blocks of the given size (a
+    /// multiple of 512 bytes), rounded up: 1K blocks for -ls and %k, for
+    /// instance.
+    pub fn blocks_of(&self, block_size: u64) -> u64 {
+        let blocks_per_block = block_size / 512;
+        (self.blocks + blocks_per_block - 1) / blocks_per_block
+    }
+}
+
+/// When the file's status last changed (see `FileMetadata::changed`), which
+/// std doesn't say: its `created` is the time the file was created instead.
+#[cfg(unix)]
+pub fn changed_time(metadata: &fs::Metadata) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    Some(system_time(metadata.ctime(), metadata.ctime_nsec()))
+}
+
+/// The time that a number of seconds and nanoseconds since the epoch (as
+/// stat gives times) stands for.
+#[cfg(unix)]
+fn system_time(seconds: i64, nanoseconds: i64) -> SystemTime {
+    let nanoseconds = Duration::new(0, nanoseconds as u32);
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64) + nanoseconds
+    } else {
+        UNIX_EPOCH - Duration::from_secs((-seconds) as u64) + nanoseconds
+    }
+}
+
+#[cfg(not(unix))]
+pub fn changed_time(_: &fs::Metadata) -> Option<SystemTime> {
+    None
+}
+
+/// Reads the metadata for the given path from the filesystem. If follow_links
+/// is false and the path is a symbolic link, then it's the link's metadata
+/// (rather than its target's) that's returned.
+pub fn read_metadata(path: &Path, follow_links: bool) -> io::Result<FileMetadata> {
+    let metadata = if follow_links {
+        fs::metadata(path)?
+    } else {
+        fs::symlink_metadata(path)?
+    };
+    Ok(FileMetadata::from_metadata(&metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn read_metadata_matches_std() {
+        let path = Path::new("test_data/size/512bytes");
+        let std_metadata = fs::metadata(path).unwrap();
+        let metadata = read_metadata(path, false).unwrap();
+        assert_eq!(metadata.len, 512);
+        assert_eq!(metadata.kind, FileKind::File);
+        assert_eq!(read_metadata(Path::new("test_data"), false).unwrap().kind, FileKind::Dir);
+        assert_eq!(metadata.modified, std_metadata.modified().ok());
+        assert_eq!(metadata.changed, changed_time(&std_metadata));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn changed_time_is_ctime() {
+        use std::os::unix::fs::MetadataExt;
+
+        let std_metadata = fs::metadata("test_data/size/512bytes").unwrap();
+        let changed = changed_time(&std_metadata).unwrap().duration_since(UNIX_EPOCH).unwrap();
+        assert_eq!((changed.as_secs() as i64, changed.subsec_nanos() as i64),
+                   (std_metadata.ctime(), std_metadata.ctime_nsec()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_stat_matches_from_metadata() {
+        use std::ffi::CString;
+
+        for &path in &["test_data/size/512bytes", "test_data"] {
+            let mut stat: ::libc::stat = unsafe { ::std::mem::zeroed() };
+            let c_path = CString::new(path).unwrap();
+            assert_eq!(unsafe { ::libc::lstat(c_path.as_ptr(), &mut stat) }, 0);
+            let metadata = read_metadata(Path::new(path), false).unwrap();
+            assert_eq!(FileMetadata::from_stat(&stat),
+                       FileMetadata { created: None, ..metadata });
+        }
+        assert_eq!(FileKind::from_mode(0o100644), FileKind::File);
+        assert_eq!(FileKind::from_mode(0o040755), FileKind::Dir);
+        assert_eq!(FileKind::from_mode(0o120777), FileKind::Symlink);
+        assert_eq!(FileKind::from_mode(0o644), FileKind::Other);
+    }
+
+    #[test]
+    fn blocks_of_sizes() {
+        let metadata = FileMetadata { blocks: 9, ..FileMetadata::default() };
+        assert_eq!(metadata.blocks_of(512), 9);
+        assert_eq!(metadata.blocks_of(1024), 5);
+        assert_eq!(metadata.blocks_of(4096), 2);
+        assert_eq!(FileMetadata::default().blocks_of(1024), 0);
+    }
+
+    #[test]
+    fn read_metadata_reports_errors() {
+        assert!(read_metadata(Path::new("test_data/does_not_exist"), true).is_err());
+    }
+}
@@ -4,22 +4,127 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
+pub mod dir_walk;
+pub mod error;
+pub mod fsinfo;
 pub mod matchers;
+pub mod metadata;
+pub mod quoting;
+pub mod time_zone;
+pub mod users;
+pub mod walker;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::error::Error;
-use std::io::{Write, stderr, stdout};
+use std::cmp::{self, Ordering};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write, stderr, stdin, stdout};
+use std::path::Path;
 use std::rc::Rc;
 use std::time::SystemTime;
-use walkdir::WalkDir;
-use walkdir::WalkDirIterator;
 
+use find::error::FindError;
+use find::fsinfo::MountTable;
+use find::matchers::{ColorMode, FormatCache, MatcherRegistry, OutputFiles, RegexType, WalkEntry};
+use find::metadata::FileMetadata;
+use find::users::UserGroupCache;
+use find::walker::{ProgressCallback, Walker};
+
+/// How find searches, as set by the options and global predicates (-L,
+/// -maxdepth, -xdev and so on). Parsing the command line starts from a
+/// config and changes it as it goes, so library users can set up the parts
+/// of the search they care about with `Config::builder()`, and leave the
+/// command line (see `try_find_main_with_config`) to say what to look for.
 pub struct Config {
     depth_first: bool,
     min_depth: usize,
     max_depth: usize,
-    sorted_output: bool,
+    sort_entries: bool,
+    /// Whether symbolic links (and, on Windows, junctions) are followed, as
+    /// with -L: they're treated as what they point to, so the walk goes into
+    /// linked directories.
+    follow_links: bool,
+    /// Whether start points that are symbolic links are followed, though
+    /// nothing below them is, as with -H (-L implies it).
+    follow_start_points: bool,
+    /// Whether -exec and friends may pass file names to Windows batch files,
+    /// which cmd.exe can't be trusted to pass on as they are.
+    allow_batch_exec: bool,
+    /// Whether to stick to what POSIX specifies (as with --posix, or when
+    /// POSIXLY_CORRECT is set), so that scripts behave the same as they
+    /// would with any other find: extensions are rejected (see
+    /// `MatcherRegistry::check_posix`), and -size and -perm arguments are
+    /// read the POSIX way.
+    posix_mode: bool,
+    /// Whether find is only being asked whether anything matches, as with
+    /// --has-match: it stops at the first match, and the exit code is the
+    /// answer (see `has_match_exit_code`).
+    has_match: bool,
     help_requested: bool,
+    /// Whether to report how often each predicate was evaluated and how
+    /// often it matched, as with "-D rates" (see `StatsWrapper`).
+    debug_rates: bool,
+    /// Whether to write a description of the expression once it's been
+    /// parsed, as with "-D tree" (see `Matcher::describe`).
+    debug_tree: bool,
+    /// Whether to report how many times metadata was looked up, directories
+    /// were read and commands were run, as with "-D stat".
+    debug_stat: bool,
+    /// Whether to skip evaluating the expression for entries that fail its
+    /// prefilter (see `Matcher::prefilter`). Only turned off to check that
+    /// it makes no difference.
+    use_prefilter: bool,
+    /// The files opened by -fprint and friends.
+    output_files: OutputFiles,
+    /// Whether those files are added to rather than truncated, as with
+    /// --append-output.
+    append_output: bool,
+    /// The formats of -printf and -fprintf, each parsed once however many
+    /// times it's used.
+    printf_formats: FormatCache,
+    /// Whether -type always looks up a file's type, rather than trusting what
+    /// the walk says (see `TypeMatcher`), as with --stat-types.
+    stat_types: bool,
+    /// When -print colours the names that -name and -iname matched (see
+    /// --color).
+    color: ColorMode,
+    /// Whether to stay on the file system each start point is on (-xdev):
+    /// directories on others are still matched, but not searched.
+    same_file_system: bool,
+    /// Whether file ages (-atime and friends) are measured from the start of
+    /// tomorrow rather than from now, as with -daystart.
+    day_start: bool,
+    /// Whether times are shown (and -daystart works) in UTC rather than the
+    /// time zone TZ names, as with --utc.
+    utc: bool,
+    /// Whether to keep quiet about files that disappear while find is
+    /// looking at them (-ignore_readdir_race).
+    ignore_readdir_race: bool,
+    /// The syntax of -regex and -iregex patterns (see -regextype).
+    regex_type: RegexType,
+    /// Whether -E was given, making extended regular expressions the
+    /// default, as with BSD find.
+    bsd_extended_regex: bool,
+    /// Whether warnings are written (-warn, the default, or -nowarn).
+    warnings_enabled: bool,
+    /// Whether hints about expressions that probably don't do what was meant
+    /// are given too, which only an explicit -warn asks for.
+    hints_enabled: bool,
+    /// Warnings about the command line, written once it's been parsed.
+    warnings: Vec<String>,
+    /// The first test or action in the expression, once there's been one:
+    /// global options that come after it get a warning.
+    first_predicate: Option<String>,
+    /// What to tell about the walk's progress as it goes, if anything (see
+    /// `ConfigBuilder::on_progress`). The command line never sets one.
+    progress: Option<RefCell<ProgressCallback>>,
+    /// How many threads evaluate the expression's tests (--threads), if it's
+    /// made of tests that can be evaluated on other threads (see
+    /// `matchers::parallel`). Otherwise, it's set back to 1 once the
+    /// expression's been parsed.
+    threads: usize,
 }
 
 impl Default for Config {
@@ -28,43 +133,320 @@ impl Default for Config {
             depth_first: false,
             min_depth: 0,
             max_depth: usize::max_value(),
-            sorted_output: false,
+            sort_entries: false,
+            follow_links: false,
+            follow_start_points: false,
+            allow_batch_exec: false,
+            posix_mode: false,
+            has_match: false,
             help_requested: false,
+            debug_rates: false,
+            debug_tree: false,
+            debug_stat: false,
+            use_prefilter: true,
+            output_files: OutputFiles::new(),
+            append_output: false,
+            printf_formats: FormatCache::new(),
+            stat_types: false,
+            color: ColorMode::Never,
+            same_file_system: false,
+            day_start: false,
+            utc: false,
+            ignore_readdir_race: false,
+            regex_type: RegexType::default(),
+            bsd_extended_regex: false,
+            warnings_enabled: true,
+            hints_enabled: false,
+            warnings: vec![],
+            first_predicate: None,
+            progress: None,
+            threads: 1,
         }
     }
 }
 
+impl Config {
+    /// Returns a builder for a config, starting from the default one.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder { config: Config::default() }
+    }
+}
+
+/// Whether symbolic links are followed, as with find's -P, -H and -L
+/// options.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FollowMode {
+    /// Links are never followed (the default).
+    P,
+    /// Only start points are followed: a start point that's a link to a
+    /// directory is searched, but links below it are just links.
+    H,
+    /// Links are always followed, so linked directories are searched.
+    L,
+}
+
+/// Builds a `Config`, checking that the settings make sense together.
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Whether directories' contents come before the directories themselves
+    /// (-depth).
+    pub fn depth_first(mut self, depth_first: bool) -> ConfigBuilder {
+        self.config.depth_first = depth_first;
+        self
+    }
+
+    /// Entries less deep than this are searched, but not matched
+    /// (-mindepth).
+    pub fn min_depth(mut self, min_depth: usize) -> ConfigBuilder {
+        self.config.min_depth = min_depth;
+        self
+    }
+
+    /// Nothing deeper than this is searched (-maxdepth).
+    pub fn max_depth(mut self, max_depth: usize) -> ConfigBuilder {
+        self.config.max_depth = max_depth;
+        self
+    }
+
+    /// How symbolic links are treated (-P, -H or -L).
+    pub fn follow(mut self, mode: FollowMode) -> ConfigBuilder {
+        self.config.follow_links = mode == FollowMode::L;
+        self.config.follow_start_points = mode != FollowMode::P;
+        self
+    }
+
+    /// Whether to stay on the file system each start point is on (-xdev).
+    pub fn same_file_system(mut self, same_file_system: bool) -> ConfigBuilder {
+        self.config.same_file_system = same_file_system;
+        self
+    }
+
+    /// Whether file ages are measured from the start of tomorrow
+    /// (-daystart).
+    pub fn day_start(mut self, day_start: bool) -> ConfigBuilder {
+        self.config.day_start = day_start;
+        self
+    }
+
+    /// Whether each directory's entries are visited in order of their names
+    /// (-s).
+    pub fn sort_entries(mut self, sort_entries: bool) -> ConfigBuilder {
+        self.config.sort_entries = sort_entries;
+        self
+    }
+
+    /// How many threads evaluate the expression's tests (--threads), if it
+    /// can be evaluated on more than one. 0 is taken to mean 1.
+    pub fn threads(mut self, threads: usize) -> ConfigBuilder {
+        self.config.threads = cmp::max(threads, 1);
+        self
+    }
+
+    /// Calls the callback with the walk's progress (see `WalkProgress`)
+    /// every so often, e.g. to show how far a long search has got.
+    pub fn on_progress(mut self, callback: ProgressCallback) -> ConfigBuilder {
+        self.config.progress = Some(RefCell::new(callback));
+        self
+    }
+
+    /// Returns the config, unless the settings contradict each other.
+    pub fn build(self) -> Result<Config, FindError> {
+        let config = self.config;
+        if config.min_depth > config.max_depth {
+            return Err(FindError::MinDepthAboveMaxDepth {
+                min_depth: config.min_depth,
+                max_depth: config.max_depth,
+            });
+        }
+        Ok(config)
+    }
+}
 
-/// Trait that encapsulates various dependencies (output, clocks, etc.) that we
-/// might want to fake out for unit tests.
+/// Trait that encapsulates various dependencies (output, clocks, file
+/// metadata etc.) that we might want to fake out for unit tests.
 pub trait Dependencies<'a> {
     fn get_output(&'a self) -> &'a RefCell<Write>;
+    /// Where diagnostics (e.g. about files that can't be read) are written.
+    fn get_error_output(&'a self) -> &'a RefCell<Write>;
+    /// Where responses to prompts (e.g. from -ok) are read from.
+    fn get_input(&'a self) -> &'a RefCell<BufRead>;
+    /// The time find started. Everything that compares times with "now"
+    /// uses the same one (see `MatcherIO::now`), so a long-running find
+    /// doesn't change its mind about a file's age partway through.
     fn now(&'a self) -> SystemTime;
+    fn user_group_cache(&'a self) -> &'a UserGroupCache;
+    /// The mounted filesystems, for -fstype and %F (only read if one of them
+    /// needs it).
+    fn mount_table(&'a self) -> &'a MountTable;
+    /// Returns the metadata for the given path (or, if follow_links is true
+    /// and the path is a symbolic link, for its target).
+    fn metadata(&'a self, path: &Path, follow_links: bool) -> io::Result<FileMetadata>;
+    /// Returns the metadata for an entry that the walk has come to, in the
+    /// same way. That's the metadata for its path, but the standard
+    /// dependencies look it up in the directory the walk has open (see
+    /// `WalkEntry::stat`), so that it can be found however long the path
+    /// is.
+    fn entry_metadata(&'a self,
+                      entry: &WalkEntry,
+                      follow_links: bool)
+                      -> io::Result<FileMetadata> {
+        self.metadata(entry.path(), follow_links)
+    }
+    /// Returns the value of the given environment variable, if it's set.
+    fn env_var(&'a self, name: &str) -> Option<OsString>;
+    /// Called as find starts to walk each of its start points. Nothing
+    /// needs to happen then, but tests want to know how far find got.
+    fn start_walking(&'a self, _start_point: &str) {}
+    /// Whether the output is going to a terminal, in which case -print makes
+    /// file names safe to show on one (see `quoting::tty_literal`).
+    fn output_is_terminal(&'a self) -> bool {
+        false
+    }
+    /// Called when writing to the output has failed (and find has said so),
+    /// so that the same failure isn't reported again when what's still
+    /// buffered can't be flushed at the end either.
+    fn output_failed(&'a self) {}
+    /// Whether `output_failed` has been called.
+    fn has_output_failed(&'a self) -> bool {
+        false
+    }
+    /// Whether the expression's tests can be evaluated on worker threads
+    /// (for --threads), which can't share these dependencies: they look up
+    /// metadata in the file system, and users, groups and mounts in the
+    /// system's own tables. Unless that gives the same answers as these
+    /// dependencies would, the whole expression is evaluated on one thread.
+    fn supports_worker_threads(&'a self) -> bool {
+        false
+    }
 }
 
 /// Struct that holds the dependencies we use when run as the real executable.
 pub struct StandardDependencies {
     output: Rc<RefCell<Write>>,
+    error_output: Rc<RefCell<Write>>,
+    input: Rc<RefCell<BufRead>>,
     now: SystemTime,
+    user_group_cache: UserGroupCache,
+    mount_table: MountTable,
+    output_is_terminal: bool,
+    output_failed: Cell<bool>,
 }
 
+/// The file to open to read from the terminal, even if stdin is something
+/// else.
+#[cfg(windows)]
+pub const TERMINAL: &'static str = "CONIN$";
+#[cfg(not(windows))]
+pub const TERMINAL: &'static str = "/dev/tty";
+
 impl StandardDependencies {
     pub fn new() -> StandardDependencies {
         StandardDependencies {
-            output: Rc::new(RefCell::new(stdout())),
+            output_is_terminal: stdout_is_terminal(),
+            ..StandardDependencies::with_output(Box::new(stdout()))
+        }
+    }
+
+    /// Creates the standard dependencies, but with output going to the given
+    /// writer rather than stdout. Output is always buffered: it's flushed when
+    /// find finishes (or when something else needs the output to be in order).
+    pub fn with_output(output: Box<Write>) -> StandardDependencies {
+        StandardDependencies {
+            output: Rc::new(RefCell::new(BufWriter::new(output))),
+            error_output: Rc::new(RefCell::new(stderr())),
+            input: Rc::new(RefCell::new(BufReader::new(stdin()))),
             now: SystemTime::now(),
+            user_group_cache: UserGroupCache::new(),
+            mount_table: MountTable::new(),
+            output_is_terminal: false,
+            output_failed: Cell::new(false),
         }
     }
 }
 
+#[cfg(unix)]
+fn stdout_is_terminal() -> bool {
+    unsafe { ::libc::isatty(1) == 1 }
+}
+
+#[cfg(not(unix))]
+fn stdout_is_terminal() -> bool {
+    false
+}
+
+impl StandardDependencies {
+    /// Reads responses to prompts from the terminal rather than stdin. Needed
+    /// when stdin has already been used for something else (e.g. reading the
+    /// list of paths to search).
+    pub fn read_input_from_terminal(&mut self) -> io::Result<()> {
+        self.input = Rc::new(RefCell::new(BufReader::new(fs::File::open(TERMINAL)?)));
+        Ok(())
+    }
+
+    /// Reads responses to prompts from the given reader.
+    pub fn set_input(&mut self, input: Box<BufRead>) {
+        self.input = Rc::new(RefCell::new(input));
+    }
+}
+
 impl<'a> Dependencies<'a> for StandardDependencies {
     fn get_output(&'a self) -> &'a RefCell<Write> {
         self.output.as_ref()
     }
 
+    fn get_error_output(&'a self) -> &'a RefCell<Write> {
+        self.error_output.as_ref()
+    }
+
+    fn get_input(&'a self) -> &'a RefCell<BufRead> {
+        self.input.as_ref()
+    }
+
     fn now(&'a self) -> SystemTime {
         self.now
     }
+
+    fn user_group_cache(&'a self) -> &'a UserGroupCache {
+        &self.user_group_cache
+    }
+
+    fn mount_table(&'a self) -> &'a MountTable {
+        &self.mount_table
+    }
+
+    fn metadata(&'a self, path: &Path, follow_links: bool) -> io::Result<FileMetadata> {
+        metadata::read_metadata(path, follow_links)
+    }
+
+    fn entry_metadata(&'a self,
+                      entry: &WalkEntry,
+                      follow_links: bool)
+                      -> io::Result<FileMetadata> {
+        entry.stat(follow_links)
+    }
+
+    fn env_var(&'a self, name: &str) -> Option<OsString> {
+        env::var_os(name)
+    }
+
+    fn output_is_terminal(&'a self) -> bool {
+        self.output_is_terminal
+    }
+
+    fn output_failed(&'a self) {
+        self.output_failed.set(true);
+    }
+
+    fn has_output_failed(&'a self) -> bool {
+        self.output_failed.get()
+    }
+
+    fn supports_worker_threads(&'a self) -> bool {
+        true
+    }
 }
 
 /// The result of parsing the command-line arguments into useful forms.
@@ -74,21 +456,94 @@ struct ParsedInfo {
     config: Config,
 }
 
-/// Function to generate a `ParsedInfo` from the strings supplied on the command-line.
-fn parse_args(args: &[&str]) -> Result<ParsedInfo, Box<Error>> {
+/// The same as `parse_args_with_config`, starting from the default config.
+#[cfg(test)]
+fn parse_args(args: &[&str], registry: &MatcherRegistry) -> Result<ParsedInfo, Box<Error>> {
+    parse_args_with_config(args, registry, Config::default())
+}
+
+/// Function to generate a `ParsedInfo` from the strings supplied on the
+/// command-line, starting from the given config (which the options can then
+/// change).
+fn parse_args_with_config(args: &[&str],
+                          registry: &MatcherRegistry,
+                          mut config: Config)
+                          -> Result<ParsedInfo, Box<Error>> {
     let mut paths = vec![];
     let mut i = 0;
-    let mut config = Config::default();
 
-    while i < args.len() && (args[i] == "-" || !args[i].starts_with('-')) && args[i] != "!" &&
-          args[i] != "(" {
+    // Options that have to come before any paths, optionally followed by "--".
+    let mut end_of_options = false;
+    while i < args.len() {
+        match args[i] {
+            "-s" | "--sort" => config.sort_entries = true,
+            // the last of these wins
+            "-L" => {
+                config.follow_links = true;
+                config.follow_start_points = true;
+            }
+            "-H" => {
+                config.follow_links = false;
+                config.follow_start_points = true;
+            }
+            "-P" => {
+                config.follow_links = false;
+                config.follow_start_points = false;
+            }
+            "--allow-batch-exec" => config.allow_batch_exec = true,
+            "--posix" => config.posix_mode = true,
+            "--has-match" => config.has_match = true,
+            "--utc" => config.utc = true,
+            "--stat-types" => config.stat_types = true,
+            "--append-output" => config.append_output = true,
+            "--color" => config.color = ColorMode::Auto,
+            arg if arg.starts_with("--color=") => {
+                config.color = ColorMode::from_name(&arg["--color=".len()..])?
+            }
+            arg if arg.starts_with("--threads=") => {
+                config.threads = parse_threads(&arg["--threads=".len()..])?
+            }
+            "-E" => {
+                config.regex_type = RegexType::Extended;
+                config.bsd_extended_regex = true;
+            }
+            "-D" => {
+                i += 1;
+                match args.get(i) {
+                    Some(options) => parse_debug_options(options, &mut config)?,
+                    None => return Err(From::from("missing argument to -D")),
+                }
+            }
+            "-f" => {
+                // a start point, which is taken as it is even if it looks
+                // like part of the expression (as with BSD find)
+                i += 1;
+                match args.get(i) {
+                    Some(path) => paths.push(path.to_string()),
+                    None => return Err(From::from("missing argument to -f")),
+                }
+            }
+            "--" => {
+                end_of_options = true;
+                i += 1;
+                break;
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+
+    while i < args.len() && is_start_point(args[i], end_of_options) {
         paths.push(args[i].to_string());
         i += 1;
     }
-    if i == 0 {
+    if paths.is_empty() {
         paths.push(".".to_string());
     }
-    let matcher = matchers::build_top_level_matcher(&args[i..], &mut config)?;
+    let matcher =
+        matchers::build_top_level_matcher_with_registry(&args[i..], &mut config, registry)?;
+    // only now that the whole expression's been parsed
+    config.output_files.create_all(config.append_output)?;
     Ok(ParsedInfo {
         matcher: matcher,
         paths: paths,
@@ -96,75 +551,248 @@ fn parse_args(args: &[&str]) -> Result<ParsedInfo, Box<Error>> {
     })
 }
 
-fn process_dir<'a>(dir: &str,
-                   config: &Config,
-                   deps: &'a Dependencies<'a>,
-                   matcher: &Box<matchers::Matcher>)
-                   -> Result<u64, Box<Error>> {
-
-    let mut found_count: u64 = 0;
-    let mut walkdir = WalkDir::new(dir)
-        .contents_first(config.depth_first)
-        .max_depth(config.max_depth)
-        .min_depth(config.min_depth);
-    if config.sorted_output {
-        walkdir = walkdir.sort_by(|a, b| a.cmp(b));
-    }
-
-    // Slighly yucky loop handling here :-(. See docs for
-    // WalkDirIterator::skip_current_dir for explanation.
-    let mut it = walkdir.into_iter();
-    loop {
-        match it.next() {
-            None => break,
-            Some(Err(err)) => {
-                writeln!(&mut stderr(), "Error: {}: {}", dir, err.description()).unwrap()
-            }
-            Some(Ok(entry)) => {
-                let mut matcher_io = matchers::MatcherIO::new(deps);
-                if matcher.matches(&entry, &mut matcher_io) {
-                    found_count += 1;
-                }
-                if matcher_io.should_skip_current_dir() {
-                    it.skip_current_dir();
-                }
+/// Reads the number of threads given to --threads, which has to be at least
+/// 1.
+fn parse_threads(threads: &str) -> Result<usize, Box<Error>> {
+    match threads.parse::<usize>() {
+        Ok(threads) if threads > 0 => Ok(threads),
+        _ => {
+            Err(From::from(format!("invalid argument ‘{}’ for ‘--threads’ (expected a \
+                                    number of threads, at least 1)",
+                                   threads)))
+        }
+    }
+}
+
+/// Turns on the debugging options in a comma-separated list (the argument to
+/// -D).
+fn parse_debug_options(options: &str, config: &mut Config) -> Result<(), Box<Error>> {
+    for option in options.split(',') {
+        match option {
+            "rates" => config.debug_rates = true,
+            "tree" => config.debug_tree = true,
+            "stat" => config.debug_stat = true,
+            _ => {
+                return Err(From::from(format!("unknown debug option ‘{}’ (valid options are: \
+                                               rates, stat, tree)",
+                                              option)))
             }
         }
     }
-    Ok(found_count)
+    Ok(())
+}
+
+/// Works out whether a command-line argument is a path to start searching from,
+/// rather than the beginning of the expression. As with GNU find, that's
+/// anything but an option or an operator that can start an expression: so
+/// "+dir" and "!dir" are paths, and so are "," and ")", which can only come
+/// after something else in an expression.
+fn is_start_point(arg: &str, after_end_of_options: bool) -> bool {
+    match arg {
+        // GNU find treats a lone "-" as a file name (not stdin).
+        "-" => true,
+        "!" | "(" => false,
+        _ if !arg.starts_with('-') => true,
+        // Once the user has said there are no more options, something that
+        // looks like one is still a path if there's a file of that name.
+        _ => after_end_of_options && fs::symlink_metadata(arg).is_ok(),
+    }
+}
+
+/// Orders file names by their raw bytes, so that sorted output doesn't depend
+/// on the locale (or anything else about the machine find is running on).
+pub fn compare_file_names(a: &OsStr, b: &OsStr) -> Ordering {
+    // OsStr's ordering is a plain byte-by-byte comparison of its underlying
+    // representation.
+    a.cmp(b)
+}
+
+/// Walks all the paths, then lets the matchers know that we're done. Returns
+/// the exit code.
+fn process_paths<'a>(parsed_info: &ParsedInfo, deps: &'a Dependencies<'a>) -> i32 {
+    let mut matcher_io = matchers::MatcherIO::new(deps);
+    let summary = Walker::new(&parsed_info.config, deps, &parsed_info.paths)
+        .run(parsed_info.matcher.as_ref(), &mut matcher_io);
+    parsed_info.matcher.finished(&mut matcher_io);
+    if parsed_info.config.debug_stat {
+        // (counting the commands that finishing off ran too)
+        writeln!(deps.get_error_output().borrow_mut(),
+                 "stat calls: {}\ndirectories read: {}\ncommands run: {}",
+                 summary.stat_calls,
+                 summary.dirs_read,
+                 matcher_io.commands_run())
+            .unwrap();
+    }
+    if parsed_info.config.has_match {
+        has_match_exit_code(summary.entries_matched > 0, matcher_io.exit_code())
+    } else {
+        matcher_io.exit_code()
+    }
 }
 
+/// The exit code for --has-match: 0 if anything matched (whatever else went
+/// wrong, as with "grep -q"), otherwise 2 if there were problems searching
+/// (so the answer may be wrong) or 1 if there weren't.
+fn has_match_exit_code(matched: bool, exit_code: i32) -> i32 {
+    if matched {
+        0
+    } else if exit_code != 0 {
+        2
+    } else {
+        1
+    }
+}
 
-fn do_find<'a>(args: &[&str], deps: &'a Dependencies<'a>) -> Result<u64, Box<Error>> {
-    let paths_and_matcher = parse_args(args)?;
+fn do_find<'a>(args: &[&str],
+               mut config: Config,
+               registry: &MatcherRegistry,
+               deps: &'a Dependencies<'a>)
+               -> Result<i32, Box<Error>> {
+    if deps.env_var("POSIXLY_CORRECT").is_some() {
+        config.posix_mode = true;
+    }
+    let paths_and_matcher = parse_args_with_config(args, registry, config)?;
+    if paths_and_matcher.config.warnings_enabled {
+        for warning in &paths_and_matcher.config.warnings {
+            writeln!(deps.get_error_output().borrow_mut(), "find: warning: {}", warning)?;
+        }
+    }
     if paths_and_matcher.config.help_requested {
-        print_help();
+        print_help(deps)?;
         return Ok(0);
     }
-    let mut found_count: u64 = 0;
-    for path in paths_and_matcher.paths {
-        found_count += process_dir(&path,
-                                   &paths_and_matcher.config,
-                                   deps,
-                                   &paths_and_matcher.matcher)?;
+    if paths_and_matcher.config.debug_tree {
+        writeln!(deps.get_error_output().borrow_mut(),
+                 "{}",
+                 paths_and_matcher.matcher)?;
     }
-    Ok(found_count)
+    Ok(process_paths(&paths_and_matcher, deps))
 }
 
-fn print_help() {
-    println!(r"Usage: find [path...] [expression]
+fn print_help<'a>(deps: &'a Dependencies<'a>) -> io::Result<()> {
+    writeln!(deps.get_output().borrow_mut(),
+             r"Usage: find [-s] [-f path] [--] [path...] [expression]
 
 If no path is supplied then the current working directory is used by default.
 
+Options that must appear before any paths:
+ -s, --sort
+    process the contents of each directory in order of their names' bytes
+    (the same as -sorted below).
+ -P
+    never follow symbolic links (the default). Tests like -type look at the
+    link itself, and linked directories aren't searched.
+ -H
+    follow symbolic links given as start points (or to -newer, -anewer or
+    -samefile), but not those found below them.
+ -L
+    follow symbolic links: tests look at what they point to, and linked
+    directories are searched (loops are reported, not followed).
+    On Windows, junctions count as symbolic links too.
+ --allow-batch-exec
+    let -exec and friends pass file names ({{}}) to Windows batch files
+    (.bat and .cmd), which is refused by default as cmd.exe may not pass them
+    on as they are.
+ --has-match
+    a non-standard extension for scripts that only want to know whether
+    anything matches: find stops at the first file the expression is true
+    for, prints nothing (unless the expression has actions of its own), and
+    exits with 0 if something matched, 1 if nothing did, or 2 if nothing did
+    but there were problems searching. Mistakes in the arguments still give
+    1.
+ --utc
+    show times (in -ls and -printf) in UTC, and make -daystart mean midnight
+    UTC, rather than going by the time zone TZ names. Either way, month and
+    day names are in English, whatever the locale.
+ --color[=when]
+    a non-standard extension: make -print show the part of each name that
+    -name or -iname matched in colour, and dim the directory separators.
+    when is 'auto' (the default: only when writing to a terminal), 'always'
+    or 'never'. -print0, -printf and -fprint never colour anything.
+ --append-output
+    make -fprint and friends add to the end of files that already exist,
+    rather than truncating them (e.g. to collect what several finds in a
+    script find in one file).
+ --stat-types
+    make -type look up each file's type, rather than going by what the
+    directory it's in says, for file systems that get that wrong. (Types
+    that a directory doesn't give are looked up anyway.)
+ --threads=n
+    a non-standard extension: evaluate tests like -name, -type and -size on
+    n threads at once, which can be quicker on a large tree. The output is
+    the same as with one thread. Only expressions made of nothing but such
+    tests, followed by -print, -print0, -printf, -ls or -printj (or none of
+    them), are evaluated this way: anything else (e.g. -exec, -ok, -fprint,
+    -prune or '-D rates') uses one thread, with a warning.
+ --posix
+    stick to what POSIX specifies (as does setting POSIXLY_CORRECT): other
+    predicates and options are rejected, -size only takes a number of
+    512-byte blocks or of bytes (with c), and -perm +mode is a symbolic mode.
+ -f path
+    search path as well as any paths that follow the options, even if it
+    starts with '-' or looks like part of the expression (as with BSD find).
+    Can be given more than once.
+ -E
+    make -regex and -iregex use extended regular expressions (as with
+    -regextype posix-extended), as BSD find does.
+ -D debugopts
+    turns on the comma-separated debugging options, which write to stderr:
+    rates reports (once find has finished) how many times each predicate
+    was evaluated and how many times it matched, stat reports (likewise)
+    how many times file metadata was looked up, directories were read and
+    commands were run, and tree shows what the expression was parsed into.
+ --
+    marks the end of the options. Paths that come after it and start with
+    '-' are still treated as paths, provided they exist.
+
 Early alpha implementation. Currently the only expressions supported are
  -print
+ -print0
+ -printf format
+    writes the format for each file, with escapes like \n and directives
+    (optionally padded, as in %-10p) for: %p (path), %f (name), %h (the
+    directory it's in), %P (path under the starting point), %H (starting
+    point), %d (depth), %s (size), %S (sparseness: space allocated
+    divided by size), %m (permissions), %M (permissions as -ls shows them,
+    e.g. -rw-r--r--), %D (device), %F (type of filesystem), %b (512-byte
+    blocks used), %k (1K blocks used), %n (hard links), %l (symbolic link
+    target), %u and %g (owner's user and group names, or ids if they have
+    none), %U and %G (their ids), %y (type, as -type's letters) and %Y
+    (the type of what a symbolic link points to, or N if it doesn't exist
+    or L if it's a loop). %% is a literal %. Unlike GNU find, there's also
+    %q: the path quoted so that a shell reads it back as it is (e.g. for
+    -printf 'rm -- %q\n'). %Z (SELinux security context) needs find to have
+    been built with the selinux feature. Other directives are written as
+    they are (with a warning), except for %{{, %[ and %(, which are errors.
+ -ls
+    describes each file as 'ls -dils' would, with unusual characters in
+    names escaped (e.g. \n for a newline)
+ -printj
+    a non-standard extension for programs to read: writes a line of JSON for
+    each file, with its path, file_type (as -type has it), size, mtime
+    (seconds since the epoch), depth and, on Unix, mode (permission bits),
+    uid and gid. Paths that aren't valid UTF-8 also have a path_bytes field,
+    with the exact bytes in base64.
+ -fprint file, -fprint0 file, -fprintf file format, -fls file
+    the same as -print, -print0, -printf and -ls, but writing to the file
+    (which is created, or truncated unless --append-output is given, even if
+    nothing's written to it)
  -name case-sensitive_filename_pattern
  -iname case-insensitive_filename_pattern
+ -path case-sensitive_path_pattern
+ -ipath case-insensitive_path_pattern
+    match the whole path, as it would be printed ('*' matches '/' too)
+ -regex pattern
+ -iregex pattern
+    the whole path matches the regular expression (see -regextype).
  -type type_char
-    currently type_char can only be f (for file) or d (for directory)
+    type_char can be f (for file), d (for directory), l (for symbolic link,
+    which on Windows includes junctions), p (for FIFO), s (for socket), b
+    (for block device) or c (for character device)
  -size [+-]N[bcwkMG]
  -delete
  -prune
+ -quit
  -not
  -a
  -o[r]
@@ -179,42 +807,151 @@ Early alpha implementation. Currently the only expressions supported are
  -atime [+-]N
  -mtime [+-]N
  -perm [-/]{{octal|u=rwx,go=w}}
+ -user name_or_uid
+ -group name_or_gid
  -newer path_to_file
+ -anewer path_to_file
+    accessed more recently than path_to_file was modified.
+ -samefile path_to_file
+    the same file as path_to_file (e.g. a hard link to it).
+    With -H or -L, a symbolic link given to -newer, -anewer or -samefile
+    stands for what it points to, and otherwise for the link itself.
+ -fstype type
+    the file is on a filesystem of the given type (e.g. ext4 or nfs, as
+    %F prints it), going by the mount table. A file on a device that isn't
+    in it is on 'unknown'.
+ -context pattern
+    the file's SELinux security context matches the pattern (e.g.
+    '*unlabeled*'). Only if find was built with the selinux feature.
  -exec[dir] executable [args] [{{}}] [more args] ;
+ -exec[dir] executable [args] {{}} +
+    runs the command on as many files at a time as will fit on its command
+    line (for -execdir, files in the same directory), and is always true.
+ -ok[dir] executable [args] [{{}}] [more args] ;
+    the same as -exec[dir], but asks before running the command.
  -sorted
     a non-standard extension that sorts directory contents by name before
     processing them. Less efficient, but allows for deterministic output.
-");
+
+-maxdepth, -mindepth, -d[epth] and -sorted, along with the options below,
+apply to the whole expression wherever they appear, so a warning is given if
+they come after a test or action.
+ -daystart
+    measure -atime, -ctime and -mtime ages from the start of tomorrow rather
+    than from now, so that -mtime 0 means modified today.
+ -follow
+    the same as -L.
+ -xdev, -mount
+    don't search directories on other file systems (though they can still
+    match).
+ -noleaf
+    accepted for compatibility with GNU find, but has no effect.
+ -ignore_readdir_race, -noignore_readdir_race
+    whether to stay quiet about files that are deleted while find is
+    looking at them.
+ -regextype type
+    the syntax of the patterns given to -regex and -iregex: emacs (the
+    default), posix-basic or posix-extended, among GNU find's other names
+    for those. Conflicts with -E unless it's an extended syntax.
+ -warn, -nowarn
+    turn warnings (like the one above) on (the default) or off. An explicit
+    -warn also gives hints about expressions that probably don't do what was
+    meant, e.g. ‘-name a -o -name b -exec ...’, which runs the command for
+    files named b but not for those named a.
+")
 }
 
 /// Does all the work for find.
 ///
 /// All main has to do is pass in the command-line args and exit the process
 /// with the exit code. Note that the first string in args is expected to be
-/// the name of the executable.
+/// the name of the executable. Everything find reads or writes (other than
+/// the files it's searching) goes through deps, and it never exits the
+/// process itself, so it can be run in-process by tests, or by a program that
+/// bundles several tools together.
 pub fn find_main<'a>(args: &[&str], deps: &'a Dependencies<'a>) -> i32 {
+    find_main_with_registry(args, &MatcherRegistry::default(), deps)
+}
 
-    match do_find(&args[1..], deps) {
-        Ok(_) => 0,
+/// The same as `find_main`, but with the predicates looked up in the given
+/// registry, allowing callers to add their own.
+pub fn find_main_with_registry<'a>(args: &[&str],
+                                   registry: &MatcherRegistry,
+                                   deps: &'a Dependencies<'a>)
+                                   -> i32 {
+    match try_find_main(args, registry, deps) {
+        Ok(rc) => rc,
         Err(e) => {
-            writeln!(&mut stderr(), "Error: {}", e).unwrap();
+            writeln!(deps.get_error_output().borrow_mut(), "find: {}", e).unwrap();
             1
         }
     }
 }
 
+/// Does all the work for find, like `find_main_with_registry`, but returns
+/// any error in the arguments (an unknown predicate, a missing argument, a
+/// file for -fprint that can't be created etc.) rather than reporting it. In
+/// that case nothing has been searched, run or written. Otherwise, the
+/// result is the exit code: problems that turn up while searching (e.g.
+/// directories that can't be read) have already been reported, and make it
+/// 1.
+pub fn try_find_main<'a>(args: &[&str],
+                         registry: &MatcherRegistry,
+                         deps: &'a Dependencies<'a>)
+                         -> Result<i32, Box<Error>> {
+    try_find_main_with_config(args, Config::default(), registry, deps)
+}
+
+/// The same as `try_find_main`, but starting from the given config rather
+/// than the default one, so that what it says (e.g. a maximum depth from
+/// `Config::builder()`) applies unless the arguments say otherwise.
+pub fn try_find_main_with_config<'a>(args: &[&str],
+                                     config: Config,
+                                     registry: &MatcherRegistry,
+                                     deps: &'a Dependencies<'a>)
+                                     -> Result<i32, Box<Error>> {
+    let result = do_find(&args[1..], config, registry, deps);
+    // Output is buffered, so make sure it's all been written before we return
+    // (the process may well exit without running any destructors). If
+    // whatever we're writing to has gone away (e.g. "find | head") there's
+    // nobody left to tell, so that isn't an error.
+    let flushed = match deps.get_output().borrow_mut().flush() {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    };
+    let rc = result?;
+    match flushed {
+        Ok(()) => Ok(rc),
+        // it's been said already
+        Err(_) if deps.has_output_failed() => Ok(rc),
+        Err(e) => {
+            writeln!(deps.get_error_output().borrow_mut(),
+                     "find: failed to write output: {}",
+                     e)
+                .unwrap();
+            Ok(1)
+        }
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub mod tests {
 
 
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
     use std::fs;
-    use std::io::{Cursor, Read, Write};
-    use std::time::{Duration, SystemTime};
+    use std::io::{self, Cursor, Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
     use std::vec::Vec;
     use tempdir::TempDir;
+    use walkdir::WalkDir;
 
-    use find::matchers::MatcherIO;
+    use find::error::FindError;
+    use find::matchers::{Matcher, MatcherIO, WalkEntry};
+    use find::metadata::{self, FileKind};
 
     use super::*;
 
@@ -234,19 +971,77 @@ mod tests {
     /// allowing us to check output, set the time returned by clocks etc.
     pub struct FakeDependencies {
         pub output: RefCell<Cursor<Vec<u8>>>,
-        now: SystemTime,
+        pub error_output: RefCell<Cursor<Vec<u8>>>,
+        input: RefCell<Cursor<Vec<u8>>>,
+        now: Cell<SystemTime>,
+        user_group_cache: UserGroupCache,
+        mount_table: MountTable,
+        /// Fake metadata, or the OS error code to fail with, for each path.
+        metadata: HashMap<PathBuf, Result<FileMetadata, i32>>,
+        /// The environment, which starts off empty rather than being ours.
+        env: HashMap<String, OsString>,
+        /// The start points find has started walking, in order.
+        start_points: RefCell<Vec<String>>,
+        output_is_terminal: bool,
     }
 
     impl<'a> FakeDependencies {
         pub fn new() -> FakeDependencies {
             FakeDependencies {
                 output: RefCell::new(Cursor::new(Vec::<u8>::new())),
-                now: SystemTime::now(),
+                error_output: RefCell::new(Cursor::new(Vec::<u8>::new())),
+                input: RefCell::new(Cursor::new(Vec::<u8>::new())),
+                now: Cell::new(SystemTime::now()),
+                user_group_cache: UserGroupCache::new(),
+                mount_table: MountTable::new(),
+                metadata: HashMap::new(),
+                env: HashMap::new(),
+                start_points: RefCell::new(vec![]),
+                output_is_terminal: false,
             }
         }
 
-        pub fn set_time(&mut self, new_time: SystemTime) {
-            self.now = new_time;
+        /// Sets the time find started. It can be changed while find is
+        /// running, to check that nothing notices.
+        pub fn set_time(&self, new_time: SystemTime) {
+            self.now.set(new_time);
+        }
+
+        /// Sets what will be read by anything that asks the user a question.
+        pub fn set_input(&mut self, input: &str) {
+            self.input = RefCell::new(Cursor::new(input.as_bytes().to_vec()));
+        }
+
+        /// Replaces the (real) uid/gid lookups with the given cache.
+        pub fn set_user_group_cache(&mut self, cache: UserGroupCache) {
+            self.user_group_cache = cache;
+        }
+
+        /// Replaces the (real) mount table with the given one.
+        pub fn set_mount_table(&mut self, mount_table: MountTable) {
+            self.mount_table = mount_table;
+        }
+
+        /// Makes any metadata lookups for the given path return the given
+        /// metadata instead of whatever's on disk. Paths without fake metadata
+        /// are looked up for real.
+        pub fn set_metadata<P: AsRef<Path>>(&mut self, path: P, metadata: FileMetadata) {
+            self.metadata.insert(path.as_ref().to_path_buf(), Ok(metadata));
+        }
+
+        /// Makes any metadata lookups for the given path fail with the given
+        /// OS error code (e.g. EACCES).
+        pub fn set_metadata_error<P: AsRef<Path>>(&mut self, path: P, code: i32) {
+            self.metadata.insert(path.as_ref().to_path_buf(), Err(code));
+        }
+
+        pub fn set_env_var(&mut self, name: &str, value: &str) {
+            self.env.insert(name.to_string(), OsString::from(value));
+        }
+
+        /// Makes find think its output is going to a terminal.
+        pub fn set_output_is_terminal(&mut self) {
+            self.output_is_terminal = true;
         }
 
         pub fn new_matcher_io(&'a self) -> MatcherIO<'a> {
@@ -260,6 +1055,18 @@ mod tests {
             cursor.read_to_string(&mut contents).unwrap();
             contents
         }
+
+        pub fn get_error_output_as_string(&self) -> String {
+            let mut cursor = self.error_output.borrow_mut();
+            cursor.set_position(0);
+            let mut contents = String::new();
+            cursor.read_to_string(&mut contents).unwrap();
+            contents
+        }
+
+        pub fn get_start_points(&self) -> Vec<String> {
+            self.start_points.borrow().clone()
+        }
     }
 
     impl<'a> Dependencies<'a> for FakeDependencies {
@@ -267,134 +1074,1058 @@ mod tests {
             &self.output
         }
 
+        fn get_error_output(&'a self) -> &'a RefCell<Write> {
+            &self.error_output
+        }
+
+        fn get_input(&'a self) -> &'a RefCell<BufRead> {
+            &self.input
+        }
+
         fn now(&'a self) -> SystemTime {
-            self.now
+            self.now.get()
+        }
+
+        fn user_group_cache(&'a self) -> &'a UserGroupCache {
+            &self.user_group_cache
+        }
+
+        fn mount_table(&'a self) -> &'a MountTable {
+            &self.mount_table
+        }
+
+        fn metadata(&'a self, path: &Path, follow_links: bool) -> io::Result<FileMetadata> {
+            match self.metadata.get(path) {
+                Some(&Ok(ref metadata)) => Ok(metadata.clone()),
+                Some(&Err(code)) => Err(io::Error::from_raw_os_error(code)),
+                None => metadata::read_metadata(path, follow_links),
+            }
+        }
+
+        fn entry_metadata(&'a self,
+                          entry: &WalkEntry,
+                          follow_links: bool)
+                          -> io::Result<FileMetadata> {
+            if self.metadata.contains_key(entry.path()) {
+                return self.metadata(entry.path(), follow_links);
+            }
+            entry.stat(follow_links)
+        }
+
+        fn env_var(&'a self, name: &str) -> Option<OsString> {
+            self.env.get(name).cloned()
+        }
+
+        fn start_walking(&'a self, start_point: &str) {
+            self.start_points.borrow_mut().push(start_point.to_string());
+        }
+
+        fn output_is_terminal(&'a self) -> bool {
+            self.output_is_terminal
+        }
+
+        // (worker threads wouldn't know about the fake metadata)
+        fn supports_worker_threads(&'a self) -> bool {
+            self.metadata.is_empty()
         }
     }
 
     #[test]
     fn parse_args_handles_single_dash() {
         // Apparently "-" should be treated as a directory name.
-        let parsed_info = super::parse_args(&["-"]).expect("parsing should succeed");
+        let parsed_info = super::parse_args(&["-"], &MatcherRegistry::default())
+            .expect("parsing should succeed");
         assert_eq!(parsed_info.paths, ["-"]);
     }
 
     #[test]
-    fn parse_args_bad_flag() {
-        //
-        let result = super::parse_args(&["-asdadsafsfsadcs"]);
+    fn parse_args_end_of_options() {
+        let parsed_info = super::parse_args(&["--", "foo", "-print"], &MatcherRegistry::default())
+            .expect("parsing should succeed");
+        assert_eq!(parsed_info.paths, ["foo"]);
+
+        let parsed_info = super::parse_args(&["-s", "--", "-"], &MatcherRegistry::default())
+            .expect("parsing should succeed");
+        assert!(parsed_info.config.sort_entries);
+        assert_eq!(parsed_info.paths, ["-"]);
+
+        let parsed_info = super::parse_args(&["--"], &MatcherRegistry::default())
+            .expect("parsing should succeed");
+        assert_eq!(parsed_info.paths, ["."]);
+
+        // there's no such file, so it's the start of the expression
+        let result = super::parse_args(&["--", "-no-such-file-findutils"],
+                                       &MatcherRegistry::default());
         if let Err(e) = result {
-            assert_eq!(e.description(), "Unrecognized flag: '-asdadsafsfsadcs'");
+            assert_eq!(e.to_string(), "Unrecognized flag: '-no-such-file-findutils'");
         } else {
             panic!("parse_args should have returned an error");
         }
     }
 
-
     #[test]
-    fn find_main_not_depth_first() {
-        let deps = FakeDependencies::new();
-
-
-        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple"), "-sorted"],
-                           &deps);
-
-        assert_eq!(rc, 0);
-        assert_eq!(deps.get_output_as_string(),
-                   fix_up_slashes("./test_data/simple\n\
-                   ./test_data/simple/abbbc\n\
-                   ./test_data/simple/subdir\n\
-                   ./test_data/simple/subdir/ABBBC\n"));
+    fn parse_args_start_point_options() {
+        let registry = MatcherRegistry::default();
+        let parsed_info = super::parse_args(&["-f", "-print", "-s", "-f", "(", "foo", "-print"],
+                                            &registry)
+            .expect("parsing should succeed");
+        assert!(parsed_info.config.sort_entries);
+        assert_eq!(parsed_info.paths, ["-print", "(", "foo"]);
+
+        let e = super::parse_args(&["-f"], &registry).err().unwrap();
+        assert_eq!(e.to_string(), "missing argument to -f");
     }
 
     #[test]
-    fn find_main_depth_first() {
+    fn find_has_match() {
+        // something matches: find stops there, without printing anything
         let deps = FakeDependencies::new();
-
-
-        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple"), "-sorted", "-depth"],
+        let rc = find_main(&["find",
+                             "--has-match",
+                             &fix_up_slashes("./test_data/depth"),
+                             "-name",
+                             "f*"],
                            &deps);
-
-        assert_eq!(rc, 0);
-        assert_eq!(deps.get_output_as_string(),
-                   fix_up_slashes("./test_data/simple/abbbc\n\
-                   ./test_data/simple/subdir/ABBBC\n\
-                   ./test_data/simple/subdir\n\
-                   ./test_data/simple\n"));
-    }
-
-    #[test]
-    fn find_maxdepth() {
-        let deps = FakeDependencies::new();
-
-        let rc =
-            find_main(&["find", &fix_up_slashes("./test_data/depth"), "-sorted", "-maxdepth", "2"],
-                      &deps);
-
         assert_eq!(rc, 0);
-        assert_eq!(deps.get_output_as_string(),
-                   fix_up_slashes("./test_data/depth\n\
-                   ./test_data/depth/1\n\
-                   ./test_data/depth/1/2\n\
-                   ./test_data/depth/1/f1\n\
-                   ./test_data/depth/f0\n"));
-    }
+        assert_eq!(deps.get_output_as_string(), "");
 
-    #[test]
-    fn find_maxdepth_depth_first() {
+        // but actions still happen
         let deps = FakeDependencies::new();
-
         let rc = find_main(&["find",
+                             "--has-match",
                              &fix_up_slashes("./test_data/depth"),
                              "-sorted",
-                             "-maxdepth",
-                             "2",
-                             "-depth"],
+                             "-name",
+                             "f*",
+                             "-print"],
                            &deps);
-
         assert_eq!(rc, 0);
         assert_eq!(deps.get_output_as_string(),
-                   fix_up_slashes("./test_data/depth/1/2\n\
-                   ./test_data/depth/1/f1\n\
-                   ./test_data/depth/1\n\
-                   ./test_data/depth/f0\n\
-                   ./test_data/depth\n"));
-    }
+                   fix_up_slashes("./test_data/depth/1/2/3/f3\n"));
 
-    #[test]
-    fn find_prune() {
+        // nothing matches
         let deps = FakeDependencies::new();
+        let rc = find_main(&["find", "--has-match", &fix_up_slashes("./test_data/depth"), "-false"],
+                           &deps);
+        assert_eq!(rc, 1);
+        assert_eq!(deps.get_output_as_string(), "");
+        assert_eq!(deps.get_error_output_as_string(), "");
 
+        // nothing matches, but the answer might have been different if
+        // everything could have been searched
+        let deps = FakeDependencies::new();
         let rc = find_main(&["find",
+                             "--has-match",
+                             &fix_up_slashes("./test_data/no_such_file"),
                              &fix_up_slashes("./test_data/depth"),
-                             "-sorted",
-                             "-print",
-                             ",",
                              "-name",
-                             "1",
-                             "-prune"],
+                             "nothing"],
                            &deps);
+        assert_eq!(rc, 2);
+        assert!(deps.get_error_output_as_string().contains("No such file or directory"));
 
+        // while a match wins over any problems
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             "--has-match",
+                             &fix_up_slashes("./test_data/no_such_file"),
+                             &fix_up_slashes("./test_data/depth"),
+                             "-name",
+                             "f0"],
+                           &deps);
         assert_eq!(rc, 0);
-        assert_eq!(deps.get_output_as_string(),
-                   fix_up_slashes("./test_data/depth\n\
-                   ./test_data/depth/1\n\
-                   ./test_data/depth/f0\n"));
     }
 
     #[test]
-    fn find_zero_maxdepth() {
+    fn find_start_point_options() {
         let deps = FakeDependencies::new();
-        let rc = find_main(&["find", &fix_up_slashes("./test_data/depth"), "-maxdepth", "0"],
+        let rc = find_main(&["find", "-f", &fix_up_slashes("./test_data/simple"), "-name", "abbbc"],
                            &deps);
-
         assert_eq!(rc, 0);
         assert_eq!(deps.get_output_as_string(),
-                   fix_up_slashes("./test_data/depth\n"));
+                   fix_up_slashes("./test_data/simple/abbbc\n"));
+
+        // a directory whose name looks like a test
+        let temp_dir = TempDir::new("find_start_point_options").unwrap();
+        let dir = temp_dir.path().join("-type");
+        fs::create_dir(&dir).unwrap();
+        fs::File::create(dir.join("f")).unwrap();
+        let dir = dir.to_string_lossy().to_string();
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", "-f", &dir, "-type", "f"], &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   format!("{}\n", Path::new(&dir).join("f").to_string_lossy()));
     }
 
     #[test]
-    fn find_zero_maxdepth_depth_first() {
+    fn parse_args_sort_flags() {
+        for arg in &["-s", "--sort"] {
+            let parsed_info =
+                super::parse_args(&[arg, "foo", "-print"], &MatcherRegistry::default())
+                    .expect("parsing should succeed");
+            assert!(parsed_info.config.sort_entries);
+            assert_eq!(parsed_info.paths, ["foo"]);
+
+            // and with no paths at all
+            let parsed_info = super::parse_args(&[arg], &MatcherRegistry::default())
+                .expect("parsing should succeed");
+            assert!(parsed_info.config.sort_entries);
+            assert_eq!(parsed_info.paths, ["."]);
+        }
+
+        let parsed_info = super::parse_args(&["foo"], &MatcherRegistry::default())
+            .expect("parsing should succeed");
+        assert!(!parsed_info.config.sort_entries);
+    }
+
+    #[test]
+    fn parse_args_bad_flag() {
+        //
+        let result = super::parse_args(&["-asdadsafsfsadcs"], &MatcherRegistry::default());
+        if let Err(e) = result {
+            assert_eq!(e.description(), "Unrecognized flag: '-asdadsafsfsadcs'");
+        } else {
+            panic!("parse_args should have returned an error");
+        }
+    }
+
+
+    #[test]
+    fn parse_args_paths_after_expression() {
+        let e = super::parse_args(&["-name", "foo", "src"], &MatcherRegistry::default())
+            .err()
+            .expect("parse_args should have returned an error");
+        assert_eq!(e.downcast_ref::<FindError>(),
+                   Some(&FindError::PathsMustPrecedeExpression {
+                       path: "src".to_string(),
+                       previous_predicate: Some("-name".to_string()),
+                   }));
+        assert!(e.to_string().starts_with("paths must precede expression: ‘src’\n"));
+        assert!(!e.to_string().contains("possible unquoted pattern"));
+
+        let e = super::parse_args(&[".", "-type", "f", "-name", "a.c", "*.h"],
+                                  &MatcherRegistry::default())
+            .err()
+            .expect("parse_args should have returned an error");
+        assert!(e.to_string().starts_with("paths must precede expression: ‘*.h’\npossible \
+                                           unquoted pattern after predicate ‘-name’?\n"));
+
+        // things that look like options are still unrecognized flags
+        let e = super::parse_args(&["-name", "foo", "-bar"], &MatcherRegistry::default())
+            .err()
+            .expect("parse_args should have returned an error");
+        assert!(e.downcast_ref::<FindError>().is_none());
+    }
+
+    #[test]
+    fn find_main_not_depth_first() {
+        let deps = FakeDependencies::new();
+
+
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple"), "-sorted"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple\n\
+                   ./test_data/simple/abbbc\n\
+                   ./test_data/simple/subdir\n\
+                   ./test_data/simple/subdir/ABBBC\n"));
+    }
+
+    #[test]
+    fn find_main_parse_errors_do_nothing() {
+        let temp_dir = TempDir::new("find_main_parse_errors_do_nothing").unwrap();
+        let out = temp_dir.path().join("out.txt").to_string_lossy().to_string();
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&["find", "./test_data/simple", "-fprint", &out, "-name"], &deps),
+                   1);
+        assert_eq!(deps.get_error_output_as_string(),
+                   "find: missing argument to -name\n");
+        assert_eq!(deps.get_output_as_string(), "");
+        assert!(deps.get_start_points().is_empty());
+        assert!(!Path::new(&out).exists());
+
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&["find", "./test_data/simple", "./test_data/depth", "-quit"],
+                             &deps),
+                   0);
+        assert_eq!(deps.get_start_points(), vec!["./test_data/simple"]);
+    }
+
+    #[test]
+    fn find_utc() {
+        let file = fix_up_slashes("./test_data/simple/abbbc");
+        let run = |args: &[&str]| {
+            let mut deps = FakeDependencies::new();
+            deps.set_env_var("TZ", "PST8");
+            deps.set_metadata(&file,
+                              FileMetadata {
+                                  modified: Some(UNIX_EPOCH + Duration::from_secs(1_000_000_000)),
+                                  ..FileMetadata::default()
+                              });
+            assert_eq!(find_main(args, &deps), 0);
+            deps.get_output_as_string()
+        };
+        // TZ is read through the dependencies, and --utc overrides it
+        assert_eq!(run(&["find", &file, "-printf", "%TH %TZ\n"]), "17 PST\n");
+        assert_eq!(run(&["find", "--utc", &file, "-printf", "%TH %TZ\n"]), "01 UTC\n");
+    }
+
+    #[test]
+    fn find_main_color() {
+        let run = |options: &[&str], terminal: bool, actions: &[&str]| {
+            let mut deps = FakeDependencies::new();
+            if terminal {
+                deps.set_output_is_terminal();
+            }
+            let mut args = vec!["find"];
+            args.extend_from_slice(options);
+            args.extend_from_slice(&["./test_data/simple", "-name", "abbbc"]);
+            args.extend_from_slice(actions);
+            assert_eq!(find_main(&args, &deps), 0);
+            deps.get_output_as_string()
+        };
+        let sep = format!("\x1b[2m{}\x1b[0m", fix_up_slashes("/"));
+        let colored = format!(".{0}test_data{0}simple{0}\x1b[1;31mabbbc\x1b[0m\n", sep);
+        let plain = fix_up_slashes("./test_data/simple/abbbc\n");
+        assert_eq!(run(&["--color"], true, &[]), colored);
+        assert_eq!(run(&["--color=auto"], true, &[]), colored);
+        assert_eq!(run(&["--color=auto"], false, &[]), plain);
+        assert_eq!(run(&["--color=always"], false, &[]), colored);
+        assert_eq!(run(&["--color=never"], true, &[]), plain);
+        assert_eq!(run(&[], true, &[]), plain);
+        // only -print is coloured
+        assert_eq!(run(&["--color=always"], true, &["-print0"]),
+                   fix_up_slashes("./test_data/simple/abbbc\0"));
+        assert_eq!(run(&["--color=always"], true, &["-printf", "%p\n"]), plain);
+
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&["find", "--color=rainbow", "./test_data/simple"], &deps), 1);
+        assert_eq!(deps.get_error_output_as_string(),
+                   "find: invalid argument ‘rainbow’ for ‘--color’ (valid arguments are \
+                    ‘auto’, ‘always’ and ‘never’)\n");
+    }
+
+    #[test]
+    fn find_main_fprintf_errors_do_nothing() {
+        let temp_dir = TempDir::new("find_main_fprintf_errors_do_nothing").unwrap();
+        let out = temp_dir.path().join("out.txt").to_string_lossy().to_string();
+        let cases: &[(&[&str], &str)] =
+            &[(&["-fprintf"], "missing file argument to -fprintf"),
+              (&["-fprintf", &out], "missing format argument to -fprintf"),
+              (&["-fprintf", &out, "%p %"], "% at end of format string"),
+              (&["-printf", "%f\\n", "-printf", "%-"],
+               "incomplete format directive ‘%-’ at end of format string")];
+        for &(args, error) in cases {
+            let deps = FakeDependencies::new();
+            let mut all_args = vec!["find", "./test_data/simple"];
+            all_args.extend_from_slice(args);
+            assert_eq!(find_main(&all_args, &deps), 1);
+            assert_eq!(deps.get_error_output_as_string(), format!("find: {}\n", error));
+            // nothing's searched (or written, or created)
+            assert!(deps.get_start_points().is_empty(), "{:?}", args);
+            assert_eq!(deps.get_output_as_string(), "");
+            assert!(!Path::new(&out).exists());
+        }
+
+        // a format used more than once works each time
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&["find", "./test_data/simple/abbbc", "-printf", "[%f]",
+                               "-fprintf", &out, "[%f]", "-printf", "[%f]"],
+                             &deps),
+                   0);
+        assert_eq!(deps.get_output_as_string(), "[abbbc][abbbc]");
+        assert_eq!(fs::read_to_string(&out).unwrap(), "[abbbc]");
+    }
+
+    #[test]
+    fn find_main_append_output() {
+        let temp_dir = TempDir::new("find_main_append_output").unwrap();
+        let out = temp_dir.path().join("out.txt").to_string_lossy().to_string();
+        let run = |options: &[&str], name: &str| {
+            let deps = FakeDependencies::new();
+            let mut args = vec!["find"];
+            args.extend_from_slice(options);
+            args.extend_from_slice(&["./test_data/simple", "-name", name, "-fprintf", &out,
+                                     "%f\\n"]);
+            assert_eq!(find_main(&args, &deps), 0);
+            fs::read_to_string(&out).unwrap()
+        };
+        assert_eq!(run(&["--append-output"], "abbbc"), "abbbc\n");
+        assert_eq!(run(&["--append-output"], "subdir"), "abbbc\nsubdir\n");
+        // without it, each run starts afresh
+        assert_eq!(run(&[], "subdir"), "subdir\n");
+        assert_eq!(run(&[], "abbbc"), "abbbc\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_main_fprint_unwritable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new("find_main_fprint_unwritable_dir").unwrap();
+        let locked = temp_dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+        let out = locked.join("out.txt").to_string_lossy().to_string();
+        // root can write anything, so there'd be no error
+        let writable = fs::File::create(locked.join("probe")).is_ok();
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", "--append-output", "./test_data/simple", "-fprint", &out],
+                           &deps);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        if !writable {
+            // it's a mistake in the arguments, so nothing's searched
+            assert_eq!(rc, 1);
+            assert_eq!(deps.get_error_output_as_string(),
+                       format!("find: ‘{}’: Permission denied\n", out));
+            assert!(deps.get_start_points().is_empty());
+        }
+    }
+
+    #[test]
+    fn try_find_main_separates_parse_and_runtime_errors() {
+        let registry = MatcherRegistry::default();
+        let deps = FakeDependencies::new();
+        let e = try_find_main(&["find", "-name", "foo", "src"], &registry, &deps).unwrap_err();
+        assert!(e.downcast_ref::<FindError>().is_some());
+        assert!(try_find_main(&["find", "-bogus"], &registry, &deps).is_err());
+        assert_eq!(deps.get_error_output_as_string(), "");
+        assert!(deps.get_start_points().is_empty());
+
+        // problems while searching are reported, and give an exit code of 1
+        let rc = try_find_main(&["find", "./test_data/simple/abbbc", "./no_such_file"],
+                               &registry,
+                               &deps);
+        assert_eq!(rc.unwrap(), 1);
+        assert!(deps.get_error_output_as_string().starts_with("find: ‘./no_such_file’: "));
+        assert_eq!(deps.get_output_as_string(), "./test_data/simple/abbbc\n");
+    }
+
+    #[test]
+    fn find_main_path() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple"), "-sorted", "-path",
+                             "*/subdir/*", "-o", "-ipath", "*/ABB?C"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n\
+                   ./test_data/simple/subdir/ABBBC\n"));
+    }
+
+    #[test]
+    fn find_main_stat_types() {
+        // what the file system says, as opposed to what the walk says
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata(fix_up_slashes("./test_data/simple/abbbc"),
+                          FileMetadata { kind: FileKind::Dir, ..FileMetadata::default() });
+        let run = |args: &[&str]| {
+            let mut args = args.to_vec();
+            let path = fix_up_slashes("./test_data/simple");
+            args.extend_from_slice(&[&path[..], "-name", "abbbc", "-type", "d"]);
+            assert_eq!(find_main(&args, &deps), 0);
+        };
+        run(&["find"]);
+        assert_eq!(deps.get_output_as_string(), "");
+        run(&["find", "--stat-types"]);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n"));
+    }
+
+    #[test]
+    fn find_main_depth_first() {
+        let deps = FakeDependencies::new();
+
+
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple"), "-sorted", "-depth"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n\
+                   ./test_data/simple/subdir/ABBBC\n\
+                   ./test_data/simple/subdir\n\
+                   ./test_data/simple\n"));
+    }
+
+    #[test]
+    fn find_sort_flag() {
+        let temp_dir = TempDir::new("find_sort_flag").unwrap();
+        // create the files in an order that's neither sorted nor reverse-sorted
+        for name in &["c", "a", "d", "B", "b"] {
+            fs::File::create(temp_dir.path().join(name)).unwrap();
+        }
+        let root = temp_dir.path().to_string_lossy().to_string();
+        let expected: Vec<String> = ["", "/B", "/a", "/b", "/c", "/d"]
+            .iter()
+            .map(|suffix| fix_up_slashes(&format!("{}{}", root, suffix)))
+            .collect();
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", "-s", &root], &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), expected.join("\n") + "\n");
+
+        // Without -s, the order is down to the filesystem, so all we can
+        // check is that we got the same entries.
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &root], &deps);
+        assert_eq!(rc, 0);
+        let mut unsorted: Vec<String> =
+            deps.get_output_as_string().lines().map(|s| s.to_string()).collect();
+        unsorted.sort();
+        assert_eq!(unsorted, expected);
+    }
+
+    #[test]
+    fn find_start_point_trailing_slashes() {
+        // Paths are printed with the start point exactly as it was typed, but
+        // no separator is added between it and the rest of the path if it
+        // already ends with one.
+        for &(start_point, expected) in
+            &[("./test_data/simple",
+               "./test_data/simple\n./test_data/simple/abbbc\n./test_data/simple/subdir\n\
+                 ./test_data/simple/subdir/ABBBC\n"),
+              ("./test_data/simple/",
+               "./test_data/simple/\n./test_data/simple/abbbc\n./test_data/simple/subdir\n\
+                 ./test_data/simple/subdir/ABBBC\n"),
+              ("./test_data/simple//",
+               "./test_data/simple//\n./test_data/simple//abbbc\n./test_data/simple//subdir\n\
+                 ./test_data/simple//subdir/ABBBC\n")] {
+            let deps = FakeDependencies::new();
+            let rc = find_main(&["find", &fix_up_slashes(start_point), "-sorted"], &deps);
+
+            assert_eq!(rc, 0);
+            assert_eq!(deps.get_output_as_string(), fix_up_slashes(expected));
+
+            // and the start point's name doesn't include the slashes
+            let deps = FakeDependencies::new();
+            let rc = find_main(&["find", &fix_up_slashes(start_point), "-name", "simple"],
+                               &deps);
+
+            assert_eq!(rc, 0);
+            assert_eq!(deps.get_output_as_string(),
+                       fix_up_slashes(start_point) + "\n");
+        }
+    }
+
+    #[test]
+    fn find_many_alternatives() {
+        // enough -name and -iname tests to be collapsed into one matcher,
+        // which has to find the same things
+        let mut args = vec!["find".to_string(),
+                            fix_up_slashes("./test_data/simple"),
+                            "-sorted".to_string(),
+                            "(".to_string()];
+        for i in 0..1000 {
+            if i > 0 {
+                args.push("-o".to_string());
+            }
+            args.push(if i % 2 == 0 { "-name" } else { "-iname" }.to_string());
+            args.push(match i {
+                500 => "ab*".to_string(),
+                501 => "SUBDIR".to_string(),
+                _ => format!("*.{}", i),
+            });
+        }
+        args.push(")".to_string());
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&args, &deps), 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n./test_data/simple/subdir\n"));
+    }
+
+    #[test]
+    fn find_name_of_start_points() {
+        let mut cases = vec![(".", "."), ("./", "."), ("./test_data/", "test_data")];
+        if cfg!(unix) {
+            cases.push(("/", "/"));
+        }
+        for &(start_point, name) in &cases {
+            for predicate in &["-name", "-iname"] {
+                let deps = FakeDependencies::new();
+                let rc = find_main(&["find", start_point, "-maxdepth", "0", predicate, name],
+                                   &deps);
+                assert_eq!(rc, 0);
+                assert_eq!(deps.get_output_as_string(), format!("{}\n", start_point));
+            }
+        }
+    }
+
+    #[test]
+    fn find_start_points_are_evaluated() {
+        // a start point that's a file, not a directory
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple/abbbc"), "-print"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n"));
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", ".", "-maxdepth", "0", "-type", "d"], &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), ".\n");
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple/abbbc"), "-type", "d"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), "");
+    }
+
+    #[test]
+    fn find_nonexistent_start_point() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/does_not_exist"),
+                             &fix_up_slashes("./test_data/simple/abbbc")],
+                           &deps);
+
+        // the other start points are still processed
+        assert_eq!(rc, 1);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n"));
+        assert!(deps.get_error_output_as_string()
+            .starts_with(&format!("find: ‘{}’: ", fix_up_slashes("./test_data/does_not_exist"))));
+    }
+
+    #[test]
+    fn parse_args_posix_mode() {
+        let posix_config = || {
+            Config {
+                posix_mode: true,
+                ..Config::default()
+            }
+        };
+        let registry = MatcherRegistry::default();
+        let cases: &[(&[&str], &str)] = &[(&["-iname", "foo"], "-iname"),
+                                          (&["-maxdepth", "1"], "-maxdepth"),
+                                          (&["-name", "foo", "-or", "-print0"], "-or")];
+        for &(args, offending) in cases {
+            assert!(super::parse_args(args, &registry).is_ok());
+            let e = super::parse_args_with_config(args, &registry, posix_config())
+                .err()
+                .expect("parse_args should have returned an error");
+            assert_eq!(e.to_string(),
+                       format!("{} is not supported in POSIX mode", offending));
+        }
+        let parsed_info =
+            super::parse_args_with_config(&["-name", "foo", "-o", "!", "(", "-size", "2c", ")"],
+                                          &registry,
+                                          posix_config())
+                .expect("parsing should succeed");
+        assert!(parsed_info.config.posix_mode);
+
+        let parsed_info = super::parse_args(&["--posix", "foo"], &registry)
+            .expect("parsing should succeed");
+        assert!(parsed_info.config.posix_mode);
+        assert_eq!(parsed_info.paths, ["foo"]);
+    }
+
+    #[test]
+    fn find_posixly_correct() {
+        let path = fix_up_slashes("./test_data/size");
+        let run = |args: &[&str], posixly_correct: bool| {
+            let mut deps = FakeDependencies::new();
+            if posixly_correct {
+                deps.set_env_var("POSIXLY_CORRECT", "1");
+            }
+            let mut full_args = vec!["find", &path];
+            full_args.extend_from_slice(args);
+            let rc = find_main(&full_args, &deps);
+            (rc, deps.get_output_as_string())
+        };
+        let found = (0, fix_up_slashes("./test_data/size/512bytes\n"));
+        let failed = (1, String::new());
+
+        // without POSIXLY_CORRECT, extensions work
+        assert_eq!(run(&["-iname", "512BYTES"], false), found);
+        assert_eq!(run(&["-size", "1k"], false), found);
+        // but with it (or --posix), they're errors
+        assert_eq!(run(&["-iname", "512BYTES"], true), failed);
+        assert_eq!(run(&["-size", "1k"], true), failed);
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&["find", "--posix", &path, "-iname", "512BYTES"], &deps), 1);
+        // and what's in POSIX works the same either way
+        for &posixly_correct in &[false, true] {
+            assert_eq!(run(&["-name", "512bytes"], posixly_correct), found);
+            assert_eq!(run(&["-size", "1", "-size", "512c"], posixly_correct), found);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_posixly_correct_perm() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new("find_posixly_correct_perm").unwrap();
+        let root = temp_dir.path().to_string_lossy().to_string();
+        let file = temp_dir.path().join("file");
+        fs::File::create(&file).unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o222)).unwrap();
+
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&["find", &root, "-perm", "+w"], &deps), 1);
+        assert_eq!(deps.get_output_as_string(), "");
+
+        // POSIX reads +w as a=w
+        let mut deps = FakeDependencies::new();
+        deps.set_env_var("POSIXLY_CORRECT", "");
+        assert_eq!(find_main(&["find", &root, "-perm", "+w"], &deps), 0);
+        assert_eq!(deps.get_output_as_string(),
+                   format!("{}\n", file.to_string_lossy()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_follow_links() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new("find_follow_links").unwrap();
+        let root = temp_dir.path().to_string_lossy().into_owned();
+        fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        fs::File::create(temp_dir.path().join("dir").join("file")).unwrap();
+        symlink("dir", temp_dir.path().join("link")).unwrap();
+        symlink("..", temp_dir.path().join("dir").join("loop")).unwrap();
+        symlink("nowhere", temp_dir.path().join("broken")).unwrap();
+        let find = |args: &[&str]| {
+            let deps = FakeDependencies::new();
+            let mut all_args = vec!["find"];
+            all_args.extend_from_slice(args);
+            let rc = find_main(&all_args, &deps);
+            let output = deps.get_output_as_string().replace(&root, "");
+            (rc, output, deps.get_error_output_as_string().replace(&root, ""))
+        };
+
+        // by default, links are just links, and aren't followed
+        assert_eq!(find(&["-s", &root, "-type", "l"]),
+                   (0, "/broken\n/dir/loop\n/link\n".to_string(), String::new()));
+        assert_eq!(find(&["-P", &root, "-sorted", "-type", "d"]),
+                   (0, "\n/dir\n".to_string(), String::new()));
+        assert_eq!(find(&[&root, "-sorted", "-name", "file"]),
+                   (0, "/dir/file\n".to_string(), String::new()));
+
+        // with -L, linked directories are searched, but loops are reported
+        // rather than followed (the broken link isn't an error, though: it's
+        // just a link)
+        let (rc, output, errors) = find(&["-L", &root, "-sorted", "-name", "file"]);
+        assert_eq!((rc, output), (1, "/dir/file\n/link/file\n".to_string()));
+        let errors: Vec<&str> =
+            errors.lines().map(|line| line.split("’: ").next().unwrap()).collect();
+        assert_eq!(errors, vec!["find: ‘/dir/loop", "find: ‘/link/loop"]);
+        let (_, output, _) = find(&["-L", "-s", &root, "-type", "d"]);
+        assert_eq!(output, "\n/dir\n/link\n");
+        let (_, output, _) = find(&["-L", "-s", &root, "-type", "l"]);
+        assert_eq!(output, "/broken\n");
+
+        // with -H, only start points are followed (and the last of -P, -H
+        // and -L wins)
+        let link = format!("{}/link", root);
+        assert_eq!(find(&["-H", "-s", &link, "-type", "d"]),
+                   (0, "/link\n".to_string(), String::new()));
+        assert_eq!(find(&["-H", "-s", &link, "-type", "l"]),
+                   (0, "/link/loop\n".to_string(), String::new()));
+        assert_eq!(find(&["-H", "-s", &root, "-type", "l"]),
+                   (0, "/broken\n/dir/loop\n/link\n".to_string(), String::new()));
+        assert_eq!(find(&["-L", "-P", "-H", "-s", &link, "-type", "d"]),
+                   (0, "/link\n".to_string(), String::new()));
+        assert_eq!(find(&["-H", "-P", "-s", &link, "-type", "d"]),
+                   (0, String::new(), String::new()));
+
+        // and only then is a start point that's a link to a directory
+        // searched: with -P, it's just a link
+        assert_eq!(find(&["-P", "-s", &link]),
+                   (0, "/link\n".to_string(), String::new()));
+        assert_eq!(find(&["-H", "-s", &link]),
+                   (0, "/link\n/link/file\n/link/loop\n".to_string(), String::new()));
+        assert_eq!(find(&["-P", "-s", &link, "-mindepth", "1"]),
+                   (0, String::new(), String::new()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_reports_metadata_errors() {
+        let mut deps = FakeDependencies::new();
+        deps.set_metadata_error("./test_data/simple/abbbc", ::libc::EACCES);
+        let rc = find_main(&["find", "./test_data/simple", "-sorted", "-size", "-100k"], &deps);
+
+        // the entry that couldn't be looked at doesn't match, but the others
+        // are still looked at
+        assert_eq!(rc, 1);
+        assert_eq!(deps.get_output_as_string(),
+                   "./test_data/simple\n./test_data/simple/subdir\n\
+                    ./test_data/simple/subdir/ABBBC\n");
+        assert_eq!(deps.get_error_output_as_string(),
+                   "find: ‘./test_data/simple/abbbc’: Permission denied\n");
+    }
+
+    #[test]
+    fn find_maxdepth() {
+        let deps = FakeDependencies::new();
+
+        let rc =
+            find_main(&["find", &fix_up_slashes("./test_data/depth"), "-sorted", "-maxdepth", "2"],
+                      &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/depth\n\
+                   ./test_data/depth/1\n\
+                   ./test_data/depth/1/2\n\
+                   ./test_data/depth/1/f1\n\
+                   ./test_data/depth/f0\n"));
+    }
+
+    #[test]
+    fn find_maxdepth_depth_first() {
+        let deps = FakeDependencies::new();
+
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/depth"),
+                             "-sorted",
+                             "-maxdepth",
+                             "2",
+                             "-depth"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/depth/1/2\n\
+                   ./test_data/depth/1/f1\n\
+                   ./test_data/depth/1\n\
+                   ./test_data/depth/f0\n\
+                   ./test_data/depth\n"));
+    }
+
+    #[test]
+    fn find_global_options_after_tests() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/depth"),
+                             "-sorted",
+                             "-name",
+                             "f*",
+                             "-maxdepth",
+                             "1"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/depth/f0\n"));
+        assert_eq!(deps.get_error_output_as_string(),
+                   "find: warning: you have specified the global option -maxdepth after the \
+                    argument -name, but global options are not positional, i.e., -maxdepth \
+                    affects tests specified before it as well as those specified after it.  \
+                    Please specify global options before other arguments.\n");
+
+        // -nowarn works wherever it is
+        for args in &[["-nowarn", "-name", "f*", "-maxdepth", "1"],
+                      ["-name", "f*", "-maxdepth", "1", "-nowarn"]] {
+            let deps = FakeDependencies::new();
+            let dir = fix_up_slashes("./test_data/depth");
+            let mut find_args = vec!["find", &dir, "-sorted"];
+            find_args.extend(args.iter());
+            assert_eq!(find_main(&find_args, &deps), 0);
+            assert_eq!(deps.get_output_as_string(),
+                       fix_up_slashes("./test_data/depth/f0\n"));
+            assert_eq!(deps.get_error_output_as_string(), "");
+        }
+
+        // options that come first, or that aren't global, don't get a warning
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/depth"),
+                             "-xdev",
+                             "-noleaf",
+                             "-ignore_readdir_race",
+                             "-name",
+                             "f0",
+                             "-regextype",
+                             "posix-extended",
+                             "-warn"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/depth/f0\n"));
+        assert_eq!(deps.get_error_output_as_string(), "");
+    }
+
+    #[test]
+    fn find_precedence_hint() {
+        // only an explicit -warn gives the hint...
+        for &(warn, hint) in &[(None, false), (Some("-warn"), true), (Some("-nowarn"), false)] {
+            let deps = FakeDependencies::new();
+            let dir = fix_up_slashes("./test_data/simple");
+            let mut args = vec!["find", &dir, "-sorted"];
+            args.extend(warn);
+            args.extend(&["-name", "abbbc", "-o", "-name", "subdir", "-print"]);
+            assert_eq!(find_main(&args, &deps), 0);
+            // ...which doesn't change what's done
+            assert_eq!(deps.get_output_as_string(),
+                       fix_up_slashes("./test_data/simple/subdir\n"));
+            let error_output = deps.get_error_output_as_string();
+            if hint {
+                assert!(error_output.starts_with("find: warning: the actions in OR( \
+                                                  name(abbbc), AND( name(subdir), print ) ) \
+                                                  only apply to some of its alternatives"),
+                        "{}",
+                        error_output);
+            } else {
+                assert_eq!(error_output, "");
+            }
+        }
+
+        // and with parentheses, there's nothing to warn about
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/simple"),
+                             "-sorted",
+                             "-warn",
+                             "(",
+                             "-name",
+                             "abbbc",
+                             "-o",
+                             "-name",
+                             "subdir",
+                             ")",
+                             "-print"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n./test_data/simple/subdir\n"));
+        assert_eq!(deps.get_error_output_as_string(), "");
+    }
+
+    #[test]
+    fn parse_global_options() {
+        let registry = MatcherRegistry::default();
+        let parsed = parse_args(&["-name", "x", "-follow", "-daystart", "-mount"], &registry)
+            .unwrap();
+        assert!(parsed.config.follow_links);
+        assert!(parsed.config.day_start);
+        assert!(parsed.config.same_file_system);
+        assert_eq!(parsed.config.warnings.len(), 3);
+
+        let parsed = parse_args(&["-ignore_readdir_race", "-noignore_readdir_race"], &registry)
+            .unwrap();
+        assert!(!parsed.config.ignore_readdir_race);
+        assert!(parsed.config.warnings.is_empty());
+
+        let e = parse_args(&["-regextype", "perl"], &registry).err().unwrap();
+        assert!(e.to_string().starts_with("Unknown regular expression type ‘perl’; valid types \
+                                           are ‘findutils-default’, ‘ed’"),
+                "{}",
+                e);
+        assert!(parse_args(&["-regextype"], &registry).is_err());
+
+        let parsed = parse_args(&["-regextype", "posix-basic"], &registry).unwrap();
+        assert_eq!(parsed.config.regex_type, RegexType::Basic);
+        let parsed = parse_args(&["-E", "-regextype", "egrep"], &registry).unwrap();
+        assert_eq!(parsed.config.regex_type, RegexType::Extended);
+        let e = parse_args(&["-E", "-regextype", "posix-basic"], &registry).err().unwrap();
+        assert_eq!(e.to_string(), "-E conflicts with -regextype posix-basic");
+    }
+
+    #[test]
+    fn find_regex_extended() {
+        // with -E, "(" and "|" are operators...
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", "-E", "./test_data/simple", "-regex", ".*/(abbbc|nothing)"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n"));
+
+        // ...but without it, they're matched literally
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", "./test_data/simple", "-regex", ".*/(abbbc|nothing)"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), "");
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             "./test_data/simple",
+                             "-sorted",
+                             "-iregex",
+                             ".*/\\(ABBBC\\|nothing\\)"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n./test_data/simple/subdir/ABBBC\n"));
+    }
+
+    #[test]
+    fn config_builder() {
+        let config = Config::builder()
+            .min_depth(1)
+            .max_depth(3)
+            .follow(FollowMode::L)
+            .depth_first(true)
+            .same_file_system(true)
+            .day_start(true)
+            .sort_entries(true)
+            .build()
+            .unwrap();
+        assert_eq!((config.min_depth, config.max_depth), (1, 3));
+        assert!(config.follow_links && config.depth_first && config.same_file_system);
+        assert!(config.day_start && config.sort_entries);
+        assert!(!Config::builder().follow(FollowMode::P).build().unwrap().follow_links);
+        let config = Config::builder().follow(FollowMode::H).build().unwrap();
+        assert!(!config.follow_links && config.follow_start_points);
+        assert_eq!(Config::builder().threads(4).build().unwrap().threads, 4);
+        assert_eq!(Config::builder().threads(0).build().unwrap().threads, 1);
+
+        // the depths can be the same, but the range can't be empty
+        assert!(Config::builder().min_depth(2).max_depth(2).build().is_ok());
+        assert!(Config::builder().min_depth(2).build().is_ok());
+        assert_eq!(Config::builder().min_depth(3).max_depth(2).build().err(),
+                   Some(FindError::MinDepthAboveMaxDepth {
+                       min_depth: 3,
+                       max_depth: 2,
+                   }));
+    }
+
+    #[test]
+    fn find_prune() {
+        let deps = FakeDependencies::new();
+
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/depth"),
+                             "-sorted",
+                             "-print",
+                             ",",
+                             "-name",
+                             "1",
+                             "-prune"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/depth\n\
+                   ./test_data/depth/1\n\
+                   ./test_data/depth/f0\n"));
+    }
+
+    #[test]
+    fn find_zero_maxdepth() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/depth"), "-maxdepth", "0"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/depth\n"));
+    }
+
+    #[test]
+    fn find_zero_maxdepth_depth_first() {
         let deps = FakeDependencies::new();
         let rc =
             find_main(&["find", &fix_up_slashes("./test_data/depth"), "-maxdepth", "0", "-depth"],
@@ -437,6 +2168,565 @@ mod tests {
                    ./test_data/depth/1/2/f2\n"));
     }
 
+    #[test]
+    fn find_print_matches_formatted_output() {
+        // -print writes raw bytes rather than going through to_string_lossy
+        // and writeln!, but for ordinary file names the result should be
+        // exactly the same.
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &fix_up_slashes("./test_data"), "-sorted", "-print"],
+                           &deps);
+        assert_eq!(rc, 0);
+
+        let mut expected = String::new();
+        for entry in WalkDir::new(fix_up_slashes("./test_data")).sort_by(|a, b| a.cmp(b)) {
+            expected.push_str(&format!("{}\n", entry.unwrap().path().to_string_lossy()));
+        }
+        assert_eq!(deps.get_output_as_string(), expected);
+    }
+
+    #[test]
+    fn find_print0() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/simple"), "-sorted", "-print0"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple\0./test_data/simple/abbbc\0\
+                                   ./test_data/simple/subdir\0./test_data/simple/subdir/ABBBC\0"));
+    }
+
+    #[test]
+    fn find_printf() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/simple"),
+                             "-sorted",
+                             "-type",
+                             "f",
+                             "-printf",
+                             "%d %-6f[%P]\\n"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("1 abbbc [abbbc]\n2 ABBBC [subdir/ABBBC]\n"));
+    }
+
+    #[test]
+    fn find_debug_rates() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             "-D",
+                             "rates",
+                             &fix_up_slashes("test_data/simple"),
+                             "(",
+                             "-name",
+                             "a*",
+                             "-o",
+                             "-type",
+                             "d",
+                             ")",
+                             "-type",
+                             "f",
+                             "-print"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("test_data/simple/abbbc\n"));
+        // four entries, of which only abbbc is named a*; the other three are
+        // tested for being directories, which two of them are
+        assert_eq!(deps.get_error_output_as_string(),
+                   "-name a*: 4 evaluations, 1 matches (25.0%)\n\
+                    -type d: 3 evaluations, 2 matches (66.7%)\n\
+                    -type f: 3 evaluations, 1 matches (33.3%)\n\
+                    -print: 1 evaluations, 1 matches (100.0%)\n");
+    }
+
+    #[test]
+    fn find_debug_stat() {
+        let entries = WalkDir::new("test_data").into_iter().count();
+        let dirs = WalkDir::new("test_data")
+            .into_iter()
+            .filter(|entry| entry.as_ref().unwrap().file_type().is_dir())
+            .count();
+        let run = |expression: &[&str]| {
+            let deps = FakeDependencies::new();
+            let mut args = vec!["find", "-D", "stat", "test_data"];
+            args.extend_from_slice(expression);
+            assert_eq!(find_main(&args, &deps), 0);
+            deps.get_error_output_as_string()
+        };
+        let report = |stat_calls: usize, dirs_read: usize, commands_run: usize| {
+            format!("stat calls: {}\ndirectories read: {}\ncommands run: {}\n",
+                    stat_calls,
+                    dirs_read,
+                    commands_run)
+        };
+
+        // names and types come from the directories themselves
+        assert_eq!(run(&["-name", "abbbc"]), report(0, dirs, 0));
+        assert_eq!(run(&["-type", "f", "-print"]), report(0, dirs, 0));
+        // sizes don't, but each entry's metadata is only looked up once,
+        // however many things want it
+        assert_eq!(run(&["-size", "-1G"]), report(entries, dirs, 0));
+        assert_eq!(run(&["-ls"]), report(entries, dirs, 0));
+        assert_eq!(run(&["-size", "-1G", "-ls", "-mtime", "-100000", "-perm", "-0"]),
+                   report(entries, dirs, 0));
+        // and pruned directories aren't read
+        assert_eq!(run(&["-name", "simple", "-prune", "-o", "-print"]), report(0, dirs - 2, 0));
+        assert_eq!(run(&["-maxdepth", "0"]), report(0, 0, 0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_debug_stat_commands() {
+        let run = |expression: &[&str]| {
+            let deps = FakeDependencies::new();
+            let mut args = vec!["find", "-D", "stat", "test_data/simple"];
+            args.extend_from_slice(expression);
+            assert_eq!(find_main(&args, &deps), 0);
+            deps.get_error_output_as_string()
+        };
+        assert_eq!(run(&["-type", "f", "-exec", "true", ";"]),
+                   "stat calls: 0\ndirectories read: 2\ncommands run: 2\n");
+        // batches run once the walk's over count too
+        assert_eq!(run(&["-exec", "true", "{}", "+"]),
+                   "stat calls: 0\ndirectories read: 2\ncommands run: 1\n");
+    }
+
+    #[test]
+    fn find_debug_options() {
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&["find", "-D", "rates,rates", "test_data/simple", "-quit"], &deps),
+                   0);
+        assert_eq!(deps.get_error_output_as_string(),
+                   "-quit: 1 evaluations, 1 matches (100.0%)\n");
+
+        let unknown = "unknown debug option ‘nonsense’ (valid options are: rates, stat, tree)";
+        for &(args, message) in &[(&["-D", "rates,nonsense", "foo"][..], unknown),
+                                  (&["-D"][..], "missing argument to -D")] {
+            match super::parse_args(args, &MatcherRegistry::default()) {
+                Err(e) => assert_eq!(e.to_string(), message),
+                Ok(_) => panic!("parse_args should have failed for {:?}", args),
+            }
+        }
+    }
+
+    #[test]
+    fn find_debug_tree() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             "-D",
+                             "tree,rates",
+                             &fix_up_slashes("test_data/simple"),
+                             "-name",
+                             "nothing",
+                             "-o",
+                             "-not",
+                             "-type",
+                             "d"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("test_data/simple/abbbc\ntest_data/simple/subdir/ABBBC\n"));
+        // the rates are reported after the tree, which doesn't show that
+        // they're being counted
+        let errors = deps.get_error_output_as_string();
+        assert!(errors.starts_with("AND( OR( name(nothing), NOT( type(d) ) ), print )\n"),
+                "{:?}",
+                errors);
+        assert_eq!(errors.lines().count(), 3);
+    }
+
+    #[test]
+    fn find_ls() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &fix_up_slashes("test_data/simple"), "-name", "abbbc", "-ls"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        let output = deps.get_output_as_string();
+        assert!(output.ends_with(&fix_up_slashes(" test_data/simple/abbbc\n")),
+                "{:?}",
+                output);
+        assert_eq!(output.lines().count(), 1);
+    }
+
+    #[test]
+    fn find_printf_unknown_directives() {
+        let temp_dir = TempDir::new("find_printf_unknown_directives").unwrap();
+        for name in &["a", "b", "c"] {
+            fs::File::create(temp_dir.path().join(name)).unwrap();
+        }
+        let dir = temp_dir.path().to_string_lossy().to_string();
+
+        // the warning's given once, however often the directive's used, and
+        // the directive's written as it is every time
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &dir, "-type", "f", "-printf", "%z|%-3z\\n"], &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), "%z|%-3z\n".repeat(3));
+        assert_eq!(deps.get_error_output_as_string(),
+                   "find: warning: unrecognized format directive ‘%z’\n");
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &dir, "-nowarn", "-type", "f", "-printf", "%z\\n"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), "%z\n".repeat(3));
+        assert_eq!(deps.get_error_output_as_string(), "");
+
+        // unlike a % at the end, which is an error
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &dir, "-printf", "%z%"], &deps);
+        assert_eq!(rc, 1);
+        assert_eq!(deps.get_output_as_string(), "");
+        assert_eq!(deps.get_error_output_as_string(), "find: % at end of format string\n");
+    }
+
+    #[test]
+    fn find_printf_nested_start_points() {
+        // the same files are found under both start points, and each time
+        // %H and %P are relative to the start point they were found under
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             "test_data",
+                             &fix_up_slashes("test_data/simple"),
+                             "-sorted",
+                             "(",
+                             "-name",
+                             "simple",
+                             "-o",
+                             "-name",
+                             "abbbc",
+                             ")",
+                             "-printf",
+                             "%H|%P|%p\\n"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("test_data|simple|test_data/simple\n\
+                                   test_data|simple/abbbc|test_data/simple/abbbc\n\
+                                   test_data/simple||test_data/simple\n\
+                                   test_data/simple|abbbc|test_data/simple/abbbc\n"));
+    }
+
+    #[test]
+    fn find_printf_start_points_with_common_prefix() {
+        // "a" is a prefix of "ab" as a string, but not as a path
+        let temp_dir = TempDir::new("find_printf_start_points_with_common_prefix").unwrap();
+        for dir in &["a", "ab"] {
+            fs::create_dir(temp_dir.path().join(dir)).unwrap();
+            fs::File::create(temp_dir.path().join(dir).join("file")).unwrap();
+        }
+        let a = temp_dir.path().join("a").to_string_lossy().to_string();
+        let ab = temp_dir.path().join("ab").to_string_lossy().to_string();
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &a, &ab, "-sorted", "-printf", "%P|%f\\n"], &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), "|a\nfile|file\n|ab\nfile|file\n");
+
+        // and the same goes for start points with trailing slashes
+        let a_slash = format!("{}{}", a, ::std::path::MAIN_SEPARATOR);
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find", &a_slash, "-sorted", "-printf", "%P|%H\\n"], &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   format!("|{0}\nfile|{0}\n", a_slash));
+    }
+
+    /// A writer that records the size of every write made to it.
+    struct RecordingWriter {
+        writes: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl Write for RecordingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes.borrow_mut().push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn standard_dependencies_buffer_output() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let deps = StandardDependencies::with_output(Box::new(RecordingWriter {
+            writes: writes.clone(),
+        }));
+
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/depth")], &deps);
+        assert_eq!(rc, 0);
+
+        // 9 entries were printed, but only with a single write to the
+        // underlying writer.
+        let expected_len: usize = WalkDir::new(fix_up_slashes("./test_data/depth"))
+            .into_iter()
+            .map(|e| e.unwrap().path().to_string_lossy().len() + 1)
+            .sum();
+        assert_eq!(*writes.borrow(), vec![expected_len]);
+    }
+
+    /// A writer whose reader has gone away.
+    struct BrokenPipeWriter;
+
+    impl Write for BrokenPipeWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn find_ignores_broken_pipe() {
+        let deps = StandardDependencies::with_output(Box::new(BrokenPipeWriter));
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/depth")], &deps);
+        assert_eq!(rc, 0);
+    }
+
+    /// A writer with no room for anything (like /dev/full), which counts how
+    /// often it's been asked to write.
+    struct FullWriter {
+        attempts: Rc<Cell<usize>>,
+    }
+
+    impl Write for FullWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            self.attempts.set(self.attempts.get() + 1);
+            Err(io::Error::new(io::ErrorKind::Other, "No space left on device"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A writer whose output can still be looked at once it's been boxed.
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn find_reports_write_errors() {
+        let attempts = Rc::new(Cell::new(0));
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let deps = StandardDependencies {
+            error_output: Rc::new(RefCell::new(SharedBuffer(errors.clone()))),
+            ..StandardDependencies::with_output(Box::new(FullWriter { attempts: attempts.clone() }))
+        };
+        // (\\c flushes the output after each file)
+        let rc = find_main(&["find", &fix_up_slashes("./test_data/depth"), "-printf", "%p\\c"],
+                           &deps);
+        assert_eq!(rc, 1);
+        // it's only said once, and find gives up straight away (the second
+        // attempt is the flush at the end)
+        assert_eq!(String::from_utf8(errors.borrow().clone()).unwrap(),
+                   "find: write error: No space left on device\n");
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[test]
+    fn find_quit() {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/simple"),
+                             &fix_up_slashes("./test_data/depth"),
+                             "-sorted",
+                             "-print",
+                             "-quit"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple
+"));
+
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/simple"),
+                             "-sorted",
+                             "-name",
+                             "subdir",
+                             "-quit",
+                             "-print"],
+                           &deps);
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(), "");
+    }
+
+    /// Matcher that counts the number of times it's told find has finished,
+    /// and fails when it is.
+    struct FailsWhenFinished {
+        finished_count: Rc<RefCell<usize>>,
+    }
+
+    impl Matcher for FailsWhenFinished {
+        fn matches(&self, _: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+            matcher_io.quit();
+            true
+        }
+
+        fn finished(&self, matcher_io: &mut MatcherIO) {
+            *self.finished_count.borrow_mut() += 1;
+            matcher_io.set_exit_code(1);
+        }
+
+        fn describe(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "fails_when_finished")
+        }
+    }
+
+    #[test]
+    fn process_paths_calls_finished_once() {
+        let finished_count = Rc::new(RefCell::new(0));
+        let parsed_info = ParsedInfo {
+            matcher: Box::new(FailsWhenFinished { finished_count: finished_count.clone() }),
+            paths: vec![fix_up_slashes("./test_data/simple"), fix_up_slashes("./test_data/depth")],
+            config: Config::default(),
+        };
+        let deps = FakeDependencies::new();
+
+        // the matcher quits on the very first entry, but still gets to fail
+        // once find has finished.
+        assert_eq!(process_paths(&parsed_info, &deps), 1);
+        assert_eq!(*finished_count.borrow(), 1);
+    }
+
+    #[test]
+    fn find_ok_declined() {
+        // Declining means the command isn't run (so it doesn't matter that
+        // it doesn't exist) and the file doesn't match.
+        let mut deps = FakeDependencies::new();
+        deps.set_input("n\n");
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/simple/abbbc"),
+                             "-ok",
+                             "no-such-command-findutils",
+                             "{}",
+                             ";",
+                             "-o",
+                             "-print"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple/abbbc\n"));
+    }
+
+    #[test]
+    fn find_threads() {
+        // enough files for plenty of batches
+        let temp_dir = TempDir::new("find_threads").unwrap();
+        for i in 0..50 {
+            let dir = temp_dir.path().join(format!("dir{}", i));
+            fs::create_dir(&dir).unwrap();
+            for j in 0..40 {
+                fs::write(dir.join(format!("file{}.{}", j, if j % 3 == 0 { "c" } else { "h" })),
+                          vec![b'x'; i * j])
+                    .unwrap();
+            }
+        }
+        let temp_dir_path = temp_dir.path().to_string_lossy().to_string();
+        let expressions: &[&[&str]] = &[&[],
+                                        &["-name", "*.c"],
+                                        &["-type", "f", "!", "-size", "-1k", "-print0"],
+                                        &["(", "-iname", "FILE1*", "-o", "-path", "*7/*", ")",
+                                          "-printf", "%p %d %s\n"],
+                                        &["-name", "*.h", ",", "-type", "d", "-ls"]];
+        for expression in expressions {
+            let run = |threads: &str| {
+                let deps = FakeDependencies::new();
+                let mut args = vec!["find", "-s", threads, "test_data", &temp_dir_path];
+                args.extend_from_slice(expression);
+                assert_eq!(find_main(&args, &deps), 0);
+                assert_eq!(deps.get_error_output_as_string(), "");
+                deps.get_output_as_string()
+            };
+            let one_thread = run("--threads=1");
+            assert!(!one_thread.is_empty());
+            assert_eq!(run("--threads=4"), one_thread, "{:?}", expression);
+        }
+
+        // fake metadata gives the same answers with any number of threads
+        let run = |threads: &str| {
+            let mut deps = FakeDependencies::new();
+            deps.set_metadata(fix_up_slashes("./test_data/simple/abbbc"),
+                              FileMetadata { len: 5000, ..FileMetadata::default() });
+            let path = fix_up_slashes("./test_data/simple");
+            assert_eq!(find_main(&["find", "-s", threads, &path, "-size", "+4k"], &deps),
+                       0);
+            deps.get_output_as_string()
+        };
+        let one_thread = run("--threads=1");
+        assert_eq!(one_thread, fix_up_slashes("./test_data/simple/abbbc\n"));
+        assert_eq!(run("--threads=4"), one_thread);
+
+        // the tests are only evaluated on other threads if they all can be
+        let registry = MatcherRegistry::default();
+        let threads = |args: &[&str]| parse_args(args, &registry).unwrap().config.threads;
+        assert_eq!(threads(&["--threads=4", ".", "-name", "*.c", "-print"]), 4);
+        assert_eq!(threads(&["--threads=4", ".", "-name", "*.c", "-prune"]), 1);
+        assert_eq!(threads(&["--threads=4", ".", "-fprint", "/dev/null"]), 1);
+        assert_eq!(threads(&["-D", "rates", "--threads=4", ".", "-name", "*.c"]), 1);
+
+        for bad in &["0", "-1", "four", ""] {
+            let args = ["--threads=".to_string() + bad];
+            assert_eq!(parse_args(&[&args[0]], &registry).err().unwrap().to_string(),
+                       format!("invalid argument ‘{}’ for ‘--threads’ (expected a number of \
+                                threads, at least 1)",
+                               bad));
+        }
+    }
+
+    #[test]
+    fn find_threads_fall_back_for_ok() {
+        // -ok asks about each file in turn, so the whole expression has to be
+        // evaluated on one thread
+        let mut deps = FakeDependencies::new();
+        deps.set_input("n\nn\nn\nn\n");
+        let rc = find_main(&["find",
+                             "-s",
+                             "--threads=4",
+                             &fix_up_slashes("./test_data/simple"),
+                             "-ok",
+                             "no-such-command-findutils",
+                             "{}",
+                             ";",
+                             "-o",
+                             "-print"],
+                           &deps);
+
+        assert_eq!(rc, 0);
+        assert_eq!(deps.get_output_as_string(),
+                   fix_up_slashes("./test_data/simple\n./test_data/simple/abbbc\n\
+                                   ./test_data/simple/subdir\n./test_data/simple/subdir/ABBBC\n"));
+        assert!(deps.get_error_output_as_string()
+                    .starts_with("find: warning: --threads=4 is ignored as ‘OR( \
+                                  ok(no-such-command-findutils {}), print )’ can't be \
+                                  evaluated on more than one thread\n"),
+                "{}",
+                deps.get_error_output_as_string());
+    }
+
     #[test]
     fn find_newer() {
         // create a temp directory and file that are newer than the static
@@ -466,6 +2756,15 @@ mod tests {
 
         assert_eq!(rc, 0);
         assert_eq!(deps.get_output_as_string(), "");
+
+        // the file has to exist
+        let deps = FakeDependencies::new();
+        let missing = fix_up_slashes("./test_data/no_such_file");
+        let rc = find_main(&["find", "./test_data/simple", "-newer", &missing], &deps);
+        assert_eq!(rc, 1);
+        assert_eq!(deps.get_output_as_string(), "");
+        assert_eq!(deps.get_error_output_as_string(),
+                   format!("find: ‘{}’: No such file or directory\n", missing));
     }
 
     #[test]
@@ -483,9 +2782,8 @@ mod tests {
     fn find_ctime() {
         let meta = fs::metadata("./test_data/simple/subdir/ABBBC").unwrap();
 
-        // metadata can return errors like StringError("creation time is not available on this platform currently")
-        // so skip tests that won't pass due to shortcomings in std:;fs.
-        if let Ok(file_time) = meta.created() {
+        // not every platform has a status change time
+        if let Some(file_time) = metadata::changed_time(&meta) {
             file_time_helper(file_time, "-ctime");
         }
     }
@@ -505,7 +2803,7 @@ mod tests {
     fn file_time_helper(file_time: SystemTime, arg: &str) {
         // check file time matches a file that's old enough
         {
-            let mut deps = FakeDependencies::new();
+            let deps = FakeDependencies::new();
             deps.set_time(file_time);
 
             let rc = find_main(&["find",
@@ -523,7 +2821,7 @@ mod tests {
 
         // now Check file time doesn't match a file that's too new
         {
-            let mut deps = FakeDependencies::new();
+            let deps = FakeDependencies::new();
             deps.set_time(file_time - Duration::from_secs(1));
 
             let rc = find_main(&["find", "./test_data/simple/subdir", "-type", "f", arg, "0"],
@@ -555,4 +2853,66 @@ mod tests {
         assert_eq!(deps.get_output_as_string(), "");
 
     }
+
+    /// Fills dir with files and directories with similar names, and the
+    /// directories with more of the same, down to the given depth.
+    fn make_prefilter_tree(dir: &Path, depth: usize) {
+        for name in &["ab", "bab", "ab.rs"] {
+            fs::File::create(dir.join(name)).unwrap();
+        }
+        for name in &["a", "Ba"] {
+            let subdir = dir.join(name);
+            fs::create_dir(&subdir).unwrap();
+            if depth > 1 {
+                make_prefilter_tree(&subdir, depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn prefilter_makes_no_difference() {
+        let temp_dir = TempDir::new("prefilter_makes_no_difference").unwrap();
+        make_prefilter_tree(temp_dir.path(), 3);
+        let root = temp_dir.path().to_string_lossy().into_owned();
+
+        let expressions: &[&[&str]] = &[&["-name", "a*"],
+                                         &["-type", "d"],
+                                         &["-type", "f", "-name", "*b*"],
+                                         &["-iname", "B*", "-type", "d"],
+                                         &["-name", "a*", "-print0"],
+                                         &["-type", "d", "-name", "a", "-prune"],
+                                         &["-type", "d", "-name", "Ba", "-prune", "-o", "-print"],
+                                         &["-name", "a*", "-o", "-type", "d"],
+                                         &["-not", "-name", "a*"],
+                                         &["-type", "f", "-name", "bab", "-quit"],
+                                         &["(", "-type", "f", "-name", "*.rs", ")", "-print"],
+                                         &["-name", "a*", ",", "-name", "*b"],
+                                         &["-name", "*a", "-type", "f", "-type", "d"],
+                                         &["-depth", "-type", "d", "-name", "a"],
+                                         &["-maxdepth", "2", "-name", "b*"],
+                                         &["-mindepth", "2", "-type", "f", "-name", "ab"],
+                                         &["-name", "ab", "-false", "-o", "-name", "bab"],
+                                         &["-size", "0", "-name", "ab.rs"]];
+        let mut prefiltered = 0;
+        for expression in expressions {
+            let mut args = vec!["-s", &root];
+            args.extend_from_slice(expression);
+            let run = |use_prefilter| {
+                let mut parsed_info = parse_args(&args, &MatcherRegistry::default()).unwrap();
+                parsed_info.config.use_prefilter = use_prefilter;
+                let deps = FakeDependencies::new();
+                let rc = process_paths(&parsed_info, &deps);
+                (rc, deps.get_output_as_string())
+            };
+            let parsed_info = parse_args(&args, &MatcherRegistry::default()).unwrap();
+            if parsed_info.matcher.prefilter().is_some() {
+                prefiltered += 1;
+            }
+            let with_prefilter = run(true);
+            assert_eq!(with_prefilter, run(false), "{:?}", expression);
+        }
+        // make sure that the prefilter was actually tried (including for the
+        // two expressions that constant folding leaves only one -name of)
+        assert_eq!(prefiltered, 14);
+    }
 }
@@ -0,0 +1,283 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! The ways find writes file names that could contain anything (including
+//! newlines, and bytes that aren't valid UTF-8) so that they can't be
+//! mistaken for something else: escaped as -ls does, quoted for the shell
+//! (-printf's %q), made safe to show on a terminal, or put in JSON (for
+//! -printj). They all work on raw bytes, as that's what file names are.
+
+use std::path::Path;
+use std::str;
+
+/// The raw bytes of a path. On platforms where paths aren't just bytes,
+/// this is a (lossy) conversion to UTF-8.
+#[cfg(unix)]
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Escapes the bytes as GNU find's -ls does: backslash escapes for
+/// backslashes, spaces and the usual control characters, and octal ones
+/// (e.g. "\377") for anything else that isn't printable ASCII.
+pub fn c_escape(bytes: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b' ' => escaped.extend_from_slice(b"\\ "),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            b'\t' => escaped.extend_from_slice(b"\\t"),
+            b'\r' => escaped.extend_from_slice(b"\\r"),
+            0x08 => escaped.extend_from_slice(b"\\b"),
+            0x0b => escaped.extend_from_slice(b"\\v"),
+            0x0c => escaped.extend_from_slice(b"\\f"),
+            b'!'..=b'~' => escaped.push(byte),
+            _ => escaped.extend_from_slice(format!("\\{:03o}", byte).as_bytes()),
+        }
+    }
+    escaped
+}
+
+/// Whether the byte never needs quoting for the shell.
+fn is_shell_safe(byte: u8) -> bool {
+    match byte {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => true,
+        b'_' | b'-' | b'+' | b'=' | b'.' | b',' | b'/' | b':' | b'@' | b'%' => true,
+        _ => false,
+    }
+}
+
+/// Quotes the bytes so that a POSIX shell reads them back as a single word,
+/// exactly as they are: they're left alone if that's safe, and otherwise
+/// put in single quotes (which is safe for everything but single quotes
+/// themselves, each of which becomes '\'').
+pub fn shell_quote(bytes: &[u8]) -> Vec<u8> {
+    if !bytes.is_empty() && bytes.iter().all(|&byte| is_shell_safe(byte)) {
+        return bytes.to_vec();
+    }
+    let mut quoted = Vec::with_capacity(bytes.len() + 2);
+    quoted.push(b'\'');
+    for &byte in bytes {
+        if byte == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// Makes the bytes safe to write to a terminal, as GNU find does for -print:
+/// every character is written as it is, except for control characters and
+/// bytes that aren't part of valid UTF-8, each of which becomes a '?'.
+pub fn tty_literal(bytes: &[u8]) -> Vec<u8> {
+    let mut literal = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (valid, invalid) = match str::from_utf8(rest) {
+            Ok(valid) => (valid, 0),
+            Err(e) => {
+                let valid = str::from_utf8(&rest[..e.valid_up_to()]).unwrap();
+                (valid, e.error_len().unwrap_or(rest.len() - e.valid_up_to()))
+            }
+        };
+        for c in valid.chars() {
+            if c.is_control() {
+                literal.push(b'?');
+            } else {
+                let mut buffer = [0; 4];
+                literal.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+        // an invalid sequence is one unprintable character, however long
+        if invalid > 0 {
+            literal.push(b'?');
+        }
+        rest = &rest[valid.len() + invalid..];
+    }
+    literal
+}
+
+/// Quotes the bytes as a JSON string. JSON strings are Unicode, so bytes
+/// that aren't part of valid UTF-8 become U+FFFD (the replacement
+/// character): to get them back exactly, see `base64`.
+pub fn json_quote(bytes: &[u8]) -> Vec<u8> {
+    let mut quoted = Vec::with_capacity(bytes.len() + 2);
+    quoted.push(b'"');
+    for c in String::from_utf8_lossy(bytes).chars() {
+        match c {
+            '"' => quoted.extend_from_slice(b"\\\""),
+            '\\' => quoted.extend_from_slice(b"\\\\"),
+            '\n' => quoted.extend_from_slice(b"\\n"),
+            '\t' => quoted.extend_from_slice(b"\\t"),
+            '\r' => quoted.extend_from_slice(b"\\r"),
+            '\x08' => quoted.extend_from_slice(b"\\b"),
+            '\x0c' => quoted.extend_from_slice(b"\\f"),
+            c if c < ' ' => quoted.extend_from_slice(format!("\\u{:04x}", c as u32).as_bytes()),
+            c => {
+                let mut buffer = [0; 4];
+                quoted.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+    }
+    quoted.push(b'"');
+    quoted
+}
+
+const BASE64_ALPHABET: &'static [u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz\
+                                             0123456789+/";
+
+/// Encodes the bytes in (standard, padded) base64.
+pub fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names with each sort of awkward character in them, which every
+    /// quoting function is tested with.
+    const NAMES: &'static [&'static [u8]] = &[b"plain.txt",
+                                              b"new\nline",
+                                              b"tab\there",
+                                              b"it's",
+                                              b"back\\slash",
+                                              b"not \xffUTF-8",
+                                              b"bell\x07",
+                                              b"caf\xc3\xa9",
+                                              b"",
+                                              b"-dash"];
+
+    fn check(quote: fn(&[u8]) -> Vec<u8>, expected: &[&[u8]]) {
+        assert_eq!(NAMES.len(), expected.len());
+        for (name, expected) in NAMES.iter().zip(expected) {
+            assert_eq!(String::from_utf8_lossy(&quote(name)),
+                       String::from_utf8_lossy(expected),
+                       "{:?}",
+                       String::from_utf8_lossy(name));
+        }
+    }
+
+    #[test]
+    fn c_escape_table() {
+        check(c_escape,
+              &[b"plain.txt",
+                b"new\\nline",
+                b"tab\\there",
+                b"it's",
+                b"back\\\\slash",
+                b"not\\ \\377UTF-8",
+                b"bell\\007",
+                b"caf\\303\\251",
+                b"",
+                b"-dash"]);
+    }
+
+    #[test]
+    fn shell_quote_table() {
+        check(shell_quote,
+              &[b"plain.txt",
+                b"'new\nline'",
+                b"'tab\there'",
+                b"'it'\\''s'",
+                b"'back\\slash'",
+                b"'not \xffUTF-8'",
+                b"'bell\x07'",
+                b"'caf\xc3\xa9'",
+                b"''",
+                b"-dash"]);
+    }
+
+    #[test]
+    fn tty_literal_table() {
+        check(tty_literal,
+              &[b"plain.txt",
+                b"new?line",
+                b"tab?here",
+                b"it's",
+                b"back\\slash",
+                b"not ?UTF-8",
+                b"bell?",
+                b"caf\xc3\xa9",
+                b"",
+                b"-dash"]);
+        // a truncated sequence at the end is one character too
+        assert_eq!(tty_literal(b"ab\xe2\x82"), b"ab?");
+    }
+
+    #[test]
+    fn json_quote_table() {
+        check(json_quote,
+              &[b"\"plain.txt\"",
+                b"\"new\\nline\"",
+                b"\"tab\\there\"",
+                b"\"it's\"",
+                b"\"back\\\\slash\"",
+                b"\"not \xef\xbf\xbdUTF-8\"",
+                b"\"bell\\u0007\"",
+                b"\"caf\xc3\xa9\"",
+                b"\"\"",
+                b"\"-dash\""]);
+        assert_eq!(json_quote(b"say \"hi\"\x08\x0c\r"), b"\"say \\\"hi\\\"\\b\\f\\r\"".to_vec());
+    }
+
+    #[test]
+    fn base64_encoding() {
+        let cases: &[(&[u8], &str)] = &[(b"", ""),
+                                        (b"f", "Zg=="),
+                                        (b"fo", "Zm8="),
+                                        (b"foo", "Zm9v"),
+                                        (b"foob", "Zm9vYg=="),
+                                        (b"fooba", "Zm9vYmE="),
+                                        (b"foobar", "Zm9vYmFy"),
+                                        (b"not \xffUTF-8", "bm90IP9VVEYtOA=="),
+                                        (b"\xff\xfe\xfd", "//79")];
+        for &(bytes, expected) in cases {
+            assert_eq!(base64(bytes), expected);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_quote_round_trips() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::process::Command;
+
+        for name in NAMES {
+            let mut script = b"printf %s ".to_vec();
+            script.extend(shell_quote(name));
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(OsStr::from_bytes(&script))
+                .output()
+                .unwrap();
+            assert_eq!(output.stdout, name.to_vec());
+        }
+    }
+}
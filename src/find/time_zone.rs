@@ -0,0 +1,263 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Turning times into dates and times of day (for -ls, -printf and
+//! -daystart), in the time zone the TZ environment variable names, or in UTC
+//! with --utc. TZ is read through the dependencies, so a simple offset from
+//! UTC (e.g. "UTC0" or "PST8") is worked out here, without involving the C
+//! library; anything more complicated (daylight saving rules, or the name of
+//! a zone file) is left to the C library's idea of local time. Month and day
+//! names are always the C locale's English ones, whatever the locale (GNU
+//! find uses the locale's), so that output doesn't depend on it.
+
+use std::ffi::OsStr;
+
+pub const MONTHS: [&'static str; 12] = ["January", "February", "March", "April", "May", "June",
+                                        "July", "August", "September", "October", "November",
+                                        "December"];
+
+pub const WEEKDAYS: [&'static str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday",
+                                         "Friday", "Saturday"];
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A date and time of day, as a particular time zone sees a moment.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BrokenDownTime {
+    pub year: i64,
+    /// 0 for January, to 11 for December.
+    pub month: usize,
+    /// The day of the month, from 1.
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 for Sunday, to 6 for Saturday.
+    pub weekday: usize,
+    /// Days since the 1st of January, so from 0 to 365.
+    pub year_day: u32,
+    /// The time zone's abbreviation at that moment (e.g. "PST").
+    pub zone_name: String,
+}
+
+/// Where times are shown for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeZone {
+    /// Local time, as the C library works it out.
+    Local,
+    /// A fixed number of seconds east of UTC, and the zone's abbreviation.
+    Fixed { name: String, offset: i64 },
+}
+
+impl TimeZone {
+    pub fn utc() -> TimeZone {
+        TimeZone::Fixed {
+            name: "UTC".to_string(),
+            offset: 0,
+        }
+    }
+
+    /// The time zone for the given value of TZ. An unset TZ means local
+    /// time, and an empty one means UTC (as POSIX says).
+    pub fn from_tz(tz: Option<&OsStr>) -> TimeZone {
+        match tz.map(|tz| tz.to_str()) {
+            Some(Some("")) => TimeZone::utc(),
+            Some(Some(tz)) => parse_fixed_zone(tz).unwrap_or(TimeZone::Local),
+            _ => TimeZone::Local,
+        }
+    }
+
+    /// Breaks down the given number of seconds since the epoch.
+    pub fn broken_down(&self, seconds: i64) -> BrokenDownTime {
+        match *self {
+            TimeZone::Local => local_time(seconds),
+            TimeZone::Fixed { ref name, offset } => {
+                let mut time = utc_time(seconds + offset);
+                time.zone_name = name.clone();
+                time
+            }
+        }
+    }
+}
+
+/// Parses a TZ value that's just a zone's abbreviation and its offset from
+/// UTC, in POSIX form: e.g. "UTC0", "PST8", "<+0530>-5:30". The offset is in
+/// hours west of UTC, with optional minutes and seconds. Anything else
+/// (including a zone with daylight saving time) gives None.
+fn parse_fixed_zone(tz: &str) -> Option<TimeZone> {
+    let (name, rest) = if tz.starts_with('<') {
+        let end = tz.find('>')?;
+        (&tz[1..end], &tz[end + 1..])
+    } else {
+        let end = tz.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(tz.len());
+        (&tz[..end], &tz[end..])
+    };
+    if name.len() < 3 {
+        return None;
+    }
+    let (sign, rest) = match rest.chars().next() {
+        Some('-') => (-1, &rest[1..]),
+        Some('+') => (1, &rest[1..]),
+        _ => (1, rest),
+    };
+    let mut seconds_west = 0;
+    let mut parts = 0;
+    for (i, part) in rest.split(':').enumerate() {
+        if i > 2 || part.is_empty() || part.len() > 2 ||
+           !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let value: i64 = part.parse().ok()?;
+        seconds_west += value * [3600, 60, 1][i];
+        parts += 1;
+    }
+    if parts == 0 || seconds_west > 24 * 3600 {
+        return None;
+    }
+    Some(TimeZone::Fixed {
+        name: name.to_string(),
+        offset: -sign * seconds_west,
+    })
+}
+
+/// The number of days from the epoch to the given date (Howard Hinnant's
+/// days_from_civil). The month is from 1 to 12 here.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date and time in UTC, without a zone name.
+fn utc_time(seconds: i64) -> BrokenDownTime {
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let seconds_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    // Howard Hinnant's civil_from_days, with years starting in March
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 -
+                       day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 2
+    } else {
+        month_from_march - 10
+    };
+    let year = year_of_era + era * 400 + if month < 2 { 1 } else { 0 };
+    BrokenDownTime {
+        year: year,
+        month: month as usize,
+        day: day as u32,
+        hour: (seconds_of_day / 3600) as u32,
+        minute: (seconds_of_day % 3600 / 60) as u32,
+        second: (seconds_of_day % 60) as u32,
+        // the epoch was a Thursday
+        weekday: (days + 4).rem_euclid(7) as usize,
+        year_day: (days - days_from_civil(year, 1, 1)) as u32,
+        zone_name: String::new(),
+    }
+}
+
+#[cfg(unix)]
+fn local_time(seconds: i64) -> BrokenDownTime {
+    use std::ffi::CStr;
+    use libc;
+
+    let time = seconds as libc::time_t;
+    let mut tm: libc::tm = unsafe { ::std::mem::zeroed() };
+    unsafe {
+        libc::localtime_r(&time, &mut tm);
+    }
+    let zone_name = if tm.tm_zone.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(tm.tm_zone) }.to_string_lossy().into_owned()
+    };
+    BrokenDownTime {
+        year: tm.tm_year as i64 + 1900,
+        month: tm.tm_mon as usize,
+        day: tm.tm_mday as u32,
+        hour: tm.tm_hour as u32,
+        minute: tm.tm_min as u32,
+        second: tm.tm_sec as u32,
+        weekday: tm.tm_wday as usize,
+        year_day: tm.tm_yday as u32,
+        zone_name: zone_name,
+    }
+}
+
+/// There's no portable way to get the local time zone here, so it's UTC.
+#[cfg(not(unix))]
+fn local_time(seconds: i64) -> BrokenDownTime {
+    TimeZone::utc().broken_down(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    fn fixed(name: &str, offset: i64) -> TimeZone {
+        TimeZone::Fixed {
+            name: name.to_string(),
+            offset: offset,
+        }
+    }
+
+    #[test]
+    fn from_tz() {
+        let zone = |tz: &str| TimeZone::from_tz(Some(OsStr::new(tz)));
+        assert_eq!(TimeZone::from_tz(None), TimeZone::Local);
+        assert_eq!(zone(""), TimeZone::utc());
+        assert_eq!(zone("UTC0"), fixed("UTC", 0));
+        assert_eq!(zone("PST8"), fixed("PST", -8 * 3600));
+        assert_eq!(zone("JST-9"), fixed("JST", 9 * 3600));
+        assert_eq!(zone("IST-5:30"), fixed("IST", 19800));
+        assert_eq!(zone("<+0545>-05:45"), fixed("+0545", 20700));
+        assert_eq!(zone("XYZ+1:02:03"), fixed("XYZ", -3723));
+        // daylight saving time, and zone files, are left to the C library
+        for &tz in &["PST8PDT", "EST5EDT,M3.2.0,M11.1.0", "Europe/London", ":UTC", "UTC",
+                     "X0", "ABC1:2:3:4", "ABC25"] {
+            assert_eq!(zone(tz), TimeZone::Local, "{}", tz);
+        }
+    }
+
+    #[test]
+    fn broken_down_fixed() {
+        let time = TimeZone::utc().broken_down(1_000_000_000);
+        assert_eq!(time,
+                   BrokenDownTime {
+                       year: 2001,
+                       month: 8,
+                       day: 9,
+                       hour: 1,
+                       minute: 46,
+                       second: 40,
+                       weekday: 0,
+                       year_day: 251,
+                       zone_name: "UTC".to_string(),
+                   });
+        let time = fixed("PST", -8 * 3600).broken_down(1_000_000_000);
+        assert_eq!((time.day, time.hour, time.weekday, time.year_day), (8, 17, 6, 250));
+        assert_eq!(time.zone_name, "PST");
+
+        // before the epoch, and in a leap year
+        let time = TimeZone::utc().broken_down(-1);
+        assert_eq!((time.year, time.month, time.day, time.hour, time.second, time.weekday),
+                   (1969, 11, 31, 23, 59, 3));
+        assert_eq!(time.year_day, 364);
+        assert_eq!(TimeZone::utc().broken_down(951_782_400).year_day, 59); // 29 Feb 2000
+        assert_eq!(TimeZone::utc().broken_down(978_220_800).year_day, 365); // 31 Dec 2000
+    }
+}
@@ -0,0 +1,416 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Translating uids and gids to names means a trip through the passwd and
+//! group databases, which can be very slow (e.g. on LDAP-backed systems). As
+//! the same handful of ids tends to crop up again and again during a walk, we
+//! remember the results of every lookup - including failed ones.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use find::metadata::FileMetadata;
+
+/// Signature for functions that translate an id into a name. Returns None if
+/// the id isn't known to the system.
+pub type IdLookup = Box<Fn(u32) -> Option<String>>;
+
+/// Memoising wrapper around the user and group lookup functions.
+pub struct UserGroupCache {
+    user_lookup: IdLookup,
+    group_lookup: IdLookup,
+    users: RefCell<HashMap<u32, Option<Rc<str>>>>,
+    groups: RefCell<HashMap<u32, Option<Rc<str>>>>,
+}
+
+impl UserGroupCache {
+    /// Creates a cache that uses the system's passwd and group databases.
+    pub fn new() -> UserGroupCache {
+        UserGroupCache::with_lookups(Box::new(system::user_name), Box::new(system::group_name))
+    }
+
+    /// Creates a cache that uses the supplied lookup functions. Mainly useful
+    /// for tests.
+    pub fn with_lookups(user_lookup: IdLookup, group_lookup: IdLookup) -> UserGroupCache {
+        UserGroupCache {
+            user_lookup: user_lookup,
+            group_lookup: group_lookup,
+            users: RefCell::new(HashMap::new()),
+            groups: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the name of the user with the given uid, or None if there's no
+    /// such user.
+    pub fn name_for_uid(&self, uid: u32) -> Option<Rc<str>> {
+        cached_lookup(&self.users, &self.user_lookup, uid)
+    }
+
+    /// Returns the name of the group with the given gid, or None if there's no
+    /// such group.
+    pub fn name_for_gid(&self, gid: u32) -> Option<Rc<str>> {
+        cached_lookup(&self.groups, &self.group_lookup, gid)
+    }
+
+    /// Returns the name of the user with the given uid, falling back to the
+    /// numeric uid (as GNU find does) if there's no such user.
+    pub fn user_display_name(&self, uid: u32) -> Rc<str> {
+        self.name_for_uid(uid).unwrap_or_else(|| Rc::from(uid.to_string()))
+    }
+
+    /// Returns the name of the group with the given gid, falling back to the
+    /// numeric gid (as GNU find does) if there's no such group.
+    pub fn group_display_name(&self, gid: u32) -> Rc<str> {
+        self.name_for_gid(gid).unwrap_or_else(|| Rc::from(gid.to_string()))
+    }
+}
+
+impl Default for UserGroupCache {
+    fn default() -> UserGroupCache {
+        UserGroupCache::new()
+    }
+}
+
+/// Returns the uid of the user with the given name, or None if there's no such
+/// user. Unlike the id-to-name lookups this isn't cached: it's only needed
+/// while parsing the command line.
+pub fn uid_for_name(name: &str) -> Option<u32> {
+    system::user_id(name)
+}
+
+/// Returns the gid of the group with the given name, or None if there's no such
+/// group.
+pub fn gid_for_name(name: &str) -> Option<u32> {
+    system::group_id(name)
+}
+
+/// Permission to read a file, for `Identity::may`.
+pub const READ: u32 = 0o4;
+/// Permission to write to a file.
+pub const WRITE: u32 = 0o2;
+/// Permission to execute a file (or, for a directory, to search it).
+pub const EXECUTE: u32 = 0o1;
+
+/// A user and the groups they're in: what decides whether they're allowed to
+/// do things to a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub uid: u32,
+    /// The user's primary group, and any others they're in.
+    pub gids: Vec<u32>,
+}
+
+impl Identity {
+    /// Returns the identity of the user running this process.
+    pub fn current() -> Identity {
+        system::current_identity()
+    }
+
+    /// Returns the identity of the user with the given name, or None if
+    /// there's no such user.
+    pub fn for_user(name: &str) -> Option<Identity> {
+        system::user_identity(name)
+    }
+
+    /// Whether the user may access the file in all of the given ways (a
+    /// combination of READ, WRITE and EXECUTE). This goes by the file's
+    /// permission bits alone, so e.g. ACLs aren't taken into account, and
+    /// (as with the kernel's checks for directories) root may do anything.
+    pub fn may(&self, metadata: &FileMetadata, access: u32) -> bool {
+        if self.uid == 0 {
+            return true;
+        }
+        let bits = if metadata.uid == self.uid {
+            metadata.mode >> 6
+        } else if self.gids.contains(&metadata.gid) {
+            metadata.mode >> 3
+        } else {
+            metadata.mode
+        };
+        bits & access == access
+    }
+}
+
+fn cached_lookup(cache: &RefCell<HashMap<u32, Option<Rc<str>>>>,
+                 lookup: &IdLookup,
+                 id: u32)
+                 -> Option<Rc<str>> {
+    if let Some(result) = cache.borrow().get(&id) {
+        return result.clone();
+    }
+    let result = lookup(id).map(Rc::from);
+    cache.borrow_mut().insert(id, result.clone());
+    result
+}
+
+#[cfg(unix)]
+mod system {
+    use libc;
+    use std::ffi::{CStr, CString};
+    use std::mem;
+    use std::ptr;
+
+    use super::Identity;
+
+    // getpwuid_r and getgrgid_r both report a too-small buffer in the same way, so
+    // this macro handles the retry-with-a-bigger-buffer dance for both of them.
+    macro_rules! reentrant_lookup {
+        ($func:ident, $key:expr, $record_type:ty, $extract:expr) => {{
+            let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+            loop {
+                let mut record: $record_type = unsafe { mem::zeroed() };
+                let mut result: *mut $record_type = ptr::null_mut();
+                let rc = unsafe {
+                    libc::$func($key, &mut record, buffer.as_mut_ptr(), buffer.len(), &mut result)
+                };
+                if rc == libc::ERANGE && buffer.len() < 1024 * 1024 {
+                    let new_len = buffer.len() * 2;
+                    buffer.resize(new_len, 0);
+                    continue;
+                }
+                if rc != 0 || result.is_null() {
+                    break None;
+                }
+                break Some($extract(&record));
+            }
+        }}
+    }
+
+    pub fn user_name(uid: u32) -> Option<String> {
+        reentrant_lookup!(getpwuid_r,
+                          uid as libc::uid_t,
+                          libc::passwd,
+                          |p: &libc::passwd| {
+                              unsafe { CStr::from_ptr(p.pw_name) }.to_string_lossy().into_owned()
+                          })
+    }
+
+    pub fn group_name(gid: u32) -> Option<String> {
+        reentrant_lookup!(getgrgid_r,
+                          gid as libc::gid_t,
+                          libc::group,
+                          |g: &libc::group| {
+                              unsafe { CStr::from_ptr(g.gr_name) }.to_string_lossy().into_owned()
+                          })
+    }
+
+    pub fn user_id(name: &str) -> Option<u32> {
+        // names with embedded NULs can't exist
+        let name = CString::new(name).ok()?;
+        reentrant_lookup!(getpwnam_r,
+                          name.as_ptr(),
+                          libc::passwd,
+                          |p: &libc::passwd| p.pw_uid as u32)
+    }
+
+    pub fn group_id(name: &str) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        reentrant_lookup!(getgrnam_r,
+                          name.as_ptr(),
+                          libc::group,
+                          |g: &libc::group| g.gr_gid as u32)
+    }
+
+    pub fn current_identity() -> Identity {
+        let gid = unsafe { libc::getgid() };
+        let count = unsafe { libc::getgroups(0, ptr::null_mut()) };
+        let mut gids: Vec<libc::gid_t> = vec![0; count.max(0) as usize];
+        let count = unsafe { libc::getgroups(gids.len() as libc::c_int, gids.as_mut_ptr()) };
+        gids.truncate(count.max(0) as usize);
+        if !gids.contains(&gid) {
+            gids.insert(0, gid);
+        }
+        Identity {
+            uid: unsafe { libc::getuid() } as u32,
+            gids: gids.into_iter().map(|gid| gid as u32).collect(),
+        }
+    }
+
+    pub fn user_identity(name: &str) -> Option<Identity> {
+        let c_name = CString::new(name).ok()?;
+        let (uid, gid) = reentrant_lookup!(getpwnam_r,
+                                           c_name.as_ptr(),
+                                           libc::passwd,
+                                           |p: &libc::passwd| (p.pw_uid, p.pw_gid))?;
+        // getgrouplist says how many groups there are if there isn't room
+        // for them all
+        let mut gids: Vec<libc::gid_t> = vec![0; 32];
+        loop {
+            let mut count = gids.len() as libc::c_int;
+            let rc = unsafe {
+                libc::getgrouplist(c_name.as_ptr(), gid, gids.as_mut_ptr(), &mut count)
+            };
+            if rc >= 0 {
+                gids.truncate(count as usize);
+                break;
+            }
+            let new_len = (count as usize).max(gids.len() * 2);
+            gids.resize(new_len, 0);
+        }
+        Some(Identity {
+            uid: uid as u32,
+            gids: gids.into_iter().map(|gid| gid as u32).collect(),
+        })
+    }
+}
+
+#[cfg(not(unix))]
+mod system {
+    use super::Identity;
+
+    // There's no equivalent of uids and gids elsewhere, so nothing is ever found.
+    pub fn user_name(_: u32) -> Option<String> {
+        None
+    }
+
+    pub fn group_name(_: u32) -> Option<String> {
+        None
+    }
+
+    pub fn user_id(_: &str) -> Option<u32> {
+        None
+    }
+
+    pub fn group_id(_: &str) -> Option<u32> {
+        None
+    }
+
+    // and nobody's permissions get in their way
+    pub fn current_identity() -> Identity {
+        Identity {
+            uid: 0,
+            gids: vec![],
+        }
+    }
+
+    pub fn user_identity(_: &str) -> Option<Identity> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use super::*;
+
+    /// Builds a lookup function that knows about ids 0 and 1000 (and nothing
+    /// else), counting how many times it's been called.
+    fn counting_lookup(calls: Rc<Cell<usize>>) -> IdLookup {
+        Box::new(move |id| {
+            calls.set(calls.get() + 1);
+            match id {
+                0 => Some("root".to_string()),
+                1000 => Some("alice".to_string()),
+                _ => None,
+            }
+        })
+    }
+
+    #[test]
+    fn lookups_are_memoised() {
+        let user_calls = Rc::new(Cell::new(0));
+        let group_calls = Rc::new(Cell::new(0));
+        let cache = UserGroupCache::with_lookups(counting_lookup(user_calls.clone()),
+                                                 counting_lookup(group_calls.clone()));
+
+        for _ in 0..3 {
+            assert_eq!(cache.name_for_uid(0).as_ref().map(|s| s.as_ref()), Some("root"));
+            assert_eq!(cache.name_for_uid(1000).as_ref().map(|s| s.as_ref()),
+                       Some("alice"));
+        }
+        assert_eq!(user_calls.get(), 2);
+        assert_eq!(group_calls.get(), 0);
+
+        assert_eq!(cache.name_for_gid(1000).as_ref().map(|s| s.as_ref()),
+                   Some("alice"));
+        assert_eq!(cache.name_for_gid(1000).as_ref().map(|s| s.as_ref()),
+                   Some("alice"));
+        assert_eq!(group_calls.get(), 1);
+    }
+
+    #[test]
+    fn misses_are_memoised() {
+        let user_calls = Rc::new(Cell::new(0));
+        let group_calls = Rc::new(Cell::new(0));
+        let cache = UserGroupCache::with_lookups(counting_lookup(user_calls.clone()),
+                                                 counting_lookup(group_calls.clone()));
+
+        assert_eq!(cache.name_for_uid(42), None);
+        assert_eq!(cache.name_for_uid(42), None);
+        assert_eq!(cache.name_for_gid(42), None);
+        assert_eq!(cache.name_for_gid(42), None);
+        assert_eq!(user_calls.get(), 1);
+        assert_eq!(group_calls.get(), 1);
+    }
+
+    #[test]
+    fn unknown_ids_display_as_numbers() {
+        let cache = UserGroupCache::with_lookups(counting_lookup(Rc::new(Cell::new(0))),
+                                                 counting_lookup(Rc::new(Cell::new(0))));
+        assert_eq!(cache.user_display_name(1000).as_ref(), "alice");
+        assert_eq!(cache.user_display_name(4321).as_ref(), "4321");
+        assert_eq!(cache.group_display_name(0).as_ref(), "root");
+        assert_eq!(cache.group_display_name(4321).as_ref(), "4321");
+    }
+
+    fn file(mode: u32, uid: u32, gid: u32) -> FileMetadata {
+        FileMetadata {
+            mode: mode,
+            uid: uid,
+            gid: gid,
+            ..FileMetadata::default()
+        }
+    }
+
+    #[test]
+    fn identity_permissions() {
+        let alice = Identity {
+            uid: 1000,
+            gids: vec![1000, 50],
+        };
+        // the owner's bits apply to the owner, even if the group's are more
+        // permissive
+        assert!(alice.may(&file(0o40700, 1000, 1), READ | EXECUTE));
+        assert!(!alice.may(&file(0o40070, 1000, 50), READ));
+        // then the group's, for anyone in the group
+        assert!(alice.may(&file(0o40750, 1, 50), READ | EXECUTE));
+        assert!(!alice.may(&file(0o40750, 1, 50), WRITE));
+        assert!(!alice.may(&file(0o40705, 1, 50), READ));
+        // then everyone else's
+        assert!(alice.may(&file(0o40701, 1, 1), EXECUTE));
+        assert!(!alice.may(&file(0o40701, 1, 1), READ | EXECUTE));
+        assert!(!alice.may(&file(0o40770, 1, 1), EXECUTE));
+
+        let root = Identity {
+            uid: 0,
+            gids: vec![0],
+        };
+        assert!(root.may(&file(0o40000, 1000, 1000), READ | WRITE | EXECUTE));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn system_identities() {
+        let root = Identity::for_user("root").unwrap();
+        assert_eq!(root.uid, 0);
+        assert!(root.gids.contains(&0));
+        assert_eq!(Identity::for_user("no-such-user-findutils"), None);
+        let current = Identity::current();
+        assert_eq!(current.uid, unsafe { ::libc::getuid() } as u32);
+        assert!(current.gids.contains(&(unsafe { ::libc::getgid() } as u32)));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn system_lookups_know_about_root() {
+        let cache = UserGroupCache::new();
+        assert_eq!(cache.name_for_uid(0).as_ref().map(|s| s.as_ref()), Some("root"));
+        assert_eq!(uid_for_name("root"), Some(0));
+        assert_eq!(uid_for_name("no-such-user-findutils"), None);
+    }
+}
@@ -0,0 +1,623 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Walking the start points: the order entries come in (-depth, -sorted),
+//! how far down to go (-maxdepth, -mindepth, -xdev, -prune), when to stop
+//! (-quit) and what to do about entries that can't be read. Evaluating the
+//! expression for each entry is left to the matcher it's given (and with
+//! --threads, to worker threads, as far as its tests go: see
+//! `matchers::parallel`).
+
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use find::{Config, Dependencies};
+use find::dir_walk::DirWalk;
+use find::matchers::{self, Matcher, MatcherIO, OutputFiles, Prefilter, WalkEntry};
+use find::matchers::parallel::{ParallelPlan, ParallelTests};
+
+/// What happened during a walk.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WalkSummary {
+    /// How many entries the walk came to (whether or not the expression
+    /// had to be evaluated for them).
+    pub entries_visited: usize,
+    /// How many entries the whole expression was true for.
+    pub entries_matched: usize,
+    /// How many entries (or start points) couldn't be read. Anything the
+    /// matchers report themselves isn't counted.
+    pub errors_reported: usize,
+    /// Whether the walk stopped early because a matcher asked find to quit.
+    pub quit: bool,
+    /// How many times metadata was looked up (see `MatcherIO::metadata`).
+    pub stat_calls: usize,
+    /// How many directories' contents were read.
+    pub dirs_read: usize,
+    /// How many commands -exec and friends ran during the walk (which
+    /// doesn't include the last of -exec ... +'s batches).
+    pub commands_run: usize,
+}
+
+/// How many entries the walk may come to between reports of its progress
+/// (as well as there being one whenever it moves to another directory).
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// How far a walk has got, as given to the callback set with
+/// `ConfigBuilder::on_progress`. The counts only ever go up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WalkProgress<'p> {
+    /// How many entries the walk has come to so far.
+    pub entries_seen: usize,
+    /// How many of them the whole expression was true for.
+    pub matches: usize,
+    /// How many entries (or start points) couldn't be read.
+    pub errors: usize,
+    /// The directory the walk is in: the one the latest entry's in, or the
+    /// latest entry itself if it's a directory.
+    pub current_dir: &'p Path,
+}
+
+/// Called with the walk's progress every so often: at least once for each
+/// directory it goes into, at least every `PROGRESS_INTERVAL` entries, and
+/// once more at the end (with an empty current directory if the walk never
+/// got anywhere).
+pub type ProgressCallback = Box<FnMut(&WalkProgress)>;
+
+/// Walks each of the start points in turn, as the config says, passing
+/// every entry it comes to to a matcher.
+pub struct Walker<'a, 'b> {
+    config: &'b Config,
+    deps: &'a Dependencies<'a>,
+    start_points: &'b [String],
+    summary: WalkSummary,
+    /// The directory the progress callback was last told about, and how
+    /// many entries there have been since.
+    progress_dir: Option<PathBuf>,
+    entries_since_progress: usize,
+    /// The threads evaluating the expression's tests, when there's more than
+    /// one (in which case the matcher the walk's given is just the action).
+    parallel: Option<ParallelTests>,
+}
+
+impl<'a, 'b> Walker<'a, 'b> {
+    pub fn new(config: &'b Config,
+               deps: &'a Dependencies<'a>,
+               start_points: &'b [String])
+               -> Walker<'a, 'b> {
+        Walker {
+            config: config,
+            deps: deps,
+            start_points: start_points,
+            summary: WalkSummary::default(),
+            progress_dir: None,
+            entries_since_progress: 0,
+            parallel: None,
+        }
+    }
+
+    /// Walks all the start points (stopping early if a matcher asks find to
+    /// quit), evaluating the matcher for each entry. The settings in the
+    /// config that matchers need to know about (e.g. -L) are passed on to
+    /// them through `matcher_io`. With more than one thread, the matcher's
+    /// tests are evaluated on the others if they can be (see `ParallelPlan`,
+    /// and `Dependencies::supports_worker_threads`), and otherwise, the whole
+    /// of it is evaluated on this one. Letting the
+    /// matchers know that the walk has finished is up to the caller.
+    pub fn run(&mut self, matcher: &Matcher, matcher_io: &mut MatcherIO) -> WalkSummary {
+        matcher_io.set_follow_links(self.config.follow_links);
+        matcher_io.set_follow_start_points(self.config.follow_start_points);
+        if self.config.utc {
+            matcher_io.set_utc();
+        }
+        matcher_io.set_day_start(self.config.day_start);
+        matcher_io.set_ignore_readdir_race(self.config.ignore_readdir_race);
+        let color = self.config.color.is_active(self.deps.output_is_terminal());
+        matcher_io.set_highlight_matches(color);
+        let prefilter = if self.config.use_prefilter {
+            matcher.prefilter()
+        } else {
+            None
+        };
+        let plan = if self.config.threads > 1 && self.deps.supports_worker_threads() {
+            ParallelPlan::new(matcher).ok()
+        } else {
+            None
+        };
+        let matcher = match plan {
+            Some(plan) => {
+                self.parallel = Some(ParallelTests::new(self.config.threads,
+                                                        plan.tests,
+                                                        matcher_io));
+                plan.action
+            }
+            None => matcher,
+        };
+        for start_point in self.start_points {
+            self.deps.start_walking(start_point);
+            self.walk(start_point, matcher, prefilter.as_ref(), matcher_io);
+            if matcher_io.should_quit() {
+                self.summary.quit = true;
+                break;
+            }
+        }
+        // (waiting for the workers to stop)
+        self.parallel = None;
+        self.summary.stat_calls = matcher_io.stat_calls();
+        self.summary.commands_run = matcher_io.commands_run();
+        // however the walk ended, the final counts are passed on
+        if self.config.progress.is_some() {
+            let dir = self.progress_dir.take().unwrap_or_else(PathBuf::new);
+            self.report_progress(dir);
+        }
+        self.summary
+    }
+
+    /// Passes the walk's progress on to the config's callback (if there is
+    /// one) when the entry takes the walk into a different directory, or
+    /// enough entries have gone by since it was last called.
+    fn entry_done(&mut self, entry: &WalkEntry) {
+        if self.config.progress.is_none() {
+            return;
+        }
+        self.entries_since_progress += 1;
+        let dir = match entry.path().parent() {
+            Some(parent) if !entry.file_type().is_dir() => parent,
+            _ => entry.path(),
+        };
+        if self.progress_dir.as_ref().map_or(true, |progress_dir| progress_dir != dir) ||
+           self.entries_since_progress >= PROGRESS_INTERVAL {
+            self.report_progress(dir.to_path_buf());
+        }
+    }
+
+    fn report_progress(&mut self, dir: PathBuf) {
+        if let Some(ref callback) = self.config.progress {
+            (*callback.borrow_mut())(&WalkProgress {
+                entries_seen: self.summary.entries_visited,
+                matches: self.summary.entries_matched,
+                errors: self.summary.errors_reported,
+                current_dir: &dir,
+            });
+        }
+        self.progress_dir = Some(dir);
+        self.entries_since_progress = 0;
+    }
+
+    fn walk(&mut self,
+            dir: &str,
+            matcher: &Matcher,
+            prefilter: Option<&Prefilter>,
+            matcher_io: &mut MatcherIO) {
+        let config = self.config;
+        // Entries' paths are built by joining their names onto the start
+        // point exactly as the user typed it, which gives the same results
+        // as GNU find: "dir/" yields "dir/sub" (no extra separator is added
+        // when there's one already) and "dir//" yields "dir//sub". Without
+        // -H or -L, a start point that's a symbolic link is just a link, so
+        // there's nothing below it.
+        let mut it = DirWalk::new(dir)
+            .contents_first(config.depth_first)
+            .follow_links(config.follow_links)
+            .follow_start(config.follow_start_points)
+            .max_depth(config.max_depth)
+            .min_depth(config.min_depth)
+            .sort(config.sort_entries);
+
+        let starting_point: Rc<Path> = Rc::from(Path::new(dir));
+        // the device the start point is on, for -xdev (worked out up front,
+        // as with -depth the start point is the last thing the walk gets to).
+        // Each start point has its own, and with -L, it's the device of what
+        // the start point links to.
+        let start_device = if config.same_file_system {
+            matcher_io.directory_metadata(&starting_point).ok().map(|metadata| metadata.dev)
+        } else {
+            None
+        };
+
+        loop {
+            let entry = match it.next() {
+                None => break,
+                Some(Ok(entry)) => WalkEntry::from_dir_walk(entry, starting_point.clone()),
+                Some(Err(err)) => {
+                    // e.g. a start point that doesn't exist, or a directory
+                    // we can't read. We carry on, but the exit code has to
+                    // reflect it.
+                    if config.same_file_system && err.depth > 0 &&
+                       is_below_mount_point(&err.path, start_device, matcher_io) {
+                        continue;
+                    }
+                    self.summary.errors_reported += 1;
+                    if err.depth == 0 {
+                        // the user said it was there, so -ignore_readdir_race
+                        // doesn't apply
+                        matchers::write_io_error(&err.path, "", &err.error, matcher_io);
+                    } else {
+                        matchers::report_io_error(&err.path, "", &err.error, matcher_io);
+                    }
+                    continue;
+                }
+            };
+            self.visit(entry, &mut it, start_device, matcher, prefilter, matcher_io);
+            if matcher_io.walk_control().quit {
+                break;
+            }
+        }
+        if let Some(ref mut parallel) = self.parallel {
+            self.summary.entries_matched += parallel.run_action(matcher, true, matcher_io);
+        }
+    }
+
+    /// Evaluates the expression for an entry (unless -xdev or the prefilter
+    /// rules it out), and skips what's below it if that's what the matchers
+    /// asked for. With more than one thread, the entry's queued for its tests
+    /// to be evaluated instead (and the action's run for whichever entries'
+    /// tests have been).
+    fn visit(&mut self,
+             entry: WalkEntry,
+             it: &mut DirWalk,
+             start_device: Option<u64>,
+             matcher: &Matcher,
+             prefilter: Option<&Prefilter>,
+             matcher_io: &mut MatcherIO) {
+        let config = self.config;
+        // (before -xdev looks at its metadata, which is then there for the
+        // matchers)
+        matcher_io.start_entry();
+        // Only a directory that's about to be read can be skipped (not,
+        // with -depth, one that's already been read). With -L, the entry's
+        // type is that of what a link points to, so a link to a directory is
+        // one that's about to be read (and without it, it's just a link, and
+        // isn't).
+        let can_skip = entry.file_type().is_dir() && !config.depth_first;
+        let mut skipped = false;
+        if config.same_file_system {
+            match matcher_io.metadata(&entry) {
+                Ok(ref metadata) if entry.depth() > 0 && Some(metadata.dev) != start_device => {
+                    if is_below_mount_point(entry.path(), start_device, matcher_io) {
+                        return;
+                    }
+                    // A mount point: it's matched like anything else, but
+                    // what's in it isn't (and, unless it's being visited
+                    // after its contents, isn't even read, as the walk can't
+                    // skip what it's already been through).
+                    if can_skip {
+                        it.skip_current_dir();
+                        skipped = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.summary.entries_visited += 1;
+        // nothing would come of evaluating the expression for an entry that
+        // fails the prefilter
+        let evaluate = prefilter.map_or(true, |prefilter| prefilter.passes(&entry));
+        if evaluate && self.parallel.is_none() {
+            if !config.output_files.is_empty() && entry.file_type().is_file() {
+                flush_if_output_file(&entry, &config.output_files, matcher_io);
+            }
+            if matcher.matches(&entry, matcher_io) {
+                self.summary.entries_matched += 1;
+            }
+            let control = matcher_io.walk_control();
+            if control.quit {
+                return;
+            }
+            if control.skip_descendants && can_skip && !skipped {
+                it.skip_current_dir();
+                skipped = true;
+            }
+        }
+        // a directory is read unless it's skipped (or, with -depth, already
+        // has been)
+        if entry.file_type().is_dir() && entry.depth() < config.max_depth && !skipped {
+            self.summary.dirs_read += 1;
+        }
+        self.entry_done(&entry);
+        if let Some(ref mut parallel) = self.parallel {
+            if evaluate {
+                parallel.push(entry);
+                self.summary.entries_matched += parallel.run_action(matcher, false, matcher_io);
+            }
+        }
+    }
+}
+
+/// Whether the path (of something below a start point) is in a directory on
+/// a different device from the start point: with -xdev, mount points
+/// themselves count, but nothing below them does.
+fn is_below_mount_point(path: &Path, start_device: Option<u64>, matcher_io: &MatcherIO) -> bool {
+    match path.parent() {
+        Some(parent) => {
+            matcher_io.directory_metadata(parent).map_or(false, |metadata| {
+                Some(metadata.dev) != start_device
+            })
+        }
+        None => false,
+    }
+}
+
+/// If the entry is one of the files -fprint and friends are writing to,
+/// flushes what's been written to it so far, so that anything that looks at
+/// it sees all of that. Any failure will come up again when the matchers
+/// flush it at the end (and be reported then).
+fn flush_if_output_file(entry: &WalkEntry, output_files: &OutputFiles, matcher_io: &MatcherIO) {
+    if let Ok(metadata) = matcher_io.metadata(entry) {
+        if let Some(file) = output_files.get(&metadata) {
+            let _ = file.flush();
+            matcher_io.forget_metadata();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fmt;
+
+    use find::Config;
+    use find::matchers::{Matcher, MatcherIO, WalkEntry};
+    use find::tests::{fix_up_slashes, FakeDependencies};
+    use super::*;
+
+    /// Records the paths of the entries it's given, and prunes or quits at
+    /// the ones with the given names.
+    struct Recorder {
+        prune: &'static [&'static str],
+        quit: &'static [&'static str],
+        seen: RefCell<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn new(prune: &'static [&'static str], quit: &'static [&'static str]) -> Recorder {
+            Recorder {
+                prune: prune,
+                quit: quit,
+                seen: RefCell::new(vec![]),
+            }
+        }
+
+        fn seen(&self) -> Vec<String> {
+            self.seen.borrow().clone()
+        }
+    }
+
+    impl Matcher for Recorder {
+        fn matches(&self, file_info: &WalkEntry, matcher_io: &mut MatcherIO) -> bool {
+            let name = file_info.name().to_string_lossy().to_string();
+            self.seen.borrow_mut().push(file_info.path().to_string_lossy().to_string());
+            if self.prune.contains(&name.as_ref()) {
+                matcher_io.mark_current_dir_to_be_skipped();
+            }
+            if self.quit.contains(&name.as_ref()) {
+                matcher_io.quit();
+            }
+            true
+        }
+
+        fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "recorder")
+        }
+    }
+
+    fn sorted_config() -> Config {
+        Config { sort_entries: true, ..Config::default() }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| fix_up_slashes(path)).collect()
+    }
+
+    fn walk(config: &Config, start_points: &[&str], matcher: &Matcher) -> WalkSummary {
+        let deps = FakeDependencies::new();
+        let start_points = paths(start_points);
+        let mut matcher_io = deps.new_matcher_io();
+        Walker::new(config, &deps, &start_points).run(matcher, &mut matcher_io)
+    }
+
+    #[test]
+    fn visits_everything() {
+        let recorder = Recorder::new(&[], &[]);
+        let summary = walk(&sorted_config(),
+                           &["test_data/simple", "test_data/depth/1/2"],
+                           &recorder);
+        assert_eq!(recorder.seen(),
+                   paths(&["test_data/simple",
+                           "test_data/simple/abbbc",
+                           "test_data/simple/subdir",
+                           "test_data/simple/subdir/ABBBC",
+                           "test_data/depth/1/2",
+                           "test_data/depth/1/2/3",
+                           "test_data/depth/1/2/3/f3",
+                           "test_data/depth/1/2/f2"]));
+        assert_eq!(summary,
+                   WalkSummary {
+                       entries_visited: 8,
+                       entries_matched: 8,
+                       errors_reported: 0,
+                       quit: false,
+                       stat_calls: 0,
+                       dirs_read: 4,
+                       commands_run: 0,
+                   });
+    }
+
+    #[test]
+    fn prune_skips_only_directories() {
+        // pruning a file makes no difference to its siblings
+        let recorder = Recorder::new(&["1", "abbbc"], &[]);
+        let summary = walk(&sorted_config(), &["test_data/depth", "test_data/simple"], &recorder);
+        assert_eq!(recorder.seen(),
+                   paths(&["test_data/depth",
+                           "test_data/depth/1",
+                           "test_data/depth/f0",
+                           "test_data/simple",
+                           "test_data/simple/abbbc",
+                           "test_data/simple/subdir",
+                           "test_data/simple/subdir/ABBBC"]));
+        assert_eq!(summary.entries_visited, 7);
+
+        // and, with -depth, pruning comes too late to make any difference
+        let recorder = Recorder::new(&["2"], &[]);
+        let config = Config { depth_first: true, ..sorted_config() };
+        walk(&config, &["test_data/depth"], &recorder);
+        assert_eq!(recorder.seen(),
+                   paths(&["test_data/depth/1/2/3/f3",
+                           "test_data/depth/1/2/3",
+                           "test_data/depth/1/2/f2",
+                           "test_data/depth/1/2",
+                           "test_data/depth/1/f1",
+                           "test_data/depth/1",
+                           "test_data/depth/f0",
+                           "test_data/depth"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prune_linked_directory() {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("prune_linked_directory").unwrap();
+        let root = temp_dir.path().to_string_lossy().into_owned();
+        fs::create_dir_all(temp_dir.path().join("dir/sub")).unwrap();
+        fs::File::create(temp_dir.path().join("dir/sub/file")).unwrap();
+        fs::File::create(temp_dir.path().join("z")).unwrap();
+        symlink("dir", temp_dir.path().join("link")).unwrap();
+        let walk_pruning = |follow_links: bool, prune: &'static [&'static str]| {
+            let recorder = Recorder::new(prune, &[]);
+            let config = Config { follow_links: follow_links, ..sorted_config() };
+            walk(&config, &[&root], &recorder);
+            recorder.seen().iter().map(|path| path.replacen(&root, "", 1)).collect::<Vec<_>>()
+        };
+
+        // with -L, a pruned link to a directory isn't searched, but its
+        // siblings (including what it points to) are
+        assert_eq!(walk_pruning(true, &["link"]),
+                   vec!["", "/dir", "/dir/sub", "/dir/sub/file", "/link", "/z"]);
+        assert_eq!(walk_pruning(true, &["sub"]),
+                   vec!["", "/dir", "/dir/sub", "/link", "/link/sub", "/z"]);
+        // and without it, pruning a link is like pruning any other file
+        assert_eq!(walk_pruning(false, &["link", "file"]),
+                   vec!["", "/dir", "/dir/sub", "/dir/sub/file", "/link", "/z"]);
+    }
+
+    #[test]
+    fn quit_stops_everything() {
+        // quitting wins over pruning, and later start points aren't walked
+        let recorder = Recorder::new(&["2"], &["2"]);
+        let summary = walk(&sorted_config(), &["test_data/depth", "test_data/simple"], &recorder);
+        assert_eq!(recorder.seen(),
+                   paths(&["test_data/depth", "test_data/depth/1", "test_data/depth/1/2"]));
+        assert_eq!(summary,
+                   WalkSummary {
+                       entries_visited: 3,
+                       entries_matched: 3,
+                       errors_reported: 0,
+                       quit: true,
+                       stat_calls: 0,
+                       dirs_read: 2,
+                       commands_run: 0,
+                   });
+
+        // including with -depth
+        let recorder = Recorder::new(&[], &["f2"]);
+        let config = Config { depth_first: true, ..sorted_config() };
+        let summary = walk(&config, &["test_data/depth"], &recorder);
+        assert_eq!(recorder.seen(),
+                   paths(&["test_data/depth/1/2/3/f3",
+                           "test_data/depth/1/2/3",
+                           "test_data/depth/1/2/f2"]));
+        assert!(summary.quit);
+    }
+
+    #[test]
+    fn errors_are_counted_and_reported() {
+        let recorder = Recorder::new(&[], &[]);
+        let deps = FakeDependencies::new();
+        let start_points = paths(&["test_data/no_such_file", "test_data/simple/abbbc"]);
+        let config = Config::default();
+        let mut matcher_io = deps.new_matcher_io();
+        let summary = Walker::new(&config, &deps, &start_points).run(&recorder, &mut matcher_io);
+        assert_eq!(summary,
+                   WalkSummary {
+                       entries_visited: 1,
+                       entries_matched: 1,
+                       errors_reported: 1,
+                       quit: false,
+                       stat_calls: 0,
+                       dirs_read: 0,
+                       commands_run: 0,
+                   });
+        assert_eq!(recorder.seen(), paths(&["test_data/simple/abbbc"]));
+        assert_eq!(matcher_io.exit_code(), 1);
+        assert_eq!(deps.get_error_output_as_string(),
+                   fix_up_slashes("find: ‘test_data/no_such_file’: No such file or directory\n"));
+        assert_eq!(deps.get_start_points(), start_points);
+    }
+
+    #[test]
+    fn same_file_system_per_start_point() {
+        use find::metadata::FileMetadata;
+        use walkdir::WalkDir;
+
+        // test_data/simple is on device 1, and test_data/depth on device 2,
+        // apart from test_data/depth/1/2, which is a mount point for device 3
+        let mut deps = FakeDependencies::new();
+        for &(start_point, dev) in &[("test_data/simple", 1), ("test_data/depth", 2)] {
+            for entry in WalkDir::new(fix_up_slashes(start_point)) {
+                let path = entry.unwrap().path().to_path_buf();
+                let dev = if path.starts_with(fix_up_slashes("test_data/depth/1/2")) {
+                    3
+                } else {
+                    dev
+                };
+                deps.set_metadata(&path,
+                                  FileMetadata {
+                                      dev: dev,
+                                      ..FileMetadata::default()
+                                  });
+            }
+        }
+        let recorder = Recorder::new(&[], &[]);
+        let config = Config { same_file_system: true, ..sorted_config() };
+        let start_points = paths(&["test_data/simple", "test_data/depth"]);
+        let mut matcher_io = deps.new_matcher_io();
+        let summary = Walker::new(&config, &deps, &start_points).run(&recorder, &mut matcher_io);
+
+        // everything below the second start point is compared with its own
+        // device, not the first one's
+        assert_eq!(recorder.seen(),
+                   paths(&["test_data/simple",
+                           "test_data/simple/abbbc",
+                           "test_data/simple/subdir",
+                           "test_data/simple/subdir/ABBBC",
+                           "test_data/depth",
+                           "test_data/depth/1",
+                           "test_data/depth/1/2",
+                           "test_data/depth/1/f1",
+                           "test_data/depth/f0"]));
+        assert_eq!(summary.errors_reported, 0);
+    }
+
+    #[test]
+    fn depth_limits() {
+        let recorder = Recorder::new(&[], &[]);
+        let config = Config {
+            min_depth: 1,
+            max_depth: 2,
+            ..sorted_config()
+        };
+        let summary = walk(&config, &["test_data/depth"], &recorder);
+        assert_eq!(recorder.seen(),
+                   paths(&["test_data/depth/1",
+                           "test_data/depth/1/2",
+                           "test_data/depth/1/f1",
+                           "test_data/depth/f0"]));
+        assert_eq!(summary.entries_visited, 4);
+    }
+}
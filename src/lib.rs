@@ -5,10 +5,22 @@
 // https://opensource.org/licenses/MIT.
 
 extern crate glob;
+extern crate libc;
 extern crate regex;
 extern crate walkdir;
 
 #[cfg(test)]
 extern crate tempdir;
 
+pub mod exec;
 pub mod find;
+pub mod locate;
+pub mod options;
+pub mod updatedb;
+pub mod xargs;
+
+// the same helpers for making special files as the integration tests have
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../tests/common/fixtures.rs"]
+mod fixtures;
@@ -0,0 +1,143 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Maps databases into memory, so that searching one doesn't mean copying it
+//! all, and the OS can page a big one in (and out again) as it's read.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+/// A file's contents: mapped into memory if possible, read into it if not.
+pub enum FileContents {
+    #[cfg(unix)]
+    Mapped(Mapping),
+    Read(Vec<u8>),
+}
+
+impl FileContents {
+    /// Maps the file into memory, or reads it if it can't be mapped (e.g.
+    /// because it's a pipe). Mapped files mustn't be changed while they're
+    /// in use, which updatedb never does: it writes a new database and
+    /// renames it over the old one.
+    pub fn open(path: &Path) -> io::Result<FileContents> {
+        let mut file = File::open(path)?;
+        #[cfg(unix)]
+        {
+            if let Some(mapping) = Mapping::new(&file)? {
+                return Ok(FileContents::Mapped(mapping));
+            }
+        }
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        Ok(FileContents::Read(contents))
+    }
+}
+
+impl Deref for FileContents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            #[cfg(unix)]
+            FileContents::Mapped(ref mapping) => mapping.bytes(),
+            FileContents::Read(ref contents) => contents,
+        }
+    }
+}
+
+/// A read-only mapping of a whole file.
+#[cfg(unix)]
+pub struct Mapping {
+    address: *mut ::libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mapping {
+    /// Maps the file, returning None if it's not the kind of file that can
+    /// be mapped. Empty files can't be either, but they're easily read.
+    fn new(file: &File) -> io::Result<Option<Mapping>> {
+        use libc;
+        use std::os::unix::io::AsRawFd;
+        use std::ptr;
+
+        let metadata = file.metadata()?;
+        if !metadata.is_file() || metadata.len() == 0 ||
+           metadata.len() > usize::max_value() as u64 {
+            return Ok(None);
+        }
+        let len = metadata.len() as usize;
+        let address = unsafe {
+            libc::mmap(ptr::null_mut(),
+                       len,
+                       libc::PROT_READ,
+                       libc::MAP_PRIVATE,
+                       file.as_raw_fd(),
+                       0)
+        };
+        if address == libc::MAP_FAILED {
+            return Ok(None);
+        }
+        Ok(Some(Mapping {
+            address: address,
+            len: len,
+        }))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.address as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            ::libc::munmap(self.address, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn open_file() {
+        let temp_dir = TempDir::new("mmap_open_file").unwrap();
+        let path = temp_dir.path().join("file");
+        File::create(&path).unwrap().write_all(b"some contents").unwrap();
+        let contents = FileContents::open(&path).unwrap();
+        assert_eq!(&contents[..], b"some contents");
+        #[cfg(unix)]
+        {
+            match contents {
+                FileContents::Mapped(_) => {}
+                FileContents::Read(_) => panic!("file wasn't mapped"),
+            }
+        }
+    }
+
+    #[test]
+    fn open_empty_file() {
+        let temp_dir = TempDir::new("mmap_open_empty_file").unwrap();
+        let path = temp_dir.path().join("file");
+        File::create(&path).unwrap();
+        assert!(FileContents::open(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_missing_file() {
+        let temp_dir = TempDir::new("mmap_open_missing_file").unwrap();
+        assert!(FileContents::open(&temp_dir.path().join("missing")).is_err());
+    }
+}
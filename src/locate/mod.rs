@@ -0,0 +1,899 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+mod mmap;
+
+use glob;
+use regex::bytes::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::io::{self, stderr, Write};
+use std::path::{Path, PathBuf};
+
+use find::Dependencies;
+use find::users::{Identity, EXECUTE};
+use options::option_value;
+use updatedb::{self, frcode};
+
+/// The characters that make a pattern a glob, rather than a string to look
+/// for.
+const GLOB_CHARS: &'static [char] = &['*', '?', '[', '\\'];
+
+/// Returns where the first occurrence of needle in haystack starts.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Returns the last component of the path.
+fn base_name(path: &[u8]) -> &[u8] {
+    match path.iter().rposition(|&b| b == b'/') {
+        // "/" is its own base name
+        Some(i) if i + 1 == path.len() => path,
+        Some(i) => &path[i + 1..],
+        None => path,
+    }
+}
+
+/// A pattern given on the command line.
+enum Pattern {
+    /// Matches paths that contain the string.
+    Substring(Vec<u8>),
+    /// Matches paths that match the glob, all of them (not just their last
+    /// component, and "*" matches "/").
+    Glob(glob::Pattern),
+    /// Matches paths that contain a match for the regular expression (with
+    /// -r).
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Parses a pattern as the options say it should be. For
+    /// case-insensitive matching, the paths it's matched against should be
+    /// lower case.
+    fn new(pattern: &str, options: &Options) -> Result<Pattern, Box<Error>> {
+        if options.regex {
+            let regex = RegexBuilder::new(pattern).case_insensitive(options.ignore_case)
+                .build()
+                .map_err(|e| format!("invalid regular expression '{}': {}", pattern, e))?;
+            return Ok(Pattern::Regex(regex));
+        }
+        let lower;
+        let pattern = if options.ignore_case {
+            lower = pattern.to_lowercase();
+            &lower
+        } else {
+            pattern
+        };
+        if pattern.contains(GLOB_CHARS) {
+            let glob = glob::Pattern::new(pattern).map_err(|e| {
+                    format!("invalid pattern '{}': {}", pattern, e)
+                })?;
+            Ok(Pattern::Glob(glob))
+        } else {
+            Ok(Pattern::Substring(pattern.as_bytes().to_vec()))
+        }
+    }
+
+    fn matches(&self, path: &[u8]) -> bool {
+        match *self {
+            Pattern::Substring(ref s) => find(path, s).is_some(),
+            Pattern::Glob(ref glob) => glob.matches(&String::from_utf8_lossy(path)),
+            Pattern::Regex(ref regex) => regex.is_match(path),
+        }
+    }
+}
+
+/// Decides which paths to print.
+pub struct Matcher {
+    patterns: Vec<Pattern>,
+    ignore_case: bool,
+    basename: bool,
+}
+
+impl Matcher {
+    fn new(options: &Options) -> Result<Matcher, Box<Error>> {
+        let patterns = options.patterns
+            .iter()
+            .map(|pattern| Pattern::new(pattern, options))
+            .collect::<Result<Vec<Pattern>, Box<Error>>>()?;
+        Ok(Matcher {
+            patterns: patterns,
+            ignore_case: options.ignore_case,
+            basename: options.basename,
+        })
+    }
+
+    /// Makes a matcher for the patterns in args, which are locate's
+    /// arguments (of the options, only those that change how the patterns
+    /// match, like -i and -r, make any difference).
+    pub fn from_args(args: &[&str]) -> Result<Matcher, Box<Error>> {
+        Matcher::new(&parse_args(args)?)
+    }
+
+    /// The substrings that the matcher looks for, if that's all it does.
+    fn substrings(&self) -> Option<Vec<&[u8]>> {
+        if self.ignore_case || self.basename {
+            return None;
+        }
+        self.patterns
+            .iter()
+            .map(|pattern| match *pattern {
+                Pattern::Substring(ref s) => Some(&s[..]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the path matches any of the patterns.
+    fn matches(&self, path: &[u8]) -> bool {
+        let path = if self.basename { base_name(path) } else { path };
+        if self.ignore_case {
+            let lower = String::from_utf8_lossy(path).to_lowercase();
+            self.patterns.iter().any(|pattern| pattern.matches(lower.as_bytes()))
+        } else {
+            self.patterns.iter().any(|pattern| pattern.matches(path))
+        }
+    }
+}
+
+/// Looks for a substring in the paths of a database, one after another,
+/// without searching again the part of each path that it has in common with
+/// the previous one (the classic locate optimisation).
+struct SubstringSearch<'a> {
+    substring: &'a [u8],
+    /// Where the first occurrence in the previous path ended, if there was
+    /// one.
+    previous_end: Option<usize>,
+}
+
+impl<'a> SubstringSearch<'a> {
+    fn new(substring: &'a [u8]) -> SubstringSearch<'a> {
+        SubstringSearch {
+            substring: substring,
+            previous_end: None,
+        }
+    }
+
+    /// Whether the path, which has its first prefix_len bytes in common with
+    /// the previous path searched, contains the substring.
+    fn matches(&mut self, path: &[u8], prefix_len: usize) -> bool {
+        self.previous_end = match self.previous_end {
+            // the first occurrence is in the common prefix, so it's still
+            // the first
+            Some(end) if end <= prefix_len => Some(end),
+            // otherwise nothing in the common prefix matched, so an
+            // occurrence has to end after it
+            _ => {
+                let start = (prefix_len + 1).saturating_sub(self.substring.len()).min(prefix_len);
+                find(&path[start..], self.substring).map(|i| start + i + self.substring.len())
+            }
+        };
+        self.previous_end.is_some()
+    }
+}
+
+/// Searches the contents of a database for the paths that the matcher
+/// matches, passing them to found in the order they're in the database,
+/// until found returns false.
+pub fn search<F>(database: &[u8], matcher: &Matcher, mut found: F) -> io::Result<()>
+    where F: FnMut(&[u8]) -> io::Result<bool>
+{
+    let mut decoder = frcode::Decoder::new(database)?;
+    let mut substring_searches = matcher.substrings()
+        .map(|substrings| substrings.into_iter().map(SubstringSearch::new).collect::<Vec<_>>());
+    while let Some((path, prefix_len)) = decoder.next_path_and_prefix_len()? {
+        let matched = match substring_searches {
+            Some(ref mut searches) => {
+                // every search has to see every path, so that it knows what
+                // was in the previous one
+                let mut matched = false;
+                for search in searches.iter_mut() {
+                    matched |= search.matches(path, prefix_len);
+                }
+                matched
+            }
+            None => matcher.matches(path),
+        };
+        if matched && !found(path)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Options {
+    patterns: Vec<String>,
+    /// The databases given by -d, if it was.
+    databases: Option<Vec<String>>,
+    count: bool,
+    ignore_case: bool,
+    basename: bool,
+    /// What to print after each path.
+    separator: u8,
+    /// The most matches to print (or count).
+    limit: Option<usize>,
+    /// Whether to leave out paths that no longer exist.
+    existing: bool,
+    /// Whether, for --existing, a symbolic link only exists if what it
+    /// points to does.
+    follow_links: bool,
+    regex: bool,
+    /// Whether to leave out paths that the user couldn't get to themselves.
+    require_visibility: bool,
+}
+
+fn parse_limit(value: &str) -> Result<usize, Box<Error>> {
+    value.parse().map_err(|_| From::from(format!("invalid limit '{}'", value)))
+}
+
+fn parse_args(args: &[&str]) -> Result<Options, Box<Error>> {
+    let mut options = Options {
+        patterns: vec![],
+        databases: None,
+        count: false,
+        ignore_case: false,
+        basename: false,
+        separator: b'\n',
+        limit: None,
+        existing: false,
+        follow_links: true,
+        regex: false,
+        require_visibility: false,
+    };
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i];
+        if arg == "--" {
+            options.patterns.extend(args[i + 1..].iter().map(|s| s.to_string()));
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            options.patterns.push(arg.to_string());
+        } else if arg == "-c" || arg == "--count" {
+            options.count = true;
+        } else if arg == "-i" || arg == "--ignore-case" {
+            options.ignore_case = true;
+        } else if arg == "-b" || arg == "--basename" {
+            options.basename = true;
+        } else if arg == "-e" || arg == "--existing" {
+            options.existing = true;
+        } else if arg == "-L" || arg == "--follow" {
+            options.follow_links = true;
+        } else if arg == "-P" || arg == "-H" || arg == "--nofollow" {
+            options.follow_links = false;
+        } else if arg == "-r" || arg == "--regex" {
+            options.regex = true;
+        } else if arg == "--require-visibility" {
+            options.require_visibility = true;
+        } else if arg == "-0" || arg == "--null" {
+            options.separator = b'\0';
+        } else if let Some(value) = option_value(args, &mut i, "-l", Some("--limit"))? {
+            options.limit = Some(parse_limit(value)?);
+        } else if let Some(value) = option_value(args, &mut i, "-n", None)? {
+            options.limit = Some(parse_limit(value)?);
+        } else if let Some(value) = option_value(args, &mut i, "-d", Some("--database"))? {
+            // several -d options add up
+            options.databases
+                .get_or_insert_with(Vec::new)
+                .extend(value.split(':').map(|s| s.to_string()));
+        } else if arg.starts_with("--") {
+            return Err(From::from(format!("unrecognized option '{}'", arg)));
+        } else {
+            return Err(From::from(format!("invalid option -- '{}'",
+                                          arg.chars().nth(1).unwrap())));
+        }
+        i += 1;
+    }
+    if options.patterns.is_empty() {
+        return Err(From::from("no pattern to search for specified"));
+    }
+    Ok(options)
+}
+
+/// Returns the databases to search: those given by -d, or by LOCATE_PATH, or
+/// updatedb's default database.
+fn databases(options: &Options) -> Result<Vec<String>, Box<Error>> {
+    if let Some(ref databases) = options.databases {
+        return Ok(databases.clone());
+    }
+    if let Some(locate_path) = env::var_os("LOCATE_PATH") {
+        if !locate_path.is_empty() {
+            return Ok(locate_path.to_string_lossy().split(':').map(|s| s.to_string()).collect());
+        }
+    }
+    let default = updatedb::default_database_path()
+        .ok_or("can't tell where the database is; use --database")?;
+    Ok(vec![default.to_string_lossy().into_owned()])
+}
+
+/// Whether the path (from the database) still exists. If we can't tell (e.g.
+/// because it's in a directory we can't read), then as far as we're concerned
+/// it doesn't.
+fn exists<'a>(path: &[u8], options: &Options, deps: &'a Dependencies<'a>) -> bool {
+    deps.metadata(&frcode::path_from_bytes(path.to_vec()), options.follow_links).is_ok()
+}
+
+/// Decides whether the user could see paths for themselves, i.e. whether they
+/// may search every directory above them. A database made as root has paths
+/// that other users shouldn't know about.
+struct Visibility {
+    identity: Identity,
+    /// The directories that have been checked, and whether the user can get
+    /// into them. Paths in the same directory are next to each other in the
+    /// database, so most paths' directories will already have been checked.
+    directories: HashMap<PathBuf, bool>,
+}
+
+impl Visibility {
+    fn new(identity: Identity) -> Visibility {
+        Visibility {
+            identity: identity,
+            directories: HashMap::new(),
+        }
+    }
+
+    fn is_visible<'a>(&mut self, path: &Path, deps: &'a Dependencies<'a>) -> bool {
+        match path.parent() {
+            Some(parent) if parent != Path::new("") => self.may_search(parent, deps),
+            _ => true,
+        }
+    }
+
+    fn may_search<'a>(&mut self, dir: &Path, deps: &'a Dependencies<'a>) -> bool {
+        if let Some(&result) = self.directories.get(dir) {
+            return result;
+        }
+        let result = self.is_visible(dir, deps) &&
+                     match deps.metadata(dir, true) {
+            Ok(metadata) => self.identity.may(&metadata, EXECUTE),
+            Err(_) => false,
+        };
+        self.directories.insert(dir.to_path_buf(), result);
+        result
+    }
+}
+
+/// Searches a database, printing (or, with -c, just counting) the paths that
+/// match, stopping once there have been max_matches of them. Returns the
+/// number of matches.
+fn search_database<'a>(database: &str,
+                       matcher: &Matcher,
+                       options: &Options,
+                       max_matches: usize,
+                       visibility: &mut Visibility,
+                       deps: &'a Dependencies<'a>)
+                       -> io::Result<usize> {
+    let contents = mmap::FileContents::open(Path::new(database))?;
+    let mut count = 0;
+    search(&contents, matcher, |path| {
+        // checking whether the path exists is much slower than matching it,
+        // so only the paths that match get checked
+        if options.require_visibility &&
+           !visibility.is_visible(&frcode::path_from_bytes(path.to_vec()), deps) ||
+           options.existing && !exists(path, options, deps) {
+            return Ok(true);
+        }
+        count += 1;
+        if !options.count {
+            let mut output = deps.get_output().borrow_mut();
+            output.write_all(path)?;
+            output.write_all(&[options.separator])?;
+        }
+        Ok(count < max_matches)
+    })?;
+    Ok(count)
+}
+
+/// Returns whether anything matched.
+fn do_locate<'a>(args: &[&str],
+                 identity: Identity,
+                 deps: &'a Dependencies<'a>)
+                 -> Result<bool, Box<Error>> {
+    let options = parse_args(args)?;
+    let matcher = Matcher::new(&options)?;
+    let mut visibility = Visibility::new(identity);
+    let limit = options.limit.unwrap_or(usize::max_value());
+    let mut count = 0;
+    let mut failed = false;
+    for database in databases(&options)? {
+        // once we've hit the limit, there's no need to read any further
+        if count >= limit {
+            break;
+        }
+        match search_database(&database,
+                              &matcher,
+                              &options,
+                              limit - count,
+                              &mut visibility,
+                              deps) {
+            Ok(matches) => count += matches,
+            Err(e) => {
+                writeln!(&mut stderr(), "locate: {}: {}", database, e).unwrap();
+                failed = true;
+            }
+        }
+    }
+    if options.count {
+        writeln!(deps.get_output().borrow_mut(), "{}", count)?;
+    }
+    Ok(count > 0 && !failed)
+}
+
+/// Prints the paths in the file name database(s) that match the patterns
+/// given in args. Returns the exit code: 0 if anything matched, 1 if
+/// nothing did or something went wrong.
+pub fn locate_main<'a>(args: &[&str], deps: &'a Dependencies<'a>) -> i32 {
+    locate_main_as(args, Identity::current(), deps)
+}
+
+/// Like locate_main, but --require-visibility goes by what the given user
+/// can see, rather than the user running locate.
+pub fn locate_main_as<'a>(args: &[&str], identity: Identity, deps: &'a Dependencies<'a>) -> i32 {
+    let result = do_locate(args, identity, deps);
+    // as with find, there's nobody to complain to if whatever we're writing
+    // to has gone away
+    let flushed = match deps.get_output().borrow_mut().flush() {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    };
+    match (result, flushed) {
+        (Err(e), _) => {
+            writeln!(&mut stderr(), "locate: {}", e).unwrap();
+            1
+        }
+        (Ok(_), Err(e)) => {
+            writeln!(&mut stderr(), "locate: failed to write output: {}", e).unwrap();
+            1
+        }
+        (Ok(matched), Ok(())) => if matched { 0 } else { 1 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::{BufWriter, Write};
+    use tempdir::TempDir;
+
+    use find::metadata::FileMetadata;
+    use find::tests::FakeDependencies;
+    use updatedb::frcode::Encoder;
+    use super::*;
+
+    const PATHS: &'static [&'static str] = &["/home",
+                                             "/home/user",
+                                             "/home/user/notes.txt",
+                                             "/home/user/photos",
+                                             "/home/user/photos/cat.jpg",
+                                             "/home/user/photos/dog.JPG",
+                                             "/usr/bin/cat",
+                                             "/usr/bin/tac"];
+
+    /// Writes a database of PATHS, returning the directory it's in and the
+    /// --database option that refers to it.
+    fn make_database() -> (TempDir, String) {
+        make_database_with_trailer(b"")
+    }
+
+    /// Like make_database, but with the given bytes after the paths.
+    fn make_database_with_trailer(trailer: &[u8]) -> (TempDir, String) {
+        let temp_dir = TempDir::new("locate").unwrap();
+        let path = temp_dir.path().join("db");
+        let mut encoder = Encoder::new(BufWriter::new(File::create(&path).unwrap())).unwrap();
+        for path in PATHS {
+            encoder.add(path.as_bytes()).unwrap();
+        }
+        encoder.finish().unwrap().write_all(trailer).unwrap();
+        let option = format!("--database={}", path.to_string_lossy());
+        (temp_dir, option)
+    }
+
+    /// Runs locate with the given arguments (after --database), returning
+    /// the exit code and output.
+    fn locate(args: &[&str]) -> (i32, String) {
+        let (_temp_dir, database) = make_database();
+        let mut all_args = vec!["locate", &database];
+        all_args.extend_from_slice(args);
+        let deps = FakeDependencies::new();
+        let rc = locate_main(&all_args, &deps);
+        (rc, deps.get_output_as_string())
+    }
+
+    #[test]
+    fn locate_substring() {
+        assert_eq!(locate(&["cat"]),
+                   (0, "/home/user/photos/cat.jpg\n/usr/bin/cat\n".to_string()));
+        assert_eq!(locate(&["user/photos"]),
+                   (0,
+                    "/home/user/photos\n/home/user/photos/cat.jpg\n/home/user/photos/dog.JPG\n"
+                        .to_string()));
+        assert_eq!(locate(&["nothing"]), (1, String::new()));
+    }
+
+    #[test]
+    fn locate_glob() {
+        // globs have to match all of the path
+        assert_eq!(locate(&["cat"]).1.lines().count(), 2);
+        assert_eq!(locate(&["*cat"]), (0, "/usr/bin/cat\n".to_string()));
+        // "*" matches "/"
+        assert_eq!(locate(&["/home*.jpg"]), (0, "/home/user/photos/cat.jpg\n".to_string()));
+        assert_eq!(locate(&["/usr/bin/[ct]a?"]),
+                   (0, "/usr/bin/cat\n/usr/bin/tac\n".to_string()));
+        assert_eq!(locate(&["*.txt?"]), (1, String::new()));
+    }
+
+    #[test]
+    fn locate_multiple_patterns() {
+        assert_eq!(locate(&["notes", "*.JPG", "notes.txt"]),
+                   (0, "/home/user/notes.txt\n/home/user/photos/dog.JPG\n".to_string()));
+    }
+
+    #[test]
+    fn locate_count() {
+        assert_eq!(locate(&["-c", "user"]), (0, "5\n".to_string()));
+        assert_eq!(locate(&["cat", "--count", "tac"]), (0, "3\n".to_string()));
+        assert_eq!(locate(&["--count", "nothing"]), (1, "0\n".to_string()));
+    }
+
+    #[test]
+    fn locate_several_databases() {
+        let (_temp_dir, database) = make_database();
+        let deps = FakeDependencies::new();
+        let database = &database["--database=".len()..];
+        let databases = format!("{}:{}", database, database);
+        let rc = locate_main(&["locate", "-c", "-d", &databases, "photos/"], &deps);
+        assert_eq!((rc, deps.get_output_as_string()), (0, "4\n".to_string()));
+    }
+
+    #[test]
+    fn locate_missing_database() {
+        let deps = FakeDependencies::new();
+        let rc = locate_main(&["locate", "--database=/doesnt/exist", "x"], &deps);
+        assert_eq!((rc, deps.get_output_as_string()), (1, String::new()));
+    }
+
+    #[test]
+    fn locate_ignore_case() {
+        assert_eq!(locate(&["-i", "CAT"]),
+                   (0, "/home/user/photos/cat.jpg\n/usr/bin/cat\n".to_string()));
+        assert_eq!(locate(&["--ignore-case", "*.jpg"]),
+                   (0, "/home/user/photos/cat.jpg\n/home/user/photos/dog.JPG\n".to_string()));
+        assert_eq!(locate(&["*.jpg"]), (0, "/home/user/photos/cat.jpg\n".to_string()));
+    }
+
+    #[test]
+    fn locate_basename() {
+        // without -b, everything under /home/user matches too
+        assert_eq!(locate(&["-b", "user"]), (0, "/home/user\n".to_string()));
+        assert_eq!(locate(&["--basename", "c*"]),
+                   (0, "/home/user/photos/cat.jpg\n/usr/bin/cat\n".to_string()));
+        assert_eq!(locate(&["-b", "/usr*"]), (1, String::new()));
+    }
+
+    #[test]
+    fn locate_basename_ignore_case() {
+        assert_eq!(locate(&["-b", "-i", "*.JPG"]),
+                   (0, "/home/user/photos/cat.jpg\n/home/user/photos/dog.JPG\n".to_string()));
+        assert_eq!(locate(&["-i", "-b", "USER", "PHOTOS"]),
+                   (0, "/home/user\n/home/user/photos\n".to_string()));
+    }
+
+    #[test]
+    fn locate_null() {
+        assert_eq!(locate(&["-0", "bin/"]), (0, "/usr/bin/cat\0/usr/bin/tac\0".to_string()));
+        // the count's still a line of its own
+        assert_eq!(locate(&["--null", "-c", "bin/"]), (0, "2\n".to_string()));
+    }
+
+    #[test]
+    fn locate_limit() {
+        assert_eq!(locate(&["-l", "2", "user"]),
+                   (0, "/home/user\n/home/user/notes.txt\n".to_string()));
+        assert_eq!(locate(&["-n1", "user"]), (0, "/home/user\n".to_string()));
+        assert_eq!(locate(&["--limit=0", "user"]), (1, String::new()));
+        assert_eq!(locate(&["-c", "--limit", "3", "user"]), (0, "3\n".to_string()));
+        assert_eq!(locate(&["-l", "10", "bin/"]),
+                   (0, "/usr/bin/cat\n/usr/bin/tac\n".to_string()));
+    }
+
+    #[test]
+    fn locate_limit_stops_reading() {
+        // the database is corrupt after the paths, which is only noticed if
+        // we read that far
+        let (_temp_dir, database) = make_database_with_trailer(b"\x7fbad");
+        let deps = FakeDependencies::new();
+        let rc = locate_main(&["locate", &database, "-l", "2", "photos"], &deps);
+        assert_eq!((rc, deps.get_output_as_string()),
+                   (0, "/home/user/photos\n/home/user/photos/cat.jpg\n".to_string()));
+
+        let deps = FakeDependencies::new();
+        let rc = locate_main(&["locate", &database, "photos"], &deps);
+        assert_eq!(rc, 1);
+        assert_eq!(deps.get_output_as_string().lines().count(), 3);
+    }
+
+    #[test]
+    fn locate_limit_several_databases() {
+        let (_temp_dir, database) = make_database();
+        let database = &database["--database=".len()..];
+        let databases = format!("{}:{}", database, database);
+        let deps = FakeDependencies::new();
+        let rc = locate_main(&["locate", "-l", "4", "-d", &databases, "photos/"], &deps);
+        assert_eq!((rc, deps.get_output_as_string()),
+                   (0,
+                    "/home/user/photos/cat.jpg\n/home/user/photos/dog.JPG\n\
+                     /home/user/photos/cat.jpg\n/home/user/photos/dog.JPG\n"
+                        .to_string()));
+    }
+
+    #[test]
+    fn locate_existing() {
+        let temp_dir = TempDir::new("locate_existing").unwrap();
+        let dir = temp_dir.path().to_string_lossy().into_owned();
+        File::create(temp_dir.path().join("kept")).unwrap();
+        File::create(temp_dir.path().join("deleted")).unwrap();
+        let db = temp_dir.path().join("db");
+        {
+            let file = BufWriter::new(File::create(&db).unwrap());
+            let mut encoder = Encoder::new(file).unwrap();
+            for name in &["deleted", "kept", "missing_dir/file"] {
+                encoder.add(format!("{}/{}", dir, name).as_bytes()).unwrap();
+            }
+            encoder.finish().unwrap();
+        }
+        fs::remove_file(temp_dir.path().join("deleted")).unwrap();
+        let database = format!("--database={}", db.to_string_lossy());
+
+        let deps = FakeDependencies::new();
+        let rc = locate_main(&["locate", &database, "-e", &dir], &deps);
+        assert_eq!((rc, deps.get_output_as_string()), (0, format!("{}/kept\n", dir)));
+        // without -e, they're all there
+        let deps = FakeDependencies::new();
+        assert_eq!(locate_main(&["locate", &database, "-c", &dir], &deps), 0);
+        assert_eq!(deps.get_output_as_string(), "3\n");
+        // if none of the matches exist, nothing matched
+        let deps = FakeDependencies::new();
+        assert_eq!(locate_main(&["locate", &database, "--existing", "deleted"], &deps), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn locate_existing_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new("locate_existing_symlinks").unwrap();
+        let broken = temp_dir.path().join("broken");
+        symlink(temp_dir.path().join("nowhere"), &broken).unwrap();
+        let db = temp_dir.path().join("db");
+        {
+            let mut encoder = Encoder::new(BufWriter::new(File::create(&db).unwrap())).unwrap();
+            encoder.add(broken.to_string_lossy().as_bytes()).unwrap();
+            encoder.finish().unwrap();
+        }
+        let database = format!("--database={}", db.to_string_lossy());
+
+        for &(args, expected) in &[(&["-e"][..], 1), (&["-e", "-L"], 1), (&["-e", "-P"], 0),
+                                   (&["--existing", "--nofollow"], 0), (&["-e", "-H"], 0)] {
+            let mut all_args = vec!["locate", &database, "broken"];
+            all_args.extend_from_slice(args);
+            let deps = FakeDependencies::new();
+            assert_eq!(locate_main(&all_args, &deps), expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn locate_regex() {
+        // unlike a substring, the regex can be anchored
+        assert_eq!(locate(&["cat"]).1.lines().count(), 2);
+        assert_eq!(locate(&["-r", "cat$"]), (0, "/usr/bin/cat\n".to_string()));
+        assert_eq!(locate(&["--regex", "^/usr"]),
+                   (0, "/usr/bin/cat\n/usr/bin/tac\n".to_string()));
+        assert_eq!(locate(&["-r", "^home"]), (1, String::new()));
+        // and isn't a glob
+        assert_eq!(locate(&["-r", "notes.txt*"]), (0, "/home/user/notes.txt\n".to_string()));
+        assert_eq!(locate(&["-r", "-i", "JPG$"]),
+                   (0, "/home/user/photos/cat.jpg\n/home/user/photos/dog.JPG\n".to_string()));
+        assert_eq!(locate(&["-r", "-b", "^c"]),
+                   (0, "/home/user/photos/cat.jpg\n/usr/bin/cat\n".to_string()));
+        assert_eq!(locate(&["-r", "(", "x"]), (1, String::new()));
+    }
+
+    /// Makes lookups of the path find a directory owned by uid (and gid) 1
+    /// with the given permissions.
+    fn fake_dir(deps: &mut FakeDependencies, path: &str, permissions: u32) {
+        deps.set_metadata(path,
+                          FileMetadata {
+                              mode: 0o40000 | permissions,
+                              uid: 1,
+                              gid: 1,
+                              ..FileMetadata::default()
+                          });
+    }
+
+    #[test]
+    fn locate_require_visibility() {
+        let temp_dir = TempDir::new("locate_require_visibility").unwrap();
+        let db = temp_dir.path().join("db");
+        {
+            let mut encoder = Encoder::new(BufWriter::new(File::create(&db).unwrap())).unwrap();
+            for path in &["/fake",
+                          "/fake/public",
+                          "/fake/public/file",
+                          "/fake/secret",
+                          "/fake/secret/file",
+                          "/fake/secret/open",
+                          "/fake/secret/open/file"] {
+                encoder.add(path.as_bytes()).unwrap();
+            }
+            encoder.finish().unwrap();
+        }
+        let database = format!("--database={}", db.to_string_lossy());
+        let locate_as = |uid, gid, args: &[&str]| {
+            let mut deps = FakeDependencies::new();
+            fake_dir(&mut deps, "/", 0o755);
+            fake_dir(&mut deps, "/fake", 0o755);
+            fake_dir(&mut deps, "/fake/public", 0o755);
+            fake_dir(&mut deps, "/fake/secret", 0o710);
+            // being able to get into this one doesn't help if you can't get
+            // into the directory it's in
+            fake_dir(&mut deps, "/fake/secret/open", 0o755);
+            let mut all_args = vec!["locate", &database];
+            all_args.extend_from_slice(args);
+            let identity = Identity {
+                uid: uid,
+                gids: vec![gid],
+            };
+            let rc = locate_main_as(&all_args, identity, &deps);
+            (rc, deps.get_output_as_string())
+        };
+
+        let visible = "/fake\n/fake/public\n/fake/public/file\n/fake/secret\n";
+        let everything = "/fake\n/fake/public\n/fake/public/file\n/fake/secret\n\
+                          /fake/secret/file\n/fake/secret/open\n/fake/secret/open/file\n";
+        assert_eq!(locate_as(2, 2, &["--require-visibility", "/fake"]),
+                   (0, visible.to_string()));
+        assert_eq!(locate_as(2, 2, &["--require-visibility", "secret/"]),
+                   (1, String::new()));
+        // the owner, the group and root can get in
+        for &(uid, gid) in &[(1, 2), (2, 1), (0, 0)] {
+            assert_eq!(locate_as(uid, gid, &["--require-visibility", "/fake"]),
+                       (0, everything.to_string()));
+        }
+        // and without --require-visibility, so can anyone
+        assert_eq!(locate_as(2, 2, &["/fake"]), (0, everything.to_string()));
+    }
+
+    #[test]
+    fn visibility_is_cached() {
+        let identity = Identity {
+            uid: 2,
+            gids: vec![2],
+        };
+        let mut visibility = Visibility::new(identity);
+        let mut deps = FakeDependencies::new();
+        fake_dir(&mut deps, "/", 0o755);
+        fake_dir(&mut deps, "/fake", 0o755);
+        fake_dir(&mut deps, "/fake/secret", 0o700);
+        assert!(visibility.is_visible(Path::new("/fake/file"), &deps));
+        assert!(!visibility.is_visible(Path::new("/fake/secret/file"), &deps));
+        assert!(visibility.is_visible(Path::new("relative"), &deps));
+
+        // what the directories were like the first time is what counts
+        let mut deps = FakeDependencies::new();
+        fake_dir(&mut deps, "/", 0o755);
+        fake_dir(&mut deps, "/fake", 0o700);
+        fake_dir(&mut deps, "/fake/secret", 0o755);
+        assert!(visibility.is_visible(Path::new("/fake/other"), &deps));
+        assert!(!visibility.is_visible(Path::new("/fake/secret/other"), &deps));
+        // but new directories are looked up
+        assert!(!visibility.is_visible(Path::new("/fake/public/file"), &deps));
+    }
+
+    /// Makes up paths out of a few characters, so that they have plenty of
+    /// prefixes in common, and plenty of matches that span the end of one.
+    fn tricky_paths() -> Vec<Vec<u8>> {
+        let mut seed: u32 = 1;
+        let mut random = |n: usize| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as usize % n
+        };
+        let mut paths = vec![vec![]];
+        for _ in 0..3000 {
+            let mut path = paths.last().unwrap().clone();
+            let keep = random(path.len() + 1);
+            path.truncate(keep);
+            for _ in 0..random(8) {
+                path.push(b"ab/"[random(3)]);
+            }
+            paths.push(path);
+        }
+        // and some long ones, with prefix lengths that need escapes
+        let long = vec![b'a'; 300];
+        let mut longer = long.clone();
+        longer.extend_from_slice(b"b/ab");
+        paths.extend(vec![long.clone(), longer, b"ab".to_vec(), long, vec![]]);
+        paths
+    }
+
+    #[test]
+    fn search_matches_naive_search() {
+        let paths = tricky_paths();
+        let mut encoder = Encoder::new(vec![]).unwrap();
+        for path in &paths {
+            encoder.add(path).unwrap();
+        }
+        let database = encoder.finish().unwrap();
+
+        let patterns: &[&[&str]] = &[&["a"], &["b"], &["/"], &[""], &["ab"], &["ba"], &["aba"],
+                                      &["abab"], &["a/b"], &["/a/"], &["aaaa"], &["bbb"],
+                                      &["ab", "/b"], &["b/ab", "ba", "aaa/"], &["a", ""],
+                                      &["-b", "ab"], &["-i", "AB"], &["*b"], &["-r", "b/a+$"]];
+        for args in patterns {
+            let mut all_args = vec!["locate"];
+            all_args.extend_from_slice(args);
+            let matcher = Matcher::from_args(&all_args).unwrap();
+
+            let mut found = vec![];
+            search(&database, &matcher, |path| {
+                    found.push(path.to_vec());
+                    Ok(true)
+                })
+                .unwrap();
+            let expected: Vec<Vec<u8>> =
+                paths.iter().filter(|path| matcher.matches(path)).cloned().collect();
+            assert!(!expected.is_empty(), "{:?}", args);
+            assert!(found == expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn search_stops() {
+        let mut encoder = Encoder::new(vec![]).unwrap();
+        for path in PATHS {
+            encoder.add(path.as_bytes()).unwrap();
+        }
+        let database = encoder.finish().unwrap();
+        let matcher = Matcher::from_args(&["locate", "photos"]).unwrap();
+        let mut found = vec![];
+        search(&database, &matcher, |path| {
+                found.push(String::from_utf8(path.to_vec()).unwrap());
+                Ok(found.len() < 2)
+            })
+            .unwrap();
+        assert_eq!(found, vec!["/home/user/photos", "/home/user/photos/cat.jpg"]);
+    }
+
+    #[test]
+    fn base_name_of_paths() {
+        assert_eq!(base_name(b"/a/b/c"), b"c");
+        assert_eq!(base_name(b"c"), b"c");
+        assert_eq!(base_name(b"/"), b"/");
+        assert_eq!(base_name(b"/a"), b"a");
+    }
+
+    #[test]
+    fn parse_args_errors() {
+        assert_eq!(parse_args(&["locate"]).err().unwrap().to_string(),
+                   "no pattern to search for specified");
+        assert_eq!(parse_args(&["locate", "-c"]).err().unwrap().to_string(),
+                   "no pattern to search for specified");
+        assert_eq!(parse_args(&["locate", "--bogus", "x"]).err().unwrap().to_string(),
+                   "unrecognized option '--bogus'");
+        assert_eq!(parse_args(&["locate", "-q", "x"]).err().unwrap().to_string(),
+                   "invalid option -- 'q'");
+        assert_eq!(parse_args(&["locate", "x", "-d"]).err().unwrap().to_string(),
+                   "option requires an argument -- 'd'");
+        assert_eq!(parse_args(&["locate", "-l", "many", "x"]).err().unwrap().to_string(),
+                   "invalid limit 'many'");
+        let options = parse_args(&["locate", "--", "-c"]).unwrap();
+        assert_eq!(options.patterns, vec!["-c"]);
+        assert!(!options.count);
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Helpers for parsing GNU-style command-line options, shared by the
+//! executables that take them.
+
+use std::error::Error;
+
+/// Returns the value from "--option=value", if arg is that option.
+pub fn long_option_value<'a>(arg: &'a str, long: &str) -> Option<&'a str> {
+    if arg.starts_with(long) && arg[long.len()..].starts_with('=') {
+        Some(&arg[long.len() + 1..])
+    } else {
+        None
+    }
+}
+
+/// If args[*i] is the given option (in its short form, e.g. "-d," or "-d ,",
+/// or its long form, if it has one, e.g. "--delimiter=," or "--delimiter ,"),
+/// returns its value, advancing i past the value if it was a separate
+/// argument.
+pub fn option_value<'a>(args: &[&'a str],
+                        i: &mut usize,
+                        short: &str,
+                        long: Option<&str>)
+                        -> Result<Option<&'a str>, Box<Error>> {
+    let arg = args[*i];
+    if arg == short || Some(arg) == long {
+        if *i + 1 >= args.len() {
+            return Err(From::from(if arg == short {
+                format!("option requires an argument -- '{}'", &short[1..])
+            } else {
+                format!("option '{}' requires an argument", arg)
+            }));
+        }
+        *i += 1;
+        Ok(Some(args[*i]))
+    } else if let Some(value) = long.and_then(|long| long_option_value(arg, long)) {
+        Ok(Some(value))
+    } else if !arg.starts_with("--") && arg.starts_with(short) {
+        Ok(Some(&arg[short.len()..]))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Like option_value, for options that only have a long form.
+pub fn long_only_option_value<'a>(args: &[&'a str],
+                                  i: &mut usize,
+                                  long: &str)
+                                  -> Result<Option<&'a str>, Box<Error>> {
+    let arg = args[*i];
+    if arg == long {
+        if *i + 1 >= args.len() {
+            return Err(From::from(format!("option '{}' requires an argument", arg)));
+        }
+        *i += 1;
+        Ok(Some(args[*i]))
+    } else {
+        Ok(long_option_value(arg, long))
+    }
+}
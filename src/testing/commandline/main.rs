@@ -4,10 +4,16 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
+#[cfg(unix)]
+extern crate libc;
+
 use std::env;
+use std::ffi::OsString;
 use std::fs::{self, File, OpenOptions};
-use std::io::Write;
+use std::io::{self, Read, Write};
 use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
 
 fn usage() -> ! {
     println!("Simple command-line app just used for testing -exec flags!");
@@ -17,6 +23,23 @@ fn usage() -> ! {
 #[derive(Default)]
 struct Config {
     exit_with_failure: bool,
+    exit_status: Option<i32>,
+    sleep_ms: u64,
+    read_stdin: bool,
+    /// Whether to write what was read from stdin to the destination file.
+    copy_stdin: bool,
+    stdin_is_tty: bool,
+    /// A signal to kill this process with, once the file's been written,
+    /// rather than exiting.
+    raise_signal: Option<i32>,
+    /// Environment variables to write the values of.
+    print_env: Vec<String>,
+    /// Lines to write to stdout (rather than to the destination file).
+    echo: Vec<String>,
+    /// Whether to write the arguments as quoted strings (as Rust's Debug
+    /// formatting does), so that empty ones, and ones with newlines in, can
+    /// be told apart.
+    quote_args: bool,
     destination_dir: String,
 }
 
@@ -37,8 +60,52 @@ fn open_file(destination_dir: &str) -> File {
     }
 }
 
+/// Writes an argument exactly as it was passed, even if it isn't valid
+/// UTF-8.
+#[cfg(unix)]
+fn write_arg(f: &mut File, arg: &OsString) {
+    use std::os::unix::ffi::OsStrExt;
+    f.write_all(arg.as_bytes()).expect("failed to write to file");
+}
+
+#[cfg(not(unix))]
+fn write_arg(f: &mut File, arg: &OsString) {
+    f.write_all(arg.to_string_lossy().as_bytes()).expect("failed to write to file");
+}
+
+#[cfg(unix)]
+fn raise_signal(signal: i32) {
+    unsafe {
+        libc::raise(signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn raise_signal(_: i32) {
+    usage();
+}
+
+#[cfg(unix)]
+fn stdin_is_tty() -> bool {
+    unsafe { libc::isatty(0) == 1 }
+}
+
+#[cfg(not(unix))]
+fn stdin_is_tty() -> bool {
+    false
+}
+
+/// Reads the whole of stdin, or returns None if that fails.
+fn read_stdin() -> Option<Vec<u8>> {
+    let mut input = vec![];
+    io::stdin().read_to_end(&mut input).ok().map(|_| input)
+}
+
 fn main() {
-    let args = env::args().collect::<Vec<String>>();
+    let os_args = env::args_os().collect::<Vec<OsString>>();
+    let args = os_args.iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
     if args.len() < 2 || args[1] == "-h" || args[1] == "--help" {
         usage();
     }
@@ -50,6 +117,37 @@ fn main() {
                 "--exit_with_failure" => {
                     config.exit_with_failure = true;
                 }
+                _ if arg.starts_with("--exit_with_status=") => {
+                    config.exit_status = Some(arg["--exit_with_status=".len()..]
+                        .parse()
+                        .unwrap_or_else(|_| usage()));
+                }
+                "--read_stdin" => {
+                    config.read_stdin = true;
+                }
+                "--copy_stdin" => {
+                    config.copy_stdin = true;
+                }
+                _ if arg.starts_with("--raise_signal=") => {
+                    config.raise_signal = Some(arg["--raise_signal=".len()..]
+                        .parse()
+                        .unwrap_or_else(|_| usage()));
+                }
+                "--stdin_is_tty" => {
+                    config.stdin_is_tty = true;
+                }
+                "--quote_args" => {
+                    config.quote_args = true;
+                }
+                _ if arg.starts_with("--print_env=") => {
+                    config.print_env.push(arg["--print_env=".len()..].to_string());
+                }
+                _ if arg.starts_with("--echo=") => {
+                    config.echo.push(arg["--echo=".len()..].to_string());
+                }
+                _ if arg.starts_with("--sleep=") => {
+                    config.sleep_ms = arg["--sleep=".len()..].parse().unwrap_or_else(|_| usage());
+                }
                 _ => {
                     usage();
                 }
@@ -57,18 +155,64 @@ fn main() {
         }
     }
 
+    // sleeping before the output file is created means that tests can tell
+    // whether other invocations ran in the meantime
+    thread::sleep(Duration::from_millis(config.sleep_ms));
+
+    // stdin's read before anything's written if it's to be copied, and
+    // otherwise afterwards (so that a command waiting for input has already
+    // shown that it ran)
+    let mut input = None;
+    if config.copy_stdin {
+        input = Some(read_stdin());
+    }
     {
         let mut f = open_file(&config.destination_dir);
         // first two args are going to be the path to this executable and
         // the destination_dir we want to write to. Don't write either of those
         // as they'll be non-deterministic.
-        f.write_fmt(format_args!("cwd={}\nargs=\n",
+        f.write_fmt(format_args!("cwd={}\n",
                                     env::current_dir().unwrap().to_string_lossy()))
             .expect("failed to write to file");
-        for arg in &args[2..] {
-            f.write_fmt(format_args!("{}\n", arg)).expect("failed to write to file");
+        for name in &config.print_env {
+            let line = match env::var_os(name) {
+                Some(value) => format!("{}={}\n", name, value.to_string_lossy()),
+                None => format!("{} is unset\n", name),
+            };
+            f.write_all(line.as_bytes()).expect("failed to write to file");
+        }
+        if let Some(Some(ref input)) = input {
+            f.write_fmt(format_args!("stdin={:?}\n", String::from_utf8_lossy(input)))
+                .expect("failed to write to file");
+        }
+        f.write_all(b"args=\n").expect("failed to write to file");
+        for arg in &os_args[2..] {
+            if config.quote_args {
+                f.write_fmt(format_args!("{:?}", arg)).expect("failed to write to file");
+            } else {
+                write_arg(&mut f, arg);
+            }
+            f.write_all(b"\n").expect("failed to write to file");
         }
 
     }
-    std::process::exit(if config.exit_with_failure { 2 } else { 0 });
+    for line in &config.echo {
+        println!("{}", line);
+    }
+    // lets tests check whether there was anything to read on stdin
+    let mut stdin_is_empty = false;
+    if config.read_stdin {
+        let input = input.unwrap_or_else(read_stdin);
+        stdin_is_empty = input.map_or(true, |input| input.is_empty());
+    }
+    // and whether it was a terminal
+    let stdin_not_tty = config.stdin_is_tty && !stdin_is_tty();
+    if let Some(signal) = config.raise_signal {
+        raise_signal(signal);
+    }
+    std::process::exit(match config.exit_status {
+        Some(status) => status,
+        None if config.exit_with_failure || stdin_is_empty || stdin_not_tty => 2,
+        None => 0,
+    });
 }
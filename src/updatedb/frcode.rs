@@ -0,0 +1,280 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Reads and writes file name databases in GNU locate's LOCATE02 format, so
+//! that databases can be shared with GNU updatedb and locate.
+//!
+//! After a header, each path is stored as the change in the length of the
+//! prefix it shares with the previous path (a signed byte, or an escape byte
+//! followed by a big-endian 16-bit value if the change is too big for one),
+//! then the rest of the path, then a NUL. Similar paths are stored next to
+//! each other, so this makes the database much smaller than the paths are.
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// What every LOCATE02 database starts with.
+pub const MAGIC: &'static [u8] = b"\0LOCATE02\0";
+
+/// Says that the prefix length's change is in the following two bytes.
+const ESCAPE: u8 = 0x80;
+
+/// The biggest change in prefix length that fits in a single byte (-128
+/// can't be used, as its byte is the escape).
+const MAX_SMALL_CHANGE: isize = 127;
+
+/// Returns the path as the bytes that are stored in the database.
+#[cfg(unix)]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Returns the path that bytes from the database stand for.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Returns the length of the prefix that a and b have in common.
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|&(x, y)| x == y).count()
+}
+
+/// Writes paths to a database.
+pub struct Encoder<W: Write> {
+    output: W,
+    previous: Vec<u8>,
+    previous_prefix_len: usize,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Starts a new database, writing its header to output.
+    pub fn new(mut output: W) -> io::Result<Encoder<W>> {
+        output.write_all(MAGIC)?;
+        Ok(Encoder {
+            output: output,
+            previous: vec![],
+            previous_prefix_len: 0,
+        })
+    }
+
+    /// Adds a path to the database. Paths that share a prefix should be
+    /// added one after another, or the database won't be any smaller than
+    /// the paths are.
+    pub fn add(&mut self, path: &[u8]) -> io::Result<()> {
+        if path.contains(&0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "paths in the database can't contain NUL"));
+        }
+        let prefix_len = common_prefix_len(&self.previous, path);
+        let change = prefix_len as isize - self.previous_prefix_len as isize;
+        if change.abs() <= MAX_SMALL_CHANGE {
+            self.output.write_all(&[change as i8 as u8])?;
+        } else if change.abs() <= i16::max_value() as isize {
+            let change = change as i16;
+            self.output.write_all(&[ESCAPE, (change >> 8) as u8, change as u8])?;
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "path is too long for the database"));
+        }
+        self.output.write_all(&path[prefix_len..])?;
+        self.output.write_all(&[0])?;
+        self.previous.clear();
+        self.previous.extend_from_slice(path);
+        self.previous_prefix_len = prefix_len;
+        Ok(())
+    }
+
+    /// Finishes the database, returning what it was written to.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// Reads the paths in a database, in the order they were added.
+pub struct Decoder<R: BufRead> {
+    input: R,
+    path: Vec<u8>,
+    prefix_len: usize,
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Reads the database's header, failing if it isn't a LOCATE02 database.
+    pub fn new(mut input: R) -> io::Result<Decoder<R>> {
+        let mut header = [0; 10];
+        input.read_exact(&mut header)
+            .map_err(|_| invalid_data("not a LOCATE02 database"))?;
+        if header != MAGIC {
+            return Err(invalid_data("not a LOCATE02 database"));
+        }
+        Ok(Decoder {
+            input: input,
+            path: vec![],
+            prefix_len: 0,
+        })
+    }
+
+    /// Reads the next path, or returns None at the end of the database.
+    pub fn next_path(&mut self) -> io::Result<Option<&[u8]>> {
+        Ok(self.next_path_and_prefix_len()?.map(|(path, _)| path))
+    }
+
+    /// Like next_path, but also returns the length of the prefix that the
+    /// path has in common with the previous one (or at least, the part of
+    /// it that the database says is in common).
+    pub fn next_path_and_prefix_len(&mut self) -> io::Result<Option<(&[u8], usize)>> {
+        let mut byte = [0; 1];
+        if self.input.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        let change = if byte[0] == ESCAPE {
+            let mut bytes = [0; 2];
+            self.input.read_exact(&mut bytes)?;
+            (((bytes[0] as u16) << 8) | bytes[1] as u16) as i16 as isize
+        } else {
+            byte[0] as i8 as isize
+        };
+        let prefix_len = self.prefix_len as isize + change;
+        if prefix_len < 0 || prefix_len as usize > self.path.len() {
+            return Err(invalid_data("corrupt database: bad prefix length"));
+        }
+        self.prefix_len = prefix_len as usize;
+        self.path.truncate(self.prefix_len);
+        self.input.read_until(0, &mut self.path)?;
+        if self.path.pop() != Some(0) {
+            return Err(invalid_data("corrupt database: path isn't terminated"));
+        }
+        Ok(Some((&self.path, self.prefix_len)))
+    }
+}
+
+impl<R: BufRead> Iterator for Decoder<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        match self.next_path() {
+            Ok(Some(path)) => Some(Ok(path.to_vec())),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+    use super::*;
+
+    fn encode(paths: &[&[u8]]) -> Vec<u8> {
+        let mut encoder = Encoder::new(vec![]).unwrap();
+        for path in paths {
+            encoder.add(path).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    fn decode(database: Vec<u8>) -> io::Result<Vec<Vec<u8>>> {
+        Decoder::new(Cursor::new(database))?.collect()
+    }
+
+    #[test]
+    fn encode_matches_gnu() {
+        // as produced by GNU's frcode
+        let mut expected = MAGIC.to_vec();
+        expected.extend_from_slice(b"\x00/usr\x00\x04/bin\x00\x04/ls\x00\xfdlib\x00");
+        assert_eq!(encode(&[b"/usr", b"/usr/bin", b"/usr/bin/ls", b"/usr/lib"]),
+                   expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let paths: Vec<&[u8]> = vec![b"/",
+                                     b"/a",
+                                     b"/a/b",
+                                     b"/a/b/c",
+                                     b"/a/bb",
+                                     b"/a/\xff\x80not utf-8",
+                                     b"/b",
+                                     b"",
+                                     b"/b"];
+        assert_eq!(decode(encode(&paths)).unwrap(), paths);
+    }
+
+    #[test]
+    fn round_trip_big_prefix_changes() {
+        let long = vec![b'x'; 1000];
+        let mut longer = long.clone();
+        longer.extend_from_slice(b"/y");
+        let paths: Vec<&[u8]> = vec![b"x", &long, &longer, b"a", &long];
+        let database = encode(&paths);
+        // the change from a prefix of 1000 bytes to 0 needs an escape
+        assert!(database.contains(&ESCAPE));
+        assert_eq!(decode(database).unwrap(), paths);
+    }
+
+    #[test]
+    fn decode_prefix_lens() {
+        let database = encode(&[b"/usr", b"/usr/bin", b"/usr/lib", b"/", b"/var"]);
+        let mut decoder = Decoder::new(Cursor::new(database)).unwrap();
+        let mut prefix_lens = vec![];
+        while let Some((_, prefix_len)) = decoder.next_path_and_prefix_len().unwrap() {
+            prefix_lens.push(prefix_len);
+        }
+        assert_eq!(prefix_lens, vec![0, 4, 5, 1, 1]);
+    }
+
+    #[test]
+    fn empty_database() {
+        assert_eq!(encode(&[]), MAGIC);
+        assert!(decode(MAGIC.to_vec()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn encode_rejects_nul() {
+        let mut encoder = Encoder::new(vec![]).unwrap();
+        let e = encoder.add(b"a\0b").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn decode_bad_header() {
+        for database in &[&b""[..], b"\0LOCATE01\0", b"\0LOC"] {
+            let e = Decoder::new(Cursor::new(database)).err().unwrap();
+            assert_eq!(e.to_string(), "not a LOCATE02 database");
+        }
+    }
+
+    #[test]
+    fn decode_corrupt_database() {
+        let mut database = MAGIC.to_vec();
+        database.extend_from_slice(b"\x00abc\x00\x05d\x00");
+        let e = decode(database).unwrap_err();
+        assert_eq!(e.to_string(), "corrupt database: bad prefix length");
+
+        let mut database = MAGIC.to_vec();
+        database.extend_from_slice(b"\x00abc\x00\x01d");
+        let e = decode(database).unwrap_err();
+        assert_eq!(e.to_string(), "corrupt database: path isn't terminated");
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Support for updatedb --incremental, which brings an existing database up
+//! to date without reading every directory again.
+//!
+//! Adding, removing or renaming something in a directory changes the
+//! directory's modification time, so a directory that hasn't been modified
+//! since the database was made still has the contents the database says it
+//! has. Those contents are in the database in the order updatedb walks them
+//! (each directory followed by its contents, sorted by name), so the new walk
+//! can pick them up as it goes, in a single pass through the old paths.
+//!
+//! This relies on the database's modification time being (a little before)
+//! the time its walk started, as anything that changed during the walk may not
+//! have been seen. It also assumes that the same directories are pruned as
+//! when the database was made: things that were left out then aren't in the
+//! old database to be picked up.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::frcode::{self, Decoder};
+
+/// The paths in the database that's being brought up to date.
+pub struct OldDatabase {
+    /// The paths, in the order they were walked.
+    paths: Vec<PathBuf>,
+    /// Where we've got to: the paths before this have been walked past.
+    next: usize,
+    /// When the database was made.
+    time: SystemTime,
+}
+
+impl OldDatabase {
+    pub fn read(path: &Path) -> io::Result<OldDatabase> {
+        let time = fs::metadata(path)?.modified()?;
+        let decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+        let paths = decoder.map(|path| path.map(frcode::path_from_bytes))
+            .collect::<io::Result<Vec<PathBuf>>>()?;
+        Ok(OldDatabase {
+            paths: paths,
+            next: 0,
+            time: time,
+        })
+    }
+
+    /// Moves to the start of the paths under the given root (the walk order
+    /// is only defined within a root, not between them).
+    pub fn start_root(&mut self, root: &Path) {
+        self.next = self.paths
+            .iter()
+            .position(|path| path == root)
+            .unwrap_or(self.paths.len());
+    }
+
+    /// Moves past the given path, which comes after all the paths passed so
+    /// far in the walk, returning whether it's in the database.
+    pub fn seek(&mut self, path: &Path) -> bool {
+        // Path's ordering compares the components' bytes one at a time,
+        // which is the order of the walk
+        while self.next < self.paths.len() && self.paths[self.next].as_path() < path {
+            self.next += 1;
+        }
+        if self.next < self.paths.len() && self.paths[self.next] == path {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the names of what's in the given directory, if it's the path
+    /// that was just passed by seek and it hasn't been modified since the
+    /// database was made. Otherwise, the directory has to be read again.
+    pub fn unchanged_contents(&self, dir: &Path, modified: SystemTime) -> Option<Vec<OsString>> {
+        if modified >= self.time || self.next == 0 || self.paths[self.next - 1] != dir {
+            return None;
+        }
+        let names = self.paths[self.next..]
+            .iter()
+            .take_while(|path| path.starts_with(dir))
+            .filter(|path| path.parent() == Some(dir))
+            .filter_map(|path| path.file_name().map(|name| name.to_os_string()))
+            .collect();
+        Some(names)
+    }
+}
+
+/// Sets the file's modification (and access) time.
+#[cfg(unix)]
+pub fn set_modified(path: &Path, time: SystemTime) -> io::Result<()> {
+    use libc;
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::time::UNIX_EPOCH;
+
+    let since_epoch = time.duration_since(UNIX_EPOCH)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let time = libc::timeval {
+        tv_sec: since_epoch.as_secs() as libc::time_t,
+        tv_usec: (since_epoch.subsec_nanos() / 1000) as libc::suseconds_t,
+    };
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::utimes(path.as_ptr(), [time, time].as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// There's no portable way of setting the time, so elsewhere the database's
+/// time is when it was finished, and --incremental may miss things that change
+/// while it's being made.
+#[cfg(not(unix))]
+pub fn set_modified(_: &Path, _: SystemTime) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+    use super::*;
+
+    fn old_database(paths: &[&str]) -> OldDatabase {
+        OldDatabase {
+            paths: paths.iter().map(PathBuf::from).collect(),
+            next: 0,
+            time: SystemTime::now(),
+        }
+    }
+
+    fn names(names: &[&str]) -> Option<Vec<OsString>> {
+        Some(names.iter().map(OsString::from).collect())
+    }
+
+    #[test]
+    fn seek_follows_walk_order() {
+        let mut old = old_database(&["r", "r/a", "r/a/x", "r/a.txt", "r/c"]);
+        old.start_root(Path::new("r"));
+        assert!(old.seek(Path::new("r")));
+        assert!(old.seek(Path::new("r/a")));
+        // r/a/x has gone
+        assert!(!old.seek(Path::new("r/a/w")));
+        assert!(old.seek(Path::new("r/a.txt")));
+        assert!(!old.seek(Path::new("r/b")));
+        assert!(old.seek(Path::new("r/c")));
+        assert!(!old.seek(Path::new("r/d")));
+    }
+
+    #[test]
+    fn start_root_finds_root() {
+        let mut old = old_database(&["b", "b/x", "a", "a/y"]);
+        old.start_root(Path::new("a"));
+        assert!(old.seek(Path::new("a")));
+        assert!(old.seek(Path::new("a/y")));
+        old.start_root(Path::new("c"));
+        assert!(!old.seek(Path::new("c")));
+    }
+
+    #[test]
+    fn unchanged_contents() {
+        let mut old = old_database(&["r", "r/a", "r/a/x", "r/a/y", "r/b", "r/b/z", "r/c", "s"]);
+        let before = old.time - Duration::from_secs(1);
+        let after = old.time + Duration::from_secs(1);
+        old.start_root(Path::new("r"));
+        old.seek(Path::new("r"));
+        assert_eq!(old.unchanged_contents(Path::new("r"), before), names(&["a", "b", "c"]));
+        assert_eq!(old.unchanged_contents(Path::new("r"), after), None);
+        assert_eq!(old.unchanged_contents(Path::new("r/a"), before), None);
+        old.seek(Path::new("r/a"));
+        assert_eq!(old.unchanged_contents(Path::new("r/a"), before), names(&["x", "y"]));
+        old.seek(Path::new("r/c"));
+        assert_eq!(old.unchanged_contents(Path::new("r/c"), before), names(&[]));
+    }
+}
@@ -0,0 +1,548 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+pub mod frcode;
+pub mod incremental;
+
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, stderr, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use find::dir_walk::{self, DirWalk};
+use find::fsinfo::MountTable;
+use find::metadata::{FileKind, FileMetadata};
+use find::users::{Identity, EXECUTE, READ};
+use options::long_only_option_value;
+
+use self::frcode::Encoder;
+use self::incremental::OldDatabase;
+
+/// The directories that are left out of the database unless the user says
+/// otherwise (the same as GNU updatedb's).
+const DEFAULT_PRUNE_PATHS: &'static str = "/tmp /usr/tmp /var/tmp /afs /amd /sfs /proc";
+
+/// The types of filesystem that are left out of the database unless the user
+/// says otherwise: network and virtual filesystems, whose contents are slow
+/// to walk or not really files.
+const DEFAULT_PRUNE_FS: &'static str = "NFS nfs nfs4 rpc_pipefs afs binfmt_misc proc smbfs \
+                                        autofs iso9660 ncpfs coda devpts ftpfs devfs mfs shfs \
+                                        sysfs cifs lustre_lite tmpfs usbfs udf ocfs2";
+
+/// Where the database goes if --output isn't given: somewhere the user can
+/// write to, as updatedb doesn't usually run as root here. None if there's
+/// nowhere suitable (i.e. neither XDG_CACHE_HOME nor HOME is set).
+pub fn default_database_path() -> Option<PathBuf> {
+    let cache_dir = match env::var_os("XDG_CACHE_HOME") {
+        Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_dir.join("findutils").join("locatedb"))
+}
+
+/// Splits a space-separated list, as GNU updatedb's options take.
+fn split_list(list: &str) -> Vec<String> {
+    list.split_whitespace().map(|s| s.to_string()).collect()
+}
+
+struct Options {
+    output: Option<PathBuf>,
+    local_paths: Vec<String>,
+    prune_paths: Vec<String>,
+    prune_fs: Vec<String>,
+    incremental: bool,
+    /// The user whose view of the filesystem the database should show, so
+    /// that it doesn't give away the names of files they can't see.
+    local_user: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            output: None,
+            local_paths: vec!["/".to_string()],
+            prune_paths: split_list(DEFAULT_PRUNE_PATHS),
+            prune_fs: split_list(DEFAULT_PRUNE_FS),
+            incremental: false,
+            local_user: None,
+        }
+    }
+}
+
+fn parse_args(args: &[&str]) -> Result<Options, Box<Error>> {
+    let mut options = Options::default();
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i];
+        if let Some(value) = long_only_option_value(args, &mut i, "--output")? {
+            options.output = Some(PathBuf::from(value));
+        } else if let Some(value) = long_only_option_value(args, &mut i, "--localpaths")? {
+            options.local_paths = split_list(value);
+        } else if let Some(value) = long_only_option_value(args, &mut i, "--prunepaths")? {
+            // paths are compared as they're found by the walk, so "/tmp/"
+            // has to be the same as "/tmp"
+            options.prune_paths = split_list(value)
+                .into_iter()
+                .map(|path| match path.trim_right_matches('/') {
+                    "" => "/".to_string(),
+                    trimmed => trimmed.to_string(),
+                })
+                .collect();
+        } else if let Some(value) = long_only_option_value(args, &mut i, "--prunefs")? {
+            options.prune_fs = split_list(value);
+        } else if let Some(value) = long_only_option_value(args, &mut i, "--localuser")? {
+            options.local_user = Some(value.to_string());
+        } else if arg == "--incremental" {
+            options.incremental = true;
+        } else {
+            return Err(From::from(format!("unrecognized option '{}'", arg)));
+        }
+        i += 1;
+    }
+    Ok(options)
+}
+
+/// Returns the id of the device that the directory is on.
+#[cfg(unix)]
+fn device(metadata: &FileMetadata) -> Option<u64> {
+    Some(metadata.dev)
+}
+
+#[cfg(not(unix))]
+fn device(_: &FileMetadata) -> Option<u64> {
+    None
+}
+
+/// Notes something we couldn't read, and so have had to leave out. (If even
+/// that can't be written, there's nowhere left to say so.)
+fn warn_unreadable(path: &Path, error: &io::Error) {
+    let _ = writeln!(&mut stderr(), "updatedb: {}: {}", path.to_string_lossy(), error);
+}
+
+/// Walks the directory trees, adding what it finds to a database.
+struct Walker<'a, W: Write> {
+    options: &'a Options,
+    /// The user to walk as, if there is one: we only go where they can.
+    identity: Option<Identity>,
+    mounts: Option<MountTable>,
+    /// The device that each of the directories above the current entry is
+    /// on. Only when the device changes do we have to look up what type of
+    /// filesystem we're on.
+    devices: Vec<Option<u64>>,
+    encoder: Encoder<W>,
+}
+
+impl<'a, W: Write> Walker<'a, W> {
+    fn new(options: &'a Options,
+           identity: Option<Identity>,
+           encoder: Encoder<W>)
+           -> Walker<'a, W> {
+        Walker {
+            options: options,
+            identity: identity,
+            mounts: if options.prune_fs.is_empty() {
+                None
+            } else {
+                Some(MountTable::new())
+            },
+            devices: vec![],
+            encoder: encoder,
+        }
+    }
+
+    /// Whether the directory at the given depth is on one of the types of
+    /// filesystem to leave out.
+    fn on_pruned_fs<F>(&mut self, path: &Path, depth: usize, metadata: F) -> bool
+        where F: FnOnce() -> Option<FileMetadata>
+    {
+        let mounts = match self.mounts {
+            Some(ref mounts) => mounts,
+            None => return false,
+        };
+        let device = metadata().and_then(|metadata| device(&metadata));
+        self.devices.truncate(depth);
+        let crossed_device = self.devices.last().map_or(true, |parent| *parent != device);
+        self.devices.push(device);
+        if !crossed_device {
+            return false;
+        }
+        let fs_type = match fs::canonicalize(path) {
+            Ok(path) => mounts.fs_type(&path).map(|s| s.to_string()),
+            Err(_) => None,
+        };
+        fs_type.map_or(false, |fs_type| self.options.prune_fs.contains(&fs_type))
+    }
+
+    /// Whether the path at the given depth (and, if it's a directory,
+    /// everything in it) should be left out of the database. The directory's
+    /// metadata is only needed if filesystem types are being checked.
+    fn is_pruned<F>(&mut self, path: &Path, is_dir: bool, depth: usize, metadata: F) -> bool
+        where F: FnOnce() -> Option<FileMetadata>
+    {
+        let path_str = path.to_string_lossy();
+        if self.options.prune_paths.iter().any(|prune_path| *prune_path == path_str) {
+            return true;
+        }
+        is_dir && self.on_pruned_fs(path, depth, metadata)
+    }
+
+    /// Whether the user we're walking as can get to root, i.e. can search
+    /// every directory above it.
+    fn may_reach(&self, root: &Path) -> bool {
+        let identity = match self.identity {
+            Some(ref identity) => identity,
+            None => return true,
+        };
+        // if root doesn't exist, then the walk will say so
+        let root = match fs::canonicalize(root) {
+            Ok(root) => root,
+            Err(_) => return true,
+        };
+        root.ancestors().skip(1).all(|dir| {
+            fs::metadata(dir)
+                .map(|metadata| identity.may(&FileMetadata::from_metadata(&metadata), EXECUTE))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Whether the user we're walking as can list the directory's contents.
+    fn may_list(&self, metadata: Option<&FileMetadata>) -> bool {
+        match (&self.identity, metadata) {
+            (&None, _) => true,
+            (&Some(ref identity), Some(metadata)) => identity.may(metadata, READ | EXECUTE),
+            (&Some(_), None) => false,
+        }
+    }
+
+    /// Notes that root is being left out because the user we're walking as
+    /// can't get to it. Returns whether it can.
+    fn check_reachable(&self, root: &Path) -> bool {
+        if self.may_reach(root) {
+            return true;
+        }
+        let error = io::Error::new(io::ErrorKind::PermissionDenied, "Permission denied");
+        warn_unreadable(root, &error);
+        false
+    }
+
+    /// Adds everything under root (and root itself) to the database, with
+    /// find's walk (so, like find, it can go deeper than a path can be
+    /// long). Things that can't be read are left out, with a note on stderr.
+    /// With an old database (for --incremental), directories that haven't
+    /// changed since it was made aren't read: their contents are taken from
+    /// it.
+    fn walk(&mut self, root: &str, mut old: Option<&mut OldDatabase>) -> io::Result<()> {
+        let root = Path::new(root);
+        if !self.check_reachable(root) {
+            return Ok(());
+        }
+        if let Some(ref mut old) = old {
+            old.start_root(root);
+        }
+        let mut it = DirWalk::new(root).sort(true);
+        while let Some(result) = it.next() {
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn_unreadable(&err.path, &err.error);
+                    continue;
+                }
+            };
+            if let Some(ref mut old) = old {
+                old.seek(&entry.path);
+            }
+            let is_dir = entry.file_type == FileKind::Dir;
+            let metadata = if is_dir {
+                dir_walk::stat_in(entry.dir.as_ref().map(|dir| &**dir), &entry.path, false).ok()
+            } else {
+                None
+            };
+            if self.is_pruned(&entry.path, is_dir, entry.depth, || metadata.clone()) {
+                it.skip_current_dir();
+                continue;
+            }
+            self.encoder.add(&frcode::path_to_bytes(&entry.path))?;
+            if !is_dir {
+                continue;
+            }
+            if !self.may_list(metadata.as_ref()) {
+                it.skip_current_dir();
+                continue;
+            }
+            // what's in the directory hasn't changed, though whether each of
+            // them is a directory might have, so the walk still looks
+            let modified = metadata.and_then(|metadata| metadata.modified);
+            let unchanged = match (old.as_ref(), modified) {
+                (Some(old), Some(modified)) => old.unchanged_contents(&entry.path, modified),
+                _ => None,
+            };
+            if let Some(names) = unchanged {
+                it.set_contents(names);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the database that --incremental is to bring up to date. If there
+/// isn't one (or it can't be read), a new one's made from scratch.
+fn read_old_database(path: &Path) -> Option<OldDatabase> {
+    match OldDatabase::read(path) {
+        Ok(old) => Some(old),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            let _ = writeln!(&mut stderr(),
+                             "updatedb: warning: can't read {}: {}; building it from scratch",
+                             path.to_string_lossy(),
+                             e);
+            None
+        }
+    }
+}
+
+/// Builds the database at output, of what the given user (or, if there
+/// isn't one, anybody) can see.
+fn write_database(options: &Options,
+                  identity: Option<Identity>,
+                  output: &Path)
+                  -> Result<(), Box<Error>> {
+    // the database is built alongside the old one, and only replaces it once
+    // it's complete, so that locate never sees half a database
+    let mut new_output = output.as_os_str().to_os_string();
+    new_output.push(".n");
+    let new_output = PathBuf::from(new_output);
+    let mut old = if options.incremental {
+        read_old_database(output)
+    } else {
+        None
+    };
+    // file times lag the clock a little (and on some filesystems, are
+    // rounded to a couple of seconds), so to be sure that a directory that
+    // looks older than the database really is, the database's time is a bit
+    // before the walk started
+    let start_time = SystemTime::now() - Duration::from_secs(2);
+    let file = File::create(&new_output).map_err(|e| {
+            format!("cannot open {} for writing: {}", new_output.to_string_lossy(), e)
+        })?;
+    let result = {
+        let mut walker = Walker::new(options, identity, Encoder::new(BufWriter::new(file))?);
+        options.local_paths
+            .iter()
+            .map(|root| walker.walk(root, old.as_mut()))
+            .collect::<io::Result<()>>()
+            .and_then(|_| walker.encoder.finish().map(|_| ()))
+            .and_then(|_| incremental::set_modified(&new_output, start_time))
+    };
+    match result {
+        Ok(()) => Ok(fs::rename(&new_output, output)?),
+        Err(e) => {
+            let _ = fs::remove_file(&new_output);
+            Err(From::from(format!("failed to write {}: {}", new_output.to_string_lossy(), e)))
+        }
+    }
+}
+
+fn do_updatedb(args: &[&str]) -> Result<(), Box<Error>> {
+    let options = parse_args(args)?;
+    let identity = match options.local_user {
+        Some(ref user) => {
+            Some(Identity::for_user(user).ok_or_else(|| format!("unknown user '{}'", user))?)
+        }
+        None => None,
+    };
+    let output = match options.output {
+        Some(ref output) => output.clone(),
+        None => {
+            let output = default_database_path()
+                .ok_or("can't tell where the database should go; use --output")?;
+            if let Some(dir) = output.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            output
+        }
+    };
+    write_database(&options, identity, &output)
+}
+
+/// Builds a database of the paths under the directories given by
+/// --localpaths (or /), which locate can then search. Returns the exit code.
+pub fn updatedb_main(args: &[&str]) -> i32 {
+    match do_updatedb(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(&mut stderr(), "updatedb: {}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use tempdir::TempDir;
+    use super::*;
+
+    #[test]
+    fn parse_args_defaults() {
+        let options = parse_args(&["updatedb"]).unwrap();
+        assert_eq!(options.output, None);
+        assert_eq!(options.local_paths, vec!["/"]);
+        assert!(options.prune_paths.contains(&"/tmp".to_string()));
+        assert!(options.prune_fs.contains(&"proc".to_string()));
+    }
+
+    #[test]
+    fn parse_args_options() {
+        let options = parse_args(&["updatedb",
+                                   "--output",
+                                   "db",
+                                   "--localpaths=/a  /b",
+                                   "--prunepaths=/a/x/ / /b/y",
+                                   "--prunefs=",
+                                   "--incremental"])
+            .unwrap();
+        assert_eq!(options.output, Some(PathBuf::from("db")));
+        assert_eq!(options.local_paths, vec!["/a", "/b"]);
+        assert_eq!(options.prune_paths, vec!["/a/x", "/", "/b/y"]);
+        assert!(options.prune_fs.is_empty());
+        assert!(options.incremental);
+        assert_eq!(options.local_user, None);
+
+        let options = parse_args(&["updatedb", "--localuser", "nobody"]).unwrap();
+        assert_eq!(options.local_user, Some("nobody".to_string()));
+
+        let options = parse_args(&["updatedb", "--output=other"]).unwrap();
+        assert_eq!(options.output, Some(PathBuf::from("other")));
+    }
+
+    #[test]
+    fn parse_args_errors() {
+        let e = parse_args(&["updatedb", "--bogus"]).err().unwrap();
+        assert_eq!(e.to_string(), "unrecognized option '--bogus'");
+        let e = parse_args(&["updatedb", "--output"]).err().unwrap();
+        assert_eq!(e.to_string(), "option '--output' requires an argument");
+    }
+
+    fn read_database(path: &Path) -> Vec<String> {
+        let decoder = frcode::Decoder::new(io::BufReader::new(File::open(path).unwrap())).unwrap();
+        decoder.map(|path| String::from_utf8(path.unwrap()).unwrap()).collect()
+    }
+
+    #[test]
+    fn updatedb_prune_paths() {
+        let temp_dir = TempDir::new("updatedb_prune_paths").unwrap();
+        let root = temp_dir.path().join("root");
+        fs::create_dir_all(root.join("keep").join("sub")).unwrap();
+        fs::create_dir_all(root.join("skip").join("sub")).unwrap();
+        File::create(root.join("keep").join("file")).unwrap();
+        File::create(root.join("skip").join("file")).unwrap();
+        File::create(root.join("skipped_file")).unwrap();
+        let root = root.to_string_lossy().into_owned();
+        let db = temp_dir.path().join("db");
+
+        let prune_paths = format!("--prunepaths={0}/skip/ {0}/skipped_file", root);
+        let rc = updatedb_main(&["updatedb",
+                                 &format!("--output={}", db.to_string_lossy()),
+                                 &format!("--localpaths={}", root),
+                                 &prune_paths,
+                                 "--prunefs="]);
+        assert_eq!(rc, 0);
+        let expected: Vec<String> = ["", "/keep", "/keep/file", "/keep/sub"]
+            .iter()
+            .map(|suffix| format!("{}{}", root, suffix))
+            .collect();
+        assert_eq!(read_database(&db), expected);
+        // nothing's left behind
+        assert!(!temp_dir.path().join("db.n").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn updatedb_local_user() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let temp_dir = TempDir::new("updatedb_local_user").unwrap();
+        let set_mode = |path: &Path, mode| {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap()
+        };
+        set_mode(temp_dir.path(), 0o755);
+        let root = temp_dir.path().join("root");
+        for dir in &["open", "closed", "closed/sub"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        File::create(root.join("open").join("file")).unwrap();
+        File::create(root.join("closed").join("file")).unwrap();
+        set_mode(&root, 0o755);
+        set_mode(&root.join("open"), 0o755);
+        set_mode(&root.join("closed"), 0o700);
+        let owner = fs::metadata(&root).unwrap().uid();
+        let root_str = root.to_string_lossy().into_owned();
+        let paths = |suffixes: &[&str]| -> Vec<String> {
+            suffixes.iter().map(|suffix| format!("{}{}", root_str, suffix)).collect()
+        };
+        let db = temp_dir.path().join("db");
+
+        // the names of what's in closed can't be given away, but that closed
+        // is there can be
+        let stranger = Identity {
+            uid: owner + 1,
+            gids: vec![],
+        };
+        for args in &[&[][..], &["--incremental"]] {
+            let mut all_args = vec!["updatedb".to_string(),
+                                    format!("--localpaths={}", root_str),
+                                    "--prunefs=".to_string()];
+            all_args.extend(args.iter().map(|arg| arg.to_string()));
+            let all_args: Vec<&str> = all_args.iter().map(|arg| arg.as_ref()).collect();
+            let options = parse_args(&all_args).unwrap();
+
+            write_database(&options, Some(stranger.clone()), &db).unwrap();
+            assert_eq!(read_database(&db), paths(&["", "/closed", "/open", "/open/file"]));
+
+            let owner = Identity {
+                uid: owner,
+                gids: vec![],
+            };
+            write_database(&options, Some(owner), &db).unwrap();
+            assert_eq!(read_database(&db),
+                       paths(&["",
+                               "/closed",
+                               "/closed/file",
+                               "/closed/sub",
+                               "/open",
+                               "/open/file"]));
+        }
+
+        // nor can anything that the user can't get to at all
+        let options = parse_args(&["updatedb",
+                                   &format!("--localpaths={}/closed/sub", root_str),
+                                   "--prunefs="])
+            .unwrap();
+        write_database(&options, Some(stranger), &db).unwrap();
+        assert!(read_database(&db).is_empty());
+        set_mode(&root.join("closed"), 0o755);
+    }
+
+    #[test]
+    fn updatedb_unknown_local_user() {
+        let rc = updatedb_main(&["updatedb", "--localuser=no-such-user-findutils"]);
+        assert_eq!(rc, 1);
+    }
+
+    #[test]
+    fn updatedb_bad_output() {
+        let temp_dir = TempDir::new("updatedb_bad_output").unwrap();
+        let db = temp_dir.path().join("missing").join("db");
+        let rc = updatedb_main(&["updatedb",
+                                 &format!("--output={}", db.to_string_lossy()),
+                                 "--localpaths=test_data"]);
+        assert_eq!(rc, 1);
+        assert!(!db.exists());
+    }
+}
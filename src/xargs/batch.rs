@@ -0,0 +1,73 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Builds the command lines for xargs -I, where each item is inserted into
+//! the command rather than appended to it. Appending items is left to
+//! exec::CommandBatcher.
+
+use std::error::Error;
+
+/// Returns arg with every occurrence of replace_str replaced by item.
+fn replace_all(arg: &[u8], replace_str: &[u8], item: &[u8]) -> Vec<u8> {
+    let mut result = vec![];
+    let mut i = 0;
+    while i < arg.len() {
+        if !replace_str.is_empty() && arg[i..].starts_with(replace_str) {
+            result.extend_from_slice(item);
+            i += replace_str.len();
+        } else {
+            result.push(arg[i]);
+            i += 1;
+        }
+    }
+    result
+}
+
+/// Builds the command for a single item in -I mode, where the item replaces
+/// every occurrence of replace_str in every argument (rather than being
+/// appended to the command). Fails if the result is too long, as there's no
+/// way of making it shorter.
+pub fn insert_item(command: &[String],
+                   replace_str: &str,
+                   item: &[u8],
+                   size_limit: usize)
+                   -> Result<Vec<Vec<u8>>, Box<Error>> {
+    let args: Vec<Vec<u8>> = command.iter()
+        .map(|arg| replace_all(arg.as_bytes(), replace_str.as_bytes(), item))
+        .collect();
+    let size: usize = args.iter().map(|arg| arg.len() + 1).sum();
+    if size > size_limit {
+        return Err(From::from("command too long"));
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: Vec<Vec<u8>>) -> Vec<String> {
+        args.into_iter().map(|arg| String::from_utf8(arg).unwrap()).collect()
+    }
+
+    #[test]
+    fn insert_item_replaces_every_occurrence() {
+        let command = vec!["cp".to_string(), "{}".to_string(), "dest/{}.{}".to_string()];
+        assert_eq!(strings(insert_item(&command, "{}", b"a b", 100).unwrap()),
+                   vec!["cp", "a b", "dest/a b.a b"]);
+        assert_eq!(strings(insert_item(&command, "XX", b"a", 100).unwrap()),
+                   vec!["cp", "{}", "dest/{}.{}"]);
+    }
+
+    #[test]
+    fn insert_item_checks_size() {
+        let command = vec!["echo".to_string(), "{}{}".to_string()];
+        // "echo\0" plus "abcabc\0" is 12 bytes
+        assert!(insert_item(&command, "{}", b"abc", 12).is_ok());
+        let e = insert_item(&command, "{}", b"abcd", 12).unwrap_err();
+        assert_eq!(e.to_string(), "command too long");
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Most of xargs's own errors make it exit with status 1, but when it stops
+//! because of something one of its commands did (or couldn't do), the exit
+//! status says what, so those errors get a type of their own.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use find::matchers::error_message;
+
+#[derive(Debug)]
+pub enum XargsError {
+    /// The command couldn't be run at all.
+    CommandNotRun { command: String, error: io::Error },
+    /// The command exited with status 255, which is how commands ask xargs
+    /// to stop.
+    CommandExited255 { command: String },
+    /// The command was killed by a signal.
+    CommandKilled { command: String, signal: i32 },
+    /// We were asked to stop by a signal (e.g. SIGINT).
+    Interrupted { signal: i32 },
+}
+
+impl XargsError {
+    /// The status xargs should exit with because of this error.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            XargsError::CommandNotRun { ref error, .. } => {
+                if error.kind() == io::ErrorKind::NotFound {
+                    127
+                } else {
+                    126
+                }
+            }
+            XargsError::CommandExited255 { .. } => 124,
+            XargsError::CommandKilled { .. } => 125,
+            // as is conventional, like being killed by the signal
+            XargsError::Interrupted { signal } => 128 + signal,
+        }
+    }
+}
+
+impl fmt::Display for XargsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            XargsError::CommandNotRun { ref command, ref error } => {
+                write!(f, "{}: {}", command, error_message(error))
+            }
+            XargsError::CommandExited255 { ref command } => {
+                write!(f, "{}: exited with status 255; aborting", command)
+            }
+            XargsError::CommandKilled { ref command, signal } => {
+                write!(f, "{}: terminated by signal {}", command, signal)
+            }
+            XargsError::Interrupted { signal } => write!(f, "interrupted by signal {}", signal),
+        }
+    }
+}
+
+impl Error for XargsError {
+    fn description(&self) -> &str {
+        match *self {
+            XargsError::CommandNotRun { .. } => "command couldn't be run",
+            XargsError::CommandExited255 { .. } => "command exited with status 255",
+            XargsError::CommandKilled { .. } => "command was killed by a signal",
+            XargsError::Interrupted { .. } => "interrupted by a signal",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let not_found = XargsError::CommandNotRun {
+            command: "foo".to_string(),
+            error: io::Error::new(io::ErrorKind::NotFound, "not found"),
+        };
+        assert_eq!(not_found.exit_code(), 127);
+        assert_eq!(not_found.to_string(), "foo: not found");
+
+        let not_executable = XargsError::CommandNotRun {
+            command: "foo".to_string(),
+            error: io::Error::new(io::ErrorKind::PermissionDenied, "permission denied"),
+        };
+        assert_eq!(not_executable.exit_code(), 126);
+
+        // like GNU xargs, without the " (os error 2)" that std adds
+        let from_os = XargsError::CommandNotRun {
+            command: "nosuch".to_string(),
+            error: io::Error::from_raw_os_error(2),
+        };
+        assert_eq!(from_os.to_string(), "nosuch: No such file or directory");
+
+        let exited_255 = XargsError::CommandExited255 { command: "foo".to_string() };
+        assert_eq!(exited_255.exit_code(), 124);
+        assert_eq!(exited_255.to_string(), "foo: exited with status 255; aborting");
+
+        let killed = XargsError::CommandKilled {
+            command: "foo".to_string(),
+            signal: 9,
+        };
+        assert_eq!(killed.exit_code(), 125);
+        assert_eq!(killed.to_string(), "foo: terminated by signal 9");
+
+        let interrupted = XargsError::Interrupted { signal: 15 };
+        assert_eq!(interrupted.exit_code(), 143);
+        assert_eq!(interrupted.to_string(), "interrupted by signal 15");
+    }
+}
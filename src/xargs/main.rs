@@ -0,0 +1,16 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+extern crate findutils;
+
+fn main() {
+    findutils::xargs::signals::install();
+    let args = std::env::args().collect::<Vec<String>>();
+    let strs: Vec<&str> = args.iter().map(|s| s.as_ref()).collect();
+    let deps = findutils::xargs::standard_dependencies();
+    let stdin = std::io::stdin();
+    std::process::exit(findutils::xargs::xargs_main(&strs, &mut stdin.lock(), &deps));
+}
@@ -0,0 +1,1026 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+pub mod batch;
+pub mod error;
+pub mod pool;
+pub mod signals;
+pub mod tokenizer;
+
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process::Stdio;
+
+use exec::{self, BatchLimits, CommandBatcher};
+use exec::batch::{SizeLimits, POSIX_ARG_MAX};
+use find::{Dependencies, StandardDependencies, TERMINAL};
+use find::quoting::shell_quote;
+use options::{long_only_option_value, long_option_value, option_value};
+
+use self::error::XargsError;
+use self::pool::ProcessPool;
+use self::tokenizer::Tokenizer;
+
+/// The command that's run if none is given.
+const DEFAULT_COMMAND: &'static str = "echo";
+
+/// The string that -i replaces if it isn't given one.
+const DEFAULT_REPLACE_STR: &'static str = "{}";
+
+/// The settings xargs was invoked with.
+#[derive(Debug)]
+struct Options {
+    /// The executable followed by its initial arguments.
+    command: Vec<String>,
+    /// The byte that separates input items, or None to split on blanks and
+    /// newlines (honouring quotes and backslashes).
+    delimiter: Option<u8>,
+    /// For -I, the string in the command that's replaced with each item.
+    replace_str: Option<String>,
+    /// The most items to pass to a single invocation (for -n).
+    max_args: Option<usize>,
+    /// The most input lines whose items can be passed to a single invocation
+    /// (for -L).
+    max_lines: Option<usize>,
+    /// The most invocations that can run at once, where 0 means no limit
+    /// (for -P).
+    max_procs: usize,
+    /// Whether to give up if a command line with max_args items (or
+    /// max_lines lines) would be too long (for -x).
+    exit_if_size_exceeded: bool,
+    /// Whether to skip running the command if there are no items (for -r).
+    no_run_if_empty: bool,
+    /// Whether to print each command line before running it (for -t).
+    verbose: bool,
+    /// Whether to ask before running each command line (for -p).
+    interactive: bool,
+    /// The longest a command line can be, if not the default (for -s).
+    max_chars: Option<usize>,
+    /// Whether to print the limits on the size of command lines.
+    show_limits: bool,
+    /// Whether the command was given, rather than being the default.
+    command_given: bool,
+    /// The file to read items from instead of stdin (for -a).
+    arg_file: Option<String>,
+    /// An item that marks the logical end of the input (for -E).
+    eof_str: Option<String>,
+    /// The environment variable that tells each command which process slot
+    /// it's running in.
+    process_slot_var: Option<String>,
+    /// Whether to give each command the terminal as its stdin (for -o).
+    open_tty: bool,
+    /// Warnings about the options, which are given before anything's run.
+    warnings: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            command: vec![],
+            delimiter: None,
+            replace_str: None,
+            max_args: None,
+            max_lines: None,
+            max_procs: 1,
+            exit_if_size_exceeded: false,
+            no_run_if_empty: false,
+            verbose: false,
+            interactive: false,
+            max_chars: None,
+            show_limits: false,
+            command_given: false,
+            arg_file: None,
+            eof_str: None,
+            process_slot_var: None,
+            open_tty: false,
+            warnings: vec![],
+        }
+    }
+}
+
+/// Parses the argument to -d, which is either a single character or a C-style
+/// escape sequence (e.g. "\n" or "\x2c").
+fn parse_delimiter(spec: &str) -> Result<u8, Box<Error>> {
+    if spec.len() == 1 {
+        return Ok(spec.as_bytes()[0]);
+    }
+    if !spec.starts_with('\\') {
+        return Err(From::from(format!("invalid input delimiter specification {}: the \
+                                       delimiter must be either a single character or an \
+                                       escape sequence starting with \\.",
+                                      spec)));
+    }
+    let invalid_escape = || format!("invalid escape sequence {} in input delimiter specification.",
+                                    spec);
+    let escaped = &spec[1..];
+    let (digits, radix) = match escaped {
+        "a" => return Ok(b'\x07'),
+        "b" => return Ok(b'\x08'),
+        "f" => return Ok(b'\x0c'),
+        "n" => return Ok(b'\n'),
+        "r" => return Ok(b'\r'),
+        "t" => return Ok(b'\t'),
+        "v" => return Ok(b'\x0b'),
+        "\\" => return Ok(b'\\'),
+        _ if escaped.starts_with('x') => (&escaped[1..], 16),
+        _ if escaped.starts_with(|c: char| c.is_digit(8)) => (escaped, 8),
+        _ => return Err(From::from(invalid_escape())),
+    };
+    match u32::from_str_radix(digits, radix) {
+        Ok(value) if value <= 0xff => Ok(value as u8),
+        Ok(_) => {
+            Err(From::from(format!("invalid escape sequence {} in input delimiter \
+                                    specification; character values must not exceed ff.",
+                                   spec)))
+        }
+        Err(_) => Err(From::from(invalid_escape())),
+    }
+}
+
+
+impl Options {
+    fn warn_if_delimiter_overridden(&mut self, previous_option: Option<&str>, option: &str) {
+        if previous_option.is_some() && previous_option != Some(option) {
+            self.warnings.push(format!("options --null and --delimiter are mutually exclusive, \
+                                        so only the last one given ({}) will be used",
+                                       option));
+        }
+    }
+
+    fn is_set(&self, batching: Batching) -> bool {
+        match batching {
+            Batching::Replace => self.replace_str.is_some(),
+            Batching::MaxArgs => self.max_args.is_some(),
+            Batching::MaxLines => self.max_lines.is_some(),
+        }
+    }
+
+    /// Drops whichever of the other options in `BATCHING_OPTIONS` was given
+    /// before the one about to be set (which the warning calls `option`).
+    fn override_batching(&mut self, batching: Batching, option: &str) {
+        for &(previous, previous_name) in BATCHING_OPTIONS {
+            if previous == batching || !self.is_set(previous) {
+                continue;
+            }
+            self.warnings.push(format!("options {} and {} are mutually exclusive, ignoring \
+                                        previous {} value",
+                                       previous_name,
+                                       option,
+                                       previous_name));
+            match previous {
+                Batching::Replace => self.replace_str = None,
+                Batching::MaxArgs => self.max_args = None,
+                Batching::MaxLines => self.max_lines = None,
+            }
+        }
+    }
+
+    fn set_replace_str(&mut self, replace_str: &str) {
+        self.override_batching(Batching::Replace, "--replace/-I/-i");
+        self.replace_str = Some(replace_str.to_string());
+    }
+
+    fn set_max_args(&mut self, max_args: usize) {
+        if max_args == 1 && self.replace_str.is_some() {
+            // -I runs the command once per item anyway
+            return;
+        }
+        self.override_batching(Batching::MaxArgs, "--max-args/-n");
+        self.max_args = Some(max_args);
+    }
+
+    /// Sets the number of lines per command, for -L (which warnings call
+    /// "-L") or -l (which they call "--max-lines/-l").
+    fn set_max_lines(&mut self, max_lines: usize, option: &str) {
+        self.override_batching(Batching::MaxLines, option);
+        self.max_lines = Some(max_lines);
+        // as with GNU xargs, -L implies -x
+        self.exit_if_size_exceeded = true;
+    }
+
+    fn set_eof_str(&mut self, eof_str: &str) {
+        // as per POSIX, an empty end of file string means there isn't one
+        self.eof_str = if eof_str.is_empty() { None } else { Some(eof_str.to_string()) };
+    }
+}
+
+/// The options that decide how items are split between commands, which
+/// (as with GNU xargs) are mutually exclusive: the last one given wins,
+/// except that -n 1 goes with -I (which runs a command per item anyway).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Batching {
+    Replace,
+    MaxArgs,
+    MaxLines,
+}
+
+/// Each of the batching options, with what a warning calls it when a later
+/// one overrides it.
+const BATCHING_OPTIONS: &'static [(Batching, &'static str)] =
+    &[(Batching::Replace, "--replace"),
+      (Batching::MaxArgs, "--max-args"),
+      (Batching::MaxLines, "--max-lines")];
+
+/// The most processes -P can ask for at once, as with GNU xargs.
+const MAX_PROCS: i32 = i32::max_value();
+
+/// The options that take a count (named as GNU xargs names them in errors,
+/// whichever spelling was used), and the smallest and largest counts each
+/// accepts. -P 0 means as many processes as there are commands to run.
+const COUNT_OPTIONS: &'static [(&'static str, i64, i64)] = &[("-n", 1, i64::max_value()),
+                                                             ("-L", 1, i64::max_value()),
+                                                             ("-l", 1, i64::max_value()),
+                                                             ("-s", 1, i64::max_value()),
+                                                             ("-P", 0, MAX_PROCS as i64)];
+
+/// Parses a number as strtol(3) would: after any leading whitespace and a
+/// sign, there has to be at least one decimal digit, and nothing but digits.
+/// Numbers too big for an i64 become the biggest (or smallest) there is.
+fn parse_number(value: &str) -> Option<i64> {
+    let trimmed = value.trim_left();
+    let (negative, digits) = match trimmed.as_bytes().first() {
+        Some(&b'-') => (true, &trimmed[1..]),
+        Some(&b'+') => (false, &trimmed[1..]),
+        _ => (false, trimmed),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let saturated = if negative { i64::min_value() } else { i64::max_value() };
+    Some(trimmed.parse().unwrap_or(saturated))
+}
+
+/// Parses the argument to an option that takes a count (one of those in
+/// `COUNT_OPTIONS`).
+fn parse_count(value: &str, option: &str) -> Result<usize, Box<Error>> {
+    let &(_, min, max) = COUNT_OPTIONS.iter()
+        .find(|&&(name, _, _)| name == option)
+        .expect("not an option that takes a count");
+    match parse_number(value) {
+        Some(count) if count < min => {
+            Err(From::from(format!("value {} for {} option should be >= {}", value, option, min)))
+        }
+        Some(count) if count > max => {
+            Err(From::from(format!("value {} for {} option should be <= {}", value, option, max)))
+        }
+        // (no option takes a negative count)
+        Some(count) => Ok((count as u64).min(usize::max_value() as u64) as usize),
+        None => Err(From::from(format!("invalid number \"{}\" for {} option", value, option))),
+    }
+}
+
+fn parse_args(args: &[&str]) -> Result<Options, Box<Error>> {
+    let mut options = Options::default();
+    // -0 and -d both set the delimiter, so the last one given wins
+    let mut delimiter_option = None;
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i];
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        if arg == "-0" || arg == "--null" {
+            options.warn_if_delimiter_overridden(delimiter_option, "--null");
+            options.delimiter = Some(b'\0');
+            delimiter_option = Some("--null");
+        } else if let Some(spec) = option_value(args, &mut i, "-d", Some("--delimiter"))? {
+            options.warn_if_delimiter_overridden(delimiter_option, "--delimiter");
+            options.delimiter = Some(parse_delimiter(spec)?);
+            delimiter_option = Some("--delimiter");
+        } else if let Some(value) = option_value(args, &mut i, "-I", None)? {
+            options.set_replace_str(value);
+        } else if arg == "-i" || arg == "--replace" {
+            // -i is the old name for -I, except that its argument is optional
+            options.set_replace_str(DEFAULT_REPLACE_STR);
+        } else if let Some(value) = long_option_value(arg, "--replace") {
+            options.set_replace_str(value);
+        } else if arg.starts_with("-i") {
+            options.set_replace_str(&arg[2..]);
+        } else if let Some(value) = option_value(args, &mut i, "-n", Some("--max-args"))? {
+            let max_args = parse_count(value, "-n")?;
+            options.set_max_args(max_args);
+        } else if let Some(value) = option_value(args, &mut i, "-L", None)? {
+            let max_lines = parse_count(value, "-L")?;
+            options.set_max_lines(max_lines, "-L");
+        } else if arg == "-r" || arg == "--no-run-if-empty" {
+            options.no_run_if_empty = true;
+        } else if arg == "-t" || arg == "--verbose" {
+            options.verbose = true;
+        } else if arg == "-p" || arg == "--interactive" {
+            // the prompt shows the command line, so there's no need to print
+            // it separately for -t
+            options.interactive = true;
+        } else if arg == "-o" || arg == "--open-tty" {
+            options.open_tty = true;
+        } else if arg == "-x" || arg == "--exit" {
+            options.exit_if_size_exceeded = true;
+        } else if let Some(value) = option_value(args, &mut i, "-P", Some("--max-procs"))? {
+            options.max_procs = parse_count(value, "-P")?;
+        } else if let Some(value) = option_value(args, &mut i, "-s", Some("--max-chars"))? {
+            options.max_chars = Some(parse_count(value, "-s")?);
+        } else if let Some(value) = option_value(args, &mut i, "-a", Some("--arg-file"))? {
+            options.arg_file = Some(value.to_string());
+        } else if let Some(value) = option_value(args, &mut i, "-E", None)? {
+            options.set_eof_str(value);
+        } else if arg == "-e" || arg == "--eof" {
+            // -e is the old name for -E, except that its argument is optional,
+            // and without one there's no end of file string
+            options.eof_str = None;
+        } else if let Some(value) = long_option_value(arg, "--eof") {
+            options.set_eof_str(value);
+        } else if arg.starts_with("-e") {
+            options.set_eof_str(&arg[2..]);
+        } else if let Some(value) = long_only_option_value(args, &mut i, "--process-slot-var")? {
+            options.process_slot_var = Some(value.to_string());
+        } else if arg == "--show-limits" {
+            options.show_limits = true;
+        } else if arg == "-l" || arg == "--max-lines" {
+            // similarly, -l is the old name for -L
+            options.set_max_lines(1, "--max-lines/-l");
+        } else if let Some(value) = long_option_value(arg, "--max-lines") {
+            let max_lines = parse_count(value, "-l")?;
+            options.set_max_lines(max_lines, "--max-lines/-l");
+        } else if arg.starts_with("-l") {
+            let max_lines = parse_count(&arg[2..], "-l")?;
+            options.set_max_lines(max_lines, "--max-lines/-l");
+        } else if arg.starts_with("--") {
+            return Err(From::from(format!("unrecognized option '{}'", arg)));
+        } else {
+            return Err(From::from(format!("invalid option -- '{}'",
+                                          arg.chars().nth(1).unwrap())));
+        }
+        i += 1;
+    }
+    options.command = args[i..].iter().map(|s| s.to_string()).collect();
+    options.command_given = !options.command.is_empty();
+    if options.eof_str.is_some() && options.delimiter.is_some() {
+        options.warnings.push("the -E option has no effect if -0 or -d is used.".to_string());
+    }
+    if !options.command_given {
+        options.command.push(DEFAULT_COMMAND.to_string());
+    }
+    Ok(options)
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(unix)]
+fn bytes_from_os_str(s: &OsStr) -> &[u8] {
+    use std::os::unix::ffi::OsStrExt;
+    s.as_bytes()
+}
+
+#[cfg(not(unix))]
+fn bytes_from_os_str(s: &OsStr) -> Vec<u8> {
+    s.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Shows the command line that's about to be run (for -t and -p), in the
+/// same format as GNU xargs: the arguments separated by spaces, each quoted
+/// for the shell if it needs to be.
+fn command_line(command: &[OsString], items: &[OsString]) -> String {
+    let args = command.iter()
+        .chain(items)
+        .map(|arg| String::from_utf8_lossy(&shell_quote(&bytes_from_os_str(arg))).into_owned())
+        .collect::<Vec<_>>();
+    args.join(" ")
+}
+
+#[cfg(unix)]
+fn stdin_is_terminal() -> bool {
+    unsafe { ::libc::isatty(0) == 1 }
+}
+
+#[cfg(not(unix))]
+fn stdin_is_terminal() -> bool {
+    false
+}
+
+/// Prints the limits on the size of command lines (for --show-limits), in the
+/// same words as GNU xargs.
+fn show_limits<'a>(limits: &SizeLimits,
+                   size_limit: usize,
+                   deps: &'a Dependencies<'a>)
+                   -> io::Result<()> {
+    let mut stderr = deps.get_error_output().borrow_mut();
+    writeln!(&mut stderr,
+             "Your environment variables take up {} bytes",
+             limits.environment)?;
+    writeln!(&mut stderr,
+             "POSIX upper limit on argument length (this system): {}",
+             limits.posix_upper_limit)?;
+    writeln!(&mut stderr,
+             "POSIX smallest allowable upper limit on argument length (all systems): {}",
+             POSIX_ARG_MAX)?;
+    writeln!(&mut stderr,
+             "Maximum length of command we could actually use: {}",
+             limits.max_usable)?;
+    writeln!(&mut stderr,
+             "Size of command buffer we are actually using: {}",
+             size_limit)?;
+    writeln!(&mut stderr,
+             "Maximum parallelism (--max-procs must be no greater): {}",
+             MAX_PROCS)
+}
+
+/// Where the commands' stdin comes from.
+enum ChildStdin {
+    /// Nowhere, as xargs is reading its items from stdin, and the commands
+    /// mustn't eat them.
+    Null,
+    /// xargs's own stdin, as it's reading its items from elsewhere (for -a).
+    Inherit,
+    /// The terminal, for interactive commands (for -o).
+    Terminal,
+}
+
+/// Runs commands on behalf of run_batches and run_per_item.
+struct Runner<'a> {
+    verbose: bool,
+    interactive: bool,
+    child_stdin: ChildStdin,
+    pool: ProcessPool,
+    /// Whether the command is the default echo, which is done here instead
+    /// if there's no echo to run (as on some Windows systems).
+    echo_fallback: bool,
+    deps: &'a Dependencies<'a>,
+}
+
+impl<'a> Runner<'a> {
+    /// Asks whether to run the command (for -p). Anything other than an
+    /// answer starting with 'y' or 'Y' (including no answer at all) means no.
+    /// Failing to ask is an error.
+    fn confirm(&self, command_line: &str) -> io::Result<bool> {
+        write!(self.deps.get_error_output().borrow_mut(), "{} ?...", command_line)?;
+        let mut response = String::new();
+        match self.deps.get_input().borrow_mut().read_line(&mut response) {
+            Ok(_) => Ok(response.starts_with('y') || response.starts_with('Y')),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Starts the command (the executable followed by its arguments) once,
+    /// with the given items appended to it.
+    fn run(&mut self, command: &[OsString], items: &[OsString]) -> Result<(), Box<Error>> {
+        if self.interactive {
+            if !self.confirm(&command_line(command, items))? {
+                return Ok(());
+            }
+        } else if self.verbose {
+            writeln!(self.deps.get_error_output().borrow_mut(),
+                     "{}",
+                     command_line(command, items))?;
+        }
+        let stdin = match self.child_stdin {
+            ChildStdin::Null => Stdio::null(),
+            ChildStdin::Inherit => Stdio::inherit(),
+            ChildStdin::Terminal => {
+                let terminal = File::open(TERMINAL)
+                    .map_err(|e| format!("failed to open {} for reading: {}", TERMINAL, e))?;
+                Stdio::from(terminal)
+            }
+        };
+        let mut child = exec::new_command(&command[0], command[1..].iter().chain(items), None);
+        child.stdin(stdin);
+        // anything we've written has to come before what the command writes
+        let _ = self.deps.get_output().borrow_mut().flush();
+        let result = self.pool.spawn(&command[0].to_string_lossy(), &mut child);
+        let not_found = match result {
+            Err(ref e) => e.downcast_ref::<XargsError>().map(XargsError::exit_code) == Some(127),
+            Ok(()) => false,
+        };
+        if not_found && self.echo_fallback {
+            return self.echo(command[1..].iter().chain(items));
+        }
+        result
+    }
+
+    /// Writes the arguments as echo would: separated by spaces, on a line of
+    /// their own.
+    fn echo<'b, I: Iterator<Item = &'b OsString>>(&self, args: I) -> Result<(), Box<Error>> {
+        let mut output = self.deps.get_output().borrow_mut();
+        for (i, arg) in args.enumerate() {
+            if i > 0 {
+                output.write_all(b" ")?;
+            }
+            output.write_all(&bytes_from_os_str(arg))?;
+        }
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Runs the command with as many items appended as will fit each time.
+fn run_batches<R: BufRead>(runner: &mut Runner,
+                           options: &Options,
+                           mut tokenizer: Tokenizer<R>,
+                           limits: BatchLimits)
+                           -> Result<(), Box<Error>> {
+    let command: Vec<OsString> = options.command.iter().map(OsString::from).collect();
+    let mut batcher = CommandBatcher::new(&command, limits)?;
+    let mut ran_command = false;
+
+    while let Some(item) = tokenizer.next() {
+        batcher.add(os_string_from_bytes(item?), tokenizer.ended_line())?;
+        while let Some(items) = batcher.next_batch() {
+            runner.run(&command, &items)?;
+            ran_command = true;
+        }
+    }
+    match batcher.take() {
+        Some(items) => runner.run(&command, &items),
+        // like GNU xargs, the command is run once even without any input,
+        // unless -r says otherwise
+        None if !ran_command && !options.no_run_if_empty => runner.run(&command, &[]),
+        None => Ok(()),
+    }
+}
+
+/// Runs the command once per item, with the item replacing replace_str
+/// wherever it occurs in the command (for -I).
+fn run_per_item<R: BufRead>(runner: &mut Runner,
+                            command: &[String],
+                            replace_str: &str,
+                            tokenizer: Tokenizer<R>,
+                            size_limit: usize)
+                            -> Result<(), Box<Error>> {
+    for item in tokenizer {
+        let command: Vec<OsString> =
+            batch::insert_item(command, replace_str, &item?, size_limit)?
+                .into_iter()
+                .map(os_string_from_bytes)
+                .collect();
+        runner.run(&command, &[])?;
+    }
+    Ok(())
+}
+
+fn do_xargs<'a>(args: &[&str],
+                input: &mut BufRead,
+                deps: &'a Dependencies<'a>)
+                -> Result<i32, Box<Error>> {
+    let options = parse_args(args)?;
+    for warning in &options.warnings {
+        writeln!(deps.get_error_output().borrow_mut(), "xargs: warning: {}", warning)?;
+    }
+    let limits = exec::batch::size_limits();
+    let size_limit = match options.max_chars {
+        Some(max_chars) if max_chars > limits.max_usable => {
+            return Err(From::from(format!("value {} for -s option should be <= {}",
+                                          max_chars,
+                                          limits.max_usable)));
+        }
+        Some(max_chars) => max_chars,
+        None => limits.default,
+    };
+    if options.show_limits {
+        show_limits(&limits, size_limit, deps)?;
+        // as with GNU xargs, "xargs --show-limits" on its own at a terminal
+        // is just asking about the limits, not for anything to be run
+        if !options.command_given && stdin_is_terminal() {
+            return Ok(0);
+        }
+    }
+    let mut runner = Runner {
+        verbose: options.verbose,
+        interactive: options.interactive,
+        child_stdin: if options.open_tty {
+            ChildStdin::Terminal
+        } else if options.arg_file.is_some() {
+            ChildStdin::Inherit
+        } else {
+            ChildStdin::Null
+        },
+        pool: ProcessPool::new(options.max_procs),
+        echo_fallback: !options.command_given,
+        deps: deps,
+    };
+    if let Some(ref slot_var) = options.process_slot_var {
+        runner.pool.set_slot_var(slot_var);
+    }
+    let mut arg_file;
+    let input: &mut BufRead = match options.arg_file {
+        Some(ref path) => {
+            arg_file = BufReader::new(File::open(path)
+                .map_err(|e| format!("Cannot open input file '{}': {}", path, e))?);
+            &mut arg_file
+        }
+        None => input,
+    };
+    let mut tokenizer = match (options.delimiter, &options.replace_str) {
+        (Some(delimiter), _) => Tokenizer::with_delimiter(input, delimiter),
+        // -I takes whole lines (unless there's an explicit delimiter)
+        (None, &Some(_)) => Tokenizer::by_line(input),
+        (None, &None) => Tokenizer::new(input),
+    };
+    if let Some(ref eof_str) = options.eof_str {
+        tokenizer.set_eof_str(eof_str);
+    }
+    let result = match options.replace_str {
+        Some(ref replace_str) => {
+            run_per_item(&mut runner, &options.command, replace_str, tokenizer, size_limit)
+        }
+        None => {
+            let limits = BatchLimits {
+                max_args: options.max_args,
+                max_lines: options.max_lines,
+                exit_if_size_exceeded: options.exit_if_size_exceeded,
+                ..BatchLimits::new(size_limit, limits.max_usable)
+            };
+            run_batches(&mut runner, &options, tokenizer, limits)
+        }
+    };
+    // even if something's gone wrong, the commands that are already running
+    // get to finish
+    let wait_result = runner.pool.wait_all();
+    if let Some(signal) = signals::interrupted() {
+        return Err(Box::new(XargsError::Interrupted { signal: signal }));
+    }
+    result?;
+    Ok(if wait_result? { 0 } else { 123 })
+}
+
+/// The dependencies to use when run as the real executable: the standard
+/// ones, except that stdin is where the items come from, so answers to -p's
+/// prompts have to come from the terminal. If there isn't one, the answer's
+/// always no.
+pub fn standard_dependencies() -> StandardDependencies {
+    let mut deps = StandardDependencies::new();
+    if deps.read_input_from_terminal().is_err() {
+        deps.set_input(Box::new(io::empty()));
+    }
+    deps
+}
+
+/// Reads items from input, and runs the command given in args with as many
+/// of them as will fit, repeatedly, until the input runs out. Answers to -p's
+/// prompts are read from deps' input. Returns the exit code to use, which as
+/// with GNU xargs is 123 if any invocation of the command failed, 124 if one
+/// exited with status 255, 125 if one was killed by a signal, 126 if the
+/// command couldn't be run, 127 if it couldn't be found, or 1 if xargs itself
+/// ran into trouble. Like `find::find_main`, it writes its messages to deps'
+/// error output and leaves exiting to the caller.
+pub fn xargs_main<'a>(args: &[&str], input: &mut BufRead, deps: &'a Dependencies<'a>) -> i32 {
+    let result = do_xargs(args, input, deps);
+    // output's buffered (though only the built-in echo writes any)
+    let _ = deps.get_output().borrow_mut().flush();
+    match result {
+        Ok(ret) => ret,
+        Err(e) => {
+            // (if that can't be written either, there's nowhere left to say
+            // so, but the exit code still does)
+            let _ = writeln!(deps.get_error_output().borrow_mut(), "xargs: {}", e);
+            match e.downcast_ref::<XargsError>() {
+                Some(e) => e.exit_code(),
+                None => 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args_command() {
+        assert_eq!(parse_args(&["xargs"]).unwrap().command, vec!["echo"]);
+        assert_eq!(parse_args(&["xargs", "ls", "-l"]).unwrap().command, vec!["ls", "-l"]);
+        assert_eq!(parse_args(&["xargs", "--", "-weird-command", "-x"]).unwrap().command,
+                   vec!["-weird-command", "-x"]);
+    }
+
+    #[test]
+    fn parse_args_null() {
+        let options = parse_args(&["xargs", "-0", "ls"]).unwrap();
+        assert_eq!(options.delimiter, Some(b'\0'));
+        assert_eq!(options.command, vec!["ls"]);
+        assert_eq!(parse_args(&["xargs", "--null"]).unwrap().delimiter, Some(b'\0'));
+        assert_eq!(parse_args(&["xargs", "ls", "-0"]).unwrap().delimiter, None);
+    }
+
+    #[test]
+    fn parse_args_delimiter() {
+        assert_eq!(parse_args(&["xargs", "-d", ",", "ls"]).unwrap().delimiter, Some(b','));
+        assert_eq!(parse_args(&["xargs", "-d,"]).unwrap().delimiter, Some(b','));
+        assert_eq!(parse_args(&["xargs", "--delimiter=\\n"]).unwrap().delimiter, Some(b'\n'));
+        assert_eq!(parse_args(&["xargs", "--delimiter", ":"]).unwrap().delimiter, Some(b':'));
+
+        // the last of -0 and -d wins
+        assert_eq!(parse_args(&["xargs", "-0", "-d", "x"]).unwrap().delimiter, Some(b'x'));
+        assert_eq!(parse_args(&["xargs", "-d", "x", "-0"]).unwrap().delimiter, Some(b'\0'));
+
+        let e = parse_args(&["xargs", "-d"]).unwrap_err();
+        assert_eq!(e.to_string(), "option requires an argument -- 'd'");
+        let e = parse_args(&["xargs", "--delimiter"]).unwrap_err();
+        assert_eq!(e.to_string(), "option '--delimiter' requires an argument");
+    }
+
+    #[test]
+    fn parse_delimiter_escapes() {
+        assert_eq!(parse_delimiter(",").unwrap(), b',');
+        assert_eq!(parse_delimiter("\\").unwrap(), b'\\');
+        assert_eq!(parse_delimiter("\\n").unwrap(), b'\n');
+        assert_eq!(parse_delimiter("\\t").unwrap(), b'\t');
+        assert_eq!(parse_delimiter("\\\\").unwrap(), b'\\');
+        assert_eq!(parse_delimiter("\\0").unwrap(), b'\0');
+        assert_eq!(parse_delimiter("\\054").unwrap(), b',');
+        assert_eq!(parse_delimiter("\\x2c").unwrap(), b',');
+        assert_eq!(parse_delimiter("\\xFF").unwrap(), 0xff);
+    }
+
+    #[test]
+    fn parse_delimiter_errors() {
+        let e = parse_delimiter("ab").unwrap_err();
+        assert_eq!(e.to_string(),
+                   "invalid input delimiter specification ab: the delimiter must be either a \
+                    single character or an escape sequence starting with \\.");
+        let e = parse_delimiter("\\q").unwrap_err();
+        assert_eq!(e.to_string(), "invalid escape sequence \\q in input delimiter specification.");
+        assert!(parse_delimiter("\\x").is_err());
+        assert!(parse_delimiter("\\09").is_err());
+        assert!(parse_delimiter("\\x100").is_err());
+        assert!(parse_delimiter("é").is_err());
+    }
+
+    #[test]
+    fn parse_args_replace() {
+        let options = parse_args(&["xargs", "-I", "%", "mv", "%", "dest"]).unwrap();
+        assert_eq!(options.replace_str, Some("%".to_string()));
+        assert_eq!(options.command, vec!["mv", "%", "dest"]);
+        assert_eq!(parse_args(&["xargs", "-IXX"]).unwrap().replace_str, Some("XX".to_string()));
+        assert_eq!(parse_args(&["xargs", "-i"]).unwrap().replace_str, Some("{}".to_string()));
+        assert_eq!(parse_args(&["xargs", "-i%", "ls"]).unwrap().replace_str,
+                   Some("%".to_string()));
+        assert_eq!(parse_args(&["xargs", "--replace", "ls"]).unwrap().replace_str,
+                   Some("{}".to_string()));
+        assert_eq!(parse_args(&["xargs", "--replace=%"]).unwrap().replace_str,
+                   Some("%".to_string()));
+        assert_eq!(parse_args(&["xargs", "ls"]).unwrap().replace_str, None);
+    }
+
+    #[test]
+    fn parse_args_max_args_and_lines() {
+        let options = parse_args(&["xargs", "-n", "2", "-L3", "ls"]).unwrap();
+        assert_eq!((options.max_args, options.max_lines), (None, Some(3)));
+        let options = parse_args(&["xargs", "--max-lines=3", "--max-args=2"]).unwrap();
+        assert_eq!((options.max_args, options.max_lines), (Some(2), None));
+        assert_eq!(parse_args(&["xargs", "-l"]).unwrap().max_lines, Some(1));
+
+        // -I and -n/-L are also mutually exclusive
+        let options = parse_args(&["xargs", "-I{}", "-n", "2"]).unwrap();
+        assert_eq!((options.replace_str, options.max_args), (None, Some(2)));
+        let options = parse_args(&["xargs", "-L", "2", "-I{}"]).unwrap();
+        assert_eq!((options.replace_str, options.max_lines), (Some("{}".to_string()), None));
+        // ...except for -n 1
+        let options = parse_args(&["xargs", "-I{}", "-n", "1"]).unwrap();
+        assert_eq!((options.replace_str, options.max_args), (Some("{}".to_string()), None));
+
+        let e = parse_args(&["xargs", "-n", "0"]).unwrap_err();
+        assert_eq!(e.to_string(), "value 0 for -n option should be >= 1");
+        let e = parse_args(&["xargs", "-L", "x"]).unwrap_err();
+        assert_eq!(e.to_string(), "invalid number \"x\" for -L option");
+    }
+
+    /// Every option that takes a count, with every way of being wrong, and
+    /// what GNU xargs says about each.
+    #[test]
+    fn parse_args_count_validation() {
+        let max_procs = |options: Options| options.max_procs;
+        let max_args = |options: Options| options.max_args.unwrap();
+        let max_lines = |options: Options| options.max_lines.unwrap();
+        let max_chars = |options: Options| options.max_chars.unwrap();
+        let cases: &[(&[&str], fn(Options) -> usize, Result<usize, &str>)] =
+            &[(&["-n", "3"], max_args, Ok(3)),
+              (&["--max-args=3"], max_args, Ok(3)),
+              (&["-n", " +3"], max_args, Ok(3)),
+              (&["-n", "010"], max_args, Ok(10)),
+              (&["-n", "99999999999999999999"], max_args, Ok(i64::max_value() as usize)),
+              (&["-n", "0"], max_args, Err("value 0 for -n option should be >= 1")),
+              (&["--max-args=-2"], max_args, Err("value -2 for -n option should be >= 1")),
+              (&["-n", "3 "], max_args, Err("invalid number \"3 \" for -n option")),
+              (&["-n", ""], max_args, Err("invalid number \"\" for -n option")),
+              (&["-n", "0x10"], max_args, Err("invalid number \"0x10\" for -n option")),
+              (&["-L", "2"], max_lines, Ok(2)),
+              (&["-L", "0"], max_lines, Err("value 0 for -L option should be >= 1")),
+              (&["-L", "two"], max_lines, Err("invalid number \"two\" for -L option")),
+              (&["-l5"], max_lines, Ok(5)),
+              (&["--max-lines=5"], max_lines, Ok(5)),
+              (&["-l0"], max_lines, Err("value 0 for -l option should be >= 1")),
+              (&["--max-lines=0"], max_lines, Err("value 0 for -l option should be >= 1")),
+              (&["-s", "100"], max_chars, Ok(100)),
+              (&["--max-chars=0"], max_chars, Err("value 0 for -s option should be >= 1")),
+              (&["-s", "-5"], max_chars, Err("value -5 for -s option should be >= 1")),
+              (&["-s", "x"], max_chars, Err("invalid number \"x\" for -s option")),
+              (&["-P", "0"], max_procs, Ok(0)),
+              (&["-P", "-0"], max_procs, Ok(0)),
+              (&["--max-procs=2147483647"], max_procs, Ok(2147483647)),
+              (&["-P", "-1"], max_procs, Err("value -1 for -P option should be >= 0")),
+              (&["-P", "2147483648"],
+               max_procs,
+               Err("value 2147483648 for -P option should be <= 2147483647")),
+              (&["-P", "99999999999999999999"],
+               max_procs,
+               Err("value 99999999999999999999 for -P option should be <= 2147483647")),
+              (&["-P", "many"], max_procs, Err("invalid number \"many\" for -P option"))];
+        for &(options, get, expected) in cases {
+            let mut args = vec!["xargs"];
+            args.extend_from_slice(options);
+            let result = parse_args(&args).map(get).map_err(|e| e.to_string());
+            assert_eq!(result, expected.map_err(|e| e.to_string()), "{:?}", options);
+        }
+    }
+
+    /// Which of -I, -n and -L wins when they're combined, and the warnings
+    /// (the same as GNU xargs's) about the ones that lose.
+    #[test]
+    fn parse_args_batching_conflicts() {
+        let replace = |what: &str| format!("options --replace and {} are mutually exclusive, \
+                                            ignoring previous --replace value",
+                                           what);
+        let max_args = |what: &str| format!("options --max-args and {} are mutually exclusive, \
+                                             ignoring previous --max-args value",
+                                            what);
+        let max_lines = |what: &str| format!("options --max-lines and {} are mutually \
+                                              exclusive, ignoring previous --max-lines value",
+                                             what);
+        let cases: &[(&[&str], (Option<&str>, Option<usize>, Option<usize>), Vec<String>)] =
+            &[(&["-I{}", "-n", "2"], (None, Some(2), None), vec![replace("--max-args/-n")]),
+              (&["-n", "2", "-I{}"], (Some("{}"), None, None), vec![max_args("--replace/-I/-i")]),
+              (&["-n", "2", "-i"], (Some("{}"), None, None), vec![max_args("--replace/-I/-i")]),
+              (&["-I{}", "-L", "2"], (None, None, Some(2)), vec![replace("-L")]),
+              (&["-L", "2", "--replace=%"],
+               (Some("%"), None, None),
+               vec![max_lines("--replace/-I/-i")]),
+              (&["-n", "3", "-L", "2"], (None, None, Some(2)), vec![max_args("-L")]),
+              (&["-n", "3", "-l"], (None, None, Some(1)), vec![max_args("--max-lines/-l")]),
+              (&["-L", "2", "--max-args=3"],
+               (None, Some(3), None),
+               vec![max_lines("--max-args/-n")]),
+              (&["-n", "3", "-L", "2", "-I{}"],
+               (Some("{}"), None, None),
+               vec![max_args("-L"), max_lines("--replace/-I/-i")]),
+              // -n 1 goes with -I, and options can be repeated
+              (&["-I{}", "-n", "1"], (Some("{}"), None, None), vec![]),
+              (&["-n", "1", "-I{}"], (Some("{}"), None, None), vec![max_args("--replace/-I/-i")]),
+              (&["-n", "2", "-n", "3"], (None, Some(3), None), vec![]),
+              (&["-L", "2", "-l"], (None, None, Some(1)), vec![])];
+        for &(options, expected, ref warnings) in cases {
+            let mut args = vec!["xargs"];
+            args.extend_from_slice(options);
+            let parsed = parse_args(&args).unwrap();
+            assert_eq!((parsed.replace_str.as_ref().map(|s| s.as_str()),
+                        parsed.max_args,
+                        parsed.max_lines),
+                       expected,
+                       "{:?}",
+                       options);
+            assert_eq!(&parsed.warnings, warnings, "{:?}", options);
+        }
+
+        // and -E can't be used with -0 or -d
+        let warning = "the -E option has no effect if -0 or -d is used.";
+        for options in &[&["-0", "-E", "END"][..], &["-e", "-d,"], &["-eEND", "-d,"]] {
+            let mut args = vec!["xargs"];
+            args.extend_from_slice(options);
+            let expected: &[&str] = if options[0] == "-e" { &[] } else { &[warning] };
+            assert_eq!(parse_args(&args).unwrap().warnings, expected, "{:?}", options);
+        }
+    }
+
+    #[test]
+    fn parse_args_exit() {
+        assert!(!parse_args(&["xargs", "-n", "2"]).unwrap().exit_if_size_exceeded);
+        assert!(parse_args(&["xargs", "-x", "-n", "2"]).unwrap().exit_if_size_exceeded);
+        assert!(parse_args(&["xargs", "--exit"]).unwrap().exit_if_size_exceeded);
+        assert!(parse_args(&["xargs", "-L", "2"]).unwrap().exit_if_size_exceeded);
+    }
+
+    #[test]
+    fn parse_args_flags() {
+        let options = parse_args(&["xargs", "ls"]).unwrap();
+        assert!(!options.no_run_if_empty && !options.verbose && !options.interactive);
+        let options = parse_args(&["xargs", "-r", "-t", "-p", "ls"]).unwrap();
+        assert!(options.no_run_if_empty && options.verbose && options.interactive);
+        let options = parse_args(&["xargs", "--no-run-if-empty", "--verbose", "--interactive"])
+            .unwrap();
+        assert!(options.no_run_if_empty && options.verbose && options.interactive);
+
+        assert!(!parse_args(&["xargs", "vim"]).unwrap().open_tty);
+        assert!(parse_args(&["xargs", "-o", "vim"]).unwrap().open_tty);
+        assert!(parse_args(&["xargs", "--open-tty", "vim"]).unwrap().open_tty);
+    }
+
+    #[test]
+    fn parse_args_max_chars() {
+        assert_eq!(parse_args(&["xargs"]).unwrap().max_chars, None);
+        assert_eq!(parse_args(&["xargs", "-s", "100"]).unwrap().max_chars, Some(100));
+        assert_eq!(parse_args(&["xargs", "--max-chars=100"]).unwrap().max_chars, Some(100));
+        let e = parse_args(&["xargs", "-s0"]).unwrap_err();
+        assert_eq!(e.to_string(), "value 0 for -s option should be >= 1");
+
+        let options = parse_args(&["xargs", "--show-limits"]).unwrap();
+        assert!(options.show_limits && !options.command_given);
+        assert!(parse_args(&["xargs", "--show-limits", "ls"]).unwrap().command_given);
+    }
+
+    #[test]
+    fn parse_args_arg_file_and_eof_str() {
+        let options = parse_args(&["xargs", "-a", "items", "ls"]).unwrap();
+        assert_eq!(options.arg_file, Some("items".to_string()));
+        assert_eq!(options.command, vec!["ls"]);
+        assert_eq!(parse_args(&["xargs", "--arg-file=items"]).unwrap().arg_file,
+                   Some("items".to_string()));
+
+        assert_eq!(parse_args(&["xargs", "-E", "END"]).unwrap().eof_str, Some("END".to_string()));
+        assert_eq!(parse_args(&["xargs", "-eEND"]).unwrap().eof_str, Some("END".to_string()));
+        assert_eq!(parse_args(&["xargs", "--eof=END"]).unwrap().eof_str, Some("END".to_string()));
+        assert_eq!(parse_args(&["xargs", "-E", "END", "-e"]).unwrap().eof_str, None);
+        assert_eq!(parse_args(&["xargs", "-E", ""]).unwrap().eof_str, None);
+    }
+
+    #[test]
+    fn parse_args_process_slot_var() {
+        assert_eq!(parse_args(&["xargs"]).unwrap().process_slot_var, None);
+        assert_eq!(parse_args(&["xargs", "--process-slot-var=SLOT", "ls"])
+                       .unwrap()
+                       .process_slot_var,
+                   Some("SLOT".to_string()));
+        let options = parse_args(&["xargs", "--process-slot-var", "SLOT", "ls"]).unwrap();
+        assert_eq!(options.process_slot_var, Some("SLOT".to_string()));
+        assert_eq!(options.command, vec!["ls"]);
+        let e = parse_args(&["xargs", "--process-slot-var"]).unwrap_err();
+        assert_eq!(e.to_string(), "option '--process-slot-var' requires an argument");
+    }
+
+    #[test]
+    fn command_line_format() {
+        let command = vec![OsString::from("echo"), OsString::from("-n")];
+        assert_eq!(command_line(&command, &[OsString::from("a"), OsString::from("b")]),
+                   "echo -n a b");
+        assert_eq!(command_line(&command, &[OsString::from("c d"), OsString::from("it's")]),
+                   "echo -n 'c d' 'it'\\''s'");
+        assert_eq!(command_line(&command[..1], &[OsString::from("")]), "echo ''");
+    }
+
+    #[test]
+    fn echo_fallback() {
+        use find::tests::FakeDependencies;
+
+        let deps = FakeDependencies::new();
+        let mut runner = Runner {
+            verbose: false,
+            interactive: false,
+            child_stdin: ChildStdin::Null,
+            pool: ProcessPool::new(1),
+            echo_fallback: true,
+            deps: &deps,
+        };
+        let command = vec![OsString::from("no-such-echo"), OsString::from("-x")];
+        let items = vec![OsString::from("a b"), OsString::from("c")];
+        runner.run(&command, &items).unwrap();
+        runner.run(&command[..1], &[]).unwrap();
+        assert_eq!(deps.get_output_as_string(), "-x a b c\n\n");
+
+        // only the default command falls back to echoing
+        runner.echo_fallback = false;
+        let e = runner.run(&command, &items).unwrap_err();
+        assert_eq!(e.downcast_ref::<XargsError>().unwrap().exit_code(), 127);
+        assert!(runner.pool.wait_all().unwrap());
+    }
+
+    #[test]
+    fn parse_args_max_procs() {
+        assert_eq!(parse_args(&["xargs"]).unwrap().max_procs, 1);
+        assert_eq!(parse_args(&["xargs", "-P", "4"]).unwrap().max_procs, 4);
+        assert_eq!(parse_args(&["xargs", "--max-procs=0"]).unwrap().max_procs, 0);
+        let e = parse_args(&["xargs", "-P", "-1"]).unwrap_err();
+        assert_eq!(e.to_string(), "value -1 for -P option should be >= 0");
+    }
+
+    #[test]
+    fn parse_args_invalid_option() {
+        let e = parse_args(&["xargs", "-q", "ls"]).unwrap_err();
+        assert_eq!(e.to_string(), "invalid option -- 'q'");
+        let e = parse_args(&["xargs", "--quiet", "ls"]).unwrap_err();
+        assert_eq!(e.to_string(), "unrecognized option '--quiet'");
+    }
+}
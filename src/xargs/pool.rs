@@ -0,0 +1,262 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Runs xargs's commands, up to a given number (-P) at a time.
+
+use std::cmp;
+use std::error::Error;
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use exec::ChildOutcome;
+use xargs::error::XargsError;
+use xargs::signals;
+
+/// How often to check for signals while waiting for children to exit.
+const SIGNAL_CHECK_INTERVAL_MS: u64 = 50;
+
+/// The slot a command ran in, its name, and how it turned out.
+type ChildExit = (usize, String, ChildOutcome);
+
+/// Keeps track of the commands that are running. Each child is waited for
+/// on a thread of its own, which reports back over a channel as soon as the
+/// child exits, so that we can wait for whichever child exits first.
+///
+/// Each running child also has a slot, a small number that no other running
+/// child has, which is reused once the child exits. Children can be told
+/// which slot they're in through an environment variable (for
+/// --process-slot-var), e.g. so that they can share out a fixed number of
+/// resources between them.
+pub struct ProcessPool {
+    /// The most children that can run at once, or None for no limit.
+    max_procs: Option<usize>,
+    running: usize,
+    /// The process ID of the child in each slot, or None if the slot's free.
+    slots: Vec<Option<u32>>,
+    /// The environment variable to put each child's slot number in.
+    slot_var: Option<String>,
+    all_succeeded: bool,
+    /// Set if a child exited in a way that means no more should be run.
+    fatal_error: Option<XargsError>,
+    /// Whether the running children have been sent the signal that
+    /// interrupted us.
+    forwarded_interrupt: bool,
+    sender: Sender<ChildExit>,
+    receiver: Receiver<ChildExit>,
+}
+
+impl ProcessPool {
+    /// Creates a pool that runs up to max_procs children at once, where 0
+    /// means as many as possible.
+    pub fn new(max_procs: usize) -> ProcessPool {
+        let (sender, receiver) = channel();
+        ProcessPool {
+            max_procs: if max_procs == 0 { None } else { Some(max_procs) },
+            running: 0,
+            slots: vec![],
+            slot_var: None,
+            all_succeeded: true,
+            fatal_error: None,
+            forwarded_interrupt: false,
+            sender: sender,
+            receiver: receiver,
+        }
+    }
+
+    /// Makes each child's slot number available to it in the given
+    /// environment variable.
+    pub fn set_slot_var(&mut self, slot_var: &str) {
+        self.slot_var = Some(slot_var.to_string());
+    }
+
+    /// Finds the lowest numbered free slot.
+    fn free_slot(&mut self) -> usize {
+        match self.slots.iter().position(|pid| pid.is_none()) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    /// Acts on any signals we've been sent since we last checked: SIGUSR1
+    /// and SIGUSR2 raise and lower max_procs (though never below 1), and the
+    /// signal that interrupted us (if any) is passed on to the children.
+    fn handle_signals(&mut self) {
+        let change = signals::take_parallelism_change();
+        if let Some(ref mut max_procs) = self.max_procs {
+            *max_procs = cmp::max(*max_procs as isize + change, 1) as usize;
+        }
+        if let Some(signal) = signals::interrupted() {
+            if !self.forwarded_interrupt {
+                for pid in self.slots.iter().filter_map(|&pid| pid) {
+                    signals::forward(signal, pid);
+                }
+                self.forwarded_interrupt = true;
+            }
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match self.max_procs {
+            Some(max_procs) => self.running >= max_procs,
+            None => false,
+        }
+    }
+
+    /// Waits a little while for any one of the running children to exit,
+    /// dealing with any signals that arrive in the meantime.
+    fn wait_for_one(&mut self) {
+        self.handle_signals();
+        let timeout = Duration::from_millis(SIGNAL_CHECK_INTERVAL_MS);
+        let (slot, name, outcome) = match self.receiver.recv_timeout(timeout) {
+            Ok(exit) => exit,
+            Err(RecvTimeoutError::Timeout) => return,
+            // the sender is never dropped, so this can't happen
+            Err(RecvTimeoutError::Disconnected) => unreachable!(),
+        };
+        self.running -= 1;
+        self.slots[slot] = None;
+        if outcome.success() {
+            return;
+        }
+        self.all_succeeded = false;
+        if self.fatal_error.is_some() {
+            return;
+        }
+        match outcome {
+            ChildOutcome::Killed(signal) => {
+                self.fatal_error = Some(XargsError::CommandKilled {
+                    command: name,
+                    signal: signal,
+                });
+            }
+            ChildOutcome::Exited(255) => {
+                self.fatal_error = Some(XargsError::CommandExited255 { command: name });
+            }
+            _ => {}
+        }
+    }
+
+    fn take_fatal_error(&mut self) -> Result<(), Box<Error>> {
+        if let Some(signal) = signals::interrupted() {
+            return Err(Box::new(XargsError::Interrupted { signal: signal }));
+        }
+        match self.fatal_error.take() {
+            Some(e) => Err(Box::new(e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts the command, first waiting for a child to exit if there are
+    /// already as many running as there can be. name is the command's name,
+    /// for error messages. Fails if the command can't be run, if one of the
+    /// children that's already exited means that no more should be, or if
+    /// we've been interrupted.
+    pub fn spawn(&mut self, name: &str, command: &mut Command) -> Result<(), Box<Error>> {
+        self.handle_signals();
+        while self.is_full() && signals::interrupted().is_none() {
+            self.wait_for_one();
+        }
+        self.take_fatal_error()?;
+        let slot = self.free_slot();
+        if let Some(ref slot_var) = self.slot_var {
+            // this only affects the child's environment, not ours
+            command.env(slot_var, slot.to_string());
+        }
+        let mut child = command.spawn()
+            .map_err(|e| {
+                XargsError::CommandNotRun {
+                    command: name.to_string(),
+                    error: e,
+                }
+            })?;
+        self.slots[slot] = Some(child.id());
+        let sender = self.sender.clone();
+        let name = name.to_string();
+        thread::spawn(move || {
+            // the receiver only goes away if we're exiting anyway
+            let _ = sender.send((slot, name, ChildOutcome::new(child.wait())));
+        });
+        self.running += 1;
+        Ok(())
+    }
+
+    /// Waits for all the running children to exit. Returns whether every
+    /// child that's been run exited successfully, or an error if one exited
+    /// in a way that means xargs should stop, or if we've been interrupted.
+    pub fn wait_all(&mut self) -> Result<bool, Box<Error>> {
+        while self.running > 0 {
+            self.wait_for_one();
+        }
+        self.take_fatal_error()?;
+        Ok(self.all_succeeded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn pool_reports_failures() {
+        let mut pool = ProcessPool::new(2);
+        for _ in 0..3 {
+            pool.spawn("true", &mut Command::new("true")).unwrap();
+            assert!(pool.running <= 2);
+        }
+        assert!(pool.wait_all().unwrap());
+        assert_eq!(pool.running, 0);
+
+        pool.spawn("false", &mut Command::new("false")).unwrap();
+        pool.spawn("true", &mut Command::new("true")).unwrap();
+        assert!(!pool.wait_all().unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn pool_stops_after_fatal_errors() {
+        let mut pool = ProcessPool::new(1);
+        pool.spawn("sh", Command::new("sh").args(&["-c", "exit 255"])).unwrap();
+        let e = pool.spawn("true", &mut Command::new("true")).unwrap_err();
+        assert_eq!(e.to_string(), "sh: exited with status 255; aborting");
+        assert_eq!(e.downcast_ref::<XargsError>().unwrap().exit_code(), 124);
+
+        pool.spawn("sh", Command::new("sh").args(&["-c", "kill -9 $$"])).unwrap();
+        let e = pool.wait_all().unwrap_err();
+        assert_eq!(e.to_string(), "sh: terminated by signal 9");
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let mut pool = ProcessPool::new(3);
+        pool.slots = vec![Some(10), Some(11), Some(12)];
+        assert_eq!(pool.free_slot(), 3);
+        pool.slots[1] = None;
+        assert_eq!(pool.free_slot(), 1);
+        pool.slots[0] = None;
+        assert_eq!(pool.free_slot(), 0);
+        assert_eq!(pool.slots.len(), 4);
+    }
+
+    #[test]
+    fn pool_without_limit() {
+        let mut pool = ProcessPool::new(0);
+        assert!(!pool.is_full());
+        let e = pool.spawn("no-such-command-findutils",
+                   &mut Command::new("no-such-command-findutils"))
+            .unwrap_err();
+        assert_eq!(e.downcast_ref::<XargsError>().unwrap().exit_code(), 127);
+        assert_eq!(pool.running, 0);
+        assert_eq!(pool.free_slot(), 0);
+        assert!(pool.wait_all().unwrap());
+    }
+}
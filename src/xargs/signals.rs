@@ -0,0 +1,91 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Handles the signals that xargs responds to. SIGINT and SIGTERM make xargs
+//! stop running commands, wait for the ones that are running and then exit,
+//! and as with GNU xargs, SIGUSR1 and SIGUSR2 raise and lower the number of
+//! commands that can run at once (-P).
+//!
+//! The handlers just record what's happened, and the process pool checks
+//! every so often while it's waiting for commands to finish.
+
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+
+/// The signal that interrupted us, or 0 if none has.
+static INTERRUPTED: AtomicUsize = AtomicUsize::new(0);
+
+/// How much the limit on the number of commands that can run at once has
+/// been raised (or, if negative, lowered) since it was last checked.
+static PARALLELISM_CHANGE: AtomicIsize = AtomicIsize::new(0);
+
+#[cfg(unix)]
+extern "C" fn handle_signal(signal: ::libc::c_int) {
+    match signal {
+        ::libc::SIGUSR1 => {
+            PARALLELISM_CHANGE.fetch_add(1, Ordering::SeqCst);
+        }
+        ::libc::SIGUSR2 => {
+            PARALLELISM_CHANGE.fetch_sub(1, Ordering::SeqCst);
+        }
+        _ => {
+            INTERRUPTED.store(signal as usize, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_handler(signal: ::libc::c_int, restart: bool) {
+    unsafe {
+        let mut action: ::libc::sigaction = ::std::mem::zeroed();
+        let handler: extern "C" fn(::libc::c_int) = handle_signal;
+        action.sa_sigaction = handler as ::libc::sighandler_t;
+        // without SA_RESTART, a read from stdin that's waiting for input is
+        // interrupted, so that we don't have to wait for the input to stop
+        action.sa_flags = if restart { ::libc::SA_RESTART } else { 0 };
+        ::libc::sigemptyset(&mut action.sa_mask);
+        ::libc::sigaction(signal, &action, ::std::ptr::null_mut());
+    }
+}
+
+/// Starts handling the signals. This should only be done by the xargs
+/// executable, as it changes what happens to the whole process.
+#[cfg(unix)]
+pub fn install() {
+    set_handler(::libc::SIGINT, false);
+    set_handler(::libc::SIGTERM, false);
+    set_handler(::libc::SIGUSR1, true);
+    set_handler(::libc::SIGUSR2, true);
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+/// Returns the signal that's asked us to stop, if there's been one.
+pub fn interrupted() -> Option<i32> {
+    match INTERRUPTED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal as i32),
+    }
+}
+
+/// Returns how much the limit on the number of commands that can run at once
+/// should change by, resetting it to 0.
+pub fn take_parallelism_change() -> isize {
+    PARALLELISM_CHANGE.swap(0, Ordering::SeqCst)
+}
+
+/// Passes on the signal that interrupted us to a command that's still
+/// running.
+#[cfg(unix)]
+pub fn forward(signal: i32, pid: u32) {
+    unsafe {
+        ::libc::kill(pid as ::libc::pid_t, signal);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn forward(_: i32, _: u32) {}
+
@@ -0,0 +1,389 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Splits xargs's input into items. Items are returned as raw bytes rather
+//! than strings because they're usually file names, and file names needn't be
+//! valid UTF-8.
+
+use std::error::Error;
+use std::io::{self, BufRead};
+
+use xargs::signals;
+
+fn is_blank(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t'
+}
+
+enum Separator {
+    BlanksAndNewlines,
+    Newlines,
+    Delimiter(u8),
+}
+
+/// Reads items from xargs's input. By default, items are separated by blanks
+/// and newlines, and as per POSIX, single and double quotes protect
+/// everything up to the matching quote (except newlines), and a backslash
+/// outside quotes protects the byte after it. Alternatively, items can be
+/// whole lines (for -I), or be separated by a single delimiter byte (e.g. NUL
+/// for -0), in which case nothing else is special.
+///
+/// As with GNU xargs, items can be empty, and are passed on as empty
+/// arguments: by default, only when quoted (e.g. ''), as runs of blanks and
+/// newlines are a single separator, and with a delimiter, whenever there's
+/// nothing between two of them (so "a\0\0b" is three items).
+pub struct Tokenizer<R: BufRead> {
+    input: R,
+    separator: Separator,
+    ended_line: bool,
+    /// An item that marks the logical end of the input (for -E).
+    eof_str: Option<Vec<u8>>,
+    /// Set once eof_str has been seen.
+    finished: bool,
+    /// The number of the input line that's being read, for error messages.
+    line: usize,
+}
+
+impl<R: BufRead> Tokenizer<R> {
+    /// Creates a tokenizer that splits on blanks and newlines.
+    pub fn new(input: R) -> Tokenizer<R> {
+        Tokenizer {
+            input: input,
+            separator: Separator::BlanksAndNewlines,
+            ended_line: false,
+            eof_str: None,
+            finished: false,
+            line: 1,
+        }
+    }
+
+    /// Creates a tokenizer that returns one item per line. Blanks at the
+    /// start of a line are skipped, but the rest are part of the item.
+    pub fn by_line(input: R) -> Tokenizer<R> {
+        Tokenizer {
+            input: input,
+            separator: Separator::Newlines,
+            ended_line: false,
+            eof_str: None,
+            finished: false,
+            line: 1,
+        }
+    }
+
+    /// Creates a tokenizer that splits on the given byte, and nothing else.
+    pub fn with_delimiter(input: R, delimiter: u8) -> Tokenizer<R> {
+        Tokenizer {
+            input: input,
+            separator: Separator::Delimiter(delimiter),
+            ended_line: false,
+            eof_str: None,
+            finished: false,
+            line: 1,
+        }
+    }
+
+    /// Makes the tokenizer stop at the first item that's exactly eof_str
+    /// (once quotes and backslashes have been dealt with), as if the input
+    /// ended there. This has no effect when splitting on a delimiter, as
+    /// then no input is special.
+    pub fn set_eof_str(&mut self, eof_str: &str) {
+        self.eof_str = Some(eof_str.as_bytes().to_vec());
+    }
+
+    /// Whether the last item returned was the last on its line (for -L). A
+    /// line that ends with a blank carries on to the next line, and when
+    /// splitting on a delimiter, each item is a line of its own.
+    pub fn ended_line(&self) -> bool {
+        self.ended_line
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, Box<Error>> {
+        loop {
+            let byte = match self.input.fill_buf() {
+                Ok(buffer) => buffer.first().cloned(),
+                // a signal that doesn't stop us shouldn't stop the input
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted &&
+                              signals::interrupted().is_none() => continue,
+                Err(e) => return Err(From::from(format!("error reading input: {}", e))),
+            };
+            if byte.is_some() {
+                self.input.consume(1);
+            }
+            if byte == Some(b'\n') {
+                self.line += 1;
+            }
+            return Ok(byte);
+        }
+    }
+
+    /// Reads the rest of a quoted string into item, stopping after the
+    /// closing quote. As per POSIX, a quoted string can't span lines, and
+    /// nothing (not even a backslash) is special inside it except the
+    /// closing quote.
+    fn read_quoted(&mut self, quote: u8, item: &mut Vec<u8>) -> Result<(), Box<Error>> {
+        let line = self.line;
+        loop {
+            match self.next_byte()? {
+                Some(byte) if byte == quote => return Ok(()),
+                Some(b'\n') | None => {
+                    let quote_name = if quote == b'\'' { "single" } else { "double" };
+                    return Err(From::from(format!("unmatched {} quote on line {}; by default \
+                                                   quotes are special to xargs unless you use \
+                                                   the -0 option",
+                                                  quote_name,
+                                                  line)));
+                }
+                Some(byte) => item.push(byte),
+            }
+        }
+    }
+
+    /// Reads up to the next delimiter. Unlike blanks, consecutive delimiters
+    /// have an empty item between them, but a final delimiter doesn't have
+    /// one after it.
+    fn read_delimited_item(&mut self, delimiter: u8) -> Result<Option<Vec<u8>>, Box<Error>> {
+        let mut item = vec![];
+        loop {
+            match self.next_byte()? {
+                Some(byte) if byte == delimiter => return Ok(Some(item)),
+                Some(byte) => item.push(byte),
+                None => return Ok(if item.is_empty() { None } else { Some(item) }),
+            }
+        }
+    }
+
+    fn read_item(&mut self) -> Result<Option<Vec<u8>>, Box<Error>> {
+        let mut item = vec![];
+        // distinguishes '' (an empty item) from no item at all
+        let mut in_item = false;
+        let blanks_separate = match self.separator {
+            Separator::BlanksAndNewlines => true,
+            _ => false,
+        };
+        // an item that's ended by a blank can't be the last on its line,
+        // because the line ends with a blank
+        self.ended_line = true;
+        loop {
+            let byte = match self.next_byte()? {
+                Some(byte) => byte,
+                None => break,
+            };
+            match byte {
+                b'\n' => {
+                    if in_item {
+                        break;
+                    }
+                }
+                byte if is_blank(byte) && (blanks_separate || !in_item) => {
+                    if in_item {
+                        self.ended_line = false;
+                        break;
+                    }
+                }
+                b'\'' | b'"' => {
+                    in_item = true;
+                    self.read_quoted(byte, &mut item)?;
+                }
+                b'\\' => {
+                    in_item = true;
+                    if let Some(escaped) = self.next_byte()? {
+                        item.push(escaped);
+                    }
+                }
+                byte => {
+                    in_item = true;
+                    item.push(byte);
+                }
+            }
+        }
+        if !in_item {
+            return Ok(None);
+        }
+        if self.eof_str.as_ref() == Some(&item) {
+            self.finished = true;
+            return Ok(None);
+        }
+        Ok(Some(item))
+    }
+}
+
+impl<R: BufRead> Iterator for Tokenizer<R> {
+    type Item = Result<Vec<u8>, Box<Error>>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>, Box<Error>>> {
+        if self.finished {
+            return None;
+        }
+        let item = match self.separator {
+            Separator::Delimiter(delimiter) => {
+                self.ended_line = true;
+                self.read_delimited_item(delimiter)
+            }
+            _ => self.read_item(),
+        };
+        match item {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn tokenize(input: &str) -> Result<Vec<String>, Box<Error>> {
+        let mut items = vec![];
+        for item in Tokenizer::new(Cursor::new(input.as_bytes())) {
+            items.push(String::from_utf8(item?).unwrap());
+        }
+        Ok(items)
+    }
+
+    #[test]
+    fn blanks_and_newlines_separate_items() {
+        assert_eq!(tokenize("a b\tc\nd\n").unwrap(), vec!["a", "b", "c", "d"]);
+        assert_eq!(tokenize("  a   \n\n  b").unwrap(), vec!["a", "b"]);
+        assert!(tokenize("").unwrap().is_empty());
+        assert!(tokenize(" \n\t\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quotes_and_backslashes() {
+        assert_eq!(tokenize("'a b' \"c d\"").unwrap(), vec!["a b", "c d"]);
+        assert_eq!(tokenize("\"a'b\" 'c\"d'").unwrap(), vec!["a'b", "c\"d"]);
+        assert_eq!(tokenize("a\\ b c\\\\d").unwrap(), vec!["a b", "c\\d"]);
+        assert_eq!(tokenize("pre'quoted'post").unwrap(), vec!["prequotedpost"]);
+        assert_eq!(tokenize("'' a").unwrap(), vec!["", "a"]);
+    }
+
+    #[test]
+    fn quotes_protect_blanks_and_each_other() {
+        assert_eq!(tokenize("'a b'").unwrap(), vec!["a b"]);
+        assert_eq!(tokenize("\"a\tb  c\"").unwrap(), vec!["a\tb  c"]);
+        assert_eq!(tokenize("\"a'b\"").unwrap(), vec!["a'b"]);
+        assert_eq!(tokenize("'a\"b'").unwrap(), vec!["a\"b"]);
+        assert_eq!(tokenize("'a'\"b\"'c d'").unwrap(), vec!["abc d"]);
+        assert_eq!(tokenize("\"\" ''").unwrap(), vec!["", ""]);
+    }
+
+    #[test]
+    fn backslashes() {
+        assert_eq!(tokenize("a\\ b").unwrap(), vec!["a b"]);
+        assert_eq!(tokenize("\\'a\\\"").unwrap(), vec!["'a\""]);
+        assert_eq!(tokenize("\\a\\b").unwrap(), vec!["ab"]);
+        // an escaped newline is part of the item
+        assert_eq!(tokenize("a\\\nb c").unwrap(), vec!["a\nb", "c"]);
+        // backslashes aren't special inside quotes
+        assert_eq!(tokenize("'a\\' \"b\\\"").unwrap(), vec!["a\\", "b\\"]);
+        // a backslash at the very end escapes nothing
+        assert_eq!(tokenize("a\\").unwrap(), vec!["a"]);
+    }
+
+    #[test]
+    fn unmatched_quotes() {
+        let e = tokenize("a 'b").unwrap_err();
+        assert_eq!(e.to_string(),
+                   "unmatched single quote on line 1; by default quotes are special to xargs \
+                    unless you use the -0 option");
+        // quotes can't span lines
+        let e = tokenize("a\nb\n\"c\nd\"").unwrap_err();
+        assert_eq!(e.to_string(),
+                   "unmatched double quote on line 3; by default quotes are special to xargs \
+                    unless you use the -0 option");
+        // escaped newlines still count as lines
+        let e = tokenize("a\\\nb 'c").unwrap_err();
+        assert!(e.to_string().starts_with("unmatched single quote on line 2;"), "{}", e);
+        let e = tokenize("'a' \"b'").unwrap_err();
+        assert!(e.to_string().starts_with("unmatched double quote on line 1;"), "{}", e);
+    }
+
+    #[test]
+    fn items_are_streamed() {
+        // items before a bad quote are still returned
+        let mut tokenizer = Tokenizer::new(Cursor::new(&b"a b\n'c"[..]));
+        assert_eq!(tokenizer.next().unwrap().unwrap(), b"a");
+        assert_eq!(tokenizer.next().unwrap().unwrap(), b"b");
+        assert!(tokenizer.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn non_utf8_bytes() {
+        let input: &[u8] = b"a\xff b";
+        let items = Tokenizer::new(Cursor::new(input)).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(items, vec![b"a\xff".to_vec(), b"b".to_vec()]);
+    }
+
+    #[test]
+    fn lines() {
+        let items = Tokenizer::by_line(Cursor::new(&b"  a b \n\n'c\\' d\\ e\n\t\n f"[..]))
+            .map(|item| String::from_utf8(item.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["a b ", "c\\ d e", "f"]);
+    }
+
+    #[test]
+    fn line_ends() {
+        let mut tokenizer = Tokenizer::new(Cursor::new(&b"a b\nc \nd\te\n\n'f '\ng"[..]));
+        let mut items = vec![];
+        while let Some(item) = tokenizer.next() {
+            items.push((String::from_utf8(item.unwrap()).unwrap(), tokenizer.ended_line()));
+        }
+        let expected = [("a", false), ("b", true), ("c", false), ("d", false), ("e", true),
+                        ("f ", true), ("g", true)];
+        assert_eq!(items,
+                   expected.iter().map(|&(s, b)| (s.to_string(), b)).collect::<Vec<_>>());
+    }
+
+    fn tokenize_until(input: &str, eof_str: &str) -> Vec<String> {
+        let mut tokenizer = Tokenizer::new(Cursor::new(input.as_bytes()));
+        tokenizer.set_eof_str(eof_str);
+        tokenizer.map(|item| String::from_utf8(item.unwrap()).unwrap()).collect()
+    }
+
+    #[test]
+    fn eof_str() {
+        assert_eq!(tokenize_until("a b\nEND\nc", "END"), vec!["a", "b"]);
+        assert_eq!(tokenize_until("a END c", "END"), vec!["a"]);
+        // the comparison is made once quotes have been removed
+        assert_eq!(tokenize_until("a 'EN'D c", "END"), vec!["a"]);
+        assert_eq!(tokenize_until("a ENDx xEND", "END"), vec!["a", "ENDx", "xEND"]);
+        // nothing after the end is read, even if it's invalid
+        assert_eq!(tokenize_until("a END 'b", "END"), vec!["a"]);
+
+        let mut tokenizer = Tokenizer::by_line(Cursor::new(&b"a b\n  END\nEND \nc"[..]));
+        tokenizer.set_eof_str("END");
+        let items = tokenizer.map(|item| String::from_utf8(item.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec!["a b"]);
+
+        let mut tokenizer = Tokenizer::with_delimiter(Cursor::new(&b"a\0END\0b"[..]), b'\0');
+        tokenizer.set_eof_str("END");
+        assert_eq!(tokenizer.count(), 3);
+    }
+
+    fn split_on_nul(input: &[u8]) -> Vec<Vec<u8>> {
+        Tokenizer::with_delimiter(Cursor::new(input), b'\0')
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn nul_delimited_items() {
+        assert_eq!(split_on_nul(b"a b\0'c\n\\d\0\xff\0"),
+                   vec![b"a b".to_vec(), b"'c\n\\d".to_vec(), b"\xff".to_vec()]);
+        // a missing final delimiter doesn't lose the last item
+        assert_eq!(split_on_nul(b"a\0b"), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(split_on_nul(b"a\0\0b\0"),
+                   vec![b"a".to_vec(), b"".to_vec(), b"b".to_vec()]);
+        assert!(split_on_nul(b"").is_empty());
+        // input that's nothing but delimiters is empty items, not no items
+        assert_eq!(split_on_nul(b"\0"), vec![b"".to_vec()]);
+        assert_eq!(split_on_nul(b"\0\0"), vec![b"".to_vec(), b"".to_vec()]);
+        assert_eq!(split_on_nul(b"a\0\0"), vec![b"a".to_vec(), b"".to_vec()]);
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Functions that make files of particular sorts in a directory (usually a
+//! TempDir's) for tests to find, returning their paths. This is shared by
+//! the integration tests and the library's own unit tests (which include it
+//! as `fixtures`), so it only uses std and libc.
+//!
+//! The ones that make what the platform can't have (or can't make without
+//! privileges it mightn't have) return None when they can't, having said so
+//! on stderr, so the test can skip whatever needs it:
+//!
+//! ```ignore
+//! let fifo = match make_fifo(temp_dir.path(), "fifo") {
+//!     Some(fifo) => fifo,
+//!     None => return,
+//! };
+//! ```
+
+extern crate libc;
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Says (on stderr, which cargo shows for failing tests and with
+/// --nocapture) that what a test needs can't be made here.
+fn skip(what: &str, path: &Path, reason: &str) -> Option<PathBuf> {
+    let _ = writeln!(io::stderr(),
+                     "skipping: can't make {} {}: {}",
+                     what,
+                     path.display(),
+                     reason);
+    None
+}
+
+/// Makes a file with the given contents.
+pub fn make_file(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    File::create(&path).unwrap().write_all(contents).unwrap();
+    path
+}
+
+/// Makes a file that's the given size but has nothing written to it, which
+/// (on file systems that support it) takes up next to no space.
+pub fn make_sparse_file(dir: &Path, name: &str, len: u64) -> PathBuf {
+    let path = dir.join(name);
+    File::create(&path).unwrap().set_len(len).unwrap();
+    path
+}
+
+/// Makes another name for an existing file.
+pub fn make_hard_link(dir: &Path, name: &str, target: &Path) -> PathBuf {
+    let path = dir.join(name);
+    fs::hard_link(target, &path).unwrap();
+    path
+}
+
+/// Makes a symbolic link to the target (relative to the directory it's in,
+/// unless it's absolute), which needn't exist. On Windows, it's a link to a
+/// directory if the target is one, and making one needs a privilege that
+/// not everyone has.
+pub fn make_symlink(dir: &Path, name: &str, target: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    match symlink(dir, target, &path) {
+        Ok(()) => Some(path),
+        Err(e) => skip("symbolic link", &path, &e.to_string()),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(_dir: &Path, target: &str, path: &Path) -> io::Result<()> {
+    ::std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(dir: &Path, target: &str, path: &Path) -> io::Result<()> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+
+    if dir.join(target).is_dir() {
+        symlink_dir(target, path)
+    } else {
+        symlink_file(target, path)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_dir: &Path, _target: &str, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "not supported on this platform"))
+}
+
+/// Makes a FIFO (named pipe). Unix only.
+pub fn make_fifo(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if cfg!(unix) {
+        match mkfifo(&path) {
+            Ok(()) => Some(path),
+            Err(e) => skip("FIFO", &path, &e.to_string()),
+        }
+    } else {
+        skip("FIFO", &path, "not supported on this platform")
+    }
+}
+
+#[cfg(unix)]
+fn mkfifo(path: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn mkfifo(_path: &Path) -> io::Result<()> {
+    unreachable!()
+}
+
+/// Makes a Unix domain socket (which stays, though nothing's listening on it
+/// any more). Unix only.
+pub fn make_socket(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if cfg!(unix) {
+        match bind_socket(&path) {
+            Ok(()) => Some(path),
+            // e.g. if the path is too long for a socket address
+            Err(e) => skip("socket", &path, &e.to_string()),
+        }
+    } else {
+        skip("socket", &path, "not supported on this platform")
+    }
+}
+
+#[cfg(unix)]
+fn bind_socket(path: &Path) -> io::Result<()> {
+    ::std::os::unix::net::UnixListener::bind(path).map(|_| ())
+}
+
+#[cfg(not(unix))]
+fn bind_socket(_path: &Path) -> io::Result<()> {
+    unreachable!()
+}
+
+/// Makes an empty file with the given permission bits (which the umask
+/// doesn't get a say in). Unix only.
+pub fn make_file_with_mode(dir: &Path, name: &str, mode: u32) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if cfg!(unix) {
+        File::create(&path).unwrap();
+        set_mode(&path, mode);
+        Some(path)
+    } else {
+        skip("file with mode", &path, "not supported on this platform")
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) {
+    unreachable!()
+}
+
+/// Makes an empty file that was last accessed and modified at the given
+/// time (to the second). Unix only.
+pub fn make_file_with_mtime(dir: &Path, name: &str, mtime: SystemTime) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if cfg!(unix) {
+        File::create(&path).unwrap();
+        set_mtime(&path, mtime);
+        Some(path)
+    } else {
+        skip("file with mtime", &path, "not supported on this platform")
+    }
+}
+
+/// Sets when an existing file (or what it links to) was last accessed and
+/// modified (to the second). Unix only.
+#[cfg(unix)]
+pub fn set_mtime(path: &Path, mtime: SystemTime) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::time::UNIX_EPOCH;
+
+    let since_epoch = mtime.duration_since(UNIX_EPOCH).unwrap();
+    let time = libc::timeval {
+        tv_sec: since_epoch.as_secs() as libc::time_t,
+        tv_usec: 0,
+    };
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::utimes(c_path.as_ptr(), [time, time].as_ptr()) }, 0);
+}
+
+#[cfg(not(unix))]
+fn set_mtime(_path: &Path, _mtime: SystemTime) {
+    unreachable!()
+}
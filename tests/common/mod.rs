@@ -8,4 +8,6 @@
 // As this module is included by all the integration tests, any function used
 // in one test but not another can cause a dead code warning.
 #[allow(dead_code)]
+pub mod fixtures;
+#[allow(dead_code)]
 pub mod test_helpers;
@@ -7,30 +7,48 @@
 
 use std::cell::RefCell;
 use std::env;
-use std::io::{Cursor, Read, Write};
+use std::ffi::OsString;
+use std::io::{self, BufRead, Cursor, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
 use std::time::SystemTime;
 use std::vec::Vec;
-use walkdir::{DirEntry, WalkDir};
+use walkdir::WalkDir;
 
-use findutils::find::matchers::MatcherIO;
+use findutils::find::matchers::{MatcherIO, WalkEntry};
 use findutils::find::Dependencies;
+use findutils::find::fsinfo::MountTable;
+use findutils::find::metadata::{self, FileMetadata};
+use findutils::find::users::UserGroupCache;
 
 /// A copy of find::tests::FakeDependencies.
 /// TODO: find out how to share #[cfg(test)] functions/structs between unit
 /// and integration tests.
 pub struct FakeDependencies {
     pub output: RefCell<Cursor<Vec<u8>>>,
+    pub error_output: RefCell<Cursor<Vec<u8>>>,
+    input: RefCell<Cursor<Vec<u8>>>,
     now: SystemTime,
+    user_group_cache: UserGroupCache,
+    mount_table: MountTable,
 }
 
 impl<'a> FakeDependencies {
     pub fn new() -> FakeDependencies {
         FakeDependencies {
             output: RefCell::new(Cursor::new(Vec::<u8>::new())),
+            error_output: RefCell::new(Cursor::new(Vec::<u8>::new())),
+            input: RefCell::new(Cursor::new(Vec::<u8>::new())),
             now: SystemTime::now(),
+            user_group_cache: UserGroupCache::new(),
+            mount_table: MountTable::new(),
         }
     }
 
+    pub fn set_input(&mut self, input: &str) {
+        self.input = RefCell::new(Cursor::new(input.as_bytes().to_vec()));
+    }
+
     pub fn new_matcher_io(&'a self) -> MatcherIO<'a> {
         MatcherIO::new(self)
     }
@@ -42,6 +60,14 @@ impl<'a> FakeDependencies {
         cursor.read_to_string(&mut contents).unwrap();
         contents
     }
+
+    pub fn get_error_output_as_string(&self) -> String {
+        let mut cursor = self.error_output.borrow_mut();
+        cursor.set_position(0);
+        let mut contents = String::new();
+        cursor.read_to_string(&mut contents).unwrap();
+        contents
+    }
 }
 
 impl<'a> Dependencies<'a> for FakeDependencies {
@@ -49,12 +75,44 @@ impl<'a> Dependencies<'a> for FakeDependencies {
         &self.output
     }
 
+    fn get_error_output(&'a self) -> &'a RefCell<Write> {
+        &self.error_output
+    }
+
+    fn get_input(&'a self) -> &'a RefCell<BufRead> {
+        &self.input
+    }
+
     fn now(&'a self) -> SystemTime {
         self.now
     }
+
+    fn user_group_cache(&'a self) -> &'a UserGroupCache {
+        &self.user_group_cache
+    }
+
+    fn mount_table(&'a self) -> &'a MountTable {
+        &self.mount_table
+    }
+
+    fn metadata(&'a self, path: &Path, follow_links: bool) -> io::Result<FileMetadata> {
+        metadata::read_metadata(path, follow_links)
+    }
+
+    fn entry_metadata(&'a self,
+                      entry: &WalkEntry,
+                      follow_links: bool)
+                      -> io::Result<FileMetadata> {
+        entry.stat(follow_links)
+    }
+
+    fn env_var(&'a self, _: &str) -> Option<OsString> {
+        None
+    }
 }
 
-pub fn path_to_testing_commandline() -> String {
+/// Returns the path to one of the binaries built alongside the tests.
+pub fn path_to_binary(name: &str) -> String {
 
     let mut path_to_use = env::current_exe()
         // this will be something along the lines of /my/homedir/findutils/target/debug/deps/findutils-5532804878869ef1
@@ -62,15 +120,19 @@ pub fn path_to_testing_commandline() -> String {
         .parent()
         .expect("can't find parent directory of this executable")
         .to_path_buf();
-    // and we want /my/homedir/findutils/target/debug/testing-commandline
+    // and we want /my/homedir/findutils/target/debug/<name>
     if path_to_use.ends_with("deps") {
         path_to_use.pop();
     }
-    path_to_use = path_to_use.join("testing-commandline");
+    path_to_use = path_to_use.join(name);
     path_to_use.to_string_lossy()
         .to_string()
 }
 
+pub fn path_to_testing_commandline() -> String {
+    path_to_binary("testing-commandline")
+}
+
 #[cfg(windows)]
 /// A copy of find::tests::fix_up_slashes.
 /// TODO: find out how to share #[cfg(test)] functions/structs between unit
@@ -84,14 +146,16 @@ pub fn fix_up_slashes(path: &str) -> String {
     path.to_string()
 }
 
-/// A copy of find::tests::FakeDependencies.
+/// A copy of find::matchers::tests::get_dir_entry_for.
 /// TODO: find out how to share #[cfg(test)] functions/structs between unit
 /// and integration tests.
-pub fn get_dir_entry_for(directory: &str, filename: &str) -> DirEntry {
-    for wrapped_dir_entry in WalkDir::new(fix_up_slashes(directory)) {
+pub fn get_dir_entry_for(directory: &str, filename: &str) -> WalkEntry {
+    let directory = fix_up_slashes(directory);
+    let starting_point: Rc<Path> = Rc::from(Path::new(&directory));
+    for wrapped_dir_entry in WalkDir::new(&directory) {
         let dir_entry = wrapped_dir_entry.unwrap();
         if dir_entry.file_name().to_string_lossy() == filename {
-            return dir_entry;
+            return WalkEntry::new(dir_entry, starting_point);
         }
     }
     panic!("Couldn't find {} in {}", directory, filename);
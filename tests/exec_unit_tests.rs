@@ -15,12 +15,13 @@ extern crate walkdir;
 
 
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
+use std::path::Path;
 use tempdir::TempDir;
-use walkdir::WalkDir;
 
 
+use findutils::exec::BatchLimits;
 use findutils::find::matchers::Matcher;
 use findutils::find::matchers::exec::*;
 use common::test_helpers::*;
@@ -80,11 +81,7 @@ fn execdir_in_current_directory() {
     let temp_dir = TempDir::new("execdir_in_current_directory").unwrap();
     let temp_dir_path = temp_dir.path().to_string_lossy();
 
-    let current_dir_entry = WalkDir::new(".")
-        .into_iter()
-        .next()
-        .expect("iterator was empty")
-        .expect("result wasn't OK");
+    let current_dir_entry = get_dir_entry_for(".", ".");
     let matcher = SingleExecMatcher::new(&path_to_testing_commandline(),
                                          &vec![temp_dir_path.as_ref(), "abc", "{}", "xyz"],
                                          true)
@@ -126,3 +123,146 @@ fn matching_fails_if_executable_fails() {
                                         /abbbc\nxyz\n",
                                        env::current_dir().unwrap().to_string_lossy())));
 }
+
+#[test]
+fn matching_fails_if_executable_cant_be_run() {
+    let temp_dir = TempDir::new("matching_fails_if_executable_cant_be_run").unwrap();
+    let missing = temp_dir.path().join("missing").to_string_lossy().to_string();
+
+    let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+    let matcher = SingleExecMatcher::new(&missing, &["{}"], false)
+        .expect("Failed to create matcher");
+    let deps = FakeDependencies::new();
+    let mut matcher_io = deps.new_matcher_io();
+    assert!(!matcher.matches(&abbbc, &mut matcher_io));
+    assert!(!matcher.matches(&abbbc, &mut matcher_io));
+    assert_eq!(matcher_io.exit_code(), 1);
+    let message = format!("find: ‘{}’: No such file or directory\n", missing);
+    assert_eq!(deps.get_error_output_as_string(), message.repeat(2));
+}
+
+#[cfg(unix)]
+#[test]
+fn matching_fails_if_executable_is_killed() {
+    let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+    let matcher = SingleExecMatcher::new("sh", &["-c", "kill -9 $$", "{}"], false)
+        .expect("Failed to create matcher");
+    let deps = FakeDependencies::new();
+    let mut matcher_io = deps.new_matcher_io();
+    assert!(!matcher.matches(&abbbc, &mut matcher_io));
+    assert_eq!(deps.get_error_output_as_string(),
+               "find: ‘sh’ terminated by signal 9\n");
+}
+
+/// Reads the arguments the testing command was run with, for each time it
+/// was run.
+fn read_invocations(dir: &Path) -> Vec<String> {
+    let mut invocations = vec![];
+    while let Ok(mut f) = File::open(dir.join(format!("{}.txt", invocations.len() + 1))) {
+        let mut s = String::new();
+        f.read_to_string(&mut s).expect("failed to read output file");
+        invocations.push(s.splitn(2, "args=\n").nth(1).unwrap().replace("\n", " "));
+    }
+    invocations
+}
+
+#[test]
+fn multi_exec_runs_batches() {
+    let temp_dir = TempDir::new("multi_exec_runs_batches").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let abbbc = get_dir_entry_for("test_data/simple", "abbbc");
+    let subdir = get_dir_entry_for("test_data/simple", "subdir");
+    let upper_abbbc = get_dir_entry_for("test_data/simple/subdir", "ABBBC");
+    let limits = BatchLimits { max_args: Some(2), ..BatchLimits::new(4096, 4096) };
+    let matcher = MultiExecMatcher::new_with_limits(&path_to_testing_commandline(),
+                                                    &[temp_dir_path.as_ref(), "abc"],
+                                                    false,
+                                                    limits)
+        .expect("Failed to create matcher");
+    let deps = FakeDependencies::new();
+    let mut matcher_io = deps.new_matcher_io();
+
+    // the command runs when a batch is full...
+    assert!(matcher.matches(&abbbc, &mut matcher_io));
+    assert!(matcher.matches(&subdir, &mut matcher_io));
+    assert_eq!(read_invocations(temp_dir.path()),
+               [fix_up_slashes("abc test_data/simple/abbbc test_data/simple/subdir ")]);
+    // ...and for what's left when find's finished
+    assert!(matcher.matches(&upper_abbbc, &mut matcher_io));
+    assert_eq!(read_invocations(temp_dir.path()).len(), 1);
+    matcher.finished(&mut matcher_io);
+    assert_eq!(read_invocations(temp_dir.path())[1],
+               fix_up_slashes("abc test_data/simple/subdir/ABBBC "));
+    assert_eq!(matcher_io.exit_code(), 0);
+}
+
+#[test]
+fn multi_execdir_batches_by_directory() {
+    let temp_dir = TempDir::new("multi_execdir_batches_by_directory").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let matcher = MultiExecMatcher::new(&path_to_testing_commandline(),
+                                        &[temp_dir_path.as_ref(), "--exit_with_failure"],
+                                        true)
+        .expect("Failed to create matcher");
+    let deps = FakeDependencies::new();
+    let mut matcher_io = deps.new_matcher_io();
+    for &(dir, name) in &[("test_data/simple", "abbbc"),
+                          ("test_data/simple", "subdir"),
+                          ("test_data/simple/subdir", "ABBBC")] {
+        // it always matches, even if the command fails
+        assert!(matcher.matches(&get_dir_entry_for(dir, name), &mut matcher_io));
+    }
+    matcher.finished(&mut matcher_io);
+
+    let current_dir = env::current_dir().unwrap().to_string_lossy().to_string();
+    let mut cwds = vec![];
+    for i in 1..3 {
+        let mut f = File::open(temp_dir.path().join(format!("{}.txt", i))).unwrap();
+        let mut s = String::new();
+        f.read_to_string(&mut s).expect("failed to read output file");
+        cwds.push(s.lines().next().unwrap().to_string());
+    }
+    assert_eq!(cwds,
+               [fix_up_slashes(&format!("cwd={}/test_data/simple", current_dir)),
+                fix_up_slashes(&format!("cwd={}/test_data/simple/subdir", current_dir))]);
+    assert_eq!(read_invocations(temp_dir.path()),
+               ["--exit_with_failure ./abbbc ./subdir ", "--exit_with_failure ./ABBBC "]);
+    // but the failures are reflected in find's exit code
+    assert_eq!(matcher_io.exit_code(), 1);
+}
+
+#[test]
+fn execdir_passes_names_that_look_like_options_safely() {
+    let temp_dir = TempDir::new("execdir_passes_names_that_look_like_options_safely").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let files_dir = TempDir::new("execdir_files").unwrap();
+    File::create(files_dir.path().join("-rf")).unwrap();
+
+    for &multi in &[false, true] {
+        let entry = get_dir_entry_for(&files_dir.path().to_string_lossy(), "-rf");
+        let deps = FakeDependencies::new();
+        let mut matcher_io = deps.new_matcher_io();
+        let args = [temp_dir_path.as_ref(), "{}"];
+        let matcher: Box<Matcher> = if multi {
+            Box::new(MultiExecMatcher::new(&path_to_testing_commandline(), &args[..1], true)
+                .unwrap())
+        } else {
+            Box::new(SingleExecMatcher::new(&path_to_testing_commandline(), &args, true).unwrap())
+        };
+        assert!(matcher.matches(&entry, &mut matcher_io));
+        matcher.finished(&mut matcher_io);
+
+        let output_file = temp_dir.path().join(if multi { "2.txt" } else { "1.txt" });
+        let mut f = File::open(output_file).expect("Failed to open output file");
+        let mut s = String::new();
+        f.read_to_string(&mut s).expect("failed to read output file");
+        // the name, never a path, with "./" in front so that it's not an option
+        assert_eq!(s,
+                   fix_up_slashes(&format!("cwd={}\nargs=\n./-rf\n",
+                                           fs::canonicalize(files_dir.path())
+                                               .unwrap()
+                                               .to_string_lossy())));
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Counts the heap allocations find makes for each entry it walks. This needs
+//! its own global allocator, so it has its own test binary (with only one
+//! test in it, so that nothing else allocates while it's counting).
+
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::File;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tempdir::TempDir;
+use walkdir::WalkDir;
+
+use findutils::find::find_main;
+use common::test_helpers::*;
+
+mod common;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Makes a directory of the given number of files.
+fn make_files(files: usize) -> TempDir {
+    let temp_dir = TempDir::new("find_alloc_tests").unwrap();
+    for i in 0..files {
+        File::create(temp_dir.path().join(format!("file{:04}", i))).unwrap();
+    }
+    temp_dir
+}
+
+/// How many allocations walking a directory of the given number of files
+/// makes for each of them: `measure` is called on directories of two
+/// different sizes, so the fixed costs (parsing the expression, setting up
+/// the walk) cancel out.
+fn allocations_per_entry<F>(measure: F) -> f64
+    where F: Fn(&str)
+{
+    let mut counts = vec![];
+    for &files in &[100, 300] {
+        let temp_dir = make_files(files);
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        let before = ALLOCATIONS.load(Ordering::SeqCst);
+        measure(&dir);
+        counts.push(ALLOCATIONS.load(Ordering::SeqCst) - before);
+    }
+    (counts[1] as f64 - counts[0] as f64) / 200.0
+}
+
+fn find_allocations_per_entry(expression: &[&str]) -> f64 {
+    allocations_per_entry(|dir| {
+        let mut args = vec!["find", dir];
+        args.extend_from_slice(expression);
+        let deps = FakeDependencies::new();
+        assert_eq!(find_main(&args, &deps), 0);
+    })
+}
+
+/// Evaluating the expression for an entry shouldn't allocate: the entries
+/// matchers see borrow the walker's paths, and printing one only appends to
+/// the output. So find should make no more allocations per entry than
+/// walkdir does itself (give or take the output buffer growing now and then).
+#[test]
+fn no_allocations_per_entry_beyond_the_walk() {
+    let walk = allocations_per_entry(|dir| {
+        assert!(WalkDir::new(dir).into_iter().all(|entry| entry.is_ok()));
+    });
+    let name_only = find_allocations_per_entry(&["-name", "nothing"]);
+    assert!(name_only <= walk,
+            "-name: {} allocations per entry, walking alone: {}",
+            name_only,
+            walk);
+    let print = find_allocations_per_entry(&["-name", "file*", "-print"]);
+    assert!(print <= walk + 0.1,
+            "-print: {} allocations per entry, walking alone: {}",
+            print,
+            walk);
+}
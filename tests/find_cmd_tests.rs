@@ -0,0 +1,480 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+/// ! This file contains tests that run the find executable itself, for
+/// ! behaviour that can't be checked by calling find_main (e.g. because it
+/// ! depends on the working directory).
+extern crate findutils;
+extern crate serde_json;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::env;
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+use serde_json::Value;
+use tempdir::TempDir;
+
+use common::fixtures::*;
+use common::test_helpers::*;
+
+mod common;
+
+/// Runs find with the given arguments in the given directory, returning its
+/// exit code, stdout and stderr.
+fn run_find(dir: &Path, args: &[&str]) -> (i32, String, String) {
+    let output = Command::new(path_to_binary("find"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("failed to run find");
+    (output.status.code().expect("find was killed by a signal"),
+     String::from_utf8(output.stdout).expect("find's output wasn't UTF-8"),
+     String::from_utf8(output.stderr).expect("find's errors weren't UTF-8"))
+}
+
+#[test]
+fn find_paths_starting_with_dash() {
+    let temp_dir = TempDir::new("find_paths_starting_with_dash").unwrap();
+    fs::create_dir(temp_dir.path().join("-name")).unwrap();
+    File::create(temp_dir.path().join("-name").join("foo")).unwrap();
+    fs::create_dir(temp_dir.path().join("-")).unwrap();
+    File::create(temp_dir.path().join("-").join("bar")).unwrap();
+
+    assert_eq!(run_find(temp_dir.path(), &["--", "-name", "-type", "f"]),
+               (0, fix_up_slashes("-name/foo\n"), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["-s", "./-name"]),
+               (0, fix_up_slashes("./-name\n./-name/foo\n"), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["-", "-type", "f"]),
+               (0, fix_up_slashes("-/bar\n"), String::new()));
+
+    // without "--", -name is the start of the expression
+    assert_eq!(run_find(temp_dir.path(), &["-name", "-name"]),
+               (0, fix_up_slashes("./-name\n"), String::new()));
+}
+
+#[test]
+fn find_paths_starting_with_operators() {
+    let temp_dir = TempDir::new("find_paths_starting_with_operators").unwrap();
+    for name in &["+data", "!weird", ",", ")"] {
+        fs::create_dir(temp_dir.path().join(name)).unwrap();
+    }
+
+    // only a whole "!" or "(" (or an option) ends the start points, as with
+    // GNU find: the operators that can't start an expression are paths too
+    assert_eq!(run_find(temp_dir.path(), &["+data", "!weird", ",", ")", "-maxdepth", "0"]),
+               (0, "+data\n!weird\n,\n)\n".to_string(), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["+data", "!", "-name", "x", "-prune"]),
+               (0, "+data\n".to_string(), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["!weird", "(", "-name", "!*", ")"]),
+               (0, "!weird\n".to_string(), String::new()));
+}
+
+#[test]
+fn find_nonexistent_start_point() {
+    let temp_dir = TempDir::new("find_nonexistent_start_point").unwrap();
+    make_file(temp_dir.path(), "exists", b"");
+
+    let (rc, stdout, stderr) = run_find(temp_dir.path(), &["does_not_exist", "exists"]);
+    assert_eq!(rc, 1);
+    assert_eq!(stdout, "exists\n");
+    assert!(stderr.starts_with("find: ‘does_not_exist’: "),
+            "unexpected error message: {}",
+            stderr);
+    #[cfg(unix)]
+    assert_eq!(stderr, "find: ‘does_not_exist’: No such file or directory\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn find_unreadable_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new("find_unreadable_dir").unwrap();
+    let locked = temp_dir.path().join("locked");
+    fs::create_dir(&locked).unwrap();
+    File::create(locked.join("hidden")).unwrap();
+    File::create(temp_dir.path().join("visible")).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // root can read anything, so there'd be no error
+    let readable = fs::read_dir(&locked).is_ok();
+
+    let (rc, stdout, stderr) = run_find(temp_dir.path(), &[".", "-sorted"]);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+    if readable {
+        assert_eq!((rc, stdout), (0, ".\n./locked\n./locked/hidden\n./visible\n".to_string()));
+    } else {
+        // everything else is still found, but the exit code reflects the error
+        assert_eq!((rc, stdout), (1, ".\n./locked\n./visible\n".to_string()));
+        assert_eq!(stderr, "find: ‘./locked’: Permission denied\n");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn find_unreadable_start_points() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new("find_unreadable_start_points").unwrap();
+    let file = make_file_with_mode(temp_dir.path(), "file", 0o000).unwrap();
+    let dir = temp_dir.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    make_file(&dir, "inside", b"");
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o000)).unwrap();
+    // root can read anything, so there'd be no errors
+    let readable = File::open(&file).is_ok() && fs::read_dir(&dir).is_ok();
+
+    let both = run_find(temp_dir.path(), &["file", "dir"]);
+    let just_file = run_find(temp_dir.path(), &["file", "-type", "f"]);
+    let pruned = run_find(temp_dir.path(), &["dir", "-prune"]);
+    let inside = run_find(temp_dir.path(), &["dir/inside"]);
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+    // a file that can't be read is found like any other
+    assert_eq!(just_file, (0, "file\n".to_string(), String::new()));
+    if readable {
+        assert_eq!(both, (0, "file\ndir\ndir/inside\n".to_string(), String::new()));
+        assert_eq!(inside, (0, "dir/inside\n".to_string(), String::new()));
+    } else {
+        // a directory that can't be read is found too, but what's in it
+        // isn't, and that's an error
+        assert_eq!(both,
+                   (1,
+                    "file\ndir\n".to_string(),
+                    "find: ‘dir’: Permission denied\n".to_string()));
+        // as is something that can't even be looked up
+        assert_eq!(inside,
+                   (1, String::new(), "find: ‘dir/inside’: Permission denied\n".to_string()));
+    }
+    // and there's nothing to complain about if it isn't read
+    assert_eq!(pruned, (0, "dir\n".to_string(), String::new()));
+}
+
+#[cfg(unix)]
+#[test]
+fn find_dangling_symlinks() {
+    let temp_dir = TempDir::new("find_dangling_symlinks").unwrap();
+    make_symlink(temp_dir.path(), "broken", "nowhere").unwrap();
+
+    // a link to nothing is just a link, whether or not links are followed
+    for args in &[&["broken"][..], &["-L", "broken"], &["-L", "broken", "-type", "l"]] {
+        assert_eq!(run_find(temp_dir.path(), args),
+                   (0, "broken\n".to_string(), String::new()),
+                   "{:?}",
+                   args);
+    }
+    assert_eq!(run_find(temp_dir.path(), &["-L", ".", "-type", "l"]),
+               (0, "./broken\n".to_string(), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["-L", ".", "-sorted", "-printf", "%p %y%Y\n"]),
+               (0, ". dd\n./broken lN\n".to_string(), String::new()));
+}
+
+#[test]
+fn find_parse_error() {
+    let (rc, stdout, stderr) = run_find(&env::current_dir().unwrap(), &[".", "-name"]);
+    assert_eq!((rc, stdout, stderr),
+               (1, String::new(), "find: missing argument to -name\n".to_string()));
+}
+
+#[test]
+fn find_exec_output_order() {
+    // find's output is buffered, but what it's printed for a file has to
+    // come before what a command run for the same file prints
+    let temp_dir = TempDir::new("find_exec_output_order").unwrap();
+    let (rc, stdout, stderr) = run_find(&env::current_dir().unwrap(),
+                                        &[&fix_up_slashes("test_data/simple"),
+                                          "-sorted",
+                                          "-print",
+                                          "-exec",
+                                          &path_to_testing_commandline(),
+                                          &temp_dir.path().to_string_lossy(),
+                                          "--echo=child",
+                                          ";"]);
+
+    assert_eq!(rc, 0, "{}", stderr);
+    assert_eq!(stdout,
+               fix_up_slashes("test_data/simple\nchild\n\
+                               test_data/simple/abbbc\nchild\n\
+                               test_data/simple/subdir\nchild\n\
+                               test_data/simple/subdir/ABBBC\nchild\n"));
+}
+
+/// Reads the paths the testing command was given each time it was run
+/// (after the given number of other arguments).
+fn read_batches(dir: &Path, skip: usize) -> Vec<Vec<String>> {
+    let mut batches = vec![];
+    while let Ok(s) = fs::read_to_string(dir.join(format!("{}.txt", batches.len() + 1))) {
+        batches.push(s.lines()
+            .skip_while(|line| *line != "args=")
+            .skip(1 + skip)
+            .map(|line| line.to_string())
+            .collect());
+    }
+    batches
+}
+
+#[test]
+fn find_exec_batch_output_order() {
+    // enough files with long enough names that they don't all fit on one
+    // command line
+    let temp_dir = TempDir::new("find_exec_batch_output_order").unwrap();
+    let files = temp_dir.path().join("files");
+    fs::create_dir(&files).unwrap();
+    for i in 0..2000 {
+        File::create(files.join(format!("{:04}{}", i, "x".repeat(100)))).unwrap();
+    }
+    let out_dir = temp_dir.path().join("out");
+    fs::create_dir(&out_dir).unwrap();
+    let out_dir = out_dir.to_string_lossy();
+
+    let (rc, stdout, stderr) = run_find(&files,
+                                        &[".",
+                                          "-type",
+                                          "f",
+                                          "-print",
+                                          "-exec",
+                                          &path_to_testing_commandline(),
+                                          &out_dir,
+                                          "--echo=batch",
+                                          "{}",
+                                          "+"]);
+    assert_eq!(rc, 0, "{}", stderr);
+
+    // every path is printed before the command is run on it (and so before
+    // whatever that run of the command prints)
+    let batches = read_batches(Path::new(out_dir.as_ref()), 1);
+    assert!(batches.len() > 1, "{}", batches.len());
+    let mut printed = vec![];
+    let mut batches_run = 0;
+    for line in stdout.lines() {
+        if line == "batch" {
+            for path in &batches[batches_run] {
+                assert!(printed.contains(&path.as_str()), "{}", path);
+            }
+            batches_run += 1;
+        } else {
+            printed.push(line);
+        }
+    }
+    assert_eq!(batches_run, batches.len());
+    // and it's run on every file, once
+    assert_eq!(printed.len(), 2000);
+    assert_eq!(batches.iter().map(|batch| batch.len()).sum::<usize>(), 2000);
+
+    // the last batch is run even if find stops early, and a failure counts
+    // towards the exit code
+    fs::remove_dir_all(out_dir.as_ref()).unwrap();
+    fs::create_dir(out_dir.as_ref()).unwrap();
+    let (rc, stdout, _) = run_find(&files,
+                                   &[".",
+                                     "-type",
+                                     "f",
+                                     "-print",
+                                     "-exec",
+                                     &path_to_testing_commandline(),
+                                     &out_dir,
+                                     "--exit_with_failure",
+                                     "{}",
+                                     "+",
+                                     "-quit"]);
+    assert_eq!(rc, 1);
+    assert_eq!(read_batches(Path::new(out_dir.as_ref()), 1),
+               [stdout.lines().map(|line| line.to_string()).collect::<Vec<_>>()]);
+    assert_eq!(stdout.lines().count(), 1);
+}
+
+#[test]
+fn find_exec_missing_command() {
+    let temp_dir = TempDir::new("find_exec_missing_command").unwrap();
+    File::create(temp_dir.path().join("a")).unwrap();
+    File::create(temp_dir.path().join("b")).unwrap();
+    let missing = temp_dir.path().join("missing").to_string_lossy().to_string();
+
+    // every file is still visited, but find's exit code shows the failures
+    let (rc, stdout, stderr) =
+        run_find(temp_dir.path(), &["-s", "-type", "f", "-exec", &missing, "{}", ";", "-o",
+                                    "-print"]);
+    assert_eq!(rc, 1);
+    assert_eq!(stdout, fix_up_slashes(".\n./a\n./b\n"));
+    assert_eq!(stderr,
+               format!("find: ‘{}’: No such file or directory\n", missing).repeat(2));
+}
+
+#[test]
+fn find_fprint_inside_search_tree() {
+    let temp_dir = TempDir::new("find_fprint_inside_search_tree").unwrap();
+    File::create(temp_dir.path().join("a")).unwrap();
+    fs::create_dir(temp_dir.path().join("sub")).unwrap();
+    File::create(temp_dir.path().join("sub").join("b")).unwrap();
+    let output = || fs::read_to_string(temp_dir.path().join("out.txt")).unwrap();
+
+    // the output file is found (exactly once) like any other file
+    assert_eq!(run_find(temp_dir.path(), &[".", "-sorted", "-fprint", "out.txt"]),
+               (0, String::new(), String::new()));
+    assert_eq!(output(), fix_up_slashes(".
+./a
+./out.txt
+./sub
+./sub/b
+"));
+
+    // and what's been written to it is flushed before it's looked at
+    assert_eq!(run_find(temp_dir.path(),
+                        &[".", "-sorted", "-type", "f", "-fprintf", "out.txt", "%f %s\\n"]),
+               (0, String::new(), String::new()));
+    assert_eq!(output(), "a 0\nout.txt 4\nb 0\n");
+}
+
+/// Makes a symbolic link to a directory, returning false if we're not allowed
+/// to (it needs a privilege that most accounts only have in developer mode).
+#[cfg(windows)]
+fn make_symlink_dir(target: &Path, link: &Path) -> bool {
+    // ERROR_PRIVILEGE_NOT_HELD
+    const PRIVILEGE_NOT_HELD: i32 = 1314;
+    match std::os::windows::fs::symlink_dir(target, link) {
+        Ok(()) => true,
+        Err(ref e) if e.raw_os_error() == Some(PRIVILEGE_NOT_HELD) => false,
+        Err(e) => panic!("failed to create {}: {}", link.to_string_lossy(), e),
+    }
+}
+
+/// Makes a junction, returning false if that fails (it doesn't need any
+/// privileges, but it may not be supported by the filesystem).
+#[cfg(windows)]
+fn make_junction(target: &Path, link: &Path) -> bool {
+    Command::new("cmd")
+        .args(&["/C", "mklink", "/J"])
+        .arg(link)
+        .arg(target)
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+#[test]
+fn find_windows_symlinks() {
+    let temp_dir = TempDir::new("find_windows_symlinks").unwrap();
+    let dir = temp_dir.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    File::create(dir.join("file")).unwrap();
+    if !make_symlink_dir(&dir, &temp_dir.path().join("link")) {
+        println!("skipping: not allowed to create symbolic links");
+        return;
+    }
+
+    // a link to a directory is a link, not a directory, and isn't searched
+    assert_eq!(run_find(temp_dir.path(), &["-s", ".", "-type", "l"]),
+               (0, fix_up_slashes("./link\n"), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["-s", ".", "-type", "d"]),
+               (0, fix_up_slashes(".\n./dir\n"), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["-s", ".", "-name", "file"]),
+               (0, fix_up_slashes("./dir/file\n"), String::new()));
+    // unless it's followed
+    assert_eq!(run_find(temp_dir.path(), &["-L", "-s", ".", "-name", "file"]),
+               (0, fix_up_slashes("./dir/file\n./link/file\n"), String::new()));
+}
+
+#[cfg(windows)]
+#[test]
+fn find_windows_junctions() {
+    let temp_dir = TempDir::new("find_windows_junctions").unwrap();
+    let dir = temp_dir.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    File::create(dir.join("file")).unwrap();
+    if !make_junction(&dir, &temp_dir.path().join("junction")) ||
+       !make_junction(&dir, &dir.join("cycle")) {
+        println!("skipping: couldn't create junctions");
+        return;
+    }
+
+    // junctions count as links, so by default the cycle isn't followed
+    assert_eq!(run_find(temp_dir.path(), &["-s", ".", "-type", "l"]),
+               (0, fix_up_slashes("./dir/cycle\n./junction\n"), String::new()));
+    assert_eq!(run_find(temp_dir.path(), &["-s", ".", "-name", "file"]),
+               (0, fix_up_slashes("./dir/file\n"), String::new()));
+    // and when links are followed, the cycle is reported rather than looped
+    // around forever
+    let (rc, stdout, stderr) = run_find(temp_dir.path(), &["-L", "-s", ".", "-name", "file"]);
+    assert_eq!((rc, stdout),
+               (1, fix_up_slashes("./dir/file\n./junction/file\n")));
+    assert!(stderr.starts_with(&format!("find: ‘{}’: ", fix_up_slashes("./dir/cycle"))),
+            "unexpected error message: {}",
+            stderr);
+}
+
+/// Runs find with -printj, and parses each line of what it writes.
+fn run_find_json(dir: &Path, args: &[&str]) -> Vec<Value> {
+    let mut all_args = args.to_vec();
+    all_args.push("-printj");
+    let (rc, stdout, stderr) = run_find(dir, &all_args);
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    stdout.lines()
+        .map(|line| serde_json::from_str(line).expect("-printj's output wasn't JSON"))
+        .collect()
+}
+
+#[test]
+fn find_printj() {
+    let objects = run_find_json(Path::new("."), &["-s", "test_data/simple"]);
+    let paths: Vec<_> = objects.iter().map(|object| object["path"].as_str().unwrap()).collect();
+    assert_eq!(paths,
+               vec![fix_up_slashes("test_data/simple"),
+                    fix_up_slashes("test_data/simple/abbbc"),
+                    fix_up_slashes("test_data/simple/subdir"),
+                    fix_up_slashes("test_data/simple/subdir/ABBBC")]);
+    let depths: Vec<_> = objects.iter().map(|object| object["depth"].as_u64().unwrap()).collect();
+    assert_eq!(depths, vec![0, 1, 1, 2]);
+    let types: Vec<_> =
+        objects.iter().map(|object| object["file_type"].as_str().unwrap()).collect();
+    assert_eq!(types, vec!["d", "f", "d", "f"]);
+
+    for object in &objects {
+        let path = object["path"].as_str().unwrap();
+        let metadata = fs::symlink_metadata(path).unwrap();
+        assert_eq!(object["size"].as_u64(), Some(metadata.len()), "{}", path);
+        let mtime = metadata.modified().unwrap().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(object["mtime"].as_u64(), Some(mtime), "{}", path);
+        assert!(object.get("path_bytes").is_none());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(object["mode"].as_u64(), Some(u64::from(metadata.mode() & 0o7777)));
+            assert_eq!(object["uid"].as_u64(), Some(u64::from(metadata.uid())));
+            assert_eq!(object["gid"].as_u64(), Some(u64::from(metadata.gid())));
+        }
+    }
+
+    // it's happy to share the output with other actions
+    let (rc, stdout, _) = run_find(Path::new("."),
+                                   &["test_data/simple/abbbc", "-print", "-printj"]);
+    assert_eq!(rc, 0);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines[0], fix_up_slashes("test_data/simple/abbbc"));
+    assert!(serde_json::from_str::<Value>(lines[1]).is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn find_printj_awkward_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = TempDir::new("find_printj_awkward_names").unwrap();
+    File::create(temp_dir.path().join("new\nline \"quoted\"")).unwrap();
+    File::create(temp_dir.path().join(OsStr::from_bytes(b"not \xff utf-8"))).unwrap();
+
+    let objects = run_find_json(temp_dir.path(), &["-s", ".", "-type", "f"]);
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects[0]["path"], "./new\nline \"quoted\"");
+    assert!(objects[0].get("path_bytes").is_none());
+    assert_eq!(objects[1]["path"], "./not \u{fffd} utf-8");
+    // base64 of the exact bytes
+    assert_eq!(objects[1]["path_bytes"], "Li9ub3Qg/yB1dGYtOA==");
+}
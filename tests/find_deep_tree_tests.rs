@@ -0,0 +1,139 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Walks a directory tree that's deeper than a path can be long, checking
+//! that find gets to the bottom of it without keeping a file descriptor open
+//! for every directory above the one it's in, and that updatedb (which uses
+//! the same walk) gets there too. This changes the working
+//! directory (to make the tree without paths getting too long), so it has
+//! its own test binary, with only one test in it.
+
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tempdir::TempDir;
+
+use findutils::find::Config;
+use findutils::find::find_main_with_registry;
+use findutils::find::matchers::{ArgIter, Matcher, MatcherIO, MatcherRegistry, WalkEntry};
+use findutils::updatedb::frcode::Decoder;
+use findutils::updatedb::updatedb_main;
+use common::test_helpers::*;
+
+mod common;
+
+/// How deep the tree is, unless FIND_DEEP_TREE_DEPTH says otherwise. The
+/// leaf's path ("./d/d/...") is more than twice as long as PATH_MAX (4096
+/// on Linux), so it can only be reached by opening each directory relative
+/// to the one it's in.
+const DEFAULT_DEPTH: usize = 5000;
+
+/// The most file descriptors that have been open at once while find was
+/// walking the tree.
+static MAX_OPEN_FDS: AtomicUsize = AtomicUsize::new(0);
+
+/// How many file descriptors are open, if there's a way of telling.
+fn open_fds() -> Option<usize> {
+    fs::read_dir("/proc/self/fd").ok().map(|entries| entries.count())
+}
+
+/// Records how many file descriptors are open at each entry, for
+/// -count_fds. Always matches.
+struct FdCounter;
+
+impl Matcher for FdCounter {
+    fn matches(&self, _: &WalkEntry, _: &mut MatcherIO) -> bool {
+        if let Some(fds) = open_fds() {
+            MAX_OPEN_FDS.fetch_max(fds, Ordering::SeqCst);
+        }
+        true
+    }
+
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "count_fds")
+    }
+}
+
+fn build_fd_counter(_: &mut ArgIter, _: &mut Config) -> Result<Box<Matcher>, Box<Error>> {
+    Ok(Box::new(FdCounter))
+}
+
+#[test]
+fn find_deep_tree() {
+    let depth = env::var("FIND_DEEP_TREE_DEPTH")
+        .ok()
+        .map_or(DEFAULT_DEPTH,
+                |depth| depth.parse().expect("FIND_DEEP_TREE_DEPTH should be a number"));
+    let temp_dir = TempDir::new("find_deep_tree").unwrap();
+    env::set_current_dir(temp_dir.path()).unwrap();
+    for _ in 0..depth {
+        fs::create_dir("d").unwrap();
+        env::set_current_dir("d").unwrap();
+    }
+    File::create("leaf").unwrap();
+    env::set_current_dir(temp_dir.path()).unwrap();
+
+    let mut registry = MatcherRegistry::with_builtins();
+    registry.register("-count_fds", build_fd_counter);
+    // on the way down, and (with -depth) on the way back up
+    for depth_first in &[false, true] {
+        let fds_before = open_fds();
+        MAX_OPEN_FDS.store(0, Ordering::SeqCst);
+        let deps = FakeDependencies::new();
+        let mut args = vec!["find", "."];
+        if *depth_first {
+            args.push("-depth");
+        }
+        args.extend_from_slice(&["-count_fds", "-name", "leaf", "-print"]);
+        let rc = find_main_with_registry(&args, &registry, &deps);
+
+        assert_eq!(deps.get_error_output_as_string(), "");
+        assert_eq!(rc, 0);
+        let expected = fix_up_slashes(&format!(".{}/leaf\n", "/d".repeat(depth)));
+        assert!(deps.get_output_as_string() == expected,
+                "expected the leaf {} directories down",
+                depth);
+        // a few directories are kept open at a time, however deep the tree
+        // is (this can only be checked where /proc/self/fd says what's open)
+        if let Some(fds_before) = fds_before {
+            let max_open_fds = MAX_OPEN_FDS.load(Ordering::SeqCst);
+            assert!(max_open_fds <= fds_before + 16,
+                    "{} file descriptors open at once, from {}",
+                    max_open_fds,
+                    fds_before);
+        }
+    }
+
+    // and the database has everything in it, whether it's made from scratch
+    // or (with --incremental) from the one before
+    // (somewhere else, so that it isn't in itself)
+    let db_dir = TempDir::new("find_deep_tree_db").unwrap();
+    let db = db_dir.path().join("db");
+    let output = format!("--output={}", db.to_string_lossy());
+    for incremental in &[false, true] {
+        let mut args = vec!["updatedb", &output, "--localpaths=.", "--prunefs="];
+        if *incremental {
+            args.push("--incremental");
+        }
+        assert_eq!(updatedb_main(&args), 0);
+        let paths: Vec<Vec<u8>> = Decoder::new(BufReader::new(File::open(&db).unwrap()))
+            .unwrap()
+            .map(|path| path.unwrap())
+            .collect();
+        assert_eq!(paths.len(), depth + 2);
+        let leaf = format!(".{}/leaf", "/d".repeat(depth));
+        assert!(paths.last() == Some(&leaf.into_bytes()),
+                "expected the leaf {} directories down in the database",
+                depth);
+    }
+}
@@ -18,9 +18,12 @@ extern crate walkdir;
 use std::env;
 use std::fs::File;
 use std::io::Read;
+#[cfg(windows)]
+use std::path::Path;
 use tempdir::TempDir;
 
 use findutils::find::find_main;
+use common::fixtures::*;
 use common::test_helpers::*;
 
 mod common;
@@ -91,3 +94,279 @@ fn find_execdir() {
                                        env::current_dir().unwrap().to_string_lossy())));
 
 }
+
+#[test]
+fn find_exec_batched() {
+    let temp_dir = TempDir::new("find_exec_batched").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let deps = FakeDependencies::new();
+
+    // "+" only ends the command straight after "{}"
+    let rc = find_main(&["find",
+                         &fix_up_slashes("./test_data/simple"),
+                         "-sorted",
+                         "-type",
+                         "f",
+                         "-exec",
+                         &path_to_testing_commandline(),
+                         temp_dir_path.as_ref(),
+                         "+",
+                         "{}",
+                         "+"],
+                       &deps);
+    assert_eq!(rc, 0);
+    assert_eq!(deps.get_output_as_string(), "");
+
+    let mut f = File::open(temp_dir.path().join("1.txt")).expect("Failed to open output file");
+    let mut s = String::new();
+    f.read_to_string(&mut s).expect("failed to read output file");
+    assert_eq!(s,
+               fix_up_slashes(&format!("cwd={}
+args=
++
+./test_data/simple/abbbc
+\
+                                        ./test_data/simple/subdir/ABBBC
+",
+                                       env::current_dir().unwrap().to_string_lossy())));
+    assert!(File::open(temp_dir.path().join("2.txt")).is_err());
+
+    // and there can only be one "{}"
+    let deps = FakeDependencies::new();
+    let rc = find_main(&["find", ".", "-exec", "echo", "{}", "{}", "+"], &deps);
+    assert_eq!(rc, 1);
+    assert_eq!(deps.get_error_output_as_string(),
+               "find: Only one instance of {} is supported with -exec ... +\n");
+}
+
+/// -type tells FIFOs and sockets apart from files, so commands are only run
+/// on what's asked for.
+#[test]
+fn find_exec_special_files() {
+    let files = TempDir::new("find_exec_special_files").unwrap();
+    let fifo = match make_fifo(files.path(), "fifo") {
+        Some(fifo) => fifo,
+        None => return,
+    };
+    let socket = match make_socket(files.path(), "socket") {
+        Some(socket) => socket,
+        None => return,
+    };
+    make_file(files.path(), "file", b"");
+    let temp_dir = TempDir::new("find_exec_special_files_output").unwrap();
+    let deps = FakeDependencies::new();
+
+    let rc = find_main(&["find",
+                         files.path().to_str().unwrap(),
+                         "-sorted",
+                         "(",
+                         "-type",
+                         "p",
+                         "-o",
+                         "-type",
+                         "s",
+                         ")",
+                         "-exec",
+                         &path_to_testing_commandline(),
+                         temp_dir.path().to_str().unwrap(),
+                         "{}",
+                         "+"],
+                       &deps);
+    assert_eq!(rc, 0);
+
+    let mut s = String::new();
+    File::open(temp_dir.path().join("1.txt")).unwrap().read_to_string(&mut s).unwrap();
+    assert_eq!(s,
+               format!("cwd={}\nargs=\n{}\n{}\n",
+                       env::current_dir().unwrap().to_string_lossy(),
+                       fifo.to_string_lossy(),
+                       socket.to_string_lossy()));
+}
+
+/// -execdir is given the last component of each path (with "./" in front),
+/// and run in the directory before it, whatever the start point looks like.
+#[cfg(unix)]
+#[test]
+fn find_execdir_start_points() {
+    use std::fs;
+
+    for &(start_point, cwd, name) in &[("test_data", ".", "./test_data"),
+                                       ("test_data/simple/subdir/..",
+                                        "test_data/simple/subdir",
+                                        "./.."),
+                                       ("test_data/simple/", "test_data", "./simple"),
+                                       ("/", "/", "./.")] {
+        let temp_dir = TempDir::new("find_execdir_start_points").unwrap();
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             start_point,
+                             "-maxdepth",
+                             "0",
+                             "-execdir",
+                             &path_to_testing_commandline(),
+                             temp_dir.path().to_str().unwrap(),
+                             "{}",
+                             ";"],
+                           &deps);
+        assert_eq!(rc, 0, "{}", start_point);
+        let s = fs::read_to_string(temp_dir.path().join("1.txt")).unwrap();
+        let cwd = fs::canonicalize(cwd).unwrap();
+        assert_eq!(s,
+                   format!("cwd={}\nargs=\n{}\n", cwd.to_string_lossy(), name),
+                   "{}",
+                   start_point);
+    }
+}
+
+/// Runs -ok over the two files in test_data/simple with the given responses,
+/// returning the paths the command was run on.
+fn run_find_ok(test_name: &str, responses: &str) -> Vec<String> {
+    let temp_dir = TempDir::new(test_name).unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let mut deps = FakeDependencies::new();
+    deps.set_input(responses);
+
+    let rc = find_main(&["find",
+                         &fix_up_slashes("./test_data/simple"),
+                         "-sorted",
+                         "-type",
+                         "f",
+                         "-ok",
+                         &path_to_testing_commandline(),
+                         temp_dir_path.as_ref(),
+                         "{}",
+                         ";",
+                         "-print"],
+                       &deps);
+    assert_eq!(rc, 0);
+
+    let mut paths = vec![];
+    for i in 1..3 {
+        if let Ok(mut f) = File::open(temp_dir.path().join(format!("{}.txt", i))) {
+            let mut s = String::new();
+            f.read_to_string(&mut s).expect("failed to read output file");
+            paths.push(s.lines().last().unwrap().to_string());
+        }
+    }
+    // -print only happens if the user said yes
+    assert_eq!(deps.get_output_as_string(),
+               paths.iter().map(|p| format!("{}\n", p)).collect::<String>());
+    paths
+}
+
+#[test]
+fn find_ok() {
+    let abbbc = fix_up_slashes("./test_data/simple/abbbc");
+    let upper_abbbc = fix_up_slashes("./test_data/simple/subdir/ABBBC");
+
+    assert_eq!(run_find_ok("find_ok_yes", "y\nyes\n"),
+               vec![abbbc.clone(), upper_abbbc.clone()]);
+    assert_eq!(run_find_ok("find_ok_no", "n\nNo\n"), Vec::<String>::new());
+    assert_eq!(run_find_ok("find_ok_mixed", "Y\nwhatever\n"), vec![abbbc.clone()]);
+    assert_eq!(run_find_ok("find_ok_mixed2", "\ny\n"), vec![upper_abbbc.clone()]);
+    // running out of input counts as no
+    assert_eq!(run_find_ok("find_ok_eof", "y"), vec![abbbc.clone()]);
+    assert_eq!(run_find_ok("find_ok_empty", ""), Vec::<String>::new());
+}
+
+#[cfg(windows)]
+#[test]
+fn find_exec_quotes_arguments() {
+    let temp_dir = TempDir::new("find_exec_quotes_arguments").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let deps = FakeDependencies::new();
+
+    let rc = find_main(&["find",
+                         &fix_up_slashes("./test_data/simple/subdir"),
+                         "-type",
+                         "f",
+                         "-exec",
+                         &path_to_testing_commandline(),
+                         temp_dir_path.as_ref(),
+                         r#"a"b"#,
+                         r"c\",
+                         r"d e\",
+                         r#"f\"g"#,
+                         ";"],
+                       &deps);
+    assert_eq!(rc, 0);
+
+    let mut f = File::open(temp_dir.path().join("1.txt")).expect("Failed to open output file");
+    let mut s = String::new();
+    f.read_to_string(&mut s).expect("failed to read output file");
+    assert_eq!(s,
+               format!("cwd={}\nargs=\na\"b\nc\\\nd e\\\nf\\\"g\n",
+                       env::current_dir().unwrap().to_string_lossy()));
+}
+
+/// Writes a batch file that writes its arguments to out.txt next to it,
+/// returning the batch file's path without its extension.
+#[cfg(windows)]
+fn write_echo_args(dir: &Path) -> String {
+    make_file(dir, "echo_args.cmd", b"@echo off\r\necho %*> \"%~dp0out.txt\"\r\n");
+    dir.join("echo_args").to_string_lossy().into_owned()
+}
+
+#[cfg(windows)]
+fn read_echoed_args(dir: &Path) -> Option<String> {
+    let mut s = String::new();
+    File::open(dir.join("out.txt")).ok()?.read_to_string(&mut s).unwrap();
+    Some(s.trim_end().to_string())
+}
+
+#[cfg(windows)]
+#[test]
+fn find_exec_resolves_pathext() {
+    let temp_dir = TempDir::new("find_exec_resolves_pathext").unwrap();
+    let echo_args = write_echo_args(temp_dir.path());
+    let deps = FakeDependencies::new();
+
+    let rc = find_main(&["find",
+                         &fix_up_slashes("./test_data/simple/subdir"),
+                         "-type",
+                         "f",
+                         "-exec",
+                         &echo_args,
+                         "literal",
+                         ";"],
+                       &deps);
+    assert_eq!(rc, 0);
+    assert_eq!(read_echoed_args(temp_dir.path()), Some("literal".to_string()));
+}
+
+#[cfg(windows)]
+#[test]
+fn find_exec_refuses_file_names_for_batch_files() {
+    let temp_dir = TempDir::new("find_exec_refuses_file_names_for_batch_files").unwrap();
+    let echo_args = write_echo_args(temp_dir.path());
+    for executable in &[echo_args.clone(), format!("{}.cmd", echo_args)] {
+        let deps = FakeDependencies::new();
+        let rc = find_main(&["find",
+                             &fix_up_slashes("./test_data/simple/subdir"),
+                             "-type",
+                             "f",
+                             "-exec",
+                             executable,
+                             "{}",
+                             ";"],
+                           &deps);
+        assert_eq!(rc, 1);
+        assert_eq!(read_echoed_args(temp_dir.path()), None);
+    }
+
+    // unless the user says it's all right
+    let deps = FakeDependencies::new();
+    let rc = find_main(&["find",
+                         "--allow-batch-exec",
+                         &fix_up_slashes("./test_data/simple/subdir"),
+                         "-type",
+                         "f",
+                         "-exec",
+                         &echo_args,
+                         "{}",
+                         ";"],
+                       &deps);
+    assert_eq!(rc, 0);
+    assert_eq!(read_echoed_args(temp_dir.path()),
+               Some(fix_up_slashes("./test_data/simple/subdir/ABBBC")));
+}
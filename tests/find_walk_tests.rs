@@ -0,0 +1,73 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Checks how -prune and -quit interact with the depth limits and with each
+//! other, over whole runs of find: whatever's asked for one entry mustn't
+//! affect how the next one's handled (other than -quit, which stops
+//! everything).
+
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use findutils::find::find_main;
+use common::test_helpers::*;
+
+mod common;
+
+/// Runs find on the given start point, sorted, returning its exit code and
+/// the paths it printed (without the start point's parent).
+fn run_find(start_point: &str, args: &[&str]) -> (i32, Vec<String>) {
+    let deps = FakeDependencies::new();
+    let start_point = fix_up_slashes(&format!("./test_data/{}", start_point));
+    let mut find_args = vec!["find", &start_point, "-sorted"];
+    find_args.extend(args);
+    let rc = find_main(&find_args, &deps);
+    assert_eq!(deps.get_error_output_as_string(), "", "{:?}", args);
+    let prefix = fix_up_slashes("./test_data/");
+    let printed = deps.get_output_as_string()
+        .lines()
+        .map(|line| line.replacen(&prefix, "", 1))
+        .collect();
+    (rc, printed)
+}
+
+#[test]
+fn find_walk_control() {
+    // (start point, arguments, what's printed), all the same as GNU find
+    let cases: &[(&str, &[&str], &[&str])] =
+        &[// pruning at exactly -maxdepth makes no difference (there's nothing
+          // below it to skip), and in particular, doesn't skip its siblings
+          ("depth", &["-maxdepth", "1", "-name", "1", "-prune", "-o", "-print"],
+           &["depth", "depth/f0"]),
+          ("depth", &["-maxdepth", "2", "-name", "2", "-prune", "-o", "-print"],
+           &["depth", "depth/1", "depth/1/f1", "depth/f0"]),
+          ("depth", &["-maxdepth", "2", "-name", "2", "-prune", "-print"], &["depth/1/2"]),
+          // an entry -mindepth leaves out isn't evaluated, so it can't prune
+          ("depth", &["-mindepth", "1", "-name", "depth", "-prune", "-o", "-print"],
+           &["depth/1", "depth/1/2", "depth/1/2/3", "depth/1/2/3/f3", "depth/1/2/f2",
+             "depth/1/f1", "depth/f0"]),
+          // but the first one it lets through can quit
+          ("depth", &["-mindepth", "2", "-print", "-quit"], &["depth/1/2"]),
+          ("depth", &["-mindepth", "3", "-maxdepth", "3", "-print", "-quit"],
+           &["depth/1/2/3"]),
+          // pruning a file is a no-op, for it and for what comes after it
+          ("simple", &["-name", "abbbc", "-prune", "-o", "-print"],
+           &["simple", "simple/subdir", "simple/subdir/ABBBC"]),
+          ("depth", &["-type", "f", "-prune", "-o", "-name", "2", "-prune", "-o", "-print"],
+           &["depth", "depth/1"]),
+          // quitting at the sibling of something that's been pruned
+          ("depth", &["-name", "2", "-prune", "-o", "-name", "f1", "-print", "-quit"],
+           &["depth/1/f1"]),
+          // and with -depth, pruning comes too late to skip anything
+          ("depth", &["-depth", "-name", "2", "-prune", "-o", "-print"],
+           &["depth/1/2/3/f3", "depth/1/2/3", "depth/1/2/f2", "depth/1/f1", "depth/1",
+             "depth/f0", "depth"])];
+    for &(start_point, args, expected) in cases {
+        let expected: Vec<String> = expected.iter().map(|path| fix_up_slashes(path)).collect();
+        assert_eq!(run_find(start_point, args), (0, expected), "{:?}", args);
+    }
+}
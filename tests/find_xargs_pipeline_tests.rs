@@ -0,0 +1,190 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Tests that run "find ... -print0 | xargs -0 ..." for real: both binaries
+//! as separate processes, connected by a pipe, with testing-commandline at
+//! the end of it. The unit tests cover each half on its own, but only this
+//! shows that a file name gets through the whole thing unchanged, however
+//! awkward it is, and that neither end falls over when the other goes away.
+
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use tempdir::TempDir;
+
+use common::test_helpers::*;
+
+mod common;
+
+/// Starts find in the given directory with the given arguments, with its
+/// stdout going into a pipe.
+fn spawn_find(dir: &Path, args: &[&str]) -> Child {
+    Command::new(path_to_binary("find"))
+        .args(args)
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run find")
+}
+
+/// Runs "find <find_args> | xargs <xargs_args>", and returns how each of
+/// them exited and what each wrote to stderr.
+fn run_pipeline(dir: &Path,
+                find_args: &[&str],
+                xargs_args: &[&str])
+                -> ((ExitStatus, String), (ExitStatus, String)) {
+    let mut find = spawn_find(dir, find_args);
+    let xargs = Command::new(path_to_binary("xargs"))
+        .args(xargs_args)
+        .current_dir(dir)
+        .stdin(find.stdout.take().unwrap())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run xargs");
+    let xargs_output = xargs.wait_with_output().expect("failed to wait for xargs");
+    let find_output = find.wait_with_output().expect("failed to wait for find");
+    ((find_output.status, String::from_utf8_lossy(&find_output.stderr).into_owned()),
+     (xargs_output.status, String::from_utf8_lossy(&xargs_output.stderr).into_owned()))
+}
+
+/// Returns every argument that testing-commandline (run with --quote_args)
+/// was given, over all the invocations that wrote to the given directory,
+/// quoted as it wrote them.
+fn all_quoted_args(dir: &Path) -> Vec<String> {
+    let mut args = vec![];
+    for entry in fs::read_dir(dir).unwrap() {
+        let mut s = String::new();
+        File::open(entry.unwrap().path())
+            .expect("failed to open output file")
+            .read_to_string(&mut s)
+            .expect("failed to read output file");
+        args.extend(s.lines()
+            .skip_while(|line| *line != "args=")
+            .skip(1)
+            .filter(|line| *line != "\"--quote_args\"")
+            .map(|line| line.to_string()));
+    }
+    args
+}
+
+/// The awkward names the tree is made of, as (directory, file name) pairs.
+fn awkward_names() -> Vec<(&'static str, OsString)> {
+    let mut names: Vec<_> = ["plain",
+                             "with space",
+                             " leading and trailing ",
+                             "new\nline",
+                             "tab\there",
+                             "single'quote",
+                             "double\"quote",
+                             "back\\slash",
+                             "-looks-like-an-option",
+                             "{}",
+                             "ünïcödé"]
+        .iter()
+        .flat_map(|name| vec![("", OsString::from(name)), ("sub dir", OsString::from(name))])
+        .collect();
+    if cfg!(unix) {
+        names.push(("", invalid_utf8_name()));
+        names.push(("sub dir", invalid_utf8_name()));
+    }
+    names
+}
+
+#[cfg(unix)]
+fn invalid_utf8_name() -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(b"not \xff utf-8 \xc3".to_vec())
+}
+
+#[cfg(not(unix))]
+fn invalid_utf8_name() -> OsString {
+    unreachable!()
+}
+
+#[test]
+fn every_file_reaches_the_command_once() {
+    let temp_dir = TempDir::new("find_xargs_pipeline").unwrap();
+    let tree = temp_dir.path().join("tree");
+    let out = temp_dir.path().join("out");
+    fs::create_dir_all(tree.join("sub dir")).unwrap();
+    fs::create_dir(&out).unwrap();
+    let mut expected = vec![];
+    for (dir, name) in awkward_names() {
+        let path = Path::new(".").join(dir).join(&name);
+        File::create(tree.join(&path)).unwrap();
+        expected.push(format!("{:?}", path.as_os_str()));
+    }
+    expected.sort();
+
+    let testing_commandline = path_to_testing_commandline();
+    let out = out.to_string_lossy().into_owned();
+    // a few names per invocation, so that how they're split up between
+    // invocations is tested too
+    let ((find_status, find_errors), (xargs_status, xargs_errors)) =
+        run_pipeline(&tree,
+                     &[".", "-type", "f", "-print0"],
+                     &["-0", "-n", "3", &testing_commandline, &out, "--quote_args"]);
+    assert_eq!((find_status.code(), find_errors.as_ref()), (Some(0), ""));
+    assert_eq!((xargs_status.code(), xargs_errors.as_ref()), (Some(0), ""));
+
+    let mut received = all_quoted_args(Path::new(&out));
+    received.sort();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn find_copes_with_xargs_stopping_early() {
+    let temp_dir = TempDir::new("find_xargs_pipeline").unwrap();
+    let tree = temp_dir.path().join("tree");
+    let out = temp_dir.path().join("out");
+    fs::create_dir(&tree).unwrap();
+    fs::create_dir(&out).unwrap();
+    // much more than a pipe holds, so that find's still writing when xargs
+    // goes away
+    let padding = "x".repeat(100);
+    for i in 0..2000 {
+        File::create(tree.join(format!("{}{:04}", padding, i))).unwrap();
+    }
+
+    // a command that exits with 255 makes xargs stop at once
+    let testing_commandline = path_to_testing_commandline();
+    let out = out.to_string_lossy().into_owned();
+    let ((find_status, find_errors), (xargs_status, xargs_errors)) =
+        run_pipeline(&tree,
+                     &[".", "-print0"],
+                     &["-0", "-n", "1", &testing_commandline, &out, "--exit_with_status=255"]);
+    assert_eq!(xargs_status.code(), Some(124));
+    assert!(!xargs_errors.contains("panicked"), "{}", xargs_errors);
+    assert_eq!(fs::read_dir(&out).unwrap().count(), 1);
+    // find just stops: the reader going away isn't worth complaining about
+    assert_eq!((find_status.code(), find_errors.as_ref()), (Some(0), ""));
+}
+
+#[test]
+fn find_copes_with_its_reader_closing_the_pipe() {
+    let temp_dir = TempDir::new("find_xargs_pipeline").unwrap();
+    let padding = "x".repeat(100);
+    for i in 0..2000 {
+        File::create(temp_dir.path().join(format!("{}{:04}", padding, i))).unwrap();
+    }
+
+    for args in &[&[".", "-print0"][..], &[".", "-print"], &[".", "-printf", "%p\\0"]] {
+        // like "find ... | head -c 10"
+        let mut find = spawn_find(temp_dir.path(), args);
+        let mut start = [0; 10];
+        find.stdout.take().unwrap().read_exact(&mut start).unwrap();
+        let output = find.wait_with_output().expect("failed to wait for find");
+        let errors = String::from_utf8_lossy(&output.stderr);
+        assert_eq!((output.status.code(), errors.as_ref()), (Some(0), ""), "{:?}", args);
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Runs whole command lines of find and xargs in-process, as a program
+//! bundling them together would, with their input and output captured.
+
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+use walkdir::WalkDir;
+
+use findutils::find::{Config, FollowMode, find_main, try_find_main_with_config};
+use findutils::find::matchers::MatcherRegistry;
+use findutils::xargs::xargs_main;
+use common::test_helpers::*;
+
+mod common;
+
+#[test]
+fn find_main_in_process() {
+    let deps = FakeDependencies::new();
+    let rc = find_main(&["find", &fix_up_slashes("./test_data/simple"), "-name", "abbbc"],
+                       &deps);
+    assert_eq!(rc, 0);
+    assert_eq!(deps.get_output_as_string(),
+               fix_up_slashes("./test_data/simple/abbbc\n"));
+    assert_eq!(deps.get_error_output_as_string(), "");
+
+    // errors are captured too
+    let deps = FakeDependencies::new();
+    assert_eq!(find_main(&["find", "-nonsense"], &deps), 1);
+    assert_eq!(deps.get_output_as_string(), "");
+    assert!(deps.get_error_output_as_string().starts_with("find: "));
+
+    // as is the help
+    let deps = FakeDependencies::new();
+    assert_eq!(find_main(&["find", "-help"], &deps), 0);
+    assert!(deps.get_output_as_string().starts_with("Usage: find "));
+}
+
+#[test]
+fn find_with_built_config() {
+    let config = Config::builder().max_depth(1).sort_entries(true).build().unwrap();
+    let deps = FakeDependencies::new();
+    let rc = try_find_main_with_config(&["find", &fix_up_slashes("./test_data/depth")],
+                                       config,
+                                       &MatcherRegistry::default(),
+                                       &deps);
+    assert_eq!(rc.unwrap(), 0);
+    assert_eq!(deps.get_output_as_string(),
+               fix_up_slashes("./test_data/depth\n./test_data/depth/1\n./test_data/depth/f0\n"));
+
+    // the arguments can still change what the config says
+    let config = Config::builder().max_depth(1).follow(FollowMode::L).build().unwrap();
+    let deps = FakeDependencies::new();
+    let rc = try_find_main_with_config(&["find",
+                                         &fix_up_slashes("./test_data/depth"),
+                                         "-maxdepth",
+                                         "0"],
+                                       config,
+                                       &MatcherRegistry::default(),
+                                       &deps);
+    assert_eq!(rc.unwrap(), 0);
+    assert_eq!(deps.get_output_as_string(), fix_up_slashes("./test_data/depth\n"));
+}
+
+#[test]
+fn find_with_progress_callback() {
+    let calls = Rc::new(RefCell::new(vec![]));
+    let calls_clone = calls.clone();
+    let config = Config::builder()
+        .on_progress(Box::new(move |progress| {
+            calls_clone.borrow_mut().push((progress.entries_seen,
+                                           progress.matches,
+                                           progress.errors,
+                                           progress.current_dir.to_path_buf()));
+        }))
+        .build()
+        .unwrap();
+    let deps = FakeDependencies::new();
+    let rc = try_find_main_with_config(&["find", "test_data", "-name", "abbbc"],
+                                       config,
+                                       &MatcherRegistry::default(),
+                                       &deps);
+    assert_eq!(rc.unwrap(), 0);
+
+    let calls = calls.borrow();
+    // the counts only go up, and the last call has the final ones
+    for pair in calls.windows(2) {
+        assert!(pair[0].0 <= pair[1].0 && pair[0].1 <= pair[1].1 && pair[0].2 <= pair[1].2,
+                "{:?}",
+                pair);
+    }
+    let entries: Vec<_> = WalkDir::new("test_data").into_iter().map(|e| e.unwrap()).collect();
+    let last = calls.last().unwrap();
+    assert_eq!((last.0, last.1, last.2), (entries.len(), 1, 0));
+    // and every directory gets a mention
+    for entry in entries.iter().filter(|entry| entry.file_type().is_dir()) {
+        assert!(calls.iter().any(|call| call.3 == entry.path()), "{:?}", entry.path());
+    }
+}
+
+#[test]
+fn xargs_main_in_process() {
+    let deps = FakeDependencies::new();
+    let rc = xargs_main(&["xargs", "-0", "-E", "x", "-r", "true"],
+                        &mut Cursor::new(""),
+                        &deps);
+    assert_eq!(rc, 0);
+    assert_eq!(deps.get_error_output_as_string(),
+               "xargs: warning: the -E option has no effect if -0 or -d is used.\n");
+
+    let deps = FakeDependencies::new();
+    assert_eq!(xargs_main(&["xargs", "-n", "0"], &mut Cursor::new(""), &deps), 1);
+    assert!(deps.get_error_output_as_string().starts_with("xargs: "));
+}
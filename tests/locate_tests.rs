@@ -0,0 +1,73 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+/// ! This file contains tests that run the locate executable itself, on
+/// ! databases written by the updatedb executable.
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::path::Path;
+use std::process::Command;
+use tempdir::TempDir;
+
+use common::test_helpers::*;
+
+mod common;
+
+/// Builds a database of test_data, returning its path.
+fn run_updatedb(dir: &Path) -> String {
+    let db = dir.join("db").to_string_lossy().into_owned();
+    let status = Command::new(path_to_binary("updatedb"))
+        .arg(format!("--output={}", db))
+        .arg("--localpaths=test_data")
+        .status()
+        .expect("failed to run updatedb");
+    assert!(status.success());
+    db
+}
+
+/// Runs locate with the given arguments and LOCATE_PATH, returning its exit
+/// code, stdout and stderr.
+fn run_locate(args: &[&str], locate_path: &str) -> (i32, String, String) {
+    let output = Command::new(path_to_binary("locate"))
+        .args(args)
+        .env("LOCATE_PATH", locate_path)
+        .output()
+        .expect("failed to run locate");
+    (output.status.code().expect("locate was killed by a signal"),
+     String::from_utf8(output.stdout).expect("locate's output wasn't UTF-8"),
+     String::from_utf8(output.stderr).expect("locate's errors weren't UTF-8"))
+}
+
+#[test]
+fn locate_finds_updatedb_paths() {
+    let temp_dir = TempDir::new("locate_finds_updatedb_paths").unwrap();
+    let db = run_updatedb(temp_dir.path());
+
+    assert_eq!(run_locate(&["abbbc"], &db),
+               (0, fix_up_slashes("test_data/simple/abbbc\n"), String::new()));
+    assert_eq!(run_locate(&["*/f?"], &db),
+               (0,
+                fix_up_slashes("test_data/depth/1/2/3/f3\ntest_data/depth/1/2/f2\n\
+                                test_data/depth/1/f1\ntest_data/depth/f0\n"),
+                String::new()));
+    assert_eq!(run_locate(&["-c", "depth", "size"], &db),
+               (0, "10\n".to_string(), String::new()));
+    assert_eq!(run_locate(&["nothing"], &db), (1, String::new(), String::new()));
+}
+
+#[test]
+fn locate_database_option_overrides_locate_path() {
+    let temp_dir = TempDir::new("locate_database_option").unwrap();
+    let db = run_updatedb(temp_dir.path());
+
+    assert_eq!(run_locate(&["-d", &db, "512bytes"], "/doesnt/exist"),
+               (0, fix_up_slashes("test_data/size/512bytes\n"), String::new()));
+    let (rc, stdout, stderr) = run_locate(&["512bytes"], &format!("/doesnt/exist:{}", db));
+    assert_eq!((rc, stdout), (1, fix_up_slashes("test_data/size/512bytes\n")));
+    assert!(stderr.starts_with("locate: /doesnt/exist: "), "unexpected stderr: {}", stderr);
+}
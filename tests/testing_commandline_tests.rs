@@ -0,0 +1,133 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Tests for testing-commandline itself, which the exec and xargs tests use
+//! to see what commands were run, and how. If it's broken, those tests fail
+//! in ways that look like bugs in find or xargs, so each of its flags is
+//! checked here on its own.
+
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use tempdir::TempDir;
+
+use common::test_helpers::*;
+
+mod common;
+
+/// Runs testing-commandline with the given arguments (after the destination
+/// directory), feeding it the given input, and returns how it exited and
+/// what it wrote to stdout and to its file.
+fn run(args: &[&str], env: &[(&str, &str)], input: &[u8]) -> (ExitStatus, String, String) {
+    let temp_dir = TempDir::new("testing_commandline").unwrap();
+    let mut child = Command::new(path_to_testing_commandline())
+        .arg(temp_dir.path())
+        .args(args)
+        .envs(env.iter().cloned())
+        .current_dir("test_data")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run testing-commandline");
+    // it needn't read it all
+    let _ = child.stdin.take().unwrap().write_all(input);
+    let output = child.wait_with_output().expect("failed to wait for testing-commandline");
+    let file = fs::read_to_string(temp_dir.path().join("1.txt")).unwrap_or_default();
+    (output.status, String::from_utf8(output.stdout).unwrap(), file)
+}
+
+/// What the file starts with, whatever the arguments.
+fn cwd_line() -> String {
+    let cwd = fs::canonicalize("test_data").unwrap();
+    format!("cwd={}\n", cwd.to_string_lossy())
+}
+
+#[test]
+fn plain_invocation() {
+    let (status, stdout, file) = run(&["a b", "", "--no_such_flag"], &[], b"");
+    // only arguments starting with "--" are flags, and unknown ones are
+    // usage errors
+    assert_eq!(status.code(), Some(2));
+    assert_eq!(file, "");
+    assert_eq!(stdout, "Simple command-line app just used for testing -exec flags!\n");
+
+    let (status, stdout, file) = run(&["a b", "", "-x"], &[], b"");
+    assert_eq!((status.code(), stdout.as_ref()), (Some(0), ""));
+    assert_eq!(file, cwd_line() + "args=\na b\n\n-x\n");
+}
+
+#[test]
+fn exit_status_flags() {
+    let cases: &[(&[&str], i32)] = &[(&["--exit_with_failure"], 2),
+                                     (&["--exit_with_status=0"], 0),
+                                     (&["--exit_with_status=7"], 7),
+                                     (&["--exit_with_status=255", "--exit_with_failure"], 255)];
+    for &(args, expected) in cases {
+        let (status, _, file) = run(args, &[], b"");
+        assert_eq!(status.code(), Some(expected), "{:?}", args);
+        // the arguments are written either way
+        assert!(file.ends_with(&format!("args=\n{}\n", args.join("\n"))), "{:?}", args);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn raise_signal() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let (status, _, file) = run(&["--raise_signal=9", "--exit_with_status=3"], &[], b"");
+    assert_eq!((status.code(), status.signal()), (None, Some(9)));
+    // but only once the file's been written
+    assert_eq!(file, cwd_line() + "args=\n--raise_signal=9\n--exit_with_status=3\n");
+}
+
+#[test]
+fn stdin_flags() {
+    let (status, _, file) = run(&["--copy_stdin"], &[], b"one\ntwo \"3\"\n");
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(file, cwd_line() + "stdin=\"one\\ntwo \\\"3\\\"\\n\"\nargs=\n--copy_stdin\n");
+
+    // --read_stdin fails if there was nothing to read, with or without
+    // --copy_stdin
+    for input in &[&b""[..], b"x"] {
+        for args in &[&["--read_stdin"][..], &["--read_stdin", "--copy_stdin"]] {
+            let (status, _, _) = run(args, &[], input);
+            assert_eq!(status.code(), Some(if input.is_empty() { 2 } else { 0 }), "{:?}", args);
+        }
+    }
+
+    // a pipe isn't a terminal
+    let (status, _, _) = run(&["--stdin_is_tty"], &[], b"");
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn output_flags() {
+    let (status, stdout, file) = run(&["--echo=hello", "--print_env=SET", "--print_env=UNSET",
+                                       "--echo=", "--quote_args", "a\nb"],
+                                     &[("SET", "value")],
+                                     b"");
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(stdout, "hello\n\n");
+    assert_eq!(file,
+               cwd_line() +
+               "SET=value\nUNSET is unset\nargs=\n\"--echo=hello\"\n\"--print_env=SET\"\n\
+                \"--print_env=UNSET\"\n\"--echo=\"\n\"--quote_args\"\n\"a\\nb\"\n");
+}
+
+#[test]
+fn sleep_flag() {
+    let start = Instant::now();
+    let (status, _, file) = run(&["--sleep=200"], &[], b"");
+    assert_eq!(status.code(), Some(0));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(file, cwd_line() + "args=\n--sleep=200\n");
+}
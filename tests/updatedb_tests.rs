@@ -0,0 +1,256 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+/// ! This file contains tests that run the updatedb executable itself, and
+/// ! read back the databases it writes.
+extern crate findutils;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::process::Command;
+#[cfg(unix)]
+use std::time::{Duration, SystemTime};
+use tempdir::TempDir;
+use walkdir::WalkDir;
+
+use findutils::find::compare_file_names;
+use findutils::updatedb::frcode::{self, Decoder};
+#[cfg(unix)]
+use findutils::updatedb::incremental::set_modified;
+use common::test_helpers::*;
+
+mod common;
+
+/// Runs updatedb with the given arguments, returning its exit code, stdout
+/// and stderr.
+fn run_updatedb(args: &[&str]) -> (i32, String, String) {
+    let output = Command::new(path_to_binary("updatedb"))
+        .args(args)
+        .output()
+        .expect("failed to run updatedb");
+    (output.status.code().expect("updatedb was killed by a signal"),
+     String::from_utf8(output.stdout).expect("updatedb's output wasn't UTF-8"),
+     String::from_utf8(output.stderr).expect("updatedb's errors weren't UTF-8"))
+}
+
+fn read_database(path: &Path) -> Vec<String> {
+    let decoder = Decoder::new(BufReader::new(File::open(path).expect("no database")))
+        .expect("not a database");
+    decoder.map(|path| frcode::path_from_bytes(path.unwrap()).to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn updatedb_matches_walk() {
+    let temp_dir = TempDir::new("updatedb_matches_walk").unwrap();
+    let db = temp_dir.path().join("db");
+    let root = fix_up_slashes("./test_data");
+    let localpaths = format!("--localpaths={}", root);
+    assert_eq!(run_updatedb(&["--output", &db.to_string_lossy(), &localpaths, "--prunefs="]),
+               (0, String::new(), String::new()));
+
+    let expected: Vec<String> = WalkDir::new(&root)
+        .sort_by(|a, b| compare_file_names(a, b))
+        .into_iter()
+        .map(|entry| entry.unwrap().path().to_string_lossy().into_owned())
+        .collect();
+    assert!(expected.len() > 10);
+    assert_eq!(read_database(&db), expected);
+}
+
+#[test]
+fn updatedb_several_roots() {
+    let temp_dir = TempDir::new("updatedb_several_roots").unwrap();
+    let db = temp_dir.path().join("db");
+    let localpaths = format!("--localpaths={} {}",
+                             fix_up_slashes("test_data/size"),
+                             fix_up_slashes("test_data/simple/subdir"));
+    assert_eq!(run_updatedb(&[&format!("--output={}", db.to_string_lossy()), &localpaths]).0,
+               0);
+    assert_eq!(read_database(&db),
+               vec![fix_up_slashes("test_data/size"),
+                    fix_up_slashes("test_data/size/512bytes"),
+                    fix_up_slashes("test_data/simple/subdir"),
+                    fix_up_slashes("test_data/simple/subdir/ABBBC")]);
+}
+
+#[cfg(unix)]
+#[test]
+fn updatedb_skips_unreadable_dirs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new("updatedb_skips_unreadable_dirs").unwrap();
+    let root = temp_dir.path().join("root");
+    let locked = root.join("locked");
+    fs::create_dir_all(&locked).unwrap();
+    File::create(locked.join("hidden")).unwrap();
+    File::create(root.join("visible")).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // root can read anything, so there'd be nothing to test
+    let readable = fs::read_dir(&locked).is_ok();
+
+    let db = temp_dir.path().join("db");
+    let root = root.to_string_lossy().into_owned();
+    let (rc, stdout, stderr) = run_updatedb(&[&format!("--output={}", db.to_string_lossy()),
+                                              &format!("--localpaths={}", root)]);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_eq!((rc, stdout), (0, String::new()));
+    let paths = read_database(&db);
+    assert!(paths.contains(&format!("{}/visible", root)));
+    assert!(paths.contains(&format!("{}/locked", root)));
+    if !readable {
+        assert!(stderr.starts_with(&format!("updatedb: {}/locked: ", root)),
+                "unexpected stderr: {}",
+                stderr);
+        assert!(!paths.contains(&format!("{}/locked/hidden", root)));
+    }
+}
+
+/// Warnings that can't be written (here, to a full stderr) don't stop the
+/// database being made.
+#[cfg(target_os = "linux")]
+#[test]
+fn updatedb_full_stderr() {
+    use std::fs::OpenOptions;
+    use std::process::Stdio;
+
+    let temp_dir = TempDir::new("updatedb_full_stderr").unwrap();
+    let db = temp_dir.path().join("db");
+    let missing = temp_dir.path().join("missing");
+    let full = OpenOptions::new().write(true).open("/dev/full").unwrap();
+    let status = Command::new(path_to_binary("updatedb"))
+        .args(&[&format!("--output={}", db.to_string_lossy()),
+                &format!("--localpaths={} test_data/simple/subdir",
+                         missing.to_string_lossy())])
+        .stderr(Stdio::from(full))
+        .status()
+        .expect("failed to run updatedb");
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(read_database(&db),
+               vec![fix_up_slashes("test_data/simple/subdir"),
+                    fix_up_slashes("test_data/simple/subdir/ABBBC")]);
+}
+
+#[test]
+fn updatedb_bad_option() {
+    let (rc, stdout, stderr) = run_updatedb(&["--bogus"]);
+    assert_eq!((rc, stdout, stderr),
+               (1, String::new(), "updatedb: unrecognized option '--bogus'\n".to_string()));
+}
+
+/// Makes a tree of files and directories under root, whose directories all
+/// look as though they were last modified an hour ago.
+#[cfg(unix)]
+fn make_old_tree(root: &Path) {
+    for dir in &["a", "a/sub", "b", "c"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    for file in &["a/x", "a/y", "a/sub/z", "b/w", "top"] {
+        File::create(root.join(file)).unwrap();
+    }
+    for dir in &["a/sub", "a", "b", "c", ""] {
+        make_old(&root.join(dir));
+    }
+}
+
+#[cfg(unix)]
+fn make_old(path: &Path) {
+    let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    set_modified(path, an_hour_ago).unwrap();
+}
+
+fn read_file(path: &Path) -> Vec<u8> {
+    let mut contents = vec![];
+    File::open(path).unwrap().read_to_end(&mut contents).unwrap();
+    contents
+}
+
+/// Builds a database of root at db, returning what it wrote.
+fn build_database(root: &Path, db: &Path, incremental: bool) -> Vec<u8> {
+    let mut args = vec![format!("--output={}", db.to_string_lossy()),
+                        format!("--localpaths={}", root.to_string_lossy())];
+    if incremental {
+        args.push("--incremental".to_string());
+    }
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+    assert_eq!(run_updatedb(&args), (0, String::new(), String::new()));
+    read_file(db)
+}
+
+#[cfg(unix)]
+#[test]
+fn updatedb_incremental_unchanged() {
+    let temp_dir = TempDir::new("updatedb_incremental_unchanged").unwrap();
+    let root = temp_dir.path().join("root");
+    make_old_tree(&root);
+    let db = temp_dir.path().join("db");
+    let full = build_database(&root, &db, false);
+    assert_eq!(read_database(&db).len(), 10);
+
+    assert_eq!(build_database(&root, &db, true), full);
+    // and again, now that the database is one --incremental made
+    assert_eq!(build_database(&root, &db, true), full);
+}
+
+#[cfg(unix)]
+#[test]
+fn updatedb_incremental_changes() {
+    let temp_dir = TempDir::new("updatedb_incremental_changes").unwrap();
+    let root = temp_dir.path().join("root");
+    make_old_tree(&root);
+    let db = temp_dir.path().join("db");
+    build_database(&root, &db, false);
+
+    // everything that changes is in a (and below it)
+    fs::remove_file(root.join("a/x")).unwrap();
+    File::create(root.join("a/new")).unwrap();
+    fs::create_dir(root.join("a/sub/deeper")).unwrap();
+    File::create(root.join("a/sub/deeper/file")).unwrap();
+    fs::remove_file(root.join("a/sub/z")).unwrap();
+    fs::create_dir(root.join("a/y2")).unwrap();
+
+    let incremental = build_database(&root, &db, true);
+    let full = build_database(&root, &temp_dir.path().join("full_db"), false);
+    assert_eq!(incremental, full);
+    let paths = read_database(&db);
+    let root = root.to_string_lossy();
+    assert!(paths.contains(&format!("{}/a/new", root)));
+    assert!(paths.contains(&format!("{}/a/sub/deeper/file", root)));
+    assert!(!paths.contains(&format!("{}/a/x", root)));
+    assert!(!paths.contains(&format!("{}/a/sub/z", root)));
+}
+
+#[cfg(unix)]
+#[test]
+fn updatedb_incremental_reuses_old_contents() {
+    let temp_dir = TempDir::new("updatedb_incremental_reuses").unwrap();
+    let root = temp_dir.path().join("root");
+    make_old_tree(&root);
+    let db = temp_dir.path().join("db");
+    build_database(&root, &db, false);
+
+    // b looks unchanged, so what's in it comes from the old database, and
+    // the new file isn't seen
+    File::create(root.join("b/unseen")).unwrap();
+    make_old(&root.join("b"));
+    let unseen = format!("{}/b/unseen", root.to_string_lossy());
+    build_database(&root, &db, true);
+    assert!(!read_database(&db).contains(&unseen));
+    build_database(&root, &db, false);
+    assert!(read_database(&db).contains(&unseen));
+}
+
+#[test]
+fn updatedb_incremental_without_database() {
+    let temp_dir = TempDir::new("updatedb_incremental_without_database").unwrap();
+    let root = Path::new("test_data");
+    let full = build_database(root, &temp_dir.path().join("full_db"), false);
+    assert_eq!(build_database(root, &temp_dir.path().join("db"), true), full);
+}
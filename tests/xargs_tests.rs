@@ -0,0 +1,823 @@
+// Copyright 2017 Google Inc.
+//
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+/// ! This file contains tests that run the xargs executable itself, with
+/// ! testing-commandline as the command so that we can see exactly which
+/// ! arguments each invocation was given.
+extern crate findutils;
+#[cfg(unix)]
+extern crate libc;
+extern crate tempdir;
+extern crate walkdir;
+
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use tempdir::TempDir;
+
+use findutils::xargs::xargs_main;
+use common::test_helpers::*;
+
+mod common;
+
+/// Runs xargs with the given arguments, feeding it the given input, and
+/// returns its exit code, stdout and stderr.
+fn run_xargs(args: &[&str], input: &[u8]) -> (i32, String, String) {
+    let mut child = Command::new(path_to_binary("xargs"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run xargs");
+    child.stdin.take().unwrap().write_all(input).expect("failed to write xargs's input");
+    let output = child.wait_with_output().expect("failed to wait for xargs");
+    (output.status.code().expect("xargs was killed by a signal"),
+     String::from_utf8(output.stdout).expect("xargs's output wasn't UTF-8"),
+     String::from_utf8(output.stderr).expect("xargs's errors weren't UTF-8"))
+}
+
+/// Returns the arguments passed to each invocation of testing-commandline
+/// that wrote to the given directory, in order.
+fn invocations(dir: &Path) -> Vec<Vec<String>> {
+    let count = fs::read_dir(dir).unwrap().count();
+    (1..count + 1)
+        .map(|i| {
+            let mut s = String::new();
+            File::open(dir.join(format!("{}.txt", i)))
+                .expect("failed to open output file")
+                .read_to_string(&mut s)
+                .expect("failed to read output file");
+            // skip everything up to and including the args= line
+            s.lines()
+                .skip_while(|line| *line != "args=")
+                .skip(1)
+                .map(|line| line.to_string())
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns exactly what the given invocation of testing-commandline wrote
+/// for its arguments, for when they might contain newlines or invalid UTF-8.
+fn invocation_bytes(dir: &Path, invocation: usize) -> Vec<u8> {
+    let mut bytes = vec![];
+    File::open(dir.join(format!("{}.txt", invocation)))
+        .expect("failed to open output file")
+        .read_to_end(&mut bytes)
+        .expect("failed to read output file");
+    let args_start = bytes.windows(6).position(|w| w == b"args=\n").unwrap() + 6;
+    bytes.split_off(args_start)
+}
+
+#[test]
+fn xargs_splits_on_blanks_and_newlines() {
+    let temp_dir = TempDir::new("xargs_splits_on_blanks_and_newlines").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, stderr) = run_xargs(&[&path_to_testing_commandline(), &temp_dir_path, "initial"],
+                                    b"a b\tc\n  'd e' \"f g\" h\\ i\n\n");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["initial", "a", "b", "c", "d e", "f g", "h i"]]);
+}
+
+#[test]
+fn xargs_splits_long_input_into_several_commands() {
+    let temp_dir = TempDir::new("xargs_splits_long_input_into_several_commands").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let items: Vec<String> = (0..3000).map(|i| format!("{:0100}", i)).collect();
+
+    let (rc, _, stderr) = run_xargs(&[&path_to_testing_commandline(), &temp_dir_path],
+                                    items.join("\n").as_bytes());
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    let invocations = invocations(temp_dir.path());
+    assert!(invocations.len() > 1);
+    assert_eq!(invocations.concat(), items);
+}
+
+#[test]
+fn xargs_runs_command_once_without_input() {
+    let temp_dir = TempDir::new("xargs_runs_command_once_without_input").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, _) = run_xargs(&[&path_to_testing_commandline(), &temp_dir_path], b"");
+    assert_eq!(rc, 0);
+    assert_eq!(invocations(temp_dir.path()), vec![Vec::<String>::new()]);
+}
+
+#[test]
+fn xargs_failures() {
+    let temp_dir = TempDir::new("xargs_failures").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, _) = run_xargs(&[&path_to_testing_commandline(),
+                                 &temp_dir_path,
+                                 "--exit_with_failure"],
+                               b"a b");
+    assert_eq!(rc, 123);
+
+    let (rc, _, stderr) = run_xargs(&[&path_to_testing_commandline(), &temp_dir_path],
+                                    b"a\nb 'c");
+    assert_eq!(rc, 1);
+    assert!(stderr.starts_with("xargs: unmatched single quote on line 2"),
+            "unexpected error message: {}",
+            stderr);
+}
+
+#[test]
+fn xargs_null() {
+    let temp_dir = TempDir::new("xargs_null").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    // the final item isn't terminated, but still counts
+    let (rc, _, stderr) = run_xargs(&["-0", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"a\nb\0'c\" d\\\0\0\xffe");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    if cfg!(unix) {
+        assert_eq!(invocation_bytes(temp_dir.path(), 1),
+                   b"a\nb\n'c\" d\\\n\n\xffe\n".to_vec());
+    }
+}
+
+#[test]
+fn xargs_delimiter() {
+    let temp_dir = TempDir::new("xargs_delimiter").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    // quotes and blanks aren't special
+    let (rc, _, stderr) = run_xargs(&["-d", ",", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"a b,'c,,d\\");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    let (rc, _, stderr) = run_xargs(&["--delimiter=\\n",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    b"e 'f\ng\n");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["a b", "'c", "", "d\\"], vec!["e 'f", "g"]]);
+}
+
+#[test]
+fn xargs_invalid_delimiter() {
+    let (rc, _, stderr) = run_xargs(&["-d", "ab", "echo"], b"");
+    assert_eq!(rc, 1);
+    assert_eq!(stderr,
+               "xargs: invalid input delimiter specification ab: the delimiter must be either a \
+                single character or an escape sequence starting with \\.\n");
+}
+
+#[test]
+fn xargs_replace() {
+    let temp_dir = TempDir::new("xargs_replace").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    // each line is one item, whatever blanks it contains
+    let (rc, _, stderr) = run_xargs(&["-I",
+                                      "%",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path,
+                                      "%",
+                                      "dest/%.%",
+                                      "{}"],
+                                    b"a b\n  'c  d'  \n\n");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    let (rc, _, stderr) = run_xargs(&["-i", &path_to_testing_commandline(), &temp_dir_path, "x{}"],
+                                    b"e");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["a b", "dest/a b.a b", "{}"],
+                    vec!["c  d  ", "dest/c  d  .c  d  ", "{}"],
+                    vec!["xe"]]);
+}
+
+#[test]
+fn xargs_replace_too_long() {
+    let temp_dir = TempDir::new("xargs_replace_too_long").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let item = "x".repeat(100 * 1024);
+
+    let (rc, _, stderr) = run_xargs(&["-I{}",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path,
+                                      "{}",
+                                      "{}"],
+                                    item.as_bytes());
+    assert_eq!((rc, stderr.as_ref()), (1, "xargs: command too long\n"));
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn xargs_max_args() {
+    let temp_dir = TempDir::new("xargs_max_args").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, stderr) = run_xargs(&["-n", "2", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"a b\nc d e\n");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+}
+
+#[test]
+fn xargs_max_lines() {
+    let temp_dir = TempDir::new("xargs_max_lines").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    // a trailing blank continues a line, and blank lines don't count
+    let (rc, _, stderr) = run_xargs(&["-L", "2", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"a b \nc\n\nd\ne f\ng\n");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["a", "b", "c", "d"], vec!["e", "f", "g"]]);
+
+    // even with -L 1, a continued line is still one line
+    let temp_dir = TempDir::new("xargs_max_lines").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let (rc, _, stderr) = run_xargs(&["-L", "1", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"a b \nc\n");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()), vec![vec!["a", "b", "c"]]);
+}
+
+#[test]
+fn xargs_max_procs() {
+    // the first invocation sleeps before recording its arguments, so if the
+    // second runs at the same time, it'll get to record its arguments first
+    let input = b"--sleep=500\nquick\n";
+
+    let temp_dir = TempDir::new("xargs_max_procs").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let (rc, _, stderr) = run_xargs(&["-P",
+                                      "2",
+                                      "-n",
+                                      "1",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    input);
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["quick"], vec!["--sleep=500"]]);
+
+    let temp_dir = TempDir::new("xargs_max_procs").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let (rc, _, stderr) = run_xargs(&["-P",
+                                      "1",
+                                      "-n",
+                                      "1",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    input);
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["--sleep=500"], vec!["quick"]]);
+}
+
+#[test]
+fn xargs_max_procs_failures() {
+    let temp_dir = TempDir::new("xargs_max_procs_failures").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    // one failure is enough, however the invocations overlap
+    let (rc, _, _) = run_xargs(&["-P",
+                                 "0",
+                                 "-n",
+                                 "1",
+                                 &path_to_testing_commandline(),
+                                 &temp_dir_path],
+                               b"--exit_with_failure --sleep=100 a b c");
+    assert_eq!(rc, 123);
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 5);
+}
+
+#[test]
+fn xargs_exit_codes() {
+    let temp_dir = TempDir::new("xargs_exit_codes").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, stderr) = run_xargs(&["no-such-command-findutils"], b"a");
+    assert_eq!(rc, 127);
+    assert_eq!(stderr, "xargs: no-such-command-findutils: No such file or directory\n");
+
+    // a status of 255 stops xargs from running any more commands
+    let (rc, _, stderr) = run_xargs(&["-n", "1", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"--exit_with_status=2 --exit_with_status=255 a");
+    assert_eq!(rc, 124);
+    assert!(stderr.ends_with(": exited with status 255; aborting\n"),
+            "unexpected error message: {}",
+            stderr);
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["--exit_with_status=2"], vec!["--exit_with_status=255"]]);
+
+    // ...whereas other failures just affect the exit code
+    let (rc, _, stderr) = run_xargs(&["-n", "1", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"--exit_with_status=2 b");
+    assert_eq!((rc, stderr.as_ref()), (123, ""));
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 4);
+}
+
+#[test]
+#[cfg(unix)]
+fn xargs_command_killed() {
+    let (rc, _, stderr) = run_xargs(&["sh", "-c", "kill -9 $$"], b"a");
+    assert_eq!((rc, stderr.as_ref()), (125, "xargs: sh: terminated by signal 9\n"));
+}
+
+#[test]
+fn xargs_exit_if_size_exceeded() {
+    let temp_dir = TempDir::new("xargs_exit_if_size_exceeded").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let item = "x".repeat(60 * 1024);
+    let input = format!("{} {} {}", item, item, item);
+
+    // without -x, the size limit just splits up the batches
+    let (rc, _, stderr) = run_xargs(&["-n", "3", &path_to_testing_commandline(), &temp_dir_path],
+                                    input.as_bytes());
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+
+    let (rc, _, stderr) = run_xargs(&["-x",
+                                      "-n",
+                                      "3",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    input.as_bytes());
+    assert_eq!((rc, stderr.as_ref()), (1, "xargs: argument list too long\n"));
+}
+
+#[test]
+fn xargs_no_run_if_empty() {
+    let temp_dir = TempDir::new("xargs_no_run_if_empty").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, _) = run_xargs(&["-r", &path_to_testing_commandline(), &temp_dir_path], b" \n");
+    assert_eq!(rc, 0);
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+    let (rc, _, _) = run_xargs(&["--no-run-if-empty",
+                                 &path_to_testing_commandline(),
+                                 &temp_dir_path],
+                               b"a");
+    assert_eq!(rc, 0);
+    assert_eq!(invocations(temp_dir.path()), vec![vec!["a"]]);
+}
+
+#[test]
+fn xargs_empty_items() {
+    // (arguments, input, the arguments of each invocation), all the same as
+    // GNU xargs: an empty item is passed on as an empty argument, and input
+    // with only empty items in isn't empty, even with -r
+    let cases: &[(&[&str], &[u8], &[&[&str]])] =
+        &[(&["-0"], b"a\0\0b", &[&["a", "", "b"]]),
+          (&["-0", "-r"], b"\0", &[&[""]]),
+          (&["-0", "-r"], b"\0\0", &[&["", ""]]),
+          (&["-0", "-r"], b"", &[]),
+          (&["-d", ","], b"a,,b,", &[&["a", "", "b"]]),
+          (&["-0", "-n", "1"], b"\0a\0", &[&[""], &["a"]]),
+          // without a delimiter, only quotes make empty items
+          (&[], b"a  \n\n b", &[&["a", "b"]]),
+          (&[], b"'' a\n", &[&["", "a"]]),
+          (&["-r"], b"\"\"\n", &[&[""]]),
+          (&["-r"], b" \n\n", &[])];
+    for &(args, input, expected) in cases {
+        let temp_dir = TempDir::new("xargs_empty_items").unwrap();
+        let temp_dir_path = temp_dir.path().to_string_lossy();
+        let mut xargs_args = args.to_vec();
+        let testing_commandline = path_to_testing_commandline();
+        xargs_args.extend(&[&testing_commandline[..], &temp_dir_path, "--quote_args"]);
+        let (rc, _, stderr) = run_xargs(&xargs_args, input);
+        assert_eq!((rc, stderr.as_ref()), (0, ""), "{:?}", args);
+        let expected: Vec<Vec<String>> = expected.iter()
+            .map(|invocation| {
+                let mut quoted = vec!["\"--quote_args\"".to_string()];
+                quoted.extend(invocation.iter().map(|arg| format!("{:?}", arg)));
+                quoted
+            })
+            .collect();
+        assert_eq!(invocations(temp_dir.path()), expected, "{:?} {:?}", args, input);
+    }
+}
+
+#[test]
+fn xargs_verbose() {
+    let temp_dir = TempDir::new("xargs_verbose").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, stderr) = run_xargs(&["-t",
+                                      "-n",
+                                      "2",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    b"a b 'c d'");
+    assert_eq!(rc, 0);
+    assert_eq!(stderr,
+               format!("{0} {1} a b\n{0} {1} 'c d'\n",
+                       path_to_testing_commandline(),
+                       temp_dir_path));
+}
+
+/// Failing to write the commands out (here, to a full stderr) is an error,
+/// and they aren't run.
+#[cfg(target_os = "linux")]
+#[test]
+fn xargs_verbose_full_stderr() {
+    use std::fs::OpenOptions;
+
+    let temp_dir = TempDir::new("xargs_verbose_full_stderr").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let full = OpenOptions::new().write(true).open("/dev/full").unwrap();
+    let mut child = Command::new(path_to_binary("xargs"))
+        .args(&["-t", &path_to_testing_commandline(), &temp_dir_path])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::from(full))
+        .spawn()
+        .expect("failed to run xargs");
+    child.stdin.take().unwrap().write_all(b"a b").expect("failed to write xargs's input");
+    let status = child.wait().expect("failed to wait for xargs");
+    assert_eq!(status.code(), Some(1));
+    assert!(invocations(temp_dir.path()).is_empty());
+}
+
+#[test]
+fn xargs_interactive() {
+    let temp_dir = TempDir::new("xargs_interactive").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let mut deps = FakeDependencies::new();
+    deps.set_input("n\nyes\n");
+
+    let rc = xargs_main(&["xargs", "-p", "-n", "1", &path_to_testing_commandline(), &temp_dir_path],
+                        &mut Cursor::new(b"a b c".to_vec()),
+                        &deps);
+    assert_eq!(rc, 0);
+    // there's no answer for c, which counts as no
+    assert_eq!(invocations(temp_dir.path()), vec![vec!["b"]]);
+    let prompt = format!("{} {}", path_to_testing_commandline(), temp_dir_path);
+    assert_eq!(deps.get_error_output_as_string(),
+               format!("{0} a ?...{0} b ?...{0} c ?...", prompt));
+}
+
+#[test]
+fn xargs_max_chars() {
+    let temp_dir = TempDir::new("xargs_max_chars").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let command_size = path_to_testing_commandline().len() + temp_dir_path.len() + 2;
+    // room for two of the three-byte "ab\0"s
+    let max_chars = (command_size + 6).to_string();
+
+    let (rc, _, stderr) = run_xargs(&["-s",
+                                      &max_chars,
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    b"ab cd ef gh i");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()),
+               vec![vec!["ab", "cd"], vec!["ef", "gh"], vec!["i"]]);
+
+    let temp_dir = TempDir::new("xargs_max_chars").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let (rc, _, stderr) = run_xargs(&["--max-chars", &max_chars,
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    b"ab abcdef");
+    assert_eq!((rc, stderr.as_ref()), (1, "xargs: argument line too long\n"));
+    assert_eq!(invocations(temp_dir.path()), Vec::<Vec<String>>::new());
+
+    let (rc, _, stderr) = run_xargs(&["-s", "1000000000000"], b"");
+    assert_eq!(rc, 1);
+    assert!(stderr.starts_with("xargs: value 1000000000000 for -s option should be <= "),
+            "{}",
+            stderr);
+}
+
+#[test]
+fn xargs_show_limits() {
+    let temp_dir = TempDir::new("xargs_show_limits").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, stderr) = run_xargs(&["--show-limits",
+                                      "-s",
+                                      "5000",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    b"a");
+    assert_eq!(rc, 0);
+    let lines: Vec<&str> = stderr.lines().collect();
+    let labels = ["Your environment variables take up ",
+                  "POSIX upper limit on argument length (this system): ",
+                  "POSIX smallest allowable upper limit on argument length (all systems): ",
+                  "Maximum length of command we could actually use: ",
+                  "Size of command buffer we are actually using: ",
+                  "Maximum parallelism (--max-procs must be no greater): "];
+    assert_eq!(lines.len(), labels.len(), "{}", stderr);
+    for (line, label) in lines.iter().zip(labels.iter()) {
+        assert!(line.starts_with(label), "{}", line);
+    }
+    assert_eq!(lines[2],
+               "POSIX smallest allowable upper limit on argument length (all systems): 4096");
+    assert_eq!(lines[4], "Size of command buffer we are actually using: 5000");
+    assert_eq!(lines[5], "Maximum parallelism (--max-procs must be no greater): 2147483647");
+    // and then it carries on as usual, as stdin isn't a terminal
+    assert_eq!(invocations(temp_dir.path()), vec![vec!["a"]]);
+
+    // as with GNU xargs, "A=b\0" takes up 4 bytes, and the environment comes
+    // off both the upper limit and what's left of it
+    #[cfg(unix)]
+    {
+        let output = Command::new(path_to_binary("xargs"))
+            .args(&["--show-limits", "-r"])
+            .env_clear()
+            .env("A", "b")
+            .stdin(Stdio::null())
+            .output()
+            .expect("failed to run xargs");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let lines: Vec<&str> = stderr.lines().collect();
+        let upper_limit = unsafe { libc::sysconf(libc::_SC_ARG_MAX) } as usize - 4 - 2048;
+        assert_eq!(lines[0], "Your environment variables take up 4 bytes");
+        assert_eq!(lines[1],
+                   format!("POSIX upper limit on argument length (this system): {}",
+                           upper_limit));
+        assert_eq!(lines[3],
+                   format!("Maximum length of command we could actually use: {}",
+                           upper_limit - 4));
+    }
+}
+
+#[test]
+fn xargs_arg_file() {
+    let temp_dir = TempDir::new("xargs_arg_file").unwrap();
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let output_dir_path = output_dir.to_string_lossy();
+    let arg_file = temp_dir.path().join("items");
+    File::create(&arg_file).unwrap().write_all(b"a b\nc").unwrap();
+
+    // the command gets xargs's own stdin
+    let (rc, _, stderr) = run_xargs(&["-a",
+                                      &arg_file.to_string_lossy(),
+                                      &path_to_testing_commandline(),
+                                      &output_dir_path,
+                                      "--read_stdin"],
+                                    b"for the command");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(&output_dir), vec![vec!["--read_stdin", "a", "b", "c"]]);
+
+    // ...which it doesn't otherwise
+    let (rc, _, _) = run_xargs(&[&path_to_testing_commandline(), &output_dir_path, "--read_stdin"],
+                               b"a");
+    assert_eq!(rc, 123);
+
+    let (rc, _, stderr) = run_xargs(&["--arg-file=/no/such/file"], b"");
+    assert_eq!(rc, 1);
+    assert!(stderr.starts_with("xargs: Cannot open input file '/no/such/file': "),
+            "{}",
+            stderr);
+}
+
+#[test]
+fn xargs_eof_str() {
+    let temp_dir = TempDir::new("xargs_eof_str").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, stderr) = run_xargs(&["-E", "END", &path_to_testing_commandline(), &temp_dir_path],
+                                    b"a b\n'c d' END e\nf\n");
+    assert_eq!((rc, stderr.as_ref()), (0, ""));
+    assert_eq!(invocations(temp_dir.path()), vec![vec!["a", "b", "c d"]]);
+
+    // with -0, nothing in the input is special
+    let temp_dir = TempDir::new("xargs_eof_str").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let (rc, _, stderr) = run_xargs(&["-0",
+                                      "-E",
+                                      "END",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path],
+                                    b"a\0END\0b\0");
+    assert_eq!((rc, stderr.as_ref()),
+               (0, "xargs: warning: the -E option has no effect if -0 or -d is used.\n"));
+    assert_eq!(invocations(temp_dir.path()), vec![vec!["a", "END", "b"]]);
+}
+
+/// Returns the lines that testing-commandline wrote for its --print_env
+/// flags, for each invocation that wrote to the given directory.
+fn printed_env(dir: &Path) -> Vec<Vec<String>> {
+    let count = fs::read_dir(dir).unwrap().count();
+    (1..count + 1)
+        .map(|i| {
+            let mut s = String::new();
+            File::open(dir.join(format!("{}.txt", i)))
+                .expect("failed to open output file")
+                .read_to_string(&mut s)
+                .expect("failed to read output file");
+            s.lines()
+                .skip(1)
+                .take_while(|line| *line != "args=")
+                .map(|line| line.to_string())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn xargs_process_slot_var() {
+    let temp_dir = TempDir::new("xargs_process_slot_var").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let mut child = Command::new(path_to_binary("xargs"))
+        .args(&["-P",
+                "2",
+                "-n",
+                "1",
+                "--process-slot-var=FINDUTILS_SLOT",
+                &path_to_testing_commandline(),
+                &temp_dir_path,
+                "--sleep=200",
+                "--print_env=FINDUTILS_SLOT",
+                "--print_env=FINDUTILS_INHERITED"])
+        .env("FINDUTILS_INHERITED", "unchanged")
+        .env_remove("FINDUTILS_SLOT")
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("failed to run xargs");
+    child.stdin.take().unwrap().write_all(b"a b c d e").unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let envs = printed_env(temp_dir.path());
+    assert_eq!(envs.len(), 5);
+    let mut slots = vec![];
+    for lines in envs {
+        assert_eq!(lines.len(), 2);
+        slots.push(lines[0].clone());
+        // the rest of the environment is passed on as it is
+        assert_eq!(lines[1], "FINDUTILS_INHERITED=unchanged");
+    }
+    assert!(slots.iter().all(|slot| slot == "FINDUTILS_SLOT=0" || slot == "FINDUTILS_SLOT=1"),
+            "{:?}",
+            slots);
+    assert!(slots.contains(&"FINDUTILS_SLOT=0".to_string()));
+    assert!(slots.contains(&"FINDUTILS_SLOT=1".to_string()));
+
+    // without --process-slot-var, the variable isn't set
+    let temp_dir = TempDir::new("xargs_process_slot_var").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+    let (rc, _, _) = run_xargs(&[&path_to_testing_commandline(),
+                                 &temp_dir_path,
+                                 "--print_env=FINDUTILS_SLOT"],
+                               b"a");
+    assert_eq!(rc, 0);
+    assert_eq!(printed_env(temp_dir.path()), vec![vec!["FINDUTILS_SLOT is unset"]]);
+}
+
+#[test]
+#[cfg(unix)]
+fn xargs_open_tty() {
+    let temp_dir = TempDir::new("xargs_open_tty").unwrap();
+    let temp_dir_path = temp_dir.path().to_string_lossy();
+
+    let (rc, _, stderr) = run_xargs(&["-o",
+                                      &path_to_testing_commandline(),
+                                      &temp_dir_path,
+                                      "--stdin_is_tty"],
+                                    b"a");
+    if File::open("/dev/tty").is_ok() {
+        assert_eq!((rc, stderr.as_ref()), (0, ""));
+        assert_eq!(invocations(temp_dir.path()), vec![vec!["--stdin_is_tty", "a"]]);
+    } else {
+        // without a controlling terminal, all we can check is that it's
+        // reported
+        assert_eq!(rc, 1);
+        assert!(stderr.starts_with("xargs: failed to open /dev/tty for reading: "),
+                "{}",
+                stderr);
+    }
+
+    // otherwise the command's stdin is never a terminal
+    let (rc, _, _) = run_xargs(&[&path_to_testing_commandline(), &temp_dir_path, "--stdin_is_tty"],
+                               b"a");
+    assert_eq!(rc, 123);
+}
+
+/// Starts xargs with the given arguments and no input, gives it time to
+/// start up, then sends it each of the given signals in turn.
+#[cfg(unix)]
+fn start_xargs_and_signal(args: &[&str], signals: &[libc::c_int]) -> Child {
+    let child = Command::new(path_to_binary("xargs"))
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run xargs");
+    thread::sleep(Duration::from_millis(300));
+    for &signal in signals {
+        unsafe {
+            libc::kill(child.id() as libc::pid_t, signal);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    child
+}
+
+/// Writes one item per line to a file, for xargs -a.
+fn write_arg_file(path: &Path, items: &[&str]) {
+    File::create(path).unwrap().write_all(items.join("\n").as_bytes()).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn xargs_signals_adjust_max_procs() {
+    let temp_dir = TempDir::new("xargs_signals_adjust_max_procs").unwrap();
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let arg_file = temp_dir.path().join("items");
+    write_arg_file(&arg_file, &["a", "b", "c", "d", "e", "f"]);
+
+    // each command takes 1.5s to create its file, so after 2.2s, there are
+    // only as many files as commands could run at once to begin with
+    let mut child = start_xargs_and_signal(&["-a",
+                                             &arg_file.to_string_lossy(),
+                                             "-P",
+                                             "1",
+                                             "-n",
+                                             "1",
+                                             &path_to_testing_commandline(),
+                                             &output_dir.to_string_lossy(),
+                                             "--sleep=1500"],
+                                           &[libc::SIGUSR1, libc::SIGUSR1]);
+    thread::sleep(Duration::from_millis(1900));
+    assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 3);
+    assert!(child.wait().unwrap().success());
+    assert_eq!(invocations(&output_dir).len(), 6);
+
+    let output_dir = temp_dir.path().join("output2");
+    fs::create_dir(&output_dir).unwrap();
+    let mut child = start_xargs_and_signal(&["-a",
+                                             &arg_file.to_string_lossy(),
+                                             "-P",
+                                             "2",
+                                             "-n",
+                                             "1",
+                                             &path_to_testing_commandline(),
+                                             &output_dir.to_string_lossy(),
+                                             "--sleep=1000"],
+                                           &[libc::SIGUSR2, libc::SIGUSR2]);
+    // the first two commands are already running, but after them, they run
+    // one at a time (as -P can't go below 1)
+    thread::sleep(Duration::from_millis(2200));
+    assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 3);
+    assert!(child.wait().unwrap().success());
+    assert_eq!(invocations(&output_dir).len(), 6);
+}
+
+#[test]
+#[cfg(unix)]
+fn xargs_interrupted() {
+    let temp_dir = TempDir::new("xargs_interrupted").unwrap();
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir(&output_dir).unwrap();
+    let arg_file = temp_dir.path().join("items");
+    write_arg_file(&arg_file, &["a", "b", "c"]);
+
+    // the running command gets the signal too, so nothing's ever created
+    let child = start_xargs_and_signal(&["-a",
+                                         &arg_file.to_string_lossy(),
+                                         "-n",
+                                         "1",
+                                         &path_to_testing_commandline(),
+                                         &output_dir.to_string_lossy(),
+                                         "--sleep=1000"],
+                                       &[libc::SIGTERM]);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(128 + libc::SIGTERM));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "xargs: interrupted by signal 15\n");
+    thread::sleep(Duration::from_millis(1200));
+    assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+
+    // xargs also stops if it's waiting for input
+    let mut child = Command::new(path_to_binary("xargs"))
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run xargs");
+    let stdin = child.stdin.take().unwrap();
+    thread::sleep(Duration::from_millis(300));
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+    assert_eq!(output.status.code(), Some(128 + libc::SIGINT));
+}
+
+#[test]
+#[cfg(unix)]
+fn xargs_default_command_is_echo() {
+    assert_eq!(run_xargs(&[], b"a\nb  c\n"),
+               (0, "a b c\n".to_string(), String::new()));
+}